@@ -5,6 +5,10 @@ pub type Result<T> = core::result::Result<T, Error>;
 #[derive(Debug, From)]
 pub enum Error {
     NotFound,
+    /// The request lacks the admin token that the route requires.
+    Unauthorized,
+    /// The request clashes with what is already there.
+    Conflict,
     Unknown,
 }
 
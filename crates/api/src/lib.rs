@@ -18,3 +18,9 @@ pub struct Credentials {
     pub username: String,
     pub password: String,
 }
+
+/// A read-only snapshot of the drive, named by the UTC time it was taken at.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub name: String,
+}
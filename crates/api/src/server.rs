@@ -1,9 +1,10 @@
 use crate::*;
 use axum::{
-    extract::State,
-    http::StatusCode,
-    response::Html,
-    routing::{get, post},
+    extract::{Path, Request, State},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{Html, IntoResponse, Response},
+    routing::{delete, get, post},
     Form, Json, Router,
 };
 use crypto::{Certificate, PrivateSignatureKey};
@@ -14,12 +15,29 @@ use tokio::sync::Mutex;
 
 pub struct Server {
     tcp_listener: TcpListener,
+    router: Router,
+    admin_token: Option<Arc<str>>,
 }
 
 pub trait Handler {
     // fn handle_home(
     //     &self,
     // ) -> impl std::future::Future<Output = String> + Send;
+
+    /// Metrics in the Prometheus text exposition format.
+    fn handle_metrics(&self) -> impl std::future::Future<Output = String> + Send;
+
+    /// The snapshots of the drive, from the oldest to the latest.
+    fn handle_list_snapshots(
+        &self
+    ) -> impl std::future::Future<Output = Result<Vec<Snapshot>>> + Send;
+
+    fn handle_create_snapshot(&self) -> impl std::future::Future<Output = Result<Snapshot>> + Send;
+
+    fn handle_remove_snapshot(
+        &self,
+        name: String,
+    ) -> impl std::future::Future<Output = Result<()>> + Send;
 }
 
 impl Server {
@@ -29,7 +47,30 @@ impl Server {
         tls_certificate: Certificate,
     ) -> Result<Self> {
         let tcp_listener = tokio::net::TcpListener::bind(listen_address).await.unwrap();
-        Ok(Self { tcp_listener })
+        Ok(Self {
+            tcp_listener,
+            router: Router::new(),
+            admin_token: None,
+        })
+    }
+
+    /// Sets the bearer token that routes changing the gateway require. Without one, these
+    /// routes refuse every request.
+    pub fn with_admin_token(
+        mut self,
+        admin_token: Option<String>,
+    ) -> Self {
+        self.admin_token = admin_token.map(Arc::from);
+        self
+    }
+
+    /// Serves the routes of `router` alongside the API, e.g. MQTT over WebSockets.
+    pub fn mount(
+        mut self,
+        router: Router,
+    ) -> Self {
+        self.router = self.router.merge(router);
+        self
     }
 
     pub async fn listen<T>(
@@ -39,12 +80,116 @@ impl Server {
     where
         T: Handler + Clone + Send + Sync + 'static,
     {
-        let shared_handler = Arc::new(Mutex::new(handler));
-        let app = Router::new()
-            // .route("/", get(home::<T>))
-            // .route("/login", post(login::<T>))
-            .with_state(shared_handler);
+        let app = routes(handler, self.admin_token).merge(self.router);
         axum::serve(self.tcp_listener, app).await.unwrap();
         Ok(())
     }
 }
+
+fn routes<T>(
+    handler: T,
+    admin_token: Option<Arc<str>>,
+) -> Router
+where
+    T: Handler + Clone + Send + Sync + 'static,
+{
+    let shared_handler = Arc::new(Mutex::new(handler));
+    let admin = middleware::from_fn_with_state(admin_token, require_admin);
+    Router::new()
+        // .route("/", get(home::<T>))
+        // .route("/login", post(login::<T>))
+        .route("/metrics", get(metrics::<T>))
+        .route(
+            "/snapshots",
+            get(list_snapshots::<T>).merge(post(create_snapshot::<T>).route_layer(admin.clone())),
+        )
+        .route(
+            "/snapshots/:name",
+            delete(remove_snapshot::<T>).route_layer(admin),
+        )
+        .with_state(shared_handler)
+}
+
+/// Lets a request through only if it carries the admin token as its bearer token.
+async fn require_admin(
+    State(admin_token): State<Option<Arc<str>>>,
+    request: Request,
+    next: Next,
+) -> Result<Response> {
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match (admin_token, token) {
+        (Some(admin_token), Some(token))
+            if constant_time_eq(admin_token.as_bytes(), token.as_bytes()) =>
+        {
+            Ok(next.run(request).await)
+        }
+        _ => Err(Error::Unauthorized),
+    }
+}
+
+/// Compares without stopping at the first difference, so that the time taken does not tell how
+/// much of a guess was right.
+fn constant_time_eq(
+    a: &[u8],
+    b: &[u8],
+) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .zip(b)
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+async fn metrics<T>(State(handler): State<Arc<Mutex<T>>>) -> impl IntoResponse
+where
+    T: Handler,
+{
+    let metrics = handler.lock().await.handle_metrics().await;
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics,
+    )
+}
+
+async fn list_snapshots<T>(State(handler): State<Arc<Mutex<T>>>) -> Result<Json<Vec<Snapshot>>>
+where
+    T: Handler,
+{
+    let snapshots = handler.lock().await.handle_list_snapshots().await?;
+    Ok(Json(snapshots))
+}
+
+async fn create_snapshot<T>(State(handler): State<Arc<Mutex<T>>>) -> Result<Json<Snapshot>>
+where
+    T: Handler,
+{
+    let snapshot = handler.lock().await.handle_create_snapshot().await?;
+    Ok(Json(snapshot))
+}
+
+async fn remove_snapshot<T>(
+    State(handler): State<Arc<Mutex<T>>>,
+    Path(name): Path<String>,
+) -> Result<StatusCode>
+where
+    T: Handler,
+{
+    handler.lock().await.handle_remove_snapshot(name).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        match self {
+            Error::NotFound => StatusCode::NOT_FOUND,
+            Error::Unauthorized => StatusCode::UNAUTHORIZED,
+            Error::Conflict => StatusCode::CONFLICT,
+            Error::Unknown => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+        .into_response()
+    }
+}
@@ -8,13 +8,67 @@ pub struct Filter {
     pub open: bool,
 }
 
+impl Filter {
+    pub fn matches(
+        &self,
+        topic: &Topic,
+    ) -> bool {
+        if topic.segments.len() < self.segments.len()
+            || (!self.open && topic.segments.len() > self.segments.len())
+        {
+            return false;
+        }
+        // Wildcards at the first level do not match topics beginning with '$', such as $SYS.
+        let reserved = topic
+            .segments
+            .first()
+            .is_some_and(|segment| segment.as_ref().starts_with('$'));
+        if reserved && !matches!(self.segments.first(), Some(Some(_))) {
+            return false;
+        }
+        self.segments
+            .iter()
+            .zip(topic.segments.iter())
+            .all(|(filter, segment)| match filter {
+                Some(filter) => filter == segment,
+                None => true,
+            })
+    }
+
+    pub fn contains(
+        &self,
+        other: &Filter,
+    ) -> bool {
+        if other.segments.len() < self.segments.len()
+            || (!self.open && (other.open || other.segments.len() > self.segments.len()))
+        {
+            return false;
+        }
+        self.segments
+            .iter()
+            .zip(other.segments.iter())
+            .all(|(filter, segment)| match (filter, segment) {
+                (Some(filter), Some(segment)) => filter == segment,
+                (Some(_), None) => false,
+                (None, _) => true,
+            })
+    }
+}
+
 impl From<&str> for Filter {
     fn from(filter: &str) -> Self {
-        let segments = filter.split('/').map(Segment::from).map(Some).collect();
-        Self {
-            segments,
-            open: false,
+        let mut segments: Vec<_> = filter
+            .split('/')
+            .map(|segment| match segment {
+                "+" => None,
+                segment => Some(Segment::from(segment)),
+            })
+            .collect();
+        let open = matches!(segments.last(), Some(Some(segment)) if segment.as_ref() == "#");
+        if open {
+            segments.pop();
         }
+        Self { segments, open }
     }
 }
 
@@ -26,10 +80,7 @@ impl From<String> for Filter {
 
 impl From<Topic> for Filter {
     fn from(topic: Topic) -> Self {
-        Filter {
-            segments: topic.segments.into_iter().map(Some).collect(),
-            open: false,
-        }
+        Self::from(topic.to_string())
     }
 }
 
@@ -38,14 +89,74 @@ impl Display for Filter {
         &self,
         f: &mut Formatter,
     ) -> fmt::Result {
-        write!(
-            f,
-            "{}",
-            self.segments
-                .iter()
-                .map(|s| s.as_ref().map(Segment::as_ref).unwrap_or("*"))
-                .collect::<Vec<_>>()
-                .join("/")
-        )
+        let segments = self
+            .segments
+            .iter()
+            .map(|s| s.as_ref().map(Segment::as_ref).unwrap_or("+"));
+        let segments = if self.open {
+            segments.chain(std::iter::once("#")).collect::<Vec<_>>()
+        } else {
+            segments.collect::<Vec<_>>()
+        };
+        write!(f, "{}", segments.join("/"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_parse_wildcards() {
+        let filter = Filter::from("kodi/+/state/#");
+        assert_eq!(
+            filter,
+            Filter {
+                segments: vec![
+                    Some(Segment::from("kodi")),
+                    None,
+                    Some(Segment::from("state")),
+                ],
+                open: true,
+            }
+        );
+        assert_eq!(filter.to_string(), "kodi/+/state/#");
+    }
+
+    #[test]
+    fn test_filter_matches() {
+        let filter = Filter::from("kodi/+/state");
+        assert!(filter.matches(&Topic::from("kodi/living-room/state")));
+        assert!(!filter.matches(&Topic::from("kodi/living-room/volume")));
+        assert!(!filter.matches(&Topic::from("kodi/living-room/state/x")));
+        assert!(!filter.matches(&Topic::from("kodi")));
+    }
+
+    #[test]
+    fn test_filter_matches_open() {
+        let filter = Filter::from("kodi/#");
+        assert!(filter.matches(&Topic::from("kodi")));
+        assert!(filter.matches(&Topic::from("kodi/living-room/state")));
+        assert!(!filter.matches(&Topic::from("jellyfin/state")));
+    }
+
+    #[test]
+    fn test_filter_matches_reserved() {
+        let topic = Topic::from("$SYS/broker/clients/connected");
+        assert!(!Filter::from("#").matches(&topic));
+        assert!(!Filter::from("+/broker/#").matches(&topic));
+        assert!(Filter::from("$SYS/#").matches(&topic));
+        assert!(Filter::from("$SYS/broker/+/connected").matches(&topic));
+    }
+
+    #[test]
+    fn test_filter_contains() {
+        let filter = Filter::from("kodi/#");
+        assert!(filter.contains(&Filter::from("kodi/#")));
+        assert!(filter.contains(&Filter::from("kodi/+/state")));
+        assert!(!filter.contains(&Filter::from("#")));
+        assert!(!filter.contains(&Filter::from("+/state")));
+        assert!(!Filter::from("kodi/+").contains(&Filter::from("kodi/+/#")));
+        assert!(Filter::from("kodi/+").contains(&Filter::from("kodi/state")));
     }
 }
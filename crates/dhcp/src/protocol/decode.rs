@@ -1 +1,136 @@
+use super::*;
+use nom::{
+    bytes::complete::{tag, take},
+    combinator::{map, map_opt, verify},
+    error::{Error, ErrorKind},
+    multi::length_data,
+    number::complete::{be_u16, be_u32, be_u8},
+    sequence::tuple,
+    IResult,
+};
+use num_traits::FromPrimitive;
 
+fn failure<T>(
+    input: &[u8],
+    kind: ErrorKind,
+) -> IResult<&[u8], T> {
+    Err(nom::Err::Error(Error::new(input, kind)))
+}
+
+fn ipv4_address(input: &[u8]) -> IResult<&[u8], Ipv4Addr> {
+    map(be_u32, Ipv4Addr::from)(input)
+}
+
+/// An option, or `None` for padding.
+fn option(input: &[u8]) -> IResult<&[u8], Option<DhcpOption>> {
+    let (input, code) = be_u8(input)?;
+    if code == OPTION_PAD {
+        return Ok((input, None));
+    }
+    let (input, data) = length_data(be_u8)(input)?;
+    let option = match code {
+        OPTION_HOST_NAME => match std::str::from_utf8(data) {
+            Ok(host_name) => DhcpOption::HostName(host_name.into()),
+            Err(_) => return failure(data, ErrorKind::Char),
+        },
+        OPTION_REQUESTED_ADDRESS if data.len() == 4 => {
+            DhcpOption::RequestedAddress(ipv4_address(data)?.1)
+        }
+        OPTION_MESSAGE_TYPE if data.len() == 1 => DhcpOption::MessageType(
+            map_opt(be_u8, MessageType::from_u8)(data)?.1,
+        ),
+        _ => DhcpOption::Unknown {
+            code,
+            data: data.to_vec(),
+        },
+    };
+    Ok((input, Some(option)))
+}
+
+/// The options up to the end option.
+fn options(mut input: &[u8]) -> IResult<&[u8], Vec<DhcpOption>> {
+    let mut options = Vec::new();
+    loop {
+        let (after, code) = be_u8(input)?;
+        if code == OPTION_END {
+            return Ok((after, options));
+        }
+        let (after, option) = option(input)?;
+        options.extend(option);
+        input = after;
+    }
+}
+
+pub fn message(input: &[u8]) -> IResult<&[u8], Message> {
+    let (input, (op, _hardware_type, hardware_length, _hops)) =
+        tuple((be_u8, be_u8, verify(be_u8, |length| *length <= 16), be_u8))(input)?;
+    let (input, (transaction_id, _seconds, _flags)) = tuple((be_u32, be_u16, be_u16))(input)?;
+    let (input, (client_address, your_address, _server_address, _relay_address)) = tuple((
+        ipv4_address,
+        ipv4_address,
+        ipv4_address,
+        ipv4_address,
+    ))(input)?;
+    let (input, hardware_address) = take(16usize)(input)?;
+    // The server host name and boot file name, which no option overloads here.
+    let (input, _) = take(64usize + 128)(input)?;
+    let (input, _) = tag(MAGIC_COOKIE)(input)?;
+    let (input, options) = options(input)?;
+    Ok((
+        input,
+        Message {
+            is_reply: op == 2,
+            transaction_id,
+            client_address,
+            your_address,
+            hardware_address: hardware_address[..hardware_length as usize].to_vec(),
+            options,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A DHCPREQUEST from a client that is not bound yet.
+    fn request(options: &[u8]) -> Vec<u8> {
+        let mut message = vec![1, 1, 6, 0, 0x39, 0x03, 0xf3, 0x26, 0, 0, 0x80, 0];
+        message.extend_from_slice(&[0; 16]);
+        message.extend_from_slice(&[0x00, 0x05, 0x3c, 0x04, 0x8d, 0x59]);
+        message.extend_from_slice(&[0; 10 + 64 + 128]);
+        message.extend_from_slice(&MAGIC_COOKIE);
+        message.extend_from_slice(options);
+        message
+    }
+
+    #[test]
+    fn test_request() {
+        let message = request(&[
+            53, 1, 3, 0, 50, 4, 192, 168, 1, 100, 12, 5, b'k', b'i', b't', b'c', b'h', 255,
+        ]);
+        let (input, message) = super::message(&message).unwrap();
+        assert!(input.is_empty());
+        assert!(!message.is_reply);
+        assert_eq!(message.transaction_id, 0x3903f326);
+        assert_eq!(message.hardware_address, [0x00, 0x05, 0x3c, 0x04, 0x8d, 0x59]);
+        assert_eq!(message.message_type(), Some(MessageType::Request));
+        assert_eq!(message.host_name(), Some("kitch"));
+        assert_eq!(
+            message.requested_address(),
+            Some(Ipv4Addr::new(192, 168, 1, 100))
+        );
+    }
+
+    #[test]
+    fn test_malformed_options() {
+        // No end option.
+        assert!(super::message(&request(&[53, 1, 3])).is_err());
+        // An option longer than the message.
+        assert!(super::message(&request(&[12, 9, b'a', 255])).is_err());
+        // A host name that is not UTF-8.
+        assert!(super::message(&request(&[12, 1, 0xff, 255])).is_err());
+        // A message type that does not exist.
+        assert!(super::message(&request(&[53, 1, 42, 255])).is_err());
+    }
+}
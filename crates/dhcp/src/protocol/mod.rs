@@ -1,2 +1,78 @@
 pub mod decode;
 pub mod encode;
+
+use num_derive::FromPrimitive;
+use std::net::Ipv4Addr;
+
+// RFC 2131, RFC 2132
+
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+const OPTION_PAD: u8 = 0;
+const OPTION_HOST_NAME: u8 = 12;
+const OPTION_REQUESTED_ADDRESS: u8 = 50;
+const OPTION_MESSAGE_TYPE: u8 = 53;
+const OPTION_END: u8 = 255;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub is_reply: bool,
+    pub transaction_id: u32,
+    /// The address of a client that is bound already and renews its lease.
+    pub client_address: Ipv4Addr,
+    /// The address that the server offers or assigns to the client.
+    pub your_address: Ipv4Addr,
+    pub hardware_address: Vec<u8>,
+    pub options: Vec<DhcpOption>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DhcpOption {
+    HostName(String),
+    RequestedAddress(Ipv4Addr),
+    MessageType(MessageType),
+    /// Another option, as it was on the wire.
+    Unknown {
+        code: u8,
+        data: Vec<u8>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+pub enum MessageType {
+    Discover = 1,
+    Offer = 2,
+    Request = 3,
+    Decline = 4,
+    Ack = 5,
+    Nak = 6,
+    Release = 7,
+    Inform = 8,
+}
+
+impl Message {
+    pub fn message_type(&self) -> Option<MessageType> {
+        self.options.iter().find_map(|option| match option {
+            DhcpOption::MessageType(message_type) => Some(*message_type),
+            _ => None,
+        })
+    }
+
+    pub fn host_name(&self) -> Option<&str> {
+        self.options.iter().find_map(|option| match option {
+            DhcpOption::HostName(host_name) => Some(host_name.as_str()),
+            _ => None,
+        })
+    }
+
+    /// The address that a client asks for: the one it requests while it is not bound yet, or
+    /// the one it renews.
+    pub fn requested_address(&self) -> Option<Ipv4Addr> {
+        self.options
+            .iter()
+            .find_map(|option| match option {
+                DhcpOption::RequestedAddress(address) => Some(*address),
+                _ => None,
+            })
+            .or((!self.client_address.is_unspecified()).then_some(self.client_address))
+    }
+}
@@ -1 +1,12 @@
-pub trait Handler {}
+use std::future::Future;
+use std::net::Ipv4Addr;
+
+pub trait Handler {
+    /// A client on the network asks for the lease of `address` under `host_name`, which the
+    /// server hears as requests are broadcast, or sent to it when it leases the address itself.
+    fn handle_lease(
+        &self,
+        host_name: &str,
+        address: Ipv4Addr,
+    ) -> impl Future<Output = ()> + Send;
+}
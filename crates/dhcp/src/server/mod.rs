@@ -4,6 +4,7 @@ mod handler;
 pub use error::Error;
 pub use handler::Handler;
 
+use crate::{decode, MessageType};
 use bytes::BytesMut;
 use std::net::SocketAddr;
 use std::sync::Arc;
@@ -35,8 +36,19 @@ impl Server {
                 tracing::error!("Error receiving data.");
                 continue;
             };
-            let message = &buffer[..bytes_read];
-            tracing::debug!("DHCP: {message:?}");
+            let Ok((_, message)) = decode::message(&buffer[..bytes_read]) else {
+                tracing::debug!("{address} sent a malformed message");
+                continue;
+            };
+            if message.is_reply || message.message_type() != Some(MessageType::Request) {
+                continue;
+            }
+            let (Some(host_name), Some(requested_address)) =
+                (message.host_name(), message.requested_address())
+            else {
+                continue;
+            };
+            handler.handle_lease(host_name, requested_address).await;
         }
     }
 }
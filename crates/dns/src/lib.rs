@@ -5,4 +5,4 @@ mod protocol;
 
 pub use protocol::*;
 #[cfg(feature = "server")]
-pub use server::{Handler, Server};
+pub use server::{Answer, Handler, Server, Zone};
@@ -1 +1,361 @@
+use super::*;
+use nom::{
+    bytes::complete::take,
+    combinator::{all_consuming, flat_map, map, map_opt, rest},
+    error::{Error, ErrorKind},
+    multi::{count, length_data, many0},
+    number::complete::{be_u16, be_u32, be_u8},
+    sequence::tuple,
+    IResult,
+};
+use num_traits::FromPrimitive;
 
+enum Resource {
+    Record(Record),
+    Opt(Edns),
+}
+
+fn failure<T>(
+    input: &[u8],
+    kind: ErrorKind,
+) -> IResult<&[u8], T> {
+    Err(nom::Err::Error(Error::new(input, kind)))
+}
+
+/// Where `input` starts within the message.
+fn offset(
+    message: &[u8],
+    input: &[u8],
+) -> usize {
+    input.as_ptr() as usize - message.as_ptr() as usize
+}
+
+/// A name, which may end in a pointer to a name earlier in the message.
+///
+/// Every pointer has to lead further back than the one before, so that a chain of pointers
+/// cannot loop.
+fn name<'a>(message: &'a [u8]) -> impl Fn(&'a [u8]) -> IResult<&'a [u8], Name> {
+    move |input: &'a [u8]| {
+        let mut labels = Vec::new();
+        let mut length = 1;
+        let mut remaining = None;
+        let mut position = input;
+        let mut limit = offset(message, input);
+        loop {
+            let (after, first) = be_u8(position)?;
+            match first & 0xc0 {
+                0x00 if first == 0 => {
+                    return Ok((remaining.unwrap_or(after), Name(labels)));
+                }
+                0x00 => {
+                    let (after, label) = take(first)(after)?;
+                    length += 1 + label.len();
+                    if length > MAX_NAME_LENGTH {
+                        return failure(input, ErrorKind::TooLarge);
+                    }
+                    labels.push(label.to_vec());
+                    position = after;
+                }
+                0xc0 => {
+                    let (after, second) = be_u8(after)?;
+                    let target = ((first as usize & 0x3f) << 8) | second as usize;
+                    if target >= limit {
+                        return failure(position, ErrorKind::Verify);
+                    }
+                    remaining.get_or_insert(after);
+                    limit = target;
+                    position = &message[target..];
+                }
+                _ => return failure(position, ErrorKind::Tag),
+            }
+        }
+    }
+}
+
+fn header(input: &[u8]) -> IResult<&[u8], (Header, [u16; 4])> {
+    let (input, (id, bits)) = tuple((be_u16, be_u16))(input)?;
+    let Some(response_code) = ResponseCode::from_u16(bits & 0xf) else {
+        return failure(input, ErrorKind::Switch);
+    };
+    let (input, counts) = count(be_u16, 4)(input)?;
+    let header = Header {
+        id,
+        is_response: bits & 0x8000 != 0,
+        opcode: Opcode::from((bits >> 11) as u8 & 0xf),
+        flags: HeaderFlags::from_bits_truncate(bits),
+        response_code,
+    };
+    Ok((
+        input,
+        (header, [counts[0], counts[1], counts[2], counts[3]]),
+    ))
+}
+
+fn question<'a>(message: &'a [u8]) -> impl Fn(&'a [u8]) -> IResult<&'a [u8], Question> {
+    move |input: &'a [u8]| {
+        map(
+            tuple((name(message), be_u16, be_u16)),
+            |(name, record_type, class)| Question {
+                name,
+                record_type: record_type.into(),
+                class: class.into(),
+            },
+        )(input)
+    }
+}
+
+fn character_string(input: &[u8]) -> IResult<&[u8], Vec<u8>> {
+    map(length_data(be_u8), <[u8]>::to_vec)(input)
+}
+
+fn soa<'a>(message: &'a [u8]) -> impl Fn(&'a [u8]) -> IResult<&'a [u8], Soa> {
+    move |input: &'a [u8]| {
+        map(
+            tuple((
+                name(message),
+                name(message),
+                be_u32,
+                be_u32,
+                be_u32,
+                be_u32,
+                be_u32,
+            )),
+            |(primary, responsible, serial, refresh, retry, expire, minimum)| Soa {
+                primary,
+                responsible,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            },
+        )(input)
+    }
+}
+
+fn record_data<'a>(
+    message: &'a [u8],
+    record_type: u16,
+) -> impl Fn(&'a [u8]) -> IResult<&'a [u8], RecordData> {
+    move |input: &'a [u8]| match RecordType::from(record_type) {
+        RecordType::A => map(take(4usize), |octets: &[u8]| {
+            RecordData::A(<[u8; 4]>::try_from(octets).unwrap().into())
+        })(input),
+        RecordType::Ns => map(name(message), RecordData::Ns)(input),
+        RecordType::Cname => map(name(message), RecordData::Cname)(input),
+        RecordType::Soa => map(soa(message), RecordData::Soa)(input),
+        RecordType::Ptr => map(name(message), RecordData::Ptr)(input),
+        RecordType::Mx => map(tuple((be_u16, name(message))), |(preference, exchange)| {
+            RecordData::Mx {
+                preference,
+                exchange,
+            }
+        })(input),
+        RecordType::Txt => map(many0(character_string), RecordData::Txt)(input),
+        RecordType::Aaaa => map(take(16usize), |octets: &[u8]| {
+            RecordData::Aaaa(<[u8; 16]>::try_from(octets).unwrap().into())
+        })(input),
+        RecordType::Srv => map(
+            tuple((be_u16, be_u16, be_u16, name(message))),
+            |(priority, weight, port, target)| RecordData::Srv {
+                priority,
+                weight,
+                port,
+                target,
+            },
+        )(input),
+        _ => map(rest, |data: &[u8]| RecordData::Unknown {
+            record_type,
+            data: data.to_vec(),
+        })(input),
+    }
+}
+
+fn edns_option(input: &[u8]) -> IResult<&[u8], EdnsOption> {
+    map(
+        tuple((be_u16, length_data(be_u16))),
+        |(code, data): (u16, &[u8])| EdnsOption {
+            code,
+            data: data.to_vec(),
+        },
+    )(input)
+}
+
+/// A resource record, or the OPT pseudo-record of EDNS.
+fn resource<'a>(message: &'a [u8]) -> impl Fn(&'a [u8]) -> IResult<&'a [u8], Resource> {
+    move |input: &'a [u8]| {
+        let (input, (name, record_type, class, ttl)) =
+            tuple((name(message), be_u16, be_u16, be_u32))(input)?;
+        let (input, data) = length_data(be_u16)(input)?;
+        if record_type == TYPE_OPT {
+            if !name.is_root() {
+                return failure(input, ErrorKind::Verify);
+            }
+            let (_, options) = all_consuming(many0(edns_option))(data)?;
+            let edns = Edns {
+                udp_payload_size: class,
+                extended_response_code: (ttl >> 24) as u8,
+                version: (ttl >> 16) as u8,
+                dnssec_ok: ttl & 0x8000 != 0,
+                options,
+            };
+            return Ok((input, Resource::Opt(edns)));
+        }
+        let (_, data) = all_consuming(record_data(message, record_type))(data)?;
+        let record = Record {
+            name,
+            class: class.into(),
+            ttl,
+            data,
+        };
+        Ok((input, Resource::Record(record)))
+    }
+}
+
+fn record<'a>(message: &'a [u8]) -> impl Fn(&'a [u8]) -> IResult<&'a [u8], Record> {
+    move |input: &'a [u8]| {
+        map_opt(resource(message), |resource| match resource {
+            Resource::Record(record) => Some(record),
+            Resource::Opt(_) => None,
+        })(input)
+    }
+}
+
+pub fn message(input: &[u8]) -> IResult<&[u8], Message> {
+    let message = input;
+    let (input, (header, [questions, answers, authorities, additionals])) = header(input)?;
+    let (input, questions) = count(question(message), questions as usize)(input)?;
+    let (input, answers) = count(record(message), answers as usize)(input)?;
+    let (input, authorities) = count(record(message), authorities as usize)(input)?;
+    let (input, resources) = count(resource(message), additionals as usize)(input)?;
+    let mut additionals = Vec::new();
+    let mut edns = None;
+    for resource in resources {
+        match resource {
+            Resource::Record(record) => additionals.push(record),
+            // A message has at most one OPT record.
+            Resource::Opt(_) if edns.is_some() => return failure(input, ErrorKind::Count),
+            Resource::Opt(opt) => edns = Some(opt),
+        }
+    }
+    Ok((
+        input,
+        Message {
+            header,
+            questions,
+            answers,
+            authorities,
+            additionals,
+            edns,
+        },
+    ))
+}
+
+/// The messages of DNS over TCP, each of which comes after its length (RFC 1035 4.2.2).
+pub fn tcp_message(input: &[u8]) -> IResult<&[u8], Message> {
+    flat_map(be_u16, |length| {
+        map_opt(take(length), |data| {
+            all_consuming(message)(data)
+                .ok()
+                .map(|(_, message)| message)
+        })
+    })(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_name_pointer() {
+        let message = &[
+            0x07, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0x03, b'c', b'o', b'm', 0x00, 0x03,
+            b'w', b'w', b'w', 0xc0, 0x00, 0xff,
+        ];
+        let (input, result) = name(message)(&message[13..]).unwrap();
+        assert_eq!(input, &[0xff]);
+        assert_eq!(result, "www.example.com".parse().unwrap());
+    }
+
+    #[test]
+    fn test_name_pointer_loop() {
+        // Each name points to the other, after a label.
+        let message = &[0x01, b'a', 0xc0, 0x04, 0x01, b'b', 0xc0, 0x00];
+        assert!(name(message)(&message[4..]).is_err());
+        assert!(name(message)(&message[..]).is_err());
+        let message = &[0xc0, 0x00];
+        assert!(name(message)(message).is_err());
+    }
+
+    #[test]
+    fn test_name_too_long() {
+        let mut message = Vec::new();
+        for _ in 0..5 {
+            message.push(63);
+            message.extend_from_slice(&[b'a'; 63]);
+        }
+        message.push(0);
+        assert!(name(&message)(&message).is_err());
+    }
+
+    /// Queries as dig 9.18 sends them, with the cookie option it adds.
+    #[test]
+    fn test_dig_queries() {
+        let query = |data: &[u8]| {
+            let (input, value) = message(data).unwrap();
+            assert!(input.is_empty());
+            assert_eq!(
+                value.header.flags,
+                HeaderFlags::RECURSION_DESIRED | HeaderFlags::AUTHENTIC_DATA
+            );
+            let edns = value.edns.unwrap();
+            assert_eq!(edns.udp_payload_size, 1232);
+            assert_eq!(edns.options.len(), 1);
+            assert_eq!(edns.options[0].code, 10);
+            assert_eq!(edns.options[0].data.len(), 8);
+            value.questions
+        };
+        // dig gateway.lararium
+        assert_eq!(
+            query(&[
+                0xe4, 0x1d, 0x01, 0x20, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x07, 0x67,
+                0x61, 0x74, 0x65, 0x77, 0x61, 0x79, 0x08, 0x6c, 0x61, 0x72, 0x61, 0x72, 0x69, 0x75,
+                0x6d, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x29, 0x04, 0xd0, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x0c, 0x00, 0x0a, 0x00, 0x08, 0xc3, 0x5a, 0x1e, 0x9b, 0x47, 0x02, 0xf0,
+                0x6d,
+            ]),
+            [Question {
+                name: "gateway.lararium".parse().unwrap(),
+                record_type: RecordType::A,
+                class: Class::In,
+            }]
+        );
+        // dig -x 192.168.1.10
+        assert_eq!(
+            query(&[
+                0x5b, 0x07, 0x01, 0x20, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x02, 0x31,
+                0x30, 0x01, 0x31, 0x03, 0x31, 0x36, 0x38, 0x03, 0x31, 0x39, 0x32, 0x07, 0x69, 0x6e,
+                0x2d, 0x61, 0x64, 0x64, 0x72, 0x04, 0x61, 0x72, 0x70, 0x61, 0x00, 0x00, 0x0c, 0x00,
+                0x01, 0x00, 0x00, 0x29, 0x04, 0xd0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x00, 0x0a,
+                0x00, 0x08, 0x9f, 0x11, 0x08, 0xc4, 0x2b, 0x7e, 0x35, 0xd0,
+            ]),
+            [Question {
+                name: "10.1.168.192.in-addr.arpa".parse().unwrap(),
+                record_type: RecordType::Ptr,
+                class: Class::In,
+            }]
+        );
+        // dig +tcp gateway.lararium AAAA
+        let data = [
+            0x00, 0x39, 0x8a, 0x90, 0x01, 0x20, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+            0x07, 0x67, 0x61, 0x74, 0x65, 0x77, 0x61, 0x79, 0x08, 0x6c, 0x61, 0x72, 0x61, 0x72,
+            0x69, 0x75, 0x6d, 0x00, 0x00, 0x1c, 0x00, 0x01, 0x00, 0x00, 0x29, 0x04, 0xd0, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x0c, 0x00, 0x0a, 0x00, 0x08, 0x5e, 0x0d, 0x6a, 0x17, 0xb2,
+            0xc4, 0xe9, 0x81,
+        ];
+        let (input, value) = tcp_message(&data).unwrap();
+        assert!(input.is_empty());
+        assert_eq!(value.header.id, 0x8a90);
+        assert_eq!(value.questions[0].record_type, RecordType::Aaaa);
+    }
+}
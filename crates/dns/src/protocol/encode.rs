@@ -1 +1,403 @@
+use super::*;
+use cookie_factory::{
+    bytes::{be_u16, be_u32, be_u8},
+    combinator::{back_to_the_buffer, slice},
+    multi::many_ref,
+    sequence::tuple,
+    GenError, Seek, SerializeFn, WriteContext,
+};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::Write;
 
+/// Pointers reach the first 16 KiB of a message.
+const MAX_POINTER: u64 = 0x3fff;
+
+/// Where names were written in the message, so that later names can point to them.
+struct Compression<'a> {
+    start: u64,
+    offsets: RefCell<HashMap<&'a [Vec<u8>], u16>>,
+}
+
+/// Writes what `inner` writes after its length.
+fn length<W: Write + Seek, F: SerializeFn<W>>(inner: F) -> impl SerializeFn<W> {
+    back_to_the_buffer(
+        2,
+        move |out: WriteContext<W>| {
+            let start = out.position;
+            let out = inner(out)?;
+            let length = out.position - start;
+            Ok((out, length))
+        },
+        move |out, length| {
+            let length = u16::try_from(length).map_err(|_| GenError::InvalidOffset)?;
+            be_u16(length)(out)
+        },
+    )
+}
+
+/// A name, which ends in a pointer once the rest of it was written before.
+fn name<'a: 'b, 'b, W: Write + 'b>(
+    value: &'a Name,
+    compression: &'b Compression<'a>,
+) -> impl SerializeFn<W> + use<'a, 'b, W> {
+    let labels: &'a [Vec<u8>] = value;
+    move |mut out: WriteContext<W>| {
+        for (i, label) in labels.iter().enumerate() {
+            let suffix = &labels[i..];
+            let position = out.position - compression.start;
+            {
+                let mut offsets = compression.offsets.borrow_mut();
+                if let Some(offset) = offsets.get(suffix) {
+                    return be_u16(0xc000 | offset)(out);
+                }
+                if position <= MAX_POINTER {
+                    offsets.insert(suffix, position as u16);
+                }
+            }
+            out = tuple((be_u8(label.len() as u8), slice(label)))(out)?;
+        }
+        be_u8(0)(out)
+    }
+}
+
+fn uncompressed_name<'a, W: Write + 'a>(value: &'a Name) -> impl SerializeFn<W> + 'a {
+    tuple((
+        many_ref(value.iter(), |label| {
+            tuple((be_u8(label.len() as u8), slice(label)))
+        }),
+        be_u8(0),
+    ))
+}
+
+fn header<W: Write>(
+    value: &Header,
+    counts: [usize; 4],
+) -> impl SerializeFn<W> {
+    let bits = (value.is_response as u16) << 15
+        | (u8::from(value.opcode) as u16) << 11
+        | value.flags.bits()
+        | value.response_code as u16;
+    tuple((
+        be_u16(value.id),
+        be_u16(bits),
+        be_u16(counts[0] as u16),
+        be_u16(counts[1] as u16),
+        be_u16(counts[2] as u16),
+        be_u16(counts[3] as u16),
+    ))
+}
+
+fn question<'a: 'b, 'b, W: Write + 'b>(
+    value: &'a Question,
+    compression: &'b Compression<'a>,
+) -> impl SerializeFn<W> + use<'a, 'b, W> {
+    tuple((
+        name(&value.name, compression),
+        be_u16(value.record_type.into()),
+        be_u16(value.class.into()),
+    ))
+}
+
+fn character_string<'a, W: Write + 'a>(value: &'a [u8]) -> impl SerializeFn<W> + 'a {
+    tuple((be_u8(value.len() as u8), slice(value)))
+}
+
+fn record_data<'a: 'b, 'b, W: Write + 'b>(
+    value: &'a RecordData,
+    compression: &'b Compression<'a>,
+) -> impl SerializeFn<W> + use<'a, 'b, W> {
+    move |out: WriteContext<W>| match value {
+        RecordData::A(address) => slice(address.octets())(out),
+        RecordData::Ns(target) | RecordData::Cname(target) | RecordData::Ptr(target) => {
+            name(target, compression)(out)
+        }
+        RecordData::Soa(soa) => tuple((
+            name(&soa.primary, compression),
+            name(&soa.responsible, compression),
+            be_u32(soa.serial),
+            be_u32(soa.refresh),
+            be_u32(soa.retry),
+            be_u32(soa.expire),
+            be_u32(soa.minimum),
+        ))(out),
+        RecordData::Mx {
+            preference,
+            exchange,
+        } => tuple((be_u16(*preference), name(exchange, compression)))(out),
+        RecordData::Txt(strings) => many_ref(strings, |string| character_string(string))(out),
+        RecordData::Aaaa(address) => slice(address.octets())(out),
+        // The targets of SRV records are never compressed (RFC 2782).
+        RecordData::Srv {
+            priority,
+            weight,
+            port,
+            target,
+        } => tuple((
+            be_u16(*priority),
+            be_u16(*weight),
+            be_u16(*port),
+            uncompressed_name(target),
+        ))(out),
+        RecordData::Unknown { data, .. } => slice(data)(out),
+    }
+}
+
+fn record<'a: 'b, 'b, W: Write + Seek + 'b>(
+    value: &'a Record,
+    compression: &'b Compression<'a>,
+) -> impl SerializeFn<W> + use<'a, 'b, W> {
+    tuple((
+        name(&value.name, compression),
+        be_u16(value.data.record_type().into()),
+        be_u16(value.class.into()),
+        be_u32(value.ttl),
+        length(record_data(&value.data, compression)),
+    ))
+}
+
+fn edns<'a, W: Write + Seek + 'a>(value: &'a Edns) -> impl SerializeFn<W> + 'a {
+    let ttl = (value.extended_response_code as u32) << 24
+        | (value.version as u32) << 16
+        | (value.dnssec_ok as u32) << 15;
+    tuple((
+        be_u8(0),
+        be_u16(TYPE_OPT),
+        be_u16(value.udp_payload_size),
+        be_u32(ttl),
+        length(many_ref(&value.options, |option| {
+            tuple((
+                be_u16(option.code),
+                be_u16(option.data.len() as u16),
+                slice(&option.data),
+            ))
+        })),
+    ))
+}
+
+pub fn message<'a, W: Write + Seek + 'a>(value: &'a Message) -> impl SerializeFn<W> + 'a {
+    move |out: WriteContext<W>| {
+        let compression = Compression {
+            start: out.position,
+            offsets: RefCell::new(HashMap::new()),
+        };
+        let counts = [
+            value.questions.len(),
+            value.answers.len(),
+            value.authorities.len(),
+            value.additionals.len() + value.edns.is_some() as usize,
+        ];
+        let out = header(&value.header, counts)(out)?;
+        let out = many_ref(&value.questions, |q| question(q, &compression))(out)?;
+        let out = many_ref(&value.answers, |r| record(r, &compression))(out)?;
+        let out = many_ref(&value.authorities, |r| record(r, &compression))(out)?;
+        let out = many_ref(&value.additionals, |r| record(r, &compression))(out)?;
+        match &value.edns {
+            Some(value) => edns(value)(out),
+            None => Ok(out),
+        }
+    }
+}
+
+/// A message for DNS over TCP, after its length.
+pub fn tcp_message<'a, W: Write + Seek + 'a>(value: &'a Message) -> impl SerializeFn<W> + 'a {
+    length(message(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cookie_factory::gen;
+    use std::io::Cursor;
+
+    macro_rules! serialize {
+        ($serializer:expr, $buffer:ident) => {{
+            let cursor = Cursor::new(&mut $buffer[..]);
+            let (_, position) = gen($serializer, cursor).unwrap();
+            &$buffer[..position as usize]
+        }};
+    }
+
+    /// Decodes `data` and checks that it encodes to the same bytes.
+    fn round_trip(data: &[u8]) -> Message {
+        let (input, value) = decode::message(data).unwrap();
+        assert!(input.is_empty());
+        let mut buffer = [0u8; 512];
+        let result = serialize!(message(&value), buffer);
+        assert_eq!(result, data);
+        value
+    }
+
+    #[test]
+    fn test_query() {
+        let value = round_trip(&[
+            0x12, 0x34, 0x01, 0x20, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x07, 0x65,
+            0x78, 0x61, 0x6d, 0x70, 0x6c, 0x65, 0x03, 0x63, 0x6f, 0x6d, 0x00, 0x00, 0x01, 0x00,
+            0x01, 0x00, 0x00, 0x29, 0x04, 0xd0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x00, 0x0a,
+            0x00, 0x08, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08,
+        ]);
+        assert_eq!(value.header.id, 0x1234);
+        assert_eq!(value.header.opcode, Opcode::Query);
+        assert_eq!(
+            value.header.flags,
+            HeaderFlags::RECURSION_DESIRED | HeaderFlags::AUTHENTIC_DATA
+        );
+        assert_eq!(
+            value.questions,
+            [Question {
+                name: "example.com".parse().unwrap(),
+                record_type: RecordType::A,
+                class: Class::In,
+            }]
+        );
+        assert!(value.additionals.is_empty());
+        let edns = value.edns.unwrap();
+        assert_eq!(edns.udp_payload_size, 1232);
+        assert_eq!(
+            edns.options,
+            [EdnsOption {
+                code: 10,
+                data: vec![1, 2, 3, 4, 5, 6, 7, 8],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_compression() {
+        let value = round_trip(&[
+            0xbe, 0xef, 0x81, 0x80, 0x00, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x05, 0x67,
+            0x6d, 0x61, 0x69, 0x6c, 0x03, 0x63, 0x6f, 0x6d, 0x00, 0x00, 0x0f, 0x00, 0x01, 0xc0,
+            0x0c, 0x00, 0x0f, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10, 0x00, 0x1b, 0x00, 0x05, 0x0d,
+            0x67, 0x6d, 0x61, 0x69, 0x6c, 0x2d, 0x73, 0x6d, 0x74, 0x70, 0x2d, 0x69, 0x6e, 0x01,
+            0x6c, 0x06, 0x67, 0x6f, 0x6f, 0x67, 0x6c, 0x65, 0xc0, 0x12, 0xc0, 0x0c, 0x00, 0x0f,
+            0x00, 0x01, 0x00, 0x00, 0x0e, 0x10, 0x00, 0x09, 0x00, 0x0a, 0x04, 0x61, 0x6c, 0x74,
+            0x31, 0xc0, 0x29,
+        ]);
+        assert!(value.header.is_response);
+        assert_eq!(
+            value.answers[1].data,
+            RecordData::Mx {
+                preference: 10,
+                exchange: "alt1.gmail-smtp-in.l.google.com".parse().unwrap(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_name_error() {
+        let value = round_trip(&[
+            0x00, 0x01, 0x81, 0x83, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x04, 0x6e,
+            0x6f, 0x70, 0x65, 0x07, 0x65, 0x78, 0x61, 0x6d, 0x70, 0x6c, 0x65, 0x03, 0x63, 0x6f,
+            0x6d, 0x00, 0x00, 0x01, 0x00, 0x01, 0xc0, 0x11, 0x00, 0x06, 0x00, 0x01, 0x00, 0x00,
+            0x03, 0x84, 0x00, 0x27, 0x03, 0x6e, 0x73, 0x31, 0xc0, 0x11, 0x0a, 0x68, 0x6f, 0x73,
+            0x74, 0x6d, 0x61, 0x73, 0x74, 0x65, 0x72, 0xc0, 0x11, 0x78, 0xc3, 0xdc, 0x29, 0x00,
+            0x00, 0x1c, 0x20, 0x00, 0x00, 0x0e, 0x10, 0x00, 0x12, 0x75, 0x00, 0x00, 0x00, 0x01,
+            0x2c,
+        ]);
+        assert_eq!(value.header.response_code, ResponseCode::NameError);
+        assert_eq!(
+            value.authorities[0].data,
+            RecordData::Soa(Soa {
+                primary: "ns1.example.com".parse().unwrap(),
+                responsible: "hostmaster.example.com".parse().unwrap(),
+                serial: 2026101801,
+                refresh: 7200,
+                retry: 3600,
+                expire: 1209600,
+                minimum: 300,
+            })
+        );
+    }
+
+    #[test]
+    fn test_records() {
+        let record = |name: &str, data| Record {
+            name: name.parse().unwrap(),
+            class: Class::In,
+            ttl: 60,
+            data,
+        };
+        let value = Message {
+            header: Header {
+                id: 7,
+                is_response: true,
+                opcode: Opcode::Query,
+                flags: HeaderFlags::AUTHORITATIVE_ANSWER,
+                response_code: ResponseCode::NoError,
+            },
+            questions: Vec::new(),
+            answers: vec![
+                record("lararium", RecordData::Ns("ns.lararium".parse().unwrap())),
+                record("ns.lararium", RecordData::Aaaa("fd00::1".parse().unwrap())),
+                record(
+                    "www.lararium",
+                    RecordData::Cname("ns.lararium".parse().unwrap()),
+                ),
+                record(
+                    "lararium",
+                    RecordData::Txt(vec![b"v=spf1 -all".to_vec(), Vec::new()]),
+                ),
+                record(
+                    "_mqtt._tcp.lararium",
+                    RecordData::Srv {
+                        priority: 0,
+                        weight: 5,
+                        port: 1883,
+                        target: "ns.lararium".parse().unwrap(),
+                    },
+                ),
+                record(
+                    "1.0.168.192.in-addr.arpa",
+                    RecordData::Ptr("ns.lararium".parse().unwrap()),
+                ),
+                record(
+                    "lararium",
+                    RecordData::Unknown {
+                        record_type: 99,
+                        data: vec![1, 2, 3],
+                    },
+                ),
+            ],
+            authorities: Vec::new(),
+            additionals: Vec::new(),
+            edns: None,
+        };
+        let mut buffer = [0u8; 512];
+        let data = serialize!(message(&value), buffer);
+        let (input, result) = decode::message(data).unwrap();
+        assert!(input.is_empty());
+        assert_eq!(result, value);
+    }
+
+    #[test]
+    fn test_tcp_message() {
+        let value = Message {
+            header: Header {
+                id: 1,
+                is_response: false,
+                opcode: Opcode::Query,
+                flags: HeaderFlags::RECURSION_DESIRED,
+                response_code: ResponseCode::NoError,
+            },
+            questions: vec![Question {
+                name: Name::root(),
+                record_type: RecordType::Ns,
+                class: Class::In,
+            }],
+            answers: Vec::new(),
+            authorities: Vec::new(),
+            additionals: Vec::new(),
+            edns: None,
+        };
+        let mut buffer = [0u8; 64];
+        let result = serialize!(tcp_message(&value), buffer);
+        assert_eq!(
+            result,
+            &[
+                0x00, 0x11, 0x00, 0x01, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x02, 0x00, 0x01
+            ]
+        );
+        assert_eq!(decode::tcp_message(result).unwrap().1, value);
+    }
+}
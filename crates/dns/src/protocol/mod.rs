@@ -1,2 +1,483 @@
 pub mod decode;
 pub mod encode;
+
+use bitflags::bitflags;
+use derive_more::{Deref, Into};
+use num_derive::FromPrimitive;
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+// RFC 1035
+
+const MAX_LABEL_LENGTH: usize = 63;
+const MAX_NAME_LENGTH: usize = 255;
+const TYPE_OPT: u16 = 41;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub header: Header,
+    pub questions: Vec<Question>,
+    pub answers: Vec<Record>,
+    pub authorities: Vec<Record>,
+    /// The additional records, apart from the OPT record, which is `edns`.
+    pub additionals: Vec<Record>,
+    pub edns: Option<Edns>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub id: u16,
+    pub is_response: bool,
+    pub opcode: Opcode,
+    pub flags: HeaderFlags,
+    pub response_code: ResponseCode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    Query,
+    InverseQuery,
+    Status,
+    Notify,
+    Update,
+    StatefulOperations,
+    Unknown(u8),
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct HeaderFlags: u16 {
+        const AUTHORITATIVE_ANSWER = 0x0400;
+        const TRUNCATED            = 0x0200;
+        const RECURSION_DESIRED    = 0x0100;
+        const RECURSION_AVAILABLE  = 0x0080;
+        const AUTHENTIC_DATA       = 0x0020;
+        const CHECKING_DISABLED    = 0x0010;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+pub enum ResponseCode {
+    NoError = 0,
+    FormatError = 1,
+    ServerFailure = 2,
+    NameError = 3,
+    NotImplemented = 4,
+    Refused = 5,
+    YxDomain = 6,
+    YxRrSet = 7,
+    NxRrSet = 8,
+    NotAuth = 9,
+    NotZone = 10,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Question {
+    pub name: Name,
+    pub record_type: RecordType,
+    pub class: Class,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub name: Name,
+    pub class: Class,
+    pub ttl: u32,
+    pub data: RecordData,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordData {
+    A(Ipv4Addr),
+    Ns(Name),
+    Cname(Name),
+    Soa(Soa),
+    Ptr(Name),
+    Mx {
+        preference: u16,
+        exchange: Name,
+    },
+    /// The character strings of the record, each up to 255 bytes.
+    Txt(Vec<Vec<u8>>),
+    Aaaa(Ipv6Addr),
+    Srv {
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: Name,
+    },
+    /// The data of a record of another type, as it was on the wire.
+    Unknown {
+        record_type: u16,
+        data: Vec<u8>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Soa {
+    pub primary: Name,
+    /// The mailbox of whoever is responsible for the zone, with the `@` as the first dot.
+    pub responsible: Name,
+    pub serial: u32,
+    pub refresh: u32,
+    pub retry: u32,
+    pub expire: u32,
+    /// The time to live of negative answers (RFC 2308).
+    pub minimum: u32,
+}
+
+/// The OPT pseudo-record of EDNS(0) (RFC 6891).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edns {
+    pub udp_payload_size: u16,
+    /// The upper 8 bits of the response code.
+    pub extended_response_code: u8,
+    pub version: u8,
+    pub dnssec_ok: bool,
+    pub options: Vec<EdnsOption>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EdnsOption {
+    pub code: u16,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RecordType {
+    A,
+    Ns,
+    Cname,
+    Soa,
+    Ptr,
+    Mx,
+    Txt,
+    Aaaa,
+    Srv,
+    Opt,
+    /// `*`, which only occurs in questions.
+    Any,
+    Unknown(u16),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Class {
+    In,
+    Chaos,
+    Hesiod,
+    Any,
+    Unknown(u16),
+}
+
+/// A domain name as its labels, from the most specific one and without the empty label of the
+/// root.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Deref, Into)]
+pub struct Name(Vec<Vec<u8>>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameError {
+    EmptyLabel,
+    LabelTooLong,
+    NameTooLong,
+    /// A `\DDD` escape above 255.
+    InvalidEscape,
+}
+
+impl std::error::Error for NameError {}
+
+impl fmt::Display for NameError {
+    fn fmt(
+        &self,
+        fmt: &mut fmt::Formatter,
+    ) -> fmt::Result {
+        write!(fmt, "{self:?}")
+    }
+}
+
+impl Name {
+    pub fn root() -> Self {
+        Self(Vec::new())
+    }
+
+    pub fn from_labels(labels: Vec<Vec<u8>>) -> Result<Self, NameError> {
+        if labels.iter().any(Vec::is_empty) {
+            return Err(NameError::EmptyLabel);
+        }
+        if labels.iter().any(|label| label.len() > MAX_LABEL_LENGTH) {
+            return Err(NameError::LabelTooLong);
+        }
+        let name = Self(labels);
+        if name.wire_length() > MAX_NAME_LENGTH {
+            return Err(NameError::NameTooLong);
+        }
+        Ok(name)
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The name with `label` in front.
+    pub fn prepend(
+        &self,
+        label: impl Into<Vec<u8>>,
+    ) -> Result<Self, NameError> {
+        let mut labels = vec![label.into()];
+        labels.extend(self.0.iter().cloned());
+        Self::from_labels(labels)
+    }
+
+    /// The name without its first label, or `None` for the root.
+    pub fn parent(&self) -> Option<Self> {
+        self.0.split_first().map(|(_, rest)| Self(rest.to_vec()))
+    }
+
+    /// Whether the name is `other` or below it, where letters match whatever their case.
+    pub fn is_subdomain_of(
+        &self,
+        other: &Name,
+    ) -> bool {
+        self.0.len() >= other.0.len()
+            && self.0[self.0.len() - other.0.len()..]
+                .iter()
+                .zip(&other.0)
+                .all(|(a, b)| a.eq_ignore_ascii_case(b))
+    }
+
+    pub fn to_lowercase(&self) -> Self {
+        Self(
+            self.0
+                .iter()
+                .map(|label| label.to_ascii_lowercase())
+                .collect(),
+        )
+    }
+
+    fn wire_length(&self) -> usize {
+        self.0.iter().map(|label| 1 + label.len()).sum::<usize>() + 1
+    }
+}
+
+impl FromStr for Name {
+    type Err = NameError;
+
+    /// Parses a name in presentation format, with or without the final dot. Dots within labels
+    /// are escaped as `\.` and other bytes as `\DDD`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "." {
+            return Ok(Self::root());
+        }
+        let s = s
+            .strip_suffix('.')
+            .filter(|s| !s.ends_with('\\'))
+            .unwrap_or(s);
+        let bytes = s.as_bytes();
+        let mut labels = Vec::new();
+        let mut label = Vec::new();
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'.' => labels.push(std::mem::take(&mut label)),
+                b'\\' => {
+                    let value = bytes
+                        .get(i + 1..i + 4)
+                        .filter(|digits| digits.iter().all(u8::is_ascii_digit))
+                        .map(|digits| {
+                            digits
+                                .iter()
+                                .fold(0u16, |value, digit| value * 10 + (digit - b'0') as u16)
+                        });
+                    match value {
+                        Some(value) => {
+                            label.push(u8::try_from(value).map_err(|_| NameError::InvalidEscape)?);
+                            i += 3;
+                        }
+                        None => {
+                            label.extend(bytes.get(i + 1));
+                            i += 1;
+                        }
+                    }
+                }
+                byte => label.push(byte),
+            }
+            i += 1;
+        }
+        labels.push(label);
+        Self::from_labels(labels)
+    }
+}
+
+impl fmt::Display for Name {
+    fn fmt(
+        &self,
+        fmt: &mut fmt::Formatter,
+    ) -> fmt::Result {
+        if self.is_root() {
+            return write!(fmt, ".");
+        }
+        for label in &self.0 {
+            for byte in label {
+                match byte {
+                    b'.' | b'\\' => write!(fmt, "\\{}", *byte as char)?,
+                    0x21..=0x7e => write!(fmt, "{}", *byte as char)?,
+                    _ => write!(fmt, "\\{byte:03}")?,
+                }
+            }
+            write!(fmt, ".")?;
+        }
+        Ok(())
+    }
+}
+
+impl RecordData {
+    pub fn record_type(&self) -> RecordType {
+        match self {
+            RecordData::A(_) => RecordType::A,
+            RecordData::Ns(_) => RecordType::Ns,
+            RecordData::Cname(_) => RecordType::Cname,
+            RecordData::Soa(_) => RecordType::Soa,
+            RecordData::Ptr(_) => RecordType::Ptr,
+            RecordData::Mx { .. } => RecordType::Mx,
+            RecordData::Txt(_) => RecordType::Txt,
+            RecordData::Aaaa(_) => RecordType::Aaaa,
+            RecordData::Srv { .. } => RecordType::Srv,
+            RecordData::Unknown { record_type, .. } => RecordType::from(*record_type),
+        }
+    }
+}
+
+impl From<u8> for Opcode {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Opcode::Query,
+            1 => Opcode::InverseQuery,
+            2 => Opcode::Status,
+            4 => Opcode::Notify,
+            5 => Opcode::Update,
+            6 => Opcode::StatefulOperations,
+            value => Opcode::Unknown(value),
+        }
+    }
+}
+
+impl From<Opcode> for u8 {
+    fn from(value: Opcode) -> Self {
+        match value {
+            Opcode::Query => 0,
+            Opcode::InverseQuery => 1,
+            Opcode::Status => 2,
+            Opcode::Notify => 4,
+            Opcode::Update => 5,
+            Opcode::StatefulOperations => 6,
+            Opcode::Unknown(value) => value,
+        }
+    }
+}
+
+impl From<u16> for RecordType {
+    fn from(value: u16) -> Self {
+        match value {
+            1 => RecordType::A,
+            2 => RecordType::Ns,
+            5 => RecordType::Cname,
+            6 => RecordType::Soa,
+            12 => RecordType::Ptr,
+            15 => RecordType::Mx,
+            16 => RecordType::Txt,
+            28 => RecordType::Aaaa,
+            33 => RecordType::Srv,
+            41 => RecordType::Opt,
+            255 => RecordType::Any,
+            value => RecordType::Unknown(value),
+        }
+    }
+}
+
+impl From<RecordType> for u16 {
+    fn from(value: RecordType) -> Self {
+        match value {
+            RecordType::A => 1,
+            RecordType::Ns => 2,
+            RecordType::Cname => 5,
+            RecordType::Soa => 6,
+            RecordType::Ptr => 12,
+            RecordType::Mx => 15,
+            RecordType::Txt => 16,
+            RecordType::Aaaa => 28,
+            RecordType::Srv => 33,
+            RecordType::Opt => 41,
+            RecordType::Any => 255,
+            RecordType::Unknown(value) => value,
+        }
+    }
+}
+
+impl From<u16> for Class {
+    fn from(value: u16) -> Self {
+        match value {
+            1 => Class::In,
+            3 => Class::Chaos,
+            4 => Class::Hesiod,
+            255 => Class::Any,
+            value => Class::Unknown(value),
+        }
+    }
+}
+
+impl From<Class> for u16 {
+    fn from(value: Class) -> Self {
+        match value {
+            Class::In => 1,
+            Class::Chaos => 3,
+            Class::Hesiod => 4,
+            Class::Any => 255,
+            Class::Unknown(value) => value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_name_presentation_format() {
+        let name = Name::from_str("gateway.lararium.").unwrap();
+        assert_eq!(name.len(), 2);
+        assert_eq!(name, Name::from_str("gateway.lararium").unwrap());
+        assert_eq!(name.to_string(), "gateway.lararium.");
+        let name = Name::from_str(r"a\.b.c\032d").unwrap();
+        assert_eq!(*name, vec![b"a.b".to_vec(), b"c d".to_vec()]);
+        assert_eq!(name.to_string(), r"a\.b.c\032d.");
+        assert_eq!(Name::from_str(".").unwrap(), Name::root());
+        assert_eq!(Name::from_str("a..b"), Err(NameError::EmptyLabel));
+        assert_eq!(*Name::from_str(r"\255").unwrap(), vec![vec![255]]);
+        assert_eq!(Name::from_str(r"a\256"), Err(NameError::InvalidEscape));
+        assert_eq!(
+            Name::from_str(r"\999.lararium"),
+            Err(NameError::InvalidEscape)
+        );
+        assert_eq!(
+            Name::from_str(&"a".repeat(64)),
+            Err(NameError::LabelTooLong)
+        );
+        assert_eq!(
+            Name::from_str(&vec!["a".repeat(63); 4].join(".")),
+            Err(NameError::NameTooLong)
+        );
+    }
+
+    #[test]
+    fn test_is_subdomain_of() {
+        let zone = Name::from_str("lararium").unwrap();
+        assert!(Name::from_str("Gateway.LARARIUM")
+            .unwrap()
+            .is_subdomain_of(&zone));
+        assert!(zone.is_subdomain_of(&zone));
+        assert!(zone.is_subdomain_of(&Name::root()));
+        assert!(!Name::from_str("lararium.net")
+            .unwrap()
+            .is_subdomain_of(&zone));
+    }
+}
@@ -1 +1,18 @@
-pub trait Handler {}
+use crate::{Question, Record, ResponseCode};
+use std::future::Future;
+
+/// An authoritative answer to a question.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Answer {
+    pub response_code: ResponseCode,
+    pub answers: Vec<Record>,
+    pub authorities: Vec<Record>,
+}
+
+pub trait Handler {
+    /// Answers the question, or returns `None` for names outside the zones of the handler.
+    fn handle_question(
+        &self,
+        question: &Question,
+    ) -> impl Future<Output = Option<Answer>> + Send;
+}
@@ -1,13 +1,28 @@
 mod error;
 mod handler;
+mod zone;
 
 pub use error::Error;
-pub use handler::Handler;
+pub use handler::{Answer, Handler};
+pub use zone::Zone;
 
+use crate::*;
+use cookie_factory::gen;
+use std::io::{self, Cursor};
 use std::net::SocketAddr;
 use std::sync::Arc;
-// use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::{TcpListener, UdpSocket};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+
+/// The largest response over UDP to clients without EDNS (RFC 1035 2.3.4).
+const MAX_UDP_LENGTH: usize = 512;
+/// The largest response over UDP to clients with EDNS, which stays clear of fragmentation.
+const UDP_PAYLOAD_SIZE: u16 = 1232;
+/// How long connections over TCP may wait between queries (RFC 7766 6.2.3).
+const TCP_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+const TYPE_IXFR: u16 = 251;
+const TYPE_AXFR: u16 = 252;
 
 #[derive(Clone)]
 pub struct Server {
@@ -15,6 +30,12 @@ pub struct Server {
     tcp_listener: Arc<TcpListener>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Transport {
+    Udp,
+    Tcp,
+}
+
 impl Server {
     pub async fn bind(listen_address: SocketAddr) -> Result<Self, Error> {
         Ok(Self {
@@ -44,12 +65,15 @@ impl Server {
     where
         T: Handler,
     {
+        let mut buffer = [0; u16::MAX as usize];
         loop {
-            let mut buffer = [0; 512];
             let (size, address) = self.udp_socket.recv_from(&mut buffer).await?;
-            let message = &buffer[..size];
-            tracing::debug!("DNS/UDP: {message:?}");
-            // self.udp_socket.send_to(&response, address).await?;
+            let Some(response) = respond(&handler, &buffer[..size], Transport::Udp).await else {
+                continue;
+            };
+            if let Err(error) = self.udp_socket.send_to(&response, address).await {
+                tracing::debug!("Failed to respond to {address}: {error}");
+            }
         }
     }
 
@@ -64,7 +88,284 @@ impl Server {
             let (socket, address) = self.tcp_listener.accept().await?;
             let handler = handler.clone();
             tracing::debug!("DNS/TCP: {address}");
-            tokio::spawn(async move {});
+            tokio::spawn(async move {
+                if let Err(error) = handle_connection(&handler, socket).await {
+                    tracing::debug!("Error handling connection from {address}: {error}");
+                }
+            });
+        }
+    }
+}
+
+/// Answers the queries of a connection, each of which comes after its length.
+async fn handle_connection<T: Handler>(
+    handler: &T,
+    mut socket: TcpStream,
+) -> Result<(), Error> {
+    loop {
+        let length = match tokio::time::timeout(TCP_IDLE_TIMEOUT, socket.read_u16()).await {
+            Ok(Ok(length)) => length,
+            Ok(Err(error)) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Ok(Err(error)) => return Err(error.into()),
+            Err(_) => return Ok(()),
+        };
+        let mut request = vec![0; length as usize];
+        socket.read_exact(&mut request).await?;
+        if let Some(response) = respond(handler, &request, Transport::Tcp).await {
+            socket.write_u16(response.len() as u16).await?;
+            socket.write_all(&response).await?;
+        }
+    }
+}
+
+/// The encoded response to a request, or `None` for requests that get none.
+async fn respond<T: Handler>(
+    handler: &T,
+    request: &[u8],
+    transport: Transport,
+) -> Option<Vec<u8>> {
+    let Ok((_, request)) = decode::message(request) else {
+        let mut response = format_error(request)?;
+        return encode(&mut response, MAX_UDP_LENGTH);
+    };
+    if request.header.is_response {
+        return None;
+    }
+    tracing::debug!("DNS/{transport:?}: {:?}", request.questions);
+    let limit = match (transport, &request.edns) {
+        (Transport::Tcp, _) => u16::MAX as usize,
+        (Transport::Udp, None) => MAX_UDP_LENGTH,
+        (Transport::Udp, Some(edns)) => {
+            (edns.udp_payload_size as usize).clamp(MAX_UDP_LENGTH, UDP_PAYLOAD_SIZE as usize)
+        }
+    };
+    let mut response = Message {
+        header: Header {
+            id: request.header.id,
+            is_response: true,
+            opcode: request.header.opcode,
+            flags: request.header.flags & HeaderFlags::RECURSION_DESIRED,
+            response_code: ResponseCode::NoError,
+        },
+        questions: request.questions.clone(),
+        answers: Vec::new(),
+        authorities: Vec::new(),
+        additionals: Vec::new(),
+        edns: request.edns.as_ref().map(|_| Edns {
+            udp_payload_size: UDP_PAYLOAD_SIZE,
+            extended_response_code: 0,
+            version: 0,
+            dnssec_ok: false,
+            options: Vec::new(),
+        }),
+    };
+    match (&request.edns, request.questions.as_slice()) {
+        // BADVERS, whose upper bits go in the OPT record (RFC 6891 6.1.3).
+        (Some(edns), _) if edns.version > 0 => {
+            if let Some(edns) = &mut response.edns {
+                edns.extended_response_code = 1;
+            }
+        }
+        _ if request.header.opcode != Opcode::Query => {
+            response.header.response_code = ResponseCode::NotImplemented;
+        }
+        (_, [question]) => match question.record_type {
+            RecordType::Unknown(TYPE_IXFR | TYPE_AXFR) => {
+                response.header.response_code = ResponseCode::Refused;
+            }
+            _ => match handler.handle_question(question).await {
+                Some(answer) => {
+                    response.header.flags |= HeaderFlags::AUTHORITATIVE_ANSWER;
+                    response.header.response_code = answer.response_code;
+                    response.answers = answer.answers;
+                    response.authorities = answer.authorities;
+                }
+                None => response.header.response_code = ResponseCode::Refused,
+            },
+        },
+        _ => response.header.response_code = ResponseCode::FormatError,
+    }
+    encode(&mut response, limit)
+}
+
+/// A response to a request that could not be decoded, if it has a header to respond to.
+fn format_error(request: &[u8]) -> Option<Message> {
+    let [first, second, third, ..] = *request else {
+        return None;
+    };
+    if third & 0x80 != 0 {
+        return None;
+    }
+    Some(Message {
+        header: Header {
+            id: u16::from_be_bytes([first, second]),
+            is_response: true,
+            opcode: Opcode::from((third >> 3) & 0xf),
+            flags: HeaderFlags::empty(),
+            response_code: ResponseCode::FormatError,
+        },
+        questions: Vec::new(),
+        answers: Vec::new(),
+        authorities: Vec::new(),
+        additionals: Vec::new(),
+        edns: None,
+    })
+}
+
+/// Encodes the response in at most `limit` bytes. Responses that do not fit lose their records
+/// and are marked as truncated, so that the client asks again over TCP.
+fn encode(
+    response: &mut Message,
+    limit: usize,
+) -> Option<Vec<u8>> {
+    let mut buffer = vec![0; limit];
+    if let Ok((_, length)) = gen(encode::message(response), Cursor::new(&mut buffer[..])) {
+        buffer.truncate(length as usize);
+        return Some(buffer);
+    }
+    response.answers.clear();
+    response.authorities.clear();
+    response.additionals.clear();
+    response.header.flags |= HeaderFlags::TRUNCATED;
+    match gen(encode::message(response), Cursor::new(&mut buffer[..])) {
+        Ok((_, length)) => {
+            buffer.truncate(length as usize);
+            Some(buffer)
+        }
+        Err(error) => {
+            tracing::warn!("Failed to encode response: {error:?}");
+            None
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zone() -> Zone {
+        let zone = Zone::new(
+            "lararium".parse().unwrap(),
+            "gateway.lararium".parse().unwrap(),
+        );
+        let gateway = (
+            "gateway.lararium".parse().unwrap(),
+            "192.168.1.1".parse().unwrap(),
+        );
+        // More addresses than fit in 512 bytes.
+        let many = (1..=50).map(|i| {
+            (
+                "many.lararium".parse().unwrap(),
+                format!("192.168.2.{i}").parse().unwrap(),
+            )
+        });
+        zone.set_hosts(std::iter::once(gateway).chain(many));
+        zone
+    }
+
+    fn query(
+        name: &str,
+        edns: Option<Edns>,
+    ) -> Vec<u8> {
+        let query = Message {
+            header: Header {
+                id: 42,
+                is_response: false,
+                opcode: Opcode::Query,
+                flags: HeaderFlags::RECURSION_DESIRED,
+                response_code: ResponseCode::NoError,
+            },
+            questions: vec![Question {
+                name: name.parse().unwrap(),
+                record_type: RecordType::A,
+                class: Class::In,
+            }],
+            answers: Vec::new(),
+            authorities: Vec::new(),
+            additionals: Vec::new(),
+            edns,
+        };
+        let mut buffer = [0u8; 512];
+        let (_, length) = gen(encode::message(&query), Cursor::new(&mut buffer[..])).unwrap();
+        buffer[..length as usize].to_vec()
+    }
+
+    async fn exchange(
+        request: &[u8],
+        transport: Transport,
+    ) -> Message {
+        let response = respond(&zone(), request, transport).await.unwrap();
+        decode::message(&response).unwrap().1
+    }
+
+    #[tokio::test]
+    async fn test_answer() {
+        let response = exchange(&query("gateway.lararium", None), Transport::Udp).await;
+        assert_eq!(response.header.id, 42);
+        assert!(response.header.is_response);
+        assert_eq!(
+            response.header.flags,
+            HeaderFlags::AUTHORITATIVE_ANSWER | HeaderFlags::RECURSION_DESIRED
+        );
+        assert_eq!(response.header.response_code, ResponseCode::NoError);
+        assert_eq!(response.questions.len(), 1);
+        assert_eq!(response.answers.len(), 1);
+        assert!(response.edns.is_none());
+        let response = exchange(&query("example.com", None), Transport::Udp).await;
+        assert_eq!(response.header.response_code, ResponseCode::Refused);
+        assert_eq!(response.header.flags, HeaderFlags::RECURSION_DESIRED);
+    }
+
+    #[tokio::test]
+    async fn test_truncation() {
+        let response = exchange(&query("many.lararium", None), Transport::Udp).await;
+        assert!(response.header.flags.contains(HeaderFlags::TRUNCATED));
+        assert!(response.answers.is_empty());
+        let edns = Edns {
+            udp_payload_size: 4096,
+            extended_response_code: 0,
+            version: 0,
+            dnssec_ok: false,
+            options: Vec::new(),
+        };
+        let response = exchange(&query("many.lararium", Some(edns)), Transport::Udp).await;
+        assert!(!response.header.flags.contains(HeaderFlags::TRUNCATED));
+        assert_eq!(response.answers.len(), 50);
+        assert_eq!(response.edns.unwrap().udp_payload_size, UDP_PAYLOAD_SIZE);
+        let response = exchange(&query("many.lararium", None), Transport::Tcp).await;
+        assert_eq!(response.answers.len(), 50);
+    }
+
+    #[tokio::test]
+    async fn test_errors() {
+        let mut request = query("gateway.lararium", None);
+        request.pop();
+        let response = exchange(&request, Transport::Udp).await;
+        assert_eq!(response.header.id, 42);
+        assert_eq!(response.header.response_code, ResponseCode::FormatError);
+        let mut request = query("gateway.lararium", None);
+        request[2] |= u8::from(Opcode::Update) << 3;
+        let response = exchange(&request, Transport::Udp).await;
+        assert_eq!(response.header.opcode, Opcode::Update);
+        assert_eq!(response.header.response_code, ResponseCode::NotImplemented);
+        let mut request = query("gateway.lararium", None);
+        request[2] |= 3 << 3;
+        let response = exchange(&request, Transport::Udp).await;
+        assert_eq!(response.header.opcode, Opcode::Unknown(3));
+        assert_eq!(response.header.response_code, ResponseCode::NotImplemented);
+        let edns = Edns {
+            udp_payload_size: 1232,
+            extended_response_code: 0,
+            version: 1,
+            dnssec_ok: false,
+            options: Vec::new(),
+        };
+        let response = exchange(&query("gateway.lararium", Some(edns)), Transport::Udp).await;
+        assert_eq!(response.edns.unwrap().extended_response_code, 1);
+        assert!(response.answers.is_empty());
+        let mut request = query("gateway.lararium", None);
+        request[2] |= 0x80;
+        assert!(respond(&zone(), &request, Transport::Udp).await.is_none());
+        assert!(respond(&zone(), &[0x00], Transport::Udp).await.is_none());
+    }
+}
@@ -0,0 +1,474 @@
+use super::{Answer, Handler};
+use crate::{Class, Name, Question, Record, RecordData, RecordType, ResponseCode, Soa};
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::RwLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How long resolvers may keep records, and the absence of records, of the zone.
+const DEFAULT_TTL: u32 = 60;
+
+/// A zone of hosts whose addresses change while the server runs. The zone also answers for the
+/// reverse names of the addresses, and is authoritative for the reverse zones of its networks.
+pub struct Zone {
+    origin: Name,
+    primary: Name,
+    ttl: u32,
+    reverse_zones: Vec<Name>,
+    state: RwLock<State>,
+}
+
+struct State {
+    serial: u32,
+    hosts: BTreeMap<Name, BTreeSet<IpAddr>>,
+    pointers: BTreeMap<Name, BTreeSet<Name>>,
+}
+
+impl Zone {
+    /// A zone without hosts, served by the name server `primary`.
+    pub fn new(
+        origin: Name,
+        primary: Name,
+    ) -> Self {
+        // Starting from the time keeps the serial increasing across restarts.
+        let serial = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs() as u32)
+            .unwrap_or_default();
+        Self {
+            origin: origin.to_lowercase(),
+            primary,
+            ttl: DEFAULT_TTL,
+            reverse_zones: Vec::new(),
+            state: RwLock::new(State {
+                serial,
+                hosts: BTreeMap::new(),
+                pointers: BTreeMap::new(),
+            }),
+        }
+    }
+
+    pub fn with_ttl(
+        mut self,
+        ttl: u32,
+    ) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Makes the zone authoritative for the reverse names of the network of `address`, so that
+    /// names without hosts there do not exist.
+    pub fn with_reverse_network(
+        mut self,
+        address: IpAddr,
+        prefix_length: u8,
+    ) -> Self {
+        for zone in reverse_zones(address, prefix_length) {
+            if !self.reverse_zones.contains(&zone) {
+                self.reverse_zones.push(zone);
+            }
+        }
+        self
+    }
+
+    pub fn origin(&self) -> &Name {
+        &self.origin
+    }
+
+    pub fn serial(&self) -> u32 {
+        self.state.read().unwrap().serial
+    }
+
+    /// Replaces the addresses of the hosts, and bumps the serial if they changed. Hosts outside
+    /// the zone are left out.
+    pub fn set_hosts(
+        &self,
+        hosts: impl IntoIterator<Item = (Name, IpAddr)>,
+    ) {
+        let mut addresses = BTreeMap::<Name, BTreeSet<IpAddr>>::new();
+        for (host, address) in hosts {
+            if !host.is_subdomain_of(&self.origin) {
+                tracing::warn!("{host} is outside of {}", self.origin);
+                continue;
+            }
+            addresses
+                .entry(host.to_lowercase())
+                .or_default()
+                .insert(address);
+        }
+        let mut state = self.state.write().unwrap();
+        if state.hosts == addresses {
+            return;
+        }
+        let mut pointers = BTreeMap::<Name, BTreeSet<Name>>::new();
+        for (host, addresses) in &addresses {
+            for address in addresses {
+                pointers
+                    .entry(reverse(*address))
+                    .or_default()
+                    .insert(host.clone());
+            }
+        }
+        state.hosts = addresses;
+        state.pointers = pointers;
+        state.serial = state.serial.wrapping_add(1);
+        tracing::debug!("{} is now at serial {}", self.origin, state.serial);
+    }
+
+    /// Answers the question, or returns `None` for names the zone is not authoritative for.
+    pub fn lookup(
+        &self,
+        question: &Question,
+    ) -> Option<Answer> {
+        if !matches!(question.class, Class::In | Class::Any) {
+            return None;
+        }
+        let name = question.name.to_lowercase();
+        let state = self.state.read().unwrap();
+        let matches = |record_type: RecordType| {
+            question.record_type == record_type || question.record_type == RecordType::Any
+        };
+        let apex = std::iter::once(&self.origin)
+            .chain(&self.reverse_zones)
+            .filter(|apex| name.is_subdomain_of(apex))
+            .max_by_key(|apex| apex.len());
+        let Some(apex) = apex else {
+            // Hosts may have addresses outside of the networks of the zone, whose reverse names
+            // are answered all the same.
+            let hosts = state.pointers.get(&name)?;
+            let answers = match matches(RecordType::Ptr) {
+                true => hosts
+                    .iter()
+                    .map(|host| self.record(&question.name, RecordData::Ptr(host.clone())))
+                    .collect(),
+                false => Vec::new(),
+            };
+            return Some(Answer {
+                response_code: ResponseCode::NoError,
+                answers,
+                authorities: Vec::new(),
+            });
+        };
+        let mut data = Vec::new();
+        if name == *apex {
+            data.push(RecordData::Soa(self.soa(state.serial)));
+            data.push(RecordData::Ns(self.primary.clone()));
+        }
+        for address in state.hosts.get(&name).into_iter().flatten() {
+            data.push(match address {
+                IpAddr::V4(address) => RecordData::A(*address),
+                IpAddr::V6(address) => RecordData::Aaaa(*address),
+            });
+        }
+        for host in state.pointers.get(&name).into_iter().flatten() {
+            data.push(RecordData::Ptr(host.clone()));
+        }
+        let answers = data
+            .into_iter()
+            .filter(|data| matches(data.record_type()))
+            .map(|data| self.record(&question.name, data))
+            .collect::<Vec<_>>();
+        if !answers.is_empty() {
+            return Some(Answer {
+                response_code: ResponseCode::NoError,
+                answers,
+                authorities: Vec::new(),
+            });
+        }
+        // A name exists when it has records or names below it, even if none of its records have
+        // the type in question (RFC 8020).
+        let exists = name == *apex
+            || state.hosts.keys().any(|host| host.is_subdomain_of(&name))
+            || state
+                .pointers
+                .keys()
+                .any(|pointer| pointer.is_subdomain_of(&name));
+        let authority = self.record(apex, RecordData::Soa(self.soa(state.serial)));
+        Some(Answer {
+            response_code: match exists {
+                true => ResponseCode::NoError,
+                false => ResponseCode::NameError,
+            },
+            answers: Vec::new(),
+            authorities: vec![authority],
+        })
+    }
+
+    fn soa(
+        &self,
+        serial: u32,
+    ) -> Soa {
+        Soa {
+            primary: self.primary.clone(),
+            responsible: self
+                .origin
+                .prepend("hostmaster")
+                .unwrap_or_else(|_| self.origin.clone()),
+            serial,
+            refresh: 3600,
+            retry: 600,
+            expire: 86400,
+            minimum: self.ttl,
+        }
+    }
+
+    fn record(
+        &self,
+        name: &Name,
+        data: RecordData,
+    ) -> Record {
+        Record {
+            name: name.clone(),
+            class: Class::In,
+            ttl: self.ttl,
+            data,
+        }
+    }
+}
+
+impl Handler for Zone {
+    async fn handle_question(
+        &self,
+        question: &Question,
+    ) -> Option<Answer> {
+        self.lookup(question)
+    }
+}
+
+/// The name that points back to the host of `address`, under `in-addr.arpa` or `ip6.arpa`.
+fn reverse(address: IpAddr) -> Name {
+    let mut labels = match address {
+        IpAddr::V4(address) => {
+            let mut labels = address
+                .octets()
+                .iter()
+                .rev()
+                .map(|octet| octet.to_string().into_bytes())
+                .collect::<Vec<_>>();
+            labels.push(b"in-addr".to_vec());
+            labels
+        }
+        IpAddr::V6(address) => {
+            let mut labels = address
+                .octets()
+                .iter()
+                .rev()
+                .flat_map(|octet| [octet & 0xf, octet >> 4])
+                .map(|nibble| format!("{nibble:x}").into_bytes())
+                .collect::<Vec<_>>();
+            labels.push(b"ip6".to_vec());
+            labels
+        }
+    };
+    labels.push(b"arpa".to_vec());
+    Name::from_labels(labels).unwrap()
+}
+
+/// The reverse zones that cover the network of `address`, which are several where the prefix
+/// does not end on an octet, or on a nibble for IPv6.
+fn reverse_zones(
+    address: IpAddr,
+    prefix_length: u8,
+) -> Vec<Name> {
+    let (bits, unit, address) = match address {
+        IpAddr::V4(address) => (32, 8, u32::from(address) as u128),
+        IpAddr::V6(address) => (128, 4, u128::from(address)),
+    };
+    let prefix_length = (prefix_length as u32).min(bits);
+    let units = prefix_length.div_ceil(unit);
+    let host_bits = bits - units * unit;
+    let network = address & u128::MAX.checked_shl(bits - prefix_length).unwrap_or(0);
+    (0..1u128 << (units * unit - prefix_length))
+        .map(|subnet| {
+            let address = network | subnet.checked_shl(host_bits).unwrap_or(0);
+            let address = match bits {
+                32 => IpAddr::from(Ipv4Addr::from(address as u32)),
+                _ => IpAddr::from(Ipv6Addr::from(address)),
+            };
+            let labels = reverse(address)[(host_bits / unit) as usize..].to_vec();
+            Name::from_labels(labels).unwrap()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zone() -> Zone {
+        let zone = Zone::new(
+            "lararium".parse().unwrap(),
+            "gateway.lararium".parse().unwrap(),
+        );
+        zone.set_hosts([
+            (
+                "gateway.lararium".parse().unwrap(),
+                "192.168.1.1".parse().unwrap(),
+            ),
+            (
+                "gateway.lararium".parse().unwrap(),
+                "fd00::1".parse().unwrap(),
+            ),
+            (
+                "tv.living-room.lararium".parse().unwrap(),
+                "192.168.1.20".parse().unwrap(),
+            ),
+        ]);
+        zone
+    }
+
+    fn question(
+        name: &str,
+        record_type: RecordType,
+    ) -> Question {
+        Question {
+            name: name.parse().unwrap(),
+            record_type,
+            class: Class::In,
+        }
+    }
+
+    #[test]
+    fn test_lookup() {
+        let zone = zone();
+        let answer = zone
+            .lookup(&question("Gateway.Lararium", RecordType::A))
+            .unwrap();
+        assert_eq!(answer.response_code, ResponseCode::NoError);
+        assert_eq!(
+            answer.answers,
+            [Record {
+                name: "Gateway.Lararium".parse().unwrap(),
+                class: Class::In,
+                ttl: DEFAULT_TTL,
+                data: RecordData::A("192.168.1.1".parse().unwrap()),
+            }]
+        );
+        let answer = zone
+            .lookup(&question("gateway.lararium", RecordType::Any))
+            .unwrap();
+        assert_eq!(answer.answers.len(), 2);
+        let answer = zone.lookup(&question("lararium", RecordType::Soa)).unwrap();
+        assert_eq!(answer.answers[0].data.record_type(), RecordType::Soa);
+        assert!(zone
+            .lookup(&question("example.com", RecordType::A))
+            .is_none());
+    }
+
+    #[test]
+    fn test_negative_answers() {
+        let zone = zone();
+        let answer = zone
+            .lookup(&question("tv.living-room.lararium", RecordType::Aaaa))
+            .unwrap();
+        assert_eq!(answer.response_code, ResponseCode::NoError);
+        assert!(answer.answers.is_empty());
+        assert_eq!(answer.authorities[0].data.record_type(), RecordType::Soa);
+        // Names with names below them exist without records of their own.
+        let answer = zone
+            .lookup(&question("living-room.lararium", RecordType::A))
+            .unwrap();
+        assert_eq!(answer.response_code, ResponseCode::NoError);
+        let answer = zone
+            .lookup(&question("radio.lararium", RecordType::A))
+            .unwrap();
+        assert_eq!(answer.response_code, ResponseCode::NameError);
+        assert!(answer.answers.is_empty());
+        assert_eq!(answer.authorities[0].name, *zone.origin());
+    }
+
+    #[test]
+    fn test_reverse() {
+        let zone = zone();
+        let answer = zone
+            .lookup(&question("20.1.168.192.in-addr.arpa", RecordType::Ptr))
+            .unwrap();
+        assert_eq!(
+            answer.answers[0].data,
+            RecordData::Ptr("tv.living-room.lararium".parse().unwrap())
+        );
+        let name = format!("1.0.{}d.f.ip6.arpa", "0.".repeat(28));
+        let answer = zone.lookup(&question(&name, RecordType::Ptr)).unwrap();
+        assert_eq!(
+            answer.answers[0].data,
+            RecordData::Ptr("gateway.lararium".parse().unwrap())
+        );
+        assert!(zone
+            .lookup(&question("2.1.168.192.in-addr.arpa", RecordType::Ptr))
+            .is_none());
+        let zone = zone.with_reverse_network("192.168.1.1".parse().unwrap(), 24);
+        let answer = zone
+            .lookup(&question("2.1.168.192.in-addr.arpa", RecordType::Ptr))
+            .unwrap();
+        assert_eq!(answer.response_code, ResponseCode::NameError);
+        assert_eq!(
+            answer.authorities[0].name,
+            "1.168.192.in-addr.arpa".parse().unwrap()
+        );
+        let answer = zone
+            .lookup(&question("1.168.192.in-addr.arpa", RecordType::Soa))
+            .unwrap();
+        assert_eq!(answer.answers[0].data.record_type(), RecordType::Soa);
+        let answer = zone
+            .lookup(&question("20.1.168.192.in-addr.arpa", RecordType::A))
+            .unwrap();
+        assert_eq!(answer.response_code, ResponseCode::NoError);
+        assert!(answer.answers.is_empty());
+        assert!(zone
+            .lookup(&question("2.2.168.192.in-addr.arpa", RecordType::Ptr))
+            .is_none());
+    }
+
+    #[test]
+    fn test_reverse_zones() {
+        let names = |address: &str, prefix_length| {
+            reverse_zones(address.parse().unwrap(), prefix_length)
+                .iter()
+                .map(Name::to_string)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names("192.168.1.20", 24), ["1.168.192.in-addr.arpa."]);
+        assert_eq!(names("10.1.2.3", 8), ["10.in-addr.arpa."]);
+        assert_eq!(
+            names("172.16.5.1", 23),
+            ["4.16.172.in-addr.arpa.", "5.16.172.in-addr.arpa."]
+        );
+        assert_eq!(names("192.168.1.20", 32), ["20.1.168.192.in-addr.arpa."]);
+        assert_eq!(names("fd00::1", 8), ["d.f.ip6.arpa."]);
+        assert_eq!(
+            names("fd12:3456:789a:1::1", 64),
+            ["1.0.0.0.a.9.8.7.6.5.4.3.2.1.d.f.ip6.arpa."]
+        );
+        assert_eq!(names("0.0.0.0", 0), ["in-addr.arpa."]);
+    }
+
+    #[test]
+    fn test_serial() {
+        let zone = zone();
+        let serial = zone.serial();
+        zone.set_hosts([
+            (
+                "tv.living-room.lararium".parse().unwrap(),
+                "192.168.1.20".parse().unwrap(),
+            ),
+            (
+                "gateway.lararium".parse().unwrap(),
+                "fd00::1".parse().unwrap(),
+            ),
+            (
+                "gateway.lararium".parse().unwrap(),
+                "192.168.1.1".parse().unwrap(),
+            ),
+        ]);
+        assert_eq!(zone.serial(), serial);
+        zone.set_hosts([(
+            "gateway.lararium".parse().unwrap(),
+            "192.168.1.1".parse().unwrap(),
+        )]);
+        assert_eq!(zone.serial(), serial.wrapping_add(1));
+        let answer = zone
+            .lookup(&question("tv.living-room.lararium", RecordType::A))
+            .unwrap();
+        assert_eq!(answer.response_code, ResponseCode::NameError);
+    }
+}
@@ -0,0 +1,918 @@
+use crate::{
+    payload, protocol::*, ConnectReasonCode, DisconnectReasonCode, Encoding, Payload, Protocol,
+    QoS, SubscribeReasonCode,
+};
+use bytes::{Buf, BytesMut};
+use derive_more::From;
+use lararium::prelude::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot, OnceCell};
+use tokio_stream::Stream;
+
+/// Asynchronous MQTT 5.0 client.
+///
+/// The connection is owned by an event loop task which reconnects with exponential backoff when
+/// the connection is lost and restores all subscriptions afterwards. Publishes issued while
+/// disconnected are queued until the connection is back.
+#[derive(Clone)]
+pub struct AsyncClient {
+    commands: mpsc::UnboundedSender<Command>,
+    shared: Arc<Shared>,
+}
+
+/// Stream of messages received on the subscriptions of an [`AsyncClient`].
+pub struct Messages {
+    receiver: mpsc::UnboundedReceiver<Message>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub host: String,
+    pub port: u16,
+    pub client_identifier: String,
+    pub username: Option<String>,
+    pub password: Option<Vec<u8>>,
+    pub keep_alive: Duration,
+    pub min_backoff: Duration,
+    pub max_backoff: Duration,
+    pub request_timeout: Duration,
+    pub encoding: Encoding,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub topic: Topic,
+    pub payload: Option<Payload>,
+    pub response_topic: Option<Topic>,
+    pub correlation_data: Option<Vec<u8>>,
+}
+
+/// A publish as received, for forwarding without decoding its payload.
+#[cfg(feature = "bridge")]
+pub(crate) struct RawMessage {
+    pub topic: Topic,
+    pub properties: Properties,
+    pub payload: Vec<u8>,
+}
+
+#[derive(Debug, From)]
+pub enum Error {
+    #[from]
+    Protocol(crate::protocol::Error),
+    #[from]
+    Io(std::io::Error),
+    #[from]
+    Payload(payload::Error),
+    ConnectionRefused(ConnectReasonCode),
+    SubscriptionRejected(SubscribeReasonCode),
+    UnexpectedPacket,
+    NoResponseTopic,
+    Timeout,
+    Closed,
+}
+
+impl std::error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter,
+    ) -> Result<(), fmt::Error> {
+        write!(f, "{self:?}")
+    }
+}
+
+enum Command {
+    Publish {
+        topic: Topic,
+        properties: Properties,
+        payload: Vec<u8>,
+    },
+    Subscribe {
+        filter: Filter,
+        response: oneshot::Sender<Result<SubscribeReasonCode, Error>>,
+    },
+    Disconnect,
+}
+
+struct Shared {
+    client_identifier: Mutex<String>,
+    next_correlation_id: AtomicU64,
+    requests: Mutex<HashMap<Vec<u8>, oneshot::Sender<Message>>>,
+    response_subscription: OnceCell<()>,
+    request_timeout: Duration,
+    encoding: Encoding,
+}
+
+struct EventLoop {
+    options: Options,
+    shared: Arc<Shared>,
+    commands: mpsc::UnboundedReceiver<Command>,
+    messages: mpsc::UnboundedSender<Message>,
+    #[cfg(feature = "bridge")]
+    raw_messages: Option<mpsc::UnboundedSender<RawMessage>>,
+    subscriptions: Vec<Filter>,
+    pending_subscriptions: Vec<(Filter, oneshot::Sender<Result<SubscribeReasonCode, Error>>)>,
+    next_packet_identifier: u16,
+}
+
+struct Connection {
+    stream: TcpStream,
+    buffer: BytesMut,
+    in_flight_subscriptions: HashMap<u16, Filter>,
+}
+
+enum Exit {
+    Disconnected,
+    ConnectionLost(Error),
+}
+
+impl Options {
+    pub fn new(
+        host: impl Into<String>,
+        port: u16,
+    ) -> Self {
+        Self {
+            host: host.into(),
+            port,
+            client_identifier: String::new(),
+            username: None,
+            password: None,
+            keep_alive: Duration::from_secs(30),
+            min_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(30),
+            request_timeout: Duration::from_secs(10),
+            encoding: Encoding::Cbor,
+        }
+    }
+}
+
+impl AsyncClient {
+    /// Connects to the broker and spawns the event loop. Fails if the first connection attempt
+    /// fails; later connection losses are retried in the background.
+    pub async fn connect(options: Options) -> Result<(Self, Messages), Error> {
+        let (messages_sender, messages_receiver) = mpsc::unbounded_channel();
+        let client = Self::spawn(
+            options,
+            messages_sender,
+            #[cfg(feature = "bridge")]
+            None,
+        )
+        .await?;
+        Ok((
+            client,
+            Messages {
+                receiver: messages_receiver,
+            },
+        ))
+    }
+
+    /// Like [`AsyncClient::connect`], but delivers publishes without decoding them.
+    #[cfg(feature = "bridge")]
+    pub(crate) async fn connect_raw(
+        options: Options
+    ) -> Result<(Self, mpsc::UnboundedReceiver<RawMessage>), Error> {
+        let (messages_sender, _) = mpsc::unbounded_channel();
+        let (raw_messages_sender, raw_messages_receiver) = mpsc::unbounded_channel();
+        let client = Self::spawn(options, messages_sender, Some(raw_messages_sender)).await?;
+        Ok((client, raw_messages_receiver))
+    }
+
+    async fn spawn(
+        options: Options,
+        messages: mpsc::UnboundedSender<Message>,
+        #[cfg(feature = "bridge")] raw_messages: Option<mpsc::UnboundedSender<RawMessage>>,
+    ) -> Result<Self, Error> {
+        let (commands_sender, commands_receiver) = mpsc::unbounded_channel();
+        let shared = Arc::new(Shared {
+            client_identifier: Mutex::new(options.client_identifier.clone()),
+            next_correlation_id: AtomicU64::new(0),
+            requests: Mutex::new(HashMap::new()),
+            response_subscription: OnceCell::new(),
+            request_timeout: options.request_timeout,
+            encoding: options.encoding,
+        });
+        let mut event_loop = EventLoop {
+            options,
+            shared: shared.clone(),
+            commands: commands_receiver,
+            messages,
+            #[cfg(feature = "bridge")]
+            raw_messages,
+            subscriptions: Vec::new(),
+            pending_subscriptions: Vec::new(),
+            next_packet_identifier: 1,
+        };
+        let connection = event_loop.connect().await?;
+        tokio::spawn(event_loop.run(connection));
+        Ok(Self {
+            commands: commands_sender,
+            shared,
+        })
+    }
+
+    /// The client identifier used for the session, assigned by the server if none was given.
+    pub fn client_identifier(&self) -> String {
+        self.shared.client_identifier.lock().unwrap().clone()
+    }
+
+    pub async fn publish(
+        &self,
+        topic: impl Into<Topic>,
+        payload: impl Into<Payload>,
+    ) -> Result<(), Error> {
+        self.publish_with_properties(topic.into(), Properties::default(), payload.into())
+    }
+
+    pub async fn subscribe(
+        &self,
+        filter: impl Into<Filter>,
+    ) -> Result<SubscribeReasonCode, Error> {
+        let (response, receiver) = oneshot::channel();
+        self.send(Command::Subscribe {
+            filter: filter.into(),
+            response,
+        })?;
+        receiver.await.map_err(|_| Error::Closed)?
+    }
+
+    /// Publishes `payload` with a response topic and correlation data and waits for the
+    /// matching response.
+    pub async fn request(
+        &self,
+        topic: impl Into<Topic>,
+        payload: impl Into<Payload>,
+    ) -> Result<Message, Error> {
+        let response_topic = self.response_topic();
+        self.shared
+            .response_subscription
+            .get_or_try_init(|| async {
+                self.subscribe(response_topic.clone()).await?;
+                Ok::<_, Error>(())
+            })
+            .await?;
+        let correlation_data = self
+            .shared
+            .next_correlation_id
+            .fetch_add(1, Ordering::SeqCst)
+            .to_be_bytes()
+            .to_vec();
+        let (sender, receiver) = oneshot::channel();
+        self.shared
+            .requests
+            .lock()
+            .unwrap()
+            .insert(correlation_data.clone(), sender);
+        let properties = Properties {
+            response_topic: Some(response_topic),
+            correlation_data: Some(correlation_data.clone()),
+            ..Default::default()
+        };
+        let result = match self.publish_with_properties(topic.into(), properties, payload.into()) {
+            Ok(()) => match tokio::time::timeout(self.shared.request_timeout, receiver).await {
+                Ok(Ok(message)) => Ok(message),
+                Ok(Err(_)) => Err(Error::Closed),
+                Err(_) => Err(Error::Timeout),
+            },
+            Err(error) => Err(error),
+        };
+        self.shared
+            .requests
+            .lock()
+            .unwrap()
+            .remove(&correlation_data);
+        result
+    }
+
+    /// Publishes `payload` to the response topic of `request`, echoing its correlation data.
+    pub async fn respond(
+        &self,
+        request: &Message,
+        payload: impl Into<Payload>,
+    ) -> Result<(), Error> {
+        let Some(response_topic) = request.response_topic.clone() else {
+            return Err(Error::NoResponseTopic);
+        };
+        let properties = Properties {
+            correlation_data: request.correlation_data.clone(),
+            ..Default::default()
+        };
+        self.publish_with_properties(response_topic, properties, payload.into())
+    }
+
+    pub async fn disconnect(&self) -> Result<(), Error> {
+        self.send(Command::Disconnect)
+    }
+
+    fn response_topic(&self) -> Topic {
+        Topic::from(format!("{}/response", self.client_identifier()))
+    }
+
+    fn publish_with_properties(
+        &self,
+        topic: Topic,
+        mut properties: Properties,
+        payload: Payload,
+    ) -> Result<(), Error> {
+        let encoding = match payload {
+            Payload::Value(_) => self.shared.encoding,
+            Payload::Bytes(_) => Encoding::Raw,
+        };
+        encoding.apply(&mut properties);
+        let payload = encoding.encode(&payload)?;
+        self.send(Command::Publish {
+            topic,
+            properties,
+            payload,
+        })
+    }
+
+    /// Publishes an already encoded payload with `properties` as given.
+    #[cfg(feature = "bridge")]
+    pub(crate) fn publish_raw(
+        &self,
+        topic: Topic,
+        properties: Properties,
+        payload: Vec<u8>,
+    ) -> Result<(), Error> {
+        self.send(Command::Publish {
+            topic,
+            properties,
+            payload,
+        })
+    }
+
+    fn send(
+        &self,
+        command: Command,
+    ) -> Result<(), Error> {
+        self.commands.send(command).map_err(|_| Error::Closed)
+    }
+}
+
+impl Messages {
+    pub async fn recv(&mut self) -> Option<Message> {
+        self.receiver.recv().await
+    }
+}
+
+impl Stream for Messages {
+    type Item = Message;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+impl EventLoop {
+    async fn run(
+        mut self,
+        mut connection: Connection,
+    ) {
+        loop {
+            match self.serve(&mut connection).await {
+                Exit::Disconnected => return,
+                Exit::ConnectionLost(error) => {
+                    tracing::warn!("Connection to MQTT broker lost: {error}");
+                }
+            }
+            let mut backoff = self.options.min_backoff;
+            connection = loop {
+                tokio::time::sleep(backoff).await;
+                if self.commands.is_closed() {
+                    return;
+                }
+                match self.connect().await {
+                    Ok(connection) => break connection,
+                    Err(error) => {
+                        tracing::debug!("Failed to reconnect to MQTT broker: {error}");
+                        backoff = (backoff * 2).min(self.options.max_backoff);
+                    }
+                }
+            };
+            tracing::info!("Reconnected to MQTT broker");
+        }
+    }
+
+    async fn connect(&mut self) -> Result<Connection, Error> {
+        let stream = TcpStream::connect((self.options.host.as_str(), self.options.port)).await?;
+        let mut connection = Connection {
+            stream,
+            buffer: BytesMut::with_capacity(4096),
+            in_flight_subscriptions: HashMap::new(),
+        };
+        let client_identifier = self.shared.client_identifier.lock().unwrap().clone();
+        connection
+            .write(ControlPacket::Connect {
+                protocol: Protocol::V5_0,
+                clean_start: true,
+                keep_alive: self.options.keep_alive.as_secs() as u16,
+                client_identifier,
+                username: self.options.username.clone(),
+                password: self.options.password.clone(),
+                properties: Properties::default(),
+            })
+            .await?;
+        let ControlPacket::Connack {
+            reason_code,
+            properties,
+            ..
+        } = connection.read().await?
+        else {
+            return Err(Error::UnexpectedPacket);
+        };
+        if reason_code != ConnectReasonCode::Success {
+            return Err(Error::ConnectionRefused(reason_code));
+        }
+        if let Some(client_identifier) = properties.assigned_client_identifier {
+            *self.shared.client_identifier.lock().unwrap() = client_identifier;
+        }
+        for filter in self.subscriptions.clone() {
+            self.send_subscribe(&mut connection, filter).await?;
+        }
+        Ok(connection)
+    }
+
+    async fn serve(
+        &mut self,
+        connection: &mut Connection,
+    ) -> Exit {
+        // A keep alive of zero turns the mechanism off (3.1.2.10).
+        let mut keep_alive = match self.options.keep_alive.is_zero() {
+            true => None,
+            false => Some(tokio::time::interval(self.options.keep_alive)),
+        };
+        if let Some(keep_alive) = &mut keep_alive {
+            keep_alive.tick().await;
+        }
+        let mut awaiting_pingresp = false;
+        loop {
+            let result = tokio::select! {
+                packet = connection.read() => match packet {
+                    Ok(ControlPacket::Pingresp) => {
+                        awaiting_pingresp = false;
+                        Ok(())
+                    }
+                    Ok(packet) => self.handle_packet(connection, packet),
+                    Err(error) => Err(error),
+                },
+                command = self.commands.recv() => match command {
+                    Some(Command::Disconnect) | None => {
+                        let _ = connection
+                            .write(ControlPacket::Disconnect {
+                                reason_code: DisconnectReasonCode::NormalDisconnection,
+                            })
+                            .await;
+                        self.fail_pending_subscriptions();
+                        return Exit::Disconnected;
+                    }
+                    Some(command) => self.handle_command(connection, command).await,
+                },
+                _ = tick(&mut keep_alive) => {
+                    if awaiting_pingresp {
+                        Err(Error::Timeout)
+                    } else {
+                        awaiting_pingresp = true;
+                        connection.write(ControlPacket::Pingreq).await
+                    }
+                }
+            };
+            if let Err(error) = result {
+                return Exit::ConnectionLost(error);
+            }
+        }
+    }
+
+    async fn handle_command(
+        &mut self,
+        connection: &mut Connection,
+        command: Command,
+    ) -> Result<(), Error> {
+        match command {
+            Command::Publish {
+                topic,
+                properties,
+                payload,
+            } => {
+                connection
+                    .write(ControlPacket::Publish {
+                        topic,
+                        qos: QoS::AtMostOnce,
+                        packet_identifier: 0,
+                        properties,
+                        payload,
+                    })
+                    .await
+            }
+            Command::Subscribe { filter, response } => {
+                if !self.subscriptions.contains(&filter) {
+                    self.subscriptions.push(filter.clone());
+                }
+                self.pending_subscriptions.push((filter.clone(), response));
+                self.send_subscribe(connection, filter).await
+            }
+            Command::Disconnect => Ok(()),
+        }
+    }
+
+    fn handle_packet(
+        &mut self,
+        connection: &mut Connection,
+        packet: ControlPacket,
+    ) -> Result<(), Error> {
+        match packet {
+            ControlPacket::Publish {
+                topic,
+                properties,
+                payload,
+                ..
+            } => {
+                #[cfg(feature = "bridge")]
+                if let Some(raw_messages) = &self.raw_messages {
+                    let _ = raw_messages.send(RawMessage {
+                        topic,
+                        properties,
+                        payload,
+                    });
+                    return Ok(());
+                }
+                let payload = if payload.is_empty() {
+                    None
+                } else {
+                    match Payload::decode(&payload, &properties, self.shared.encoding) {
+                        Ok(payload) => Some(payload),
+                        Err(error) => {
+                            tracing::warn!(
+                                "Dropping message on {topic} with faulty payload: {error}"
+                            );
+                            return Ok(());
+                        }
+                    }
+                };
+                let message = Message {
+                    topic,
+                    payload,
+                    response_topic: properties.response_topic,
+                    correlation_data: properties.correlation_data,
+                };
+                if let Some(correlation_data) = &message.correlation_data {
+                    let request = self
+                        .shared
+                        .requests
+                        .lock()
+                        .unwrap()
+                        .remove(correlation_data);
+                    if let Some(request) = request {
+                        let _ = request.send(message);
+                        return Ok(());
+                    }
+                }
+                let _ = self.messages.send(message);
+                Ok(())
+            }
+            ControlPacket::Suback {
+                packet_identifier,
+                reason_codes,
+            } => {
+                let Some(filter) = connection
+                    .in_flight_subscriptions
+                    .remove(&packet_identifier)
+                else {
+                    return Ok(());
+                };
+                let reason_code = reason_codes
+                    .first()
+                    .copied()
+                    .unwrap_or(SubscribeReasonCode::UnspecifiedError);
+                let granted = matches!(
+                    reason_code,
+                    SubscribeReasonCode::GrantedQoS0
+                        | SubscribeReasonCode::GrantedQoS1
+                        | SubscribeReasonCode::GrantedQoS2
+                );
+                if !granted {
+                    self.subscriptions
+                        .retain(|subscription| subscription != &filter);
+                }
+                let (pending, remaining) = std::mem::take(&mut self.pending_subscriptions)
+                    .into_iter()
+                    .partition(|(subscription, _)| subscription == &filter);
+                self.pending_subscriptions = remaining;
+                for (_, response) in pending {
+                    let _ = response.send(match granted {
+                        true => Ok(reason_code),
+                        false => Err(Error::SubscriptionRejected(reason_code)),
+                    });
+                }
+                Ok(())
+            }
+            ControlPacket::Puback { .. } => Ok(()),
+            ControlPacket::Disconnect { reason_code } => {
+                tracing::debug!("Disconnected by MQTT broker: {reason_code:?}");
+                Err(Error::Closed)
+            }
+            _ => Err(Error::UnexpectedPacket),
+        }
+    }
+
+    async fn send_subscribe(
+        &mut self,
+        connection: &mut Connection,
+        filter: Filter,
+    ) -> Result<(), Error> {
+        let packet_identifier = self.next_packet_identifier;
+        self.next_packet_identifier = self.next_packet_identifier.checked_add(1).unwrap_or(1);
+        connection
+            .write(ControlPacket::Subscribe {
+                packet_identifier,
+                topic: Topic::from(filter.to_string()),
+            })
+            .await?;
+        connection
+            .in_flight_subscriptions
+            .insert(packet_identifier, filter);
+        Ok(())
+    }
+
+    fn fail_pending_subscriptions(&mut self) {
+        for (_, response) in self.pending_subscriptions.drain(..) {
+            let _ = response.send(Err(Error::Closed));
+        }
+    }
+}
+
+impl Connection {
+    async fn write(
+        &mut self,
+        packet: ControlPacket,
+    ) -> Result<(), Error> {
+        let packet = packet.encode(Protocol::V5_0)?;
+        self.stream.write_all(&packet).await?;
+        Ok(())
+    }
+
+    /// Reads the next complete packet, buffering partial reads and keeping any bytes of
+    /// following packets for the next call. Cancel safe.
+    async fn read(&mut self) -> Result<ControlPacket, Error> {
+        loop {
+            match ControlPacket::decode(&self.buffer[..], Protocol::V5_0) {
+                Ok((packet, remaining_bytes)) => {
+                    let length = self.buffer.len() - remaining_bytes;
+                    self.buffer.advance(length);
+                    return Ok(packet);
+                }
+                Err(crate::protocol::Error::Incomplete) => {}
+                Err(error) => return Err(error.into()),
+            }
+            if self.stream.read_buf(&mut self.buffer).await? == 0 {
+                return Err(Error::Closed);
+            }
+        }
+    }
+}
+
+/// Waits for the next keep alive, or forever if keep alive is off.
+async fn tick(keep_alive: &mut Option<tokio::time::Interval>) {
+    match keep_alive {
+        Some(keep_alive) => {
+            keep_alive.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use super::*;
+    use crate::server::{self, Connack, Connect, Disconnect, Handler, Puback, Suback, Subscribe};
+    use crate::{PubackReasonCode, Server};
+    use std::net::SocketAddr;
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+
+    #[derive(Clone)]
+    struct TestHandler;
+
+    impl Handler for TestHandler {
+        async fn handle_connect(
+            &self,
+            _connect: Connect,
+        ) -> Connack {
+            Connack {
+                reason_code: ConnectReasonCode::Success,
+            }
+        }
+
+        async fn handle_disconnect(
+            &self,
+            _disconnect: Disconnect,
+        ) {
+        }
+
+        async fn handle_ping(&self) {}
+
+        async fn handle_publish(
+            &self,
+            _publish: server::Publish,
+        ) -> Puback {
+            Puback {
+                reason_code: PubackReasonCode::Success,
+            }
+        }
+
+        async fn handle_subscribe(
+            &self,
+            _subscribe: Subscribe,
+        ) -> Suback {
+            Suback {
+                reason_codes: vec![SubscribeReasonCode::GrantedQoS0],
+            }
+        }
+    }
+
+    async fn start_server() -> SocketAddr {
+        let server = Server::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let address = server.local_address().unwrap();
+        tokio::spawn(async move { server.listen(TestHandler).await });
+        address
+    }
+
+    /// Forwards connections to `target` and lets the test cut them to simulate network loss.
+    async fn start_proxy(target: SocketAddr) -> (SocketAddr, Arc<Mutex<Vec<JoinHandle<()>>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let connections = Arc::new(Mutex::new(Vec::new()));
+        tokio::spawn({
+            let connections = connections.clone();
+            async move {
+                loop {
+                    let (mut inbound, _) = listener.accept().await.unwrap();
+                    let connection = tokio::spawn(async move {
+                        let mut outbound = TcpStream::connect(target).await.unwrap();
+                        let _ = tokio::io::copy_bidirectional(&mut inbound, &mut outbound).await;
+                    });
+                    connections.lock().unwrap().push(connection);
+                }
+            }
+        });
+        (address, connections)
+    }
+
+    fn options(address: SocketAddr) -> Options {
+        Options::new(address.ip().to_string(), address.port())
+    }
+
+    async fn next(messages: &mut Messages) -> Message {
+        tokio::time::timeout(Duration::from_secs(5), messages.recv())
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_publish_subscribe() {
+        let address = start_server().await;
+        let (subscriber, mut messages) = AsyncClient::connect(options(address)).await.unwrap();
+        let (publisher, _) = AsyncClient::connect(options(address)).await.unwrap();
+        let reason_code = subscriber.subscribe("kodi/#").await.unwrap();
+        assert_eq!(reason_code, SubscribeReasonCode::GrantedQoS0);
+        publisher
+            .publish("jellyfin/state", Value::Text("ignored".into()))
+            .await
+            .unwrap();
+        publisher
+            .publish("kodi/state", Value::Text("playing".into()))
+            .await
+            .unwrap();
+        let message = next(&mut messages).await;
+        assert_eq!(message.topic, Topic::from("kodi/state"));
+        assert_eq!(message.payload, Some(Value::Text("playing".into()).into()));
+    }
+
+    #[tokio::test]
+    async fn test_keep_alive_off() {
+        let address = start_server().await;
+        let (client, mut messages) = AsyncClient::connect(Options {
+            keep_alive: Duration::ZERO,
+            ..options(address)
+        })
+        .await
+        .unwrap();
+        client.subscribe("kodi/state").await.unwrap();
+        client
+            .publish("kodi/state", Value::Text("idle".into()))
+            .await
+            .unwrap();
+        let message = next(&mut messages).await;
+        assert_eq!(message.payload, Some(Value::Text("idle".into()).into()));
+    }
+
+    #[tokio::test]
+    async fn test_large_payload() {
+        let address = start_server().await;
+        let (subscriber, mut messages) = AsyncClient::connect(options(address)).await.unwrap();
+        subscriber.subscribe("blob").await.unwrap();
+        let payload = Value::Text("x".repeat(100_000));
+        subscriber.publish("blob", payload.clone()).await.unwrap();
+        let message = next(&mut messages).await;
+        assert_eq!(message.payload, Some(payload.into()));
+    }
+
+    #[tokio::test]
+    async fn test_mixed_encodings() {
+        let address = start_server().await;
+        let (subscriber, mut messages) = AsyncClient::connect(options(address)).await.unwrap();
+        let (publisher, _) = AsyncClient::connect(Options {
+            encoding: Encoding::Json,
+            ..options(address)
+        })
+        .await
+        .unwrap();
+        subscriber.subscribe("sensor/#").await.unwrap();
+        publisher
+            .publish("sensor/temperature", Value::Integer(21))
+            .await
+            .unwrap();
+        let message = next(&mut messages).await;
+        assert_eq!(message.payload, Some(Payload::Value(Value::Integer(21))));
+        publisher
+            .publish("sensor/image", vec![0x89, 0x50, 0x4E, 0x47])
+            .await
+            .unwrap();
+        let message = next(&mut messages).await;
+        assert_eq!(
+            message.payload,
+            Some(Payload::Bytes(vec![0x89, 0x50, 0x4E, 0x47]))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_request_response() {
+        let address = start_server().await;
+        let (responder, mut requests) = AsyncClient::connect(options(address)).await.unwrap();
+        let (requester, _) = AsyncClient::connect(options(address)).await.unwrap();
+        responder.subscribe("service/echo").await.unwrap();
+        tokio::spawn(async move {
+            while let Some(request) = requests.recv().await {
+                responder
+                    .respond(&request, request.payload.clone().unwrap())
+                    .await
+                    .unwrap();
+            }
+        });
+        for i in 0..3 {
+            let response = requester
+                .request("service/echo", Value::Integer(i))
+                .await
+                .unwrap();
+            assert_eq!(response.payload, Some(Value::Integer(i).into()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_resubscribes() {
+        let address = start_server().await;
+        let (proxy_address, proxied) = start_proxy(address).await;
+        let (subscriber, mut messages) = AsyncClient::connect(Options {
+            min_backoff: Duration::from_millis(10),
+            ..options(proxy_address)
+        })
+        .await
+        .unwrap();
+        let (publisher, _) = AsyncClient::connect(options(address)).await.unwrap();
+        subscriber.subscribe("kodi/state").await.unwrap();
+
+        for connection in proxied.lock().unwrap().drain(..) {
+            connection.abort();
+        }
+
+        let mut received = None;
+        for _ in 0..50 {
+            publisher
+                .publish("kodi/state", Value::Boolean(true))
+                .await
+                .unwrap();
+            if let Ok(Some(message)) =
+                tokio::time::timeout(Duration::from_millis(100), messages.recv()).await
+            {
+                if !proxied.lock().unwrap().is_empty() {
+                    received = Some(message);
+                    break;
+                }
+            }
+        }
+        let message = received.expect("no message after reconnect");
+        assert_eq!(message.topic, Topic::from("kodi/state"));
+    }
+}
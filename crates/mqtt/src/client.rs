@@ -1,13 +1,14 @@
-use crate::{protocol::*, ConnectReasonCode, DisconnectReasonCode, QoS};
+use crate::{protocol::*, ConnectReasonCode, DisconnectReasonCode, Protocol, QoS};
+use bytes::{Buf, BytesMut};
 use derive_more::From;
 use lararium::prelude::*;
 use std::fmt;
 use std::io::{self, Read, Write};
-use std::net::TcpStream;
-use std::sync::Arc;
+use std::net::{Shutdown, TcpStream};
 
 pub struct Client {
     stream: TcpStream,
+    buffer: BytesMut,
 }
 
 #[derive(Debug, Clone)]
@@ -24,6 +25,10 @@ pub enum Error {
     Io(std::io::Error),
     #[from]
     Serialization(ciborium::ser::Error<std::io::Error>),
+    #[from]
+    Deserialization(ciborium::de::Error<std::io::Error>),
+    ConnectionRefused(ConnectReasonCode),
+    UnexpectedPacket,
     ConnectionLost,
 }
 
@@ -39,58 +44,111 @@ impl fmt::Display for Error {
 }
 
 impl Client {
+    /// Connects to the broker, failing with [`Error::ConnectionRefused`] carrying the CONNACK
+    /// reason code if the broker does not accept the connection.
     pub fn connect(
         host: &str,
         port: u16,
     ) -> Result<Self, Error> {
-        let mut stream = TcpStream::connect((host, port))?;
-        stream.write_all(
-            &ControlPacket::Connect { clean_start: true }
-                .encode()
-                .unwrap(),
-        )?;
-        let mut buffer = [0; 1024];
-        let bytes_read = stream.read(&mut buffer)?;
-        let (packet, _) = ControlPacket::decode(&buffer[..bytes_read]).unwrap();
-        let ControlPacket::Connack { reason_code } = packet else {
-            panic!();
+        let stream = TcpStream::connect((host, port))?;
+        let mut client = Self {
+            stream,
+            buffer: BytesMut::with_capacity(4096),
+        };
+        client.write(ControlPacket::Connect {
+            protocol: Protocol::V3_1_1,
+            clean_start: true,
+            keep_alive: 0,
+            client_identifier: String::new(),
+            username: None,
+            password: None,
+            properties: Properties::default(),
+        })?;
+        let packet = loop {
+            if let Some(packet) = client.decode()? {
+                break packet;
+            }
+            client.fill_buffer()?;
+        };
+        let ControlPacket::Connack { reason_code, .. } = packet else {
+            return Err(Error::UnexpectedPacket);
         };
         if reason_code != ConnectReasonCode::Success {
-            panic!();
+            return Err(Error::ConnectionRefused(reason_code));
         }
-        stream.set_nonblocking(true)?;
-        Ok(Self { stream })
+        client.stream.set_nonblocking(true)?;
+        Ok(client)
     }
 
+    /// Returns the next message if one has been received in full, without blocking.
     pub fn poll_message(&mut self) -> Result<Option<Message>, Error> {
-        let mut buffer = [0; 1024];
-        let bytes_read = match self.stream.read(&mut buffer) {
-            Ok(bytes_read) => bytes_read,
-            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(None),
-            Err(e) => return Err(e.into()),
-        };
-        let (packet, _) = ControlPacket::decode(&buffer[..bytes_read])?;
-        match packet {
-            ControlPacket::Publish { topic, payload } => {
-                let Ok(payload) = ciborium::de::from_reader(&payload[..]) else {
-                    panic!("Received faulty payload.");
-                };
-                Ok(Some(Message { topic, payload }))
-            }
-            ControlPacket::Puback { .. } => {
-                tracing::debug!("Published successfully");
-                Ok(None)
+        loop {
+            let Some(packet) = self.decode()? else {
+                match self.fill_buffer() {
+                    Ok(()) => continue,
+                    Err(Error::Io(error)) if error.kind() == io::ErrorKind::WouldBlock => {
+                        return Ok(None)
+                    }
+                    Err(error) => return Err(error),
+                }
+            };
+            match packet {
+                ControlPacket::Publish { topic, payload, .. } => {
+                    let payload = ciborium::de::from_reader(&payload[..])?;
+                    return Ok(Some(Message { topic, payload }));
+                }
+                ControlPacket::Puback { .. } => {
+                    tracing::debug!("Published successfully");
+                }
+                ControlPacket::Suback { .. } => {
+                    tracing::debug!("Subscribed successfully");
+                }
+                ControlPacket::Unsuback { .. } => {
+                    tracing::debug!("Unsubscribed successfully");
+                }
+                ControlPacket::Pingresp => {}
+                ControlPacket::Disconnect { .. } => return Err(Error::ConnectionLost),
+                _ => return Err(Error::UnexpectedPacket),
             }
-            ControlPacket::Suback { .. } => {
-                tracing::debug!("Subscribed successfully");
-                Ok(None)
+        }
+    }
+
+    fn decode(&mut self) -> Result<Option<ControlPacket>, Error> {
+        match ControlPacket::decode(&self.buffer[..], Protocol::V3_1_1) {
+            Ok((packet, remaining_bytes)) => {
+                let length = self.buffer.len() - remaining_bytes;
+                self.buffer.advance(length);
+                Ok(Some(packet))
             }
-            _ => {
-                panic!("Unexpected packet: {packet:?}");
+            Err(crate::protocol::Error::Incomplete) => Ok(None),
+            Err(error) => {
+                // Nothing after a malformed packet can be trusted to start a packet, so the
+                // connection is dropped.
+                self.buffer.clear();
+                let _ = self.stream.shutdown(Shutdown::Both);
+                Err(error.into())
             }
         }
     }
 
+    fn fill_buffer(&mut self) -> Result<(), Error> {
+        let mut read_buffer = [0; 4096];
+        let bytes_read = self.stream.read(&mut read_buffer)?;
+        if bytes_read == 0 {
+            return Err(Error::ConnectionLost);
+        }
+        self.buffer.extend_from_slice(&read_buffer[..bytes_read]);
+        Ok(())
+    }
+
+    fn write(
+        &mut self,
+        packet: ControlPacket,
+    ) -> Result<(), Error> {
+        self.stream.write_all(&packet.encode(Protocol::V3_1_1)?)?;
+        Ok(())
+    }
+
     pub fn publish(
         &mut self,
         topic: impl Into<Topic>,
@@ -99,15 +157,13 @@ impl Client {
     ) -> Result<(), Error> {
         let mut payload = Vec::new();
         ciborium::ser::into_writer(&value, &mut payload)?;
-        self.stream.write_all(
-            &ControlPacket::Publish {
-                topic: topic.into(),
-                payload,
-            }
-            .encode()
-            .unwrap(),
-        )?;
-        Ok(())
+        self.write(ControlPacket::Publish {
+            topic: topic.into(),
+            qos: QoS::AtMostOnce,
+            packet_identifier: 0,
+            properties: Properties::default(),
+            payload,
+        })
     }
 
     pub fn subscribe(
@@ -115,25 +171,166 @@ impl Client {
         topic: impl Into<Topic>,
         qos: QoS,
     ) -> Result<(), Error> {
-        self.stream.write_all(
-            &ControlPacket::Subscribe {
-                topic: topic.into(),
-                packet_identifier: 0,
-            }
-            .encode()
-            .unwrap(),
-        )?;
-        Ok(())
+        self.write(ControlPacket::Subscribe {
+            topic: topic.into(),
+            packet_identifier: 0,
+        })
     }
 
     pub fn disconnect(&mut self) -> Result<(), Error> {
-        self.stream.write_all(
-            &ControlPacket::Disconnect {
-                reason_code: DisconnectReasonCode::NormalDisconnection,
+        self.write(ControlPacket::Disconnect {
+            reason_code: DisconnectReasonCode::NormalDisconnection,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Accepts a single connection, consumes its CONNECT and writes `chunks` one at a time.
+    fn serve(chunks: Vec<Vec<u8>>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buffer = [0; 1024];
+            let _ = stream.read(&mut buffer).unwrap();
+            for chunk in chunks {
+                stream.write_all(&chunk).unwrap();
+                stream.flush().unwrap();
+                thread::sleep(std::time::Duration::from_millis(10));
             }
-            .encode()
-            .unwrap(),
-        )?;
-        Ok(())
+            thread::sleep(std::time::Duration::from_millis(200));
+        });
+        port
+    }
+
+    fn connack(reason_code: ConnectReasonCode) -> Vec<u8> {
+        ControlPacket::Connack {
+            session_present: false,
+            reason_code,
+            properties: Properties::default(),
+        }
+        .encode(Protocol::V3_1_1)
+        .unwrap()
+    }
+
+    fn publish(
+        topic: &str,
+        payload: Vec<u8>,
+    ) -> Vec<u8> {
+        ControlPacket::Publish {
+            topic: Topic::from(topic),
+            qos: QoS::AtMostOnce,
+            packet_identifier: 0,
+            properties: Properties::default(),
+            payload,
+        }
+        .encode(Protocol::V3_1_1)
+        .unwrap()
+    }
+
+    fn cbor(value: &Value) -> Vec<u8> {
+        let mut payload = Vec::new();
+        ciborium::ser::into_writer(value, &mut payload).unwrap();
+        payload
+    }
+
+    fn wait_for_message(client: &mut Client) -> Result<Message, Error> {
+        for _ in 0..500 {
+            if let Some(message) = client.poll_message()? {
+                return Ok(message);
+            }
+            thread::sleep(std::time::Duration::from_millis(1));
+        }
+        panic!("no message received");
+    }
+
+    #[test]
+    fn test_connect_refused() {
+        let port = serve(vec![connack(ConnectReasonCode::NotAuthorized)]);
+        let result = Client::connect("127.0.0.1", port);
+        assert!(matches!(
+            result,
+            Err(Error::ConnectionRefused(ConnectReasonCode::NotAuthorized))
+        ));
+    }
+
+    #[test]
+    fn test_split_packets() {
+        let value = Value::Text("x".repeat(4000));
+        let mut bytes = connack(ConnectReasonCode::Success);
+        bytes.extend(publish("kodi/state", cbor(&value)));
+        let mut chunks = vec![bytes[..1].to_vec(), bytes[1..7].to_vec()];
+        chunks.extend(bytes[7..].chunks(700).map(<[u8]>::to_vec));
+        let port = serve(chunks);
+        let mut client = Client::connect("127.0.0.1", port).unwrap();
+        let message = wait_for_message(&mut client).unwrap();
+        assert_eq!(message.topic, Topic::from("kodi/state"));
+        assert_eq!(message.payload, value);
+    }
+
+    #[test]
+    fn test_coalesced_packets() {
+        let mut bytes = connack(ConnectReasonCode::Success);
+        bytes.extend(publish("a", cbor(&Value::Integer(1))));
+        bytes.extend(publish("b", cbor(&Value::Integer(2))));
+        let port = serve(vec![bytes]);
+        let mut client = Client::connect("127.0.0.1", port).unwrap();
+        let first = wait_for_message(&mut client).unwrap();
+        let second = wait_for_message(&mut client).unwrap();
+        assert_eq!(first.payload, Value::Integer(1));
+        assert_eq!(second.payload, Value::Integer(2));
+    }
+
+    #[test]
+    fn test_faulty_payload() {
+        let mut bytes = connack(ConnectReasonCode::Success);
+        bytes.extend(publish("a", vec![0xFF, 0xFF]));
+        bytes.extend(publish("b", cbor(&Value::Integer(2))));
+        let port = serve(vec![bytes]);
+        let mut client = Client::connect("127.0.0.1", port).unwrap();
+        assert!(matches!(
+            wait_for_message(&mut client),
+            Err(Error::Deserialization(_))
+        ));
+        let message = wait_for_message(&mut client).unwrap();
+        assert_eq!(message.payload, Value::Integer(2));
+    }
+
+    #[test]
+    fn test_unsuback_and_auth() {
+        let mut bytes = connack(ConnectReasonCode::Success);
+        bytes.extend([0xB0, 0x02, 0x00, 0x01]);
+        bytes.extend(publish("a", cbor(&Value::Integer(1))));
+        bytes.extend([0xF0, 0x00]);
+        let port = serve(vec![bytes]);
+        let mut client = Client::connect("127.0.0.1", port).unwrap();
+        let message = wait_for_message(&mut client).unwrap();
+        assert_eq!(message.payload, Value::Integer(1));
+        // AUTH does not exist in MQTT 3.1.1.
+        assert!(matches!(
+            wait_for_message(&mut client),
+            Err(Error::Protocol(_))
+        ));
+        assert!(matches!(
+            wait_for_message(&mut client),
+            Err(Error::ConnectionLost)
+        ));
+    }
+
+    #[test]
+    fn test_unexpected_packet() {
+        let mut bytes = connack(ConnectReasonCode::Success);
+        bytes.extend([0xC0, 0x00]);
+        let port = serve(vec![bytes]);
+        let mut client = Client::connect("127.0.0.1", port).unwrap();
+        assert!(matches!(
+            wait_for_message(&mut client),
+            Err(Error::UnexpectedPacket)
+        ));
     }
 }
@@ -1,11 +1,17 @@
+#[cfg(feature = "async-client")]
+pub mod async_client;
 #[cfg(feature = "client")]
 pub mod client;
+pub mod payload;
 mod protocol;
 #[cfg(feature = "server")]
 pub mod server;
 
+#[cfg(feature = "async-client")]
+pub use async_client::AsyncClient;
 #[cfg(feature = "client")]
 pub use client::Client;
+pub use payload::{Encoding, Payload};
 #[cfg(feature = "server")]
 pub use server::{Handler, Server};
 
@@ -70,6 +76,24 @@ pub enum SubscribeReasonCode {
     WildcardSubscriptionsNotSupported,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnsubscribeReasonCode {
+    Success,
+    NoSubscriptionExisted,
+    UnspecifiedError,
+    ImplementationSpecificError,
+    NotAuthorized,
+    TopicFilterInvalid,
+    PacketIdentifierInUse,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthReasonCode {
+    Success,
+    ContinueAuthentication,
+    ReAuthenticate,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DisconnectReasonCode {
     NormalDisconnection,
@@ -0,0 +1,271 @@
+use crate::protocol::Properties;
+use derive_more::From;
+use lararium::prelude::*;
+use std::fmt;
+
+/// How a PUBLISH payload is encoded on the wire.
+///
+/// MQTT 5.0 publishers declare it through the content type and payload format indicator
+/// properties. MQTT 3.1.1 publishers cannot, so receivers fall back to a default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Cbor,
+    Json,
+    Text,
+    Raw,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Payload {
+    Value(Value),
+    Bytes(Vec<u8>),
+}
+
+#[derive(Debug, From)]
+pub enum Error {
+    #[from]
+    Deserialization(ciborium::de::Error<std::io::Error>),
+    #[from]
+    Serialization(ciborium::ser::Error<std::io::Error>),
+    #[from]
+    Json(serde_json::Error),
+    InvalidUtf8,
+    /// The payload holds more than one CBOR value.
+    TrailingBytes,
+    Unrepresentable,
+}
+
+impl std::error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter,
+    ) -> Result<(), fmt::Error> {
+        write!(f, "{self:?}")
+    }
+}
+
+impl Encoding {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Encoding::Cbor => "application/cbor",
+            Encoding::Json => "application/json",
+            Encoding::Text => "text/plain",
+            Encoding::Raw => "application/octet-stream",
+        }
+    }
+
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let essence = content_type.split(';').next().unwrap_or_default().trim();
+        match essence.to_ascii_lowercase().as_str() {
+            "application/cbor" => Some(Encoding::Cbor),
+            "application/json" => Some(Encoding::Json),
+            "text/plain" => Some(Encoding::Text),
+            "application/octet-stream" => Some(Encoding::Raw),
+            _ => None,
+        }
+    }
+
+    /// The encoding declared by the publisher. An unknown content type is treated as opaque
+    /// bytes, and a payload format indicator of 1 without a content type as UTF-8 text.
+    pub(crate) fn from_properties(properties: &Properties) -> Option<Self> {
+        match (
+            &properties.content_type,
+            properties.payload_format_indicator,
+        ) {
+            (Some(content_type), _) => {
+                Some(Self::from_content_type(content_type).unwrap_or(Encoding::Raw))
+            }
+            (None, Some(1)) => Some(Encoding::Text),
+            (None, _) => None,
+        }
+    }
+
+    /// Declares this encoding on an outgoing publish.
+    pub(crate) fn apply(
+        &self,
+        properties: &mut Properties,
+    ) {
+        properties.content_type = Some(self.content_type().into());
+        properties.payload_format_indicator = match self {
+            Encoding::Json | Encoding::Text => Some(1),
+            Encoding::Cbor | Encoding::Raw => Some(0),
+        };
+    }
+
+    pub fn decode(
+        &self,
+        bytes: &[u8],
+    ) -> Result<Payload, Error> {
+        match self {
+            Encoding::Cbor => {
+                let mut reader = bytes;
+                let value = ciborium::de::from_reader(&mut reader)?;
+                match reader.is_empty() {
+                    true => Ok(Payload::Value(value)),
+                    false => Err(Error::TrailingBytes),
+                }
+            }
+            Encoding::Json => Ok(Payload::Value(serde_json::from_slice(bytes)?)),
+            Encoding::Text => match std::str::from_utf8(bytes) {
+                Ok(text) => Ok(Payload::Value(Value::Text(text.into()))),
+                Err(_) => Err(Error::InvalidUtf8),
+            },
+            Encoding::Raw => Ok(Payload::Bytes(bytes.to_vec())),
+        }
+    }
+
+    pub fn encode(
+        &self,
+        payload: &Payload,
+    ) -> Result<Vec<u8>, Error> {
+        match (self, payload) {
+            (_, Payload::Bytes(bytes)) => Ok(bytes.clone()),
+            (Encoding::Cbor, Payload::Value(value)) => {
+                let mut bytes = Vec::new();
+                ciborium::ser::into_writer(value, &mut bytes)?;
+                Ok(bytes)
+            }
+            (Encoding::Json, Payload::Value(value)) => Ok(serde_json::to_vec(value)?),
+            (Encoding::Text, Payload::Value(Value::Text(text))) => Ok(text.as_bytes().to_vec()),
+            (Encoding::Raw, Payload::Value(Value::Bytes(bytes))) => Ok(bytes.clone()),
+            (Encoding::Text | Encoding::Raw, Payload::Value(_)) => Err(Error::Unrepresentable),
+        }
+    }
+}
+
+impl Payload {
+    /// Decodes `bytes` as declared by `properties`, using `default` if nothing is declared.
+    ///
+    /// Payloads that do not decode with the default encoding are passed through as raw bytes,
+    /// while a payload that contradicts its declared encoding is an error.
+    pub(crate) fn decode(
+        bytes: &[u8],
+        properties: &Properties,
+        default: Encoding,
+    ) -> Result<Self, Error> {
+        match Encoding::from_properties(properties) {
+            Some(encoding) => encoding.decode(bytes),
+            None => Ok(default
+                .decode(bytes)
+                .unwrap_or_else(|_| Payload::Bytes(bytes.to_vec()))),
+        }
+    }
+
+    pub fn value(&self) -> Option<&Value> {
+        match self {
+            Payload::Value(value) => Some(value),
+            Payload::Bytes(_) => None,
+        }
+    }
+
+    pub fn bytes(&self) -> Option<&[u8]> {
+        match self {
+            Payload::Value(_) => None,
+            Payload::Bytes(bytes) => Some(bytes),
+        }
+    }
+}
+
+impl From<Value> for Payload {
+    fn from(value: Value) -> Self {
+        Payload::Value(value)
+    }
+}
+
+impl From<Vec<u8>> for Payload {
+    fn from(bytes: Vec<u8>) -> Self {
+        Payload::Bytes(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn properties(
+        content_type: Option<&str>,
+        payload_format_indicator: Option<u8>,
+    ) -> Properties {
+        Properties {
+            content_type: content_type.map(Into::into),
+            payload_format_indicator,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_decode_json() {
+        let properties = properties(Some("application/json; charset=utf-8"), Some(1));
+        let payload = Payload::decode(br#"{"volume":42}"#, &properties, Encoding::Cbor).unwrap();
+        assert_eq!(
+            payload,
+            Payload::Value(Value::Map(vec![(
+                "volume".into(),
+                Box::new(Value::Integer(42))
+            )]))
+        );
+    }
+
+    #[test]
+    fn test_decode_text_from_payload_format_indicator() {
+        let properties = properties(None, Some(1));
+        let payload = Payload::decode(b"on", &properties, Encoding::Cbor).unwrap();
+        assert_eq!(payload, Payload::Value(Value::Text("on".into())));
+    }
+
+    #[test]
+    fn test_decode_invalid_text() {
+        let properties = properties(Some("text/plain"), None);
+        let result = Payload::decode(&[0xFF, 0xFE], &properties, Encoding::Cbor);
+        assert!(matches!(result, Err(Error::InvalidUtf8)));
+    }
+
+    #[test]
+    fn test_decode_trailing_bytes() {
+        let properties = properties(Some("application/cbor"), None);
+        let result = Payload::decode(&[0x01, 0x02], &properties, Encoding::Cbor);
+        assert!(matches!(result, Err(Error::TrailingBytes)));
+    }
+
+    #[test]
+    fn test_decode_unknown_content_type() {
+        let properties = properties(Some("image/png"), None);
+        let payload = Payload::decode(&[0x89, 0x50], &properties, Encoding::Cbor).unwrap();
+        assert_eq!(payload, Payload::Bytes(vec![0x89, 0x50]));
+    }
+
+    #[test]
+    fn test_decode_default_falls_back_to_bytes() {
+        let properties = Properties::default();
+        let payload = Payload::decode(&[0xFF, 0xFF], &properties, Encoding::Cbor).unwrap();
+        assert_eq!(payload, Payload::Bytes(vec![0xFF, 0xFF]));
+        let payload = Payload::decode(&[0x18, 0x2A], &properties, Encoding::Cbor).unwrap();
+        assert_eq!(payload, Payload::Value(Value::Integer(42)));
+        // Raw bytes that start with a CBOR value.
+        let payload = Payload::decode(&[0x18, 0x2A, 0xFF], &properties, Encoding::Cbor).unwrap();
+        assert_eq!(payload, Payload::Bytes(vec![0x18, 0x2A, 0xFF]));
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        let value = Value::Array(vec![Value::Integer(1), Value::Text("two".into())]);
+        for encoding in [Encoding::Cbor, Encoding::Json] {
+            let bytes = encoding.encode(&Payload::Value(value.clone())).unwrap();
+            assert_eq!(
+                encoding.decode(&bytes).unwrap(),
+                Payload::Value(value.clone())
+            );
+        }
+    }
+
+    #[test]
+    fn test_encode_unrepresentable() {
+        let payload = Payload::Value(Value::Integer(1));
+        assert!(matches!(
+            Encoding::Text.encode(&payload),
+            Err(Error::Unrepresentable)
+        ));
+    }
+}
@@ -1,4 +1,7 @@
-use crate::{ConnectReasonCode, DisconnectReasonCode, Protocol, QoS, SubscribeReasonCode};
+use crate::{
+    AuthReasonCode, ConnectReasonCode, DisconnectReasonCode, Protocol, PubackReasonCode, QoS,
+    SubscribeReasonCode, UnsubscribeReasonCode,
+};
 use bytes::{Buf, BufMut};
 use lararium::prelude::*;
 
@@ -24,16 +27,41 @@ impl core::fmt::Display for Error {
 #[derive(Debug, PartialEq)]
 pub enum ControlPacket {
     Connect {
+        protocol: Protocol,
         clean_start: bool,
+        keep_alive: u16,
+        client_identifier: String,
+        username: Option<String>,
+        password: Option<Vec<u8>>,
+        properties: Properties,
     },
     Connack {
+        session_present: bool,
         reason_code: ConnectReasonCode,
+        properties: Properties,
     },
     Publish {
         topic: Topic,
+        qos: QoS,
+        /// Only present on the wire for QoS 1 and 2.
+        packet_identifier: u16,
+        properties: Properties,
         payload: Vec<u8>,
     },
-    Puback {},
+    Puback {
+        packet_identifier: u16,
+        reason_code: PubackReasonCode,
+    },
+    Pubrec {
+        packet_identifier: u16,
+        reason_code: PubackReasonCode,
+    },
+    Pubrel {
+        packet_identifier: u16,
+    },
+    Pubcomp {
+        packet_identifier: u16,
+    },
     Subscribe {
         packet_identifier: u16,
         topic: Topic,
@@ -42,11 +70,45 @@ pub enum ControlPacket {
         packet_identifier: u16,
         reason_codes: Vec<SubscribeReasonCode>,
     },
+    Unsubscribe {
+        packet_identifier: u16,
+        topics: Vec<Topic>,
+    },
+    Unsuback {
+        packet_identifier: u16,
+        /// One for each topic unsubscribed from, and none for MQTT 3.1.1.
+        reason_codes: Vec<UnsubscribeReasonCode>,
+    },
     Pingreq,
     Pingresp,
     Disconnect {
         reason_code: DisconnectReasonCode,
     },
+    Auth {
+        reason_code: AuthReasonCode,
+    },
+}
+
+/// 2.2.2.2 Property
+///
+/// Properties are only present on the wire for MQTT 5.0 and are silently dropped when encoding
+/// for MQTT 3.1.1.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Properties {
+    pub payload_format_indicator: Option<u8>,
+    pub message_expiry_interval: Option<u32>,
+    pub content_type: Option<String>,
+    pub response_topic: Option<Topic>,
+    pub correlation_data: Option<Vec<u8>>,
+    pub session_expiry_interval: Option<u32>,
+    pub assigned_client_identifier: Option<String>,
+    pub server_keep_alive: Option<u16>,
+    pub reason_string: Option<String>,
+    pub receive_maximum: Option<u16>,
+    pub topic_alias_maximum: Option<u16>,
+    pub topic_alias: Option<u16>,
+    pub maximum_packet_size: Option<u32>,
+    pub user_properties: Vec<(String, String)>,
 }
 
 pub enum PacketType {
@@ -68,14 +130,31 @@ pub enum PacketType {
 }
 
 impl ControlPacket {
-    pub fn decode(input: &[u8]) -> Result<(Self, usize), Error> {
+    /// The total size of the first packet in `input`, known as soon as its fixed header is
+    /// complete. Lets a receiver reject oversized packets before buffering them.
+    pub fn size(input: &[u8]) -> Result<usize, Error> {
+        if input.is_empty() {
+            return Err(Error::Incomplete);
+        }
+        let mut buf = &input[1..];
+        let remaining_length = decode_remaining_length(&mut buf)? as usize;
+        Ok(input.len() - buf.len() + remaining_length)
+    }
+
+    /// Decodes the first packet in `input`, returning it together with the number of bytes left
+    /// over after it. `protocol` is the version negotiated on the connection; CONNECT packets
+    /// carry their own.
+    pub fn decode(
+        input: &[u8],
+        protocol: Protocol,
+    ) -> Result<(Self, usize), Error> {
         let mut buf = &input[..];
 
         if buf.remaining() < 2 {
             return Err(Error::Incomplete);
         }
 
-        // Fixed header
+        // 2.1.1 Fixed Header
         let packet_type_and_flags = buf.get_u8();
         let packet_type = packet_type_and_flags >> 4;
         let packet_type = match packet_type {
@@ -97,32 +176,33 @@ impl ControlPacket {
             _ => return Err(Error::Invalid),
         };
         let flags = packet_type_and_flags & 0x0F;
-        let remaining_length = buf.get_u8();
 
-        if buf.remaining() < remaining_length as usize {
+        // 2.1.4 Remaining Length
+        let remaining_length = decode_remaining_length(&mut buf)? as usize;
+        if buf.remaining() < remaining_length {
             return Err(Error::Incomplete);
         }
+        let remaining_bytes = buf.remaining() - remaining_length;
+        let mut buf = &buf[..remaining_length];
 
         let packet = match packet_type {
             // 3.1.2 CONNECT Variable Header
             PacketType::Connect => {
                 // 3.1.2.1 Protocol Name
-                let protocol_name_length = buf.get_u16();
-                let protocol_name = &buf[..protocol_name_length as usize];
+                let protocol_name = buf.get_binary_data()?;
                 if protocol_name != b"MQTT" {
                     return Err(Error::UnsupportedProtocol);
                 }
-                buf.advance(protocol_name_length as usize);
 
                 // 3.1.2.2 Protocol Version
-                match buf.get_u8() {
+                let protocol = match buf.get_u8_checked()? {
                     0x04 => Protocol::V3_1_1,
                     0x05 => Protocol::V5_0,
                     _ => return Err(Error::UnsupportedProtocolVersion),
                 };
 
                 // 3.1.2.3 Connect Flags
-                let connect_flags = buf.get_u8();
+                let connect_flags = buf.get_u8_checked()?;
                 let clean_start = (connect_flags & 0b00000010) != 0;
                 let will_flag = (connect_flags & 0b00000100) != 0;
                 let will_qos = match (connect_flags & 0b00011000) >> 3 {
@@ -140,18 +220,41 @@ impl ControlPacket {
                 let username_flag = (connect_flags & 0b10000000) != 0;
 
                 // 3.1.2.10 Keep Alive
-                let keep_alive = buf.get_u16();
+                let keep_alive = buf.get_u16_checked()?;
 
                 // 3.1.2.11 CONNECT Properties
-                let Ok(properties_length) = buf.get_variable_length() else {
-                    return Err(Error::Invalid);
+                let properties = buf.get_properties(protocol)?;
+
+                // 3.1.3.1 Client Identifier
+                let client_identifier = buf.get_utf8_string()?;
+
+                // 3.1.3.2 Will Properties, 3.1.3.3 Will Topic and 3.1.3.4 Will Payload
+                if will_flag {
+                    buf.get_properties(protocol)?;
+                    buf.get_utf8_string()?;
+                    buf.get_binary_data()?;
+                }
+
+                // 3.1.3.5 User Name
+                let username = match username_flag {
+                    true => Some(buf.get_utf8_string()?),
+                    false => None,
                 };
 
-                // TODO
-                buf.get_u8();
+                // 3.1.3.6 Password
+                let password = match password_flag {
+                    true => Some(buf.get_binary_data()?),
+                    false => None,
+                };
 
                 ControlPacket::Connect {
+                    protocol,
                     clean_start,
+                    keep_alive,
+                    client_identifier,
+                    username,
+                    password,
+                    properties,
                     //will: Will {
                     //    retain: will_retain,
                     //    qos: will_qos,
@@ -161,11 +264,11 @@ impl ControlPacket {
             // 3.2.2 CONNACK Variable Header
             PacketType::Connack => {
                 // 3.2.2.1 Connect Acknowledge Flags
-                let connect_acknowledge_flags = buf.get_u8();
+                let connect_acknowledge_flags = buf.get_u8_checked()?;
                 let session_present = (connect_acknowledge_flags & 0b00000001) != 0;
 
                 // 3.2.2.2 Connect Reason Code
-                let reason_code = match buf.get_u8() {
+                let reason_code = match buf.get_u8_checked()? {
                     0x00 => ConnectReasonCode::Success,
                     0x80 => ConnectReasonCode::UnspecifiedError,
                     0x81 => ConnectReasonCode::MalformedPacket,
@@ -191,8 +294,13 @@ impl ControlPacket {
                     _ => return Err(Error::Invalid),
                 };
 
+                // 3.2.2.3 CONNACK Properties
+                let properties = buf.get_properties(protocol)?;
+
                 ControlPacket::Connack {
-                    reason_code: ConnectReasonCode::Success,
+                    session_present,
+                    reason_code,
+                    properties,
                 }
             }
             // 3.3.2 PUBLISH Variable Header
@@ -207,42 +315,117 @@ impl ControlPacket {
                 let dup_flag = (flags & 0b00001000) != 0;
 
                 // 3.3.2.1 Topic Name
-                let topic_name_length = buf.get_u16();
-                let topic_name = &buf[..topic_name_length as usize];
-                let Ok(topic_name) = std::str::from_utf8(topic_name) else {
-                    return Err(Error::Invalid);
+                let topic_name = buf.get_utf8_string()?;
+
+                // 3.3.2.2 Packet Identifier
+                let packet_identifier = match qos {
+                    QoS::AtMostOnce => 0,
+                    QoS::AtLeastOnce | QoS::ExactlyOnce => buf.get_u16_checked()?,
                 };
-                buf.advance(topic_name_length as usize);
+
+                // 3.3.2.3 PUBLISH Properties
+                let properties = buf.get_properties(protocol)?;
 
                 // 3.3.3 PUBLISH Payload
-                let payload =
-                    &buf[..(remaining_length as usize - (input.len() - buf.remaining() - 2))];
+                let payload = buf.to_vec();
                 buf.advance(payload.len());
 
                 ControlPacket::Publish {
                     topic: topic_name.into(),
-                    payload: payload.to_vec(),
+                    qos,
+                    packet_identifier,
+                    properties,
+                    payload,
                 }
             }
             // 3.4.2 PUBACK Variable Header
             PacketType::Puback => {
-                //
-                ControlPacket::Puback {}
+                let packet_identifier = if remaining_length >= 2 {
+                    buf.get_u16()
+                } else {
+                    0
+                };
+
+                // 3.4.2.1 PUBACK Reason Code
+                let reason_code = if remaining_length >= 3 {
+                    match buf.get_u8() {
+                        0x00 => PubackReasonCode::Success,
+                        0x10 => PubackReasonCode::NoMatchingSubscribers,
+                        0x80 => PubackReasonCode::UnspecifiedError,
+                        0x83 => PubackReasonCode::ImplementationSpecificError,
+                        0x87 => PubackReasonCode::NotAuthorized,
+                        0x90 => PubackReasonCode::TopicNameInvalid,
+                        0x91 => PubackReasonCode::PacketIdentifierInUse,
+                        0x97 => PubackReasonCode::QuotaExceeded,
+                        0x99 => PubackReasonCode::PayloadFormatInvalid,
+                        _ => return Err(Error::Invalid),
+                    }
+                } else {
+                    PubackReasonCode::Success
+                };
+
+                // 3.4.2.2 PUBACK Properties
+                if remaining_length >= 4 {
+                    buf.get_properties(protocol)?;
+                }
+
+                ControlPacket::Puback {
+                    packet_identifier,
+                    reason_code,
+                }
+            }
+            // 3.5.2 PUBREC Variable Header
+            PacketType::Pubrec => {
+                let packet_identifier = buf.get_u16_checked()?;
+
+                // 3.5.2.1 PUBREC Reason Code
+                let reason_code = if remaining_length >= 3 {
+                    match buf.get_u8() {
+                        0x00 => PubackReasonCode::Success,
+                        0x10 => PubackReasonCode::NoMatchingSubscribers,
+                        0x80 => PubackReasonCode::UnspecifiedError,
+                        0x83 => PubackReasonCode::ImplementationSpecificError,
+                        0x87 => PubackReasonCode::NotAuthorized,
+                        0x90 => PubackReasonCode::TopicNameInvalid,
+                        0x91 => PubackReasonCode::PacketIdentifierInUse,
+                        0x97 => PubackReasonCode::QuotaExceeded,
+                        0x99 => PubackReasonCode::PayloadFormatInvalid,
+                        _ => return Err(Error::Invalid),
+                    }
+                } else {
+                    PubackReasonCode::Success
+                };
+
+                // 3.5.2.2 PUBREC Properties
+                if remaining_length >= 4 {
+                    buf.get_properties(protocol)?;
+                }
+
+                ControlPacket::Pubrec {
+                    packet_identifier,
+                    reason_code,
+                }
+            }
+            // 3.6.2 PUBREL Variable Header
+            PacketType::Pubrel => {
+                // The reason code only distinguishes an unknown packet identifier.
+                let packet_identifier = buf.get_u16_checked()?;
+                ControlPacket::Pubrel { packet_identifier }
+            }
+            // 3.7.2 PUBCOMP Variable Header
+            PacketType::Pubcomp => {
+                let packet_identifier = buf.get_u16_checked()?;
+                ControlPacket::Pubcomp { packet_identifier }
             }
-            PacketType::Pubrec => todo!(),
-            PacketType::Pubrel => todo!(),
-            PacketType::Pubcomp => todo!(),
             PacketType::Subscribe => {
-                let packet_identifier = buf.get_u16();
+                let packet_identifier = buf.get_u16_checked()?;
+
+                // 3.8.2.1 SUBSCRIBE Properties
+                buf.get_properties(protocol)?;
 
                 // 3.8.3 SUBSCRIBE Payload
-                let topic_name_length = buf.get_u16();
-                let topic_name = &buf[..topic_name_length as usize];
-                let Ok(topic_name) = std::str::from_utf8(topic_name) else {
-                    return Err(Error::Invalid);
-                };
-                buf.advance(topic_name_length as usize);
-                let subscription_options = buf.get_u8();
+                let topic_name = buf.get_utf8_string()?;
+                let subscription_options = buf.get_u8_checked()?;
 
                 ControlPacket::Subscribe {
                     packet_identifier,
@@ -250,16 +433,82 @@ impl ControlPacket {
                 }
             }
             PacketType::Suback => {
-                let packet_identifier = buf.get_u16();
-                let qos = buf.get_u8();
+                let packet_identifier = buf.get_u16_checked()?;
+
+                // 3.9.2.1 SUBACK Properties
+                buf.get_properties(protocol)?;
+
+                // 3.9.3 SUBACK Payload
+                let mut reason_codes = Vec::with_capacity(buf.remaining());
+                while buf.has_remaining() {
+                    reason_codes.push(match buf.get_u8() {
+                        0x00 => SubscribeReasonCode::GrantedQoS0,
+                        0x01 => SubscribeReasonCode::GrantedQoS1,
+                        0x02 => SubscribeReasonCode::GrantedQoS2,
+                        0x80 => SubscribeReasonCode::UnspecifiedError,
+                        0x83 => SubscribeReasonCode::ImplementationSpecificError,
+                        0x87 => SubscribeReasonCode::NotAuthorized,
+                        0x8F => SubscribeReasonCode::TopicFilterInvalid,
+                        0x91 => SubscribeReasonCode::PacketIdentifierInUse,
+                        0x97 => SubscribeReasonCode::QuotaExceeded,
+                        0x9E => SubscribeReasonCode::SharedSubscriptionsNotSupported,
+                        0xA1 => SubscribeReasonCode::SubscriptionIdentifiersNotSupported,
+                        0xA2 => SubscribeReasonCode::WildcardSubscriptionsNotSupported,
+                        _ => return Err(Error::Invalid),
+                    });
+                }
 
                 ControlPacket::Suback {
                     packet_identifier,
-                    reason_codes: vec![SubscribeReasonCode::GrantedQoS0],
+                    reason_codes,
+                }
+            }
+            PacketType::Unsubscribe => {
+                let packet_identifier = buf.get_u16_checked()?;
+
+                // 3.10.2.1 UNSUBSCRIBE Properties
+                buf.get_properties(protocol)?;
+
+                // 3.10.3 UNSUBSCRIBE Payload
+                let mut topics = Vec::new();
+                while buf.has_remaining() {
+                    topics.push(buf.get_utf8_string()?.into());
+                }
+                if topics.is_empty() {
+                    return Err(Error::Invalid);
+                }
+
+                ControlPacket::Unsubscribe {
+                    packet_identifier,
+                    topics,
+                }
+            }
+            PacketType::Unsuback => {
+                let packet_identifier = buf.get_u16_checked()?;
+
+                // 3.11.2.1 UNSUBACK Properties
+                buf.get_properties(protocol)?;
+
+                // 3.11.3 UNSUBACK Payload
+                let mut reason_codes = Vec::with_capacity(buf.remaining());
+                while buf.has_remaining() {
+                    reason_codes.push(match buf.get_u8() {
+                        0x00 => UnsubscribeReasonCode::Success,
+                        0x11 => UnsubscribeReasonCode::NoSubscriptionExisted,
+                        0x80 => UnsubscribeReasonCode::UnspecifiedError,
+                        0x83 => UnsubscribeReasonCode::ImplementationSpecificError,
+                        0x87 => UnsubscribeReasonCode::NotAuthorized,
+                        0x8F => UnsubscribeReasonCode::TopicFilterInvalid,
+                        0x91 => UnsubscribeReasonCode::PacketIdentifierInUse,
+                        _ => return Err(Error::Invalid),
+                    });
+                }
+
+                ControlPacket::Unsuback {
+                    packet_identifier,
+                    reason_codes,
                 }
             }
-            PacketType::Unsubscribe => todo!(),
-            PacketType::Unsuback => todo!(),
             PacketType::Pingreq => {
                 //
                 ControlPacket::Pingreq
@@ -269,7 +518,7 @@ impl ControlPacket {
                 ControlPacket::Pingresp
             }
             PacketType::Disconnect => {
-                //
+                // 3.14.2.1 Disconnect Reason Code
                 let reason_code = if remaining_length > 0 {
                     match buf.get_u8() {
                         0x00 => DisconnectReasonCode::NormalDisconnection,
@@ -307,70 +556,203 @@ impl ControlPacket {
                     DisconnectReasonCode::NormalDisconnection
                 };
 
-                ControlPacket::Disconnect {
-                    reason_code: DisconnectReasonCode::NormalDisconnection,
+                // 3.14.2.2 DISCONNECT Properties
+                if remaining_length > 1 {
+                    buf.get_properties(protocol)?;
                 }
+
+                ControlPacket::Disconnect { reason_code }
+            }
+            PacketType::Auth => {
+                if protocol != Protocol::V5_0 {
+                    return Err(Error::Invalid);
+                }
+
+                // 3.15.2.1 Authenticate Reason Code
+                let reason_code = if remaining_length > 0 {
+                    match buf.get_u8() {
+                        0x00 => AuthReasonCode::Success,
+                        0x18 => AuthReasonCode::ContinueAuthentication,
+                        0x19 => AuthReasonCode::ReAuthenticate,
+                        _ => return Err(Error::Invalid),
+                    }
+                } else {
+                    AuthReasonCode::Success
+                };
+
+                // 3.15.2.2 AUTH Properties
+                if remaining_length > 1 {
+                    buf.get_properties(protocol)?;
+                }
+
+                ControlPacket::Auth { reason_code }
             }
-            PacketType::Auth => todo!(),
         };
-        Ok((packet, buf.remaining()))
+        Ok((packet, remaining_bytes))
     }
 
-    pub fn encode(&self) -> Result<Vec<u8>, Error> {
-        let mut buffer = Vec::new();
-        match self {
-            ControlPacket::Connect { clean_start } => {
-                buffer.put_u8(0x10);
-                buffer.put_u8(0x0c);
-                buffer.put_u8(0x00);
-                buffer.put_u8(0x04);
-                buffer.extend_from_slice(b"MQTT");
-                buffer.put_u8(0x04);
-                buffer.put_u8(0x02);
-                buffer.put_u8(0x00);
-                buffer.put_u8(0x00);
-                buffer.put_u8(0x00);
-                buffer.put_u8(0x00);
-            }
-            ControlPacket::Connack { reason_code } => {
-                buffer.put_u8(0x20);
-                buffer.put_u8(0x02);
-                buffer.put_u8(0x00);
-                buffer.put_u8(0x00);
-            }
-            ControlPacket::Publish { topic, payload } => {
-                let topic_name = topic.to_string();
-                buffer.put_u8(0x30);
-                buffer.put_variable_length((2 + topic_name.len() + payload.len()) as u32);
-                buffer.put_u16(topic_name.len() as u16);
-                buffer.extend_from_slice(topic_name.as_bytes());
-                buffer.extend_from_slice(payload.as_slice());
-            }
-            ControlPacket::Puback {} => {
-                buffer.put_u8(0x40);
-                buffer.put_u8(0x00);
+    pub fn encode(
+        &self,
+        protocol: Protocol,
+    ) -> Result<Vec<u8>, Error> {
+        let mut body = Vec::new();
+        let packet_type_and_flags = match self {
+            ControlPacket::Connect {
+                protocol,
+                clean_start,
+                keep_alive,
+                client_identifier,
+                username,
+                password,
+                properties,
+            } => {
+                let mut connect_flags = 0;
+                if *clean_start {
+                    connect_flags |= 0b00000010;
+                }
+                if username.is_some() {
+                    connect_flags |= 0b10000000;
+                }
+                if password.is_some() {
+                    connect_flags |= 0b01000000;
+                }
+                body.put_utf8_string("MQTT");
+                body.put_u8(match protocol {
+                    Protocol::V3_1_1 => 0x04,
+                    Protocol::V5_0 => 0x05,
+                });
+                body.put_u8(connect_flags);
+                body.put_u16(*keep_alive);
+                body.put_properties(*protocol, properties);
+                body.put_utf8_string(client_identifier);
+                if let Some(username) = username {
+                    body.put_utf8_string(username);
+                }
+                if let Some(password) = password {
+                    body.put_binary_data(password);
+                }
+                0x10
+            }
+            ControlPacket::Connack {
+                session_present,
+                reason_code,
+                properties,
+            } => {
+                body.put_u8(*session_present as u8);
+                body.put_u8(match reason_code {
+                    ConnectReasonCode::Success => 0x00,
+                    ConnectReasonCode::UnspecifiedError => 0x80,
+                    ConnectReasonCode::MalformedPacket => 0x81,
+                    ConnectReasonCode::ProtocolError => 0x82,
+                    ConnectReasonCode::ImplementationSpecificError => 0x83,
+                    ConnectReasonCode::UnsupportedProtocolVersion => 0x84,
+                    ConnectReasonCode::ClientIdentifierNotValid => 0x85,
+                    ConnectReasonCode::BadUserNameOrPassword => 0x86,
+                    ConnectReasonCode::NotAuthorized => 0x87,
+                    ConnectReasonCode::ServerUnavailable => 0x88,
+                    ConnectReasonCode::ServerBusy => 0x89,
+                    ConnectReasonCode::Banned => 0x8A,
+                    ConnectReasonCode::BadAuthenticationMethod => 0x8B,
+                    ConnectReasonCode::TopicNameInvalid => 0x8C,
+                    ConnectReasonCode::PacketTooLarge => 0x8F,
+                    ConnectReasonCode::QuotaExceeded => 0x97,
+                    ConnectReasonCode::PayloadFormatInvalid => 0x99,
+                    ConnectReasonCode::RetainNotSupported => 0x9A,
+                    ConnectReasonCode::QoSNotSupported => 0x9B,
+                    ConnectReasonCode::UseAnotherServer => 0x9C,
+                    ConnectReasonCode::ServerMoved => 0x9D,
+                    ConnectReasonCode::ConnectionRateExceeded => 0x9F,
+                });
+                body.put_properties(protocol, properties);
+                0x20
+            }
+            ControlPacket::Publish {
+                topic,
+                qos,
+                packet_identifier,
+                properties,
+                payload,
+            } => {
+                body.put_utf8_string(&topic.to_string());
+                if *qos != QoS::AtMostOnce {
+                    body.put_u16(*packet_identifier);
+                }
+                body.put_properties(protocol, properties);
+                body.extend_from_slice(payload.as_slice());
+                match qos {
+                    QoS::AtMostOnce => 0x30,
+                    QoS::AtLeastOnce => 0x32,
+                    QoS::ExactlyOnce => 0x34,
+                }
+            }
+            ControlPacket::Puback {
+                packet_identifier,
+                reason_code,
+            } => {
+                body.put_u16(*packet_identifier);
+                // MQTT 3.1.1 has no reason codes, and 5.0 leaves out that of a success.
+                if protocol == Protocol::V5_0 && *reason_code != PubackReasonCode::Success {
+                    body.put_u8(match reason_code {
+                        PubackReasonCode::Success => 0x00,
+                        PubackReasonCode::NoMatchingSubscribers => 0x10,
+                        PubackReasonCode::UnspecifiedError => 0x80,
+                        PubackReasonCode::ImplementationSpecificError => 0x83,
+                        PubackReasonCode::NotAuthorized => 0x87,
+                        PubackReasonCode::TopicNameInvalid => 0x90,
+                        PubackReasonCode::PacketIdentifierInUse => 0x91,
+                        PubackReasonCode::QuotaExceeded => 0x97,
+                        PubackReasonCode::PayloadFormatInvalid => 0x99,
+                    });
+                }
+                0x40
+            }
+            ControlPacket::Pubrec {
+                packet_identifier,
+                reason_code,
+            } => {
+                body.put_u16(*packet_identifier);
+                // MQTT 3.1.1 has no reason codes, and 5.0 leaves out that of a success.
+                if protocol == Protocol::V5_0 && *reason_code != PubackReasonCode::Success {
+                    body.put_u8(match reason_code {
+                        PubackReasonCode::Success => 0x00,
+                        PubackReasonCode::NoMatchingSubscribers => 0x10,
+                        PubackReasonCode::UnspecifiedError => 0x80,
+                        PubackReasonCode::ImplementationSpecificError => 0x83,
+                        PubackReasonCode::NotAuthorized => 0x87,
+                        PubackReasonCode::TopicNameInvalid => 0x90,
+                        PubackReasonCode::PacketIdentifierInUse => 0x91,
+                        PubackReasonCode::QuotaExceeded => 0x97,
+                        PubackReasonCode::PayloadFormatInvalid => 0x99,
+                    });
+                }
+                0x50
+            }
+            ControlPacket::Pubrel { packet_identifier } => {
+                body.put_u16(*packet_identifier);
+                0x62
+            }
+            ControlPacket::Pubcomp { packet_identifier } => {
+                body.put_u16(*packet_identifier);
+                0x70
             }
             ControlPacket::Subscribe {
                 packet_identifier,
                 topic,
             } => {
-                let topic_name = topic.to_string();
-                buffer.put_u8(0x82); // control packet type and flags
-                buffer.put_variable_length((4 + topic_name.len() + 1) as u32); // remaining length
-                buffer.put_u16(*packet_identifier);
-                buffer.put_u16(topic_name.len() as u16);
-                buffer.extend_from_slice(topic_name.as_bytes());
-                buffer.put_u8(0x00); // subscription options
+                body.put_u16(*packet_identifier);
+                body.put_properties(protocol, &Properties::default());
+                body.put_utf8_string(&topic.to_string());
+                body.put_u8(0x00); // subscription options
+                0x82
             }
             ControlPacket::Suback {
                 packet_identifier,
                 reason_codes,
             } => {
-                buffer.put_u8(0x90); // control packet type and flags
-                buffer.put_variable_length((2 + reason_codes.len()) as u32); // remaining length
-                buffer.put_u16(*packet_identifier);
+                body.put_u16(*packet_identifier);
+                body.put_properties(protocol, &Properties::default());
                 for reason_code in reason_codes {
-                    buffer.put_u8(match reason_code {
+                    body.put_u8(match reason_code {
                         SubscribeReasonCode::GrantedQoS0 => 0x00,
                         SubscribeReasonCode::GrantedQoS1 => 0x01,
                         SubscribeReasonCode::GrantedQoS2 => 0x02,
@@ -385,54 +767,119 @@ impl ControlPacket {
                         SubscribeReasonCode::WildcardSubscriptionsNotSupported => 0xA2,
                     });
                 }
+                0x90
             }
-            ControlPacket::Pingreq => {
-                buffer.put_u8(0xC0);
-                buffer.put_u8(0x00);
+            ControlPacket::Unsubscribe {
+                packet_identifier,
+                topics,
+            } => {
+                body.put_u16(*packet_identifier);
+                body.put_properties(protocol, &Properties::default());
+                for topic in topics {
+                    body.put_utf8_string(&topic.to_string());
+                }
+                0xA2
             }
-            ControlPacket::Pingresp => {
-                buffer.put_u8(0xD0);
-                buffer.put_u8(0x00);
+            ControlPacket::Unsuback {
+                packet_identifier,
+                reason_codes,
+            } => {
+                body.put_u16(*packet_identifier);
+                body.put_properties(protocol, &Properties::default());
+                if protocol == Protocol::V5_0 {
+                    for reason_code in reason_codes {
+                        body.put_u8(match reason_code {
+                            UnsubscribeReasonCode::Success => 0x00,
+                            UnsubscribeReasonCode::NoSubscriptionExisted => 0x11,
+                            UnsubscribeReasonCode::UnspecifiedError => 0x80,
+                            UnsubscribeReasonCode::ImplementationSpecificError => 0x83,
+                            UnsubscribeReasonCode::NotAuthorized => 0x87,
+                            UnsubscribeReasonCode::TopicFilterInvalid => 0x8F,
+                            UnsubscribeReasonCode::PacketIdentifierInUse => 0x91,
+                        });
+                    }
+                }
+                0xB0
             }
+            ControlPacket::Pingreq => 0xC0,
+            ControlPacket::Pingresp => 0xD0,
             ControlPacket::Disconnect { reason_code } => {
-                buffer.put_u8(0xE0);
-                buffer.put_u8(match reason_code {
-                    DisconnectReasonCode::NormalDisconnection => 0x00,
-                    DisconnectReasonCode::DisconnectWithWillMessage => 0x04,
-                    DisconnectReasonCode::UnspecifiedError => 0x80,
-                    DisconnectReasonCode::MalformedPacket => 0x81,
-                    DisconnectReasonCode::ProtocolError => 0x82,
-                    DisconnectReasonCode::ImplementationSpecificError => 0x83,
-                    DisconnectReasonCode::NotAuthorized => 0x87,
-                    DisconnectReasonCode::ServerBusy => 0x89,
-                    DisconnectReasonCode::ServerShuttingDown => 0x8B,
-                    DisconnectReasonCode::KeepAliveTimeout => 0x8D,
-                    DisconnectReasonCode::SessionTakenOver => 0x8E,
-                    DisconnectReasonCode::TopicFilterInvalid => 0x8F,
-                    DisconnectReasonCode::TopicNameInvalid => 0x90,
-                    DisconnectReasonCode::ReceiveMaximumExceeded => 0x93,
-                    DisconnectReasonCode::TopicAliasInvalid => 0x94,
-                    DisconnectReasonCode::PacketTooLarge => 0x95,
-                    DisconnectReasonCode::MessageRateTooHigh => 0x96,
-                    DisconnectReasonCode::QuotaExceeded => 0x97,
-                    DisconnectReasonCode::AdministrativeAction => 0x98,
-                    DisconnectReasonCode::PayloadFormatInvalid => 0x99,
-                    DisconnectReasonCode::RetainNotSupported => 0x9A,
-                    DisconnectReasonCode::QoSNotSupported => 0x9B,
-                    DisconnectReasonCode::UseAnotherServer => 0x9C,
-                    DisconnectReasonCode::ServerMoved => 0x9D,
-                    DisconnectReasonCode::SharedSubscriptionsNotSupported => 0x9E,
-                    DisconnectReasonCode::ConnectionRateExceeded => 0x9F,
-                    DisconnectReasonCode::MaximumConnectTime => 0xA0,
-                    DisconnectReasonCode::SubscriptionIdentifiersNotSupported => 0xA1,
-                    DisconnectReasonCode::WildcardSubscriptionsNotSupported => 0xA2,
-                });
+                if protocol == Protocol::V5_0
+                    && *reason_code != DisconnectReasonCode::NormalDisconnection
+                {
+                    body.put_u8(match reason_code {
+                        DisconnectReasonCode::NormalDisconnection => 0x00,
+                        DisconnectReasonCode::DisconnectWithWillMessage => 0x04,
+                        DisconnectReasonCode::UnspecifiedError => 0x80,
+                        DisconnectReasonCode::MalformedPacket => 0x81,
+                        DisconnectReasonCode::ProtocolError => 0x82,
+                        DisconnectReasonCode::ImplementationSpecificError => 0x83,
+                        DisconnectReasonCode::NotAuthorized => 0x87,
+                        DisconnectReasonCode::ServerBusy => 0x89,
+                        DisconnectReasonCode::ServerShuttingDown => 0x8B,
+                        DisconnectReasonCode::KeepAliveTimeout => 0x8D,
+                        DisconnectReasonCode::SessionTakenOver => 0x8E,
+                        DisconnectReasonCode::TopicFilterInvalid => 0x8F,
+                        DisconnectReasonCode::TopicNameInvalid => 0x90,
+                        DisconnectReasonCode::ReceiveMaximumExceeded => 0x93,
+                        DisconnectReasonCode::TopicAliasInvalid => 0x94,
+                        DisconnectReasonCode::PacketTooLarge => 0x95,
+                        DisconnectReasonCode::MessageRateTooHigh => 0x96,
+                        DisconnectReasonCode::QuotaExceeded => 0x97,
+                        DisconnectReasonCode::AdministrativeAction => 0x98,
+                        DisconnectReasonCode::PayloadFormatInvalid => 0x99,
+                        DisconnectReasonCode::RetainNotSupported => 0x9A,
+                        DisconnectReasonCode::QoSNotSupported => 0x9B,
+                        DisconnectReasonCode::UseAnotherServer => 0x9C,
+                        DisconnectReasonCode::ServerMoved => 0x9D,
+                        DisconnectReasonCode::SharedSubscriptionsNotSupported => 0x9E,
+                        DisconnectReasonCode::ConnectionRateExceeded => 0x9F,
+                        DisconnectReasonCode::MaximumConnectTime => 0xA0,
+                        DisconnectReasonCode::SubscriptionIdentifiersNotSupported => 0xA1,
+                        DisconnectReasonCode::WildcardSubscriptionsNotSupported => 0xA2,
+                    });
+                }
+                0xE0
             }
-        }
+            ControlPacket::Auth { reason_code } => {
+                if *reason_code != AuthReasonCode::Success {
+                    body.put_u8(match reason_code {
+                        AuthReasonCode::Success => 0x00,
+                        AuthReasonCode::ContinueAuthentication => 0x18,
+                        AuthReasonCode::ReAuthenticate => 0x19,
+                    });
+                }
+                0xF0
+            }
+        };
+        let mut buffer = Vec::with_capacity(body.len() + 5);
+        buffer.put_u8(packet_type_and_flags);
+        buffer.put_variable_length(body.len() as u32);
+        buffer.extend_from_slice(&body);
         Ok(buffer)
     }
 }
 
+fn decode_remaining_length(buf: &mut &[u8]) -> Result<u32, Error> {
+    let mut multiplier: u32 = 1;
+    let mut value: u32 = 0;
+    loop {
+        if !buf.has_remaining() {
+            return Err(Error::Incomplete);
+        }
+        let encoded_byte = buf.get_u8();
+        value += (encoded_byte as u32 & 127) * multiplier;
+        if (encoded_byte & 128) == 0 {
+            break;
+        }
+        multiplier *= 128;
+        if multiplier > 128 * 128 * 128 {
+            return Err(Error::Invalid);
+        }
+    }
+    Ok(value)
+}
+
 #[derive(Debug, PartialEq)]
 pub struct ConnectFlags {
     will: Will,
@@ -452,6 +899,21 @@ pub struct ConnectAcknowledgeFlags {
 
 pub trait BufExt {
     fn get_variable_length(&mut self) -> Result<u32, ()>;
+
+    fn get_u8_checked(&mut self) -> Result<u8, Error>;
+
+    fn get_u16_checked(&mut self) -> Result<u16, Error>;
+
+    fn get_u32_checked(&mut self) -> Result<u32, Error>;
+
+    fn get_utf8_string(&mut self) -> Result<String, Error>;
+
+    fn get_binary_data(&mut self) -> Result<Vec<u8>, Error>;
+
+    fn get_properties(
+        &mut self,
+        protocol: Protocol,
+    ) -> Result<Properties, Error>;
 }
 
 pub trait BufMutExt {
@@ -459,6 +921,22 @@ pub trait BufMutExt {
         &mut self,
         value: u32,
     );
+
+    fn put_utf8_string(
+        &mut self,
+        value: &str,
+    );
+
+    fn put_binary_data(
+        &mut self,
+        value: &[u8],
+    );
+
+    fn put_properties(
+        &mut self,
+        protocol: Protocol,
+        properties: &Properties,
+    );
 }
 
 impl<T: Buf> BufExt for T {
@@ -467,6 +945,9 @@ impl<T: Buf> BufExt for T {
         let mut value: u32 = 0;
         let mut encoded_byte;
         loop {
+            if !self.has_remaining() {
+                return Err(());
+            }
             encoded_byte = self.get_u8();
             value += (encoded_byte as u32 & 127) * multiplier;
             if (encoded_byte & 128) == 0 {
@@ -479,6 +960,111 @@ impl<T: Buf> BufExt for T {
         }
         Ok(value)
     }
+
+    fn get_u8_checked(&mut self) -> Result<u8, Error> {
+        if self.remaining() < 1 {
+            return Err(Error::Invalid);
+        }
+        Ok(self.get_u8())
+    }
+
+    fn get_u16_checked(&mut self) -> Result<u16, Error> {
+        if self.remaining() < 2 {
+            return Err(Error::Invalid);
+        }
+        Ok(self.get_u16())
+    }
+
+    fn get_u32_checked(&mut self) -> Result<u32, Error> {
+        if self.remaining() < 4 {
+            return Err(Error::Invalid);
+        }
+        Ok(self.get_u32())
+    }
+
+    fn get_utf8_string(&mut self) -> Result<String, Error> {
+        let data = self.get_binary_data()?;
+        String::from_utf8(data).map_err(|_| Error::Invalid)
+    }
+
+    fn get_binary_data(&mut self) -> Result<Vec<u8>, Error> {
+        let length = self.get_u16_checked()? as usize;
+        if self.remaining() < length {
+            return Err(Error::Invalid);
+        }
+        let mut data = vec![0; length];
+        self.copy_to_slice(&mut data);
+        Ok(data)
+    }
+
+    fn get_properties(
+        &mut self,
+        protocol: Protocol,
+    ) -> Result<Properties, Error> {
+        let mut properties = Properties::default();
+        if protocol != Protocol::V5_0 {
+            return Ok(properties);
+        }
+        let Ok(properties_length) = self.get_variable_length() else {
+            return Err(Error::Invalid);
+        };
+        let properties_length = properties_length as usize;
+        if self.remaining() < properties_length {
+            return Err(Error::Invalid);
+        }
+        let mut buf = Buf::take(&mut *self, properties_length);
+        while buf.has_remaining() {
+            let Ok(identifier) = buf.get_variable_length() else {
+                return Err(Error::Invalid);
+            };
+            match identifier {
+                0x01 => properties.payload_format_indicator = Some(buf.get_u8_checked()?),
+                0x02 => properties.message_expiry_interval = Some(buf.get_u32_checked()?),
+                0x03 => properties.content_type = Some(buf.get_utf8_string()?),
+                0x08 => properties.response_topic = Some(buf.get_utf8_string()?.into()),
+                0x09 => properties.correlation_data = Some(buf.get_binary_data()?),
+                0x0B => {
+                    // Subscription Identifier
+                    if buf.get_variable_length().is_err() {
+                        return Err(Error::Invalid);
+                    }
+                }
+                0x11 => properties.session_expiry_interval = Some(buf.get_u32_checked()?),
+                0x12 => properties.assigned_client_identifier = Some(buf.get_utf8_string()?),
+                0x13 => properties.server_keep_alive = Some(buf.get_u16_checked()?),
+                0x15 | 0x1A | 0x1C => {
+                    // Authentication Method, Response Information, Server Reference
+                    buf.get_utf8_string()?;
+                }
+                0x16 => {
+                    // Authentication Data
+                    buf.get_binary_data()?;
+                }
+                0x17 | 0x19 | 0x24 | 0x25 | 0x28 | 0x29 | 0x2A => {
+                    // Request Problem Information, Request Response Information, Maximum QoS,
+                    // Retain Available, Wildcard, Subscription Identifier and Shared
+                    // Subscription Available
+                    buf.get_u8_checked()?;
+                }
+                0x18 => {
+                    // Will Delay Interval
+                    buf.get_u32_checked()?;
+                }
+                0x1F => properties.reason_string = Some(buf.get_utf8_string()?),
+                0x21 => properties.receive_maximum = Some(buf.get_u16_checked()?),
+                0x22 => properties.topic_alias_maximum = Some(buf.get_u16_checked()?),
+                0x23 => properties.topic_alias = Some(buf.get_u16_checked()?),
+                0x26 => {
+                    let key = buf.get_utf8_string()?;
+                    let value = buf.get_utf8_string()?;
+                    properties.user_properties.push((key, value));
+                }
+                0x27 => properties.maximum_packet_size = Some(buf.get_u32_checked()?),
+                _ => return Err(Error::Invalid),
+            }
+        }
+        Ok(properties)
+    }
 }
 
 impl<T: BufMut> BufMutExt for T {
@@ -498,6 +1084,91 @@ impl<T: BufMut> BufMutExt for T {
             }
         }
     }
+
+    fn put_utf8_string(
+        &mut self,
+        value: &str,
+    ) {
+        self.put_binary_data(value.as_bytes());
+    }
+
+    fn put_binary_data(
+        &mut self,
+        value: &[u8],
+    ) {
+        self.put_u16(value.len() as u16);
+        self.put_slice(value);
+    }
+
+    fn put_properties(
+        &mut self,
+        protocol: Protocol,
+        properties: &Properties,
+    ) {
+        if protocol != Protocol::V5_0 {
+            return;
+        }
+        let mut buffer = Vec::new();
+        if let Some(value) = properties.payload_format_indicator {
+            buffer.put_u8(0x01);
+            buffer.put_u8(value);
+        }
+        if let Some(value) = properties.message_expiry_interval {
+            buffer.put_u8(0x02);
+            buffer.put_u32(value);
+        }
+        if let Some(value) = &properties.content_type {
+            buffer.put_u8(0x03);
+            buffer.put_utf8_string(value);
+        }
+        if let Some(value) = &properties.response_topic {
+            buffer.put_u8(0x08);
+            buffer.put_utf8_string(&value.to_string());
+        }
+        if let Some(value) = &properties.correlation_data {
+            buffer.put_u8(0x09);
+            buffer.put_binary_data(value);
+        }
+        if let Some(value) = properties.session_expiry_interval {
+            buffer.put_u8(0x11);
+            buffer.put_u32(value);
+        }
+        if let Some(value) = &properties.assigned_client_identifier {
+            buffer.put_u8(0x12);
+            buffer.put_utf8_string(value);
+        }
+        if let Some(value) = properties.server_keep_alive {
+            buffer.put_u8(0x13);
+            buffer.put_u16(value);
+        }
+        if let Some(value) = &properties.reason_string {
+            buffer.put_u8(0x1F);
+            buffer.put_utf8_string(value);
+        }
+        if let Some(value) = properties.receive_maximum {
+            buffer.put_u8(0x21);
+            buffer.put_u16(value);
+        }
+        if let Some(value) = properties.topic_alias_maximum {
+            buffer.put_u8(0x22);
+            buffer.put_u16(value);
+        }
+        if let Some(value) = properties.topic_alias {
+            buffer.put_u8(0x23);
+            buffer.put_u16(value);
+        }
+        for (key, value) in properties.user_properties.iter() {
+            buffer.put_u8(0x26);
+            buffer.put_utf8_string(key);
+            buffer.put_utf8_string(value);
+        }
+        if let Some(value) = properties.maximum_packet_size {
+            buffer.put_u8(0x27);
+            buffer.put_u32(value);
+        }
+        self.put_variable_length(buffer.len() as u32);
+        self.put_slice(&buffer);
+    }
 }
 
 #[cfg(test)]
@@ -506,8 +1177,16 @@ mod tests {
 
     #[test]
     fn test_encode_connect() {
-        let packet = ControlPacket::Connect { clean_start: true };
-        let actual = packet.encode().unwrap();
+        let packet = ControlPacket::Connect {
+            protocol: Protocol::V3_1_1,
+            clean_start: true,
+            keep_alive: 0,
+            client_identifier: String::new(),
+            username: None,
+            password: None,
+            properties: Properties::default(),
+        };
+        let actual = packet.encode(Protocol::V3_1_1).unwrap();
         let expected = [
             0x10, 0x0c, 0x00, 0x04, b'M', b'Q', b'T', b'T', 0x04, 0x02, 0x00, 0x00, 0x00, 0x00,
         ];
@@ -519,8 +1198,16 @@ mod tests {
         let packet = [
             0x10, 0x0c, 0x00, 0x04, b'M', b'Q', b'T', b'T', 0x04, 0x02, 0x00, 0x00, 0x00, 0x00,
         ];
-        let (actual, remaining_bytes) = ControlPacket::decode(&packet).unwrap();
-        let expected = ControlPacket::Connect { clean_start: true };
+        let (actual, remaining_bytes) = ControlPacket::decode(&packet, Protocol::V3_1_1).unwrap();
+        let expected = ControlPacket::Connect {
+            protocol: Protocol::V3_1_1,
+            clean_start: true,
+            keep_alive: 0,
+            client_identifier: String::new(),
+            username: None,
+            password: None,
+            properties: Properties::default(),
+        };
         assert_eq!(actual, expected);
         assert_eq!(remaining_bytes, 0);
     }
@@ -528,9 +1215,11 @@ mod tests {
     #[test]
     fn test_encode_connack() {
         let packet = ControlPacket::Connack {
+            session_present: false,
             reason_code: ConnectReasonCode::Success,
+            properties: Properties::default(),
         };
-        let actual = packet.encode().unwrap();
+        let actual = packet.encode(Protocol::V3_1_1).unwrap();
         let expected = [0x20, 0x02, 0x00, 0x00];
         assert_eq!(actual, expected);
     }
@@ -538,9 +1227,11 @@ mod tests {
     #[test]
     fn test_decode_connack() {
         let packet = [0x20, 0x02, 0x00, 0x00];
-        let (actual, remaining_bytes) = ControlPacket::decode(&packet).unwrap();
+        let (actual, remaining_bytes) = ControlPacket::decode(&packet, Protocol::V3_1_1).unwrap();
         let expected = ControlPacket::Connack {
+            session_present: false,
             reason_code: ConnectReasonCode::Success,
+            properties: Properties::default(),
         };
         assert_eq!(actual, expected);
         assert_eq!(remaining_bytes, 0);
@@ -550,9 +1241,12 @@ mod tests {
     fn test_encode_publish_1() {
         let packet = ControlPacket::Publish {
             topic: Topic::from("test/topic"),
+            qos: QoS::AtMostOnce,
+            packet_identifier: 0,
+            properties: Properties::default(),
             payload: b"test message".to_vec(),
         };
-        let actual = packet.encode().unwrap();
+        let actual = packet.encode(Protocol::V3_1_1).unwrap();
         let expected = [
             0x30, 0x18, 0x00, 0x0a, 0x74, 0x65, 0x73, 0x74, 0x2f, 0x74, 0x6f, 0x70, 0x69, 0x63,
             0x74, 0x65, 0x73, 0x74, 0x20, 0x6d, 0x65, 0x73, 0x73, 0x61, 0x67, 0x65,
@@ -564,9 +1258,12 @@ mod tests {
     fn test_encode_publish_2() {
         let packet = ControlPacket::Publish {
             topic: Topic::from("abc/def/ghi/jkl/mno"),
+            qos: QoS::AtMostOnce,
+            packet_identifier: 0,
+            properties: Properties::default(),
             payload: b"all your base are belong to us".to_vec(),
         };
-        let actual = packet.encode().unwrap();
+        let actual = packet.encode(Protocol::V3_1_1).unwrap();
         let expected = [
             0x30, 0x33, 0x00, 0x13, 0x61, 0x62, 0x63, 0x2f, 0x64, 0x65, 0x66, 0x2f, 0x67, 0x68,
             0x69, 0x2f, 0x6a, 0x6b, 0x6c, 0x2f, 0x6d, 0x6e, 0x6f, 0x61, 0x6c, 0x6c, 0x20, 0x79,
@@ -582,9 +1279,12 @@ mod tests {
             0x30, 0x18, 0x00, 0x0a, 0x74, 0x65, 0x73, 0x74, 0x2f, 0x74, 0x6f, 0x70, 0x69, 0x63,
             0x74, 0x65, 0x73, 0x74, 0x20, 0x6d, 0x65, 0x73, 0x73, 0x61, 0x67, 0x65,
         ];
-        let (actual, remaining_bytes) = ControlPacket::decode(&packet).unwrap();
+        let (actual, remaining_bytes) = ControlPacket::decode(&packet, Protocol::V3_1_1).unwrap();
         let expected = ControlPacket::Publish {
             topic: Topic::from("test/topic"),
+            qos: QoS::AtMostOnce,
+            packet_identifier: 0,
+            properties: Properties::default(),
             payload: b"test message".to_vec(),
         };
         assert_eq!(actual, expected);
@@ -599,9 +1299,12 @@ mod tests {
             0x6f, 0x75, 0x72, 0x20, 0x62, 0x61, 0x73, 0x65, 0x20, 0x61, 0x72, 0x65, 0x20, 0x62,
             0x65, 0x6c, 0x6f, 0x6e, 0x67, 0x20, 0x74, 0x6f, 0x20, 0x75, 0x73,
         ];
-        let (actual, remaining_bytes) = ControlPacket::decode(&packet).unwrap();
+        let (actual, remaining_bytes) = ControlPacket::decode(&packet, Protocol::V3_1_1).unwrap();
         let expected = ControlPacket::Publish {
             topic: Topic::from("abc/def/ghi/jkl/mno"),
+            qos: QoS::AtMostOnce,
+            packet_identifier: 0,
+            properties: Properties::default(),
             payload: b"all your base are belong to us".to_vec(),
         };
         assert_eq!(actual, expected);
@@ -610,28 +1313,151 @@ mod tests {
 
     #[test]
     fn test_encode_puback() {
-        let packet = ControlPacket::Puback {};
-        let actual = packet.encode().unwrap();
-        let expected = [0x40, 0x00];
+        let packet = ControlPacket::Puback {
+            packet_identifier: 0,
+            reason_code: PubackReasonCode::Success,
+        };
+        let actual = packet.encode(Protocol::V3_1_1).unwrap();
+        let expected = [0x40, 0x02, 0x00, 0x00];
         assert_eq!(actual, expected);
     }
 
     #[test]
     fn test_decode_puback() {
         let packet = [0x40, 0x00];
-        let (actual, remaining_bytes) = ControlPacket::decode(&packet).unwrap();
-        let expected = ControlPacket::Puback {};
+        let (actual, remaining_bytes) = ControlPacket::decode(&packet, Protocol::V3_1_1).unwrap();
+        let expected = ControlPacket::Puback {
+            packet_identifier: 0,
+            reason_code: PubackReasonCode::Success,
+        };
+        assert_eq!(actual, expected);
+        assert_eq!(remaining_bytes, 0);
+    }
+
+    #[test]
+    fn test_encode_puback_v5() {
+        let packet = ControlPacket::Puback {
+            packet_identifier: 7,
+            reason_code: PubackReasonCode::Success,
+        };
+        let actual = packet.encode(Protocol::V5_0).unwrap();
+        let expected = [0x40, 0x02, 0x00, 0x07];
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_encode_puback_not_authorized() {
+        let packet = ControlPacket::Puback {
+            packet_identifier: 7,
+            reason_code: PubackReasonCode::NotAuthorized,
+        };
+        let actual = packet.encode(Protocol::V5_0).unwrap();
+        let expected = [0x40, 0x03, 0x00, 0x07, 0x87];
+        assert_eq!(actual, expected);
+        // MQTT 3.1.1 cannot carry the reason.
+        let actual = packet.encode(Protocol::V3_1_1).unwrap();
+        let expected = [0x40, 0x02, 0x00, 0x07];
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_decode_puback_not_authorized() {
+        let packet = [0x40, 0x03, 0x00, 0x07, 0x87];
+        let (actual, remaining_bytes) = ControlPacket::decode(&packet, Protocol::V5_0).unwrap();
+        let expected = ControlPacket::Puback {
+            packet_identifier: 7,
+            reason_code: PubackReasonCode::NotAuthorized,
+        };
         assert_eq!(actual, expected);
         assert_eq!(remaining_bytes, 0);
     }
 
+    #[test]
+    fn test_encode_decode_unsubscribe() {
+        let packet = ControlPacket::Unsubscribe {
+            packet_identifier: 3,
+            topics: vec![Topic::from("a/b"), Topic::from("c")],
+        };
+        let actual = packet.encode(Protocol::V3_1_1).unwrap();
+        let expected = [
+            0xA2, 0x0A, 0x00, 0x03, 0x00, 0x03, 0x61, 0x2f, 0x62, 0x00, 0x01, 0x63,
+        ];
+        assert_eq!(actual, expected);
+        let (actual, remaining_bytes) = ControlPacket::decode(&expected, Protocol::V3_1_1).unwrap();
+        assert_eq!(actual, packet);
+        assert_eq!(remaining_bytes, 0);
+    }
+
+    #[test]
+    fn test_decode_unsuback() {
+        let packet = [0xB0, 0x02, 0x00, 0x03];
+        let (actual, remaining_bytes) = ControlPacket::decode(&packet, Protocol::V3_1_1).unwrap();
+        let expected = ControlPacket::Unsuback {
+            packet_identifier: 3,
+            reason_codes: Vec::new(),
+        };
+        assert_eq!(actual, expected);
+        assert_eq!(remaining_bytes, 0);
+        let packet = [0xB0, 0x05, 0x00, 0x03, 0x00, 0x00, 0x11];
+        let (actual, remaining_bytes) = ControlPacket::decode(&packet, Protocol::V5_0).unwrap();
+        let expected = ControlPacket::Unsuback {
+            packet_identifier: 3,
+            reason_codes: vec![
+                UnsubscribeReasonCode::Success,
+                UnsubscribeReasonCode::NoSubscriptionExisted,
+            ],
+        };
+        assert_eq!(actual, expected);
+        assert_eq!(remaining_bytes, 0);
+        assert_eq!(expected.encode(Protocol::V5_0).unwrap(), packet);
+    }
+
+    #[test]
+    fn test_decode_auth() {
+        let packet = [0xF0, 0x00];
+        let (actual, remaining_bytes) = ControlPacket::decode(&packet, Protocol::V5_0).unwrap();
+        let expected = ControlPacket::Auth {
+            reason_code: AuthReasonCode::Success,
+        };
+        assert_eq!(actual, expected);
+        assert_eq!(remaining_bytes, 0);
+        let packet = [0xF0, 0x02, 0x18, 0x00];
+        let (actual, _) = ControlPacket::decode(&packet, Protocol::V5_0).unwrap();
+        let expected = ControlPacket::Auth {
+            reason_code: AuthReasonCode::ContinueAuthentication,
+        };
+        assert_eq!(actual, expected);
+        // MQTT 3.1.1 has no AUTH packet.
+        assert!(matches!(
+            ControlPacket::decode(&packet, Protocol::V3_1_1),
+            Err(Error::Invalid)
+        ));
+    }
+
+    #[test]
+    fn test_connect_with_credentials() {
+        let packet = ControlPacket::Connect {
+            protocol: Protocol::V3_1_1,
+            clean_start: true,
+            keep_alive: 30,
+            client_identifier: "kodi".into(),
+            username: Some("kodi".into()),
+            password: Some(b"secret".to_vec()),
+            properties: Properties::default(),
+        };
+        let encoded = packet.encode(Protocol::V3_1_1).unwrap();
+        let (actual, remaining_bytes) = ControlPacket::decode(&encoded, Protocol::V3_1_1).unwrap();
+        assert_eq!(actual, packet);
+        assert_eq!(remaining_bytes, 0);
+    }
+
     #[test]
     fn test_encode_subscribe_1() {
         let packet = ControlPacket::Subscribe {
             packet_identifier: 4,
             topic: Topic::from("lararium/station"),
         };
-        let actual = packet.encode().unwrap();
+        let actual = packet.encode(Protocol::V3_1_1).unwrap();
         let expected = [
             0x82, 0x15, 0x00, 0x04, 0x00, 0x10, 0x6c, 0x61, 0x72, 0x61, 0x72, 0x69, 0x75, 0x6d,
             0x2f, 0x73, 0x74, 0x61, 0x74, 0x69, 0x6f, 0x6e, 0x00,
@@ -645,7 +1471,7 @@ mod tests {
             packet_identifier: 3,
             topic: Topic::from("lararium/beehive"),
         };
-        let actual = packet.encode().unwrap();
+        let actual = packet.encode(Protocol::V3_1_1).unwrap();
         let expected = [
             0x82, 0x15, 0x00, 0x03, 0x00, 0x10, 0x6c, 0x61, 0x72, 0x61, 0x72, 0x69, 0x75, 0x6d,
             0x2f, 0x62, 0x65, 0x65, 0x68, 0x69, 0x76, 0x65, 0x00,
@@ -659,7 +1485,7 @@ mod tests {
             0x82, 0x15, 0x00, 0x04, 0x00, 0x10, 0x6c, 0x61, 0x72, 0x61, 0x72, 0x69, 0x75, 0x6d,
             0x2f, 0x73, 0x74, 0x61, 0x74, 0x69, 0x6f, 0x6e, 0x00,
         ];
-        let (actual, remaining_bytes) = ControlPacket::decode(&packet).unwrap();
+        let (actual, remaining_bytes) = ControlPacket::decode(&packet, Protocol::V3_1_1).unwrap();
         let expected = ControlPacket::Subscribe {
             packet_identifier: 4,
             topic: Topic::from("lararium/station"),
@@ -674,7 +1500,7 @@ mod tests {
             0x82, 0x15, 0x00, 0x03, 0x00, 0x10, 0x6c, 0x61, 0x72, 0x61, 0x72, 0x69, 0x75, 0x6d,
             0x2f, 0x62, 0x65, 0x65, 0x68, 0x69, 0x76, 0x65, 0x00,
         ];
-        let (actual, remaining_bytes) = ControlPacket::decode(&packet).unwrap();
+        let (actual, remaining_bytes) = ControlPacket::decode(&packet, Protocol::V3_1_1).unwrap();
         let expected = ControlPacket::Subscribe {
             packet_identifier: 3,
             topic: Topic::from("lararium/beehive"),
@@ -689,7 +1515,7 @@ mod tests {
             packet_identifier: 4,
             reason_codes: vec![SubscribeReasonCode::GrantedQoS0],
         };
-        let actual = packet.encode().unwrap();
+        let actual = packet.encode(Protocol::V3_1_1).unwrap();
         let expected = [0x90, 0x03, 0x00, 0x04, 0x00];
         assert_eq!(actual, expected);
     }
@@ -697,7 +1523,7 @@ mod tests {
     #[test]
     fn test_decode_suback() {
         let packet = [0x90, 0x03, 0x00, 0x04, 0x00];
-        let (actual, remaining_bytes) = ControlPacket::decode(&packet).unwrap();
+        let (actual, remaining_bytes) = ControlPacket::decode(&packet, Protocol::V3_1_1).unwrap();
         let expected = ControlPacket::Suback {
             packet_identifier: 4,
             reason_codes: vec![SubscribeReasonCode::GrantedQoS0],
@@ -709,7 +1535,7 @@ mod tests {
     #[test]
     fn test_encode_pingreq() {
         let packet = ControlPacket::Pingreq {};
-        let actual = packet.encode().unwrap();
+        let actual = packet.encode(Protocol::V3_1_1).unwrap();
         let expected = [0xC0, 0x00];
         assert_eq!(actual, expected);
     }
@@ -717,7 +1543,7 @@ mod tests {
     #[test]
     fn test_decode_pingreq() {
         let packet = [0xC0, 0x00];
-        let (actual, remaining_bytes) = ControlPacket::decode(&packet).unwrap();
+        let (actual, remaining_bytes) = ControlPacket::decode(&packet, Protocol::V3_1_1).unwrap();
         let expected = ControlPacket::Pingreq {};
         assert_eq!(actual, expected);
         assert_eq!(remaining_bytes, 0);
@@ -726,7 +1552,7 @@ mod tests {
     #[test]
     fn test_encode_pingresp() {
         let packet = ControlPacket::Pingresp {};
-        let actual = packet.encode().unwrap();
+        let actual = packet.encode(Protocol::V3_1_1).unwrap();
         let expected = [0xD0, 0x00];
         assert_eq!(actual, expected);
     }
@@ -734,7 +1560,7 @@ mod tests {
     #[test]
     fn test_decode_pingresp() {
         let packet = [0xD0, 0x00];
-        let (actual, remaining_bytes) = ControlPacket::decode(&packet).unwrap();
+        let (actual, remaining_bytes) = ControlPacket::decode(&packet, Protocol::V3_1_1).unwrap();
         let expected = ControlPacket::Pingresp {};
         assert_eq!(actual, expected);
         assert_eq!(remaining_bytes, 0);
@@ -745,7 +1571,7 @@ mod tests {
         let packet = ControlPacket::Disconnect {
             reason_code: DisconnectReasonCode::NormalDisconnection,
         };
-        let actual = packet.encode().unwrap();
+        let actual = packet.encode(Protocol::V3_1_1).unwrap();
         let expected = [0xE0, 0x00];
         assert_eq!(actual, expected);
     }
@@ -753,11 +1579,165 @@ mod tests {
     #[test]
     fn test_decode_disconnect() {
         let packet = [0xE0, 0x00];
-        let (actual, remaining_bytes) = ControlPacket::decode(&packet).unwrap();
+        let (actual, remaining_bytes) = ControlPacket::decode(&packet, Protocol::V3_1_1).unwrap();
         let expected = ControlPacket::Disconnect {
             reason_code: DisconnectReasonCode::NormalDisconnection,
         };
         assert_eq!(actual, expected);
         assert_eq!(remaining_bytes, 0);
     }
+
+    #[test]
+    fn test_encode_connack_v5() {
+        let packet = ControlPacket::Connack {
+            session_present: false,
+            reason_code: ConnectReasonCode::NotAuthorized,
+            properties: Properties::default(),
+        };
+        let actual = packet.encode(Protocol::V5_0).unwrap();
+        let expected = [0x20, 0x03, 0x00, 0x87, 0x00];
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_decode_connack_v5() {
+        let packet = [0x20, 0x06, 0x01, 0x00, 0x03, 0x21, 0x00, 0x0a];
+        let (actual, remaining_bytes) = ControlPacket::decode(&packet, Protocol::V5_0).unwrap();
+        let expected = ControlPacket::Connack {
+            session_present: true,
+            reason_code: ConnectReasonCode::Success,
+            properties: Properties {
+                receive_maximum: Some(10),
+                ..Default::default()
+            },
+        };
+        assert_eq!(actual, expected);
+        assert_eq!(remaining_bytes, 0);
+    }
+
+    #[test]
+    fn test_publish_v5_properties() {
+        let packet = ControlPacket::Publish {
+            topic: Topic::from("kodi/request"),
+            qos: QoS::AtMostOnce,
+            packet_identifier: 0,
+            properties: Properties {
+                response_topic: Some(Topic::from("kodi/response")),
+                correlation_data: Some(vec![0x00, 0x01]),
+                user_properties: vec![("origin".into(), "test".into())],
+                ..Default::default()
+            },
+            payload: b"ping".to_vec(),
+        };
+        let encoded = packet.encode(Protocol::V5_0).unwrap();
+        let (actual, remaining_bytes) = ControlPacket::decode(&encoded, Protocol::V5_0).unwrap();
+        assert_eq!(actual, packet);
+        assert_eq!(remaining_bytes, 0);
+    }
+
+    #[test]
+    fn test_publish_multi_byte_remaining_length() {
+        let packet = ControlPacket::Publish {
+            topic: Topic::from("test/topic"),
+            qos: QoS::AtMostOnce,
+            packet_identifier: 0,
+            properties: Properties::default(),
+            payload: vec![0xAB; 20_000],
+        };
+        let encoded = packet.encode(Protocol::V3_1_1).unwrap();
+        assert_eq!(&encoded[..4], [0x30, 0xac, 0x9c, 0x01]);
+        let (actual, remaining_bytes) = ControlPacket::decode(&encoded, Protocol::V3_1_1).unwrap();
+        assert_eq!(actual, packet);
+        assert_eq!(remaining_bytes, 0);
+    }
+
+    #[test]
+    fn test_publish_qos1() {
+        let packet = ControlPacket::Publish {
+            topic: Topic::from("a"),
+            qos: QoS::AtLeastOnce,
+            packet_identifier: 7,
+            properties: Properties::default(),
+            payload: vec![0x01],
+        };
+        let encoded = packet.encode(Protocol::V3_1_1).unwrap();
+        assert_eq!(encoded, [0x32, 0x06, 0x00, 0x01, b'a', 0x00, 0x07, 0x01]);
+        let (actual, remaining_bytes) = ControlPacket::decode(&encoded, Protocol::V3_1_1).unwrap();
+        assert_eq!(actual, packet);
+        assert_eq!(remaining_bytes, 0);
+    }
+
+    #[test]
+    fn test_qos2_acknowledgements() {
+        for (packet, expected) in [
+            (
+                ControlPacket::Pubrec {
+                    packet_identifier: 7,
+                    reason_code: PubackReasonCode::Success,
+                },
+                vec![0x50, 0x02, 0x00, 0x07],
+            ),
+            (
+                ControlPacket::Pubrec {
+                    packet_identifier: 7,
+                    reason_code: PubackReasonCode::QuotaExceeded,
+                },
+                vec![0x50, 0x03, 0x00, 0x07, 0x97],
+            ),
+            (
+                ControlPacket::Pubrel {
+                    packet_identifier: 7,
+                },
+                vec![0x62, 0x02, 0x00, 0x07],
+            ),
+            (
+                ControlPacket::Pubcomp {
+                    packet_identifier: 7,
+                },
+                vec![0x70, 0x02, 0x00, 0x07],
+            ),
+        ] {
+            let encoded = packet.encode(Protocol::V5_0).unwrap();
+            assert_eq!(encoded, expected);
+            let (actual, remaining_bytes) =
+                ControlPacket::decode(&encoded, Protocol::V5_0).unwrap();
+            assert_eq!(actual, packet);
+            assert_eq!(remaining_bytes, 0);
+        }
+    }
+
+    #[test]
+    fn test_size() {
+        assert_eq!(
+            ControlPacket::size(&[0x30, 0xac, 0x9c, 0x01]).unwrap(),
+            20_016
+        );
+        assert_eq!(ControlPacket::size(&[0xD0, 0x00]).unwrap(), 2);
+        assert!(matches!(
+            ControlPacket::size(&[0x30, 0xac]),
+            Err(Error::Incomplete)
+        ));
+    }
+
+    #[test]
+    fn test_decode_incomplete() {
+        let packet = [0x30, 0xac, 0x9c, 0x01, 0x00, 0x0a];
+        assert!(matches!(
+            ControlPacket::decode(&packet, Protocol::V3_1_1),
+            Err(Error::Incomplete)
+        ));
+        assert!(matches!(
+            ControlPacket::decode(&packet[..2], Protocol::V3_1_1),
+            Err(Error::Incomplete)
+        ));
+    }
+
+    #[test]
+    fn test_decode_coalesced() {
+        let mut packet = vec![0xD0, 0x00];
+        packet.extend_from_slice(&[0x40, 0x02, 0x00, 0x01]);
+        let (actual, remaining_bytes) = ControlPacket::decode(&packet, Protocol::V3_1_1).unwrap();
+        assert_eq!(actual, ControlPacket::Pingresp);
+        assert_eq!(remaining_bytes, 4);
+    }
 }
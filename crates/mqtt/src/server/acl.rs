@@ -0,0 +1,196 @@
+use lararium::prelude::*;
+
+/// Per-topic access control for publishes and subscriptions.
+///
+/// A client is allowed an operation if any rule matching its identity grants it. Filters may
+/// contain `%c` and `%u`, which are substituted with the client identifier and user name of the
+/// client before matching, e.g. `%u/#` confines every user to its own subtree.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Acl {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub identity: Identity,
+    pub read: Vec<Filter>,
+    pub write: Vec<Filter>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Identity {
+    Any,
+    Anonymous,
+    User(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub client_identifier: String,
+    pub username: Option<String>,
+}
+
+impl Acl {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        Self { rules }
+    }
+
+    pub fn add_rule(
+        &mut self,
+        rule: Rule,
+    ) {
+        self.rules.push(rule);
+    }
+
+    pub fn can_publish(
+        &self,
+        credentials: &Credentials,
+        topic: &Topic,
+    ) -> bool {
+        self.rules
+            .iter()
+            .filter(|rule| rule.identity.matches(credentials))
+            .flat_map(|rule| rule.write.iter())
+            .filter_map(|filter| substitute(filter, credentials))
+            .any(|filter| filter.matches(topic))
+    }
+
+    pub fn can_subscribe(
+        &self,
+        credentials: &Credentials,
+        filter: &Filter,
+    ) -> bool {
+        self.rules
+            .iter()
+            .filter(|rule| rule.identity.matches(credentials))
+            .flat_map(|rule| rule.read.iter())
+            .filter_map(|pattern| substitute(pattern, credentials))
+            .any(|pattern| pattern.contains(filter))
+    }
+}
+
+impl Identity {
+    fn matches(
+        &self,
+        credentials: &Credentials,
+    ) -> bool {
+        match self {
+            Identity::Any => true,
+            Identity::Anonymous => credentials.username.is_none(),
+            Identity::User(username) => credentials.username.as_ref() == Some(username),
+        }
+    }
+}
+
+fn substitute(
+    filter: &Filter,
+    credentials: &Credentials,
+) -> Option<Filter> {
+    let mut segments = Vec::with_capacity(filter.segments.len());
+    for segment in filter.segments.iter() {
+        let Some(segment) = segment else {
+            segments.push(None);
+            continue;
+        };
+        let mut segment = segment.to_string();
+        if segment.contains("%c") {
+            segment = segment.replace("%c", valid_identity(&credentials.client_identifier)?);
+        }
+        if segment.contains("%u") {
+            segment = segment.replace("%u", valid_identity(credentials.username.as_ref()?)?);
+        }
+        segments.push(Some(Segment::from(segment)));
+    }
+    Some(Filter {
+        segments,
+        open: filter.open,
+    })
+}
+
+/// Identities containing topic separators or wildcards could escape their subtree, so patterns
+/// referring to them never match.
+fn valid_identity(identity: &str) -> Option<&str> {
+    if identity.is_empty() || identity.contains(['/', '+', '#']) {
+        return None;
+    }
+    Some(identity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn credentials(
+        client_identifier: &str,
+        username: Option<&str>,
+    ) -> Credentials {
+        Credentials {
+            client_identifier: client_identifier.into(),
+            username: username.map(Into::into),
+        }
+    }
+
+    #[test]
+    fn test_acl_empty_denies() {
+        let acl = Acl::default();
+        let credentials = credentials("kodi", Some("kodi"));
+        assert!(!acl.can_publish(&credentials, &Topic::from("kodi/state")));
+        assert!(!acl.can_subscribe(&credentials, &Filter::from("kodi/#")));
+    }
+
+    #[test]
+    fn test_acl_user_rule() {
+        let acl = Acl::new(vec![Rule {
+            identity: Identity::User("kodi".into()),
+            read: vec![Filter::from("kodi/#")],
+            write: vec![Filter::from("kodi/+/state")],
+        }]);
+        let kodi = credentials("a", Some("kodi"));
+        let jellyfin = credentials("b", Some("jellyfin"));
+        assert!(acl.can_publish(&kodi, &Topic::from("kodi/living-room/state")));
+        assert!(!acl.can_publish(&kodi, &Topic::from("kodi/living-room/volume")));
+        assert!(!acl.can_publish(&jellyfin, &Topic::from("kodi/living-room/state")));
+        assert!(acl.can_subscribe(&kodi, &Filter::from("kodi/#")));
+        assert!(acl.can_subscribe(&kodi, &Filter::from("kodi/+/state")));
+        assert!(!acl.can_subscribe(&kodi, &Filter::from("#")));
+        assert!(!acl.can_subscribe(&jellyfin, &Filter::from("kodi/#")));
+    }
+
+    #[test]
+    fn test_acl_substitution() {
+        let acl = Acl::new(vec![Rule {
+            identity: Identity::Any,
+            read: vec![Filter::from("%u/#")],
+            write: vec![Filter::from("%u/%c/#")],
+        }]);
+        let kodi = credentials("living-room", Some("kodi"));
+        assert!(acl.can_subscribe(&kodi, &Filter::from("kodi/#")));
+        assert!(!acl.can_subscribe(&kodi, &Filter::from("jellyfin/#")));
+        assert!(acl.can_publish(&kodi, &Topic::from("kodi/living-room/state")));
+        assert!(!acl.can_publish(&kodi, &Topic::from("kodi/bedroom/state")));
+    }
+
+    #[test]
+    fn test_acl_substitution_requires_username() {
+        let acl = Acl::new(vec![Rule {
+            identity: Identity::Any,
+            read: vec![Filter::from("%u/#")],
+            write: vec![],
+        }]);
+        let anonymous = credentials("kodi", None);
+        assert!(!acl.can_subscribe(&anonymous, &Filter::from("kodi/#")));
+    }
+
+    #[test]
+    fn test_acl_substitution_rejects_wildcards() {
+        let acl = Acl::new(vec![Rule {
+            identity: Identity::Any,
+            read: vec![Filter::from("%c/#")],
+            write: vec![Filter::from("%c/state")],
+        }]);
+        let escaping = credentials("#", None);
+        assert!(!acl.can_subscribe(&escaping, &Filter::from("#")));
+        let escaping = credentials("kodi/x", None);
+        assert!(!acl.can_publish(&escaping, &Topic::from("kodi/x/state")));
+    }
+}
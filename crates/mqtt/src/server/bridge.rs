@@ -0,0 +1,467 @@
+use super::*;
+use crate::async_client::{AsyncClient, Options, RawMessage};
+use tokio::sync::mpsc;
+
+/// User property recording each bridge a publish has passed through. Loops are only detected
+/// through it, which is why bridges only connect to brokers speaking MQTT 5.0.
+const BRIDGE_PROPERTY: &str = "bridge";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// From the remote broker to this server.
+    In,
+    /// From this server to the remote broker.
+    Out,
+    Both,
+}
+
+/// Mirrors topics matching `filter` between the brokers. A topic `t` matching the filter is
+/// named `local_prefix` + `t` on this server and `remote_prefix` + `t` on the remote broker.
+#[derive(Debug, Clone)]
+pub struct Mapping {
+    pub filter: Filter,
+    pub direction: Direction,
+    pub local_prefix: String,
+    pub remote_prefix: String,
+}
+
+/// Connects this server to a remote broker, e.g. one a household already runs.
+#[derive(Debug, Clone)]
+pub struct Bridge {
+    name: String,
+    options: Options,
+    mappings: Vec<Mapping>,
+}
+
+impl Mapping {
+    pub fn new(
+        filter: impl Into<Filter>,
+        direction: Direction,
+    ) -> Self {
+        Self {
+            filter: filter.into(),
+            direction,
+            local_prefix: String::new(),
+            remote_prefix: String::new(),
+        }
+    }
+
+    pub fn with_local_prefix(
+        mut self,
+        prefix: impl Into<String>,
+    ) -> Self {
+        self.local_prefix = prefix.into();
+        self
+    }
+
+    pub fn with_remote_prefix(
+        mut self,
+        prefix: impl Into<String>,
+    ) -> Self {
+        self.remote_prefix = prefix.into();
+        self
+    }
+
+    fn is_inbound(&self) -> bool {
+        matches!(self.direction, Direction::In | Direction::Both)
+    }
+
+    fn is_outbound(&self) -> bool {
+        matches!(self.direction, Direction::Out | Direction::Both)
+    }
+
+    fn local_filter(&self) -> Filter {
+        Filter::from(format!("{}{}", self.local_prefix, self.filter))
+    }
+
+    fn remote_filter(&self) -> Filter {
+        Filter::from(format!("{}{}", self.remote_prefix, self.filter))
+    }
+
+    fn to_local(
+        &self,
+        topic: &Topic,
+    ) -> Option<Topic> {
+        if !self.is_inbound() {
+            return None;
+        }
+        remap(&self.filter, topic, &self.remote_prefix, &self.local_prefix)
+    }
+
+    fn to_remote(
+        &self,
+        topic: &Topic,
+    ) -> Option<Topic> {
+        if !self.is_outbound() {
+            return None;
+        }
+        remap(&self.filter, topic, &self.local_prefix, &self.remote_prefix)
+    }
+}
+
+fn remap(
+    filter: &Filter,
+    topic: &Topic,
+    from: &str,
+    to: &str,
+) -> Option<Topic> {
+    let topic = topic.to_string();
+    let suffix = topic.strip_prefix(from)?;
+    if !filter.matches(&Topic::from(suffix)) {
+        return None;
+    }
+    Some(Topic::from(format!("{to}{suffix}")))
+}
+
+impl Bridge {
+    /// `name` marks the publishes passing through the bridge, so that it must be unique among
+    /// the bridges of all connected brokers.
+    pub fn new(
+        name: impl Into<String>,
+        options: Options,
+        mappings: Vec<Mapping>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            options,
+            mappings,
+        }
+    }
+
+    /// Whether a publish has already passed through this bridge and would loop.
+    fn has_visited(
+        &self,
+        properties: &Properties,
+    ) -> bool {
+        properties
+            .user_properties
+            .iter()
+            .any(|(key, value)| key == BRIDGE_PROPERTY && *value == self.name)
+    }
+
+    fn visit(
+        &self,
+        properties: &mut Properties,
+    ) {
+        properties
+            .user_properties
+            .push((BRIDGE_PROPERTY.into(), self.name.clone()));
+    }
+}
+
+impl<T> Server<T>
+where
+    T: Handler + Clone + Send + Sync + 'static,
+{
+    /// Connects to the remote broker of `bridge` and mirrors its mappings in both directions.
+    /// Connection failures are retried with the backoff configured in its options, except for
+    /// brokers without MQTT 5.0, which cannot carry the user properties that prevent loops.
+    ///
+    /// Publishes received from the remote broker are passed to `handler` like those of local
+    /// clients.
+    pub async fn bridge(
+        &self,
+        handler: T,
+        bridge: Bridge,
+    ) -> Result<(), Error> {
+        let (client, mut incoming) = connect(&bridge.options).await?;
+        for mapping in bridge
+            .mappings
+            .iter()
+            .filter(|mapping| mapping.is_inbound())
+        {
+            client.subscribe(mapping.remote_filter()).await?;
+        }
+        let (sender, mut outgoing) = mpsc::unbounded_channel();
+        let connection = self.register(handler, Writer::Bridge(sender));
+        let subscriptions: Vec<_> = bridge
+            .mappings
+            .iter()
+            .filter(|mapping| mapping.is_outbound())
+            .map(Mapping::local_filter)
+            .collect();
+        self.metrics.session_started();
+        for _ in &subscriptions {
+            self.metrics.subscription_added();
+        }
+        *connection.session.lock().await = Some(Session {
+            protocol: Protocol::V5_0,
+            credentials: Credentials {
+                client_identifier: bridge.name.clone(),
+                username: None,
+            },
+            subscriptions,
+            maximum_packet_size: None,
+            topic_alias_maximum: 0,
+            inbound_aliases: HashMap::new(),
+            outbound_aliases: HashMap::new(),
+            inflight: HashSet::new(),
+        });
+        tracing::info!(
+            "Bridge {} connected to {}:{}",
+            bridge.name,
+            bridge.options.host,
+            bridge.options.port
+        );
+        let result = loop {
+            tokio::select! {
+                message = incoming.recv() => match message {
+                    Some(message) => self.bridge_in(&bridge, &connection, message).await,
+                    None => break Ok(()),
+                },
+                Some(packet) = outgoing.recv() => {
+                    if let Err(error) = bridge_out(&bridge, &client, packet) {
+                        break Err(error);
+                    }
+                }
+            }
+        };
+        self.unregister(&connection).await;
+        result
+    }
+
+    async fn bridge_in(
+        &self,
+        bridge: &Bridge,
+        connection: &Connection<T>,
+        message: RawMessage,
+    ) {
+        let RawMessage {
+            topic,
+            mut properties,
+            payload,
+        } = message;
+        if bridge.has_visited(&properties) {
+            tracing::debug!("Bridge {} dropping looped publish on {topic}", bridge.name);
+            return;
+        }
+        let Some(topic) = bridge
+            .mappings
+            .iter()
+            .find_map(|mapping| mapping.to_local(&topic))
+        else {
+            return;
+        };
+        self.metrics.publish_received();
+        let decoded = if payload.is_empty() {
+            None
+        } else {
+            match Payload::decode(&payload, &properties, self.default_encoding) {
+                Ok(decoded) => Some(decoded),
+                Err(error) => {
+                    tracing::debug!(
+                        "Bridge {} received invalid payload on {topic}: {error}",
+                        bridge.name
+                    );
+                    self.metrics.publish_dropped();
+                    return;
+                }
+            }
+        };
+        let puback = connection
+            .handler
+            .handle_publish(Publish {
+                client_id: connection.client_id,
+                topic: topic.clone(),
+                payload: decoded,
+            })
+            .await;
+        if puback.reason_code != PubackReasonCode::Success {
+            tracing::debug!(
+                "Bridge {} publish to {topic} rejected: {:?}",
+                bridge.name,
+                puback.reason_code
+            );
+            self.metrics.publish_dropped();
+            return;
+        }
+        bridge.visit(&mut properties);
+        route(
+            &self.connections,
+            Some(connection.client_id),
+            &topic,
+            &properties,
+            &payload,
+        )
+        .await;
+    }
+}
+
+async fn connect(
+    options: &Options
+) -> Result<(AsyncClient, mpsc::UnboundedReceiver<RawMessage>), Error> {
+    let mut backoff = options.min_backoff;
+    loop {
+        match AsyncClient::connect_raw(options.clone()).await {
+            Ok(connected) => return Ok(connected),
+            Err(
+                error @ crate::async_client::Error::ConnectionRefused(
+                    ConnectReasonCode::UnsupportedProtocolVersion,
+                ),
+            ) => {
+                tracing::error!(
+                    "Bridge to {}:{} needs a broker speaking MQTT 5.0",
+                    options.host,
+                    options.port
+                );
+                return Err(error.into());
+            }
+            Err(error) => {
+                tracing::warn!(
+                    "Failed to connect bridge to {}:{}: {error}",
+                    options.host,
+                    options.port
+                );
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(options.max_backoff);
+            }
+        }
+    }
+}
+
+/// Forwards a publish routed to the bridge to the remote broker.
+fn bridge_out(
+    bridge: &Bridge,
+    client: &AsyncClient,
+    packet: ControlPacket,
+) -> Result<(), Error> {
+    let ControlPacket::Publish {
+        topic,
+        mut properties,
+        payload,
+        ..
+    } = packet
+    else {
+        tracing::debug!("Bridge {} ignoring {packet:?}", bridge.name);
+        return Ok(());
+    };
+    if bridge.has_visited(&properties) {
+        return Ok(());
+    }
+    let Some(topic) = bridge
+        .mappings
+        .iter()
+        .find_map(|mapping| mapping.to_remote(&topic))
+    else {
+        return Ok(());
+    };
+    bridge.visit(&mut properties);
+    client.publish_raw(topic, properties, payload)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::TestHandler;
+    use super::*;
+    use crate::async_client::{Message, Messages};
+
+    async fn start_server() -> (Server<TestHandler>, SocketAddr) {
+        let server = Server::bind("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap()
+            .with_sys_interval(None);
+        let address = server.local_address().unwrap();
+        tokio::spawn({
+            let server = server.clone();
+            async move { server.listen(TestHandler).await }
+        });
+        (server, address)
+    }
+
+    fn options(address: SocketAddr) -> Options {
+        Options::new(address.ip().to_string(), address.port())
+    }
+
+    async fn next(messages: &mut Messages) -> Option<Message> {
+        tokio::time::timeout(Duration::from_millis(200), messages.recv())
+            .await
+            .ok()
+            .flatten()
+    }
+
+    #[test]
+    fn test_mapping_remap() {
+        let mapping = Mapping::new("sensor/#", Direction::Both)
+            .with_local_prefix("")
+            .with_remote_prefix("home/");
+        assert_eq!(
+            mapping.to_remote(&Topic::from("sensor/temperature")),
+            Some(Topic::from("home/sensor/temperature"))
+        );
+        assert_eq!(
+            mapping.to_local(&Topic::from("home/sensor/temperature")),
+            Some(Topic::from("sensor/temperature"))
+        );
+        assert_eq!(mapping.to_remote(&Topic::from("kodi/state")), None);
+        assert_eq!(mapping.to_local(&Topic::from("sensor/temperature")), None);
+        assert_eq!(mapping.remote_filter(), Filter::from("home/sensor/#"));
+
+        let mapping = Mapping::new("sensor/#", Direction::In);
+        assert_eq!(mapping.to_remote(&Topic::from("sensor/temperature")), None);
+    }
+
+    #[tokio::test]
+    async fn test_bridge() {
+        let (local, local_address) = start_server().await;
+        let (_remote, remote_address) = start_server().await;
+        let (local_client, mut local_messages) =
+            AsyncClient::connect(options(local_address)).await.unwrap();
+        let (remote_client, mut remote_messages) =
+            AsyncClient::connect(options(remote_address)).await.unwrap();
+        local_client.subscribe("#").await.unwrap();
+        remote_client.subscribe("#").await.unwrap();
+        let bridge = Bridge::new(
+            "gateway",
+            options(remote_address),
+            vec![Mapping::new("sensor/#", Direction::Both).with_remote_prefix("home/")],
+        );
+        tokio::spawn(async move { local.bridge(TestHandler, bridge).await });
+
+        // Wait for the bridge to subscribe on the remote broker.
+        let mut bridged = false;
+        for _ in 0..50 {
+            remote_client
+                .publish("home/sensor/ready", Value::Null)
+                .await
+                .unwrap();
+            if next(&mut local_messages).await.is_some() {
+                bridged = true;
+                break;
+            }
+        }
+        assert!(bridged);
+        while next(&mut remote_messages).await.is_some() {}
+        while next(&mut local_messages).await.is_some() {}
+
+        remote_client
+            .publish("home/sensor/humidity", Value::Integer(40))
+            .await
+            .unwrap();
+        let message = next(&mut local_messages).await.unwrap();
+        assert_eq!(message.topic, Topic::from("sensor/humidity"));
+        assert_eq!(message.payload, Some(Value::Integer(40).into()));
+        let message = next(&mut remote_messages).await.unwrap();
+        assert_eq!(message.topic, Topic::from("home/sensor/humidity"));
+        assert!(next(&mut remote_messages).await.is_none());
+        assert!(next(&mut local_messages).await.is_none());
+
+        local_client
+            .publish("sensor/temperature", Value::Integer(21))
+            .await
+            .unwrap();
+        local_client
+            .publish("kodi/state", Value::Integer(1))
+            .await
+            .unwrap();
+        let message = next(&mut remote_messages).await.unwrap();
+        assert_eq!(message.topic, Topic::from("home/sensor/temperature"));
+        assert_eq!(message.payload, Some(Value::Integer(21).into()));
+        assert!(next(&mut remote_messages).await.is_none());
+        let message = next(&mut local_messages).await.unwrap();
+        assert_eq!(message.topic, Topic::from("sensor/temperature"));
+        let message = next(&mut local_messages).await.unwrap();
+        assert_eq!(message.topic, Topic::from("kodi/state"));
+        assert!(next(&mut local_messages).await.is_none());
+    }
+}
@@ -0,0 +1,358 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Counters and gauges maintained by the broker.
+#[derive(Debug)]
+pub struct Metrics {
+    started_at: Instant,
+    connections: AtomicU64,
+    clients_connected: AtomicU64,
+    disconnections: AtomicU64,
+    packets_received: AtomicU64,
+    packets_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    publishes_received: AtomicU64,
+    publishes_sent: AtomicU64,
+    publishes_dropped: AtomicU64,
+    subscriptions: AtomicU64,
+    inflight: AtomicU64,
+}
+
+/// A point-in-time copy of [`Metrics`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub uptime: Duration,
+    /// Transport connections accepted since the broker started.
+    pub connections: u64,
+    /// Clients with an established session.
+    pub clients_connected: u64,
+    /// Sessions ended by disconnecting or being taken over.
+    pub disconnections: u64,
+    pub packets_received: u64,
+    pub packets_sent: u64,
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+    pub publishes_received: u64,
+    pub publishes_sent: u64,
+    /// Publishes rejected for lacking authorization or carrying an invalid payload.
+    pub publishes_dropped: u64,
+    pub subscriptions: u64,
+    /// Packets waiting for their connection's transport to accept them.
+    pub inflight: u64,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            started_at: Instant::now(),
+            connections: AtomicU64::new(0),
+            clients_connected: AtomicU64::new(0),
+            disconnections: AtomicU64::new(0),
+            packets_received: AtomicU64::new(0),
+            packets_sent: AtomicU64::new(0),
+            bytes_received: AtomicU64::new(0),
+            bytes_sent: AtomicU64::new(0),
+            publishes_received: AtomicU64::new(0),
+            publishes_sent: AtomicU64::new(0),
+            publishes_dropped: AtomicU64::new(0),
+            subscriptions: AtomicU64::new(0),
+            inflight: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Metrics {
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            uptime: self.started_at.elapsed(),
+            connections: self.connections.load(Ordering::Relaxed),
+            clients_connected: self.clients_connected.load(Ordering::Relaxed),
+            disconnections: self.disconnections.load(Ordering::Relaxed),
+            packets_received: self.packets_received.load(Ordering::Relaxed),
+            packets_sent: self.packets_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            publishes_received: self.publishes_received.load(Ordering::Relaxed),
+            publishes_sent: self.publishes_sent.load(Ordering::Relaxed),
+            publishes_dropped: self.publishes_dropped.load(Ordering::Relaxed),
+            subscriptions: self.subscriptions.load(Ordering::Relaxed),
+            inflight: self.inflight.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format.
+    pub fn prometheus(&self) -> String {
+        self.snapshot().prometheus()
+    }
+
+    pub(super) fn connection_opened(&self) {
+        self.connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn session_started(&self) {
+        self.clients_connected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn session_ended(
+        &self,
+        subscriptions: usize,
+    ) {
+        self.clients_connected.fetch_sub(1, Ordering::Relaxed);
+        self.subscriptions
+            .fetch_sub(subscriptions as u64, Ordering::Relaxed);
+        self.disconnections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn packet_received(
+        &self,
+        bytes: usize,
+    ) {
+        self.packets_received.fetch_add(1, Ordering::Relaxed);
+        self.bytes_received
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(super) fn packet_sent(
+        &self,
+        bytes: usize,
+    ) {
+        self.packets_sent.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(super) fn publish_received(&self) {
+        self.publishes_received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn publish_sent(&self) {
+        self.publishes_sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn publish_dropped(&self) {
+        self.publishes_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn subscription_added(&self) {
+        self.subscriptions.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn subscription_removed(&self) {
+        self.subscriptions.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn inflight_added(&self) {
+        self.inflight.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn inflight_removed(&self) {
+        self.inflight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Snapshot {
+    /// The `$SYS/broker/...` topics and values published periodically by the broker.
+    pub fn sys_topics(&self) -> Vec<(&'static str, u64)> {
+        vec![
+            ("$SYS/broker/uptime", self.uptime.as_secs()),
+            ("$SYS/broker/clients/connected", self.clients_connected),
+            ("$SYS/broker/clients/total", self.connections),
+            ("$SYS/broker/clients/disconnected", self.disconnections),
+            ("$SYS/broker/messages/received", self.packets_received),
+            ("$SYS/broker/messages/sent", self.packets_sent),
+            ("$SYS/broker/messages/inflight", self.inflight),
+            ("$SYS/broker/bytes/received", self.bytes_received),
+            ("$SYS/broker/bytes/sent", self.bytes_sent),
+            (
+                "$SYS/broker/publish/messages/received",
+                self.publishes_received,
+            ),
+            ("$SYS/broker/publish/messages/sent", self.publishes_sent),
+            (
+                "$SYS/broker/publish/messages/dropped",
+                self.publishes_dropped,
+            ),
+            ("$SYS/broker/subscriptions/count", self.subscriptions),
+        ]
+    }
+
+    /// The `$SYS/broker/load/...` rates per minute, measured since `previous`.
+    pub fn sys_load(
+        &self,
+        previous: &Snapshot,
+    ) -> Vec<(&'static str, u64)> {
+        let seconds = self.uptime.saturating_sub(previous.uptime).as_secs_f64();
+        let rate = |current: u64, previous: u64| {
+            if seconds > 0.0 {
+                (current.saturating_sub(previous) as f64 * 60.0 / seconds).round() as u64
+            } else {
+                0
+            }
+        };
+        vec![
+            (
+                "$SYS/broker/load/messages/received",
+                rate(self.packets_received, previous.packets_received),
+            ),
+            (
+                "$SYS/broker/load/messages/sent",
+                rate(self.packets_sent, previous.packets_sent),
+            ),
+            (
+                "$SYS/broker/load/publish/received",
+                rate(self.publishes_received, previous.publishes_received),
+            ),
+            (
+                "$SYS/broker/load/publish/sent",
+                rate(self.publishes_sent, previous.publishes_sent),
+            ),
+            (
+                "$SYS/broker/load/connections",
+                rate(self.connections, previous.connections),
+            ),
+        ]
+    }
+
+    pub fn prometheus(&self) -> String {
+        let mut output = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: u64| {
+            let _ = writeln!(output, "# HELP {name} {help}");
+            let _ = writeln!(output, "# TYPE {name} {kind}");
+            let _ = writeln!(output, "{name} {value}");
+        };
+        metric(
+            "mqtt_uptime_seconds",
+            "gauge",
+            "Seconds since the broker started.",
+            self.uptime.as_secs(),
+        );
+        metric(
+            "mqtt_connections_total",
+            "counter",
+            "Transport connections accepted.",
+            self.connections,
+        );
+        metric(
+            "mqtt_clients_connected",
+            "gauge",
+            "Clients with an established session.",
+            self.clients_connected,
+        );
+        metric(
+            "mqtt_client_disconnections_total",
+            "counter",
+            "Sessions ended by disconnecting or being taken over.",
+            self.disconnections,
+        );
+        metric(
+            "mqtt_packets_received_total",
+            "counter",
+            "Control packets received.",
+            self.packets_received,
+        );
+        metric(
+            "mqtt_packets_sent_total",
+            "counter",
+            "Control packets sent.",
+            self.packets_sent,
+        );
+        metric(
+            "mqtt_bytes_received_total",
+            "counter",
+            "Bytes of control packets received.",
+            self.bytes_received,
+        );
+        metric(
+            "mqtt_bytes_sent_total",
+            "counter",
+            "Bytes of control packets sent.",
+            self.bytes_sent,
+        );
+        metric(
+            "mqtt_publishes_received_total",
+            "counter",
+            "PUBLISH packets received.",
+            self.publishes_received,
+        );
+        metric(
+            "mqtt_publishes_sent_total",
+            "counter",
+            "PUBLISH packets sent.",
+            self.publishes_sent,
+        );
+        metric(
+            "mqtt_publishes_dropped_total",
+            "counter",
+            "PUBLISH packets rejected.",
+            self.publishes_dropped,
+        );
+        metric(
+            "mqtt_subscriptions",
+            "gauge",
+            "Active subscriptions.",
+            self.subscriptions,
+        );
+        metric(
+            "mqtt_packets_inflight",
+            "gauge",
+            "Packets waiting to be written.",
+            self.inflight,
+        );
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_lifecycle() {
+        let metrics = Metrics::default();
+        metrics.connection_opened();
+        metrics.session_started();
+        metrics.subscription_added();
+        metrics.subscription_added();
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.clients_connected, 1);
+        assert_eq!(snapshot.subscriptions, 2);
+        metrics.session_ended(2);
+        metrics.connection_opened();
+        metrics.session_started();
+        metrics.session_ended(0);
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.connections, 2);
+        assert_eq!(snapshot.clients_connected, 0);
+        assert_eq!(snapshot.subscriptions, 0);
+        assert_eq!(snapshot.disconnections, 2);
+    }
+
+    #[test]
+    fn test_prometheus() {
+        let metrics = Metrics::default();
+        metrics.packet_received(12);
+        metrics.session_started();
+        metrics.session_ended(0);
+        let output = metrics.prometheus();
+        assert!(output.contains("# TYPE mqtt_bytes_received_total counter\n"));
+        assert!(output.contains("\nmqtt_bytes_received_total 12\n"));
+        assert!(output.contains("\nmqtt_client_disconnections_total 1\n"));
+    }
+
+    #[test]
+    fn test_sys_load() {
+        let previous = Snapshot {
+            uptime: Duration::from_secs(10),
+            ..Metrics::default().snapshot()
+        };
+        let current = Snapshot {
+            uptime: Duration::from_secs(40),
+            packets_received: 15,
+            ..previous.clone()
+        };
+        let load = current.sys_load(&previous);
+        assert_eq!(load[0], ("$SYS/broker/load/messages/received", 30));
+        assert_eq!(load[1], ("$SYS/broker/load/messages/sent", 0));
+    }
+}
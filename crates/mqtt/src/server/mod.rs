@@ -0,0 +1,1616 @@
+mod acl;
+#[cfg(feature = "bridge")]
+mod bridge;
+mod metrics;
+#[cfg(feature = "websocket")]
+mod websocket;
+
+pub use acl::{Acl, Credentials, Identity, Rule};
+#[cfg(feature = "bridge")]
+pub use bridge::{Bridge, Direction, Mapping};
+pub use metrics::{Metrics, Snapshot};
+
+use crate::{protocol::*, *};
+use bytes::{Buf, BytesMut};
+use dashmap::DashMap;
+use derive_more::From;
+use lararium::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{
+    tcp::{OwnedReadHalf, OwnedWriteHalf},
+    TcpListener,
+};
+use tokio::sync::{mpsc, Mutex};
+
+type ClientId = u64;
+
+/// How many publishes may wait for delivery to a subscriber before further ones are dropped, so
+/// that a slow subscriber does not hold up the publishers.
+const OUTBOX_CAPACITY: usize = 256;
+
+#[derive(Clone)]
+pub struct Server<T>
+where
+    T: Handler,
+{
+    tcp_listener: Arc<TcpListener>,
+    next_client_id: Arc<AtomicU64>,
+    connections: Arc<DashMap<ClientId, Connection<T>>>,
+    acl: Option<Arc<Acl>>,
+    default_encoding: Encoding,
+    metrics: Arc<Metrics>,
+    sys_interval: Option<Duration>,
+    limits: Limits,
+}
+
+#[derive(Clone)]
+struct Connection<T>
+where
+    T: Handler,
+{
+    client_id: ClientId,
+    writer: Arc<Mutex<Writer>>,
+    handler: T,
+    acl: Option<Arc<Acl>>,
+    default_encoding: Encoding,
+    metrics: Arc<Metrics>,
+    limits: Limits,
+    connections: Arc<DashMap<ClientId, Connection<T>>>,
+    session: Arc<Mutex<Option<Session>>>,
+    /// Publishes routed to the connection, or `None` once it is unregistered.
+    outbox: Arc<Mutex<Option<mpsc::Sender<Arc<Delivery>>>>>,
+}
+
+/// A publish routed to subscribers.
+struct Delivery {
+    topic: Topic,
+    properties: Properties,
+    payload: Vec<u8>,
+}
+
+/// The sending half of a client's transport.
+enum Writer {
+    Tcp(OwnedWriteHalf),
+    #[cfg(feature = "websocket")]
+    WebSocket(websocket::Sink),
+    /// Packets routed to a bridge, which forwards them to its remote broker.
+    #[cfg(feature = "bridge")]
+    Bridge(mpsc::UnboundedSender<ControlPacket>),
+}
+
+/// Flow control limits the server imposes on its clients, advertised in CONNACK.
+#[derive(Debug, Clone, Copy)]
+struct Limits {
+    maximum_packet_size: u32,
+    receive_maximum: u16,
+    topic_alias_maximum: u16,
+}
+
+struct Session {
+    protocol: Protocol,
+    credentials: Credentials,
+    subscriptions: Vec<Filter>,
+    /// The largest packet the client accepts.
+    maximum_packet_size: Option<u32>,
+    /// The number of topic aliases the client accepts from the server.
+    topic_alias_maximum: u16,
+    inbound_aliases: HashMap<u16, Topic>,
+    outbound_aliases: HashMap<String, u16>,
+    /// Packet identifiers of QoS 2 publishes awaiting PUBREL.
+    inflight: HashSet<u16>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Connect {
+    pub client_id: ClientId,
+    pub clean_start: bool,
+    pub client_identifier: String,
+    pub username: Option<String>,
+    pub password: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Disconnect {
+    pub client_id: ClientId,
+    pub reason_code: DisconnectReasonCode,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Publish {
+    pub client_id: ClientId,
+    pub topic: Topic,
+    pub payload: Option<Payload>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Subscribe {
+    pub client_id: ClientId,
+    pub filter: Filter,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Connack {
+    pub reason_code: ConnectReasonCode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Puback {
+    /// Anything but `Success` rejects the publish, which is then not routed to subscribers.
+    pub reason_code: PubackReasonCode,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suback {
+    pub reason_codes: Vec<SubscribeReasonCode>,
+}
+
+#[derive(Debug, From)]
+pub enum Error {
+    #[from]
+    Io(std::io::Error),
+    #[from]
+    Payload(payload::Error),
+    #[cfg(feature = "bridge")]
+    #[from]
+    Bridge(crate::async_client::Error),
+}
+
+impl std::error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter,
+    ) -> Result<(), fmt::Error> {
+        write!(f, "{self:?}")
+    }
+}
+
+enum Action {
+    Respond(ControlPacket),
+    Continue,
+    Disconnect,
+    Reject(DisconnectReasonCode),
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            maximum_packet_size: 1024 * 1024,
+            receive_maximum: 32,
+            topic_alias_maximum: 16,
+        }
+    }
+}
+
+impl Session {
+    /// Replaces `topic` with an alias the client already knows, or assigns it a new one while
+    /// the client accepts more. Returns the topic name if a new alias was assigned.
+    fn alias(
+        &mut self,
+        topic: &mut Topic,
+        properties: &mut Properties,
+    ) -> Option<String> {
+        if self.protocol != Protocol::V5_0 || self.topic_alias_maximum == 0 {
+            return None;
+        }
+        let name = topic.to_string();
+        if let Some(alias) = self.outbound_aliases.get(&name) {
+            properties.topic_alias = Some(*alias);
+            *topic = Topic::from("");
+            return None;
+        }
+        if self.outbound_aliases.len() >= self.topic_alias_maximum as usize {
+            return None;
+        }
+        let alias = self.outbound_aliases.len() as u16 + 1;
+        self.outbound_aliases.insert(name.clone(), alias);
+        properties.topic_alias = Some(alias);
+        Some(name)
+    }
+
+    /// Resolves the topic of an incoming publish against the aliases the client has assigned.
+    fn resolve(
+        &mut self,
+        topic: Topic,
+        alias: Option<u16>,
+        topic_alias_maximum: u16,
+    ) -> Option<Topic> {
+        let Some(alias) = alias else {
+            return Some(topic);
+        };
+        if alias == 0 || alias > topic_alias_maximum {
+            return None;
+        }
+        if topic.to_string().is_empty() {
+            self.inbound_aliases.get(&alias).cloned()
+        } else {
+            self.inbound_aliases.insert(alias, topic.clone());
+            Some(topic)
+        }
+    }
+}
+
+pub trait Handler {
+    fn handle_connect(
+        &self,
+        connect: Connect,
+    ) -> impl std::future::Future<Output = Connack> + Send;
+
+    fn handle_disconnect(
+        &self,
+        disconnect: Disconnect,
+    ) -> impl std::future::Future<Output = ()> + Send;
+
+    fn handle_ping(&self) -> impl std::future::Future<Output = ()> + Send;
+
+    fn handle_publish(
+        &self,
+        publish: Publish,
+    ) -> impl std::future::Future<Output = Puback> + Send;
+
+    fn handle_subscribe(
+        &self,
+        subscribe: Subscribe,
+    ) -> impl std::future::Future<Output = Suback> + Send;
+}
+
+impl<T> Server<T>
+where
+    T: Handler + Clone + Send + Sync + 'static,
+{
+    pub async fn bind(listen_address: SocketAddr) -> Result<Self, Error> {
+        Ok(Self {
+            tcp_listener: Arc::new(TcpListener::bind(listen_address).await?),
+            next_client_id: Arc::new(AtomicU64::new(0)),
+            connections: Arc::new(DashMap::new()),
+            acl: None,
+            default_encoding: Encoding::Cbor,
+            metrics: Arc::new(Metrics::default()),
+            sys_interval: Some(Duration::from_secs(10)),
+            limits: Limits::default(),
+        })
+    }
+
+    pub fn local_address(&self) -> Result<SocketAddr, Error> {
+        Ok(self.tcp_listener.local_addr()?)
+    }
+
+    /// Restricts publishes and subscriptions to those granted by `acl`. Without an ACL every
+    /// client may publish and subscribe to any topic.
+    pub fn with_acl(
+        mut self,
+        acl: Acl,
+    ) -> Self {
+        self.acl = Some(Arc::new(acl));
+        self
+    }
+
+    /// Sets the encoding assumed for payloads whose publisher does not declare one, and used
+    /// for payloads published by the server. Defaults to CBOR.
+    pub fn with_default_encoding(
+        mut self,
+        encoding: Encoding,
+    ) -> Self {
+        self.default_encoding = encoding;
+        self
+    }
+
+    /// Sets how often metrics are published under `$SYS/broker/...`, or disables publishing
+    /// them. Defaults to every ten seconds.
+    pub fn with_sys_interval(
+        mut self,
+        interval: Option<Duration>,
+    ) -> Self {
+        self.sys_interval = interval;
+        self
+    }
+
+    /// Sets the largest packet accepted from clients. Larger packets disconnect the client with
+    /// `PacketTooLarge`. Defaults to 1 MiB.
+    pub fn with_maximum_packet_size(
+        mut self,
+        maximum_packet_size: u32,
+    ) -> Self {
+        self.limits.maximum_packet_size = maximum_packet_size;
+        self
+    }
+
+    /// Sets how many QoS 2 publishes a client may have unacknowledged. Exceeding it disconnects
+    /// the client with `ReceiveMaximumExceeded`. Defaults to 32.
+    pub fn with_receive_maximum(
+        mut self,
+        receive_maximum: u16,
+    ) -> Self {
+        self.limits.receive_maximum = receive_maximum.max(1);
+        self
+    }
+
+    /// Sets how many topic aliases a client may assign. Defaults to 16; zero disables them.
+    pub fn with_topic_alias_maximum(
+        mut self,
+        topic_alias_maximum: u16,
+    ) -> Self {
+        self.limits.topic_alias_maximum = topic_alias_maximum;
+        self
+    }
+
+    pub fn metrics(&self) -> Arc<Metrics> {
+        self.metrics.clone()
+    }
+
+    pub async fn listen(
+        &self,
+        handler: T,
+    ) -> Result<(), Error> {
+        if let Some(interval) = self.sys_interval {
+            tokio::spawn(self.clone().publish_sys(interval));
+        }
+        loop {
+            let (stream, address) = self.tcp_listener.accept().await?;
+            let (reader, writer) = stream.into_split();
+            let connection = self.register(handler.clone(), Writer::Tcp(writer));
+            let this = self.clone();
+            tokio::spawn(async move {
+                if let Err(error) = connection.read(reader).await {
+                    tracing::error!("Error handling connection from {address}: {error}");
+                }
+                tracing::debug!("Connection from {address} closed");
+                this.unregister(&connection).await;
+            });
+        }
+    }
+
+    async fn publish_sys(
+        self,
+        interval: Duration,
+    ) {
+        let mut interval = tokio::time::interval(interval);
+        let mut previous = self.metrics.snapshot();
+        loop {
+            interval.tick().await;
+            let snapshot = self.metrics.snapshot();
+            let topics = snapshot
+                .sys_topics()
+                .into_iter()
+                .chain(snapshot.sys_load(&previous));
+            for (topic, value) in topics {
+                let mut properties = Properties::default();
+                self.default_encoding.apply(&mut properties);
+                let payload = Payload::Value(Value::Integer(value.into()));
+                let payload = match self.default_encoding.encode(&payload) {
+                    Ok(payload) => payload,
+                    Err(error) => {
+                        tracing::error!("Failed to encode {topic}: {error}");
+                        continue;
+                    }
+                };
+                route(
+                    &self.connections,
+                    None,
+                    &Topic::from(topic),
+                    &properties,
+                    &payload,
+                )
+                .await;
+            }
+            previous = snapshot;
+        }
+    }
+
+    /// Adds a connection to the routing table shared by all transports.
+    fn register(
+        &self,
+        handler: T,
+        writer: Writer,
+    ) -> Connection<T> {
+        let client_id = self.next_client_id.fetch_add(1, Ordering::SeqCst);
+        let (outbox, inbox) = mpsc::channel(OUTBOX_CAPACITY);
+        let connection = Connection {
+            client_id,
+            writer: Arc::new(Mutex::new(writer)),
+            handler,
+            acl: self.acl.clone(),
+            default_encoding: self.default_encoding,
+            metrics: self.metrics.clone(),
+            limits: self.limits,
+            connections: self.connections.clone(),
+            session: Arc::new(Mutex::new(None)),
+            outbox: Arc::new(Mutex::new(Some(outbox))),
+        };
+        tokio::spawn(connection.clone().deliver(inbox));
+        self.connections.insert(client_id, connection.clone());
+        self.metrics.connection_opened();
+        connection
+    }
+
+    async fn unregister(
+        &self,
+        connection: &Connection<T>,
+    ) {
+        self.connections.remove(&connection.client_id);
+        connection.outbox.lock().await.take();
+        if let Some(session) = connection.session.lock().await.take() {
+            self.metrics.session_ended(session.subscriptions.len());
+        }
+    }
+
+    pub async fn publish(
+        &self,
+        client_ids: &[ClientId],
+        topic: &Topic,
+        payload: Option<Payload>,
+    ) -> Result<(), Error> {
+        for client_id in client_ids {
+            if let Some(connection) = self.connections.get(client_id) {
+                connection.publish(topic.clone(), payload.clone()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Publishes to every subscriber of the topic on behalf of the broker, for changes that did
+    /// not arrive as publishes.
+    pub async fn broadcast(
+        &self,
+        topic: &Topic,
+        payload: Option<Payload>,
+    ) -> Result<(), Error> {
+        let mut properties = Properties::default();
+        let payload = match payload {
+            Some(payload) => {
+                self.default_encoding.apply(&mut properties);
+                self.default_encoding.encode(&payload)?
+            }
+            None => Vec::new(),
+        };
+        route(&self.connections, None, topic, &properties, &payload).await;
+        Ok(())
+    }
+}
+
+impl<T> Connection<T>
+where
+    T: Handler + Clone,
+{
+    async fn publish(
+        &self,
+        topic: Topic,
+        payload: Option<Payload>,
+    ) -> Result<(), Error> {
+        tracing::debug!("Publishing to {}: {topic}", self.client_id);
+        let mut properties = Properties::default();
+        let payload = match payload {
+            Some(payload) => {
+                self.default_encoding.apply(&mut properties);
+                self.default_encoding.encode(&payload)?
+            }
+            None => Vec::new(),
+        };
+        let packet = ControlPacket::Publish {
+            topic,
+            qos: QoS::AtMostOnce,
+            packet_identifier: 0,
+            properties,
+            payload,
+        };
+        self.write(packet).await
+    }
+
+    async fn is_subscribed(
+        &self,
+        topic: &Topic,
+    ) -> bool {
+        match &*self.session.lock().await {
+            Some(session) => session
+                .subscriptions
+                .iter()
+                .any(|filter| filter.matches(topic)),
+            None => false,
+        }
+    }
+
+    /// Forwards the publishes routed to this connection until it is unregistered.
+    async fn deliver(
+        self,
+        mut inbox: mpsc::Receiver<Arc<Delivery>>,
+    ) {
+        while let Some(delivery) = inbox.recv().await {
+            let Delivery {
+                topic,
+                properties,
+                payload,
+            } = &*delivery;
+            if let Err(error) = self.forward(topic, properties, payload).await {
+                tracing::debug!("Failed to forward to {}: {error}", self.client_id);
+            }
+        }
+    }
+
+    /// Delivers a publish received from another client to this connection.
+    async fn forward(
+        &self,
+        topic: &Topic,
+        properties: &Properties,
+        payload: &[u8],
+    ) -> Result<(), Error> {
+        tracing::debug!("Forwarding to {}: {topic}", self.client_id);
+        let packet = ControlPacket::Publish {
+            topic: topic.clone(),
+            qos: QoS::AtMostOnce,
+            packet_identifier: 0,
+            properties: Properties {
+                payload_format_indicator: properties.payload_format_indicator,
+                message_expiry_interval: properties.message_expiry_interval,
+                content_type: properties.content_type.clone(),
+                response_topic: properties.response_topic.clone(),
+                correlation_data: properties.correlation_data.clone(),
+                user_properties: properties.user_properties.clone(),
+                ..Default::default()
+            },
+            payload: payload.to_vec(),
+        };
+        self.write(packet).await
+    }
+
+    async fn protocol(&self) -> Protocol {
+        match &*self.session.lock().await {
+            Some(session) => session.protocol,
+            None => Protocol::V3_1_1,
+        }
+    }
+
+    /// Encodes and sends `packet`, aliasing the topic of publishes where the client allows it.
+    /// Packets larger than the client accepts are discarded.
+    async fn write(
+        &self,
+        mut packet: ControlPacket,
+    ) -> Result<(), Error> {
+        let is_publish = matches!(packet, ControlPacket::Publish { .. });
+        #[cfg(feature = "bridge")]
+        if let Writer::Bridge(sender) = &*self.writer.lock().await {
+            // The bridge republishes the packet itself, so it is not encoded.
+            sender
+                .send(packet)
+                .map_err(|_| std::io::Error::from(std::io::ErrorKind::BrokenPipe))?;
+            if is_publish {
+                self.metrics.publish_sent();
+            }
+            return Ok(());
+        }
+        let packet = {
+            let mut session = self.session.lock().await;
+            let Some(session) = &mut *session else {
+                drop(session);
+                return self
+                    .send(packet.encode(Protocol::V3_1_1).unwrap(), is_publish)
+                    .await;
+            };
+            let assigned = match &mut packet {
+                ControlPacket::Publish {
+                    topic, properties, ..
+                } => session.alias(topic, properties),
+                _ => None,
+            };
+            let packet = packet.encode(session.protocol).unwrap();
+            if let Some(maximum_packet_size) = session.maximum_packet_size {
+                if packet.len() > maximum_packet_size as usize {
+                    if let Some(name) = assigned {
+                        session.outbound_aliases.remove(&name);
+                    }
+                    tracing::debug!(
+                        "Discarding {} byte packet exceeding the maximum packet size of client {}",
+                        packet.len(),
+                        self.client_id
+                    );
+                    if is_publish {
+                        self.metrics.publish_dropped();
+                    }
+                    return Ok(());
+                }
+            }
+            packet
+        };
+        self.send(packet, is_publish).await
+    }
+
+    async fn send(
+        &self,
+        packet: Vec<u8>,
+        is_publish: bool,
+    ) -> Result<(), Error> {
+        let length = packet.len();
+        self.metrics.inflight_added();
+        let result = {
+            let mut writer = self.writer.lock().await;
+            match &mut *writer {
+                Writer::Tcp(writer) => writer.write_all(&packet).await,
+                #[cfg(feature = "websocket")]
+                Writer::WebSocket(sink) => websocket::send(sink, packet).await,
+                #[cfg(feature = "bridge")]
+                Writer::Bridge(_) => unreachable!("bridges are written to without encoding"),
+            }
+        };
+        self.metrics.inflight_removed();
+        result?;
+        self.metrics.packet_sent(length);
+        if is_publish {
+            self.metrics.publish_sent();
+        }
+        Ok(())
+    }
+
+    async fn read(
+        &self,
+        mut reader: OwnedReadHalf,
+    ) -> Result<(), Error> {
+        let mut buffer = BytesMut::with_capacity(4096);
+        loop {
+            let mut read_buffer = [0; 1024];
+            let bytes_read = reader.read(&mut read_buffer).await?;
+            if bytes_read == 0 {
+                break Ok(());
+            }
+            buffer.extend_from_slice(&read_buffer[..bytes_read]);
+            if !self.receive(&mut buffer).await? {
+                break Ok(());
+            }
+        }
+    }
+
+    /// Handles every complete packet in `buffer`, leaving any partial packet in place.
+    /// Returns `false` once the connection should be closed.
+    async fn receive(
+        &self,
+        buffer: &mut BytesMut,
+    ) -> Result<bool, Error> {
+        loop {
+            if let Ok(size) = ControlPacket::size(&buffer[..]) {
+                if size > self.limits.maximum_packet_size as usize {
+                    tracing::debug!(
+                        "Client {} sent a {size} byte packet exceeding the maximum packet size",
+                        self.client_id
+                    );
+                    self.reject(DisconnectReasonCode::PacketTooLarge).await?;
+                    return Ok(false);
+                }
+            }
+            let protocol = self.protocol().await;
+            match ControlPacket::decode(&buffer[..], protocol) {
+                Ok((packet, remaining_bytes)) => {
+                    let length = buffer.len() - remaining_bytes;
+                    buffer.advance(length);
+                    self.metrics.packet_received(length);
+                    match self.handle_packet(packet).await {
+                        Ok(Action::Respond(packet)) => {
+                            self.write(packet).await?;
+                        }
+                        Ok(Action::Disconnect) => {
+                            return Ok(false);
+                        }
+                        Ok(Action::Reject(reason_code)) => {
+                            self.reject(reason_code).await?;
+                            return Ok(false);
+                        }
+                        Ok(Action::Continue) => {}
+                        Err(error) => {
+                            tracing::error!("Error handling packet: {error}");
+                        }
+                    }
+                    if remaining_bytes == 0 {
+                        return Ok(true);
+                    }
+                }
+                Err(crate::protocol::Error::Incomplete) => {
+                    return Ok(true);
+                }
+                Err(error) => {
+                    tracing::debug!("Client {} sent a malformed packet: {error}", self.client_id);
+                    self.reject(DisconnectReasonCode::MalformedPacket).await?;
+                    return Ok(false);
+                }
+            }
+        }
+    }
+
+    /// Closes the connection on the server's initiative. MQTT 3.1.1 has no server DISCONNECT,
+    /// so those clients are only told by the connection closing.
+    async fn reject(
+        &self,
+        reason_code: DisconnectReasonCode,
+    ) -> Result<(), Error> {
+        self.handler
+            .handle_disconnect(Disconnect {
+                client_id: self.client_id,
+                reason_code,
+            })
+            .await;
+        if self.protocol().await == Protocol::V5_0 {
+            self.write(ControlPacket::Disconnect { reason_code })
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn is_authorized(
+        &self,
+        check: impl FnOnce(&Acl, &Credentials) -> bool,
+    ) -> bool {
+        let Some(acl) = &self.acl else {
+            return true;
+        };
+        match &*self.session.lock().await {
+            Some(session) => check(acl, &session.credentials),
+            None => false,
+        }
+    }
+
+    async fn handle_packet(
+        &self,
+        packet: ControlPacket,
+    ) -> Result<Action, Error>
+    where
+        T: Handler,
+    {
+        match packet {
+            ControlPacket::Connect {
+                protocol,
+                clean_start,
+                client_identifier,
+                username,
+                password,
+                properties,
+                ..
+            } => {
+                let (client_identifier, assigned_client_identifier) =
+                    if client_identifier.is_empty() {
+                        let client_identifier = self.client_id.to_string();
+                        (client_identifier.clone(), Some(client_identifier))
+                    } else {
+                        (client_identifier, None)
+                    };
+                let connack = self
+                    .handler
+                    .handle_connect(Connect {
+                        client_id: self.client_id,
+                        clean_start,
+                        client_identifier: client_identifier.clone(),
+                        username: username.clone(),
+                        password,
+                    })
+                    .await;
+                if connack.reason_code == ConnectReasonCode::Success {
+                    let previous = self.session.lock().await.replace(Session {
+                        protocol,
+                        credentials: Credentials {
+                            client_identifier,
+                            username,
+                        },
+                        subscriptions: Vec::new(),
+                        maximum_packet_size: properties.maximum_packet_size,
+                        topic_alias_maximum: properties.topic_alias_maximum.unwrap_or(0),
+                        inbound_aliases: HashMap::new(),
+                        outbound_aliases: HashMap::new(),
+                        inflight: HashSet::new(),
+                    });
+                    if let Some(previous) = previous {
+                        self.metrics.session_ended(previous.subscriptions.len());
+                    }
+                    self.metrics.session_started();
+                }
+                Ok(Action::Respond(ControlPacket::Connack {
+                    session_present: false,
+                    reason_code: connack.reason_code,
+                    properties: Properties {
+                        assigned_client_identifier,
+                        receive_maximum: Some(self.limits.receive_maximum),
+                        maximum_packet_size: Some(self.limits.maximum_packet_size),
+                        topic_alias_maximum: Some(self.limits.topic_alias_maximum),
+                        ..Default::default()
+                    },
+                }))
+            }
+            ControlPacket::Publish {
+                topic,
+                qos,
+                packet_identifier,
+                properties,
+                payload,
+            } => {
+                self.metrics.publish_received();
+                // Publishes at QoS 0 are not acknowledged.
+                let acknowledge = |reason_code| match qos {
+                    QoS::AtMostOnce => Action::Continue,
+                    QoS::AtLeastOnce => Action::Respond(ControlPacket::Puback {
+                        packet_identifier,
+                        reason_code,
+                    }),
+                    QoS::ExactlyOnce => Action::Respond(ControlPacket::Pubrec {
+                        packet_identifier,
+                        reason_code,
+                    }),
+                };
+                let topic = {
+                    let mut session = self.session.lock().await;
+                    let Some(session) = &mut *session else {
+                        return Ok(Action::Reject(DisconnectReasonCode::ProtocolError));
+                    };
+                    let Some(topic) = session.resolve(
+                        topic,
+                        properties.topic_alias,
+                        self.limits.topic_alias_maximum,
+                    ) else {
+                        return Ok(Action::Reject(DisconnectReasonCode::TopicAliasInvalid));
+                    };
+                    if qos == QoS::ExactlyOnce {
+                        if session.inflight.contains(&packet_identifier) {
+                            // A retransmission of a publish that was already delivered.
+                            return Ok(acknowledge(PubackReasonCode::Success));
+                        }
+                        if session.inflight.len() >= self.limits.receive_maximum as usize {
+                            return Ok(Action::Reject(
+                                DisconnectReasonCode::ReceiveMaximumExceeded,
+                            ));
+                        }
+                    }
+                    topic
+                };
+                if !self
+                    .is_authorized(|acl, credentials| acl.can_publish(credentials, &topic))
+                    .await
+                {
+                    tracing::debug!(
+                        "Client {} not authorized to publish to {topic}",
+                        self.client_id
+                    );
+                    self.metrics.publish_dropped();
+                    return Ok(acknowledge(PubackReasonCode::NotAuthorized));
+                }
+                let decoded = if payload.is_empty() {
+                    None
+                } else {
+                    match Payload::decode(&payload, &properties, self.default_encoding) {
+                        Ok(decoded) => Some(decoded),
+                        Err(error) => {
+                            tracing::debug!(
+                                "Client {} published invalid payload to {topic}: {error}",
+                                self.client_id
+                            );
+                            self.metrics.publish_dropped();
+                            return Ok(acknowledge(PubackReasonCode::PayloadFormatInvalid));
+                        }
+                    }
+                };
+                let puback = self
+                    .handler
+                    .handle_publish(Publish {
+                        client_id: self.client_id,
+                        topic: topic.clone(),
+                        payload: decoded,
+                    })
+                    .await;
+                if puback.reason_code != PubackReasonCode::Success {
+                    tracing::debug!(
+                        "Publish of client {} to {topic} rejected: {:?}",
+                        self.client_id,
+                        puback.reason_code
+                    );
+                    self.metrics.publish_dropped();
+                    return Ok(acknowledge(puback.reason_code));
+                }
+                route(&self.connections, None, &topic, &properties, &payload).await;
+                if qos == QoS::ExactlyOnce {
+                    if let Some(session) = &mut *self.session.lock().await {
+                        session.inflight.insert(packet_identifier);
+                    }
+                }
+                Ok(acknowledge(PubackReasonCode::Success))
+            }
+            ControlPacket::Pubrel { packet_identifier } => {
+                if let Some(session) = &mut *self.session.lock().await {
+                    session.inflight.remove(&packet_identifier);
+                }
+                Ok(Action::Respond(ControlPacket::Pubcomp {
+                    packet_identifier,
+                }))
+            }
+            ControlPacket::Subscribe {
+                packet_identifier,
+                topic,
+            } => {
+                let filter = Filter::from(topic);
+                if !self
+                    .is_authorized(|acl, credentials| acl.can_subscribe(credentials, &filter))
+                    .await
+                {
+                    tracing::debug!(
+                        "Client {} not authorized to subscribe to {filter}",
+                        self.client_id
+                    );
+                    return Ok(Action::Respond(ControlPacket::Suback {
+                        packet_identifier,
+                        reason_codes: vec![SubscribeReasonCode::NotAuthorized],
+                    }));
+                }
+                let suback = self
+                    .handler
+                    .handle_subscribe(Subscribe {
+                        client_id: self.client_id,
+                        filter: filter.clone(),
+                    })
+                    .await;
+                let granted = suback.reason_codes.iter().all(|reason_code| {
+                    matches!(
+                        reason_code,
+                        SubscribeReasonCode::GrantedQoS0
+                            | SubscribeReasonCode::GrantedQoS1
+                            | SubscribeReasonCode::GrantedQoS2
+                    )
+                });
+                if granted {
+                    if let Some(session) = &mut *self.session.lock().await {
+                        if !session.subscriptions.contains(&filter) {
+                            session.subscriptions.push(filter);
+                            self.metrics.subscription_added();
+                        }
+                    }
+                }
+                Ok(Action::Respond(ControlPacket::Suback {
+                    packet_identifier,
+                    reason_codes: suback.reason_codes.to_vec(),
+                }))
+            }
+            ControlPacket::Unsubscribe {
+                packet_identifier,
+                topics,
+            } => {
+                let mut reason_codes = Vec::with_capacity(topics.len());
+                if let Some(session) = &mut *self.session.lock().await {
+                    for topic in topics {
+                        let filter = Filter::from(topic);
+                        let count = session.subscriptions.len();
+                        session
+                            .subscriptions
+                            .retain(|subscription| subscription != &filter);
+                        reason_codes.push(match session.subscriptions.len() < count {
+                            true => {
+                                self.metrics.subscription_removed();
+                                UnsubscribeReasonCode::Success
+                            }
+                            false => UnsubscribeReasonCode::NoSubscriptionExisted,
+                        });
+                    }
+                }
+                Ok(Action::Respond(ControlPacket::Unsuback {
+                    packet_identifier,
+                    reason_codes,
+                }))
+            }
+            ControlPacket::Pingreq => {
+                self.handler.handle_ping().await;
+                Ok(Action::Respond(ControlPacket::Pingresp))
+            }
+            ControlPacket::Disconnect { reason_code } => {
+                self.handler
+                    .handle_disconnect(Disconnect {
+                        client_id: self.client_id,
+                        reason_code,
+                    })
+                    .await;
+                Ok(Action::Disconnect)
+            }
+            packet => {
+                tracing::debug!(
+                    "Ignoring unexpected packet from {}: {packet:?}",
+                    self.client_id
+                );
+                Ok(Action::Continue)
+            }
+        }
+    }
+}
+
+/// Queues a publish for every connection with a matching subscription, except `origin`.
+async fn route<T>(
+    connections: &DashMap<ClientId, Connection<T>>,
+    origin: Option<ClientId>,
+    topic: &Topic,
+    properties: &Properties,
+    payload: &[u8],
+) where
+    T: Handler + Clone,
+{
+    let connections: Vec<_> = connections
+        .iter()
+        .filter(|entry| Some(*entry.key()) != origin)
+        .map(|entry| entry.value().clone())
+        .collect();
+    let delivery = Arc::new(Delivery {
+        topic: topic.clone(),
+        properties: properties.clone(),
+        payload: payload.to_vec(),
+    });
+    for connection in connections {
+        if !connection.is_subscribed(topic).await {
+            continue;
+        }
+        let Some(outbox) = connection.outbox.lock().await.clone() else {
+            continue;
+        };
+        // Rather than waiting for a subscriber that falls behind, its publishes are dropped.
+        if outbox.try_send(delivery.clone()).is_err() {
+            tracing::debug!(
+                "Dropping publish to {topic} for {}, whose outbox is full",
+                connection.client_id
+            );
+            connection.metrics.publish_dropped();
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use tokio::net::TcpStream;
+
+    #[derive(Clone)]
+    pub(crate) struct TestHandler;
+
+    impl Handler for TestHandler {
+        async fn handle_connect(
+            &self,
+            _connect: Connect,
+        ) -> Connack {
+            Connack {
+                reason_code: ConnectReasonCode::Success,
+            }
+        }
+
+        async fn handle_disconnect(
+            &self,
+            _disconnect: Disconnect,
+        ) {
+        }
+
+        async fn handle_ping(&self) {}
+
+        async fn handle_publish(
+            &self,
+            _publish: Publish,
+        ) -> Puback {
+            Puback {
+                reason_code: PubackReasonCode::Success,
+            }
+        }
+
+        async fn handle_subscribe(
+            &self,
+            _subscribe: Subscribe,
+        ) -> Suback {
+            Suback {
+                reason_codes: vec![SubscribeReasonCode::GrantedQoS0],
+            }
+        }
+    }
+
+    /// Accepts only integers, like a record whose schema is `Integer`.
+    #[derive(Clone)]
+    struct IntegerHandler;
+
+    impl Handler for IntegerHandler {
+        async fn handle_connect(
+            &self,
+            connect: Connect,
+        ) -> Connack {
+            TestHandler.handle_connect(connect).await
+        }
+
+        async fn handle_disconnect(
+            &self,
+            disconnect: Disconnect,
+        ) {
+            TestHandler.handle_disconnect(disconnect).await
+        }
+
+        async fn handle_ping(&self) {}
+
+        async fn handle_publish(
+            &self,
+            publish: Publish,
+        ) -> Puback {
+            Puback {
+                reason_code: match publish.payload {
+                    Some(Payload::Value(Value::Integer(_))) => PubackReasonCode::Success,
+                    _ => PubackReasonCode::PayloadFormatInvalid,
+                },
+            }
+        }
+
+        async fn handle_subscribe(
+            &self,
+            subscribe: Subscribe,
+        ) -> Suback {
+            TestHandler.handle_subscribe(subscribe).await
+        }
+    }
+
+    struct TestClient {
+        stream: TcpStream,
+        buffer: BytesMut,
+    }
+
+    impl TestClient {
+        async fn connect(
+            address: SocketAddr,
+            properties: Properties,
+        ) -> (Self, Properties) {
+            let mut client = Self {
+                stream: TcpStream::connect(address).await.unwrap(),
+                buffer: BytesMut::new(),
+            };
+            client
+                .send(ControlPacket::Connect {
+                    protocol: Protocol::V5_0,
+                    clean_start: true,
+                    keep_alive: 60,
+                    client_identifier: String::new(),
+                    username: None,
+                    password: None,
+                    properties,
+                })
+                .await;
+            match client.receive().await {
+                Some(ControlPacket::Connack { properties, .. }) => (client, properties),
+                packet => panic!("unexpected packet: {packet:?}"),
+            }
+        }
+
+        async fn send(
+            &mut self,
+            packet: ControlPacket,
+        ) {
+            let packet = packet.encode(Protocol::V5_0).unwrap();
+            self.stream.write_all(&packet).await.unwrap();
+        }
+
+        /// The next packet, or `None` once the server closes the connection.
+        async fn receive(&mut self) -> Option<ControlPacket> {
+            loop {
+                match ControlPacket::decode(&self.buffer, Protocol::V5_0) {
+                    Ok((packet, remaining_bytes)) => {
+                        let length = self.buffer.len() - remaining_bytes;
+                        self.buffer.advance(length);
+                        return Some(packet);
+                    }
+                    Err(crate::protocol::Error::Incomplete) => {}
+                    Err(error) => panic!("invalid packet: {error}"),
+                }
+                let mut read_buffer = [0; 1024];
+                let bytes_read = tokio::time::timeout(
+                    Duration::from_secs(5),
+                    self.stream.read(&mut read_buffer),
+                )
+                .await
+                .unwrap()
+                .unwrap_or(0);
+                if bytes_read == 0 {
+                    return None;
+                }
+                self.buffer.extend_from_slice(&read_buffer[..bytes_read]);
+            }
+        }
+    }
+
+    fn publish(
+        topic: &str,
+        qos: QoS,
+        packet_identifier: u16,
+        properties: Properties,
+    ) -> ControlPacket {
+        ControlPacket::Publish {
+            topic: Topic::from(topic),
+            qos,
+            packet_identifier,
+            properties,
+            payload: vec![0x18, 0x2A],
+        }
+    }
+
+    async fn start_server(
+        configure: impl FnOnce(Server<TestHandler>) -> Server<TestHandler>
+    ) -> SocketAddr {
+        let server = Server::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let server = configure(server.with_sys_interval(None));
+        let address = server.local_address().unwrap();
+        tokio::spawn(async move { server.listen(TestHandler).await });
+        address
+    }
+
+    #[tokio::test]
+    async fn test_connack_advertises_limits() {
+        let address = start_server(|server| {
+            server
+                .with_maximum_packet_size(4096)
+                .with_receive_maximum(2)
+                .with_topic_alias_maximum(4)
+        })
+        .await;
+        let (_client, properties) = TestClient::connect(address, Properties::default()).await;
+        assert_eq!(properties.maximum_packet_size, Some(4096));
+        assert_eq!(properties.receive_maximum, Some(2));
+        assert_eq!(properties.topic_alias_maximum, Some(4));
+    }
+
+    #[tokio::test]
+    async fn test_packet_too_large() {
+        let address = start_server(|server| server.with_maximum_packet_size(64)).await;
+        let (mut client, _) = TestClient::connect(address, Properties::default()).await;
+        // Only the fixed header is sent; the size alone is enough to reject the packet.
+        client
+            .stream
+            .write_all(&[0x30, 0xac, 0x9c, 0x01])
+            .await
+            .unwrap();
+        assert_eq!(
+            client.receive().await,
+            Some(ControlPacket::Disconnect {
+                reason_code: DisconnectReasonCode::PacketTooLarge,
+            })
+        );
+        assert_eq!(client.receive().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_receive_maximum_exceeded() {
+        let address = start_server(|server| server.with_receive_maximum(2)).await;
+        let (mut client, _) = TestClient::connect(address, Properties::default()).await;
+        for packet_identifier in 1..=2 {
+            client
+                .send(publish(
+                    "sensor/temperature",
+                    QoS::ExactlyOnce,
+                    packet_identifier,
+                    Properties::default(),
+                ))
+                .await;
+            assert_eq!(
+                client.receive().await,
+                Some(ControlPacket::Pubrec {
+                    packet_identifier,
+                    reason_code: PubackReasonCode::Success,
+                })
+            );
+        }
+        client
+            .send(ControlPacket::Pubrel {
+                packet_identifier: 1,
+            })
+            .await;
+        assert_eq!(
+            client.receive().await,
+            Some(ControlPacket::Pubcomp {
+                packet_identifier: 1,
+            })
+        );
+        for packet_identifier in 3..=4 {
+            client
+                .send(publish(
+                    "sensor/temperature",
+                    QoS::ExactlyOnce,
+                    packet_identifier,
+                    Properties::default(),
+                ))
+                .await;
+        }
+        assert!(matches!(
+            client.receive().await,
+            Some(ControlPacket::Pubrec {
+                packet_identifier: 3,
+                ..
+            })
+        ));
+        assert_eq!(
+            client.receive().await,
+            Some(ControlPacket::Disconnect {
+                reason_code: DisconnectReasonCode::ReceiveMaximumExceeded,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_topic_aliases() {
+        let address = start_server(|server| server).await;
+        let (mut subscriber, _) = TestClient::connect(
+            address,
+            Properties {
+                topic_alias_maximum: Some(1),
+                ..Default::default()
+            },
+        )
+        .await;
+        subscriber
+            .send(ControlPacket::Subscribe {
+                packet_identifier: 1,
+                topic: Topic::from("sensor/#"),
+            })
+            .await;
+        assert!(matches!(
+            subscriber.receive().await,
+            Some(ControlPacket::Suback { .. })
+        ));
+        let (mut publisher, _) = TestClient::connect(address, Properties::default()).await;
+        let aliased = |topic: &str| {
+            publish(
+                topic,
+                QoS::AtLeastOnce,
+                1,
+                Properties {
+                    topic_alias: Some(1),
+                    ..Default::default()
+                },
+            )
+        };
+        publisher.send(aliased("sensor/temperature")).await;
+        publisher.send(aliased("")).await;
+        publisher
+            .send(publish(
+                "sensor/humidity",
+                QoS::AtMostOnce,
+                0,
+                Properties::default(),
+            ))
+            .await;
+
+        let mut received = Vec::new();
+        for _ in 0..3 {
+            match subscriber.receive().await {
+                Some(ControlPacket::Publish {
+                    topic, properties, ..
+                }) => received.push((topic.to_string(), properties.topic_alias)),
+                packet => panic!("unexpected packet: {packet:?}"),
+            }
+        }
+        assert_eq!(
+            received,
+            vec![
+                ("sensor/temperature".to_string(), Some(1)),
+                (String::new(), Some(1)),
+                ("sensor/humidity".to_string(), None),
+            ]
+        );
+
+        publisher.send(aliased("")).await;
+        publisher
+            .send(publish(
+                "",
+                QoS::AtMostOnce,
+                0,
+                Properties {
+                    topic_alias: Some(2),
+                    ..Default::default()
+                },
+            ))
+            .await;
+        let mut packets = Vec::new();
+        while let Some(packet) = publisher.receive().await {
+            packets.push(packet);
+        }
+        assert_eq!(
+            packets.last(),
+            Some(&ControlPacket::Disconnect {
+                reason_code: DisconnectReasonCode::TopicAliasInvalid,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unsubscribe() {
+        let address = start_server(|server| server).await;
+        let (mut client, _) = TestClient::connect(address, Properties::default()).await;
+        client
+            .send(ControlPacket::Subscribe {
+                packet_identifier: 1,
+                topic: Topic::from("sensor/#"),
+            })
+            .await;
+        assert!(matches!(
+            client.receive().await,
+            Some(ControlPacket::Suback { .. })
+        ));
+        client
+            .send(ControlPacket::Unsubscribe {
+                packet_identifier: 2,
+                topics: vec![Topic::from("sensor/#"), Topic::from("kodi/#")],
+            })
+            .await;
+        assert_eq!(
+            client.receive().await,
+            Some(ControlPacket::Unsuback {
+                packet_identifier: 2,
+                reason_codes: vec![
+                    UnsubscribeReasonCode::Success,
+                    UnsubscribeReasonCode::NoSubscriptionExisted,
+                ],
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_slow_subscriber() {
+        let address = start_server(|server| server).await;
+        let (mut subscriber, _) = TestClient::connect(address, Properties::default()).await;
+        subscriber
+            .send(ControlPacket::Subscribe {
+                packet_identifier: 1,
+                topic: Topic::from("camera/#"),
+            })
+            .await;
+        assert!(matches!(
+            subscriber.receive().await,
+            Some(ControlPacket::Suback { .. })
+        ));
+        // The subscriber never reads, so that its socket fills up long before the publisher is
+        // done.
+        let (mut publisher, _) = TestClient::connect(address, Properties::default()).await;
+        let mut payload = vec![0x5A, 0x00, 0x01, 0x00, 0x00];
+        payload.resize(payload.len() + 0x10000, 0);
+        for packet_identifier in 1..=512 {
+            publisher
+                .send(ControlPacket::Publish {
+                    topic: Topic::from("camera/frame"),
+                    qos: QoS::AtLeastOnce,
+                    packet_identifier,
+                    properties: Properties::default(),
+                    payload: payload.clone(),
+                })
+                .await;
+            assert_eq!(
+                publisher.receive().await,
+                Some(ControlPacket::Puback {
+                    packet_identifier,
+                    reason_code: PubackReasonCode::Success,
+                })
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rejected_publish() {
+        let server = Server::bind("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap()
+            .with_sys_interval(None);
+        let address = server.local_address().unwrap();
+        tokio::spawn(async move { server.listen(IntegerHandler).await });
+        let (mut subscriber, _) = TestClient::connect(address, Properties::default()).await;
+        subscriber
+            .send(ControlPacket::Subscribe {
+                packet_identifier: 1,
+                topic: Topic::from("kodi/#"),
+            })
+            .await;
+        assert!(matches!(
+            subscriber.receive().await,
+            Some(ControlPacket::Suback { .. })
+        ));
+        let (mut publisher, _) = TestClient::connect(address, Properties::default()).await;
+        for (packet_identifier, payload, reason_code) in [
+            (
+                1,
+                vec![0x63, 0x6F, 0x66, 0x66],
+                PubackReasonCode::PayloadFormatInvalid,
+            ),
+            (2, vec![0x18, 0x2A], PubackReasonCode::Success),
+        ] {
+            publisher
+                .send(ControlPacket::Publish {
+                    topic: Topic::from("kodi/volume"),
+                    qos: QoS::AtLeastOnce,
+                    packet_identifier,
+                    properties: Properties::default(),
+                    payload,
+                })
+                .await;
+            assert_eq!(
+                publisher.receive().await,
+                Some(ControlPacket::Puback {
+                    packet_identifier,
+                    reason_code,
+                })
+            );
+        }
+        // A publish at QoS 0 is not acknowledged, even when rejected.
+        publisher
+            .send(publish(
+                "kodi/volume",
+                QoS::AtMostOnce,
+                0,
+                Properties::default(),
+            ))
+            .await;
+        publisher
+            .send(publish(
+                "kodi/volume",
+                QoS::AtLeastOnce,
+                3,
+                Properties::default(),
+            ))
+            .await;
+        assert_eq!(
+            publisher.receive().await,
+            Some(ControlPacket::Puback {
+                packet_identifier: 3,
+                reason_code: PubackReasonCode::Success,
+            })
+        );
+        // Only the integers reach the subscriber.
+        for _ in 0..3 {
+            assert!(matches!(
+                subscriber.receive().await,
+                Some(ControlPacket::Publish { payload, .. }) if payload == [0x18, 0x2A]
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sys_topics() {
+        let server = Server::bind("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap()
+            .with_sys_interval(Some(Duration::from_millis(50)));
+        let address = server.local_address().unwrap();
+        let metrics = server.metrics();
+        tokio::spawn(async move { server.listen(TestHandler).await });
+        let (mut client, _) = TestClient::connect(address, Properties::default()).await;
+        for (packet_identifier, topic) in [(1, "#"), (2, "$SYS/broker/clients/+")] {
+            client
+                .send(ControlPacket::Subscribe {
+                    packet_identifier,
+                    topic: Topic::from(topic),
+                })
+                .await;
+            assert!(matches!(
+                client.receive().await,
+                Some(ControlPacket::Suback { .. })
+            ));
+        }
+        // Only the subscription naming `$SYS` receives the broker topics.
+        match client.receive().await {
+            Some(ControlPacket::Publish { topic, .. }) => {
+                assert!(topic.to_string().starts_with("$SYS/broker/clients/"))
+            }
+            packet => panic!("unexpected packet: {packet:?}"),
+        }
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.clients_connected, 1);
+        assert_eq!(snapshot.subscriptions, 2);
+        assert!(metrics
+            .prometheus()
+            .contains("\nmqtt_clients_connected 1\n"));
+    }
+
+    #[tokio::test]
+    async fn test_malformed_packet() {
+        let address = start_server(|server| server).await;
+        let (mut client, _) = TestClient::connect(address, Properties::default()).await;
+        // A CONNECT for an unknown protocol, which may only be the first packet anyway.
+        client
+            .stream
+            .write_all(&[0x10, 0x07, 0x00, 0x04, b'M', b'Q', b'T', b'X', 0x05])
+            .await
+            .unwrap();
+        assert_eq!(
+            client.receive().await,
+            Some(ControlPacket::Disconnect {
+                reason_code: DisconnectReasonCode::MalformedPacket,
+            })
+        );
+        assert_eq!(client.receive().await, None);
+    }
+}
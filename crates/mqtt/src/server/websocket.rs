@@ -0,0 +1,198 @@
+use super::*;
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::Response,
+    routing::get,
+    Router,
+};
+use futures_util::{stream::SplitSink, SinkExt, StreamExt};
+use std::io;
+
+pub(super) type Sink = SplitSink<WebSocket, Message>;
+
+impl<T> Server<T>
+where
+    T: Handler + Clone + Send + Sync + 'static,
+{
+    /// A router accepting MQTT over WebSockets at `/mqtt`.
+    ///
+    /// Clients connected through it share sessions, routing and ACLs with the TCP listener.
+    pub fn router(
+        &self,
+        handler: T,
+    ) -> Router {
+        Router::new()
+            .route("/mqtt", get(upgrade::<T>))
+            .with_state((self.clone(), handler))
+    }
+}
+
+async fn upgrade<T>(
+    State((server, handler)): State<(Server<T>, T)>,
+    upgrade: WebSocketUpgrade,
+) -> Response
+where
+    T: Handler + Clone + Send + Sync + 'static,
+{
+    upgrade
+        .protocols(["mqtt"])
+        .on_upgrade(move |socket| serve(server, handler, socket))
+}
+
+async fn serve<T>(
+    server: Server<T>,
+    handler: T,
+    socket: WebSocket,
+) where
+    T: Handler + Clone + Send + Sync + 'static,
+{
+    let (sink, mut stream) = socket.split();
+    let connection = server.register(handler, Writer::WebSocket(sink));
+    let client_id = connection.client_id;
+    let mut buffer = BytesMut::with_capacity(4096);
+    while let Some(message) = stream.next().await {
+        let result = match message {
+            Ok(Message::Binary(data)) => {
+                buffer.extend_from_slice(&data);
+                connection.receive(&mut buffer).await
+            }
+            Ok(Message::Close(_)) => break,
+            Ok(_) => continue,
+            Err(error) => Err(Error::Io(io::Error::other(error))),
+        };
+        match result {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(error) => {
+                tracing::error!("Error handling WebSocket connection {client_id}: {error}");
+                break;
+            }
+        }
+    }
+    tracing::debug!("WebSocket connection {client_id} closed");
+    server.unregister(&connection).await;
+}
+
+pub(super) async fn send(
+    sink: &mut Sink,
+    packet: Vec<u8>,
+) -> Result<(), io::Error> {
+    sink.send(Message::Binary(packet))
+        .await
+        .map_err(io::Error::other)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::TestHandler;
+    use super::*;
+    use tokio::net::TcpStream;
+    use tokio_tungstenite::tungstenite::{self, client::IntoClientRequest};
+
+    fn connect(client_identifier: &str) -> Vec<u8> {
+        ControlPacket::Connect {
+            protocol: Protocol::V5_0,
+            clean_start: true,
+            keep_alive: 60,
+            client_identifier: client_identifier.into(),
+            username: None,
+            password: None,
+            properties: Properties::default(),
+        }
+        .encode(Protocol::V5_0)
+        .unwrap()
+    }
+
+    async fn receive<S>(socket: &mut S) -> ControlPacket
+    where
+        S: futures_util::Stream<Item = Result<tungstenite::Message, tungstenite::Error>> + Unpin,
+    {
+        loop {
+            let message = tokio::time::timeout(std::time::Duration::from_secs(5), socket.next())
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            if let tungstenite::Message::Binary(data) = message {
+                let (packet, remaining_bytes) =
+                    ControlPacket::decode(&data, Protocol::V5_0).unwrap();
+                assert_eq!(remaining_bytes, 0);
+                return packet;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_round_trip() {
+        let server = Server::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let tcp_address = server.local_address().unwrap();
+        let router = server.router(TestHandler);
+        tokio::spawn(async move { server.listen(TestHandler).await });
+        let http_listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let http_address = http_listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(http_listener, router).await });
+
+        let mut request = format!("ws://{http_address}/mqtt")
+            .into_client_request()
+            .unwrap();
+        request
+            .headers_mut()
+            .insert("Sec-WebSocket-Protocol", "mqtt".parse().unwrap());
+        let (mut socket, response) = tokio_tungstenite::connect_async(request).await.unwrap();
+        assert_eq!(response.headers()["Sec-WebSocket-Protocol"], "mqtt");
+
+        socket
+            .send(tungstenite::Message::Binary(connect("dashboard")))
+            .await
+            .unwrap();
+        assert!(matches!(
+            receive(&mut socket).await,
+            ControlPacket::Connack {
+                reason_code: ConnectReasonCode::Success,
+                ..
+            }
+        ));
+        let subscribe = ControlPacket::Subscribe {
+            packet_identifier: 1,
+            topic: "kodi/#".into(),
+        };
+        socket
+            .send(tungstenite::Message::Binary(
+                subscribe.encode(Protocol::V5_0).unwrap(),
+            ))
+            .await
+            .unwrap();
+        assert!(matches!(
+            receive(&mut socket).await,
+            ControlPacket::Suback {
+                packet_identifier: 1,
+                ..
+            }
+        ));
+
+        let publish = ControlPacket::Publish {
+            topic: Topic::from("kodi/state"),
+            qos: QoS::AtMostOnce,
+            packet_identifier: 0,
+            properties: Properties::default(),
+            payload: vec![0x18, 0x2A],
+        };
+        let mut stream = TcpStream::connect(tcp_address).await.unwrap();
+        stream.write_all(&connect("kodi")).await.unwrap();
+        stream
+            .write_all(&publish.encode(Protocol::V5_0).unwrap())
+            .await
+            .unwrap();
+
+        match receive(&mut socket).await {
+            ControlPacket::Publish { topic, payload, .. } => {
+                assert_eq!(topic, Topic::from("kodi/state"));
+                assert_eq!(payload, vec![0x18, 0x2A]);
+            }
+            packet => panic!("unexpected packet: {packet:?}"),
+        }
+    }
+}
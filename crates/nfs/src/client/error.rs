@@ -0,0 +1,28 @@
+use derive_more::From;
+
+#[derive(Debug, From)]
+pub enum Error {
+    #[from]
+    Io(std::io::Error),
+    /// An operation failed on the server.
+    #[from]
+    Nfs(crate::protocol::Error),
+    /// The server refused the call, for its RPC version or its credentials.
+    #[from]
+    Rejected(crate::protocol::RejectedReply),
+    /// The server did not execute the call, e.g. as it could not decode the arguments.
+    NotExecuted(crate::protocol::AcceptedReplyBody<'static>),
+    /// The server sent a reply that could not be decoded.
+    InvalidReply,
+}
+
+impl std::error::Error for Error {}
+
+impl core::fmt::Display for Error {
+    fn fmt(
+        &self,
+        fmt: &mut core::fmt::Formatter,
+    ) -> core::result::Result<(), core::fmt::Error> {
+        write!(fmt, "{self:?}")
+    }
+}
@@ -0,0 +1,517 @@
+mod error;
+
+pub use error::Error;
+
+use crate::protocol::{self, *};
+use crate::record;
+use bytes::BytesMut;
+use cookie_factory::{gen, sequence::tuple};
+use std::io::{self, Cursor};
+use std::net::SocketAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::net::TcpStream;
+
+/// Largest READ or WRITE payload asked for.
+const MAX_TRANSFER_LENGTH: u32 = 1024 * 1024;
+/// Room in a request or reply for the operations around a READ or WRITE payload.
+const COMPOUND_OVERHEAD: u32 = 4 * 1024;
+const READ_DIRECTORY_LENGTH: u32 = 64 * 1024;
+const OPEN_OWNER: &[u8] = b"client";
+
+/// An NFSv4.1 client with a session on a single connection, whose calls take turns on the
+/// only slot of the session.
+pub struct Client {
+    stream: TcpStream,
+    cred: OpaqueAuth<'static>,
+    next_xid: u32,
+    client_id: ClientId,
+    session_id: SessionId,
+    sequence_id: SequenceId,
+    max_operations: u32,
+    transfer_length: u32,
+    /// Room for the largest call, a WRITE with the operations around it.
+    output: Vec<u8>,
+}
+
+impl Client {
+    /// Connects to a server and sets up a session, calling as the given AUTH_SYS identity.
+    pub async fn connect(
+        address: SocketAddr,
+        credentials: &AuthSysParms<'_>,
+    ) -> Result<Self, Error> {
+        let stream = TcpStream::connect(address).await?;
+        let (body, _) = gen(protocol::encode::auth_sys_parms(credentials), Vec::new())
+            .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+        // The owner tells the connections of one machine apart, and the verifier tells the
+        // server that a client with the same owner restarted.
+        let owner_id = format!("{}/{}", credentials.machine_name, stream.local_addr()?);
+        let verifier = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        let mut client = Self {
+            stream,
+            cred: OpaqueAuth {
+                flavor: AuthFlavor::AuthSys,
+                body: body.into(),
+            },
+            next_xid: 1,
+            client_id: 0,
+            session_id: [0; 16],
+            sequence_id: 0,
+            max_operations: 0,
+            transfer_length: MAX_TRANSFER_LENGTH,
+            output: vec![0; (MAX_TRANSFER_LENGTH + COMPOUND_OVERHEAD) as usize],
+        };
+
+        let reply = client
+            .call(vec![NfsArgOp::ExchangeId(ExchangeIdArgs {
+                clientowner: ClientOwner {
+                    verifier: verifier.to_be_bytes(),
+                    owner_id: owner_id.as_bytes().into(),
+                },
+                flags: ExchangeIdFlags::empty(),
+                state_protect: StateProtectArgs::None,
+                client_impl_id: None,
+            })])
+            .await?;
+        let Some(NfsResOp::ExchangeId(Ok(exchange_id))) = results(&reply)?.pop() else {
+            return Err(Error::InvalidReply);
+        };
+        client.client_id = exchange_id.client_id;
+
+        let channel_attributes = ChannelAttributes {
+            header_pad_size: 0,
+            max_request_size: MAX_TRANSFER_LENGTH + COMPOUND_OVERHEAD,
+            max_response_size: MAX_TRANSFER_LENGTH + COMPOUND_OVERHEAD,
+            max_response_size_cached: COMPOUND_OVERHEAD,
+            max_operations: 16,
+            max_requests: 1,
+            rdma_ird: None,
+        };
+        let reply = client
+            .call(vec![NfsArgOp::CreateSession(CreateSessionArgs {
+                client_id: exchange_id.client_id,
+                sequence_id: exchange_id.sequence_id,
+                flags: CreateSessionFlags::empty(),
+                fore_channel_attributes: channel_attributes,
+                back_channel_attributes: channel_attributes,
+                cb_program: 0,
+                sec_parms: vec![CallbackSecParms::AuthNone],
+            })])
+            .await?;
+        let Some(NfsResOp::CreateSession(Ok(session))) = results(&reply)?.pop() else {
+            return Err(Error::InvalidReply);
+        };
+        let attributes = session.fore_channel_attributes;
+        client.session_id = session.session_id;
+        client.max_operations = attributes.max_operations;
+        client.transfer_length = attributes
+            .max_request_size
+            .min(attributes.max_response_size)
+            .saturating_sub(COMPOUND_OVERHEAD)
+            .clamp(1, MAX_TRANSFER_LENGTH);
+
+        // There is no state from before a restart to reclaim.
+        let reply = client
+            .sequenced(vec![NfsArgOp::ReclaimComplete(ReclaimCompleteArgs {
+                one_fs: false,
+            })])
+            .await?;
+        results(&reply)?;
+        Ok(client)
+    }
+
+    pub async fn read_file(
+        &mut self,
+        path: &str,
+    ) -> Result<Vec<u8>, Error> {
+        let file_handle = self.lookup(&components(path)).await?;
+        self.read_file_handle(&file_handle).await
+    }
+
+    /// The handle of a file, for servers whose handles outlive the session.
+    pub async fn file_handle(
+        &mut self,
+        path: &str,
+    ) -> Result<FileHandle<'static>, Error> {
+        self.lookup(&components(path)).await
+    }
+
+    pub async fn read_file_handle(
+        &mut self,
+        file_handle: &FileHandle<'_>,
+    ) -> Result<Vec<u8>, Error> {
+        let (file_handle, state_id) = self
+            .open(
+                file_handle,
+                ShareAccessFlags::READ,
+                OpenFlag::NoCreate,
+                OpenClaim::FileHandle,
+            )
+            .await?;
+        let result = self.read_all(&file_handle, &state_id).await;
+        self.close(&file_handle, state_id).await?;
+        result
+    }
+
+    /// Replaces the content of a file, creating it if it does not exist.
+    pub async fn write_file(
+        &mut self,
+        path: &str,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        let mut components = components(path);
+        let Some(name) = components.pop() else {
+            return Err(protocol::Error::ISDIR.into());
+        };
+        let directory = self.lookup(&components).await?;
+        // An unchecked create of a file that exists opens it, setting its size to zero.
+        let how = OpenFlag::Create(OpenFlagCreate::Unchecked {
+            attributes: vec![AttributeValue::Size(0)],
+        });
+        let (file_handle, state_id) = self
+            .open(
+                &directory,
+                ShareAccessFlags::WRITE,
+                how,
+                OpenClaim::Null(name),
+            )
+            .await?;
+        let result = self.write_all(&file_handle, &state_id, data).await;
+        self.close(&file_handle, state_id).await?;
+        result
+    }
+
+    /// Creates a directory, failing with EXIST if there is one already.
+    pub async fn create_dir(
+        &mut self,
+        path: &str,
+    ) -> Result<(), Error> {
+        let mut components = components(path);
+        let Some(name) = components.pop() else {
+            return Err(protocol::Error::EXIST.into());
+        };
+        let directory = self.lookup(&components).await?;
+        let reply = self
+            .sequenced(vec![
+                NfsArgOp::PutFileHandle(directory),
+                NfsArgOp::Create(CreateArgs {
+                    object_type: CreateType::Directory,
+                    name,
+                    attributes: vec![],
+                }),
+            ])
+            .await?;
+        results(&reply)?;
+        Ok(())
+    }
+
+    /// Lists the names in a directory.
+    pub async fn list_dir(
+        &mut self,
+        path: &str,
+    ) -> Result<Vec<String>, Error> {
+        let directory = self.lookup(&components(path)).await?;
+        let mut names = Vec::new();
+        let mut cookie = 0;
+        let mut cookie_verifier = [0; 8];
+        loop {
+            let reply = self
+                .sequenced(vec![
+                    NfsArgOp::PutFileHandle(directory.clone()),
+                    NfsArgOp::ReadDirectory(ReadDirectoryArgs {
+                        cookie,
+                        cookie_verifier,
+                        dir_count: READ_DIRECTORY_LENGTH,
+                        max_count: READ_DIRECTORY_LENGTH,
+                        attributes: AttributeMask::new(),
+                    }),
+                ])
+                .await?;
+            let Some(NfsResOp::ReadDirectory(Ok(result))) = results(&reply)?.pop() else {
+                return Err(Error::InvalidReply);
+            };
+            cookie_verifier = result.cookie_verf;
+            let directory_list = result.directory_list;
+            if let Some(entry) = directory_list.entries.last() {
+                cookie = entry.cookie;
+            }
+            let done = directory_list.eof || directory_list.entries.is_empty();
+            names.extend(
+                directory_list
+                    .entries
+                    .into_iter()
+                    .map(|entry| entry.name.into_owned()),
+            );
+            if done {
+                return Ok(names);
+            }
+        }
+    }
+
+    /// Destroys the session and the client ID, which releases whatever the client still
+    /// holds on the server.
+    pub async fn disconnect(mut self) -> Result<(), Error> {
+        let reply = self
+            .call(vec![NfsArgOp::DestroySession(self.session_id)])
+            .await?;
+        results(&reply)?;
+        let reply = self
+            .call(vec![NfsArgOp::DestroyClientId(self.client_id)])
+            .await?;
+        results(&reply)?;
+        Ok(())
+    }
+
+    /// Resolves a path from the root, in as many compounds as the session allows operations.
+    async fn lookup(
+        &mut self,
+        path: &[&str],
+    ) -> Result<FileHandle<'static>, Error> {
+        // SEQUENCE, PUTROOTFH or PUTFH and GETFH leave the rest of a compound to LOOKUP.
+        let lookups = (self.max_operations as usize).saturating_sub(3).max(1);
+        let mut chunks = path.chunks(lookups);
+        let mut chunk = chunks.next().unwrap_or_default();
+        let mut file_handle: Option<FileHandle<'static>> = None;
+        loop {
+            let mut argarray = vec![match file_handle {
+                Some(ref file_handle) => NfsArgOp::PutFileHandle(file_handle.clone()),
+                None => NfsArgOp::PutRootFileHandle,
+            }];
+            argarray.extend(chunk.iter().map(|name| NfsArgOp::Lookup(name)));
+            argarray.push(NfsArgOp::GetFileHandle);
+            let reply = self.sequenced(argarray).await?;
+            let Some(NfsResOp::GetFileHandle(Ok(found))) = results(&reply)?.pop() else {
+                return Err(Error::InvalidReply);
+            };
+            let found = FileHandle::from(found.to_vec());
+            match chunks.next() {
+                Some(next) => {
+                    chunk = next;
+                    file_handle = Some(found);
+                }
+                None => return Ok(found),
+            }
+        }
+    }
+
+    /// Opens a file, returning its file handle and the stateid for reading and writing it.
+    async fn open(
+        &mut self,
+        file_handle: &FileHandle<'_>,
+        share_access: ShareAccessFlags,
+        how: OpenFlag<'_>,
+        claim: OpenClaim<'_>,
+    ) -> Result<(FileHandle<'static>, StateId), Error> {
+        let owner = OpenOwner::from(StateOwner {
+            client_id: self.client_id,
+            owner: OPEN_OWNER.into(),
+        });
+        let reply = self
+            .sequenced(vec![
+                NfsArgOp::PutFileHandle(file_handle.clone()),
+                NfsArgOp::Open(OpenArgs {
+                    // Sequence IDs of open owners are not used since NFSv4.1.
+                    sequence_id: 0,
+                    // Without a back channel, delegations could not be recalled.
+                    share_access: share_access | ShareAccessFlags::WANT_NO_DELEG,
+                    share_deny: ShareDenyFlags::empty(),
+                    owner,
+                    how,
+                    claim,
+                }),
+                NfsArgOp::GetFileHandle,
+            ])
+            .await?;
+        let mut results = results(&reply)?;
+        let (Some(NfsResOp::GetFileHandle(Ok(opened))), Some(NfsResOp::Open(Ok(open)))) =
+            (results.pop(), results.pop())
+        else {
+            return Err(Error::InvalidReply);
+        };
+        Ok((FileHandle::from(opened.to_vec()), open.state_id))
+    }
+
+    async fn read_all(
+        &mut self,
+        file_handle: &FileHandle<'_>,
+        state_id: &StateId,
+    ) -> Result<Vec<u8>, Error> {
+        let mut data = Vec::new();
+        loop {
+            let reply = self
+                .sequenced(vec![
+                    NfsArgOp::PutFileHandle(file_handle.clone()),
+                    NfsArgOp::Read(ReadArgs {
+                        state_id: state_id.clone(),
+                        offset: data.len() as u64,
+                        count: self.transfer_length,
+                    }),
+                ])
+                .await?;
+            let Some(NfsResOp::Read(Ok(read))) = results(&reply)?.pop() else {
+                return Err(Error::InvalidReply);
+            };
+            data.extend_from_slice(&read.data);
+            if read.eof || read.data.is_empty() {
+                return Ok(data);
+            }
+        }
+    }
+
+    async fn write_all(
+        &mut self,
+        file_handle: &FileHandle<'_>,
+        state_id: &StateId,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        let mut offset = 0;
+        while offset < data.len() {
+            let end = (offset + self.transfer_length as usize).min(data.len());
+            let reply = self
+                .sequenced(vec![
+                    NfsArgOp::PutFileHandle(file_handle.clone()),
+                    NfsArgOp::Write(WriteArgs {
+                        state_id: state_id.clone(),
+                        offset: offset as u64,
+                        stable: StableHow::FileSync,
+                        data: data[offset..end].into(),
+                    }),
+                ])
+                .await?;
+            let Some(NfsResOp::Write(Ok(written))) = results(&reply)?.pop() else {
+                return Err(Error::InvalidReply);
+            };
+            if written.count == 0 {
+                return Err(protocol::Error::IO.into());
+            }
+            offset += written.count as usize;
+        }
+        Ok(())
+    }
+
+    async fn close(
+        &mut self,
+        file_handle: &FileHandle<'_>,
+        state_id: StateId,
+    ) -> Result<(), Error> {
+        let reply = self
+            .sequenced(vec![
+                NfsArgOp::PutFileHandle(file_handle.clone()),
+                NfsArgOp::Close(CloseArgs {
+                    sequence_id: 0,
+                    open_state_id: state_id,
+                }),
+            ])
+            .await?;
+        results(&reply)?;
+        Ok(())
+    }
+
+    /// Sends operations behind a SEQUENCE on the only slot of the session.
+    async fn sequenced(
+        &mut self,
+        mut argarray: Vec<NfsArgOp<'_>>,
+    ) -> Result<BytesMut, Error> {
+        self.sequence_id = self.sequence_id.wrapping_add(1);
+        argarray.insert(
+            0,
+            NfsArgOp::Sequence(SequenceArgs {
+                session_id: self.session_id,
+                sequence_id: self.sequence_id,
+                slot_id: 0,
+                highest_slot_id: 0,
+                cache_this: false,
+            }),
+        );
+        let reply = self.call(argarray).await?;
+        // The slot only moves on when the server got as far as executing the SEQUENCE.
+        let result = compound_result(&reply)?;
+        if !matches!(result.resarray.first(), Some(NfsResOp::Sequence(Ok(_)))) {
+            self.sequence_id = self.sequence_id.wrapping_sub(1);
+        }
+        Ok(reply)
+    }
+
+    async fn call(
+        &mut self,
+        argarray: Vec<NfsArgOp<'_>>,
+    ) -> Result<BytesMut, Error> {
+        let xid = self.next_xid;
+        self.next_xid = self.next_xid.wrapping_add(1);
+        let call = Call {
+            cred: self.cred.clone(),
+            verf: OpaqueAuth {
+                flavor: AuthFlavor::AuthNone,
+                body: (&[]).into(),
+            },
+            procedure: ProcedureCall::Compound(CompoundArgs {
+                tag: "".into(),
+                minorversion: 1,
+                argarray,
+            }),
+        };
+        let generator = tuple((
+            protocol::encode::message(RpcMessage {
+                xid,
+                message_type: MessageType::Call,
+            }),
+            protocol::encode::call(&call),
+        ));
+        let (_, length) = gen(generator, Cursor::new(&mut self.output[..]))
+            .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+        record::write(&mut self.stream, &self.output[..length as usize]).await?;
+        let Some(reply) = record::read(&mut self.stream).await? else {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        };
+        match protocol::decode::message(&reply) {
+            Ok((_, message))
+                if message.xid == xid && message.message_type == MessageType::Reply =>
+            {
+                Ok(reply)
+            }
+            _ => Err(Error::InvalidReply),
+        }
+    }
+}
+
+fn components(path: &str) -> Vec<&str> {
+    path.split('/')
+        .filter(|component| !component.is_empty())
+        .collect()
+}
+
+/// Decodes the reply to a COMPOUND, after the RPC message header.
+fn compound_result(reply: &[u8]) -> Result<CompoundResult<'_>, Error> {
+    let Ok((input, _)) = protocol::decode::message(reply) else {
+        return Err(Error::InvalidReply);
+    };
+    let Ok((_, reply)) = protocol::decode::reply(ProcedureNumber::Compound)(input) else {
+        return Err(Error::InvalidReply);
+    };
+    let body = match reply {
+        Reply::Accepted(AcceptedReply { body, .. }) => body,
+        Reply::Rejected(rejected) => return Err(rejected.into()),
+    };
+    Err(Error::NotExecuted(match body {
+        AcceptedReplyBody::Success(ProcedureReply::Compound(result)) => return Ok(result),
+        AcceptedReplyBody::Success(ProcedureReply::Null) => return Err(Error::InvalidReply),
+        AcceptedReplyBody::ProgramUnavailable => AcceptedReplyBody::ProgramUnavailable,
+        AcceptedReplyBody::ProgramMismatch { low, high } => {
+            AcceptedReplyBody::ProgramMismatch { low, high }
+        }
+        AcceptedReplyBody::ProcedureUnavailable => AcceptedReplyBody::ProcedureUnavailable,
+        AcceptedReplyBody::GarbageArgs => AcceptedReplyBody::GarbageArgs,
+        AcceptedReplyBody::SystemError => AcceptedReplyBody::SystemError,
+    }))
+}
+
+/// The results of the operations of a COMPOUND, or the error of the one that failed.
+fn results(reply: &[u8]) -> Result<Vec<NfsResOp<'_>>, Error> {
+    let result = compound_result(reply)?;
+    match result.error {
+        None => Ok(result.resarray),
+        Some(error) => Err(error.into()),
+    }
+}
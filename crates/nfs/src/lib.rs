@@ -1,8 +1,14 @@
+#[cfg(feature = "client")]
+pub mod client;
 #[cfg(feature = "server")]
 pub mod server;
 
 mod protocol;
+#[cfg(any(feature = "client", feature = "server"))]
+mod record;
 
+#[cfg(feature = "client")]
+pub use client::Client;
 pub use protocol::*;
 #[cfg(feature = "server")]
-pub use server::{Handler, Server};
+pub use server::{Credentials, Handler, IdentityMap, Permissions, Server, ANONYMOUS_ID};
@@ -63,7 +63,7 @@ fn fixed_width<const N: usize>(input: &[u8]) -> IResult<&[u8], [u8; N]> {
     map_res(take(N), TryInto::try_into)(input)
 }
 
-fn auth_sys_parms(input: &[u8]) -> IResult<&[u8], AuthSysParms> {
+pub fn auth_sys_parms(input: &[u8]) -> IResult<&[u8], AuthSysParms> {
     map(
         tuple((
             be_u32,
@@ -150,8 +150,149 @@ fn attribute_mask(input: &[u8]) -> IResult<&[u8], AttributeMask> {
     })(input)
 }
 
+fn file_type(input: &[u8]) -> IResult<&[u8], FileType> {
+    map_opt(be_u32, FileType::from_u32)(input)
+}
+
+fn file_system_id(input: &[u8]) -> IResult<&[u8], FileSystemId> {
+    map(tuple((be_u64, be_u64)), |(major, minor)| FileSystemId {
+        major,
+        minor,
+    })(input)
+}
+
+fn acl_support_flags(input: &[u8]) -> IResult<&[u8], AclSupportFlags> {
+    map_opt(be_u32, AclSupportFlags::from_bits)(input)
+}
+
+fn attribute_list(input: &[u8]) -> IResult<&[u8], Cow<[Attribute]>> {
+    map(attribute_mask, |mask| mask.into_iter().collect())(input)
+}
+
+fn set_time_discriminant(input: &[u8]) -> IResult<&[u8], SetTimeDiscriminant> {
+    map_opt(be_u32, SetTimeDiscriminant::from_u32)(input)
+}
+
+fn set_time(input: &[u8]) -> IResult<&[u8], SetTime> {
+    let (input, discriminant) = set_time_discriminant(input)?;
+    match discriminant {
+        SetTimeDiscriminant::ServerTime => Ok((input, SetTime::ServerTime)),
+        SetTimeDiscriminant::ClientTime => map(time, SetTime::ClientTime)(input),
+    }
+}
+
+fn change_policy(input: &[u8]) -> IResult<&[u8], ChangePolicy> {
+    map(tuple((be_u64, be_u64)), ChangePolicy::from)(input)
+}
+
+fn mode_masked(input: &[u8]) -> IResult<&[u8], ModeMasked> {
+    map(tuple((be_u32, be_u32)), ModeMasked::from)(input)
+}
+
+fn file_system_charset_capabilities(input: &[u8]) -> IResult<&[u8], FileSystemCharsetCapabilities> {
+    map(be_u32, FileSystemCharsetCapabilities::from_bits_retain)(input)
+}
+
+fn attribute_value(
+    attribute: Attribute,
+    input: &[u8],
+) -> IResult<&[u8], AttributeValue> {
+    match attribute {
+        Attribute::SupportedAttributes => {
+            map(attribute_list, AttributeValue::SupportedAttributes)(input)
+        }
+        Attribute::Type => map(file_type, AttributeValue::Type)(input),
+        Attribute::FileHandleExpireType => map(be_u32, AttributeValue::FileHandleExpireType)(input),
+        Attribute::Change => map(be_u64, AttributeValue::Change)(input),
+        Attribute::Size => map(be_u64, AttributeValue::Size)(input),
+        Attribute::LinkSupport => map(bool_u32, AttributeValue::LinkSupport)(input),
+        Attribute::SymlinkSupport => map(bool_u32, AttributeValue::SymlinkSupport)(input),
+        Attribute::NamedAttributes => map(bool_u32, AttributeValue::NamedAttributes)(input),
+        Attribute::FileSystemId => map(file_system_id, AttributeValue::FileSystemId)(input),
+        Attribute::UniqueHandles => map(bool_u32, AttributeValue::UniqueHandles)(input),
+        Attribute::LeaseTime => map(be_u32, AttributeValue::LeaseTime)(input),
+        Attribute::ReadDirAttributeError => {
+            map(be_u32, |_| AttributeValue::ReadDirAttributeError)(input)
+        }
+        Attribute::AclSupport => map(acl_support_flags, AttributeValue::AclSupport)(input),
+        Attribute::Archive => map(bool_u32, AttributeValue::Archive)(input),
+        Attribute::CanSetTime => map(bool_u32, AttributeValue::CanSetTime)(input),
+        Attribute::CaseInsensitive => map(bool_u32, AttributeValue::CaseInsensitive)(input),
+        Attribute::CasePreserving => map(bool_u32, AttributeValue::CasePreserving)(input),
+        Attribute::ChownRestricted => map(bool_u32, AttributeValue::ChownRestricted)(input),
+        Attribute::FileHandle => map(file_handle, AttributeValue::FileHandle)(input),
+        Attribute::FileId => map(be_u64, AttributeValue::FileId)(input),
+        Attribute::FilesAvailable => map(be_u64, AttributeValue::FilesAvailable)(input),
+        Attribute::FilesFree => map(be_u64, AttributeValue::FilesFree)(input),
+        Attribute::FilesTotal => map(be_u64, AttributeValue::FilesTotal)(input),
+        Attribute::Hidden => map(bool_u32, AttributeValue::Hidden)(input),
+        Attribute::Homogeneous => map(bool_u32, AttributeValue::Homogeneous)(input),
+        Attribute::MaxFileSize => map(be_u64, AttributeValue::MaxFileSize)(input),
+        Attribute::MaxLink => map(be_u32, AttributeValue::MaxLink)(input),
+        Attribute::MaxName => map(be_u32, AttributeValue::MaxName)(input),
+        Attribute::MaxRead => map(be_u64, AttributeValue::MaxRead)(input),
+        Attribute::MaxWrite => map(be_u64, AttributeValue::MaxWrite)(input),
+        Attribute::MimeType => map(string, |value| AttributeValue::MimeType(value.into()))(input),
+        Attribute::Mode => map(be_u32, AttributeValue::Mode)(input),
+        Attribute::NoTruncate => map(bool_u32, AttributeValue::NoTruncate)(input),
+        Attribute::NumberOfLinks => map(be_u32, AttributeValue::NumberOfLinks)(input),
+        Attribute::Owner => map(string, |value| AttributeValue::Owner(value.into()))(input),
+        Attribute::OwnerGroup => {
+            map(string, |value| AttributeValue::OwnerGroup(value.into()))(input)
+        }
+        Attribute::QuotaAvailableHard => map(be_u64, AttributeValue::QuotaAvailableHard)(input),
+        Attribute::QuotaAvailableSoft => map(be_u64, AttributeValue::QuotaAvailableSoft)(input),
+        Attribute::QuotaUsed => map(be_u64, AttributeValue::QuotaUsed)(input),
+        Attribute::RawDevice => map(spec_data, AttributeValue::RawDevice)(input),
+        Attribute::SpaceAvailable => map(be_u64, AttributeValue::SpaceAvailable)(input),
+        Attribute::SpaceFree => map(be_u64, AttributeValue::SpaceFree)(input),
+        Attribute::SpaceTotal => map(be_u64, AttributeValue::SpaceTotal)(input),
+        Attribute::SpaceUsed => map(be_u64, AttributeValue::SpaceUsed)(input),
+        Attribute::System => map(bool_u32, AttributeValue::System)(input),
+        Attribute::TimeAccess => map(time, AttributeValue::TimeAccess)(input),
+        Attribute::TimeAccessSet => map(set_time, AttributeValue::TimeAccessSet)(input),
+        Attribute::TimeBackup => map(time, AttributeValue::TimeBackup)(input),
+        Attribute::TimeCreate => map(time, AttributeValue::TimeCreate)(input),
+        Attribute::TimeDelta => map(time, AttributeValue::TimeDelta)(input),
+        Attribute::TimeMetadata => map(time, AttributeValue::TimeMetadata)(input),
+        Attribute::TimeModify => map(time, AttributeValue::TimeModify)(input),
+        Attribute::TimeModifySet => map(set_time, AttributeValue::TimeModifySet)(input),
+        Attribute::MountedOnFileId => map(be_u64, AttributeValue::MountedOnFileId)(input),
+        Attribute::DirectoryNotificationDelay => {
+            map(time, AttributeValue::DirectoryNotificationDelay)(input)
+        }
+        Attribute::DirectoryEntryNotificationDelay => {
+            map(time, AttributeValue::DirectoryEntryNotificationDelay)(input)
+        }
+        Attribute::ChangePolicy => map(change_policy, AttributeValue::ChangePolicy)(input),
+        Attribute::ModeSetMasked => map(mode_masked, AttributeValue::ModeSetMasked)(input),
+        Attribute::SupportedAttributesExclusiveCreate => map(
+            attribute_list,
+            AttributeValue::SupportedAttributesExclusiveCreate,
+        )(input),
+        Attribute::FileSystemCharsetCapabilities => map(
+            file_system_charset_capabilities,
+            AttributeValue::FileSystemCharsetCapabilities,
+        )(input),
+    }
+}
+
 fn file_attributes(input: &[u8]) -> IResult<&[u8], Vec<AttributeValue>> {
-    todo!()
+    let (input, mask) = attribute_mask(input)?;
+    let (input, mut values) = variable_length_opaque(u32::MAX)(input)?;
+    // Values are packed back to back, so an attribute we cannot size makes the rest unreadable.
+    let known = mask.clone().into_iter().count();
+    let requested: u32 = mask.iter().map(|word| word.count_ones()).sum();
+    if known != requested as usize {
+        return fail(input);
+    }
+    let mut attributes = Vec::with_capacity(known);
+    for attribute in mask {
+        let (rest, value) = attribute_value(attribute, values)?;
+        values = rest;
+        attributes.push(value);
+    }
+    Ok((input, attributes))
 }
 
 fn ssv_sp_parms(input: &[u8]) -> IResult<&[u8], SsvSpParms> {
@@ -188,10 +329,24 @@ fn nfs_impl_id(input: &[u8]) -> IResult<&[u8], NfsImplId> {
 }
 
 fn compound_args(input: &[u8]) -> IResult<&[u8], CompoundArgs> {
-    map(
-        tuple((string, be_u32, variable_length_array(u32::MAX, nfs_argop))),
-        CompoundArgs::from,
-    )(input)
+    map(tuple((string, be_u32, nfs_argops)), CompoundArgs::from)(input)
+}
+
+/// Decodes the operations up to the first one that is not supported, since what follows it
+/// cannot be told apart. The compound stops there anyway.
+fn nfs_argops(input: &[u8]) -> IResult<&[u8], Vec<NfsArgOp>> {
+    let (mut input, length) = be_u32(input)?;
+    let mut argops = Vec::new();
+    for _ in 0..length {
+        let (rest, argop) = nfs_argop(input)?;
+        input = rest;
+        let is_supported = !matches!(argop, NfsArgOp::Unsupported(_) | NfsArgOp::Illegal);
+        argops.push(argop);
+        if !is_supported {
+            break;
+        }
+    }
+    Ok((input, argops))
 }
 
 fn compound_result(input: &[u8]) -> IResult<&[u8], CompoundResult> {
@@ -202,32 +357,74 @@ fn compound_result(input: &[u8]) -> IResult<&[u8], CompoundResult> {
 }
 
 fn nfs_resop(input: &[u8]) -> IResult<&[u8], NfsResOp> {
-    flat_map(nfs_opnum, |opnum| match opnum {
-        NfsOpnum::GetAttributes => {
-            move |input| map(get_attributes_result, NfsResOp::GetAttributes)(input)
-        }
-        NfsOpnum::GetFileHandle => {
-            move |input| map(get_file_handle_result, NfsResOp::GetFileHandle)(input)
+    let (input, opnum) = nfs_opnum(input)?;
+    match opnum {
+        NfsOpnum::Close => map(close_result, NfsResOp::Close)(input),
+        NfsOpnum::Commit => map(commit_result, NfsResOp::Commit)(input),
+        NfsOpnum::Create => map(create_result, NfsResOp::Create)(input),
+        NfsOpnum::DelegationReturn => {
+            map(delegation_return_result, NfsResOp::DelegationReturn)(input)
         }
+        NfsOpnum::GetAttributes => map(get_attributes_result, NfsResOp::GetAttributes)(input),
+        NfsOpnum::GetFileHandle => map(get_file_handle_result, NfsResOp::GetFileHandle)(input),
+        NfsOpnum::Link => map(link_result, NfsResOp::Link)(input),
+        NfsOpnum::Lock => map(lock_result, NfsResOp::Lock)(input),
+        NfsOpnum::LockTest => map(lock_test_result, NfsResOp::LockTest)(input),
+        NfsOpnum::Unlock => map(unlock_result, NfsResOp::Unlock)(input),
+        NfsOpnum::Lookup => map(lookup_result, NfsResOp::Lookup)(input),
+        NfsOpnum::LookupParent => map(lookup_parent_result, NfsResOp::LookupParent)(input),
+        NfsOpnum::Open => map(open_result, NfsResOp::Open)(input),
+        NfsOpnum::OpenAttributes => map(open_attributes_result, NfsResOp::OpenAttributes)(input),
+        NfsOpnum::PutFileHandle => map(put_file_handle_result, NfsResOp::PutFileHandle)(input),
         NfsOpnum::PutRootFileHandle => {
-            move |input| map(put_root_file_handle_result, NfsResOp::PutRootFileHandle)(input)
+            map(put_root_file_handle_result, NfsResOp::PutRootFileHandle)(input)
         }
-        NfsOpnum::ExchangeId => move |input| map(exchange_id_result, NfsResOp::ExchangeId)(input),
-        NfsOpnum::CreateSession => {
-            move |input| map(create_session_result, NfsResOp::CreateSession)(input)
-        }
-        NfsOpnum::DestroySession => {
-            move |input| map(destroy_session_result, NfsResOp::DestroySession)(input)
+        NfsOpnum::Read => map(read_result, NfsResOp::Read)(input),
+        NfsOpnum::ReadDirectory => map(read_directory_result, NfsResOp::ReadDirectory)(input),
+        NfsOpnum::ReadLink => map(read_link_result, NfsResOp::ReadLink)(input),
+        NfsOpnum::Remove => map(remove_result, NfsResOp::Remove)(input),
+        NfsOpnum::Rename => map(rename_result, NfsResOp::Rename)(input),
+        NfsOpnum::RestoreFileHandle => {
+            map(restore_file_handle_result, NfsResOp::RestoreFileHandle)(input)
         }
+        NfsOpnum::SaveFileHandle => map(save_file_handle_result, NfsResOp::SaveFileHandle)(input),
+        NfsOpnum::SetAttributes => map(set_attributes_result, NfsResOp::SetAttributes)(input),
+        NfsOpnum::Write => map(write_result, NfsResOp::Write)(input),
+        NfsOpnum::ExchangeId => map(exchange_id_result, NfsResOp::ExchangeId)(input),
+        NfsOpnum::CreateSession => map(create_session_result, NfsResOp::CreateSession)(input),
+        NfsOpnum::DestroySession => map(destroy_session_result, NfsResOp::DestroySession)(input),
         NfsOpnum::DestroyClientId => {
-            move |input| map(destroy_client_id_result, NfsResOp::DestroyClientId)(input)
-        }
-        NfsOpnum::Sequence => move |input| map(sequence_result, NfsResOp::Sequence)(input),
-        NfsOpnum::ReclaimComplete => {
-            move |input| map(reclaim_complete_result, NfsResOp::ReclaimComplete)(input)
+            map(destroy_client_id_result, NfsResOp::DestroyClientId)(input)
         }
-        _ => todo!(),
-    })(input)
+        NfsOpnum::Sequence => map(sequence_result, NfsResOp::Sequence)(input),
+        NfsOpnum::ReclaimComplete => map(reclaim_complete_result, NfsResOp::ReclaimComplete)(input),
+        NfsOpnum::Illegal => map_opt(error, |error| error.map(NfsResOp::Illegal))(input),
+        // Without a decoder for the result, only failures can be told apart, by their status.
+        _ => map_opt(error, |error| {
+            error.map(|error| NfsResOp::Unsupported(opnum, error))
+        })(input),
+    }
+}
+
+// Attribute 12: acl
+
+fn ace_type(input: &[u8]) -> IResult<&[u8], AceType> {
+    map_opt(be_u32, AceType::from_bits)(input)
+}
+
+fn ace_flag(input: &[u8]) -> IResult<&[u8], AceFlag> {
+    map_opt(be_u32, AceFlag::from_bits)(input)
+}
+
+fn ace_access_mask(input: &[u8]) -> IResult<&[u8], AceAccessMask> {
+    map_opt(be_u32, AceAccessMask::from_bits)(input)
+}
+
+fn nfs_ace(input: &[u8]) -> IResult<&[u8], NfsAce> {
+    map(
+        tuple((ace_type, ace_flag, ace_access_mask, string)),
+        NfsAce::from,
+    )(input)
 }
 
 // Operation 3: ACCESS
@@ -251,6 +448,69 @@ fn close_result(input: &[u8]) -> IResult<&[u8], Result<StateId, Error>> {
     })(input)
 }
 
+// Operation 5: COMMIT
+
+fn commit_args(input: &[u8]) -> IResult<&[u8], CommitArgs> {
+    map(tuple((be_u64, be_u32)), CommitArgs::from)(input)
+}
+
+fn commit_result(input: &[u8]) -> IResult<&[u8], Result<Verifier, Error>> {
+    flat_map(error, |error| {
+        move |input| match error {
+            None => map(fixed_width, Ok)(input),
+            Some(error) => Ok((input, Err(error))),
+        }
+    })(input)
+}
+
+// Operation 6: CREATE
+
+fn spec_data(input: &[u8]) -> IResult<&[u8], SpecData> {
+    map(tuple((be_u32, be_u32)), SpecData::from)(input)
+}
+
+fn create_type(input: &[u8]) -> IResult<&[u8], CreateType> {
+    let (input, file_type) = file_type(input)?;
+    match file_type {
+        FileType::Symlink => map(string, |data| CreateType::Link(data.into()))(input),
+        FileType::BlockDevice => map(spec_data, CreateType::BlockDevice)(input),
+        FileType::CharacterDevice => map(spec_data, CreateType::CharacterDevice)(input),
+        FileType::Socket => Ok((input, CreateType::Socket)),
+        FileType::Fifo => Ok((input, CreateType::Fifo)),
+        FileType::Directory => Ok((input, CreateType::Directory)),
+        _ => fail(input),
+    }
+}
+
+fn create_args(input: &[u8]) -> IResult<&[u8], CreateArgs> {
+    map(
+        tuple((create_type, string, file_attributes)),
+        CreateArgs::from,
+    )(input)
+}
+
+fn create_result(input: &[u8]) -> IResult<&[u8], Result<CreateResult, Error>> {
+    flat_map(error, |error| {
+        move |input| match error {
+            None => map(tuple((change_info, attribute_mask)), |value| {
+                Ok(CreateResult::from(value))
+            })(input),
+            Some(error) => Ok((input, Err(error))),
+        }
+    })(input)
+}
+
+// Operation 8: DELEGRETURN
+
+fn delegation_return_result(input: &[u8]) -> IResult<&[u8], Result<(), Error>> {
+    flat_map(error, |error| {
+        move |input| match error {
+            None => Ok((input, Ok(()))),
+            Some(error) => Ok((input, Err(error))),
+        }
+    })(input)
+}
+
 // Operation 9: GETATTR
 
 fn get_attributes_result(input: &[u8]) -> IResult<&[u8], Result<Vec<AttributeValue>, Error>> {
@@ -273,6 +533,123 @@ fn get_file_handle_result(input: &[u8]) -> IResult<&[u8], Result<FileHandle, Err
     })(input)
 }
 
+// Operation 11: LINK
+
+fn link_result(input: &[u8]) -> IResult<&[u8], Result<ChangeInfo, Error>> {
+    flat_map(error, |error| {
+        move |input| match error {
+            None => map(change_info, Ok)(input),
+            Some(error) => Ok((input, Err(error))),
+        }
+    })(input)
+}
+
+// Operation 12: LOCK
+
+fn lock_type(input: &[u8]) -> IResult<&[u8], LockType> {
+    map_opt(be_u32, LockType::from_u32)(input)
+}
+
+fn lock_owner(input: &[u8]) -> IResult<&[u8], LockOwner> {
+    map(state_owner, LockOwner)(input)
+}
+
+fn locker(input: &[u8]) -> IResult<&[u8], Locker> {
+    flat_map(bool_u32, |new_lock_owner| {
+        move |input| match new_lock_owner {
+            true => map(tuple((be_u32, state_id, be_u32, lock_owner)), |value| {
+                Locker::New(OpenToLockOwner::from(value))
+            })(input),
+            false => map(tuple((state_id, be_u32)), |value| {
+                Locker::Existing(ExistingLockOwner::from(value))
+            })(input),
+        }
+    })(input)
+}
+
+fn lock_args(input: &[u8]) -> IResult<&[u8], LockArgs> {
+    map(
+        tuple((lock_type, bool_u32, be_u64, be_u64, locker)),
+        LockArgs::from,
+    )(input)
+}
+
+fn lock_denied(input: &[u8]) -> IResult<&[u8], LockDenied> {
+    map(
+        tuple((be_u64, be_u64, lock_type, lock_owner)),
+        LockDenied::from,
+    )(input)
+}
+
+fn lock_result(input: &[u8]) -> IResult<&[u8], Result<StateId, LockError>> {
+    flat_map(error, |error| {
+        move |input| match error {
+            None => map(state_id, Ok)(input),
+            Some(Error::DENIED) => map(lock_denied, |denied| Err(denied.into()))(input),
+            Some(error) => Ok((input, Err(error.into()))),
+        }
+    })(input)
+}
+
+// Operation 13: LOCKT
+
+fn lock_test_args(input: &[u8]) -> IResult<&[u8], LockTestArgs> {
+    map(
+        tuple((lock_type, be_u64, be_u64, lock_owner)),
+        LockTestArgs::from,
+    )(input)
+}
+
+fn lock_test_result(input: &[u8]) -> IResult<&[u8], Result<(), LockError>> {
+    flat_map(error, |error| {
+        move |input| match error {
+            None => Ok((input, Ok(()))),
+            Some(Error::DENIED) => map(lock_denied, |denied| Err(denied.into()))(input),
+            Some(error) => Ok((input, Err(error.into()))),
+        }
+    })(input)
+}
+
+// Operation 14: LOCKU
+
+fn unlock_args(input: &[u8]) -> IResult<&[u8], UnlockArgs> {
+    map(
+        tuple((lock_type, be_u32, state_id, be_u64, be_u64)),
+        UnlockArgs::from,
+    )(input)
+}
+
+fn unlock_result(input: &[u8]) -> IResult<&[u8], Result<StateId, Error>> {
+    flat_map(error, |error| {
+        move |input| match error {
+            None => map(state_id, Ok)(input),
+            Some(error) => Ok((input, Err(error))),
+        }
+    })(input)
+}
+
+// Operation 15: LOOKUP
+
+fn lookup_result(input: &[u8]) -> IResult<&[u8], Result<(), Error>> {
+    flat_map(error, |error| {
+        move |input| match error {
+            None => Ok((input, Ok(()))),
+            Some(error) => Ok((input, Err(error))),
+        }
+    })(input)
+}
+
+// Operation 16: LOOKUPP
+
+fn lookup_parent_result(input: &[u8]) -> IResult<&[u8], Result<(), Error>> {
+    flat_map(error, |error| {
+        move |input| match error {
+            None => Ok((input, Ok(()))),
+            Some(error) => Ok((input, Err(error))),
+        }
+    })(input)
+}
+
 // Operation 18: OPEN
 
 fn open_flag_create_discriminant(input: &[u8]) -> IResult<&[u8], OpenFlagCreateDiscriminant> {
@@ -282,9 +659,19 @@ fn open_flag_create_discriminant(input: &[u8]) -> IResult<&[u8], OpenFlagCreateD
 fn open_flag_create(input: &[u8]) -> IResult<&[u8], OpenFlagCreate> {
     let (input, discriminant) = open_flag_create_discriminant(input)?;
     match discriminant {
-        OpenFlagCreateDiscriminant::Unchecked => Ok((input, OpenFlagCreate::Unchecked)),
-        OpenFlagCreateDiscriminant::Guarded => todo!(),
-        OpenFlagCreateDiscriminant::Exclusive4_1 => todo!(),
+        OpenFlagCreateDiscriminant::Unchecked => map(file_attributes, |attributes| {
+            OpenFlagCreate::Unchecked { attributes }
+        })(input),
+        OpenFlagCreateDiscriminant::Guarded => map(file_attributes, |attributes| {
+            OpenFlagCreate::Guarded { attributes }
+        })(input),
+        OpenFlagCreateDiscriminant::Exclusive4_1 => map(
+            tuple((fixed_width, file_attributes)),
+            |(verifier, attributes)| OpenFlagCreate::Exclusive4_1 {
+                verifier,
+                attributes,
+            },
+        )(input),
     }
 }
 
@@ -365,6 +752,109 @@ fn open_args(input: &[u8]) -> IResult<&[u8], OpenArgs> {
     )(input)
 }
 
+fn open_read_delegation(input: &[u8]) -> IResult<&[u8], OpenReadDelegation> {
+    map(
+        tuple((state_id, bool_u32, nfs_ace)),
+        OpenReadDelegation::from,
+    )(input)
+}
+
+fn open_write_delegation(input: &[u8]) -> IResult<&[u8], OpenWriteDelegation> {
+    map(
+        tuple((state_id, bool_u32, space_limit, nfs_ace)),
+        OpenWriteDelegation::from,
+    )(input)
+}
+
+fn open_none_delegation_discriminant(
+    input: &[u8]
+) -> IResult<&[u8], OpenNoneDelegationDiscriminant> {
+    map_opt(be_u32, OpenNoneDelegationDiscriminant::from_u32)(input)
+}
+
+fn open_none_delegation(input: &[u8]) -> IResult<&[u8], OpenNoneDelegation> {
+    let (input, discriminant) = open_none_delegation_discriminant(input)?;
+    match discriminant {
+        OpenNoneDelegationDiscriminant::NotWanted => Ok((input, OpenNoneDelegation::NotWanted)),
+        OpenNoneDelegationDiscriminant::Contention => map(bool_u32, |server_will_push_deleg| {
+            OpenNoneDelegation::Contention {
+                server_will_push_deleg,
+            }
+        })(input),
+        OpenNoneDelegationDiscriminant::Resource => map(bool_u32, |server_will_signal_avail| {
+            OpenNoneDelegation::Resource {
+                server_will_signal_avail,
+            }
+        })(input),
+        OpenNoneDelegationDiscriminant::NotSupportedFileType => {
+            Ok((input, OpenNoneDelegation::NotSupportedFileType))
+        }
+        OpenNoneDelegationDiscriminant::WriteDelegationNotSupportedFileType => Ok((
+            input,
+            OpenNoneDelegation::WriteDelegationNotSupportedFileType,
+        )),
+        OpenNoneDelegationDiscriminant::NotSupportedUpgrade => {
+            Ok((input, OpenNoneDelegation::NotSupportedUpgrade))
+        }
+        OpenNoneDelegationDiscriminant::NotSupportedDowngrade => {
+            Ok((input, OpenNoneDelegation::NotSupportedDowngrade))
+        }
+        OpenNoneDelegationDiscriminant::Cancelled => Ok((input, OpenNoneDelegation::Cancelled)),
+        OpenNoneDelegationDiscriminant::IsDirectory => Ok((input, OpenNoneDelegation::IsDirectory)),
+    }
+}
+
+fn open_delegation(input: &[u8]) -> IResult<&[u8], OpenDelegation> {
+    let (input, delegation_type) = open_delegation_type(input)?;
+    match delegation_type {
+        OpenDelegationType::None => Ok((input, OpenDelegation::None)),
+        OpenDelegationType::Read => map(open_read_delegation, OpenDelegation::Read)(input),
+        OpenDelegationType::Write => map(open_write_delegation, OpenDelegation::Write)(input),
+        OpenDelegationType::NoneExt => map(open_none_delegation, OpenDelegation::NoneExt)(input),
+    }
+}
+
+fn open_result_flags(input: &[u8]) -> IResult<&[u8], OpenResultFlags> {
+    map_opt(be_u32, OpenResultFlags::from_bits)(input)
+}
+
+fn open_result(input: &[u8]) -> IResult<&[u8], Result<OpenResult, Error>> {
+    flat_map(error, |error| {
+        move |input| match error {
+            None => map(open_result_ok, Ok)(input),
+            Some(error) => Ok((input, Err(error))),
+        }
+    })(input)
+}
+
+fn open_result_ok(input: &[u8]) -> IResult<&[u8], OpenResult> {
+    map(
+        tuple((
+            state_id,
+            change_info,
+            open_result_flags,
+            attribute_mask,
+            open_delegation,
+        )),
+        OpenResult::from,
+    )(input)
+}
+
+// Operation 19: OPENATTR
+
+fn open_attributes_args(input: &[u8]) -> IResult<&[u8], OpenAttributesArgs> {
+    map(bool_u32, OpenAttributesArgs::from)(input)
+}
+
+fn open_attributes_result(input: &[u8]) -> IResult<&[u8], Result<(), Error>> {
+    flat_map(error, |error| {
+        move |input| match error {
+            None => Ok((input, Ok(()))),
+            Some(error) => Ok((input, Err(error))),
+        }
+    })(input)
+}
+
 // Operation 22: PUTFH
 
 fn put_file_handle_result(input: &[u8]) -> IResult<&[u8], Result<(), Error>> {
@@ -393,6 +883,18 @@ fn read_args(input: &[u8]) -> IResult<&[u8], ReadArgs> {
     map(tuple((state_id, be_u64, be_u32)), ReadArgs::from)(input)
 }
 
+fn read_result(input: &[u8]) -> IResult<&[u8], Result<ReadResult, Error>> {
+    flat_map(error, |error| {
+        move |input| match error {
+            None => map(
+                tuple((bool_u32, variable_length_opaque(u32::MAX))),
+                |(eof, data)| Ok(ReadResult::from((eof, data))),
+            )(input),
+            Some(error) => Ok((input, Err(error))),
+        }
+    })(input)
+}
+
 // Operation 26: READDIR
 
 fn read_directory_args(input: &[u8]) -> IResult<&[u8], ReadDirectoryArgs> {
@@ -402,6 +904,108 @@ fn read_directory_args(input: &[u8]) -> IResult<&[u8], ReadDirectoryArgs> {
     )(input)
 }
 
+fn entry(input: &[u8]) -> IResult<&[u8], Entry> {
+    map(
+        tuple((be_u64, string, file_attributes)),
+        |(cookie, name, attributes)| Entry {
+            cookie,
+            name: name.into(),
+            attributes,
+        },
+    )(input)
+}
+
+/// Decodes the entries, each of which is preceded by a flag telling whether it is present.
+fn directory_list(input: &[u8]) -> IResult<&[u8], DirectoryList> {
+    let (mut input, mut follows) = bool_u32(input)?;
+    let mut entries = Vec::new();
+    while follows {
+        let (rest, value) = entry(input)?;
+        entries.push(value);
+        (input, follows) = bool_u32(rest)?;
+    }
+    let (input, eof) = bool_u32(input)?;
+    Ok((input, DirectoryList { entries, eof }))
+}
+
+fn read_directory_result(input: &[u8]) -> IResult<&[u8], Result<ReadDirectoryResult, Error>> {
+    flat_map(error, |error| {
+        move |input| match error {
+            None => map(
+                tuple((fixed_width, directory_list)),
+                |(cookie_verf, directory_list)| {
+                    Ok(ReadDirectoryResult {
+                        cookie_verf,
+                        directory_list,
+                    })
+                },
+            )(input),
+            Some(error) => Ok((input, Err(error))),
+        }
+    })(input)
+}
+
+// Operation 27: READLINK
+
+fn read_link_result(input: &[u8]) -> IResult<&[u8], Result<Cow<str>, Error>> {
+    flat_map(error, |error| {
+        move |input| match error {
+            None => map(string, |link| Ok(link.into()))(input),
+            Some(error) => Ok((input, Err(error))),
+        }
+    })(input)
+}
+
+// Operation 28: REMOVE
+
+fn remove_result(input: &[u8]) -> IResult<&[u8], Result<ChangeInfo, Error>> {
+    flat_map(error, |error| {
+        move |input| match error {
+            None => map(change_info, Ok)(input),
+            Some(error) => Ok((input, Err(error))),
+        }
+    })(input)
+}
+
+// Operation 29: RENAME
+
+fn rename_args(input: &[u8]) -> IResult<&[u8], RenameArgs> {
+    map(tuple((string, string)), RenameArgs::from)(input)
+}
+
+fn rename_result(input: &[u8]) -> IResult<&[u8], Result<RenameResult, Error>> {
+    flat_map(error, |error| {
+        move |input| match error {
+            None => map(tuple((change_info, change_info)), |value| {
+                Ok(RenameResult::from(value))
+            })(input),
+            Some(error) => Ok((input, Err(error))),
+        }
+    })(input)
+}
+
+// Operation 31: RESTOREFH
+
+fn restore_file_handle_result(input: &[u8]) -> IResult<&[u8], Result<(), Error>> {
+    flat_map(error, |error| {
+        move |input| match error {
+            None => Ok((input, Ok(()))),
+            Some(error) => Ok((input, Err(error))),
+        }
+    })(input)
+}
+
+// Operation 32: SAVEFH
+
+fn save_file_handle_result(input: &[u8]) -> IResult<&[u8], Result<(), Error>> {
+    flat_map(error, |error| {
+        move |input| match error {
+            None => Ok((input, Ok(()))),
+            Some(error) => Ok((input, Err(error))),
+        }
+    })(input)
+}
+
 // Operation 33: SECINFO
 
 fn get_security_info_args(input: &[u8]) -> IResult<&[u8], GetSecurityInfoArgs> {
@@ -428,6 +1032,52 @@ fn get_security_info_result_ok(input: &[u8]) -> IResult<&[u8], GetSecurityInfoRe
     todo!()
 }
 
+// Operation 34: SETATTR
+
+fn set_attributes_args(input: &[u8]) -> IResult<&[u8], SetAttributesArgs> {
+    map(tuple((state_id, file_attributes)), SetAttributesArgs::from)(input)
+}
+
+fn set_attributes_result(input: &[u8]) -> IResult<&[u8], Result<AttributeMask, Error>> {
+    // The applied attributes are reported even when the operation fails.
+    map(
+        tuple((error, attribute_mask)),
+        |(error, mask)| match error {
+            None => Ok(mask),
+            Some(error) => Err(error),
+        },
+    )(input)
+}
+
+// Operation 38: WRITE
+
+fn stable_how(input: &[u8]) -> IResult<&[u8], StableHow> {
+    map_opt(be_u32, StableHow::from_u32)(input)
+}
+
+fn write_args(input: &[u8]) -> IResult<&[u8], WriteArgs> {
+    map(
+        tuple((
+            state_id,
+            be_u64,
+            stable_how,
+            variable_length_opaque(u32::MAX),
+        )),
+        WriteArgs::from,
+    )(input)
+}
+
+fn write_result(input: &[u8]) -> IResult<&[u8], Result<WriteResult, Error>> {
+    flat_map(error, |error| {
+        move |input| match error {
+            None => map(tuple((be_u32, stable_how, fixed_width)), |value| {
+                Ok(WriteResult::from(value))
+            })(input),
+            Some(error) => Ok((input, Err(error))),
+        }
+    })(input)
+}
+
 // Operation 40
 
 fn callback_sec_parms(input: &[u8]) -> IResult<&[u8], CallbackSecParms> {
@@ -628,42 +1278,180 @@ fn reclaim_complete_result(input: &[u8]) -> IResult<&[u8], Result<(), Error>> {
     })(input)
 }
 
-//
+// Callbacks
 
-fn nfs_argop(input: &[u8]) -> IResult<&[u8], NfsArgOp> {
-    flat_map(nfs_opnum, |opnum| match opnum {
-        NfsOpnum::Access => move |input| map(access_flags, NfsArgOp::Access)(input),
-        NfsOpnum::Close => move |input| map(close_args, NfsArgOp::Close)(input),
-        NfsOpnum::GetAttributes => move |input| map(attribute_mask, NfsArgOp::GetAttributes)(input),
-        NfsOpnum::GetFileHandle => move |input| Ok((input, NfsArgOp::GetFileHandle)),
-        NfsOpnum::Lookup => move |input| map(string, NfsArgOp::Lookup)(input),
-        NfsOpnum::Open => move |input| map(open_args, NfsArgOp::Open)(input),
-        NfsOpnum::PutFileHandle => move |input| map(file_handle, NfsArgOp::PutFileHandle)(input),
-        NfsOpnum::PutRootFileHandle => move |input| Ok((input, NfsArgOp::PutRootFileHandle)),
-        NfsOpnum::Read => move |input| map(read_args, NfsArgOp::Read)(input),
-        NfsOpnum::ReadDirectory => {
-            move |input| map(read_directory_args, NfsArgOp::ReadDirectory)(input)
-        }
-        NfsOpnum::ExchangeId => move |input| map(exchange_id_args, NfsArgOp::ExchangeId)(input),
-        NfsOpnum::CreateSession => {
-            move |input| map(create_session_args, NfsArgOp::CreateSession)(input)
-        }
-        NfsOpnum::DestroySession => move |input| map(fixed_width, NfsArgOp::DestroySession)(input),
-        NfsOpnum::DestroyClientId => move |input| map(be_u64, NfsArgOp::DestroyClientId)(input),
-        NfsOpnum::GetSecurityInfoNoName => move |input| {
-            map(
-                get_security_info_no_name_args,
-                NfsArgOp::GetSecurityInfoNoName,
-            )(input)
-        },
-        NfsOpnum::Sequence => move |input| map(sequence_args, NfsArgOp::Sequence)(input),
-        NfsOpnum::ReclaimComplete => {
-            move |input| map(reclaim_complete_args, NfsArgOp::ReclaimComplete)(input)
+fn callback_opnum(input: &[u8]) -> IResult<&[u8], CallbackOpnum> {
+    map_opt(be_u32, CallbackOpnum::from_u32)(input)
+}
+
+fn callback_compound_args(input: &[u8]) -> IResult<&[u8], CallbackCompoundArgs> {
+    map(
+        tuple((string, be_u32, be_u32, callback_argops)),
+        CallbackCompoundArgs::from,
+    )(input)
+}
+
+/// Decodes the operations up to the first one that is not supported, like [`nfs_argops`].
+fn callback_argops(input: &[u8]) -> IResult<&[u8], Vec<CallbackArgOp>> {
+    let (mut input, length) = be_u32(input)?;
+    let mut argops = Vec::new();
+    for _ in 0..length {
+        let (rest, argop) = callback_argop(input)?;
+        input = rest;
+        let is_supported = !matches!(
+            argop,
+            CallbackArgOp::Unsupported(_) | CallbackArgOp::Illegal
+        );
+        argops.push(argop);
+        if !is_supported {
+            break;
+        }
+    }
+    Ok((input, argops))
+}
+
+fn callback_argop(input: &[u8]) -> IResult<&[u8], CallbackArgOp> {
+    let (input, opnum) = be_u32(input)?;
+    let Some(opnum) = CallbackOpnum::from_u32(opnum) else {
+        return Ok((input, CallbackArgOp::Illegal));
+    };
+    match opnum {
+        CallbackOpnum::Recall => map(callback_recall_args, CallbackArgOp::Recall)(input),
+        CallbackOpnum::Sequence => map(callback_sequence_args, CallbackArgOp::Sequence)(input),
+        CallbackOpnum::Illegal => Ok((input, CallbackArgOp::Illegal)),
+        _ => Ok((input, CallbackArgOp::Unsupported(opnum))),
+    }
+}
+
+fn callback_compound_result(input: &[u8]) -> IResult<&[u8], CallbackCompoundResult> {
+    map(
+        tuple((
+            error,
+            string,
+            variable_length_array(u32::MAX, callback_resop),
+        )),
+        CallbackCompoundResult::from,
+    )(input)
+}
+
+fn callback_resop(input: &[u8]) -> IResult<&[u8], CallbackResOp> {
+    let (input, opnum) = callback_opnum(input)?;
+    match opnum {
+        CallbackOpnum::Recall => map(callback_recall_result, CallbackResOp::Recall)(input),
+        CallbackOpnum::Sequence => map(callback_sequence_result, CallbackResOp::Sequence)(input),
+        CallbackOpnum::Illegal => map_opt(error, |error| error.map(CallbackResOp::Illegal))(input),
+        _ => map_opt(error, |error| {
+            error.map(|error| CallbackResOp::Unsupported(opnum, error))
+        })(input),
+    }
+}
+
+// Callback operation 4: CB_RECALL
+
+fn callback_recall_args(input: &[u8]) -> IResult<&[u8], CallbackRecallArgs> {
+    map(
+        tuple((state_id, bool_u32, file_handle)),
+        CallbackRecallArgs::from,
+    )(input)
+}
+
+fn callback_recall_result(input: &[u8]) -> IResult<&[u8], Result<(), Error>> {
+    flat_map(error, |error| {
+        move |input| match error {
+            None => Ok((input, Ok(()))),
+            Some(error) => Ok((input, Err(error))),
+        }
+    })(input)
+}
+
+// Callback operation 11: CB_SEQUENCE
+
+fn referring_call(input: &[u8]) -> IResult<&[u8], ReferringCall> {
+    map(tuple((be_u32, be_u32)), ReferringCall::from)(input)
+}
+
+fn referring_call_list(input: &[u8]) -> IResult<&[u8], ReferringCallList> {
+    map(
+        tuple((fixed_width, variable_length_array(u32::MAX, referring_call))),
+        ReferringCallList::from,
+    )(input)
+}
+
+fn callback_sequence_args(input: &[u8]) -> IResult<&[u8], CallbackSequenceArgs> {
+    map(
+        tuple((
+            fixed_width,
+            be_u32,
+            be_u32,
+            be_u32,
+            bool_u32,
+            variable_length_array(u32::MAX, referring_call_list),
+        )),
+        CallbackSequenceArgs::from,
+    )(input)
+}
+
+fn callback_sequence_result(input: &[u8]) -> IResult<&[u8], Result<CallbackSequenceResult, Error>> {
+    flat_map(error, |error| {
+        move |input| match error {
+            None => map(
+                tuple((fixed_width, be_u32, be_u32, be_u32, be_u32)),
+                |value| Ok(CallbackSequenceResult::from(value)),
+            )(input),
+            Some(error) => Ok((input, Err(error))),
         }
-        _ => todo!("{opnum:?} not implemented"),
     })(input)
 }
 
+//
+
+fn nfs_argop(input: &[u8]) -> IResult<&[u8], NfsArgOp> {
+    let (input, opnum) = be_u32(input)?;
+    let Some(opnum) = NfsOpnum::from_u32(opnum) else {
+        return Ok((input, NfsArgOp::Illegal));
+    };
+    match opnum {
+        NfsOpnum::Access => map(access_flags, NfsArgOp::Access)(input),
+        NfsOpnum::Close => map(close_args, NfsArgOp::Close)(input),
+        NfsOpnum::Commit => map(commit_args, NfsArgOp::Commit)(input),
+        NfsOpnum::Create => map(create_args, NfsArgOp::Create)(input),
+        NfsOpnum::DelegationReturn => map(state_id, NfsArgOp::DelegationReturn)(input),
+        NfsOpnum::GetAttributes => map(attribute_mask, NfsArgOp::GetAttributes)(input),
+        NfsOpnum::GetFileHandle => Ok((input, NfsArgOp::GetFileHandle)),
+        NfsOpnum::Link => map(string, NfsArgOp::Link)(input),
+        NfsOpnum::Lock => map(lock_args, NfsArgOp::Lock)(input),
+        NfsOpnum::LockTest => map(lock_test_args, NfsArgOp::LockTest)(input),
+        NfsOpnum::Unlock => map(unlock_args, NfsArgOp::Unlock)(input),
+        NfsOpnum::Lookup => map(string, NfsArgOp::Lookup)(input),
+        NfsOpnum::LookupParent => Ok((input, NfsArgOp::LookupParent)),
+        NfsOpnum::Open => map(open_args, NfsArgOp::Open)(input),
+        NfsOpnum::OpenAttributes => map(open_attributes_args, NfsArgOp::OpenAttributes)(input),
+        NfsOpnum::PutFileHandle => map(file_handle, NfsArgOp::PutFileHandle)(input),
+        NfsOpnum::PutRootFileHandle => Ok((input, NfsArgOp::PutRootFileHandle)),
+        NfsOpnum::Read => map(read_args, NfsArgOp::Read)(input),
+        NfsOpnum::ReadDirectory => map(read_directory_args, NfsArgOp::ReadDirectory)(input),
+        NfsOpnum::ReadLink => Ok((input, NfsArgOp::ReadLink)),
+        NfsOpnum::Remove => map(string, NfsArgOp::Remove)(input),
+        NfsOpnum::Rename => map(rename_args, NfsArgOp::Rename)(input),
+        NfsOpnum::RestoreFileHandle => Ok((input, NfsArgOp::RestoreFileHandle)),
+        NfsOpnum::SaveFileHandle => Ok((input, NfsArgOp::SaveFileHandle)),
+        NfsOpnum::SetAttributes => map(set_attributes_args, NfsArgOp::SetAttributes)(input),
+        NfsOpnum::Write => map(write_args, NfsArgOp::Write)(input),
+        NfsOpnum::ExchangeId => map(exchange_id_args, NfsArgOp::ExchangeId)(input),
+        NfsOpnum::CreateSession => map(create_session_args, NfsArgOp::CreateSession)(input),
+        NfsOpnum::DestroySession => map(fixed_width, NfsArgOp::DestroySession)(input),
+        NfsOpnum::DestroyClientId => map(be_u64, NfsArgOp::DestroyClientId)(input),
+        NfsOpnum::GetSecurityInfoNoName => map(
+            get_security_info_no_name_args,
+            NfsArgOp::GetSecurityInfoNoName,
+        )(input),
+        NfsOpnum::Sequence => map(sequence_args, NfsArgOp::Sequence)(input),
+        NfsOpnum::ReclaimComplete => map(reclaim_complete_args, NfsArgOp::ReclaimComplete)(input),
+        NfsOpnum::Illegal => Ok((input, NfsArgOp::Illegal)),
+        _ => Ok((input, NfsArgOp::Unsupported(opnum))),
+    }
+}
+
 fn state_protect_how(input: &[u8]) -> IResult<&[u8], StateProtectHow> {
     map_opt(be_u32, StateProtectHow::from_u32)(input)
 }
@@ -720,12 +1508,46 @@ pub fn call(input: &[u8]) -> IResult<&[u8], Call> {
     ))
 }
 
+/// Decodes a CB_COMPOUND call to the callback program of a client.
+pub fn callback_call(input: &[u8]) -> IResult<&[u8], CallbackCall> {
+    let (input, _) = verify(be_u32, |&rpcvers| rpcvers == 2)(input)?;
+    let (input, program) = be_u32(input)?;
+    let (input, _) = verify(be_u32, |&vers| vers == 1)(input)?;
+    let (input, _) = verify(be_u32, |&proc| proc == ProcedureNumber::Compound as u32)(input)?;
+    let (input, cred) = opaque_auth(input)?;
+    let (input, verf) = opaque_auth(input)?;
+    let (input, args) = callback_compound_args(input)?;
+    Ok((
+        input,
+        CallbackCall {
+            program,
+            cred,
+            verf,
+            args,
+        },
+    ))
+}
+
+/// Decodes the reply to a CB_COMPOUND, which is `None` if the client did not execute it.
+pub fn callback_reply(input: &[u8]) -> IResult<&[u8], Option<CallbackCompoundResult>> {
+    let (input, reply_status) = be_u32(input)?;
+    if reply_status != 0 {
+        return Ok((input, None));
+    }
+    let (input, _) = opaque_auth(input)?;
+    let (input, status) = accept_status(input)?;
+    match status {
+        AcceptStatus::Success => map(callback_compound_result, Some)(input),
+        _ => Ok((input, None)),
+    }
+}
+
 pub fn reply(procedure_number: ProcedureNumber) -> impl FnMut(&[u8]) -> IResult<&[u8], Reply> {
     move |input| {
         let (input, tag) = be_u32(input)?;
         match tag {
             0 => map(accepted_reply(procedure_number), Reply::Accepted)(input),
-            1 => map(rejected_reply(procedure_number), Reply::Rejected)(input),
+            1 => map(rejected_reply, Reply::Rejected)(input),
             _ => fail(input),
         }
     }
@@ -746,13 +1568,22 @@ fn accepted_reply_body(
     procedure_number: ProcedureNumber
 ) -> impl FnMut(&[u8]) -> IResult<&[u8], AcceptedReplyBody> {
     move |input| {
-        flat_map(accept_status, |status| match status {
+        let (input, status) = accept_status(input)?;
+        match status {
             AcceptStatus::Success => map(
                 procedure_reply(procedure_number),
                 AcceptedReplyBody::Success,
-            ),
-            _ => todo!(),
-        })(input)
+            )(input),
+            AcceptStatus::ProgramUnavailable => Ok((input, AcceptedReplyBody::ProgramUnavailable)),
+            AcceptStatus::ProgramMismatch => map(tuple((be_u32, be_u32)), |(low, high)| {
+                AcceptedReplyBody::ProgramMismatch { low, high }
+            })(input),
+            AcceptStatus::ProcedureUnavailable => {
+                Ok((input, AcceptedReplyBody::ProcedureUnavailable))
+            }
+            AcceptStatus::GarbageArgs => Ok((input, AcceptedReplyBody::GarbageArgs)),
+            AcceptStatus::SystemError => Ok((input, AcceptedReplyBody::SystemError)),
+        }
     }
 }
 
@@ -773,10 +1604,24 @@ fn accept_status(input: &[u8]) -> IResult<&[u8], AcceptStatus> {
     map_opt(be_u32, AcceptStatus::from_u32)(input)
 }
 
-fn rejected_reply(
-    procedure_number: ProcedureNumber
-) -> impl FnMut(&[u8]) -> IResult<&[u8], RejectedReply> {
-    move |input| todo!()
+fn rejected_reply(input: &[u8]) -> IResult<&[u8], RejectedReply> {
+    let (input, status) = reject_status(input)?;
+    match status {
+        RejectStatus::RpcMismatch => map(tuple((be_u32, be_u32)), |(low, high)| {
+            RejectedReply::RpcMismatch { low, high }
+        })(input),
+        RejectStatus::AuthError => {
+            map(auth_status, |stat| RejectedReply::AuthError { stat })(input)
+        }
+    }
+}
+
+fn reject_status(input: &[u8]) -> IResult<&[u8], RejectStatus> {
+    map_opt(be_u32, RejectStatus::from_u32)(input)
+}
+
+fn auth_status(input: &[u8]) -> IResult<&[u8], AuthStatus> {
+    map_opt(be_u32, AuthStatus::from_u32)(input)
 }
 
 fn opaque_auth(input: &[u8]) -> IResult<&[u8], OpaqueAuth> {
@@ -899,6 +1744,78 @@ mod tests {
         assert_eq!(input, &[]);
     }
 
+    #[test]
+    fn test_compound_args_stop_at_unsupported() {
+        let input = &[
+            0, 0, 0, 0, // tag
+            0, 0, 0, 1, // minor version
+            0, 0, 0, 5, // operations
+            0, 0, 0, 32, // SAVEFH
+            0, 0, 0, 16, // LOOKUPP
+            0, 0, 0, 19, 0, 0, 0, 1, // OPENATTR
+            0, 0, 0, 37, 0, 0, 0, 0, // VERIFY
+            0, 0, 0, 31, // RESTOREFH
+        ];
+        let (_, args) = compound_args(input).unwrap();
+        assert_eq!(
+            args.argarray,
+            vec![
+                NfsArgOp::SaveFileHandle,
+                NfsArgOp::LookupParent,
+                NfsArgOp::OpenAttributes(OpenAttributesArgs {
+                    create_directory: true
+                }),
+                NfsArgOp::Unsupported(NfsOpnum::VERIFY),
+            ]
+        );
+        let input = &[0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0x27, 0x0f];
+        let (_, args) = compound_args(input).unwrap();
+        assert_eq!(args.argarray, vec![NfsArgOp::Illegal]);
+    }
+
+    #[test]
+    fn test_unsuccessful_replies() {
+        let accepted = |body| {
+            Reply::Accepted(AcceptedReply {
+                verf: OpaqueAuth {
+                    flavor: AuthFlavor::AuthNone,
+                    body: (&[]).into(),
+                },
+                body,
+            })
+        };
+        let replies = [
+            // MSG_DENIED, AUTH_ERROR, AUTH_TOOWEAK
+            (
+                vec![1, 1, 5],
+                Reply::Rejected(RejectedReply::AuthError {
+                    stat: AuthStatus::AUTH_TOOWEAK,
+                }),
+            ),
+            // MSG_DENIED, RPC_MISMATCH, lowest and highest version
+            (
+                vec![1, 0, 2, 2],
+                Reply::Rejected(RejectedReply::RpcMismatch { low: 2, high: 2 }),
+            ),
+            // MSG_ACCEPTED, AUTH_NONE verifier, PROG_MISMATCH, lowest and highest version
+            (
+                vec![0, 0, 0, 2, 4, 4],
+                accepted(AcceptedReplyBody::ProgramMismatch { low: 4, high: 4 }),
+            ),
+            // MSG_ACCEPTED, AUTH_NONE verifier, GARBAGE_ARGS
+            (vec![0, 0, 0, 4], accepted(AcceptedReplyBody::GarbageArgs)),
+        ];
+        for (words, expected) in replies {
+            let input = words
+                .into_iter()
+                .flat_map(u32::to_be_bytes)
+                .collect::<Vec<_>>();
+            let (input, reply) = reply(ProcedureNumber::Compound)(&input).unwrap();
+            assert_eq!(input, &[]);
+            assert_eq!(reply, expected);
+        }
+    }
+
     #[test]
     fn test_get_attributes_reply() {
         let input = &[
@@ -948,18 +1865,32 @@ mod tests {
                         })),
                         NfsResOp::PutRootFileHandle(Ok(())),
                         NfsResOp::GetFileHandle(Ok(FileHandle::from(&[1, 0, 1, 0, 0, 0, 0, 0]))),
-                        // NfsResOp::GetAttributes(Ok(FileAttributes {
-                        //     mask: Bitmap::from(&[1048858, 11575866]),
-                        //     values: &[
-                        //         0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 16, 0, 0, 0,
-                        //         0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 254, 0, 1,
-                        //         0, 0, 1, 237, 0, 0, 0, 3, 0, 0, 0, 4, 49, 48, 48, 48, 0, 0, 0, 4,
-                        //         49, 48, 48, 48, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 16, 0, 0,
-                        //         0, 0, 0, 103, 136, 26, 222, 44, 229, 231, 141, 0, 0, 0, 0, 103,
-                        //         136, 24, 98, 20, 67, 36, 191, 0, 0, 0, 0, 103, 136, 24, 98, 20, 67,
-                        //         36, 191, 0, 0, 0, 0, 0, 73, 9, 232
-                        //     ].into(),
-                        // })),
+                        NfsResOp::GetAttributes(Ok(vec![
+                            AttributeValue::Type(FileType::Directory),
+                            AttributeValue::Change(5),
+                            AttributeValue::Size(4096),
+                            AttributeValue::FileSystemId(FileSystemId { major: 0, minor: 0 }),
+                            AttributeValue::FileId(50200577),
+                            AttributeValue::Mode(0o755),
+                            AttributeValue::NumberOfLinks(3),
+                            AttributeValue::Owner("1000".into()),
+                            AttributeValue::OwnerGroup("1000".into()),
+                            AttributeValue::RawDevice(SpecData { major: 0, minor: 0 }),
+                            AttributeValue::SpaceUsed(4096),
+                            AttributeValue::TimeAccess(Time {
+                                seconds: 1736973022,
+                                nanoseconds: 753264525,
+                            }),
+                            AttributeValue::TimeMetadata(Time {
+                                seconds: 1736972386,
+                                nanoseconds: 339944639,
+                            }),
+                            AttributeValue::TimeModify(Time {
+                                seconds: 1736972386,
+                                nanoseconds: 339944639,
+                            }),
+                            AttributeValue::MountedOnFileId(4786664),
+                        ])),
                     ]
                 }))
             })
@@ -982,6 +1913,65 @@ mod tests {
         assert_eq!(mask.next(), None);
     }
 
+    #[test]
+    fn test_write_args() {
+        let input = &[
+            0, 0, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 0, 0, 0, 0, 0, 0, 16, 0, 0, 0, 0, 2, 0,
+            0, 0, 5, b'h', b'e', b'l', b'l', b'o', 0, 0, 0,
+        ];
+        let (input, args) = write_args(input).unwrap();
+        assert_eq!(input, &[]);
+        assert_eq!(
+            args,
+            WriteArgs {
+                state_id: StateId {
+                    sequence_id: 1,
+                    other: [2; 12],
+                },
+                offset: 4096,
+                stable: StableHow::FileSync,
+                data: b"hello".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_set_attributes_args() {
+        let input = &[
+            0, 0, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 0, 0, 0, 2, 0, 0, 0, 0x10, 0, 0, 0,
+            0x02, 0, 0, 0, 12, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01, 0xa4,
+        ];
+        let (input, args) = set_attributes_args(input).unwrap();
+        assert_eq!(input, &[]);
+        assert_eq!(
+            args.attributes,
+            vec![AttributeValue::Size(0), AttributeValue::Mode(0o644)]
+        );
+    }
+
+    #[test]
+    fn test_file_attributes_unknown() {
+        let input = &[0, 0, 0, 1, 0, 0, 0x10, 0, 0, 0, 0, 4, 0, 0, 0, 0];
+        assert!(file_attributes(input).is_err());
+    }
+
+    #[test]
+    fn test_create_args() {
+        let input = &[
+            0, 0, 0, 2, 0, 0, 0, 4, b'k', b'o', b'd', b'i', 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        let (input, args) = create_args(input).unwrap();
+        assert_eq!(input, &[]);
+        assert_eq!(
+            args,
+            CreateArgs {
+                object_type: CreateType::Directory,
+                name: "kodi",
+                attributes: vec![],
+            }
+        );
+    }
+
     #[test]
     fn test_decode_open_call() {
         let input = &[
@@ -87,11 +87,14 @@ fn time<W: Write>(value: Time) -> impl SerializeFn<W> {
 fn file_attributes<'a, 'b: 'a, W: Write + Seek + 'a>(
     values: &'a [AttributeValue<'b>]
 ) -> impl SerializeFn<W> + 'a {
+    // The values follow the order of their bits in the mask, not the order they are given in.
+    let mut sorted: Vec<_> = values.iter().collect();
+    sorted.sort_by_key(|value| value.attribute() as usize);
     tuple((
         attribute_mask(values.iter().map(|v| v.attribute())),
         back_to_the_buffer(
             4,
-            move |out| gen(many_ref(values, attribute_value), out),
+            move |out| gen(many_ref(sorted.iter().copied(), attribute_value), out),
             move |out, length| gen_simple(be_u32(length as u32), out),
         ),
     ))
@@ -119,6 +122,26 @@ where
     }
 }
 
+#[inline(always)]
+fn set_time<W: Write>(value: SetTime) -> impl SerializeFn<W> {
+    move |out| match value {
+        SetTime::ServerTime => be_u32(SetTimeDiscriminant::ServerTime as u32)(out),
+        SetTime::ClientTime(value) => {
+            tuple((be_u32(SetTimeDiscriminant::ClientTime as u32), time(value)))(out)
+        }
+    }
+}
+
+#[inline(always)]
+fn change_policy<W: Write>(value: ChangePolicy) -> impl SerializeFn<W> {
+    tuple((be_u64(value.major), be_u64(value.minor)))
+}
+
+#[inline(always)]
+fn mode_masked<W: Write>(value: ModeMasked) -> impl SerializeFn<W> {
+    tuple((be_u32(value.mode), be_u32(value.mask)))
+}
+
 #[inline(always)]
 fn attribute_value<'a, 'b: 'a, W: Write + 'a>(
     value: &'a AttributeValue<'b>
@@ -139,19 +162,55 @@ fn attribute_value<'a, 'b: 'a, W: Write + 'a>(
         AttributeValue::LeaseTime(value) => be_u32(*value)(out),
         AttributeValue::ReadDirAttributeError => todo!(),
         AttributeValue::AclSupport(value) => acl_support_flags(*value)(out),
+        AttributeValue::Archive(value) => bool_u32(*value)(out),
+        AttributeValue::CanSetTime(value) => bool_u32(*value)(out),
         AttributeValue::CaseInsensitive(value) => bool_u32(*value)(out),
         AttributeValue::CasePreserving(value) => bool_u32(*value)(out),
+        AttributeValue::ChownRestricted(value) => bool_u32(*value)(out),
         AttributeValue::FileHandle(value) => file_handle(value)(out),
         AttributeValue::FileId(value) => be_u64(*value)(out),
+        AttributeValue::FilesAvailable(value) => be_u64(*value)(out),
+        AttributeValue::FilesFree(value) => be_u64(*value)(out),
+        AttributeValue::FilesTotal(value) => be_u64(*value)(out),
+        AttributeValue::Hidden(value) => bool_u32(*value)(out),
+        AttributeValue::Homogeneous(value) => bool_u32(*value)(out),
         AttributeValue::MaxFileSize(value) => be_u64(*value)(out),
+        AttributeValue::MaxLink(value) => be_u32(*value)(out),
+        AttributeValue::MaxName(value) => be_u32(*value)(out),
         AttributeValue::MaxRead(value) => be_u64(*value)(out),
         AttributeValue::MaxWrite(value) => be_u64(*value)(out),
+        AttributeValue::MimeType(value) => string(value)(out),
         AttributeValue::Mode(value) => be_u32(*value)(out),
+        AttributeValue::NoTruncate(value) => bool_u32(*value)(out),
         AttributeValue::NumberOfLinks(value) => be_u32(*value)(out),
+        AttributeValue::Owner(value) => string(value)(out),
+        AttributeValue::OwnerGroup(value) => string(value)(out),
+        AttributeValue::QuotaAvailableHard(value) => be_u64(*value)(out),
+        AttributeValue::QuotaAvailableSoft(value) => be_u64(*value)(out),
+        AttributeValue::QuotaUsed(value) => be_u64(*value)(out),
+        AttributeValue::RawDevice(value) => spec_data(*value)(out),
+        AttributeValue::SpaceAvailable(value) => be_u64(*value)(out),
+        AttributeValue::SpaceFree(value) => be_u64(*value)(out),
+        AttributeValue::SpaceTotal(value) => be_u64(*value)(out),
+        AttributeValue::SpaceUsed(value) => be_u64(*value)(out),
+        AttributeValue::System(value) => bool_u32(*value)(out),
+        AttributeValue::TimeAccess(value) => time(*value)(out),
+        AttributeValue::TimeAccessSet(value) => set_time(*value)(out),
+        AttributeValue::TimeBackup(value) => time(*value)(out),
+        AttributeValue::TimeCreate(value) => time(*value)(out),
+        AttributeValue::TimeDelta(value) => time(*value)(out),
+        AttributeValue::TimeMetadata(value) => time(*value)(out),
+        AttributeValue::TimeModify(value) => time(*value)(out),
+        AttributeValue::TimeModifySet(value) => set_time(*value)(out),
         AttributeValue::MountedOnFileId(value) => be_u64(*value)(out),
+        AttributeValue::DirectoryNotificationDelay(value) => time(*value)(out),
+        AttributeValue::DirectoryEntryNotificationDelay(value) => time(*value)(out),
+        AttributeValue::ChangePolicy(value) => change_policy(*value)(out),
+        AttributeValue::ModeSetMasked(value) => mode_masked(*value)(out),
         AttributeValue::SupportedAttributesExclusiveCreate(value) => {
             attribute_mask(value.into_iter().cloned())(out)
         }
+        AttributeValue::FileSystemCharsetCapabilities(value) => be_u32(value.bits())(out),
     }
 }
 
@@ -294,6 +353,100 @@ fn ssv_prot_info<'a, 'b: 'a, W: Write + 'a>(
     ))
 }
 
+#[inline(always)]
+fn compound_args<'a, 'b: 'a, W: Write + Seek + 'a>(
+    value: &'a CompoundArgs<'b>
+) -> impl SerializeFn<W> + 'a {
+    tuple((
+        string(&value.tag),
+        be_u32(value.minorversion),
+        variable_length_array(&value.argarray, nfs_argop),
+    ))
+}
+
+#[inline(always)]
+fn nfs_argop<'a, 'b: 'a, W: Write + Seek + 'a>(
+    value: &'a NfsArgOp<'b>
+) -> impl SerializeFn<W> + 'a {
+    move |out| match value {
+        NfsArgOp::Access(value) => tuple((nfs_opnum(NfsOpnum::Access), access_flags(*value)))(out),
+        NfsArgOp::Close(value) => tuple((nfs_opnum(NfsOpnum::Close), close_args(value)))(out),
+        NfsArgOp::Commit(value) => tuple((nfs_opnum(NfsOpnum::Commit), commit_args(value)))(out),
+        NfsArgOp::Create(value) => tuple((nfs_opnum(NfsOpnum::Create), create_args(value)))(out),
+        NfsArgOp::DelegationReturn(value) => {
+            tuple((nfs_opnum(NfsOpnum::DelegationReturn), state_id(value)))(out)
+        }
+        NfsArgOp::GetAttributes(value) => tuple((
+            nfs_opnum(NfsOpnum::GetAttributes),
+            attribute_mask(value.clone()),
+        ))(out),
+        NfsArgOp::GetFileHandle => nfs_opnum(NfsOpnum::GetFileHandle)(out),
+        NfsArgOp::Link(value) => tuple((nfs_opnum(NfsOpnum::Link), string(value)))(out),
+        NfsArgOp::Lock(value) => tuple((nfs_opnum(NfsOpnum::Lock), lock_args(value)))(out),
+        NfsArgOp::LockTest(value) => {
+            tuple((nfs_opnum(NfsOpnum::LockTest), lock_test_args(value)))(out)
+        }
+        NfsArgOp::Unlock(value) => tuple((nfs_opnum(NfsOpnum::Unlock), unlock_args(value)))(out),
+        NfsArgOp::Lookup(value) => tuple((nfs_opnum(NfsOpnum::Lookup), string(value)))(out),
+        NfsArgOp::LookupParent => nfs_opnum(NfsOpnum::LookupParent)(out),
+        NfsArgOp::Open(value) => tuple((nfs_opnum(NfsOpnum::Open), open_args(value)))(out),
+        NfsArgOp::OpenAttributes(value) => tuple((
+            nfs_opnum(NfsOpnum::OpenAttributes),
+            open_attributes_args(value),
+        ))(out),
+        NfsArgOp::PutFileHandle(value) => {
+            tuple((nfs_opnum(NfsOpnum::PutFileHandle), file_handle(value)))(out)
+        }
+        NfsArgOp::PutRootFileHandle => nfs_opnum(NfsOpnum::PutRootFileHandle)(out),
+        NfsArgOp::Read(value) => tuple((nfs_opnum(NfsOpnum::Read), read_args(value)))(out),
+        NfsArgOp::ReadDirectory(value) => tuple((
+            nfs_opnum(NfsOpnum::ReadDirectory),
+            read_directory_args(value),
+        ))(out),
+        NfsArgOp::ReadLink => nfs_opnum(NfsOpnum::ReadLink)(out),
+        NfsArgOp::Remove(value) => tuple((nfs_opnum(NfsOpnum::Remove), string(value)))(out),
+        NfsArgOp::Rename(value) => tuple((nfs_opnum(NfsOpnum::Rename), rename_args(value)))(out),
+        NfsArgOp::RestoreFileHandle => nfs_opnum(NfsOpnum::RestoreFileHandle)(out),
+        NfsArgOp::SaveFileHandle => nfs_opnum(NfsOpnum::SaveFileHandle)(out),
+        NfsArgOp::GetSecurityInfo(value) => tuple((
+            nfs_opnum(NfsOpnum::GetSecurityInfo),
+            get_security_info_args(value),
+        ))(out),
+        NfsArgOp::SetAttributes(value) => tuple((
+            nfs_opnum(NfsOpnum::SetAttributes),
+            set_attributes_args(value),
+        ))(out),
+        NfsArgOp::Write(value) => tuple((nfs_opnum(NfsOpnum::Write), write_args(value)))(out),
+        NfsArgOp::ExchangeId(value) => {
+            tuple((nfs_opnum(NfsOpnum::ExchangeId), exchange_id_args(value)))(out)
+        }
+        NfsArgOp::CreateSession(value) => tuple((
+            nfs_opnum(NfsOpnum::CreateSession),
+            create_session_args(value),
+        ))(out),
+        NfsArgOp::DestroySession(value) => {
+            tuple((nfs_opnum(NfsOpnum::DestroySession), slice(value)))(out)
+        }
+        NfsArgOp::GetSecurityInfoNoName(value) => tuple((
+            nfs_opnum(NfsOpnum::GetSecurityInfoNoName),
+            get_security_info_no_name_args(value.clone()),
+        ))(out),
+        NfsArgOp::Sequence(value) => {
+            tuple((nfs_opnum(NfsOpnum::Sequence), sequence_args(value.clone())))(out)
+        }
+        NfsArgOp::DestroyClientId(value) => {
+            tuple((nfs_opnum(NfsOpnum::DestroyClientId), be_u64(*value)))(out)
+        }
+        NfsArgOp::ReclaimComplete(value) => tuple((
+            nfs_opnum(NfsOpnum::ReclaimComplete),
+            reclaim_complete_args(value.clone()),
+        ))(out),
+        // The arguments of an unsupported operation are unknown, so only its number is sent.
+        NfsArgOp::Unsupported(opnum) => nfs_opnum(*opnum)(out),
+        NfsArgOp::Illegal => nfs_opnum(NfsOpnum::Illegal)(out),
+    }
+}
+
 #[inline(always)]
 fn nfs_resop<'a, 'b: 'a, W: Write + Seek + 'a>(
     value: &'a NfsResOp<'b>
@@ -303,6 +456,16 @@ fn nfs_resop<'a, 'b: 'a, W: Write + Seek + 'a>(
             tuple((nfs_opnum(NfsOpnum::Access), access_result(value)))(out)
         }
         NfsResOp::Close(ref value) => tuple((nfs_opnum(NfsOpnum::Close), close_result(value)))(out),
+        NfsResOp::Commit(ref value) => {
+            tuple((nfs_opnum(NfsOpnum::Commit), commit_result(value)))(out)
+        }
+        NfsResOp::Create(ref value) => {
+            tuple((nfs_opnum(NfsOpnum::Create), create_result(value)))(out)
+        }
+        NfsResOp::DelegationReturn(ref value) => tuple((
+            nfs_opnum(NfsOpnum::DelegationReturn),
+            delegation_return_result(value),
+        ))(out),
         NfsResOp::GetAttributes(ref value) => tuple((
             nfs_opnum(NfsOpnum::GetAttributes),
             get_attributes_result(value),
@@ -311,10 +474,26 @@ fn nfs_resop<'a, 'b: 'a, W: Write + Seek + 'a>(
             nfs_opnum(NfsOpnum::GetFileHandle),
             get_file_handle_result(value),
         ))(out),
+        NfsResOp::Link(ref value) => tuple((nfs_opnum(NfsOpnum::Link), link_result(value)))(out),
+        NfsResOp::Lock(ref value) => tuple((nfs_opnum(NfsOpnum::Lock), lock_result(value)))(out),
+        NfsResOp::LockTest(ref value) => {
+            tuple((nfs_opnum(NfsOpnum::LockTest), lock_test_result(value)))(out)
+        }
+        NfsResOp::Unlock(ref value) => {
+            tuple((nfs_opnum(NfsOpnum::Unlock), unlock_result(value)))(out)
+        }
         NfsResOp::Lookup(ref value) => {
             tuple((nfs_opnum(NfsOpnum::Lookup), lookup_result(value)))(out)
         }
+        NfsResOp::LookupParent(ref value) => tuple((
+            nfs_opnum(NfsOpnum::LookupParent),
+            lookup_parent_result(value),
+        ))(out),
         NfsResOp::Open(ref value) => tuple((nfs_opnum(NfsOpnum::Open), open_result(value)))(out),
+        NfsResOp::OpenAttributes(ref value) => tuple((
+            nfs_opnum(NfsOpnum::OpenAttributes),
+            open_attributes_result(value),
+        ))(out),
         NfsResOp::PutFileHandle(ref value) => tuple((
             nfs_opnum(NfsOpnum::PutFileHandle),
             put_file_handle_result(value),
@@ -328,6 +507,28 @@ fn nfs_resop<'a, 'b: 'a, W: Write + Seek + 'a>(
             nfs_opnum(NfsOpnum::ReadDirectory),
             read_directory_result(value),
         ))(out),
+        NfsResOp::ReadLink(ref value) => {
+            tuple((nfs_opnum(NfsOpnum::ReadLink), read_link_result(value)))(out)
+        }
+        NfsResOp::Remove(ref value) => {
+            tuple((nfs_opnum(NfsOpnum::Remove), remove_result(value)))(out)
+        }
+        NfsResOp::Rename(ref value) => {
+            tuple((nfs_opnum(NfsOpnum::Rename), rename_result(value)))(out)
+        }
+        NfsResOp::RestoreFileHandle(ref value) => tuple((
+            nfs_opnum(NfsOpnum::RestoreFileHandle),
+            restore_file_handle_result(value),
+        ))(out),
+        NfsResOp::SaveFileHandle(ref value) => tuple((
+            nfs_opnum(NfsOpnum::SaveFileHandle),
+            save_file_handle_result(value),
+        ))(out),
+        NfsResOp::SetAttributes(ref value) => tuple((
+            nfs_opnum(NfsOpnum::SetAttributes),
+            set_attributes_result(value),
+        ))(out),
+        NfsResOp::Write(ref value) => tuple((nfs_opnum(NfsOpnum::Write), write_result(value)))(out),
         NfsResOp::GetSecurityInfo(ref value) => tuple((
             nfs_opnum(NfsOpnum::GetSecurityInfo),
             get_security_info_result(value),
@@ -358,6 +559,8 @@ fn nfs_resop<'a, 'b: 'a, W: Write + Seek + 'a>(
             nfs_opnum(NfsOpnum::ReclaimComplete),
             reclaim_complete_result(value),
         ))(out),
+        NfsResOp::Unsupported(opnum, value) => tuple((nfs_opnum(*opnum), error(Some(*value))))(out),
+        NfsResOp::Illegal(value) => tuple((nfs_opnum(NfsOpnum::Illegal), error(Some(*value))))(out),
     }
 }
 
@@ -438,6 +641,83 @@ fn close_result<'a, 'b: 'a, W: Write + Seek + 'a>(
     }
 }
 
+// Operation 5: COMMIT
+
+#[inline(always)]
+fn commit_args<'a, W: Write + 'a>(value: &'a CommitArgs) -> impl SerializeFn<W> + 'a {
+    tuple((be_u64(value.offset), be_u32(value.count)))
+}
+
+#[inline(always)]
+fn commit_result<'a, W: Write + 'a>(
+    value: &'a Result<Verifier, Error>
+) -> impl SerializeFn<W> + 'a {
+    move |out| match value {
+        Ok(ref value) => tuple((error(None), slice(value)))(out),
+        Err(value) => error(Some(*value))(out),
+    }
+}
+
+// Operation 6: CREATE
+
+#[inline(always)]
+fn spec_data<W: Write>(value: SpecData) -> impl SerializeFn<W> {
+    tuple((be_u32(value.major), be_u32(value.minor)))
+}
+
+#[inline(always)]
+fn create_type<'a, 'b: 'a, W: Write + 'a>(value: &'a CreateType<'b>) -> impl SerializeFn<W> + 'a {
+    move |out| match value {
+        CreateType::Link(data) => tuple((file_type(FileType::Symlink), string(data)))(out),
+        CreateType::BlockDevice(value) => {
+            tuple((file_type(FileType::BlockDevice), spec_data(*value)))(out)
+        }
+        CreateType::CharacterDevice(value) => {
+            tuple((file_type(FileType::CharacterDevice), spec_data(*value)))(out)
+        }
+        CreateType::Socket => file_type(FileType::Socket)(out),
+        CreateType::Fifo => file_type(FileType::Fifo)(out),
+        CreateType::Directory => file_type(FileType::Directory)(out),
+    }
+}
+
+#[inline(always)]
+fn create_args<'a, 'b: 'a, W: Write + Seek + 'a>(
+    value: &'a CreateArgs<'b>
+) -> impl SerializeFn<W> + 'a {
+    tuple((
+        create_type(&value.object_type),
+        string(value.name),
+        file_attributes(&value.attributes),
+    ))
+}
+
+#[inline(always)]
+fn create_result<'a, 'b: 'a, W: Write + 'a>(
+    value: &'a Result<CreateResult<'b>, Error>
+) -> impl SerializeFn<W> + 'a {
+    move |out| match value {
+        Ok(ref value) => tuple((
+            error(None),
+            change_info(&value.change_info),
+            attribute_mask(value.attributes.clone()),
+        ))(out),
+        Err(value) => error(Some(*value))(out),
+    }
+}
+
+// Operation 8: DELEGRETURN
+
+#[inline(always)]
+fn delegation_return_result<'a, W: Write + 'a>(
+    value: &'a Result<(), Error>
+) -> impl SerializeFn<W> + 'a {
+    move |out| match value {
+        Ok(_) => error(None)(out),
+        Err(value) => error(Some(*value))(out),
+    }
+}
+
 // Operation 9: GETATTR
 
 #[inline(always)]
@@ -462,6 +742,134 @@ fn get_file_handle_result<'a, 'b: 'a, W: Write + 'a>(
     }
 }
 
+// Operation 11: LINK
+
+#[inline(always)]
+fn link_result<'a, W: Write + 'a>(
+    value: &'a Result<ChangeInfo, Error>
+) -> impl SerializeFn<W> + 'a {
+    move |out| match value {
+        Ok(ref value) => tuple((error(None), change_info(value)))(out),
+        Err(value) => error(Some(*value))(out),
+    }
+}
+
+// Operation 12: LOCK
+
+#[inline(always)]
+fn lock_type<W: Write>(value: LockType) -> impl SerializeFn<W> {
+    be_u32(value as u32)
+}
+
+#[inline(always)]
+fn lock_owner<'a, 'b: 'a, W: Write + 'a>(value: &'a LockOwner<'b>) -> impl SerializeFn<W> + 'a {
+    state_owner(&value.0)
+}
+
+#[inline(always)]
+fn locker<'a, 'b: 'a, W: Write + 'a>(value: &'a Locker<'b>) -> impl SerializeFn<W> + 'a {
+    move |out| match value {
+        Locker::New(ref value) => tuple((
+            bool_u32(true),
+            be_u32(value.open_sequence_id),
+            state_id(&value.open_state_id),
+            be_u32(value.lock_sequence_id),
+            lock_owner(&value.lock_owner),
+        ))(out),
+        Locker::Existing(ref value) => tuple((
+            bool_u32(false),
+            state_id(&value.lock_state_id),
+            be_u32(value.lock_sequence_id),
+        ))(out),
+    }
+}
+
+#[inline(always)]
+fn lock_args<'a, 'b: 'a, W: Write + 'a>(value: &'a LockArgs<'b>) -> impl SerializeFn<W> + 'a {
+    tuple((
+        lock_type(value.lock_type),
+        bool_u32(value.reclaim),
+        be_u64(value.offset),
+        be_u64(value.length),
+        locker(&value.locker),
+    ))
+}
+
+#[inline(always)]
+fn lock_denied<'a, 'b: 'a, W: Write + 'a>(value: &'a LockDenied<'b>) -> impl SerializeFn<W> + 'a {
+    tuple((
+        be_u64(value.offset),
+        be_u64(value.length),
+        lock_type(value.lock_type),
+        lock_owner(&value.owner),
+    ))
+}
+
+#[inline(always)]
+fn lock_error<'a, 'b: 'a, W: Write + 'a>(value: &'a LockError<'b>) -> impl SerializeFn<W> + 'a {
+    move |out| match value {
+        LockError::Denied(ref value) => {
+            tuple((error(Some(Error::DENIED)), lock_denied(value)))(out)
+        }
+        LockError::Error(value) => error(Some(*value))(out),
+    }
+}
+
+#[inline(always)]
+fn lock_result<'a, 'b: 'a, W: Write + 'a>(
+    value: &'a Result<StateId, LockError<'b>>
+) -> impl SerializeFn<W> + 'a {
+    move |out| match value {
+        Ok(ref value) => tuple((error(None), state_id(value)))(out),
+        Err(ref value) => lock_error(value)(out),
+    }
+}
+
+// Operation 13: LOCKT
+
+#[inline(always)]
+fn lock_test_args<'a, 'b: 'a, W: Write + 'a>(
+    value: &'a LockTestArgs<'b>
+) -> impl SerializeFn<W> + 'a {
+    tuple((
+        lock_type(value.lock_type),
+        be_u64(value.offset),
+        be_u64(value.length),
+        lock_owner(&value.owner),
+    ))
+}
+
+#[inline(always)]
+fn lock_test_result<'a, 'b: 'a, W: Write + 'a>(
+    value: &'a Result<(), LockError<'b>>
+) -> impl SerializeFn<W> + 'a {
+    move |out| match value {
+        Ok(_) => error(None)(out),
+        Err(ref value) => lock_error(value)(out),
+    }
+}
+
+// Operation 14: LOCKU
+
+#[inline(always)]
+fn unlock_args<'a, W: Write + 'a>(value: &'a UnlockArgs) -> impl SerializeFn<W> + 'a {
+    tuple((
+        lock_type(value.lock_type),
+        be_u32(value.sequence_id),
+        state_id(&value.lock_state_id),
+        be_u64(value.offset),
+        be_u64(value.length),
+    ))
+}
+
+#[inline(always)]
+fn unlock_result<'a, W: Write + 'a>(value: &'a Result<StateId, Error>) -> impl SerializeFn<W> + 'a {
+    move |out| match value {
+        Ok(ref value) => tuple((error(None), state_id(value)))(out),
+        Err(value) => error(Some(*value))(out),
+    }
+}
+
 // Operation 15: LOOKUP
 
 #[inline(always)]
@@ -472,6 +880,18 @@ fn lookup_result<'a, W: Write + 'a>(value: &'a Result<(), Error>) -> impl Serial
     }
 }
 
+// Operation 16: LOOKUPP
+
+#[inline(always)]
+fn lookup_parent_result<'a, W: Write + 'a>(
+    value: &'a Result<(), Error>
+) -> impl SerializeFn<W> + 'a {
+    move |out| match value {
+        Ok(_) => error(None)(out),
+        Err(value) => error(Some(*value))(out),
+    }
+}
+
 // Operation 18: OPEN
 
 #[inline(always)]
@@ -498,11 +918,108 @@ fn space_limit<'a, W: Write + 'a>(value: &'a SpaceLimit) -> impl SerializeFn<W>
     }
 }
 
+#[inline(always)]
+fn open_flag_create<'a, 'b: 'a, W: Write + Seek + 'a>(
+    value: &'a OpenFlagCreate<'b>
+) -> impl SerializeFn<W> + 'a {
+    move |out| match value {
+        OpenFlagCreate::Unchecked { attributes } => tuple((
+            be_u32(OpenFlagCreateDiscriminant::Unchecked as u32),
+            file_attributes(attributes),
+        ))(out),
+        OpenFlagCreate::Guarded { attributes } => tuple((
+            be_u32(OpenFlagCreateDiscriminant::Guarded as u32),
+            file_attributes(attributes),
+        ))(out),
+        OpenFlagCreate::Exclusive4_1 {
+            verifier,
+            attributes,
+        } => tuple((
+            be_u32(OpenFlagCreateDiscriminant::Exclusive4_1 as u32),
+            slice(verifier),
+            file_attributes(attributes),
+        ))(out),
+    }
+}
+
+#[inline(always)]
+fn open_flag<'a, 'b: 'a, W: Write + Seek + 'a>(
+    value: &'a OpenFlag<'b>
+) -> impl SerializeFn<W> + 'a {
+    move |out| match value {
+        OpenFlag::NoCreate => be_u32(OpenFlagDiscriminant::NoCreate as u32)(out),
+        OpenFlag::Create(value) => tuple((
+            be_u32(OpenFlagDiscriminant::Create as u32),
+            open_flag_create(value),
+        ))(out),
+    }
+}
+
+#[inline(always)]
+fn share_access_flags<W: Write>(flags: ShareAccessFlags) -> impl SerializeFn<W> {
+    be_u32(flags.bits())
+}
+
+#[inline(always)]
+fn share_deny_flags<W: Write>(flags: ShareDenyFlags) -> impl SerializeFn<W> {
+    be_u32(flags.bits())
+}
+
 #[inline(always)]
 fn open_delegation_type<W: Write>(value: OpenDelegationType) -> impl SerializeFn<W> {
     be_u32(value as u32)
 }
 
+#[inline(always)]
+fn open_claim_discriminant<W: Write>(value: OpenClaimDiscriminant) -> impl SerializeFn<W> {
+    be_u32(value as u32)
+}
+
+#[inline(always)]
+fn open_claim<'a, 'b: 'a, W: Write + 'a>(value: &'a OpenClaim<'b>) -> impl SerializeFn<W> + 'a {
+    move |out| match value {
+        OpenClaim::Null(name) => tuple((
+            open_claim_discriminant(OpenClaimDiscriminant::Null),
+            string(name),
+        ))(out),
+        OpenClaim::Previous(delegation_type) => tuple((
+            open_claim_discriminant(OpenClaimDiscriminant::Previous),
+            open_delegation_type(*delegation_type),
+        ))(out),
+        OpenClaim::DelegateCurrent(delegation_state_id, name) => tuple((
+            open_claim_discriminant(OpenClaimDiscriminant::DelegateCurrent),
+            state_id(delegation_state_id),
+            string(name),
+        ))(out),
+        OpenClaim::DelegatePrevious(name) => tuple((
+            open_claim_discriminant(OpenClaimDiscriminant::DelegatePrevious),
+            string(name),
+        ))(out),
+        OpenClaim::FileHandle => open_claim_discriminant(OpenClaimDiscriminant::FileHandle)(out),
+        OpenClaim::DelegateCurrentFileHandle(delegation_state_id) => tuple((
+            open_claim_discriminant(OpenClaimDiscriminant::DelegateCurrentFileHandle),
+            state_id(delegation_state_id),
+        ))(out),
+        OpenClaim::DelegatePreviousFileHandle => {
+            open_claim_discriminant(OpenClaimDiscriminant::DelegatePreviousFileHandle)(out)
+        }
+    }
+}
+
+#[inline(always)]
+fn open_args<'a, 'b: 'a, W: Write + Seek + 'a>(
+    value: &'a OpenArgs<'b>
+) -> impl SerializeFn<W> + 'a {
+    tuple((
+        be_u32(value.sequence_id),
+        share_access_flags(value.share_access),
+        share_deny_flags(value.share_deny),
+        open_owner(&value.owner),
+        open_flag(&value.how),
+        open_claim(&value.claim),
+    ))
+}
+
 #[inline(always)]
 fn open_read_delegation<'a, 'b: 'a, W: Write + 'a>(
     value: &'a OpenReadDelegation<'b>
@@ -538,7 +1055,41 @@ fn open_none_delegation<'a, W: Write + 'a>(
     value: &'a OpenNoneDelegation
 ) -> impl SerializeFn<W> + 'a {
     move |out| match value {
-        _ => todo!(),
+        OpenNoneDelegation::NotWanted => {
+            open_none_delegation_discriminant(OpenNoneDelegationDiscriminant::NotWanted)(out)
+        }
+        OpenNoneDelegation::Contention {
+            server_will_push_deleg,
+        } => tuple((
+            open_none_delegation_discriminant(OpenNoneDelegationDiscriminant::Contention),
+            bool_u32(*server_will_push_deleg),
+        ))(out),
+        OpenNoneDelegation::Resource {
+            server_will_signal_avail,
+        } => tuple((
+            open_none_delegation_discriminant(OpenNoneDelegationDiscriminant::Resource),
+            bool_u32(*server_will_signal_avail),
+        ))(out),
+        OpenNoneDelegation::NotSupportedFileType => open_none_delegation_discriminant(
+            OpenNoneDelegationDiscriminant::NotSupportedFileType,
+        )(out),
+        OpenNoneDelegation::WriteDelegationNotSupportedFileType => {
+            open_none_delegation_discriminant(
+                OpenNoneDelegationDiscriminant::WriteDelegationNotSupportedFileType,
+            )(out)
+        }
+        OpenNoneDelegation::NotSupportedUpgrade => open_none_delegation_discriminant(
+            OpenNoneDelegationDiscriminant::NotSupportedUpgrade,
+        )(out),
+        OpenNoneDelegation::NotSupportedDowngrade => open_none_delegation_discriminant(
+            OpenNoneDelegationDiscriminant::NotSupportedDowngrade,
+        )(out),
+        OpenNoneDelegation::Cancelled => {
+            open_none_delegation_discriminant(OpenNoneDelegationDiscriminant::Cancelled)(out)
+        }
+        OpenNoneDelegation::IsDirectory => {
+            open_none_delegation_discriminant(OpenNoneDelegationDiscriminant::IsDirectory)(out)
+        }
     }
 }
 
@@ -591,6 +1142,25 @@ fn open_result_ok<'a, 'b: 'a, W: Write + 'a>(
     ))
 }
 
+// Operation 19: OPENATTR
+
+#[inline(always)]
+fn open_attributes_args<'a, W: Write + 'a>(
+    value: &'a OpenAttributesArgs
+) -> impl SerializeFn<W> + 'a {
+    bool_u32(value.create_directory)
+}
+
+#[inline(always)]
+fn open_attributes_result<'a, W: Write + 'a>(
+    value: &'a Result<(), Error>
+) -> impl SerializeFn<W> + 'a {
+    move |out| match value {
+        Ok(_) => error(None)(out),
+        Err(value) => error(Some(*value))(out),
+    }
+}
+
 // Operation 22: PUTFH
 
 #[inline(always)]
@@ -643,48 +1213,130 @@ fn read_result_ok<'a, 'b: 'a, W: Write + 'a>(
     tuple((bool_u32(value.eof), variable_length_opaque(&value.data)))
 }
 
-// Operation 26: READDIR
+// Operation 26: READDIR
+
+#[inline(always)]
+fn read_directory_args<'a, 'b: 'a, W: Write + 'a>(
+    value: &'a ReadDirectoryArgs<'b>
+) -> impl SerializeFn<W> + 'a {
+    tuple((
+        be_u64(value.cookie),
+        slice(value.cookie_verifier),
+        be_u32(value.dir_count),
+        be_u32(value.max_count),
+        attribute_mask(value.attributes.clone()),
+    ))
+}
+
+#[inline(always)]
+fn entry<'a, 'b: 'a, W: Write + Seek + 'a>(value: &'a Entry<'b>) -> impl SerializeFn<W> + 'a {
+    tuple((
+        be_u64(value.cookie),
+        string(&value.name),
+        file_attributes(&value.attributes),
+    ))
+}
+
+#[inline(always)]
+fn directory_list<'a, 'b: 'a, W: Write + Seek + 'a>(
+    value: &'a DirectoryList<'b>
+) -> impl SerializeFn<W> + 'a {
+    tuple((
+        many_ref(value.entries.iter(), |value| {
+            tuple((bool_u32(true), entry(value)))
+        }),
+        bool_u32(false),
+        bool_u32(value.eof),
+    ))
+}
+
+#[inline(always)]
+fn read_directory_result<'a, 'b: 'a, W: Write + Seek + 'a>(
+    value: &'a Result<ReadDirectoryResult<'b>, Error>
+) -> impl SerializeFn<W> + 'a {
+    move |out| match value {
+        Ok(ref value) => tuple((error(None), read_directory_result_ok(value)))(out),
+        Err(value) => error(Some(*value))(out),
+    }
+}
+
+#[inline(always)]
+fn read_directory_result_ok<'a, 'b: 'a, W: Write + Seek + 'a>(
+    value: &'a ReadDirectoryResult<'b>
+) -> impl SerializeFn<W> + 'a {
+    tuple((
+        slice(&value.cookie_verf),
+        directory_list(&value.directory_list),
+    ))
+}
+
+// Operation 27: READLINK
+
+#[inline(always)]
+fn read_link_result<'a, W: Write + 'a>(
+    value: &'a Result<Cow<'_, str>, Error>
+) -> impl SerializeFn<W> + 'a {
+    move |out| match value {
+        Ok(ref value) => tuple((error(None), string(value)))(out),
+        Err(value) => error(Some(*value))(out),
+    }
+}
+
+// Operation 28: REMOVE
+
+#[inline(always)]
+fn remove_result<'a, W: Write + 'a>(
+    value: &'a Result<ChangeInfo, Error>
+) -> impl SerializeFn<W> + 'a {
+    move |out| match value {
+        Ok(ref value) => tuple((error(None), change_info(value)))(out),
+        Err(value) => error(Some(*value))(out),
+    }
+}
+
+// Operation 29: RENAME
 
 #[inline(always)]
-fn entry<'a, 'b: 'a, W: Write + Seek + 'a>(value: &'a Entry<'b>) -> impl SerializeFn<W> + 'a {
-    tuple((
-        be_u64(value.cookie),
-        string(&value.name),
-        file_attributes(&value.attributes),
-    ))
+fn rename_args<'a, 'b: 'a, W: Write + 'a>(value: &'a RenameArgs<'b>) -> impl SerializeFn<W> + 'a {
+    tuple((string(value.old_name), string(value.new_name)))
 }
 
 #[inline(always)]
-fn directory_list<'a, 'b: 'a, W: Write + Seek + 'a>(
-    value: &'a DirectoryList<'b>
+fn rename_result<'a, W: Write + 'a>(
+    value: &'a Result<RenameResult, Error>
 ) -> impl SerializeFn<W> + 'a {
-    tuple((
-        many_ref(value.entries.iter(), |value| {
-            tuple((bool_u32(true), entry(value)))
-        }),
-        bool_u32(false),
-        bool_u32(value.eof),
-    ))
+    move |out| match value {
+        Ok(ref value) => tuple((
+            error(None),
+            change_info(&value.source_change_info),
+            change_info(&value.target_change_info),
+        ))(out),
+        Err(value) => error(Some(*value))(out),
+    }
 }
 
+// Operation 31: RESTOREFH
+
 #[inline(always)]
-fn read_directory_result<'a, 'b: 'a, W: Write + Seek + 'a>(
-    value: &'a Result<ReadDirectoryResult<'b>, Error>
+fn restore_file_handle_result<'a, W: Write + 'a>(
+    value: &'a Result<(), Error>
 ) -> impl SerializeFn<W> + 'a {
     move |out| match value {
-        Ok(ref value) => tuple((error(None), read_directory_result_ok(value)))(out),
+        Ok(_) => error(None)(out),
         Err(value) => error(Some(*value))(out),
     }
 }
 
+// Operation 32: SAVEFH
+
 #[inline(always)]
-fn read_directory_result_ok<'a, 'b: 'a, W: Write + Seek + 'a>(
-    value: &'a ReadDirectoryResult<'b>
+fn save_file_handle_result<'a, W: Write + 'a>(
+    value: &'a Result<(), Error>
 ) -> impl SerializeFn<W> + 'a {
-    tuple((
-        slice(&value.cookie_verf),
-        directory_list(&value.directory_list),
-    ))
+    move |out| match value {
+        Ok(_) => error(None)(out),
+        Err(value) => error(Some(*value))(out),
+    }
 }
 
 // Operation 33: SECINFO
@@ -735,6 +1387,7 @@ fn get_security_info_result<'a, 'b: 'a, W: Write + 'a>(
         GetSecurityInfoResult::Ok(value) => {
             tuple((error(None), get_security_info_result_ok(value)))(out)
         }
+        GetSecurityInfoResult::Err(value) => error(Some(*value))(out),
     }
 }
 
@@ -745,6 +1398,80 @@ fn get_security_info_result_ok<'a, 'b: 'a, W: Write + 'a>(
     variable_length_array(&value.0, get_security_info)
 }
 
+// Operation 34: SETATTR
+
+#[inline(always)]
+fn set_attributes_args<'a, 'b: 'a, W: Write + Seek + 'a>(
+    value: &'a SetAttributesArgs<'b>
+) -> impl SerializeFn<W> + 'a {
+    tuple((
+        state_id(&value.state_id),
+        file_attributes(&value.attributes),
+    ))
+}
+
+#[inline(always)]
+fn set_attributes_result<'a, 'b: 'a, W: Write + 'a>(
+    value: &'a Result<AttributeMask<'b>, Error>
+) -> impl SerializeFn<W> + 'a {
+    move |out| match value {
+        Ok(ref value) => tuple((error(None), attribute_mask(value.clone())))(out),
+        Err(value) => tuple((error(Some(*value)), be_u32(0)))(out),
+    }
+}
+
+// Operation 38: WRITE
+
+#[inline(always)]
+fn stable_how<W: Write>(value: StableHow) -> impl SerializeFn<W> {
+    be_u32(value as u32)
+}
+
+#[inline(always)]
+fn write_args<'a, 'b: 'a, W: Write + 'a>(value: &'a WriteArgs<'b>) -> impl SerializeFn<W> + 'a {
+    tuple((
+        state_id(&value.state_id),
+        be_u64(value.offset),
+        stable_how(value.stable),
+        variable_length_opaque(&value.data),
+    ))
+}
+
+#[inline(always)]
+fn write_result<'a, W: Write + 'a>(
+    value: &'a Result<WriteResult, Error>
+) -> impl SerializeFn<W> + 'a {
+    move |out| match value {
+        Ok(ref value) => tuple((
+            error(None),
+            be_u32(value.count),
+            stable_how(value.committed),
+            slice(value.verifier),
+        ))(out),
+        Err(value) => error(Some(*value))(out),
+    }
+}
+
+// Operation 40
+
+#[inline(always)]
+fn callback_sec_parms<'a, 'b: 'a, W: Write + 'a>(
+    value: &'a CallbackSecParms<'b>
+) -> impl SerializeFn<W> + 'a {
+    move |out| match value {
+        CallbackSecParms::AuthNone => auth_flavor(AuthFlavor::AuthNone)(out),
+        CallbackSecParms::AuthSys(parms) => {
+            tuple((auth_flavor(AuthFlavor::AuthSys), auth_sys_parms(parms)))(out)
+        }
+        CallbackSecParms::RpcSecGss(handles) => tuple((
+            auth_flavor(AuthFlavor::RpcSecGss),
+            rpc_gss_svc(handles.service),
+            gss_handle(&handles.handle_from_server),
+            gss_handle(&handles.handle_from_client),
+        ))(out),
+    }
+}
+
 // Operation 42: EXCHANGE_ID
 
 #[inline(always)]
@@ -752,6 +1479,18 @@ fn exchange_id_flags<W: Write>(flags: ExchangeIdFlags) -> impl SerializeFn<W> {
     be_u32(flags.bits() as u32)
 }
 
+#[inline(always)]
+fn exchange_id_args<'a, 'b: 'a, W: Write + 'a>(
+    value: &'a ExchangeIdArgs<'b>
+) -> impl SerializeFn<W> + 'a {
+    tuple((
+        client_owner(&value.clientowner),
+        exchange_id_flags(value.flags),
+        state_protect_args(&value.state_protect),
+        variable_length_array(&value.client_impl_id, nfs_impl_id),
+    ))
+}
+
 #[inline(always)]
 fn exchange_id_result<'a, 'b: 'a, W: Write + 'a>(
     value: &'a Result<ExchangeIdResult<'b>, Error>
@@ -797,6 +1536,21 @@ fn create_session_flags<W: Write>(flags: CreateSessionFlags) -> impl SerializeFn
     be_u32(flags.bits() as u32)
 }
 
+#[inline(always)]
+fn create_session_args<'a, 'b: 'a, W: Write + 'a>(
+    value: &'a CreateSessionArgs<'b>
+) -> impl SerializeFn<W> + 'a {
+    tuple((
+        be_u64(value.client_id),
+        be_u32(value.sequence_id),
+        create_session_flags(value.flags),
+        channel_attributes(value.fore_channel_attributes),
+        channel_attributes(value.back_channel_attributes),
+        be_u32(value.cb_program),
+        variable_length_array(&value.sec_parms, callback_sec_parms),
+    ))
+}
+
 #[inline(always)]
 fn create_session_result<'a, W: Write + 'a>(
     value: &'a Result<CreateSessionResult, Error>
@@ -918,6 +1672,145 @@ fn reclaim_complete_result<'a, W: Write + 'a>(
     }
 }
 
+// Callbacks
+
+#[inline(always)]
+fn callback_opnum<W: Write>(value: CallbackOpnum) -> impl SerializeFn<W> {
+    be_u32(value as u32)
+}
+
+#[inline(always)]
+fn callback_compound_args<'a, 'b: 'a, W: Write + 'a>(
+    value: &'a CallbackCompoundArgs<'b>
+) -> impl SerializeFn<W> + 'a {
+    tuple((
+        string(&value.tag),
+        be_u32(value.minorversion),
+        be_u32(value.callback_ident),
+        variable_length_array(&value.argarray, callback_argop),
+    ))
+}
+
+#[inline(always)]
+fn callback_argop<'a, 'b: 'a, W: Write + 'a>(
+    value: &'a CallbackArgOp<'b>
+) -> impl SerializeFn<W> + 'a {
+    move |out| match value {
+        CallbackArgOp::Recall(ref value) => tuple((
+            callback_opnum(CallbackOpnum::Recall),
+            callback_recall_args(value),
+        ))(out),
+        CallbackArgOp::Sequence(ref value) => tuple((
+            callback_opnum(CallbackOpnum::Sequence),
+            callback_sequence_args(value),
+        ))(out),
+        CallbackArgOp::Unsupported(opnum) => callback_opnum(*opnum)(out),
+        CallbackArgOp::Illegal => callback_opnum(CallbackOpnum::Illegal)(out),
+    }
+}
+
+#[inline(always)]
+fn callback_compound_result<'a, 'b: 'a, W: Write + 'a>(
+    value: &'a CallbackCompoundResult<'b>
+) -> impl SerializeFn<W> + 'a {
+    tuple((
+        error(value.error),
+        string(&value.tag),
+        variable_length_array(&value.resarray, callback_resop),
+    ))
+}
+
+#[inline(always)]
+fn callback_resop<'a, W: Write + 'a>(value: &'a CallbackResOp) -> impl SerializeFn<W> + 'a {
+    move |out| match value {
+        CallbackResOp::Recall(ref value) => tuple((
+            callback_opnum(CallbackOpnum::Recall),
+            callback_recall_result(value),
+        ))(out),
+        CallbackResOp::Sequence(ref value) => tuple((
+            callback_opnum(CallbackOpnum::Sequence),
+            callback_sequence_result(value),
+        ))(out),
+        CallbackResOp::Unsupported(opnum, value) => {
+            tuple((callback_opnum(*opnum), error(Some(*value))))(out)
+        }
+        CallbackResOp::Illegal(value) => {
+            tuple((callback_opnum(CallbackOpnum::Illegal), error(Some(*value))))(out)
+        }
+    }
+}
+
+// Callback operation 4: CB_RECALL
+
+#[inline(always)]
+fn callback_recall_args<'a, 'b: 'a, W: Write + 'a>(
+    value: &'a CallbackRecallArgs<'b>
+) -> impl SerializeFn<W> + 'a {
+    tuple((
+        state_id(&value.state_id),
+        bool_u32(value.truncate),
+        file_handle(&value.file_handle),
+    ))
+}
+
+#[inline(always)]
+fn callback_recall_result<'a, W: Write + 'a>(
+    value: &'a Result<(), Error>
+) -> impl SerializeFn<W> + 'a {
+    move |out| match value {
+        Ok(_) => error(None)(out),
+        Err(value) => error(Some(*value))(out),
+    }
+}
+
+// Callback operation 11: CB_SEQUENCE
+
+#[inline(always)]
+fn referring_call<'a, W: Write + 'a>(value: &'a ReferringCall) -> impl SerializeFn<W> + 'a {
+    tuple((be_u32(value.sequence_id), be_u32(value.slot_id)))
+}
+
+#[inline(always)]
+fn referring_call_list<'a, W: Write + 'a>(
+    value: &'a ReferringCallList
+) -> impl SerializeFn<W> + 'a {
+    tuple((
+        slice(value.session_id),
+        variable_length_array(&value.referring_calls, referring_call),
+    ))
+}
+
+#[inline(always)]
+fn callback_sequence_args<'a, W: Write + 'a>(
+    value: &'a CallbackSequenceArgs
+) -> impl SerializeFn<W> + 'a {
+    tuple((
+        slice(value.session_id),
+        be_u32(value.sequence_id),
+        be_u32(value.slot_id),
+        be_u32(value.highest_slot_id),
+        bool_u32(value.cache_this),
+        variable_length_array(&value.referring_call_lists, referring_call_list),
+    ))
+}
+
+#[inline(always)]
+fn callback_sequence_result<'a, W: Write + 'a>(
+    value: &'a Result<CallbackSequenceResult, Error>
+) -> impl SerializeFn<W> + 'a {
+    move |out| match value {
+        Ok(ref value) => tuple((
+            error(None),
+            slice(value.session_id),
+            be_u32(value.sequence_id),
+            be_u32(value.slot_id),
+            be_u32(value.highest_slot_id),
+            be_u32(value.target_highest_slot_id),
+        ))(out),
+        Err(value) => error(Some(*value))(out),
+    }
+}
+
 //
 
 #[inline(always)]
@@ -931,8 +1824,60 @@ fn message_type<W: Write>(value: MessageType) -> impl SerializeFn<W> {
 }
 
 #[inline(always)]
-pub fn call<'a, 'b: 'a, W: Write + 'a>(value: &'a Call<'b>) -> impl SerializeFn<W> + 'a {
-    move |out| Ok(todo!())
+pub fn call<'a, 'b: 'a, W: Write + Seek + 'a>(value: &'a Call<'b>) -> impl SerializeFn<W> + 'a {
+    let procedure_number = match value.procedure {
+        ProcedureCall::Null => ProcedureNumber::Null,
+        ProcedureCall::Compound(_) => ProcedureNumber::Compound,
+    };
+    tuple((
+        be_u32(2),
+        be_u32(100003),
+        be_u32(4),
+        be_u32(procedure_number as u32),
+        opaque_auth(&value.cred),
+        opaque_auth(&value.verf),
+        procedure_call(&value.procedure),
+    ))
+}
+
+#[inline(always)]
+fn procedure_call<'a, 'b: 'a, W: Write + Seek + 'a>(
+    value: &'a ProcedureCall<'b>
+) -> impl SerializeFn<W> + 'a {
+    move |out| match value {
+        ProcedureCall::Null => Ok(out),
+        ProcedureCall::Compound(value) => compound_args(value)(out),
+    }
+}
+
+/// Encodes a CB_COMPOUND call to the callback program of a client.
+#[inline(always)]
+pub fn callback_call<'a, 'b: 'a, W: Write + 'a>(
+    value: &'a CallbackCall<'b>
+) -> impl SerializeFn<W> + 'a {
+    tuple((
+        be_u32(2),
+        be_u32(value.program),
+        be_u32(1),
+        be_u32(ProcedureNumber::Compound as u32),
+        opaque_auth(&value.cred),
+        opaque_auth(&value.verf),
+        callback_compound_args(&value.args),
+    ))
+}
+
+/// Encodes the successful reply of a client to a CB_COMPOUND.
+#[inline(always)]
+pub fn callback_reply<'a, 'b: 'a, W: Write + 'a>(
+    value: &'a CallbackCompoundResult<'b>
+) -> impl SerializeFn<W> + 'a {
+    tuple((
+        be_u32(0),
+        auth_flavor(AuthFlavor::AuthNone),
+        be_u32(0),
+        accept_status(AcceptStatus::Success),
+        callback_compound_result(value),
+    ))
 }
 
 #[inline(always)]
@@ -963,11 +1908,19 @@ fn accepted_reply_body<'a, 'b: 'a, W: Write + Seek + 'a>(
         AcceptedReplyBody::Success(ref value) => {
             tuple((accept_status(AcceptStatus::Success), procedure_reply(value)))(out)
         }
-        AcceptedReplyBody::ProgramUnavailable => todo!(),
-        AcceptedReplyBody::ProgramMismatch { low, high } => todo!(),
-        AcceptedReplyBody::ProcedureUnavailable => todo!(),
-        AcceptedReplyBody::GarbageArgs => todo!(),
-        AcceptedReplyBody::SystemError => todo!(),
+        AcceptedReplyBody::ProgramUnavailable => {
+            accept_status(AcceptStatus::ProgramUnavailable)(out)
+        }
+        AcceptedReplyBody::ProgramMismatch { low, high } => tuple((
+            accept_status(AcceptStatus::ProgramMismatch),
+            be_u32(*low),
+            be_u32(*high),
+        ))(out),
+        AcceptedReplyBody::ProcedureUnavailable => {
+            accept_status(AcceptStatus::ProcedureUnavailable)(out)
+        }
+        AcceptedReplyBody::GarbageArgs => accept_status(AcceptStatus::GarbageArgs)(out),
+        AcceptedReplyBody::SystemError => accept_status(AcceptStatus::SystemError)(out),
     }
 }
 
@@ -983,7 +1936,22 @@ fn procedure_reply<'a, 'b: 'a, W: Write + Seek + 'a>(
 
 #[inline(always)]
 fn rejected_reply<W: Write>(value: &RejectedReply) -> impl SerializeFn<W> {
-    move |out| Ok(todo!())
+    let value = *value;
+    move |out| match value {
+        RejectedReply::RpcMismatch { low, high } => tuple((
+            be_u32(RejectStatus::RpcMismatch as u32),
+            be_u32(low),
+            be_u32(high),
+        ))(out),
+        RejectedReply::AuthError { stat } => {
+            tuple((be_u32(RejectStatus::AuthError as u32), auth_status(stat)))(out)
+        }
+    }
+}
+
+#[inline(always)]
+fn auth_status<W: Write>(value: AuthStatus) -> impl SerializeFn<W> {
+    be_u32(value as u32)
 }
 
 #[inline(always)]
@@ -994,6 +1962,19 @@ fn opaque_auth<'a, 'b: 'a, W: Write + 'a>(value: &'a OpaqueAuth<'b>) -> impl Ser
     ))
 }
 
+#[inline(always)]
+pub fn auth_sys_parms<'a, 'b: 'a, W: Write + 'a>(
+    value: &'a AuthSysParms<'b>
+) -> impl SerializeFn<W> + 'a {
+    tuple((
+        be_u32(value.stamp),
+        string(&value.machine_name),
+        be_u32(value.uid),
+        be_u32(value.gid),
+        variable_length_array(&value.gids, |gid| be_u32(*gid)),
+    ))
+}
+
 #[inline(always)]
 fn auth_flavor<W: Write>(value: AuthFlavor) -> impl SerializeFn<W> {
     be_u32(value as u32)
@@ -1187,9 +2168,76 @@ mod tests {
             result,
             &[
                 0x00, 0x00, 0x00, 0x01, 0b00000000, 0b00000000, 0b00000000, 0b00011000, 0x00, 0x00,
-                0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x39, 0x00, 0x00, 0x00, 0x00,
-                0x00, 0x01, 0xE2, 0x40
+                0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0xE2, 0x40, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x05, 0x39
+            ],
+        );
+    }
+
+    #[test]
+    pub fn test_file_attributes_padding() {
+        let value = vec![
+            AttributeValue::OwnerGroup("wheel".into()),
+            AttributeValue::Owner("abc".into()),
+        ];
+        let mut buffer = [0u8; 64];
+        let result = serialize!(file_attributes(&value), buffer);
+        assert_eq!(
+            result,
+            &[
+                0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0b00110000, 0, 0, 0, 20, 0, 0, 0, 3, b'a', b'b',
+                b'c', 0, 0, 0, 0, 5, b'w', b'h', b'e', b'e', b'l', 0, 0, 0
+            ],
+        );
+    }
+
+    #[test]
+    pub fn test_file_attributes_mask_words() {
+        let value = vec![AttributeValue::ModeSetMasked(ModeMasked {
+            mode: 0o644,
+            mask: 0o777,
+        })];
+        let mut buffer = [0u8; 64];
+        let result = serialize!(file_attributes(&value), buffer);
+        assert_eq!(
+            result,
+            &[
+                0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x04, 0, 0, 0, 0, 8, 0, 0, 0x01, 0xA4, 0,
+                0, 0x01, 0xFF
             ],
         );
     }
+
+    #[test]
+    fn test_lock_result_denied() {
+        let value = Err(LockError::Denied(LockDenied {
+            offset: 16,
+            length: u64::MAX,
+            lock_type: LockType::Write,
+            owner: LockOwner(StateOwner {
+                client_id: 7,
+                owner: b"own".as_slice().into(),
+            }),
+        }));
+        let mut buffer = [0u8; 64];
+        let result = serialize!(lock_result(&value), buffer);
+        assert_eq!(
+            result,
+            &[
+                0x00, 0x00, 0x27, 0x1A, 0, 0, 0, 0, 0, 0, 0, 16, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+                0xFF, 0xFF, 0xFF, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 7, 0, 0, 0, 3, b'o', b'w', b'n',
+                0
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rejected_reply() {
+        let value = Reply::Rejected(RejectedReply::AuthError {
+            stat: AuthStatus::AUTH_BADCRED,
+        });
+        let mut buffer = [0u8; 16];
+        let result = serialize!(reply(&value), buffer);
+        assert_eq!(result, &[0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1]);
+    }
 }
@@ -14,11 +14,11 @@ use std::borrow::Cow;
 #[derive(Debug, Clone, PartialEq, Eq, From)]
 #[from(forward)]
 pub struct AuthSysParms<'a> {
-    stamp: u32,
-    machine_name: Cow<'a, str>,
-    uid: u32,
-    gid: u32,
-    gids: Vec<u32>,
+    pub stamp: u32,
+    pub machine_name: Cow<'a, str>,
+    pub uid: u32,
+    pub gid: u32,
+    pub gids: Vec<u32>,
 }
 
 //
@@ -166,24 +166,53 @@ pub enum Attribute {
     LeaseTime = 10,
     ReadDirAttributeError = 11,
     AclSupport = 13,
+    Archive = 14,
+    CanSetTime = 15,
     CaseInsensitive = 16,
     CasePreserving = 17,
+    ChownRestricted = 18,
     FileHandle = 19,
     FileId = 20,
+    FilesAvailable = 21,
+    FilesFree = 22,
+    FilesTotal = 23,
+    Hidden = 25,
+    Homogeneous = 26,
     MaxFileSize = 27,
+    MaxLink = 28,
+    MaxName = 29,
     MaxRead = 30,
     MaxWrite = 31,
+    MimeType = 32,
     Mode = 33,
+    NoTruncate = 34,
     NumberOfLinks = 35,
-    //Owner = 36,
-    //OwnerGroup = 37,
-    //RawDevice = 41,
-    //SpaceUsed = 45,
-    //TimeAccess = 47,
-    //TimeMetadata = 52,
-    //TimeModify = 53,
+    Owner = 36,
+    OwnerGroup = 37,
+    QuotaAvailableHard = 38,
+    QuotaAvailableSoft = 39,
+    QuotaUsed = 40,
+    RawDevice = 41,
+    SpaceAvailable = 42,
+    SpaceFree = 43,
+    SpaceTotal = 44,
+    SpaceUsed = 45,
+    System = 46,
+    TimeAccess = 47,
+    TimeAccessSet = 48,
+    TimeBackup = 49,
+    TimeCreate = 50,
+    TimeDelta = 51,
+    TimeMetadata = 52,
+    TimeModify = 53,
+    TimeModifySet = 54,
     MountedOnFileId = 55,
+    DirectoryNotificationDelay = 56,
+    DirectoryEntryNotificationDelay = 57,
+    ChangePolicy = 60,
+    ModeSetMasked = 74,
     SupportedAttributesExclusiveCreate = 75,
+    FileSystemCharsetCapabilities = 76,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -201,17 +230,89 @@ pub enum AttributeValue<'a> {
     LeaseTime(u32),
     ReadDirAttributeError, // enum
     AclSupport(AclSupportFlags),
+    Archive(bool),
+    CanSetTime(bool),
     CaseInsensitive(bool),
     CasePreserving(bool),
+    ChownRestricted(bool),
     FileHandle(FileHandle<'a>),
     FileId(u64),
+    FilesAvailable(u64),
+    FilesFree(u64),
+    FilesTotal(u64),
+    Hidden(bool),
+    Homogeneous(bool),
     MaxFileSize(u64),
+    MaxLink(u32),
+    MaxName(u32),
     MaxRead(u64),
     MaxWrite(u64),
+    MimeType(Cow<'a, str>),
     Mode(Mode),
+    NoTruncate(bool),
     NumberOfLinks(u32),
+    Owner(Cow<'a, str>),
+    OwnerGroup(Cow<'a, str>),
+    QuotaAvailableHard(u64),
+    QuotaAvailableSoft(u64),
+    QuotaUsed(u64),
+    RawDevice(SpecData),
+    SpaceAvailable(u64),
+    SpaceFree(u64),
+    SpaceTotal(u64),
+    SpaceUsed(u64),
+    System(bool),
+    TimeAccess(Time),
+    TimeAccessSet(SetTime),
+    TimeBackup(Time),
+    TimeCreate(Time),
+    TimeDelta(Time),
+    TimeMetadata(Time),
+    TimeModify(Time),
+    TimeModifySet(SetTime),
     MountedOnFileId(u64),
+    DirectoryNotificationDelay(Time),
+    DirectoryEntryNotificationDelay(Time),
+    ChangePolicy(ChangePolicy),
+    ModeSetMasked(ModeMasked),
     SupportedAttributesExclusiveCreate(Cow<'a, [Attribute]>),
+    FileSystemCharsetCapabilities(FileSystemCharsetCapabilities),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+pub enum SetTimeDiscriminant {
+    ServerTime = 0,
+    ClientTime = 1,
+}
+
+/// How SETATTR sets a time, either to the time on the server or to one from the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetTime {
+    ServerTime,
+    ClientTime(Time),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, From)]
+#[from(forward)]
+pub struct ChangePolicy {
+    pub major: u64,
+    pub minor: u64,
+}
+
+/// Sets the bits of the mode that are in the mask and leaves the others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, From)]
+#[from(forward)]
+pub struct ModeMasked {
+    pub mode: Mode,
+    pub mask: Mode,
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub struct FileSystemCharsetCapabilities: u32 {
+        const CONTAINS_NON_UTF8 = 0x00000001;
+        const ALLOWS_ONLY_UTF8  = 0x00000002;
+    }
 }
 
 pub type ClientId = u64;
@@ -272,6 +373,9 @@ pub struct StateOwner<'a> {
 #[derive(Debug, Clone, PartialEq, Eq, From)]
 pub struct OpenOwner<'a>(StateOwner<'a>);
 
+#[derive(Debug, Clone, PartialEq, Eq, From)]
+pub struct LockOwner<'a>(pub StateOwner<'a>);
+
 #[derive(Debug, Clone, PartialEq, Eq, From)]
 #[from(forward)]
 pub struct StateId {
@@ -318,21 +422,21 @@ pub enum FileType {
 pub enum NfsOpnum {
     Access = 3,
     Close = 4,
-    COMMIT = 5,
-    CREATE = 6,
+    Commit = 5,
+    Create = 6,
     DELEGPURGE = 7,
-    DELEGRETURN = 8,
+    DelegationReturn = 8,
     GetAttributes = 9,
     GetFileHandle = 10,
-    LINK = 11,
-    LOCK = 12,
-    LOCKT = 13,
-    LOCKU = 14,
+    Link = 11,
+    Lock = 12,
+    LockTest = 13,
+    Unlock = 14,
     Lookup = 15,
-    LOOKUPP = 16,
+    LookupParent = 16,
     NVERIFY = 17,
     Open = 18,
-    OPENATTR = 19,
+    OpenAttributes = 19,
     OPEN_CONFIRM = 20,
     OPEN_DOWNGRADE = 21,
     PutFileHandle = 22,
@@ -340,18 +444,18 @@ pub enum NfsOpnum {
     PutRootFileHandle = 24,
     Read = 25,
     ReadDirectory = 26,
-    READLINK = 27,
-    REMOVE = 28,
-    RENAME = 29,
+    ReadLink = 27,
+    Remove = 28,
+    Rename = 29,
     RENEW = 30, /* Mandatory not-to-implement */
-    RESTOREFH = 31,
-    SAVEFH = 32,
+    RestoreFileHandle = 31,
+    SaveFileHandle = 32,
     GetSecurityInfo = 33,
-    SETATTR = 34,
+    SetAttributes = 34,
     SETCLIENTID = 35,         /* Mandatory not-to-implement */
     SETCLIENTID_CONFIRM = 36, /* Mandatory not-to-implement */
     VERIFY = 37,
-    WRITE = 38,
+    Write = 38,
     RELEASE_LOCKOWNER = 39, /* Mandatory not-to-implement */
     BACKCHANNEL_CTL = 40,
     BIND_CONN_TO_SESSION = 41,
@@ -372,28 +476,28 @@ pub enum NfsOpnum {
     WANT_DELEGATION = 56,
     DestroyClientId = 57,
     ReclaimComplete = 58,
-    ILLEGAL = 10044,
+    Illegal = 10044,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum NfsArgOp<'a> {
     Access(AccessFlags),
     Close(CloseArgs),
-    //COMMIT(COMMIT4args),
-    //CREATE(CREATE4args),
+    Commit(CommitArgs),
+    Create(CreateArgs<'a>),
     //DELEGPURGE(DELEGPURGE4args),
-    //DELEGRETURN(DELEGRETURN4args),
+    DelegationReturn(StateId),
     GetAttributes(AttributeMask<'a>),
     GetFileHandle,
-    //LINK(LINK4args),
-    //LOCK(LOCK4args),
-    //LOCKT(LOCKT4args),
-    //LOCKU(LOCKU4args),
+    Link(&'a str),
+    Lock(LockArgs<'a>),
+    LockTest(LockTestArgs<'a>),
+    Unlock(UnlockArgs),
     Lookup(&'a str),
-    //LOOKUPP,
+    LookupParent,
     //NVERIFY(NVERIFY4args),
     Open(OpenArgs<'a>),
-    //OPENATTR(OPENATTR4args),
+    OpenAttributes(OpenAttributesArgs),
     //OPEN_CONFIRM(OPEN_CONFIRM4args),
     //OPEN_DOWNGRADE(OPEN_DOWNGRADE4args),
     PutFileHandle(FileHandle<'a>),
@@ -401,18 +505,18 @@ pub enum NfsArgOp<'a> {
     PutRootFileHandle,
     Read(ReadArgs),
     ReadDirectory(ReadDirectoryArgs<'a>),
-    //READLINK,
-    //REMOVE(REMOVE4args),
-    //RENAME(RENAME4args),
+    ReadLink,
+    Remove(&'a str),
+    Rename(RenameArgs<'a>),
     //RENEW(RENEW4args),
-    //RESTOREFH,
-    //SAVEFH,
+    RestoreFileHandle,
+    SaveFileHandle,
     GetSecurityInfo(GetSecurityInfoArgs<'a>),
-    //SETATTR(SETATTR4args),
+    SetAttributes(SetAttributesArgs<'a>),
     //SETCLIENTID(SETCLIENTID4args),
     //SETCLIENTID_CONFIRM(SETCLIENTID_CONFIRM4args),
     //VERIFY(VERIFY4args),
-    //WRITE(WRITE4args),
+    Write(WriteArgs<'a>),
     //RELEASE_LOCKOWNER(RELEASE_LOCKOWNER4args),
     //BACKCHANNEL_CTL(BACKCHANNEL_CTL4args),
     //BIND_CONN_TO_SESSION(BIND_CONN_TO_SESSION4args),
@@ -433,7 +537,9 @@ pub enum NfsArgOp<'a> {
     //WANT_DELEGATION(WANT_DELEGATION4args),
     DestroyClientId(ClientId),
     ReclaimComplete(ReclaimCompleteArgs),
-    //ILLEGAL,
+    /// An operation that is defined but not implemented, whose arguments cannot be decoded.
+    Unsupported(NfsOpnum),
+    Illegal,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, From)]
@@ -448,21 +554,21 @@ pub struct CompoundArgs<'a> {
 pub enum NfsResOp<'a> {
     Access(Result<AccessResult, Error>),
     Close(Result<StateId, Error>),
-    //COMMIT(COMMIT4res),
-    //CREATE(CREATE4res),
+    Commit(Result<Verifier, Error>),
+    Create(Result<CreateResult<'a>, Error>),
     //DELEGPURGE(DELEGPURGE4res),
-    //DELEGRETURN(DELEGRETURN4res),
+    DelegationReturn(Result<(), Error>),
     GetAttributes(Result<Vec<AttributeValue<'a>>, Error>),
     GetFileHandle(Result<FileHandle<'a>, Error>),
-    //LINK(LINK4res),
-    //LOCK(LOCK4res),
-    //LOCKT(LOCKT4res),
-    //LOCKU(LOCKU4res),
+    Link(Result<ChangeInfo, Error>),
+    Lock(Result<StateId, LockError<'a>>),
+    LockTest(Result<(), LockError<'a>>),
+    Unlock(Result<StateId, Error>),
     Lookup(Result<(), Error>),
-    //LOOKUPP(LOOKUPP4res),
+    LookupParent(Result<(), Error>),
     //NVERIFY(NVERIFY4res),
     Open(Result<OpenResult<'a>, Error>),
-    //OPENATTR(OPENATTR4res),
+    OpenAttributes(Result<(), Error>),
     //OPEN_CONFIRM(OPEN_CONFIRM4res),
     //OPEN_DOWNGRADE(OPEN_DOWNGRADE4res),
     PutFileHandle(Result<(), Error>),
@@ -470,18 +576,18 @@ pub enum NfsResOp<'a> {
     PutRootFileHandle(Result<(), Error>),
     Read(Result<ReadResult<'a>, Error>),
     ReadDirectory(Result<ReadDirectoryResult<'a>, Error>),
-    //READLINK(READLINK4res),
-    //REMOVE(REMOVE4res),
-    //RENAME(RENAME4res),
+    ReadLink(Result<Cow<'a, str>, Error>),
+    Remove(Result<ChangeInfo, Error>),
+    Rename(Result<RenameResult, Error>),
     //RENEW(RENEW4res),
-    //RESTOREFH(RESTOREFH4res),
-    //SAVEFH(SAVEFH4res),
+    RestoreFileHandle(Result<(), Error>),
+    SaveFileHandle(Result<(), Error>),
     GetSecurityInfo(GetSecurityInfoResult<'a>),
-    //SETATTR(SETATTR4res),
+    SetAttributes(Result<AttributeMask<'a>, Error>),
     //SETCLIENTID(SETCLIENTID4res),
     //SETCLIENTID_CONFIRM(SETCLIENTID_CONFIRM4res),
     //VERIFY(VERIFY4res),
-    //WRITE(WRITE4res),
+    Write(Result<WriteResult, Error>),
     //RELEASE_LOCKOWNER(RELEASE_LOCKOWNER4res),
     //BACKCHANNEL_CTL(BACKCHANNEL_CTL4res),
     //BIND_CONN_TO_SESSION(BIND_CONN_TO_SESSION4res),
@@ -502,7 +608,8 @@ pub enum NfsResOp<'a> {
     //WANT_DELEGATION(WANT_DELEGATION4res),
     DestroyClientId(Result<(), Error>),
     ReclaimComplete(Result<(), Error>),
-    //ILLEGAL(ILLEGAL4res),
+    Unsupported(NfsOpnum, Error),
+    Illegal(Error),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, From)]
@@ -599,12 +706,142 @@ pub struct CloseArgs {
     pub open_state_id: StateId,
 }
 
+// Operation 5: COMMIT
+
+#[derive(Debug, Clone, PartialEq, Eq, From)]
+#[from(forward)]
+pub struct CommitArgs {
+    pub offset: u64,
+    pub count: u32,
+}
+
+// Operation 6: CREATE
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, From)]
+#[from(forward)]
+pub struct SpecData {
+    pub major: u32,
+    pub minor: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CreateType<'a> {
+    Link(Cow<'a, str>),
+    BlockDevice(SpecData),
+    CharacterDevice(SpecData),
+    Socket,
+    Fifo,
+    Directory,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, From)]
+#[from(forward)]
+pub struct CreateArgs<'a> {
+    pub object_type: CreateType<'a>,
+    pub name: &'a str,
+    pub attributes: Vec<AttributeValue<'a>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, From)]
+#[from(forward)]
+pub struct CreateResult<'a> {
+    pub change_info: ChangeInfo,
+    pub attributes: AttributeMask<'a>,
+}
+
 // Operation 9: GETATTR
 
 // Operation 10: GETFH
 
+// Operation 11: LINK
+
+// Operation 12: LOCK
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+pub enum LockType {
+    Read = 1,
+    Write = 2,
+    /// Blocking read lock, the client polls until it is granted.
+    ReadBlocking = 3,
+    /// Blocking write lock, the client polls until it is granted.
+    WriteBlocking = 4,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, From)]
+#[from(forward)]
+pub struct OpenToLockOwner<'a> {
+    pub open_sequence_id: SequenceId,
+    pub open_state_id: StateId,
+    pub lock_sequence_id: SequenceId,
+    pub lock_owner: LockOwner<'a>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, From)]
+#[from(forward)]
+pub struct ExistingLockOwner {
+    pub lock_state_id: StateId,
+    pub lock_sequence_id: SequenceId,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Locker<'a> {
+    New(OpenToLockOwner<'a>),
+    Existing(ExistingLockOwner),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, From)]
+#[from(forward)]
+pub struct LockArgs<'a> {
+    pub lock_type: LockType,
+    pub reclaim: bool,
+    pub offset: u64,
+    pub length: u64,
+    pub locker: Locker<'a>,
+}
+
+/// The lock that conflicts with a request.
+#[derive(Debug, Clone, PartialEq, Eq, From)]
+#[from(forward)]
+pub struct LockDenied<'a> {
+    pub offset: u64,
+    pub length: u64,
+    pub lock_type: LockType,
+    pub owner: LockOwner<'a>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, From)]
+pub enum LockError<'a> {
+    Denied(LockDenied<'a>),
+    Error(Error),
+}
+
+// Operation 13: LOCKT
+
+#[derive(Debug, Clone, PartialEq, Eq, From)]
+#[from(forward)]
+pub struct LockTestArgs<'a> {
+    pub lock_type: LockType,
+    pub offset: u64,
+    pub length: u64,
+    pub owner: LockOwner<'a>,
+}
+
+// Operation 14: LOCKU
+
+#[derive(Debug, Clone, PartialEq, Eq, From)]
+#[from(forward)]
+pub struct UnlockArgs {
+    pub lock_type: LockType,
+    pub sequence_id: SequenceId,
+    pub lock_state_id: StateId,
+    pub offset: u64,
+    pub length: u64,
+}
+
 // Operation 15: LOOKUP
 
+// Operation 16: LOOKUPP
+
 // Operation 18: OPEN
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
@@ -618,7 +855,9 @@ pub enum OpenFlagCreateDiscriminant {
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum OpenFlagCreate<'a> {
-    Unchecked,
+    Unchecked {
+        attributes: Vec<AttributeValue<'a>>,
+    },
     Guarded {
         attributes: Vec<AttributeValue<'a>>,
     },
@@ -798,6 +1037,13 @@ pub struct OpenResult<'a> {
     pub delegation: OpenDelegation<'a>,
 }
 
+// Operation 19: OPENATTR
+
+#[derive(Debug, Clone, PartialEq, Eq, From)]
+pub struct OpenAttributesArgs {
+    pub create_directory: bool,
+}
+
 // Operation 22: PUTFH
 
 // Operation 24: PUTROOTFH
@@ -850,6 +1096,30 @@ pub struct ReadDirectoryResult<'a> {
     pub directory_list: DirectoryList<'a>,
 }
 
+// Operation 27: READLINK
+
+// Operation 28: REMOVE
+
+// Operation 29: RENAME
+
+#[derive(Debug, Clone, PartialEq, Eq, From)]
+#[from(forward)]
+pub struct RenameArgs<'a> {
+    pub old_name: &'a str,
+    pub new_name: &'a str,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, From)]
+#[from(forward)]
+pub struct RenameResult {
+    pub source_change_info: ChangeInfo,
+    pub target_change_info: ChangeInfo,
+}
+
+// Operation 31: RESTOREFH
+
+// Operation 32: SAVEFH
+
 // Operation 33: SECINFO
 
 #[derive(Debug, Clone, PartialEq, Eq, From)]
@@ -886,11 +1156,47 @@ pub enum GetSecurityInfo<'a> {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum GetSecurityInfoResult<'a> {
     Ok(GetSecurityInfoResultOk<'a>),
+    Err(Error),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct GetSecurityInfoResultOk<'a>(pub Vec<GetSecurityInfo<'a>>);
 
+// Operation 34: SETATTR
+
+#[derive(Debug, Clone, PartialEq, Eq, From)]
+#[from(forward)]
+pub struct SetAttributesArgs<'a> {
+    pub state_id: StateId,
+    pub attributes: Vec<AttributeValue<'a>>,
+}
+
+// Operation 38: WRITE
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, FromPrimitive)]
+pub enum StableHow {
+    Unstable = 0,
+    DataSync = 1,
+    FileSync = 2,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, From)]
+#[from(forward)]
+pub struct WriteArgs<'a> {
+    pub state_id: StateId,
+    pub offset: u64,
+    pub stable: StableHow,
+    pub data: Cow<'a, [u8]>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, From)]
+#[from(forward)]
+pub struct WriteResult {
+    pub count: u32,
+    pub committed: StableHow,
+    pub verifier: Verifier,
+}
+
 // Operation 40
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -1109,6 +1415,125 @@ pub struct ReclaimCompleteArgs {
     pub one_fs: bool,
 }
 
+// Callbacks
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+pub enum CallbackOpnum {
+    CB_GETATTR = 3,
+    Recall = 4,
+    CB_LAYOUTRECALL = 5,
+    CB_NOTIFY = 6,
+    CB_PUSH_DELEG = 7,
+    CB_RECALL_ANY = 8,
+    CB_RECALLABLE_OBJ_AVAIL = 9,
+    CB_RECALL_SLOT = 10,
+    Sequence = 11,
+    CB_WANTS_CANCELLED = 12,
+    CB_NOTIFY_LOCK = 13,
+    CB_NOTIFY_DEVICEID = 14,
+    Illegal = 10044,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CallbackArgOp<'a> {
+    //CB_GETATTR(CB_GETATTR4args),
+    Recall(CallbackRecallArgs<'a>),
+    //CB_LAYOUTRECALL(CB_LAYOUTRECALL4args),
+    //CB_NOTIFY(CB_NOTIFY4args),
+    //CB_PUSH_DELEG(CB_PUSH_DELEG4args),
+    //CB_RECALL_ANY(CB_RECALL_ANY4args),
+    //CB_RECALLABLE_OBJ_AVAIL(CB_RECALLABLE_OBJ_AVAIL4args),
+    //CB_RECALL_SLOT(CB_RECALL_SLOT4args),
+    Sequence(CallbackSequenceArgs),
+    //CB_WANTS_CANCELLED(CB_WANTS_CANCELLED4args),
+    //CB_NOTIFY_LOCK(CB_NOTIFY_LOCK4args),
+    //CB_NOTIFY_DEVICEID(CB_NOTIFY_DEVICEID4args),
+    /// An operation that is defined but not implemented, whose arguments cannot be decoded.
+    Unsupported(CallbackOpnum),
+    Illegal,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CallbackResOp {
+    Recall(Result<(), Error>),
+    Sequence(Result<CallbackSequenceResult, Error>),
+    Unsupported(CallbackOpnum, Error),
+    Illegal(Error),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, From)]
+#[from(forward)]
+pub struct CallbackCompoundArgs<'a> {
+    pub tag: Cow<'a, str>,
+    pub minorversion: u32,
+    pub callback_ident: u32,
+    pub argarray: Vec<CallbackArgOp<'a>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, From)]
+#[from(forward)]
+pub struct CallbackCompoundResult<'a> {
+    pub error: Option<Error>,
+    pub tag: Cow<'a, str>,
+    pub resarray: Vec<CallbackResOp>,
+}
+
+/// A CB_COMPOUND sent to the callback program that the client named in CREATE_SESSION.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallbackCall<'a> {
+    pub program: u32,
+    pub cred: OpaqueAuth<'a>,
+    pub verf: OpaqueAuth<'a>,
+    pub args: CallbackCompoundArgs<'a>,
+}
+
+// Callback operation 4: CB_RECALL
+
+#[derive(Debug, Clone, PartialEq, Eq, From)]
+#[from(forward)]
+pub struct CallbackRecallArgs<'a> {
+    pub state_id: StateId,
+    pub truncate: bool,
+    pub file_handle: FileHandle<'a>,
+}
+
+// Callback operation 11: CB_SEQUENCE
+
+#[derive(Debug, Clone, PartialEq, Eq, From)]
+#[from(forward)]
+pub struct ReferringCall {
+    pub sequence_id: SequenceId,
+    pub slot_id: SlotId,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, From)]
+#[from(forward)]
+pub struct ReferringCallList {
+    pub session_id: SessionId,
+    pub referring_calls: Vec<ReferringCall>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, From)]
+#[from(forward)]
+pub struct CallbackSequenceArgs {
+    pub session_id: SessionId,
+    pub sequence_id: SequenceId,
+    pub slot_id: SlotId,
+    pub highest_slot_id: SlotId,
+    pub cache_this: bool,
+    pub referring_call_lists: Vec<ReferringCallList>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, From)]
+#[from(forward)]
+pub struct CallbackSequenceResult {
+    pub session_id: SessionId,
+    pub sequence_id: SequenceId,
+    pub slot_id: SlotId,
+    pub highest_slot_id: SlotId,
+    pub target_highest_slot_id: SlotId,
+}
+
 //
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
@@ -1194,6 +1619,44 @@ pub enum Error {
     FILE_OPEN = 10046,           /* open file blocks op.     */
     ADMIN_REVOKED = 10047,       /* lock-owner state revoked */
     CB_PATH_DOWN = 10048,        /* callback path down       */
+    BADIOMODE = 10049,
+    BADLAYOUT = 10050,
+    BAD_SESSION_DIGEST = 10051,
+    BADSESSION = 10052,
+    BADSLOT = 10053,
+    COMPLETE_ALREADY = 10054,
+    CONN_NOT_BOUND_TO_SESSION = 10055,
+    DELEG_ALREADY_WANTED = 10056,
+    BACK_CHAN_BUSY = 10057, /* backchan reqs outstanding */
+    LAYOUTTRYLATER = 10058,
+    LAYOUTUNAVAILABLE = 10059,
+    NOMATCHING_LAYOUT = 10060,
+    RECALLCONFLICT = 10061,
+    UNKNOWN_LAYOUTTYPE = 10062,
+    SEQ_MISORDERED = 10063,       /* unexpected seq.ID in req */
+    SEQUENCE_POS = 10064,         /* [CB_]SEQ. op not 1st op  */
+    REQ_TOO_BIG = 10065,          /* request too big          */
+    REP_TOO_BIG = 10066,          /* reply too big            */
+    REP_TOO_BIG_TO_CACHE = 10067, /* rep. not all cached     */
+    RETRY_UNCACHED_REP = 10068,   /* retry & rep. uncached    */
+    UNSAFE_COMPOUND = 10069,      /* retry/recovery too hard  */
+    TOO_MANY_OPS = 10070,         /* too many ops in [CB_]COMP */
+    OP_NOT_IN_SESSION = 10071,    /* op needs [CB_]SEQ. op    */
+    HASH_ALG_UNSUPP = 10072,      /* hash alg. not supp.      */
+    CLIENTID_BUSY = 10074,        /* clientid has state       */
+    PNFS_IO_HOLE = 10075,         /* IO to _SPARSE file hole  */
+    SEQ_FALSE_RETRY = 10076,      /* Retry != original req.   */
+    BAD_HIGH_SLOT = 10077,        /* req has bad highest_slot */
+    DEADSESSION = 10078,          /* new req sent to dead sess */
+    ENCR_ALG_UNSUPP = 10079,      /* encr alg. not supp.      */
+    PNFS_NO_LAYOUT = 10080,       /* I/O without a layout     */
+    NOT_ONLY_OP = 10081,          /* addl ops not allowed     */
+    WRONG_CRED = 10082,           /* op done by wrong cred    */
+    WRONG_TYPE = 10083,           /* op on wrong type object  */
+    DIRDELEG_UNAVAIL = 10084,     /* delegation not avail.    */
+    REJECT_DELEG = 10085,         /* cb rejected delegation   */
+    RETURNCONFLICT = 10086,       /* layout get before return */
+    DELEG_REVOKED = 10087,        /* deleg./layout revoked    */
 }
 
 impl<'a, T> From<T> for Bitmap<'a>
@@ -1214,10 +1677,93 @@ where
     }
 }
 
+impl<'a> FileHandle<'a> {
+    pub fn into_owned(self) -> FileHandle<'static> {
+        FileHandle(Cow::Owned(self.0.into_owned()))
+    }
+}
+
 impl<'a> AttributeMask<'a> {
     pub fn new() -> Self {
         Self(vec![].into())
     }
+
+    pub fn into_owned(self) -> AttributeMask<'static> {
+        AttributeMask(Cow::Owned(self.0.into_owned()))
+    }
+}
+
+impl StateOwner<'_> {
+    pub fn into_owned(self) -> StateOwner<'static> {
+        StateOwner {
+            client_id: self.client_id,
+            owner: Cow::Owned(self.owner.into_owned()),
+        }
+    }
+}
+
+impl OpenOwner<'_> {
+    pub fn into_owned(self) -> OpenOwner<'static> {
+        OpenOwner(self.0.into_owned())
+    }
+}
+
+impl CreateType<'_> {
+    pub fn into_owned(self) -> CreateType<'static> {
+        match self {
+            Self::Link(target) => CreateType::Link(Cow::Owned(target.into_owned())),
+            Self::BlockDevice(data) => CreateType::BlockDevice(data),
+            Self::CharacterDevice(data) => CreateType::CharacterDevice(data),
+            Self::Socket => CreateType::Socket,
+            Self::Fifo => CreateType::Fifo,
+            Self::Directory => CreateType::Directory,
+        }
+    }
+}
+
+impl OpenFlag<'_> {
+    pub fn into_owned(self) -> OpenFlag<'static> {
+        let owned = |attributes: Vec<AttributeValue<'_>>| {
+            attributes
+                .into_iter()
+                .map(AttributeValue::into_owned)
+                .collect()
+        };
+        match self {
+            Self::NoCreate => OpenFlag::NoCreate,
+            Self::Create(OpenFlagCreate::Unchecked { attributes }) => {
+                OpenFlag::Create(OpenFlagCreate::Unchecked {
+                    attributes: owned(attributes),
+                })
+            }
+            Self::Create(OpenFlagCreate::Guarded { attributes }) => {
+                OpenFlag::Create(OpenFlagCreate::Guarded {
+                    attributes: owned(attributes),
+                })
+            }
+            Self::Create(OpenFlagCreate::Exclusive4_1 {
+                verifier,
+                attributes,
+            }) => OpenFlag::Create(OpenFlagCreate::Exclusive4_1 {
+                verifier,
+                attributes: owned(attributes),
+            }),
+        }
+    }
+}
+
+impl FromIterator<Attribute> for AttributeMask<'_> {
+    fn from_iter<T: IntoIterator<Item = Attribute>>(attributes: T) -> Self {
+        let mut bitmap: Vec<u32> = vec![];
+        for attribute in attributes {
+            let attribute = attribute as usize;
+            if bitmap.len() <= attribute / 32 {
+                bitmap.resize(attribute / 32 + 1, 0);
+            }
+            bitmap[attribute / 32] |= 1 << (attribute % 32);
+        }
+        Self(bitmap.into())
+    }
 }
 
 impl<'a> IntoIterator for AttributeMask<'a> {
@@ -1251,7 +1797,198 @@ impl Iterator for AttributeMaskIntoIter<'_> {
     }
 }
 
+impl NfsArgOp<'_> {
+    /// The result of this operation failing with the given error.
+    pub fn error_result<'a>(
+        &self,
+        error: Error,
+    ) -> NfsResOp<'a> {
+        match self {
+            Self::Access(_) => NfsResOp::Access(Err(error)),
+            Self::Close(_) => NfsResOp::Close(Err(error)),
+            Self::Commit(_) => NfsResOp::Commit(Err(error)),
+            Self::Create(_) => NfsResOp::Create(Err(error)),
+            Self::DelegationReturn(_) => NfsResOp::DelegationReturn(Err(error)),
+            Self::GetAttributes(_) => NfsResOp::GetAttributes(Err(error)),
+            Self::GetFileHandle => NfsResOp::GetFileHandle(Err(error)),
+            Self::Link(_) => NfsResOp::Link(Err(error)),
+            Self::Lock(_) => NfsResOp::Lock(Err(error.into())),
+            Self::LockTest(_) => NfsResOp::LockTest(Err(error.into())),
+            Self::Unlock(_) => NfsResOp::Unlock(Err(error)),
+            Self::Lookup(_) => NfsResOp::Lookup(Err(error)),
+            Self::LookupParent => NfsResOp::LookupParent(Err(error)),
+            Self::Open(_) => NfsResOp::Open(Err(error)),
+            Self::OpenAttributes(_) => NfsResOp::OpenAttributes(Err(error)),
+            Self::PutFileHandle(_) => NfsResOp::PutFileHandle(Err(error)),
+            Self::PutRootFileHandle => NfsResOp::PutRootFileHandle(Err(error)),
+            Self::Read(_) => NfsResOp::Read(Err(error)),
+            Self::ReadDirectory(_) => NfsResOp::ReadDirectory(Err(error)),
+            Self::ReadLink => NfsResOp::ReadLink(Err(error)),
+            Self::Remove(_) => NfsResOp::Remove(Err(error)),
+            Self::Rename(_) => NfsResOp::Rename(Err(error)),
+            Self::RestoreFileHandle => NfsResOp::RestoreFileHandle(Err(error)),
+            Self::SaveFileHandle => NfsResOp::SaveFileHandle(Err(error)),
+            Self::GetSecurityInfo(_) => {
+                NfsResOp::GetSecurityInfo(GetSecurityInfoResult::Err(error))
+            }
+            Self::SetAttributes(_) => NfsResOp::SetAttributes(Err(error)),
+            Self::Write(_) => NfsResOp::Write(Err(error)),
+            Self::ExchangeId(_) => NfsResOp::ExchangeId(Err(error)),
+            Self::CreateSession(_) => NfsResOp::CreateSession(Err(error)),
+            Self::DestroySession(_) => NfsResOp::DestroySession(Err(error)),
+            Self::GetSecurityInfoNoName(_) => NfsResOp::GetSecurityInfoNoName(
+                GetSecurityInfoNoNameResult(GetSecurityInfoResult::Err(error)),
+            ),
+            Self::Sequence(_) => NfsResOp::Sequence(Err(error)),
+            Self::DestroyClientId(_) => NfsResOp::DestroyClientId(Err(error)),
+            Self::ReclaimComplete(_) => NfsResOp::ReclaimComplete(Err(error)),
+            Self::Unsupported(opnum) => NfsResOp::Unsupported(*opnum, error),
+            Self::Illegal => NfsResOp::Illegal(error),
+        }
+    }
+}
+
+impl NfsResOp<'_> {
+    /// The status of the operation; a compound stops at the first error.
+    pub fn error(&self) -> Option<Error> {
+        match self {
+            Self::Access(result) => result.as_ref().err(),
+            Self::Close(result) => result.as_ref().err(),
+            Self::Commit(result) => result.as_ref().err(),
+            Self::Create(result) => result.as_ref().err(),
+            Self::DelegationReturn(result) => result.as_ref().err(),
+            Self::GetAttributes(result) => result.as_ref().err(),
+            Self::GetFileHandle(result) => result.as_ref().err(),
+            Self::Link(result) => result.as_ref().err(),
+            Self::Lock(Err(error)) | Self::LockTest(Err(error)) => match error {
+                LockError::Denied(_) => Some(&Error::DENIED),
+                LockError::Error(error) => Some(error),
+            },
+            Self::Lock(Ok(_)) | Self::LockTest(Ok(_)) => None,
+            Self::Unlock(result) => result.as_ref().err(),
+            Self::Lookup(result) => result.as_ref().err(),
+            Self::LookupParent(result) => result.as_ref().err(),
+            Self::Open(result) => result.as_ref().err(),
+            Self::OpenAttributes(result) => result.as_ref().err(),
+            Self::PutFileHandle(result) => result.as_ref().err(),
+            Self::PutRootFileHandle(result) => result.as_ref().err(),
+            Self::Read(result) => result.as_ref().err(),
+            Self::ReadDirectory(result) => result.as_ref().err(),
+            Self::ReadLink(result) => result.as_ref().err(),
+            Self::Remove(result) => result.as_ref().err(),
+            Self::Rename(result) => result.as_ref().err(),
+            Self::RestoreFileHandle(result) => result.as_ref().err(),
+            Self::SaveFileHandle(result) => result.as_ref().err(),
+            Self::GetSecurityInfo(GetSecurityInfoResult::Ok(_)) => None,
+            Self::GetSecurityInfo(GetSecurityInfoResult::Err(error)) => Some(error),
+            Self::SetAttributes(result) => result.as_ref().err(),
+            Self::Write(result) => result.as_ref().err(),
+            Self::ExchangeId(result) => result.as_ref().err(),
+            Self::CreateSession(result) => result.as_ref().err(),
+            Self::DestroySession(result) => result.as_ref().err(),
+            Self::GetSecurityInfoNoName(GetSecurityInfoNoNameResult(
+                GetSecurityInfoResult::Ok(_),
+            )) => None,
+            Self::GetSecurityInfoNoName(GetSecurityInfoNoNameResult(
+                GetSecurityInfoResult::Err(error),
+            )) => Some(error),
+            Self::Sequence(result) => result.as_ref().err(),
+            Self::DestroyClientId(result) => result.as_ref().err(),
+            Self::ReclaimComplete(result) => result.as_ref().err(),
+            Self::Unsupported(_, error) | Self::Illegal(error) => Some(error),
+        }
+        .copied()
+    }
+}
+
+impl CallbackResOp {
+    /// The status of the operation; a callback compound stops at the first error.
+    pub fn error(&self) -> Option<Error> {
+        match self {
+            Self::Recall(result) => result.as_ref().err(),
+            Self::Sequence(result) => result.as_ref().err(),
+            Self::Unsupported(_, error) | Self::Illegal(error) => Some(error),
+        }
+        .copied()
+    }
+}
+
 impl AttributeValue<'_> {
+    pub fn into_owned(self) -> AttributeValue<'static> {
+        match self {
+            Self::SupportedAttributes(value) => {
+                AttributeValue::SupportedAttributes(Cow::Owned(value.into_owned()))
+            }
+            Self::Type(value) => AttributeValue::Type(value),
+            Self::FileHandleExpireType(value) => AttributeValue::FileHandleExpireType(value),
+            Self::Change(value) => AttributeValue::Change(value),
+            Self::Size(value) => AttributeValue::Size(value),
+            Self::LinkSupport(value) => AttributeValue::LinkSupport(value),
+            Self::SymlinkSupport(value) => AttributeValue::SymlinkSupport(value),
+            Self::NamedAttributes(value) => AttributeValue::NamedAttributes(value),
+            Self::FileSystemId(value) => AttributeValue::FileSystemId(value),
+            Self::UniqueHandles(value) => AttributeValue::UniqueHandles(value),
+            Self::LeaseTime(value) => AttributeValue::LeaseTime(value),
+            Self::ReadDirAttributeError => AttributeValue::ReadDirAttributeError,
+            Self::AclSupport(value) => AttributeValue::AclSupport(value),
+            Self::Archive(value) => AttributeValue::Archive(value),
+            Self::CanSetTime(value) => AttributeValue::CanSetTime(value),
+            Self::CaseInsensitive(value) => AttributeValue::CaseInsensitive(value),
+            Self::CasePreserving(value) => AttributeValue::CasePreserving(value),
+            Self::ChownRestricted(value) => AttributeValue::ChownRestricted(value),
+            Self::FileHandle(value) => AttributeValue::FileHandle(value.into_owned()),
+            Self::FileId(value) => AttributeValue::FileId(value),
+            Self::FilesAvailable(value) => AttributeValue::FilesAvailable(value),
+            Self::FilesFree(value) => AttributeValue::FilesFree(value),
+            Self::FilesTotal(value) => AttributeValue::FilesTotal(value),
+            Self::Hidden(value) => AttributeValue::Hidden(value),
+            Self::Homogeneous(value) => AttributeValue::Homogeneous(value),
+            Self::MaxFileSize(value) => AttributeValue::MaxFileSize(value),
+            Self::MaxLink(value) => AttributeValue::MaxLink(value),
+            Self::MaxName(value) => AttributeValue::MaxName(value),
+            Self::MaxRead(value) => AttributeValue::MaxRead(value),
+            Self::MaxWrite(value) => AttributeValue::MaxWrite(value),
+            Self::MimeType(value) => AttributeValue::MimeType(Cow::Owned(value.into_owned())),
+            Self::Mode(value) => AttributeValue::Mode(value),
+            Self::NoTruncate(value) => AttributeValue::NoTruncate(value),
+            Self::NumberOfLinks(value) => AttributeValue::NumberOfLinks(value),
+            Self::Owner(value) => AttributeValue::Owner(Cow::Owned(value.into_owned())),
+            Self::OwnerGroup(value) => AttributeValue::OwnerGroup(Cow::Owned(value.into_owned())),
+            Self::QuotaAvailableHard(value) => AttributeValue::QuotaAvailableHard(value),
+            Self::QuotaAvailableSoft(value) => AttributeValue::QuotaAvailableSoft(value),
+            Self::QuotaUsed(value) => AttributeValue::QuotaUsed(value),
+            Self::RawDevice(value) => AttributeValue::RawDevice(value),
+            Self::SpaceAvailable(value) => AttributeValue::SpaceAvailable(value),
+            Self::SpaceFree(value) => AttributeValue::SpaceFree(value),
+            Self::SpaceTotal(value) => AttributeValue::SpaceTotal(value),
+            Self::SpaceUsed(value) => AttributeValue::SpaceUsed(value),
+            Self::System(value) => AttributeValue::System(value),
+            Self::TimeAccess(value) => AttributeValue::TimeAccess(value),
+            Self::TimeAccessSet(value) => AttributeValue::TimeAccessSet(value),
+            Self::TimeBackup(value) => AttributeValue::TimeBackup(value),
+            Self::TimeCreate(value) => AttributeValue::TimeCreate(value),
+            Self::TimeDelta(value) => AttributeValue::TimeDelta(value),
+            Self::TimeMetadata(value) => AttributeValue::TimeMetadata(value),
+            Self::TimeModify(value) => AttributeValue::TimeModify(value),
+            Self::TimeModifySet(value) => AttributeValue::TimeModifySet(value),
+            Self::MountedOnFileId(value) => AttributeValue::MountedOnFileId(value),
+            Self::DirectoryNotificationDelay(value) => {
+                AttributeValue::DirectoryNotificationDelay(value)
+            }
+            Self::DirectoryEntryNotificationDelay(value) => {
+                AttributeValue::DirectoryEntryNotificationDelay(value)
+            }
+            Self::ChangePolicy(value) => AttributeValue::ChangePolicy(value),
+            Self::ModeSetMasked(value) => AttributeValue::ModeSetMasked(value),
+            Self::SupportedAttributesExclusiveCreate(value) => {
+                AttributeValue::SupportedAttributesExclusiveCreate(Cow::Owned(value.into_owned()))
+            }
+            Self::FileSystemCharsetCapabilities(value) => {
+                AttributeValue::FileSystemCharsetCapabilities(value)
+            }
+        }
+    }
+
     #[inline]
     fn attribute(&self) -> Attribute {
         match self {
@@ -1268,19 +2005,55 @@ impl AttributeValue<'_> {
             Self::LeaseTime(_) => Attribute::LeaseTime,
             Self::ReadDirAttributeError => Attribute::ReadDirAttributeError,
             Self::AclSupport(_) => Attribute::AclSupport,
+            Self::Archive(_) => Attribute::Archive,
+            Self::CanSetTime(_) => Attribute::CanSetTime,
             Self::CaseInsensitive(_) => Attribute::CaseInsensitive,
             Self::CasePreserving(_) => Attribute::CasePreserving,
+            Self::ChownRestricted(_) => Attribute::ChownRestricted,
             Self::FileHandle(_) => Attribute::FileHandle,
             Self::FileId(_) => Attribute::FileId,
+            Self::FilesAvailable(_) => Attribute::FilesAvailable,
+            Self::FilesFree(_) => Attribute::FilesFree,
+            Self::FilesTotal(_) => Attribute::FilesTotal,
+            Self::Hidden(_) => Attribute::Hidden,
+            Self::Homogeneous(_) => Attribute::Homogeneous,
             Self::MaxFileSize(_) => Attribute::MaxFileSize,
+            Self::MaxLink(_) => Attribute::MaxLink,
+            Self::MaxName(_) => Attribute::MaxName,
             Self::MaxRead(_) => Attribute::MaxRead,
             Self::MaxWrite(_) => Attribute::MaxWrite,
+            Self::MimeType(_) => Attribute::MimeType,
             Self::Mode(_) => Attribute::Mode,
+            Self::NoTruncate(_) => Attribute::NoTruncate,
             Self::NumberOfLinks(_) => Attribute::NumberOfLinks,
+            Self::Owner(_) => Attribute::Owner,
+            Self::OwnerGroup(_) => Attribute::OwnerGroup,
+            Self::QuotaAvailableHard(_) => Attribute::QuotaAvailableHard,
+            Self::QuotaAvailableSoft(_) => Attribute::QuotaAvailableSoft,
+            Self::QuotaUsed(_) => Attribute::QuotaUsed,
+            Self::RawDevice(_) => Attribute::RawDevice,
+            Self::SpaceAvailable(_) => Attribute::SpaceAvailable,
+            Self::SpaceFree(_) => Attribute::SpaceFree,
+            Self::SpaceTotal(_) => Attribute::SpaceTotal,
+            Self::SpaceUsed(_) => Attribute::SpaceUsed,
+            Self::System(_) => Attribute::System,
+            Self::TimeAccess(_) => Attribute::TimeAccess,
+            Self::TimeAccessSet(_) => Attribute::TimeAccessSet,
+            Self::TimeBackup(_) => Attribute::TimeBackup,
+            Self::TimeCreate(_) => Attribute::TimeCreate,
+            Self::TimeDelta(_) => Attribute::TimeDelta,
+            Self::TimeMetadata(_) => Attribute::TimeMetadata,
+            Self::TimeModify(_) => Attribute::TimeModify,
+            Self::TimeModifySet(_) => Attribute::TimeModifySet,
             Self::MountedOnFileId(_) => Attribute::MountedOnFileId,
+            Self::DirectoryNotificationDelay(_) => Attribute::DirectoryNotificationDelay,
+            Self::DirectoryEntryNotificationDelay(_) => Attribute::DirectoryEntryNotificationDelay,
+            Self::ChangePolicy(_) => Attribute::ChangePolicy,
+            Self::ModeSetMasked(_) => Attribute::ModeSetMasked,
             Self::SupportedAttributesExclusiveCreate(_) => {
                 Attribute::SupportedAttributesExclusiveCreate
             }
+            Self::FileSystemCharsetCapabilities(_) => Attribute::FileSystemCharsetCapabilities,
         }
     }
 }
@@ -1383,4 +2156,462 @@ mod tests {
         assert_eq!(message, decoded_message);
         assert_eq!(reply, decoded_reply);
     }
+
+    #[test]
+    fn test_encode_decode_write_path_reply() {
+        let change_info = ChangeInfo {
+            atomic: true,
+            before: 1,
+            after: 2,
+        };
+        let reply = Reply::Accepted(AcceptedReply {
+            verf: OpaqueAuth {
+                flavor: AuthFlavor::AuthNone,
+                body: (&[]).into(),
+            },
+            body: AcceptedReplyBody::Success(ProcedureReply::Compound(CompoundResult {
+                error: None,
+                tag: "".into(),
+                resarray: vec![
+                    NfsResOp::Write(Ok(WriteResult {
+                        count: 5,
+                        committed: StableHow::Unstable,
+                        verifier: [7; 8],
+                    })),
+                    NfsResOp::Commit(Ok([7; 8])),
+                    NfsResOp::Create(Ok(CreateResult {
+                        change_info: change_info.clone(),
+                        attributes: AttributeMask(vec![0, 2].into()),
+                    })),
+                    NfsResOp::Link(Err(Error::NOTSUPP)),
+                    NfsResOp::Remove(Ok(change_info.clone())),
+                    NfsResOp::Rename(Ok(RenameResult {
+                        source_change_info: change_info.clone(),
+                        target_change_info: change_info,
+                    })),
+                    NfsResOp::SetAttributes(Ok(AttributeMask(vec![16].into()))),
+                    NfsResOp::SetAttributes(Err(Error::ROFS)),
+                ],
+            })),
+        });
+        let mut buffer = [0u8; 1024];
+        let buffer = serialize!(encode::reply(&reply), buffer);
+        let (buffer, decoded_reply) = decode::reply(ProcedureNumber::Compound)(buffer).unwrap();
+        assert_eq!(buffer, &[]);
+        assert_eq!(reply, decoded_reply);
+    }
+
+    #[test]
+    fn test_encode_decode_file_handle_stack_reply() {
+        let reply = Reply::Accepted(AcceptedReply {
+            verf: OpaqueAuth {
+                flavor: AuthFlavor::AuthNone,
+                body: (&[]).into(),
+            },
+            body: AcceptedReplyBody::Success(ProcedureReply::Compound(CompoundResult {
+                error: Some(Error::OP_ILLEGAL),
+                tag: "".into(),
+                resarray: vec![
+                    NfsResOp::SaveFileHandle(Ok(())),
+                    NfsResOp::LookupParent(Ok(())),
+                    NfsResOp::ReadLink(Ok("../target".into())),
+                    NfsResOp::RestoreFileHandle(Err(Error::RESTOREFH)),
+                    NfsResOp::OpenAttributes(Err(Error::NOTSUPP)),
+                    NfsResOp::Illegal(Error::OP_ILLEGAL),
+                ],
+            })),
+        });
+        let mut buffer = [0u8; 1024];
+        let buffer = serialize!(encode::reply(&reply), buffer);
+        let (buffer, decoded_reply) = decode::reply(ProcedureNumber::Compound)(buffer).unwrap();
+        assert_eq!(buffer, &[]);
+        assert_eq!(reply, decoded_reply);
+    }
+
+    const TIME: Time = Time {
+        seconds: 1736972386,
+        nanoseconds: 339944639,
+    };
+
+    /// Encodes the attributes in a GETATTR reply, which ends with them, and checks that they
+    /// decode in the order of the mask. Returns the encoded reply.
+    fn round_trip_attributes(
+        attributes: Vec<AttributeValue>,
+        decoded: Vec<AttributeValue>,
+    ) -> Vec<u8> {
+        let reply = |attributes| {
+            Reply::Accepted(AcceptedReply {
+                verf: OpaqueAuth {
+                    flavor: AuthFlavor::AuthNone,
+                    body: (&[]).into(),
+                },
+                body: AcceptedReplyBody::Success(ProcedureReply::Compound(CompoundResult {
+                    error: None,
+                    tag: "".into(),
+                    resarray: vec![NfsResOp::GetAttributes(Ok(attributes))],
+                })),
+            })
+        };
+        let mut buffer = [0u8; 1024];
+        let buffer = serialize!(encode::reply(&reply(attributes)), buffer);
+        let (rest, decoded_reply) = decode::reply(ProcedureNumber::Compound)(buffer).unwrap();
+        assert_eq!(rest, &[]);
+        assert_eq!(decoded_reply, reply(decoded));
+        buffer.to_vec()
+    }
+
+    fn round_trip_attribute(attribute: AttributeValue) {
+        round_trip_attributes(vec![attribute.clone()], vec![attribute]);
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_archive() {
+        round_trip_attribute(AttributeValue::Archive(false));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_can_set_time() {
+        round_trip_attribute(AttributeValue::CanSetTime(true));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_chown_restricted() {
+        round_trip_attribute(AttributeValue::ChownRestricted(true));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_files_available() {
+        round_trip_attribute(AttributeValue::FilesAvailable(1));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_files_free() {
+        round_trip_attribute(AttributeValue::FilesFree(2));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_files_total() {
+        round_trip_attribute(AttributeValue::FilesTotal(3));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_hidden() {
+        round_trip_attribute(AttributeValue::Hidden(false));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_homogeneous() {
+        round_trip_attribute(AttributeValue::Homogeneous(true));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_max_link() {
+        round_trip_attribute(AttributeValue::MaxLink(255));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_max_name() {
+        round_trip_attribute(AttributeValue::MaxName(255));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_mime_type() {
+        round_trip_attribute(AttributeValue::MimeType("text/plain".into()));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_no_truncate() {
+        round_trip_attribute(AttributeValue::NoTruncate(true));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_owner() {
+        round_trip_attribute(AttributeValue::Owner("1000".into()));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_owner_group() {
+        round_trip_attribute(AttributeValue::OwnerGroup("users@lararium".into()));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_quota_available_hard() {
+        round_trip_attribute(AttributeValue::QuotaAvailableHard(4));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_quota_available_soft() {
+        round_trip_attribute(AttributeValue::QuotaAvailableSoft(5));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_quota_used() {
+        round_trip_attribute(AttributeValue::QuotaUsed(6));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_raw_device() {
+        round_trip_attribute(AttributeValue::RawDevice(SpecData { major: 8, minor: 1 }));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_space_available() {
+        round_trip_attribute(AttributeValue::SpaceAvailable(7));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_space_free() {
+        round_trip_attribute(AttributeValue::SpaceFree(8));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_space_total() {
+        round_trip_attribute(AttributeValue::SpaceTotal(9));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_space_used() {
+        round_trip_attribute(AttributeValue::SpaceUsed(4096));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_system() {
+        round_trip_attribute(AttributeValue::System(false));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_time_access() {
+        round_trip_attribute(AttributeValue::TimeAccess(TIME));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_time_access_set_server() {
+        round_trip_attribute(AttributeValue::TimeAccessSet(SetTime::ServerTime));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_time_backup() {
+        round_trip_attribute(AttributeValue::TimeBackup(TIME));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_time_create() {
+        round_trip_attribute(AttributeValue::TimeCreate(TIME));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_time_delta() {
+        round_trip_attribute(AttributeValue::TimeDelta(Time {
+            seconds: 0,
+            nanoseconds: 1,
+        }));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_time_metadata() {
+        round_trip_attribute(AttributeValue::TimeMetadata(TIME));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_time_modify() {
+        round_trip_attribute(AttributeValue::TimeModify(TIME));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_time_modify_set_client() {
+        round_trip_attribute(AttributeValue::TimeModifySet(SetTime::ClientTime(TIME)));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_directory_notification_delay() {
+        round_trip_attribute(AttributeValue::DirectoryNotificationDelay(TIME));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_directory_entry_notification_delay() {
+        round_trip_attribute(AttributeValue::DirectoryEntryNotificationDelay(TIME));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_change_policy() {
+        round_trip_attribute(AttributeValue::ChangePolicy(ChangePolicy {
+            major: 1,
+            minor: 2,
+        }));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_mode_set_masked() {
+        round_trip_attribute(AttributeValue::ModeSetMasked(ModeMasked {
+            mode: 0o644,
+            mask: 0o777,
+        }));
+    }
+
+    #[test]
+    fn test_encode_decode_attribute_file_system_charset_capabilities() {
+        round_trip_attribute(AttributeValue::FileSystemCharsetCapabilities(
+            FileSystemCharsetCapabilities::ALLOWS_ONLY_UTF8,
+        ));
+    }
+
+    #[test]
+    fn test_encode_decode_attributes_out_of_order() {
+        let encoded = round_trip_attributes(
+            vec![
+                AttributeValue::Owner("1000".into()),
+                AttributeValue::SpaceUsed(4096),
+                AttributeValue::Archive(true),
+            ],
+            vec![
+                AttributeValue::Archive(true),
+                AttributeValue::Owner("1000".into()),
+                AttributeValue::SpaceUsed(4096),
+            ],
+        );
+        assert!(encoded.ends_with(&[
+            0, 0, 0, 2, 0, 0, 0x40, 0, 0, 0, 0x20, 0x10, 0, 0, 0, 20, 0, 0, 0, 1, 0, 0, 0, 4, b'1',
+            b'0', b'0', b'0', 0, 0, 0, 0, 0, 0, 0x10, 0
+        ]));
+    }
+
+    #[test]
+    fn test_encode_decode_attributes_odd_length_owners() {
+        for owner in ["a", "ab", "abc", "abcde"] {
+            let attributes = vec![
+                AttributeValue::Owner(owner.into()),
+                AttributeValue::OwnerGroup("users".into()),
+            ];
+            let encoded = round_trip_attributes(attributes.clone(), attributes);
+            let padding = vec![0; (4 - owner.len() % 4) % 4];
+            let values = [
+                &(owner.len() as u32).to_be_bytes()[..],
+                owner.as_bytes(),
+                &padding,
+                &[0, 0, 0, 5],
+                b"users",
+                &[0, 0, 0],
+            ]
+            .concat();
+            assert!(encoded.ends_with(&values), "{owner}");
+        }
+    }
+
+    #[test]
+    fn test_encode_decode_open_delegation_reply() {
+        let reply = Reply::Accepted(AcceptedReply {
+            verf: OpaqueAuth {
+                flavor: AuthFlavor::AuthNone,
+                body: (&[]).into(),
+            },
+            body: AcceptedReplyBody::Success(ProcedureReply::Compound(CompoundResult {
+                error: None,
+                tag: "".into(),
+                resarray: vec![
+                    NfsResOp::Open(Ok(OpenResult {
+                        state_id: StateId {
+                            sequence_id: 1,
+                            other: [1; 12],
+                        },
+                        change_info: ChangeInfo {
+                            atomic: true,
+                            before: 1,
+                            after: 2,
+                        },
+                        flags: OpenResultFlags::LOCKTYPE_POSIX,
+                        attributes: AttributeMask::new(),
+                        delegation: OpenDelegation::Write(OpenWriteDelegation {
+                            state_id: StateId {
+                                sequence_id: 1,
+                                other: [2; 12],
+                            },
+                            recall: false,
+                            space_limit: SpaceLimit::Size(u64::MAX),
+                            permissions: NfsAce {
+                                r#type: AceType::ACCESS_ALLOWED_ACE_TYPE,
+                                flag: AceFlag::empty(),
+                                access_mask: AceAccessMask::empty(),
+                                who: "".into(),
+                            },
+                        }),
+                    })),
+                    NfsResOp::DelegationReturn(Err(Error::BAD_STATEID)),
+                ],
+            })),
+        });
+        let mut buffer = [0u8; 1024];
+        let buffer = serialize!(encode::reply(&reply), buffer);
+        let (buffer, decoded_reply) = decode::reply(ProcedureNumber::Compound)(buffer).unwrap();
+        assert_eq!(buffer, &[]);
+        assert_eq!(reply, decoded_reply);
+    }
+
+    #[test]
+    fn test_encode_decode_callback_call() {
+        let call = CallbackCall {
+            program: 0x40000000,
+            cred: OpaqueAuth {
+                flavor: AuthFlavor::AuthNone,
+                body: (&[]).into(),
+            },
+            verf: OpaqueAuth {
+                flavor: AuthFlavor::AuthNone,
+                body: (&[]).into(),
+            },
+            args: CallbackCompoundArgs {
+                tag: "".into(),
+                minorversion: 1,
+                callback_ident: 0,
+                argarray: vec![
+                    CallbackArgOp::Sequence(CallbackSequenceArgs {
+                        session_id: [3; 16],
+                        sequence_id: 1,
+                        slot_id: 0,
+                        highest_slot_id: 0,
+                        cache_this: false,
+                        referring_call_lists: vec![ReferringCallList {
+                            session_id: [3; 16],
+                            referring_calls: vec![ReferringCall {
+                                sequence_id: 4,
+                                slot_id: 5,
+                            }],
+                        }],
+                    }),
+                    CallbackArgOp::Recall(CallbackRecallArgs {
+                        state_id: StateId {
+                            sequence_id: 1,
+                            other: [2; 12],
+                        },
+                        truncate: true,
+                        file_handle: FileHandle::from(&[1, 2, 3]),
+                    }),
+                ],
+            },
+        };
+        let mut buffer = [0u8; 1024];
+        let buffer = serialize!(encode::callback_call(&call), buffer);
+        let (buffer, decoded_call) = decode::callback_call(buffer).unwrap();
+        assert_eq!(buffer, &[]);
+        assert_eq!(call, decoded_call);
+
+        let result = CallbackCompoundResult {
+            error: Some(Error::BADHANDLE),
+            tag: "".into(),
+            resarray: vec![
+                CallbackResOp::Sequence(Ok(CallbackSequenceResult {
+                    session_id: [3; 16],
+                    sequence_id: 1,
+                    slot_id: 0,
+                    highest_slot_id: 0,
+                    target_highest_slot_id: 0,
+                })),
+                CallbackResOp::Recall(Err(Error::BADHANDLE)),
+            ],
+        };
+        let mut buffer = [0u8; 1024];
+        let buffer = serialize!(encode::callback_reply(&result), buffer);
+        let (buffer, decoded_result) = decode::callback_reply(buffer).unwrap();
+        assert_eq!(buffer, &[]);
+        assert_eq!(Some(result), decoded_result);
+    }
 }
@@ -0,0 +1,92 @@
+use bytes::BytesMut;
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Largest record accepted or produced, leaving room for the compound around a 1 MiB READ or
+/// WRITE payload.
+pub const MAX_RECORD_LENGTH: usize = 1024 * 1024 + 64 * 1024;
+
+const MAX_FRAGMENT_LENGTH: usize = 64 * 1024;
+const LAST_FRAGMENT: u32 = 1 << 31;
+
+/// Reads fragments until the last one of a record, returning `None` if the peer closed the
+/// connection between records.
+pub async fn read<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<Option<BytesMut>> {
+    let mut record = BytesMut::new();
+    loop {
+        let record_mark = match reader.read_u32().await {
+            Ok(record_mark) => record_mark,
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof && record.is_empty() => {
+                return Ok(None);
+            }
+            Err(error) => return Err(error),
+        };
+        let fragment_length = (record_mark & !LAST_FRAGMENT) as usize;
+        if record.len() + fragment_length > MAX_RECORD_LENGTH {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "record exceeds maximum length",
+            ));
+        }
+        let offset = record.len();
+        record.resize(offset + fragment_length, 0);
+        reader.read_exact(&mut record[offset..]).await?;
+        if record_mark & LAST_FRAGMENT != 0 {
+            return Ok(Some(record));
+        }
+    }
+}
+
+/// Writes a record, split into fragments so that large replies are not limited by the size
+/// of a single fragment.
+pub async fn write<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    record: &[u8],
+) -> io::Result<()> {
+    let mut fragments = record.chunks(MAX_FRAGMENT_LENGTH).peekable();
+    if fragments.peek().is_none() {
+        writer.write_u32(LAST_FRAGMENT).await?;
+    }
+    while let Some(fragment) = fragments.next() {
+        let mut record_mark = fragment.len() as u32;
+        if fragments.peek().is_none() {
+            record_mark |= LAST_FRAGMENT;
+        }
+        writer.write_u32(record_mark).await?;
+        writer.write_all(fragment).await?;
+    }
+    writer.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_multi_fragment_record() {
+        let payload = (0..MAX_RECORD_LENGTH).map(|i| i as u8).collect::<Vec<_>>();
+        let (mut client, mut server) = tokio::io::duplex(4096);
+        let writer = tokio::spawn({
+            let payload = payload.clone();
+            async move {
+                write(&mut client, &payload).await.unwrap();
+                write(&mut client, &[]).await.unwrap();
+            }
+        });
+        assert_eq!(read(&mut server).await.unwrap().unwrap(), payload);
+        assert_eq!(read(&mut server).await.unwrap().unwrap(), &[][..]);
+        writer.await.unwrap();
+        assert!(read(&mut server).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_record_too_long() {
+        let (mut client, mut server) = tokio::io::duplex(4096);
+        client
+            .write_u32(LAST_FRAGMENT | (MAX_RECORD_LENGTH as u32 + 1))
+            .await
+            .unwrap();
+        let error = read(&mut server).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
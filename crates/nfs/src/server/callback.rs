@@ -0,0 +1,197 @@
+use crate::protocol::{self, *};
+use crate::record;
+use bytes::BytesMut;
+use cookie_factory::{gen, sequence::tuple};
+use std::collections::HashMap;
+use std::io::{self, Cursor};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::sync::oneshot;
+
+/// How long the server waits for a client to answer a callback.
+const CALLBACK_TIMEOUT: Duration = Duration::from_secs(10);
+const CALLBACK_LENGTH: usize = 1024;
+
+/// The sending half of a connection, shared by the replies to the calls of the client and the
+/// calls that the server makes on the back channel.
+#[derive(Clone)]
+pub struct Channel {
+    writer: Arc<tokio::sync::Mutex<OwnedWriteHalf>>,
+    next_xid: Arc<AtomicU32>,
+    pending: Arc<Mutex<HashMap<u32, oneshot::Sender<BytesMut>>>>,
+}
+
+impl Channel {
+    pub fn new(writer: OwnedWriteHalf) -> Self {
+        Self {
+            writer: Arc::new(tokio::sync::Mutex::new(writer)),
+            next_xid: Arc::new(AtomicU32::new(1)),
+            pending: Arc::default(),
+        }
+    }
+
+    pub async fn write(
+        &self,
+        record: &[u8],
+    ) -> io::Result<()> {
+        let mut writer = self.writer.lock().await;
+        record::write(&mut *writer, record).await
+    }
+
+    /// Sends a call and waits for the reply that the connection hands over with
+    /// [`Channel::reply`].
+    pub async fn call(
+        &self,
+        xid: u32,
+        record: &[u8],
+    ) -> io::Result<BytesMut> {
+        let (sender, receiver) = oneshot::channel();
+        self.pending.lock().unwrap().insert(xid, sender);
+        let result = tokio::time::timeout(CALLBACK_TIMEOUT, async {
+            self.write(record).await?;
+            receiver
+                .await
+                .map_err(|_| io::Error::from(io::ErrorKind::ConnectionAborted))
+        })
+        .await
+        .unwrap_or_else(|_| Err(io::ErrorKind::TimedOut.into()));
+        self.pending.lock().unwrap().remove(&xid);
+        result
+    }
+
+    pub fn next_xid(&self) -> u32 {
+        self.next_xid.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Passes a reply on to the call waiting for it, returning whether there was one.
+    pub fn reply(
+        &self,
+        xid: u32,
+        message: BytesMut,
+    ) -> bool {
+        match self.pending.lock().unwrap().remove(&xid) {
+            Some(sender) => sender.send(message).is_ok(),
+            None => false,
+        }
+    }
+}
+
+/// The back channel of a session, on the connection that created it.
+pub struct BackChannel {
+    channel: Channel,
+    program: u32,
+    session_id: SessionId,
+    cred: OpaqueAuth<'static>,
+    /// The sequence ID of the only slot, which also keeps callbacks to one at a time.
+    sequence_id: tokio::sync::Mutex<SequenceId>,
+}
+
+impl BackChannel {
+    pub fn new(
+        channel: Channel,
+        session_id: SessionId,
+        args: &CreateSessionArgs<'_>,
+    ) -> Self {
+        // RPCSEC_GSS would need a context with the client, so the first of the other flavors
+        // is used.
+        let cred = args
+            .sec_parms
+            .iter()
+            .find_map(|sec_parms| match sec_parms {
+                CallbackSecParms::AuthNone => Some(OpaqueAuth {
+                    flavor: AuthFlavor::AuthNone,
+                    body: vec![].into(),
+                }),
+                CallbackSecParms::AuthSys(parms) => {
+                    let body = gen(protocol::encode::auth_sys_parms(parms), Vec::new())
+                        .ok()?
+                        .0;
+                    Some(OpaqueAuth {
+                        flavor: AuthFlavor::AuthSys,
+                        body: body.into(),
+                    })
+                }
+                CallbackSecParms::RpcSecGss(_) => None,
+            })
+            .unwrap_or(OpaqueAuth {
+                flavor: AuthFlavor::AuthNone,
+                body: vec![].into(),
+            });
+        Self {
+            channel,
+            program: args.cb_program,
+            session_id,
+            cred,
+            sequence_id: tokio::sync::Mutex::new(0),
+        }
+    }
+
+    /// Asks the client to return a delegation with CB_RECALL.
+    pub async fn recall(
+        &self,
+        state_id: &StateId,
+        file_handle: &FileHandle<'_>,
+    ) -> Result<(), Error> {
+        tracing::debug!("CB_RECALL {state_id:?}");
+        let mut sequence_id = self.sequence_id.lock().await;
+        *sequence_id = sequence_id.wrapping_add(1);
+        let call = CallbackCall {
+            program: self.program,
+            cred: self.cred.clone(),
+            verf: OpaqueAuth {
+                flavor: AuthFlavor::AuthNone,
+                body: (&[]).into(),
+            },
+            args: CallbackCompoundArgs {
+                tag: "".into(),
+                minorversion: 1,
+                callback_ident: 0,
+                argarray: vec![
+                    CallbackArgOp::Sequence(CallbackSequenceArgs {
+                        session_id: self.session_id,
+                        sequence_id: *sequence_id,
+                        slot_id: 0,
+                        highest_slot_id: 0,
+                        cache_this: false,
+                        referring_call_lists: vec![],
+                    }),
+                    CallbackArgOp::Recall(CallbackRecallArgs {
+                        state_id: state_id.clone(),
+                        truncate: false,
+                        file_handle: file_handle.clone(),
+                    }),
+                ],
+            },
+        };
+        let xid = self.channel.next_xid();
+        let mut buffer = vec![0; CALLBACK_LENGTH];
+        let generator = tuple((
+            protocol::encode::message(RpcMessage {
+                xid,
+                message_type: MessageType::Call,
+            }),
+            protocol::encode::callback_call(&call),
+        ));
+        let (_, length) =
+            gen(generator, Cursor::new(&mut buffer[..])).map_err(|_| Error::SERVERFAULT)?;
+        let reply = match self.channel.call(xid, &buffer[..length as usize]).await {
+            Ok(reply) => reply,
+            Err(error) => {
+                tracing::debug!("Callback failed: {error}");
+                return Err(Error::CB_PATH_DOWN);
+            }
+        };
+        let Ok((_, Some(result))) = protocol::decode::message(&reply)
+            .and_then(|(input, _)| protocol::decode::callback_reply(input))
+        else {
+            tracing::debug!("Invalid callback reply.");
+            return Err(Error::CB_PATH_DOWN);
+        };
+        match result.error {
+            None => Ok(()),
+            Some(error) => Err(error),
+        }
+    }
+}
@@ -1,5 +1,10 @@
-use super::Handler;
+use super::callback::{BackChannel, Channel};
+use super::delegation::Delegated;
+use super::state::{Released, Sequence, State};
+use super::{Credentials, Handler};
 use crate::protocol::*;
+use std::borrow::Cow;
+use std::sync::Arc;
 use tokio::sync::RwLock;
 
 #[derive(Clone)]
@@ -8,20 +13,38 @@ where
     T: Handler + Clone + Send + Sync + 'static,
 {
     handler: T,
+    state: Arc<State>,
+    channel: Channel,
 }
 
 impl<T> Connection<T>
 where
     T: Handler + Clone + Send + Sync + 'static,
 {
-    pub fn new(handler: T) -> Self {
-        Self { handler }
+    pub fn new(
+        handler: T,
+        state: Arc<State>,
+        channel: Channel,
+    ) -> Self {
+        Self {
+            handler,
+            state,
+            channel,
+        }
     }
 
-    pub fn begin(&self) -> Transaction<T> {
+    pub fn begin(
+        &self,
+        credentials: Credentials,
+    ) -> Transaction<T> {
         Transaction {
             handler: &self.handler,
+            state: &self.state,
+            channel: &self.channel,
+            credentials,
+            session: RwLock::new(None),
             current_file_handle: RwLock::new(None),
+            saved_file_handle: RwLock::new(None),
         }
     }
 }
@@ -31,7 +54,19 @@ where
     T: Handler + Clone + Send + Sync + 'static,
 {
     handler: &'a T,
+    state: &'a Arc<State>,
+    channel: &'a Channel,
+    credentials: Credentials,
+    session: RwLock<Option<SessionSlot>>,
     current_file_handle: RwLock<Option<FileHandle<'a>>>,
+    saved_file_handle: RwLock<Option<FileHandle<'a>>>,
+}
+
+/// The session slot that a compound was sequenced on.
+struct SessionSlot {
+    session_id: SessionId,
+    slot_id: SlotId,
+    client_id: ClientId,
 }
 
 impl<'a, T> Transaction<'a, T>
@@ -44,7 +79,11 @@ where
     ) -> Result<AccessResult, Error> {
         tracing::debug!("ACCESS");
         match *self.current_file_handle.read().await {
-            Some(ref file_handle) => self.handler.access(file_handle, flags).await,
+            Some(ref file_handle) => {
+                self.handler
+                    .access(&self.credentials, file_handle, flags)
+                    .await
+            }
             None => Err(Error::NOENT),
         }
     }
@@ -58,10 +97,140 @@ where
             return Err(Error::NOENT);
         };
         let open_state_id = args.open_state_id.clone();
-        self.handler.close(file_handle, args).await?;
+        self.handler
+            .close(&self.credentials, file_handle, args)
+            .await?;
+        if let Ok(client_id) = self.client_id().await {
+            self.state.close(client_id, file_handle);
+        }
         Ok(open_state_id)
     }
 
+    pub async fn commit(
+        &self,
+        args: CommitArgs,
+    ) -> Result<Verifier, Error> {
+        tracing::debug!("COMMIT");
+        match *self.current_file_handle.read().await {
+            Some(ref file_handle) => {
+                self.handler
+                    .commit(&self.credentials, file_handle, args)
+                    .await
+            }
+            None => Err(Error::NOFILEHANDLE),
+        }
+    }
+
+    pub async fn create(
+        &self,
+        args: CreateArgs<'a>,
+    ) -> Result<CreateResult<'a>, Error> {
+        tracing::debug!("CREATE");
+        let mut file_handle_guard = self.current_file_handle.write().await;
+        let Some(ref directory) = *file_handle_guard else {
+            return Err(Error::NOFILEHANDLE);
+        };
+        let (file_handle, create_result) = self
+            .handler
+            .create(&self.credentials, directory, args)
+            .await?;
+        *file_handle_guard = Some(file_handle);
+        Ok(create_result)
+    }
+
+    pub async fn delegation_return(
+        &self,
+        state_id: StateId,
+    ) -> Result<(), Error> {
+        tracing::debug!("DELEGRETURN");
+        let Some(ref file_handle) = *self.current_file_handle.read().await else {
+            return Err(Error::NOFILEHANDLE);
+        };
+        let client_id = self.client_id().await?;
+        self.state
+            .delegation_return(client_id, file_handle, &state_id)
+    }
+
+    pub async fn link(
+        &self,
+        name: &str,
+    ) -> Result<ChangeInfo, Error> {
+        tracing::debug!("LINK");
+        let Some(ref source) = *self.saved_file_handle.read().await else {
+            return Err(Error::NOFILEHANDLE);
+        };
+        let Some(ref directory) = *self.current_file_handle.read().await else {
+            return Err(Error::NOFILEHANDLE);
+        };
+        self.recall_delegations(source, true).await?;
+        self.handler
+            .link(&self.credentials, source, directory, name)
+            .await
+    }
+
+    pub async fn lock(
+        &self,
+        args: LockArgs<'a>,
+    ) -> Result<StateId, LockError<'a>> {
+        tracing::debug!("LOCK");
+        let Some(ref file_handle) = *self.current_file_handle.read().await else {
+            return Err(Error::NOFILEHANDLE.into());
+        };
+        let client_id = self.client_id().await?;
+        let locked = self.state.lock(client_id, file_handle, &args)?;
+        let result = self
+            .handler
+            .lock(
+                file_handle,
+                &locked.owner,
+                args.lock_type,
+                args.offset,
+                args.length,
+            )
+            .await;
+        if let Err(error) = result {
+            // Give the range back so that the server does not hold a lock the backend refused.
+            let args = UnlockArgs {
+                lock_type: args.lock_type,
+                sequence_id: 0,
+                lock_state_id: locked.state_id,
+                offset: args.offset,
+                length: args.length,
+            };
+            self.state.unlock(client_id, file_handle, &args)?;
+            return Err(error.into());
+        }
+        Ok(locked.state_id)
+    }
+
+    pub async fn lock_test(
+        &self,
+        args: LockTestArgs<'a>,
+    ) -> Result<(), LockError<'a>> {
+        tracing::debug!("LOCKT");
+        let Some(ref file_handle) = *self.current_file_handle.read().await else {
+            return Err(Error::NOFILEHANDLE.into());
+        };
+        let client_id = self.client_id().await?;
+        self.state.lock_test(client_id, file_handle, &args)
+    }
+
+    pub async fn unlock(
+        &self,
+        args: UnlockArgs,
+    ) -> Result<StateId, Error> {
+        tracing::debug!("LOCKU");
+        let Some(ref file_handle) = *self.current_file_handle.read().await else {
+            return Err(Error::NOFILEHANDLE);
+        };
+        let client_id = self.client_id().await?;
+        let unlocked = self.state.unlock(client_id, file_handle, &args)?;
+        self.handler
+            .unlock(file_handle, &unlocked.owner, args.offset, args.length)
+            .await?;
+        Ok(unlocked.state_id)
+    }
+
     pub async fn lookup(
         &self,
         name: &str,
@@ -71,29 +240,118 @@ where
         let Some(ref file_handle) = *file_handle_guard else {
             return Err(Error::NOENT);
         };
-        let file_handle = self.handler.lookup(file_handle, name).await?;
+        let file_handle = self
+            .handler
+            .lookup(&self.credentials, file_handle, name)
+            .await?;
+        *file_handle_guard = Some(file_handle);
+        Ok(())
+    }
+
+    pub async fn lookup_parent(&self) -> Result<(), Error> {
+        tracing::debug!("LOOKUPP");
+        let mut file_handle_guard = self.current_file_handle.write().await;
+        let Some(ref file_handle) = *file_handle_guard else {
+            return Err(Error::NOFILEHANDLE);
+        };
+        let file_handle = self
+            .handler
+            .lookup_parent(&self.credentials, file_handle)
+            .await?;
         *file_handle_guard = Some(file_handle);
         Ok(())
     }
 
     pub async fn open(
         &self,
-        args: OpenArgs<'a>,
+        mut args: OpenArgs<'a>,
     ) -> Result<OpenResult<'a>, Error> {
         tracing::debug!("OPEN");
         let mut file_handle_guard = self.current_file_handle.write().await;
-        let (file_handle, open_result) = self.handler.open(args).await?;
+        let Some(ref directory) = *file_handle_guard else {
+            return Err(Error::NOFILEHANDLE);
+        };
+        let client_id = self.client_id().await?;
+        let share_access = args.share_access;
+        let write = share_access.contains(ShareAccessFlags::WRITE);
+        let target = match args.claim {
+            // The file may not exist yet, in which case nobody holds a delegation on it.
+            OpenClaim::Null(name) => self
+                .handler
+                .lookup(&self.credentials, directory, name)
+                .await
+                .ok(),
+            OpenClaim::DelegateCurrent(_, name) => Some(
+                self.handler
+                    .lookup(&self.credentials, directory, name)
+                    .await?,
+            ),
+            OpenClaim::FileHandle | OpenClaim::DelegateCurrentFileHandle(_) => {
+                Some(directory.clone())
+            }
+            _ => None,
+        };
+        // Opens under a delegation are plain opens to the handler, which knows nothing about
+        // delegations.
+        args.claim = match args.claim {
+            OpenClaim::DelegateCurrent(state_id, name) => {
+                if let Some(ref target) = target {
+                    self.state
+                        .validate_delegation(client_id, target, &state_id)?;
+                }
+                OpenClaim::Null(name)
+            }
+            OpenClaim::DelegateCurrentFileHandle(state_id) => {
+                self.state
+                    .validate_delegation(client_id, directory, &state_id)?;
+                OpenClaim::FileHandle
+            }
+            claim => claim,
+        };
+        if let Some(ref target) = target {
+            self.recall_delegations(target, write).await?;
+        }
+        let (file_handle, mut open_result) = self
+            .handler
+            .open(&self.credentials, directory, args)
+            .await?;
+        self.state.open(client_id, &file_handle, write);
+        if open_result.delegation == OpenDelegation::None && self.handler.delegates(&file_handle) {
+            if let Some(delegated) =
+                self.state
+                    .grant_delegation(client_id, &file_handle, share_access)
+            {
+                tracing::debug!("Delegated {:?}.", delegated.state_id);
+                open_result.delegation = open_delegation(delegated);
+            }
+        }
         *file_handle_guard = Some(file_handle);
         Ok(open_result)
     }
 
+    /// Named attributes are not supported.
+    pub async fn open_attributes(
+        &self,
+        _args: OpenAttributesArgs,
+    ) -> Result<(), Error> {
+        tracing::debug!("OPENATTR");
+        match *self.current_file_handle.read().await {
+            Some(_) => Err(Error::NOTSUPP),
+            None => Err(Error::NOFILEHANDLE),
+        }
+    }
+
     pub async fn get_attributes(
         &self,
         mask: AttributeMask<'a>,
     ) -> Result<Vec<AttributeValue<'a>>, Error> {
         tracing::debug!("GETATTR");
         match *self.current_file_handle.read().await {
-            Some(ref file_handle) => self.handler.get_attributes(file_handle, mask).await,
+            Some(ref file_handle) => {
+                self.handler
+                    .get_attributes(&self.credentials, file_handle, mask)
+                    .await
+            }
             None => Err(Error::NOENT),
         }
     }
@@ -127,7 +385,11 @@ where
     ) -> Result<ReadResult<'a>, Error> {
         tracing::debug!("READ");
         match *self.current_file_handle.read().await {
-            Some(ref file_handle) => self.handler.read(file_handle, args).await,
+            Some(ref file_handle) => {
+                self.handler
+                    .read(&self.credentials, file_handle, args)
+                    .await
+            }
             None => Err(Error::NOENT),
         }
     }
@@ -138,20 +400,125 @@ where
     ) -> Result<ReadDirectoryResult<'a>, Error> {
         tracing::debug!("READDIR");
         match *self.current_file_handle.read().await {
-            Some(ref file_handle) => self.handler.read_directory(file_handle, args).await,
+            Some(ref file_handle) => {
+                self.handler
+                    .read_directory(&self.credentials, file_handle, args)
+                    .await
+            }
             None => Err(Error::NOENT),
         }
     }
 
+    pub async fn read_link(&self) -> Result<Cow<'a, str>, Error> {
+        tracing::debug!("READLINK");
+        match *self.current_file_handle.read().await {
+            Some(ref file_handle) => self.handler.read_link(&self.credentials, file_handle).await,
+            None => Err(Error::NOFILEHANDLE),
+        }
+    }
+
+    pub async fn remove(
+        &self,
+        name: &str,
+    ) -> Result<ChangeInfo, Error> {
+        tracing::debug!("REMOVE");
+        let Some(ref directory) = *self.current_file_handle.read().await else {
+            return Err(Error::NOFILEHANDLE);
+        };
+        self.recall_entry_delegations(directory, name).await?;
+        self.handler
+            .remove(&self.credentials, directory, name)
+            .await
+    }
+
+    pub async fn rename(
+        &self,
+        args: RenameArgs<'a>,
+    ) -> Result<RenameResult, Error> {
+        tracing::debug!("RENAME");
+        let Some(ref source_directory) = *self.saved_file_handle.read().await else {
+            return Err(Error::NOFILEHANDLE);
+        };
+        let Some(ref target_directory) = *self.current_file_handle.read().await else {
+            return Err(Error::NOFILEHANDLE);
+        };
+        self.recall_entry_delegations(source_directory, args.old_name)
+            .await?;
+        self.recall_entry_delegations(target_directory, args.new_name)
+            .await?;
+        self.handler
+            .rename(&self.credentials, source_directory, target_directory, args)
+            .await
+    }
+
+    pub async fn restore_file_handle(&self) -> Result<(), Error> {
+        tracing::debug!("RESTOREFH");
+        let Some(ref file_handle) = *self.saved_file_handle.read().await else {
+            return Err(Error::RESTOREFH);
+        };
+        *self.current_file_handle.write().await = Some(file_handle.clone());
+        Ok(())
+    }
+
+    pub async fn save_file_handle(&self) -> Result<(), Error> {
+        tracing::debug!("SAVEFH");
+        let Some(ref file_handle) = *self.current_file_handle.read().await else {
+            return Err(Error::NOFILEHANDLE);
+        };
+        *self.saved_file_handle.write().await = Some(file_handle.clone());
+        Ok(())
+    }
+
+    pub async fn set_attributes(
+        &self,
+        args: SetAttributesArgs<'a>,
+    ) -> Result<AttributeMask<'a>, Error> {
+        tracing::debug!("SETATTR");
+        let Some(ref file_handle) = *self.current_file_handle.read().await else {
+            return Err(Error::NOFILEHANDLE);
+        };
+        if args
+            .attributes
+            .iter()
+            .any(|attribute| matches!(attribute, AttributeValue::Size(_)))
+        {
+            self.recall_delegations(file_handle, true).await?;
+        }
+        self.handler
+            .set_attributes(&self.credentials, file_handle, args)
+            .await
+    }
+
+    pub async fn write(
+        &self,
+        args: WriteArgs<'a>,
+    ) -> Result<WriteResult, Error> {
+        tracing::debug!("WRITE");
+        let Some(ref file_handle) = *self.current_file_handle.read().await else {
+            return Err(Error::NOFILEHANDLE);
+        };
+        self.recall_delegations(file_handle, true).await?;
+        self.handler
+            .write(&self.credentials, file_handle, args)
+            .await
+    }
+
     pub async fn exchange_id<'b>(
         &self,
         args: ExchangeIdArgs<'b>,
     ) -> Result<ExchangeIdResult<'b>, Error> {
         tracing::debug!("EXCHANGE_ID");
+        self.expire_leases().await;
+        let (exchange_id, released) = self.state.exchange_id(&args.clientowner);
+        self.release(released).await;
+        let mut flags = ExchangeIdFlags::USE_PNFS_MDS | ExchangeIdFlags::SUPP_MOVED_REFER;
+        if exchange_id.confirmed {
+            flags |= ExchangeIdFlags::CONFIRMED_R;
+        }
         Ok(ExchangeIdResult {
-            client_id: 1,
-            sequence_id: 1,
-            flags: ExchangeIdFlags::USE_PNFS_MDS | ExchangeIdFlags::SUPP_MOVED_REFER,
+            client_id: exchange_id.client_id,
+            sequence_id: exchange_id.sequence_id,
+            flags,
             state_protect: StateProtectResult::None,
             server_owner: ServerOwner {
                 minor_id: 0,
@@ -174,13 +541,15 @@ where
         args: CreateSessionArgs<'_>,
     ) -> Result<CreateSessionResult, Error> {
         tracing::debug!("CREATE_SESSION");
-        Ok(CreateSessionResult {
-            session_id: [1; 16].into(),
-            sequence_id: args.sequence_id,
-            flags: CreateSessionFlags::CONN_BACK_CHAN,
-            fore_channel_attributes: args.fore_channel_attributes,
-            back_channel_attributes: args.back_channel_attributes,
-        })
+        self.expire_leases().await;
+        let (result, released) = self.state.create_session(&args)?;
+        self.release(released).await;
+        if result.flags.contains(CreateSessionFlags::CONN_BACK_CHAN) {
+            let back_channel = BackChannel::new(self.channel.clone(), result.session_id, &args);
+            self.state
+                .bind_back_channel(&result.session_id, Arc::new(back_channel));
+        }
+        Ok(result)
     }
 
     pub async fn destroy_session(
@@ -188,6 +557,7 @@ where
         session_id: SessionId,
     ) -> Result<(), Error> {
         tracing::debug!("DESTROY_SESSION");
+        self.state.destroy_session(&session_id)?;
         self.handler.destroy_session(session_id).await
     }
 
@@ -196,23 +566,29 @@ where
         client_id: ClientId,
     ) -> Result<(), Error> {
         tracing::debug!("DESTROY_CLIENT_ID");
-        self.handler.destroy_client_id(client_id).await
+        let released = self.state.destroy_client_id(client_id)?;
+        self.release(released).await;
+        Ok(())
     }
 
     pub async fn get_security_info(
         &self,
         args: GetSecurityInfoArgs<'_>,
-    ) -> GetSecurityInfoResult {
+    ) -> GetSecurityInfoResult<'a> {
         tracing::debug!("SECINFO");
-        GetSecurityInfoResult::Ok(GetSecurityInfoResultOk(vec![GetSecurityInfo::AuthNone]))
+        GetSecurityInfoResult::Ok(GetSecurityInfoResultOk(vec![
+            GetSecurityInfo::AuthSys,
+            GetSecurityInfo::AuthNone,
+        ]))
     }
 
     pub async fn get_security_info_no_name(
         &self,
         args: GetSecurityInfoNoNameArgs,
-    ) -> GetSecurityInfoNoNameResult {
+    ) -> GetSecurityInfoNoNameResult<'a> {
         tracing::debug!("SECINFO_NO_NAME");
         GetSecurityInfoNoNameResult(GetSecurityInfoResult::Ok(GetSecurityInfoResultOk(vec![
+            GetSecurityInfo::AuthSys,
             GetSecurityInfo::AuthNone,
         ])))
     }
@@ -220,16 +596,29 @@ where
     pub async fn sequence(
         &self,
         args: SequenceArgs,
-    ) -> Result<SequenceResult, Error> {
+    ) -> Result<Sequence, Error> {
         tracing::debug!("SEQUENCE");
-        Ok(SequenceResult {
-            session_id: args.session_id,
-            sequence_id: args.sequence_id,
-            slot_id: args.slot_id,
-            highest_slot_id: args.highest_slot_id,
-            target_highest_slot_id: args.highest_slot_id,
-            status_flags: SequenceStatusFlags::empty(),
-        })
+        self.expire_leases().await;
+        let sequence = self.state.sequence(&args)?;
+        if let Sequence::New { client_id, .. } = sequence {
+            *self.session.write().await = Some(SessionSlot {
+                session_id: args.session_id,
+                slot_id: args.slot_id,
+                client_id,
+            });
+        }
+        Ok(sequence)
+    }
+
+    /// Releases the slot of the compound, caching the encoded reply for retransmissions.
+    pub async fn complete(
+        &self,
+        reply: Option<Vec<u8>>,
+    ) {
+        if let Some(ref session) = *self.session.read().await {
+            self.state
+                .complete(&session.session_id, session.slot_id, reply);
+        }
     }
 
     pub async fn reclaim_complete(
@@ -237,6 +626,111 @@ where
         args: ReclaimCompleteArgs,
     ) -> Result<(), Error> {
         tracing::debug!("RECLAIM_COMPLETE");
-        Ok(())
+        let Some(ref session) = *self.session.read().await else {
+            return Err(Error::OP_NOT_IN_SESSION);
+        };
+        self.state.reclaim_complete(session.client_id, args.one_fs)
+    }
+
+    /// Recalls the delegations of other clients that reading or writing the file conflicts
+    /// with, failing with DELAY until they are returned or revoked.
+    async fn recall_delegations(
+        &self,
+        file_handle: &FileHandle<'_>,
+        write: bool,
+    ) -> Result<(), Error> {
+        let client_id = self.client_id().await?;
+        let Some(recalls) = self.state.recall_delegations(client_id, file_handle, write) else {
+            return Ok(());
+        };
+        for (back_channel, recall) in recalls {
+            let state = self.state.clone();
+            tokio::spawn(async move {
+                let result = back_channel
+                    .recall(&recall.state_id, &recall.file_handle)
+                    .await;
+                if let Err(error) = result {
+                    tracing::debug!("Failed to recall delegation: {error:?}");
+                    state.revoke_delegation(&recall.state_id);
+                }
+            });
+        }
+        Err(Error::DELAY)
+    }
+
+    /// Recalls the delegations on the entry of the directory, if there is one, before it is
+    /// removed or replaced.
+    async fn recall_entry_delegations(
+        &self,
+        directory: &FileHandle<'_>,
+        name: &str,
+    ) -> Result<(), Error> {
+        match self
+            .handler
+            .lookup(&self.credentials, directory, name)
+            .await
+        {
+            Ok(file_handle) => self.recall_delegations(&file_handle, true).await,
+            Err(_) => Ok(()),
+        }
+    }
+
+    async fn client_id(&self) -> Result<ClientId, Error> {
+        match *self.session.read().await {
+            Some(ref session) => Ok(session.client_id),
+            None => Err(Error::OP_NOT_IN_SESSION),
+        }
+    }
+
+    async fn expire_leases(&self) {
+        let released = self.state.expire();
+        self.release(released).await;
+    }
+
+    /// Lets the handler drop whatever it kept for sessions, clients and locks the server
+    /// forgot.
+    async fn release(
+        &self,
+        released: Released,
+    ) {
+        for (file_handle, owner) in released.locks {
+            if let Err(error) = self.handler.unlock(&file_handle, &owner, 0, u64::MAX).await {
+                tracing::debug!("Failed to release lock: {error:?}");
+            }
+        }
+        for session_id in released.sessions {
+            if let Err(error) = self.handler.destroy_session(session_id).await {
+                tracing::debug!("Failed to release session: {error:?}");
+            }
+        }
+        for client_id in released.clients {
+            if let Err(error) = self.handler.destroy_client_id(client_id).await {
+                tracing::debug!("Failed to release client: {error:?}");
+            }
+        }
+    }
+}
+
+/// Opens are checked against the mode of the file every time, so the ACE of a delegation
+/// allows nothing by itself.
+fn open_delegation<'a>(delegated: Delegated) -> OpenDelegation<'a> {
+    let permissions = NfsAce {
+        r#type: AceType::ACCESS_ALLOWED_ACE_TYPE,
+        flag: AceFlag::empty(),
+        access_mask: AceAccessMask::empty(),
+        who: "".into(),
+    };
+    match delegated.write {
+        true => OpenDelegation::Write(OpenWriteDelegation {
+            state_id: delegated.state_id,
+            recall: false,
+            space_limit: SpaceLimit::Size(u64::MAX),
+            permissions,
+        }),
+        false => OpenDelegation::Read(OpenReadDelegation {
+            state_id: delegated.state_id,
+            recall: false,
+            permissions,
+        }),
     }
 }
@@ -0,0 +1,322 @@
+use crate::protocol::*;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Files that clients hold open and the delegations handed out on them, keyed by the `other`
+/// field of their stateids.
+#[derive(Default)]
+pub struct Delegations {
+    next_state_id: u64,
+    /// Whether each client has a file open for writing, by client and file handle.
+    opens: HashMap<(ClientId, Vec<u8>), bool>,
+    delegations: HashMap<[u8; 12], Delegation>,
+}
+
+struct Delegation {
+    client_id: ClientId,
+    file_handle: Vec<u8>,
+    write: bool,
+    /// When the delegation was recalled, after which its holder has a lease period to return
+    /// it.
+    recalled: Option<Instant>,
+}
+
+/// A delegation granted with an open.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Delegated {
+    pub state_id: StateId,
+    pub write: bool,
+}
+
+/// A delegation to recall from its holder.
+#[derive(Debug)]
+pub struct Recall {
+    pub client_id: ClientId,
+    pub state_id: StateId,
+    pub file_handle: FileHandle<'static>,
+}
+
+impl Delegations {
+    pub fn open(
+        &mut self,
+        client_id: ClientId,
+        file_handle: &[u8],
+        write: bool,
+    ) {
+        *self
+            .opens
+            .entry((client_id, file_handle.to_vec()))
+            .or_default() |= write;
+    }
+
+    pub fn close(
+        &mut self,
+        client_id: ClientId,
+        file_handle: &[u8],
+    ) {
+        self.opens.remove(&(client_id, file_handle.to_vec()));
+    }
+
+    /// Hands out a delegation with an open unless the client asked for none, already holds
+    /// one, or other clients use the file in a way the delegation would conflict with.
+    pub fn grant(
+        &mut self,
+        client_id: ClientId,
+        file_handle: &[u8],
+        share_access: ShareAccessFlags,
+    ) -> Option<Delegated> {
+        let want = share_access & ShareAccessFlags::WANT_DELEG_MASK;
+        if want == ShareAccessFlags::WANT_NO_DELEG || want == ShareAccessFlags::WANT_CANCEL {
+            return None;
+        }
+        // Nothing is handed out next to a write delegation or while a recall is under way.
+        if self.delegations.values().any(|delegation| {
+            delegation.file_handle == file_handle
+                && (delegation.client_id == client_id
+                    || delegation.write
+                    || delegation.recalled.is_some())
+        }) {
+            return None;
+        }
+        let shared = self
+            .delegations
+            .values()
+            .any(|delegation| delegation.file_handle == file_handle);
+        let mut opened_by_others = self
+            .opens
+            .iter()
+            .filter(|((holder, held), _)| *holder != client_id && held == file_handle);
+        let written_by_others = opened_by_others.clone().any(|(_, write)| *write);
+        let can_write = share_access.contains(ShareAccessFlags::WRITE)
+            && !shared
+            && opened_by_others.next().is_none();
+        let write = match want {
+            ShareAccessFlags::WANT_READ_DELEG => false,
+            ShareAccessFlags::WANT_WRITE_DELEG if !can_write => return None,
+            _ => can_write,
+        };
+        // A file opened for writing is not read delegated, since the client would write to
+        // it without telling other readers.
+        if !write && (written_by_others || share_access.contains(ShareAccessFlags::WRITE)) {
+            return None;
+        }
+        self.next_state_id += 1;
+        let mut key = [0; 12];
+        key[..4].copy_from_slice(b"DELE");
+        key[4..].copy_from_slice(&self.next_state_id.to_be_bytes());
+        self.delegations.insert(
+            key,
+            Delegation {
+                client_id,
+                file_handle: file_handle.to_vec(),
+                write,
+                recalled: None,
+            },
+        );
+        Some(Delegated {
+            state_id: StateId {
+                sequence_id: 1,
+                other: key,
+            },
+            write,
+        })
+    }
+
+    /// Whether delegations of other clients stand in the way of the client opening the file.
+    /// Reads only conflict with write delegations.
+    pub fn conflicts(
+        &self,
+        client_id: ClientId,
+        file_handle: &[u8],
+        write: bool,
+    ) -> bool {
+        self.conflicting(client_id, file_handle, write)
+            .next()
+            .is_some()
+    }
+
+    /// Marks the delegations that conflict with the open as recalled, returning those that
+    /// were not recalled before.
+    pub fn recall(
+        &mut self,
+        client_id: ClientId,
+        file_handle: &[u8],
+        write: bool,
+    ) -> Vec<Recall> {
+        let keys = self
+            .conflicting(client_id, file_handle, write)
+            .filter(|(_, delegation)| delegation.recalled.is_none())
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+        let now = Instant::now();
+        keys.into_iter()
+            .map(|key| {
+                let delegation = self.delegations.get_mut(&key).unwrap();
+                delegation.recalled = Some(now);
+                Recall {
+                    client_id: delegation.client_id,
+                    state_id: StateId {
+                        sequence_id: 1,
+                        other: key,
+                    },
+                    file_handle: FileHandle::from(delegation.file_handle.clone()),
+                }
+            })
+            .collect()
+    }
+
+    /// Checks that the client holds the delegation on the file.
+    pub fn validate(
+        &self,
+        client_id: ClientId,
+        file_handle: &[u8],
+        state_id: &StateId,
+    ) -> Result<(), Error> {
+        self.delegations
+            .get(&state_id.other)
+            .filter(|delegation| {
+                delegation.client_id == client_id && delegation.file_handle == file_handle
+            })
+            .ok_or(Error::BAD_STATEID)?;
+        // Delegation stateids never change, so their sequence ID stays at one.
+        match state_id.sequence_id {
+            0 | 1 => Ok(()),
+            _ => Err(Error::BAD_STATEID),
+        }
+    }
+
+    pub fn delegation_return(
+        &mut self,
+        client_id: ClientId,
+        file_handle: &[u8],
+        state_id: &StateId,
+    ) -> Result<(), Error> {
+        self.validate(client_id, file_handle, state_id)?;
+        self.delegations.remove(&state_id.other);
+        Ok(())
+    }
+
+    pub fn revoke(
+        &mut self,
+        state_id: &StateId,
+    ) {
+        self.delegations.remove(&state_id.other);
+    }
+
+    /// Revokes the delegations that were not returned within the lease time of their recall.
+    pub fn expire(
+        &mut self,
+        lease_time: Duration,
+    ) -> Vec<StateId> {
+        let mut revoked = Vec::new();
+        self.delegations
+            .retain(|key, delegation| match delegation.recalled {
+                Some(recalled) if recalled.elapsed() >= lease_time => {
+                    revoked.push(StateId {
+                        sequence_id: 1,
+                        other: *key,
+                    });
+                    false
+                }
+                _ => true,
+            });
+        revoked
+    }
+
+    pub fn remove_client(
+        &mut self,
+        client_id: ClientId,
+    ) {
+        self.opens.retain(|(holder, _), _| *holder != client_id);
+        self.delegations
+            .retain(|_, delegation| delegation.client_id != client_id);
+    }
+
+    fn conflicting<'a>(
+        &'a self,
+        client_id: ClientId,
+        file_handle: &'a [u8],
+        write: bool,
+    ) -> impl Iterator<Item = (&'a [u8; 12], &'a Delegation)> + Clone + 'a {
+        self.delegations.iter().filter(move |(_, delegation)| {
+            delegation.client_id != client_id
+                && delegation.file_handle == file_handle
+                && (write || delegation.write)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &[u8] = &[1, 0, 0, 0, 0, 0, 0, 0, 7];
+
+    #[test]
+    fn test_write_delegation_requires_exclusive_use() {
+        let mut delegations = Delegations::default();
+        delegations.open(2, FILE, false);
+        delegations.open(1, FILE, true);
+        assert_eq!(delegations.grant(1, FILE, ShareAccessFlags::WRITE), None);
+        delegations.close(2, FILE);
+        let delegated = delegations.grant(1, FILE, ShareAccessFlags::WRITE).unwrap();
+        assert!(delegated.write);
+        assert_eq!(delegations.grant(1, FILE, ShareAccessFlags::WRITE), None);
+        assert!(delegations.conflicts(2, FILE, false));
+        assert!(!delegations.conflicts(1, FILE, true));
+    }
+
+    #[test]
+    fn test_shared_read_delegations() {
+        let mut delegations = Delegations::default();
+        for client_id in [1, 2] {
+            delegations.open(client_id, FILE, false);
+            let delegated = delegations
+                .grant(client_id, FILE, ShareAccessFlags::READ)
+                .unwrap();
+            assert!(!delegated.write);
+        }
+        assert!(!delegations.conflicts(3, FILE, false));
+        assert!(delegations.conflicts(3, FILE, true));
+        assert_eq!(
+            delegations.grant(
+                3,
+                FILE,
+                ShareAccessFlags::READ | ShareAccessFlags::WANT_NO_DELEG
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_recall_and_return() {
+        let mut delegations = Delegations::default();
+        delegations.open(1, FILE, false);
+        let delegated = delegations.grant(1, FILE, ShareAccessFlags::READ).unwrap();
+        let recalls = delegations.recall(2, FILE, true);
+        assert_eq!(recalls.len(), 1);
+        assert_eq!(recalls[0].client_id, 1);
+        assert_eq!(recalls[0].state_id, delegated.state_id);
+        assert!(delegations.recall(2, FILE, true).is_empty());
+        assert!(delegations.conflicts(2, FILE, true));
+        assert_eq!(
+            delegations.delegation_return(2, FILE, &delegated.state_id),
+            Err(Error::BAD_STATEID)
+        );
+        assert_eq!(
+            delegations.delegation_return(1, FILE, &delegated.state_id),
+            Ok(())
+        );
+        assert!(!delegations.conflicts(2, FILE, true));
+    }
+
+    #[test]
+    fn test_expire_revokes_recalled_delegations() {
+        let mut delegations = Delegations::default();
+        delegations.grant(1, FILE, ShareAccessFlags::READ).unwrap();
+        assert!(delegations.expire(Duration::ZERO).is_empty());
+        delegations.recall(2, FILE, true);
+        assert_eq!(delegations.expire(Duration::ZERO).len(), 1);
+        assert!(!delegations.conflicts(2, FILE, true));
+    }
+}
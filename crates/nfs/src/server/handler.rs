@@ -1,46 +1,160 @@
+use super::Credentials;
 use crate::protocol::*;
+use std::borrow::Cow;
 
+/// The operations on files carry the credentials of the caller, after identity mapping, for
+/// the handler to check access against.
 pub trait Handler {
     fn access<'a>(
         &self,
+        credentials: &Credentials,
         file_handle: &FileHandle<'a>,
         flags: AccessFlags,
     ) -> impl std::future::Future<Output = Result<AccessResult, Error>> + Send;
 
     fn close<'a>(
         &self,
+        credentials: &Credentials,
         file_handle: &FileHandle<'a>,
         args: CloseArgs,
     ) -> impl std::future::Future<Output = Result<(), Error>> + Send;
 
     fn lookup<'a>(
         &self,
+        credentials: &Credentials,
         file_handle: &FileHandle<'a>,
         name: &str,
     ) -> impl std::future::Future<Output = Result<FileHandle<'a>, Error>> + Send;
 
+    /// Fails with [`Error::NOENT`] for the root.
+    fn lookup_parent<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'a>,
+    ) -> impl std::future::Future<Output = Result<FileHandle<'a>, Error>> + Send;
+
     fn get_attributes<'a>(
         &self,
+        credentials: &Credentials,
         file_handle: &FileHandle<'a>,
         mask: AttributeMask<'a>,
     ) -> impl std::future::Future<Output = Result<Vec<AttributeValue<'a>>, Error>> + Send;
 
     fn read<'a>(
         &self,
+        credentials: &Credentials,
         file_handle: &FileHandle<'a>,
         args: ReadArgs,
     ) -> impl std::future::Future<Output = Result<ReadResult<'a>, Error>> + Send;
 
     fn read_directory<'a>(
         &self,
+        credentials: &Credentials,
         file_handle: &FileHandle<'a>,
         args: ReadDirectoryArgs<'a>,
     ) -> impl std::future::Future<Output = Result<ReadDirectoryResult<'a>, Error>> + Send;
 
+    fn read_link<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'a>,
+    ) -> impl std::future::Future<Output = Result<Cow<'a, str>, Error>> + Send;
+
+    /// The file handle is the directory for [`OpenClaim::Null`] and the file itself for
+    /// [`OpenClaim::FileHandle`].
     fn open<'a>(
         &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'a>,
         args: OpenArgs<'a>,
-    ) -> impl std::future::Future<Output = Result<(FileHandle, OpenResult<'a>), Error>> + Send;
+    ) -> impl std::future::Future<Output = Result<(FileHandle<'a>, OpenResult<'a>), Error>> + Send;
+
+    fn create<'a>(
+        &self,
+        credentials: &Credentials,
+        directory: &FileHandle<'a>,
+        args: CreateArgs<'a>,
+    ) -> impl std::future::Future<Output = Result<(FileHandle<'a>, CreateResult<'a>), Error>> + Send;
+
+    fn link<'a>(
+        &self,
+        credentials: &Credentials,
+        source: &FileHandle<'a>,
+        directory: &FileHandle<'a>,
+        name: &str,
+    ) -> impl std::future::Future<Output = Result<ChangeInfo, Error>> + Send;
+
+    fn remove<'a>(
+        &self,
+        credentials: &Credentials,
+        directory: &FileHandle<'a>,
+        name: &str,
+    ) -> impl std::future::Future<Output = Result<ChangeInfo, Error>> + Send;
+
+    fn rename<'a>(
+        &self,
+        credentials: &Credentials,
+        source_directory: &FileHandle<'a>,
+        target_directory: &FileHandle<'a>,
+        args: RenameArgs<'a>,
+    ) -> impl std::future::Future<Output = Result<RenameResult, Error>> + Send;
+
+    fn set_attributes<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'a>,
+        args: SetAttributesArgs<'a>,
+    ) -> impl std::future::Future<Output = Result<AttributeMask<'a>, Error>> + Send;
+
+    /// The returned verifier must match the one from `commit` until the server restarts,
+    /// so that clients know to resend data written with [`StableHow::Unstable`].
+    fn write<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'a>,
+        args: WriteArgs<'a>,
+    ) -> impl std::future::Future<Output = Result<WriteResult, Error>> + Send;
+
+    fn commit<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'a>,
+        args: CommitArgs,
+    ) -> impl std::future::Future<Output = Result<Verifier, Error>> + Send;
+
+    /// Called once the server granted a byte-range lock, so that a backend shared with other
+    /// processes can take it too. Conflicts between NFS clients are resolved by the server.
+    fn lock<'a>(
+        &self,
+        _file_handle: &FileHandle<'a>,
+        _owner: &LockOwner<'a>,
+        _lock_type: LockType,
+        _offset: u64,
+        _length: u64,
+    ) -> impl std::future::Future<Output = Result<(), Error>> + Send {
+        async { Ok(()) }
+    }
+
+    /// Called when a lock owner releases a range, also for the whole file when its client's
+    /// lease expires.
+    fn unlock<'a>(
+        &self,
+        _file_handle: &FileHandle<'a>,
+        _owner: &LockOwner<'a>,
+        _offset: u64,
+        _length: u64,
+    ) -> impl std::future::Future<Output = Result<(), Error>> + Send {
+        async { Ok(()) }
+    }
+
+    /// Whether the file may be delegated to clients, which is only safe as long as nothing but
+    /// the server changes it.
+    fn delegates(
+        &self,
+        _file_handle: &FileHandle<'_>,
+    ) -> bool {
+        true
+    }
 
     fn destroy_session(
         &self,
@@ -0,0 +1,310 @@
+use crate::protocol::*;
+use bitflags::bitflags;
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+/// The `nobody` user and group that anonymous and squashed callers act as.
+pub const ANONYMOUS_ID: u32 = 65534;
+
+/// The identity that an operation is performed as, taken from the AUTH_SYS credential of the
+/// call after identity mapping.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub uid: u32,
+    pub gid: u32,
+    pub gids: Vec<u32>,
+}
+
+bitflags! {
+    /// The permission bits of one class (owner, group or other) of a file mode.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub struct Permissions: u32 {
+        const READ    = 0o4;
+        const WRITE   = 0o2;
+        const EXECUTE = 0o1;
+    }
+}
+
+impl Credentials {
+    pub fn anonymous() -> Self {
+        Self {
+            uid: ANONYMOUS_ID,
+            gid: ANONYMOUS_ID,
+            gids: vec![],
+        }
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.uid == 0
+    }
+
+    /// The permissions that `mode` grants the caller on a file owned by `uid` and `gid`.
+    pub fn permissions(
+        &self,
+        mode: u32,
+        uid: u32,
+        gid: u32,
+        is_directory: bool,
+    ) -> Permissions {
+        if self.is_root() {
+            // Root may search any directory but only execute files that someone can execute.
+            let mut permissions = Permissions::READ | Permissions::WRITE;
+            if is_directory || mode & 0o111 != 0 {
+                permissions |= Permissions::EXECUTE;
+            }
+            return permissions;
+        }
+        let bits = if self.uid == uid {
+            mode >> 6
+        } else if self.gid == gid || self.gids.contains(&gid) {
+            mode >> 3
+        } else {
+            mode
+        };
+        Permissions::from_bits_truncate(bits & 0o7)
+    }
+
+    /// Answers an ACCESS request from the mode and owner of the file.
+    pub fn access(
+        &self,
+        mode: u32,
+        uid: u32,
+        gid: u32,
+        is_directory: bool,
+    ) -> AccessFlags {
+        let permissions = self.permissions(mode, uid, gid, is_directory);
+        let mut access = AccessFlags::empty();
+        if permissions.contains(Permissions::READ) {
+            access |= AccessFlags::READ;
+        }
+        if permissions.contains(Permissions::WRITE) {
+            access |= AccessFlags::MODIFY | AccessFlags::EXTEND;
+            if is_directory {
+                access |= AccessFlags::DELETE;
+            }
+        }
+        if permissions.contains(Permissions::EXECUTE) {
+            access |= match is_directory {
+                true => AccessFlags::LOOKUP,
+                false => AccessFlags::EXECUTE,
+            };
+        }
+        access
+    }
+}
+
+/// Maps the credentials that clients send to the identities that file access is checked
+/// against.
+///
+/// Stations are told apart by the address that they connect from, as the machine name in an
+/// AUTH_SYS credential is whatever the client claims.
+#[derive(Debug, Clone, Default)]
+pub struct IdentityMap {
+    squash_root: bool,
+    stations: HashMap<IpAddr, StationMap>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct StationMap {
+    uids: HashMap<u32, u32>,
+    gids: HashMap<u32, u32>,
+}
+
+impl IdentityMap {
+    /// Treats root on the clients as the anonymous user, unless a station maps it explicitly.
+    pub fn squash_root(mut self) -> Self {
+        self.squash_root = true;
+        self
+    }
+
+    pub fn map_uid(
+        mut self,
+        station: IpAddr,
+        from: u32,
+        to: u32,
+    ) -> Self {
+        let station = self.stations.entry(station).or_default();
+        station.uids.insert(from, to);
+        self
+    }
+
+    pub fn map_gid(
+        mut self,
+        station: IpAddr,
+        from: u32,
+        to: u32,
+    ) -> Self {
+        let station = self.stations.entry(station).or_default();
+        station.gids.insert(from, to);
+        self
+    }
+
+    /// Returns the credentials of the caller at `address`, or why its credential is rejected.
+    pub fn map(
+        &self,
+        credential: &OpaqueAuth<'_>,
+        address: IpAddr,
+    ) -> Result<Credentials, AuthStatus> {
+        let parms = match credential.flavor {
+            AuthFlavor::AuthNone => return Ok(Credentials::anonymous()),
+            AuthFlavor::AuthSys => match decode::auth_sys_parms(&credential.body) {
+                Ok((_, parms)) => parms,
+                Err(_) => return Err(AuthStatus::AUTH_BADCRED),
+            },
+            _ => return Err(AuthStatus::AUTH_BADCRED),
+        };
+        let station = self.stations.get(&address);
+        let uid = |uid: u32| match station.and_then(|station| station.uids.get(&uid)) {
+            Some(&uid) => uid,
+            None if uid == 0 && self.squash_root => ANONYMOUS_ID,
+            None => uid,
+        };
+        let gid = |gid: u32| match station.and_then(|station| station.gids.get(&gid)) {
+            Some(&gid) => gid,
+            None if gid == 0 && self.squash_root => ANONYMOUS_ID,
+            None => gid,
+        };
+        Ok(Credentials {
+            uid: uid(parms.uid),
+            gid: gid(parms.gid),
+            gids: parms.gids.iter().map(|&id| gid(id)).collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auth_sys(
+        machine_name: &str,
+        uid: u32,
+        gid: u32,
+        gids: &[u32],
+    ) -> OpaqueAuth<'static> {
+        let mut body = Vec::new();
+        body.extend(0u32.to_be_bytes());
+        body.extend((machine_name.len() as u32).to_be_bytes());
+        body.extend(machine_name.as_bytes());
+        body.resize(body.len() + (4 - machine_name.len() % 4) % 4, 0);
+        body.extend(uid.to_be_bytes());
+        body.extend(gid.to_be_bytes());
+        body.extend((gids.len() as u32).to_be_bytes());
+        for gid in gids {
+            body.extend(gid.to_be_bytes());
+        }
+        OpaqueAuth {
+            flavor: AuthFlavor::AuthSys,
+            body: body.into(),
+        }
+    }
+
+    const ADDRESS: IpAddr = IpAddr::V4(std::net::Ipv4Addr::LOCALHOST);
+
+    #[test]
+    fn test_squash_root() {
+        let identity_map = IdentityMap::default().squash_root();
+        assert_eq!(
+            identity_map.map(&auth_sys("laptop", 0, 0, &[0, 10]), ADDRESS),
+            Ok(Credentials {
+                uid: ANONYMOUS_ID,
+                gid: ANONYMOUS_ID,
+                gids: vec![ANONYMOUS_ID, 10],
+            })
+        );
+        assert_eq!(
+            IdentityMap::default().map(&auth_sys("laptop", 0, 0, &[]), ADDRESS),
+            Ok(Credentials {
+                uid: 0,
+                gid: 0,
+                gids: vec![],
+            })
+        );
+    }
+
+    #[test]
+    fn test_map_station() {
+        let tv = IpAddr::from([192, 168, 1, 20]);
+        let laptop = IpAddr::from([192, 168, 1, 30]);
+        let identity_map = IdentityMap::default()
+            .squash_root()
+            .map_uid(tv, 0, 1000)
+            .map_gid(tv, 100, 1000);
+        assert_eq!(
+            identity_map.map(&auth_sys("tv.lararium", 0, 100, &[]), tv),
+            Ok(Credentials {
+                uid: 1000,
+                gid: 1000,
+                gids: vec![],
+            })
+        );
+        // Claiming the machine name of the station does not make a client the station.
+        assert_eq!(
+            identity_map
+                .map(&auth_sys("tv.lararium", 0, 100, &[]), laptop)
+                .map(|credentials| credentials.uid),
+            Ok(ANONYMOUS_ID)
+        );
+    }
+
+    #[test]
+    fn test_rejected_flavors() {
+        let identity_map = IdentityMap::default();
+        let none = OpaqueAuth {
+            flavor: AuthFlavor::AuthNone,
+            body: (&[]).into(),
+        };
+        assert_eq!(
+            identity_map.map(&none, ADDRESS),
+            Ok(Credentials::anonymous())
+        );
+        let gss = OpaqueAuth {
+            flavor: AuthFlavor::RpcSecGss,
+            body: (&[]).into(),
+        };
+        assert_eq!(
+            identity_map.map(&gss, ADDRESS),
+            Err(AuthStatus::AUTH_BADCRED)
+        );
+        let truncated = OpaqueAuth {
+            flavor: AuthFlavor::AuthSys,
+            body: (&[0, 0, 0, 1]).into(),
+        };
+        assert_eq!(
+            identity_map.map(&truncated, ADDRESS),
+            Err(AuthStatus::AUTH_BADCRED)
+        );
+    }
+
+    #[test]
+    fn test_permissions() {
+        let user = Credentials {
+            uid: 1000,
+            gid: 1000,
+            gids: vec![20],
+        };
+        assert_eq!(
+            user.access(0o640, 1000, 0, false),
+            AccessFlags::READ | AccessFlags::MODIFY | AccessFlags::EXTEND
+        );
+        assert_eq!(user.access(0o640, 0, 20, false), AccessFlags::READ);
+        assert_eq!(user.access(0o640, 0, 0, false), AccessFlags::empty());
+        assert_eq!(
+            user.access(0o755, 0, 0, true),
+            AccessFlags::READ | AccessFlags::LOOKUP
+        );
+        let root = Credentials {
+            uid: 0,
+            gid: 0,
+            gids: vec![],
+        };
+        assert_eq!(
+            root.permissions(0o000, 1000, 1000, false),
+            Permissions::READ | Permissions::WRITE
+        );
+        assert_eq!(
+            root.permissions(0o000, 1000, 1000, true),
+            Permissions::all()
+        );
+    }
+}
@@ -0,0 +1,495 @@
+use crate::protocol::*;
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// Byte-range locks of every lock owner, keyed by the `other` field of their lock stateids.
+#[derive(Default)]
+pub struct Locks {
+    next_state_id: u64,
+    states: HashMap<[u8; 12], LockState>,
+}
+
+/// The locks that one lock owner holds on one file.
+struct LockState {
+    client_id: ClientId,
+    owner: Vec<u8>,
+    file_handle: Vec<u8>,
+    sequence_id: SequenceId,
+    ranges: Vec<Range>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Range {
+    start: u64,
+    /// Exclusive, `u64::MAX` for a range that extends to the end of the file.
+    end: u64,
+    write: bool,
+}
+
+/// A lock owner whose locks changed, for the handler to mirror.
+#[derive(Debug)]
+pub struct Locked {
+    pub state_id: StateId,
+    pub owner: LockOwner<'static>,
+}
+
+impl Locks {
+    pub fn lock(
+        &mut self,
+        client_id: ClientId,
+        file_handle: &[u8],
+        args: &LockArgs<'_>,
+    ) -> Result<Locked, LockError<'static>> {
+        if args.reclaim {
+            return Err(Error::NO_GRACE.into());
+        }
+        let range = Range::new(args.offset, args.length, args.lock_type)?;
+        let key = match args.locker {
+            Locker::New(ref locker) => {
+                // The client ID of the lock owner is taken from the session in NFSv4.1.
+                let owner = &locker.lock_owner.0.owner;
+                match self.find(client_id, owner, file_handle) {
+                    Some(key) => key,
+                    None => self.insert(client_id, owner, file_handle),
+                }
+            }
+            Locker::Existing(ref locker) => {
+                self.validate(client_id, file_handle, &locker.lock_state_id)?;
+                locker.lock_state_id.other
+            }
+        };
+        if let Some(denied) = self.conflict(Some(key), file_handle, range) {
+            return Err(denied.into());
+        }
+        let state = self.states.get_mut(&key).unwrap();
+        subtract(&mut state.ranges, range.start, range.end);
+        state.ranges.push(range);
+        Ok(state.bump(key))
+    }
+
+    pub fn lock_test(
+        &self,
+        client_id: ClientId,
+        file_handle: &[u8],
+        args: &LockTestArgs<'_>,
+    ) -> Result<(), LockError<'static>> {
+        let range = Range::new(args.offset, args.length, args.lock_type)?;
+        let key = self.find(client_id, &args.owner.0.owner, file_handle);
+        match self.conflict(key, file_handle, range) {
+            Some(denied) => Err(denied.into()),
+            None => Ok(()),
+        }
+    }
+
+    pub fn unlock(
+        &mut self,
+        client_id: ClientId,
+        file_handle: &[u8],
+        args: &UnlockArgs,
+    ) -> Result<Locked, Error> {
+        let range = Range::new(args.offset, args.length, args.lock_type)?;
+        self.validate(client_id, file_handle, &args.lock_state_id)?;
+        let key = args.lock_state_id.other;
+        let state = self.states.get_mut(&key).unwrap();
+        subtract(&mut state.ranges, range.start, range.end);
+        Ok(state.bump(key))
+    }
+
+    /// Forgets the lock stateids of the client, returning the files on which it still held
+    /// locks.
+    pub fn remove_client(
+        &mut self,
+        client_id: ClientId,
+    ) -> Vec<(FileHandle<'static>, LockOwner<'static>)> {
+        let mut released = Vec::new();
+        self.states.retain(|_, state| {
+            if state.client_id != client_id {
+                return true;
+            }
+            if !state.ranges.is_empty() {
+                released.push((
+                    FileHandle::from(state.file_handle.clone()),
+                    state.lock_owner(),
+                ));
+            }
+            false
+        });
+        released
+    }
+
+    fn find(
+        &self,
+        client_id: ClientId,
+        owner: &[u8],
+        file_handle: &[u8],
+    ) -> Option<[u8; 12]> {
+        self.states
+            .iter()
+            .find(|(_, state)| {
+                state.client_id == client_id
+                    && state.owner == owner
+                    && state.file_handle == file_handle
+            })
+            .map(|(key, _)| *key)
+    }
+
+    fn insert(
+        &mut self,
+        client_id: ClientId,
+        owner: &[u8],
+        file_handle: &[u8],
+    ) -> [u8; 12] {
+        self.next_state_id += 1;
+        let mut key = [0; 12];
+        key[..4].copy_from_slice(b"LOCK");
+        key[4..].copy_from_slice(&self.next_state_id.to_be_bytes());
+        self.states.insert(
+            key,
+            LockState {
+                client_id,
+                owner: owner.to_vec(),
+                file_handle: file_handle.to_vec(),
+                sequence_id: 0,
+                ranges: vec![],
+            },
+        );
+        key
+    }
+
+    fn validate(
+        &self,
+        client_id: ClientId,
+        file_handle: &[u8],
+        state_id: &StateId,
+    ) -> Result<(), Error> {
+        let state = self
+            .states
+            .get(&state_id.other)
+            .filter(|state| state.client_id == client_id && state.file_handle == file_handle)
+            .ok_or(Error::BAD_STATEID)?;
+        // A sequence ID of zero refers to the current version of the stateid.
+        match state_id.sequence_id {
+            0 => Ok(()),
+            sequence_id if sequence_id == state.sequence_id => Ok(()),
+            sequence_id if sequence_id < state.sequence_id => Err(Error::OLD_STATEID),
+            _ => Err(Error::BAD_STATEID),
+        }
+    }
+
+    /// Finds a lock held on the file by another owner than the key's that overlaps the range,
+    /// unless both are read locks.
+    fn conflict(
+        &self,
+        key: Option<[u8; 12]>,
+        file_handle: &[u8],
+        range: Range,
+    ) -> Option<LockDenied<'static>> {
+        self.states
+            .iter()
+            .filter(|(other, state)| Some(**other) != key && state.file_handle == file_handle)
+            .find_map(|(_, state)| {
+                state
+                    .ranges
+                    .iter()
+                    .find(|held| held.overlaps(&range) && (held.write || range.write))
+                    .map(|held| LockDenied {
+                        offset: held.start,
+                        length: match held.end {
+                            u64::MAX => u64::MAX,
+                            end => end - held.start,
+                        },
+                        lock_type: match held.write {
+                            true => LockType::Write,
+                            false => LockType::Read,
+                        },
+                        owner: state.lock_owner(),
+                    })
+            })
+    }
+}
+
+impl LockState {
+    fn bump(
+        &mut self,
+        key: [u8; 12],
+    ) -> Locked {
+        self.sequence_id = self.sequence_id.wrapping_add(1).max(1);
+        Locked {
+            state_id: StateId {
+                sequence_id: self.sequence_id,
+                other: key,
+            },
+            owner: self.lock_owner(),
+        }
+    }
+
+    fn lock_owner(&self) -> LockOwner<'static> {
+        LockOwner(StateOwner {
+            client_id: self.client_id,
+            owner: Cow::Owned(self.owner.clone()),
+        })
+    }
+}
+
+impl Range {
+    fn new(
+        offset: u64,
+        length: u64,
+        lock_type: LockType,
+    ) -> Result<Self, Error> {
+        let end = match length {
+            0 => return Err(Error::INVAL),
+            u64::MAX => u64::MAX,
+            length => offset.checked_add(length).ok_or(Error::INVAL)?,
+        };
+        Ok(Self {
+            start: offset,
+            end,
+            write: matches!(lock_type, LockType::Write | LockType::WriteBlocking),
+        })
+    }
+
+    fn overlaps(
+        &self,
+        other: &Range,
+    ) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+}
+
+/// Removes the span from the ranges, splitting those that extend beyond it on either side.
+fn subtract(
+    ranges: &mut Vec<Range>,
+    start: u64,
+    end: u64,
+) {
+    *ranges = ranges
+        .drain(..)
+        .flat_map(|range| {
+            if range.end <= start || end <= range.start {
+                return vec![range];
+            }
+            let mut remainder = vec![];
+            if range.start < start {
+                remainder.push(Range {
+                    end: start,
+                    ..range
+                });
+            }
+            if end < range.end {
+                remainder.push(Range {
+                    start: end,
+                    ..range
+                });
+            }
+            remainder
+        })
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &[u8] = &[1, 2, 3];
+
+    fn owner(
+        client_id: ClientId,
+        name: &'static [u8],
+    ) -> LockOwner<'static> {
+        LockOwner(StateOwner {
+            client_id,
+            owner: name.into(),
+        })
+    }
+
+    fn lock(
+        lock_type: LockType,
+        offset: u64,
+        length: u64,
+        locker: Locker<'static>,
+    ) -> LockArgs<'static> {
+        LockArgs {
+            lock_type,
+            reclaim: false,
+            offset,
+            length,
+            locker,
+        }
+    }
+
+    fn new_owner(name: &'static [u8]) -> Locker<'static> {
+        Locker::New(OpenToLockOwner {
+            open_sequence_id: 0,
+            open_state_id: StateId {
+                sequence_id: 0,
+                other: [0; 12],
+            },
+            lock_sequence_id: 0,
+            lock_owner: owner(0, name),
+        })
+    }
+
+    fn existing_owner(lock_state_id: StateId) -> Locker<'static> {
+        Locker::Existing(ExistingLockOwner {
+            lock_state_id,
+            lock_sequence_id: 0,
+        })
+    }
+
+    #[test]
+    fn test_conflicting_write_lock_is_denied() {
+        let mut locks = Locks::default();
+        let locked = locks
+            .lock(1, FILE, &lock(LockType::Write, 100, 50, new_owner(b"a")))
+            .unwrap();
+        assert_eq!(locked.state_id.sequence_id, 1);
+        assert_eq!(
+            locks
+                .lock(2, FILE, &lock(LockType::Read, 0, u64::MAX, new_owner(b"b")))
+                .err(),
+            Some(LockError::Denied(LockDenied {
+                offset: 100,
+                length: 50,
+                lock_type: LockType::Write,
+                owner: owner(1, b"a"),
+            }))
+        );
+        assert!(locks
+            .lock(2, FILE, &lock(LockType::Write, 150, 10, new_owner(b"b")))
+            .is_ok());
+        assert!(locks
+            .lock(2, &[4], &lock(LockType::Write, 100, 50, new_owner(b"b")))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_shared_read_locks() {
+        let mut locks = Locks::default();
+        for name in [b"a", b"b"] {
+            assert!(locks
+                .lock(1, FILE, &lock(LockType::Read, 0, u64::MAX, new_owner(name)))
+                .is_ok());
+        }
+        let args = LockTestArgs {
+            lock_type: LockType::Write,
+            offset: 10,
+            length: 1,
+            owner: owner(0, b"c"),
+        };
+        assert!(matches!(
+            locks.lock_test(1, FILE, &args),
+            Err(LockError::Denied(LockDenied {
+                length: u64::MAX,
+                ..
+            }))
+        ));
+        let args = LockTestArgs {
+            lock_type: LockType::Read,
+            ..args
+        };
+        assert_eq!(locks.lock_test(1, FILE, &args), Ok(()));
+    }
+
+    #[test]
+    fn test_unlock_splits_range() {
+        let mut locks = Locks::default();
+        let locked = locks
+            .lock(1, FILE, &lock(LockType::Write, 0, 100, new_owner(b"a")))
+            .unwrap();
+        let unlocked = locks
+            .unlock(
+                1,
+                FILE,
+                &UnlockArgs {
+                    lock_type: LockType::Write,
+                    sequence_id: 0,
+                    lock_state_id: locked.state_id.clone(),
+                    offset: 40,
+                    length: 20,
+                },
+            )
+            .unwrap();
+        assert_eq!(unlocked.state_id.sequence_id, 2);
+        assert!(locks
+            .lock(2, FILE, &lock(LockType::Write, 40, 20, new_owner(b"b")))
+            .is_ok());
+        assert!(matches!(
+            locks.lock(2, FILE, &lock(LockType::Write, 30, 20, new_owner(b"b"))),
+            Err(LockError::Denied(LockDenied {
+                offset: 0,
+                length: 40,
+                ..
+            }))
+        ));
+        assert_eq!(
+            locks
+                .lock(
+                    1,
+                    FILE,
+                    &lock(LockType::Write, 0, 1, existing_owner(locked.state_id))
+                )
+                .err(),
+            Some(LockError::Error(Error::OLD_STATEID))
+        );
+    }
+
+    #[test]
+    fn test_invalid_ranges() {
+        let mut locks = Locks::default();
+        assert_eq!(
+            locks
+                .lock(1, FILE, &lock(LockType::Read, 0, 0, new_owner(b"a")))
+                .err(),
+            Some(LockError::Error(Error::INVAL))
+        );
+        assert_eq!(
+            locks
+                .lock(
+                    1,
+                    FILE,
+                    &lock(LockType::Read, u64::MAX - 1, 2, new_owner(b"a"))
+                )
+                .err(),
+            Some(LockError::Error(Error::INVAL))
+        );
+        let state_id = StateId {
+            sequence_id: 0,
+            other: [9; 12],
+        };
+        assert_eq!(
+            locks
+                .lock(
+                    1,
+                    FILE,
+                    &lock(LockType::Read, 0, 1, existing_owner(state_id))
+                )
+                .err(),
+            Some(LockError::Error(Error::BAD_STATEID))
+        );
+    }
+
+    #[test]
+    fn test_remove_client_releases_locks() {
+        let mut locks = Locks::default();
+        locks
+            .lock(
+                1,
+                FILE,
+                &lock(LockType::Write, 0, u64::MAX, new_owner(b"a")),
+            )
+            .unwrap();
+        let released = locks.remove_client(1);
+        assert_eq!(
+            released,
+            vec![(FileHandle::from(FILE.to_vec()), owner(1, b"a"))]
+        );
+        assert!(locks
+            .lock(
+                2,
+                FILE,
+                &lock(LockType::Write, 0, u64::MAX, new_owner(b"b"))
+            )
+            .is_ok());
+    }
+}
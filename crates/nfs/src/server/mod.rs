@@ -1,33 +1,59 @@
+mod callback;
 mod connection;
+mod delegation;
 mod error;
 mod handler;
+mod identity;
+mod lock;
+mod state;
 
 pub use error::Error;
 pub use handler::Handler;
+pub use identity::{Credentials, IdentityMap, Permissions, ANONYMOUS_ID};
 
 use crate::protocol::{self, *};
+use crate::record;
 
-use bytes::BytesMut;
-use connection::Connection;
+use callback::Channel;
+use connection::{Connection, Transaction};
 use cookie_factory::{gen, sequence::tuple};
-use std::io::{self, Cursor};
+use state::{Sequence, State};
+use std::io::Cursor;
 use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpListener;
 
+const INITIAL_OUTPUT_LENGTH: usize = 64 * 1024;
+
 #[derive(Clone)]
 pub struct Server {
     listener: Arc<TcpListener>,
+    state: Arc<State>,
+    identity_map: Arc<IdentityMap>,
 }
 
 impl Server {
     pub async fn bind(listen_address: SocketAddr) -> Result<Self, Error> {
         Ok(Self {
             listener: Arc::new(TcpListener::bind(listen_address).await?),
+            state: Arc::new(State::default()),
+            identity_map: Arc::new(IdentityMap::default()),
         })
     }
 
+    /// Replaces the mapping from client credentials to the identities that handlers see.
+    pub fn map_identities(
+        mut self,
+        identity_map: IdentityMap,
+    ) -> Self {
+        self.identity_map = Arc::new(identity_map);
+        self
+    }
+
+    pub fn local_address(&self) -> Result<SocketAddr, Error> {
+        Ok(self.listener.local_addr()?)
+    }
+
     pub async fn listen<T>(
         &self,
         handler: T,
@@ -36,39 +62,24 @@ impl Server {
         T: Handler + Clone + Send + Sync + 'static,
     {
         loop {
-            let (mut socket, address) = self.listener.accept().await?;
+            let (socket, address) = self.listener.accept().await?;
             tracing::debug!("Received connection from {address}.");
-            let connection = Connection::new(handler.clone());
+            let (mut reader, writer) = socket.into_split();
+            let channel = Channel::new(writer);
+            let connection = Connection::new(handler.clone(), self.state.clone(), channel.clone());
+            let identity_map = self.identity_map.clone();
             tokio::spawn({
                 async move {
-                    let mut buffer = BytesMut::with_capacity(1024);
-                    'connection: loop {
-                        loop {
-                            let record_mark = match socket.read_u32().await {
-                                Ok(record_mark) => record_mark,
-                                Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => {
-                                    break 'connection
-                                }
-                                Err(error) => {
-                                    tracing::debug!("Failed to read record mark: {error}");
-                                    break 'connection;
-                                }
-                            };
-                            let last_fragment = record_mark & (1 << 31) != 0;
-                            let fragment_length = (record_mark & ((1 << 31) - 1)) as usize;
-                            if buffer.capacity() + fragment_length > 8192 {
-                                break 'connection;
-                            }
-                            buffer.reserve(fragment_length);
-                            if socket.read_buf(&mut buffer).await.is_err() {
-                                tracing::debug!("Failed to read record fragment.");
-                                break 'connection;
-                            }
-                            if last_fragment {
+                    let mut output = vec![0; INITIAL_OUTPUT_LENGTH];
+                    loop {
+                        let message = match record::read(&mut reader).await {
+                            Ok(Some(message)) => message,
+                            Ok(None) => break,
+                            Err(error) => {
+                                tracing::debug!("Failed to read record: {error}");
                                 break;
                             }
-                        }
-                        let message = buffer.split_to(buffer.len());
+                        };
                         let Ok((input, RpcMessage { xid, message_type })) =
                             protocol::decode::message(&message)
                         else {
@@ -83,140 +94,92 @@ impl Server {
                                     Ok(value) => value,
                                     Err(_) => {
                                         tracing::debug!("Invalid RPC call.");
-                                        println!("{input:?}");
+                                        let reply = Reply::Accepted(AcceptedReply {
+                                            verf: OpaqueAuth {
+                                                flavor: AuthFlavor::AuthNone,
+                                                body: (&[]).into(),
+                                            },
+                                            body: AcceptedReplyBody::GarbageArgs,
+                                        });
+                                        let Some(length) = encode_reply(xid, &reply, &mut output)
+                                        else {
+                                            continue;
+                                        };
+                                        if let Err(error) = channel.write(&output[..length]).await {
+                                            tracing::debug!("Failed to write record: {error}");
+                                            break;
+                                        }
+                                        continue;
+                                    }
+                                };
+                                let credentials = match identity_map
+                                    .map(&call.cred, address.ip().to_canonical())
+                                {
+                                    Ok(credentials) => credentials,
+                                    Err(stat) => {
+                                        tracing::debug!("Rejected credential: {stat:?}");
+                                        let reply =
+                                            Reply::Rejected(RejectedReply::AuthError { stat });
+                                        let Some(length) = encode_reply(xid, &reply, &mut output)
+                                        else {
+                                            continue;
+                                        };
+                                        if let Err(error) = channel.write(&output[..length]).await {
+                                            tracing::debug!("Failed to write record: {error}");
+                                            break;
+                                        }
                                         continue;
                                     }
                                 };
-                                let transaction = connection.begin();
+                                let transaction = connection.begin(credentials);
                                 let reply = match call.procedure {
                                     ProcedureCall::Null => ProcedureReply::Null,
                                     ProcedureCall::Compound(args) => {
-                                        // TODO args.minorversion
-                                        let mut resarray = Vec::with_capacity(args.argarray.len());
-                                        for nfs_argop in args.argarray.into_iter() {
-                                            resarray.push(match nfs_argop {
-                                                NfsArgOp::Access(args) => {
-                                                    NfsResOp::Access(transaction.access(args).await)
-                                                }
-                                                NfsArgOp::Close(args) => {
-                                                    NfsResOp::Close(transaction.close(args).await)
-                                                }
-                                                NfsArgOp::GetAttributes(args) => {
-                                                    NfsResOp::GetAttributes(
-                                                        transaction.get_attributes(args).await,
-                                                    )
-                                                }
-                                                NfsArgOp::GetFileHandle => NfsResOp::GetFileHandle(
-                                                    transaction.get_file_handle().await,
-                                                ),
-                                                NfsArgOp::Lookup(args) => NfsResOp::Lookup(
-                                                    transaction.lookup(&args).await,
-                                                ),
-                                                NfsArgOp::Open(args) => {
-                                                    NfsResOp::Open(transaction.open(args).await)
-                                                }
-                                                NfsArgOp::PutFileHandle(args) => {
-                                                    NfsResOp::PutFileHandle(
-                                                        transaction.put_file_handle(args).await,
-                                                    )
+                                        match compound(&transaction, args).await {
+                                            Compound::Result(result) => {
+                                                ProcedureReply::Compound(result)
+                                            }
+                                            Compound::Replay(mut output) => {
+                                                output[..4].copy_from_slice(&xid.to_be_bytes());
+                                                if let Err(error) = channel.write(&output).await {
+                                                    tracing::debug!(
+                                                        "Failed to write record: {error}"
+                                                    );
+                                                    break;
                                                 }
-                                                NfsArgOp::PutRootFileHandle => {
-                                                    NfsResOp::PutRootFileHandle(
-                                                        transaction.put_root_file_handle().await,
-                                                    )
-                                                }
-                                                NfsArgOp::Read(args) => {
-                                                    NfsResOp::Read(transaction.read(args).await)
-                                                }
-                                                NfsArgOp::ReadDirectory(args) => {
-                                                    NfsResOp::ReadDirectory(
-                                                        transaction.read_directory(args).await,
-                                                    )
-                                                }
-                                                NfsArgOp::GetSecurityInfo(args) => {
-                                                    NfsResOp::GetSecurityInfo(
-                                                        transaction.get_security_info(args).await,
-                                                    )
-                                                }
-                                                NfsArgOp::ExchangeId(args) => NfsResOp::ExchangeId(
-                                                    transaction.exchange_id(args).await,
-                                                ),
-                                                NfsArgOp::CreateSession(args) => {
-                                                    NfsResOp::CreateSession(
-                                                        transaction.create_session(args).await,
-                                                    )
-                                                }
-                                                NfsArgOp::DestroySession(args) => {
-                                                    NfsResOp::DestroySession(
-                                                        transaction.destroy_session(args).await,
-                                                    )
-                                                }
-                                                NfsArgOp::DestroyClientId(args) => {
-                                                    NfsResOp::DestroyClientId(
-                                                        transaction.destroy_client_id(args).await,
-                                                    )
-                                                }
-                                                NfsArgOp::GetSecurityInfoNoName(args) => {
-                                                    NfsResOp::GetSecurityInfoNoName(
-                                                        transaction
-                                                            .get_security_info_no_name(args)
-                                                            .await,
-                                                    )
-                                                }
-                                                NfsArgOp::Sequence(args) => NfsResOp::Sequence(
-                                                    transaction.sequence(args).await,
-                                                ),
-                                                NfsArgOp::ReclaimComplete(args) => {
-                                                    NfsResOp::ReclaimComplete(
-                                                        transaction.reclaim_complete(args).await,
-                                                    )
-                                                }
-                                            });
+                                                continue;
+                                            }
                                         }
-                                        ProcedureReply::Compound(CompoundResult {
-                                            error: None,
-                                            tag: args.tag,
-                                            resarray,
-                                        })
                                     }
                                 };
-                                let mut buffer = [0; 1024];
-                                let output = {
-                                    let reply = Reply::Accepted(AcceptedReply {
-                                        verf: OpaqueAuth {
-                                            flavor: AuthFlavor::AuthNone, // TODO
-                                            body: (&[]).into(),           // TODO
-                                        },
-                                        body: AcceptedReplyBody::Success(reply),
-                                    });
-                                    let generator = tuple((
-                                        protocol::encode::message(RpcMessage {
-                                            xid,
-                                            message_type: MessageType::Reply,
-                                        }),
-                                        protocol::encode::reply(&reply),
-                                    ));
-                                    let cursor = Cursor::new(&mut buffer[..]);
-                                    let Ok((_, position)) = gen(generator, cursor) else {
-                                        tracing::debug!("Failed to encode reply.");
-                                        continue;
-                                    };
-                                    &buffer[..position as usize]
-                                };
-                                let last_fragment = true;
-                                let record_mark = if last_fragment {
-                                    output.len() as u32 | 1 << 31
-                                } else {
-                                    output.len() as u32
+                                let reply = Reply::Accepted(AcceptedReply {
+                                    // AUTH_SYS and AUTH_NONE have no server verifier.
+                                    verf: OpaqueAuth {
+                                        flavor: AuthFlavor::AuthNone,
+                                        body: (&[]).into(),
+                                    },
+                                    body: AcceptedReplyBody::Success(reply),
+                                });
+                                let length = encode_reply(xid, &reply, &mut output).or_else(|| {
+                                    tracing::error!("Failed to encode reply.");
+                                    encode_reply(xid, &server_fault(&reply), &mut output)
+                                });
+                                transaction
+                                    .complete(length.map(|length| output[..length].to_vec()))
+                                    .await;
+                                let Some(length) = length else {
+                                    continue;
                                 };
-                                if socket.write_u32(record_mark).await.is_err() {
+                                if let Err(error) = channel.write(&output[..length]).await {
+                                    tracing::debug!("Failed to write record: {error}");
                                     break;
                                 }
-                                if socket.write_all(output).await.is_err() {
-                                    break;
+                            }
+                            MessageType::Reply => {
+                                if !channel.reply(xid, message) {
+                                    tracing::debug!("Unexpected RPC reply.");
                                 }
                             }
-                            MessageType::Reply => break,
                         }
                     }
                     tracing::debug!("Connection to {address} lost.");
@@ -226,3 +189,929 @@ impl Server {
         }
     }
 }
+
+enum Compound<'a> {
+    Result(CompoundResult<'a>),
+    Replay(Vec<u8>),
+}
+
+async fn compound<'a, T>(
+    transaction: &Transaction<'a, T>,
+    args: CompoundArgs<'a>,
+) -> Compound<'a>
+where
+    T: Handler + Clone + Send + Sync + 'static,
+{
+    let mut result = CompoundResult {
+        error: None,
+        tag: args.tag,
+        resarray: Vec::with_capacity(args.argarray.len()),
+    };
+    if args.minorversion != 1 {
+        result.error = Some(protocol::Error::MINOR_VERS_MISMATCH);
+        return Compound::Result(result);
+    }
+    let only_op = args.argarray.len() == 1;
+    for (index, nfs_argop) in args.argarray.into_iter().enumerate() {
+        let nfs_resop = match nfs_argop {
+            NfsArgOp::Sequence(args) if index == 0 => match transaction.sequence(args).await {
+                Ok(Sequence::Replay(output)) => return Compound::Replay(output),
+                Ok(Sequence::New { result, .. }) => NfsResOp::Sequence(Ok(result)),
+                Err(error) => NfsResOp::Sequence(Err(error)),
+            },
+            // Without a SEQUENCE, only the operations that manage sessions are allowed, and
+            // only on their own.
+            NfsArgOp::ExchangeId(_)
+            | NfsArgOp::CreateSession(_)
+            | NfsArgOp::DestroySession(_)
+            | NfsArgOp::DestroyClientId(_)
+                if index == 0 && !only_op =>
+            {
+                nfs_argop.error_result(protocol::Error::NOT_ONLY_OP)
+            }
+            NfsArgOp::ExchangeId(_)
+            | NfsArgOp::CreateSession(_)
+            | NfsArgOp::DestroySession(_)
+            | NfsArgOp::DestroyClientId(_) => dispatch(transaction, nfs_argop).await,
+            _ if index == 0 => nfs_argop.error_result(protocol::Error::OP_NOT_IN_SESSION),
+            _ => dispatch(transaction, nfs_argop).await,
+        };
+        let error = nfs_resop.error();
+        result.resarray.push(nfs_resop);
+        if error.is_some() {
+            result.error = error;
+            break;
+        }
+    }
+    Compound::Result(result)
+}
+
+async fn dispatch<'a, T>(
+    transaction: &Transaction<'a, T>,
+    nfs_argop: NfsArgOp<'a>,
+) -> NfsResOp<'a>
+where
+    T: Handler + Clone + Send + Sync + 'static,
+{
+    match nfs_argop {
+        NfsArgOp::Access(args) => NfsResOp::Access(transaction.access(args).await),
+        NfsArgOp::Close(args) => NfsResOp::Close(transaction.close(args).await),
+        NfsArgOp::Commit(args) => NfsResOp::Commit(transaction.commit(args).await),
+        NfsArgOp::Create(args) => NfsResOp::Create(transaction.create(args).await),
+        NfsArgOp::DelegationReturn(args) => {
+            NfsResOp::DelegationReturn(transaction.delegation_return(args).await)
+        }
+        NfsArgOp::GetAttributes(args) => {
+            NfsResOp::GetAttributes(transaction.get_attributes(args).await)
+        }
+        NfsArgOp::GetFileHandle => NfsResOp::GetFileHandle(transaction.get_file_handle().await),
+        NfsArgOp::Link(args) => NfsResOp::Link(transaction.link(args).await),
+        NfsArgOp::Lock(args) => NfsResOp::Lock(transaction.lock(args).await),
+        NfsArgOp::LockTest(args) => NfsResOp::LockTest(transaction.lock_test(args).await),
+        NfsArgOp::Unlock(args) => NfsResOp::Unlock(transaction.unlock(args).await),
+        NfsArgOp::Lookup(args) => NfsResOp::Lookup(transaction.lookup(&args).await),
+        NfsArgOp::LookupParent => NfsResOp::LookupParent(transaction.lookup_parent().await),
+        NfsArgOp::Open(args) => NfsResOp::Open(transaction.open(args).await),
+        NfsArgOp::OpenAttributes(args) => {
+            NfsResOp::OpenAttributes(transaction.open_attributes(args).await)
+        }
+        NfsArgOp::PutFileHandle(args) => {
+            NfsResOp::PutFileHandle(transaction.put_file_handle(args).await)
+        }
+        NfsArgOp::PutRootFileHandle => {
+            NfsResOp::PutRootFileHandle(transaction.put_root_file_handle().await)
+        }
+        NfsArgOp::Read(args) => NfsResOp::Read(transaction.read(args).await),
+        NfsArgOp::ReadDirectory(args) => {
+            NfsResOp::ReadDirectory(transaction.read_directory(args).await)
+        }
+        NfsArgOp::ReadLink => NfsResOp::ReadLink(transaction.read_link().await),
+        NfsArgOp::Remove(args) => NfsResOp::Remove(transaction.remove(args).await),
+        NfsArgOp::Rename(args) => NfsResOp::Rename(transaction.rename(args).await),
+        NfsArgOp::RestoreFileHandle => {
+            NfsResOp::RestoreFileHandle(transaction.restore_file_handle().await)
+        }
+        NfsArgOp::SaveFileHandle => NfsResOp::SaveFileHandle(transaction.save_file_handle().await),
+        NfsArgOp::SetAttributes(args) => {
+            NfsResOp::SetAttributes(transaction.set_attributes(args).await)
+        }
+        NfsArgOp::Write(args) => NfsResOp::Write(transaction.write(args).await),
+        NfsArgOp::GetSecurityInfo(args) => {
+            NfsResOp::GetSecurityInfo(transaction.get_security_info(args).await)
+        }
+        NfsArgOp::ExchangeId(args) => NfsResOp::ExchangeId(transaction.exchange_id(args).await),
+        NfsArgOp::CreateSession(args) => {
+            NfsResOp::CreateSession(transaction.create_session(args).await)
+        }
+        NfsArgOp::DestroySession(args) => {
+            NfsResOp::DestroySession(transaction.destroy_session(args).await)
+        }
+        NfsArgOp::DestroyClientId(args) => {
+            NfsResOp::DestroyClientId(transaction.destroy_client_id(args).await)
+        }
+        NfsArgOp::GetSecurityInfoNoName(args) => {
+            NfsResOp::GetSecurityInfoNoName(transaction.get_security_info_no_name(args).await)
+        }
+        // Only valid as the first operation, which is handled by the compound.
+        NfsArgOp::Sequence(_) => NfsResOp::Sequence(Err(protocol::Error::SEQUENCE_POS)),
+        NfsArgOp::ReclaimComplete(args) => {
+            NfsResOp::ReclaimComplete(transaction.reclaim_complete(args).await)
+        }
+        NfsArgOp::Unsupported(opnum) => {
+            tracing::debug!("{opnum:?} not supported");
+            NfsResOp::Unsupported(opnum, protocol::Error::NOTSUPP)
+        }
+        NfsArgOp::Illegal => NfsResOp::Illegal(protocol::Error::OP_ILLEGAL),
+    }
+}
+
+/// The reply in place of one that cannot be encoded, e.g. because it exceeds the maximum record
+/// length.
+fn server_fault<'a>(reply: &Reply<'a>) -> Reply<'a> {
+    let tag = match reply {
+        Reply::Accepted(AcceptedReply {
+            body: AcceptedReplyBody::Success(ProcedureReply::Compound(result)),
+            ..
+        }) => result.tag.clone(),
+        _ => "".into(),
+    };
+    Reply::Accepted(AcceptedReply {
+        verf: OpaqueAuth {
+            flavor: AuthFlavor::AuthNone,
+            body: (&[]).into(),
+        },
+        body: AcceptedReplyBody::Success(ProcedureReply::Compound(CompoundResult {
+            error: Some(protocol::Error::SERVERFAULT),
+            tag,
+            resarray: Vec::new(),
+        })),
+    })
+}
+
+/// Encodes the reply into the buffer, growing it up to the maximum record length until the
+/// output fits.
+fn encode_reply(
+    xid: u32,
+    reply: &Reply<'_>,
+    buffer: &mut Vec<u8>,
+) -> Option<usize> {
+    loop {
+        let generator = tuple((
+            protocol::encode::message(RpcMessage {
+                xid,
+                message_type: MessageType::Reply,
+            }),
+            protocol::encode::reply(reply),
+        ));
+        match gen(generator, Cursor::new(&mut buffer[..])).map(|(_, position)| position) {
+            Ok(position) => return Some(position as usize),
+            Err(_) if buffer.len() < record::MAX_RECORD_LENGTH => {
+                let length = (buffer.len() * 2).min(record::MAX_RECORD_LENGTH);
+                buffer.resize(length, 0);
+            }
+            Err(_) => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::Error;
+    use std::borrow::Cow;
+    use std::sync::Mutex;
+    use tokio::net::TcpStream;
+
+    const FILE_LENGTH: usize = 4 * 1024 * 1024;
+    const CHUNK_LENGTH: usize = 1024 * 1024;
+
+    #[derive(Clone)]
+    struct TestHandler {
+        file: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl Handler for TestHandler {
+        async fn access(
+            &self,
+            _credentials: &Credentials,
+            _file_handle: &FileHandle<'_>,
+            _flags: AccessFlags,
+        ) -> Result<AccessResult, Error> {
+            Err(Error::NOTSUPP)
+        }
+
+        async fn close(
+            &self,
+            _credentials: &Credentials,
+            _file_handle: &FileHandle<'_>,
+            _args: CloseArgs,
+        ) -> Result<(), Error> {
+            Ok(())
+        }
+
+        async fn lookup<'a>(
+            &self,
+            _credentials: &Credentials,
+            _file_handle: &FileHandle<'a>,
+            name: &str,
+        ) -> Result<FileHandle<'a>, Error> {
+            match name {
+                "file" => Ok(FileHandle::from(&[0])),
+                _ => Err(Error::NOENT),
+            }
+        }
+
+        async fn lookup_parent<'a>(
+            &self,
+            _credentials: &Credentials,
+            _file_handle: &FileHandle<'a>,
+        ) -> Result<FileHandle<'a>, Error> {
+            Err(Error::NOENT)
+        }
+
+        async fn get_attributes<'a>(
+            &self,
+            _credentials: &Credentials,
+            _file_handle: &FileHandle<'a>,
+            _mask: AttributeMask<'a>,
+        ) -> Result<Vec<AttributeValue<'a>>, Error> {
+            Err(Error::NOTSUPP)
+        }
+
+        async fn read<'a>(
+            &self,
+            _credentials: &Credentials,
+            _file_handle: &FileHandle<'a>,
+            args: ReadArgs,
+        ) -> Result<ReadResult<'a>, Error> {
+            let file = self.file.lock().unwrap();
+            let start = (args.offset as usize).min(file.len());
+            let end = (start + args.count as usize).min(file.len());
+            Ok(ReadResult {
+                eof: end == file.len(),
+                data: file[start..end].to_vec().into(),
+            })
+        }
+
+        async fn read_directory<'a>(
+            &self,
+            _credentials: &Credentials,
+            _file_handle: &FileHandle<'a>,
+            _args: ReadDirectoryArgs<'a>,
+        ) -> Result<ReadDirectoryResult<'a>, Error> {
+            Ok(ReadDirectoryResult {
+                cookie_verf: [0; 8],
+                directory_list: DirectoryList {
+                    entries: vec![Entry {
+                        cookie: 1,
+                        name: "file".into(),
+                        attributes: vec![],
+                    }],
+                    eof: true,
+                },
+            })
+        }
+
+        async fn read_link<'a>(
+            &self,
+            _credentials: &Credentials,
+            _file_handle: &FileHandle<'a>,
+        ) -> Result<Cow<'a, str>, Error> {
+            Err(Error::INVAL)
+        }
+
+        async fn open<'a>(
+            &self,
+            _credentials: &Credentials,
+            file_handle: &FileHandle<'a>,
+            _args: OpenArgs<'a>,
+        ) -> Result<(FileHandle<'a>, OpenResult<'a>), Error> {
+            let open_result = OpenResult {
+                state_id: StateId {
+                    sequence_id: 1,
+                    other: [0; 12],
+                },
+                change_info: ChangeInfo {
+                    atomic: true,
+                    before: 0,
+                    after: 0,
+                },
+                flags: OpenResultFlags::empty(),
+                attributes: AttributeMask::new(),
+                delegation: OpenDelegation::None,
+            };
+            Ok((file_handle.clone(), open_result))
+        }
+
+        async fn create<'a>(
+            &self,
+            _credentials: &Credentials,
+            _directory: &FileHandle<'a>,
+            _args: CreateArgs<'a>,
+        ) -> Result<(FileHandle<'a>, CreateResult<'a>), Error> {
+            Err(Error::NOTSUPP)
+        }
+
+        async fn link<'a>(
+            &self,
+            _credentials: &Credentials,
+            _source: &FileHandle<'a>,
+            _directory: &FileHandle<'a>,
+            _name: &str,
+        ) -> Result<ChangeInfo, Error> {
+            Err(Error::NOTSUPP)
+        }
+
+        async fn remove<'a>(
+            &self,
+            _credentials: &Credentials,
+            _directory: &FileHandle<'a>,
+            _name: &str,
+        ) -> Result<ChangeInfo, Error> {
+            Err(Error::NOTSUPP)
+        }
+
+        async fn rename<'a>(
+            &self,
+            _credentials: &Credentials,
+            _source_directory: &FileHandle<'a>,
+            _target_directory: &FileHandle<'a>,
+            _args: RenameArgs<'a>,
+        ) -> Result<RenameResult, Error> {
+            Err(Error::NOTSUPP)
+        }
+
+        async fn set_attributes<'a>(
+            &self,
+            _credentials: &Credentials,
+            _file_handle: &FileHandle<'a>,
+            _args: SetAttributesArgs<'a>,
+        ) -> Result<AttributeMask<'a>, Error> {
+            Err(Error::NOTSUPP)
+        }
+
+        async fn write<'a>(
+            &self,
+            _credentials: &Credentials,
+            _file_handle: &FileHandle<'a>,
+            args: WriteArgs<'a>,
+        ) -> Result<WriteResult, Error> {
+            let mut file = self.file.lock().unwrap();
+            let start = args.offset as usize;
+            let end = start + args.data.len();
+            if file.len() < end {
+                file.resize(end, 0);
+            }
+            file[start..end].copy_from_slice(&args.data);
+            Ok(WriteResult {
+                count: args.data.len() as u32,
+                committed: StableHow::FileSync,
+                verifier: [0; 8],
+            })
+        }
+
+        async fn commit<'a>(
+            &self,
+            _credentials: &Credentials,
+            _file_handle: &FileHandle<'a>,
+            _args: CommitArgs,
+        ) -> Result<Verifier, Error> {
+            Err(Error::NOTSUPP)
+        }
+
+        async fn destroy_session(
+            &self,
+            _session_id: SessionId,
+        ) -> Result<(), Error> {
+            Ok(())
+        }
+
+        async fn destroy_client_id(
+            &self,
+            _client_id: ClientId,
+        ) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    fn call(
+        xid: u32,
+        session_id: Option<SessionId>,
+        operations: &[u8],
+    ) -> Vec<u8> {
+        let mut call = Vec::new();
+        // xid, CALL, RPC version 2, NFS program 100003 version 4, COMPOUND, AUTH_NONE twice,
+        // empty tag and minor version 1
+        for word in [xid, 0, 2, 100003, 4, 1, 0, 0, 0, 0, 0, 1] {
+            call.extend_from_slice(&word.to_be_bytes());
+        }
+        match session_id {
+            Some(session_id) => {
+                // SEQUENCE on slot 0 with the xid as sequence ID, then PUTROOTFH
+                for word in [3u32, 53] {
+                    call.extend_from_slice(&word.to_be_bytes());
+                }
+                call.extend_from_slice(&session_id);
+                for word in [xid, 0, 0, 0, 24] {
+                    call.extend_from_slice(&word.to_be_bytes());
+                }
+            }
+            None => {
+                for word in [2u32, 24] {
+                    call.extend_from_slice(&word.to_be_bytes());
+                }
+            }
+        }
+        call.extend_from_slice(operations);
+        call
+    }
+
+    /// A compound with a single operation that manages sessions, without a SEQUENCE.
+    fn session_call(
+        xid: u32,
+        operation: &[u8],
+    ) -> Vec<u8> {
+        let mut call = [xid, 0, 2, 100003, 4, 1, 0, 0, 0, 0, 0, 1, 1]
+            .map(u32::to_be_bytes)
+            .concat();
+        call.extend_from_slice(operation);
+        call
+    }
+
+    async fn send<'a>(
+        stream: &mut TcpStream,
+        xid: u32,
+        session_id: Option<SessionId>,
+        operations: &[u8],
+        buffer: &'a mut Vec<u8>,
+    ) -> CompoundResult<'a> {
+        send_call(stream, xid, &call(xid, session_id, operations), buffer).await
+    }
+
+    async fn send_call<'a>(
+        stream: &mut TcpStream,
+        xid: u32,
+        call: &[u8],
+        buffer: &'a mut Vec<u8>,
+    ) -> CompoundResult<'a> {
+        record::write(stream, call).await.unwrap();
+        *buffer = record::read(stream).await.unwrap().unwrap().to_vec();
+        let (input, message) = protocol::decode::message(buffer).unwrap();
+        assert_eq!(message.xid, xid);
+        let (_, reply) = protocol::decode::reply(ProcedureNumber::Compound)(input).unwrap();
+        let Reply::Accepted(AcceptedReply {
+            body: AcceptedReplyBody::Success(ProcedureReply::Compound(result)),
+            ..
+        }) = reply
+        else {
+            panic!("unexpected reply: {reply:?}");
+        };
+        result
+    }
+
+    fn read(offset: usize) -> Vec<u8> {
+        let mut read = vec![0, 0, 0, 25];
+        read.extend_from_slice(&[0; 16]);
+        read.extend_from_slice(&(offset as u64).to_be_bytes());
+        read.extend_from_slice(&(CHUNK_LENGTH as u32).to_be_bytes());
+        read
+    }
+
+    fn open(share_access: ShareAccessFlags) -> Vec<u8> {
+        // OPEN with sequence ID 0, no deny, owner "owner", NOCREATE and CLAIM_FH
+        let mut open = [18, 0, share_access.bits(), 0, 0, 0, 5]
+            .map(u32::to_be_bytes)
+            .concat();
+        open.extend_from_slice(b"owner\0\0\0");
+        open.extend_from_slice(&[0u32, 4].map(u32::to_be_bytes).concat());
+        open
+    }
+
+    async fn start_server(handler: TestHandler) -> (SocketAddr, SessionId) {
+        let server = Server::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let address = server.local_address().unwrap();
+        let channel_attributes = ChannelAttributes {
+            header_pad_size: 0,
+            max_request_size: u32::MAX,
+            max_response_size: u32::MAX,
+            max_response_size_cached: u32::MAX,
+            max_operations: 8,
+            max_requests: 1,
+            rdma_ird: None,
+        };
+        let (exchange_id, _) = server.state.exchange_id(&ClientOwner {
+            verifier: [0; 8],
+            owner_id: b"test".into(),
+        });
+        let (session, _) = server
+            .state
+            .create_session(&CreateSessionArgs {
+                client_id: exchange_id.client_id,
+                sequence_id: exchange_id.sequence_id,
+                flags: CreateSessionFlags::empty(),
+                fore_channel_attributes: channel_attributes,
+                back_channel_attributes: channel_attributes,
+                cb_program: 0,
+                sec_parms: vec![],
+            })
+            .unwrap();
+        tokio::spawn(async move { server.listen(handler).await });
+        (address, session.session_id)
+    }
+
+    #[tokio::test]
+    async fn test_transfer_multi_megabyte_file() {
+        let content = (0..FILE_LENGTH)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let handler = TestHandler {
+            file: Arc::new(Mutex::new(content.clone())),
+        };
+        let (address, session_id) = start_server(handler.clone()).await;
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let mut buffer = Vec::new();
+        let mut xid = 0;
+
+        let mut received = Vec::new();
+        for offset in (0..FILE_LENGTH).step_by(CHUNK_LENGTH) {
+            xid += 1;
+            let mut result = send(
+                &mut stream,
+                xid,
+                Some(session_id),
+                &read(offset),
+                &mut buffer,
+            )
+            .await;
+            assert_eq!(result.error, None);
+            let Some(NfsResOp::Read(Ok(result))) = result.resarray.pop() else {
+                panic!("READ failed");
+            };
+            assert_eq!(result.eof, offset + CHUNK_LENGTH == FILE_LENGTH);
+            received.extend_from_slice(&result.data);
+        }
+        assert_eq!(received, content);
+
+        let content = content.iter().rev().copied().collect::<Vec<_>>();
+        for (index, chunk) in content.chunks(CHUNK_LENGTH).enumerate() {
+            let offset = index * CHUNK_LENGTH;
+            let mut write = vec![0, 0, 0, 38];
+            write.extend_from_slice(&[0; 16]);
+            write.extend_from_slice(&(offset as u64).to_be_bytes());
+            write.extend_from_slice(&2u32.to_be_bytes());
+            write.extend_from_slice(&(chunk.len() as u32).to_be_bytes());
+            write.extend_from_slice(chunk);
+            xid += 1;
+            let mut result = send(&mut stream, xid, Some(session_id), &write, &mut buffer).await;
+            assert_eq!(result.error, None);
+            let Some(NfsResOp::Write(Ok(result))) = result.resarray.pop() else {
+                panic!("WRITE failed");
+            };
+            assert_eq!(result.count as usize, chunk.len());
+        }
+        assert_eq!(*handler.file.lock().unwrap(), content);
+    }
+
+    #[test]
+    fn test_server_fault() {
+        let mime_type = "a".repeat(record::MAX_RECORD_LENGTH);
+        let reply = Reply::Accepted(AcceptedReply {
+            verf: OpaqueAuth {
+                flavor: AuthFlavor::AuthNone,
+                body: (&[]).into(),
+            },
+            body: AcceptedReplyBody::Success(ProcedureReply::Compound(CompoundResult {
+                error: None,
+                tag: "getattr".into(),
+                resarray: vec![NfsResOp::GetAttributes(Ok(vec![AttributeValue::MimeType(
+                    mime_type.as_str().into(),
+                )]))],
+            })),
+        });
+        let mut output = vec![0; INITIAL_OUTPUT_LENGTH];
+        assert_eq!(encode_reply(1, &reply, &mut output), None);
+        let length = encode_reply(1, &server_fault(&reply), &mut output).unwrap();
+        let (input, _) = protocol::decode::message(&output[..length]).unwrap();
+        let (_, reply) = protocol::decode::reply(ProcedureNumber::Compound)(input).unwrap();
+        let Reply::Accepted(AcceptedReply {
+            body: AcceptedReplyBody::Success(ProcedureReply::Compound(result)),
+            ..
+        }) = reply
+        else {
+            panic!("expected a compound result");
+        };
+        assert_eq!(result.error, Some(Error::SERVERFAULT));
+        assert_eq!(&*result.tag, "getattr");
+        assert!(result.resarray.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_compound_requires_session() {
+        let handler = TestHandler {
+            file: Arc::new(Mutex::new(vec![])),
+        };
+        let (address, session_id) = start_server(handler).await;
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let mut buffer = Vec::new();
+
+        let result = send(&mut stream, 1, None, &read(0), &mut buffer).await;
+        assert_eq!(result.error, Some(protocol::Error::OP_NOT_IN_SESSION));
+        assert_eq!(
+            result.resarray,
+            vec![NfsResOp::PutRootFileHandle(Err(
+                protocol::Error::OP_NOT_IN_SESSION
+            ))]
+        );
+
+        let result = send(&mut stream, 1, Some(session_id), &read(0), &mut buffer).await;
+        assert_eq!(result.error, None);
+        assert_eq!(result.resarray.len(), 3);
+
+        let result = send(&mut stream, 3, Some(session_id), &read(0), &mut buffer).await;
+        assert_eq!(result.error, Some(protocol::Error::SEQ_MISORDERED));
+        assert_eq!(result.resarray.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_unsupported_credentials() {
+        let handler = TestHandler {
+            file: Arc::new(Mutex::new(vec![])),
+        };
+        let (address, _) = start_server(handler).await;
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let mut call = call(7, None, &read(0));
+        // RPCSEC_GSS instead of AUTH_NONE as the credential flavor
+        call[24..28].copy_from_slice(&6u32.to_be_bytes());
+        record::write(&mut stream, &call).await.unwrap();
+        let reply = record::read(&mut stream).await.unwrap().unwrap();
+        // xid, REPLY, MSG_DENIED, AUTH_ERROR, AUTH_BADCRED
+        let words = [7u32, 1, 1, 1, 1].map(u32::to_be_bytes).concat();
+        assert_eq!(&reply[..], &words[..]);
+    }
+
+    #[tokio::test]
+    async fn test_garbage_args() {
+        let handler = TestHandler {
+            file: Arc::new(Mutex::new(vec![])),
+        };
+        let (address, _) = start_server(handler).await;
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let mut call = call(7, None, &read(0));
+        call.truncate(call.len() - 4);
+        record::write(&mut stream, &call).await.unwrap();
+        let reply = record::read(&mut stream).await.unwrap().unwrap();
+        let (input, _) = protocol::decode::message(&reply).unwrap();
+        let (_, reply) = protocol::decode::reply(ProcedureNumber::Compound)(input).unwrap();
+        assert!(matches!(
+            reply,
+            Reply::Accepted(AcceptedReply {
+                body: AcceptedReplyBody::GarbageArgs,
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_file_handle_stack() {
+        let handler = TestHandler {
+            file: Arc::new(Mutex::new(vec![])),
+        };
+        let (address, session_id) = start_server(handler).await;
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let mut buffer = Vec::new();
+
+        // RESTOREFH before SAVEFH
+        let result = send(
+            &mut stream,
+            1,
+            Some(session_id),
+            &[0, 0, 0, 31],
+            &mut buffer,
+        )
+        .await;
+        assert_eq!(result.error, Some(protocol::Error::RESTOREFH));
+
+        let result = send(
+            &mut stream,
+            2,
+            Some(session_id),
+            &[0, 0, 0, 16],
+            &mut buffer,
+        )
+        .await;
+        assert_eq!(
+            result.resarray.last(),
+            Some(&NfsResOp::LookupParent(Err(protocol::Error::NOENT)))
+        );
+        // SAVEFH, RESTOREFH, GETFH and VERIFY, after which nothing can be decoded
+        let mut call = call(3, Some(session_id), &[0, 0, 0, 32]);
+        call[48..52].copy_from_slice(&6u32.to_be_bytes());
+        for word in [31, 10, 37, 0, 25] {
+            call.extend_from_slice(&u32::to_be_bytes(word));
+        }
+        record::write(&mut stream, &call).await.unwrap();
+        let message = record::read(&mut stream).await.unwrap().unwrap();
+        let (input, _) = protocol::decode::message(&message).unwrap();
+        let (_, reply) = protocol::decode::reply(ProcedureNumber::Compound)(input).unwrap();
+        let Reply::Accepted(AcceptedReply {
+            body: AcceptedReplyBody::Success(ProcedureReply::Compound(result)),
+            ..
+        }) = reply
+        else {
+            panic!("unexpected reply: {reply:?}");
+        };
+        assert_eq!(result.error, Some(protocol::Error::NOTSUPP));
+        assert_eq!(
+            result.resarray[2..],
+            [
+                NfsResOp::SaveFileHandle(Ok(())),
+                NfsResOp::RestoreFileHandle(Ok(())),
+                NfsResOp::GetFileHandle(Ok(FileHandle::from(&[0]))),
+                NfsResOp::Unsupported(NfsOpnum::VERIFY, protocol::Error::NOTSUPP),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_recall_delegation() {
+        let handler = TestHandler {
+            file: Arc::new(Mutex::new(vec![])),
+        };
+        let (address, session_id) = start_server(handler).await;
+        let mut holder = TcpStream::connect(address).await.unwrap();
+        let mut other = TcpStream::connect(address).await.unwrap();
+        let mut buffer = Vec::new();
+
+        // EXCHANGE_ID of owner "holder" without state protection or implementation ID
+        let mut exchange_id = [42u32, 0, 0, 6].map(u32::to_be_bytes).concat();
+        exchange_id.extend_from_slice(b"holder\0\0");
+        exchange_id.extend_from_slice(&[0u32, 0, 0].map(u32::to_be_bytes).concat());
+        let result = send_call(&mut holder, 1, &session_call(1, &exchange_id), &mut buffer).await;
+        let Some(NfsResOp::ExchangeId(Ok(exchange_id))) = result.resarray.first() else {
+            panic!("EXCHANGE_ID failed");
+        };
+        let (client_id, sequence_id) = (exchange_id.client_id, exchange_id.sequence_id);
+        // CREATE_SESSION with a back channel to program 0x40000000 as root over AUTH_SYS
+        let mut create_session = vec![0, 0, 0, 43];
+        create_session.extend_from_slice(&client_id.to_be_bytes());
+        let channel_attributes = [0, 1 << 20, 1 << 20, 1 << 20, 8, 1, 0];
+        let words = [
+            &[sequence_id, 2][..],
+            &channel_attributes,
+            &channel_attributes,
+        ]
+        .concat()
+        .into_iter()
+        .chain([0x40000000, 1, 1, 0, 4])
+        .map(u32::to_be_bytes)
+        .collect::<Vec<_>>()
+        .concat();
+        create_session.extend_from_slice(&words);
+        create_session.extend_from_slice(b"test");
+        create_session.extend_from_slice(&[0u32, 0, 0].map(u32::to_be_bytes).concat());
+        let result = send_call(
+            &mut holder,
+            2,
+            &session_call(2, &create_session),
+            &mut buffer,
+        )
+        .await;
+        let Some(NfsResOp::CreateSession(Ok(session))) = result.resarray.first() else {
+            panic!("CREATE_SESSION failed");
+        };
+        assert!(session.flags.contains(CreateSessionFlags::CONN_BACK_CHAN));
+        let holder_session_id = session.session_id;
+
+        let access = ShareAccessFlags::READ | ShareAccessFlags::WRITE;
+        let mut result = send(
+            &mut holder,
+            1,
+            Some(holder_session_id),
+            &open(access),
+            &mut buffer,
+        )
+        .await;
+        let Some(NfsResOp::Open(Ok(OpenResult {
+            delegation: OpenDelegation::Write(delegation),
+            ..
+        }))) = result.resarray.pop()
+        else {
+            panic!("expected a write delegation");
+        };
+        let state_id = delegation.state_id.clone();
+
+        // WRITE of "data" at offset 0 with the anonymous stateid, UNSTABLE
+        let mut write = vec![0, 0, 0, 38];
+        write.extend_from_slice(&[0; 16 + 8]);
+        write.extend_from_slice(&[0u32, 4].map(u32::to_be_bytes).concat());
+        write.extend_from_slice(b"data");
+        let result = send(&mut other, 1, Some(session_id), &write, &mut buffer).await;
+        assert_eq!(result.error, Some(protocol::Error::DELAY));
+        let result = send(
+            &mut other,
+            2,
+            Some(session_id),
+            &open(ShareAccessFlags::READ),
+            &mut buffer,
+        )
+        .await;
+        assert_eq!(result.error, Some(protocol::Error::DELAY));
+
+        let message = record::read(&mut holder).await.unwrap().unwrap();
+        let (input, message) = protocol::decode::message(&message).unwrap();
+        assert_eq!(message.message_type, MessageType::Call);
+        let (_, call) = protocol::decode::callback_call(input).unwrap();
+        assert_eq!(call.program, 0x40000000);
+        assert_eq!(call.cred.flavor, AuthFlavor::AuthSys);
+        let [CallbackArgOp::Sequence(ref sequence), CallbackArgOp::Recall(ref recall)] =
+            call.args.argarray[..]
+        else {
+            panic!("unexpected callback: {call:?}");
+        };
+        assert_eq!(sequence.session_id, holder_session_id);
+        assert_eq!(recall.state_id, state_id);
+        assert_eq!(recall.file_handle, FileHandle::from(&[0]));
+        let reply = CallbackCompoundResult {
+            error: None,
+            tag: "".into(),
+            resarray: vec![
+                CallbackResOp::Sequence(Ok(CallbackSequenceResult {
+                    session_id: holder_session_id,
+                    sequence_id: sequence.sequence_id,
+                    slot_id: 0,
+                    highest_slot_id: 0,
+                    target_highest_slot_id: 0,
+                })),
+                CallbackResOp::Recall(Ok(())),
+            ],
+        };
+        let generator = tuple((
+            protocol::encode::message(RpcMessage {
+                xid: message.xid,
+                message_type: MessageType::Reply,
+            }),
+            protocol::encode::callback_reply(&reply),
+        ));
+        let (reply, _) = gen(generator, Vec::new()).unwrap();
+        record::write(&mut holder, &reply).await.unwrap();
+
+        // DELEGRETURN
+        let mut delegation_return = vec![0, 0, 0, 8];
+        delegation_return.extend_from_slice(&state_id.sequence_id.to_be_bytes());
+        delegation_return.extend_from_slice(&state_id.other);
+        let result = send(
+            &mut holder,
+            2,
+            Some(holder_session_id),
+            &delegation_return,
+            &mut buffer,
+        )
+        .await;
+        assert_eq!(result.error, None);
+
+        // Without a back channel, the other client gets no delegation.
+        let mut result = send(
+            &mut other,
+            3,
+            Some(session_id),
+            &open(ShareAccessFlags::READ),
+            &mut buffer,
+        )
+        .await;
+        assert_eq!(result.error, None);
+        let Some(NfsResOp::Open(Ok(open_result))) = result.resarray.pop() else {
+            panic!("OPEN failed");
+        };
+        assert_eq!(open_result.delegation, OpenDelegation::None);
+    }
+
+    #[cfg(feature = "client")]
+    #[tokio::test]
+    async fn test_client() {
+        let handler = TestHandler {
+            file: Arc::new(Mutex::new(vec![])),
+        };
+        let (address, _) = start_server(handler.clone()).await;
+        let credentials = AuthSysParms {
+            stamp: 0,
+            machine_name: "test".into(),
+            uid: 1000,
+            gid: 1000,
+            gids: vec![],
+        };
+        let mut client = crate::Client::connect(address, &credentials).await.unwrap();
+        let content = (0..FILE_LENGTH)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+
+        client.write_file("/file", &content).await.unwrap();
+        assert_eq!(*handler.file.lock().unwrap(), content);
+        assert_eq!(client.read_file("/file").await.unwrap(), content);
+        assert_eq!(client.list_dir("/").await.unwrap(), ["file"]);
+        assert!(matches!(
+            client.read_file("/missing").await,
+            Err(crate::client::Error::Nfs(Error::NOENT))
+        ));
+        client.disconnect().await.unwrap();
+    }
+}
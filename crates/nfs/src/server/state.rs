@@ -0,0 +1,762 @@
+use super::callback::BackChannel;
+use super::delegation::{Delegated, Delegations, Recall};
+use super::lock::{Locked, Locks};
+use super::record::MAX_RECORD_LENGTH;
+use crate::protocol::*;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub const LEASE_TIME: Duration = Duration::from_secs(90);
+const MAX_SLOTS: u32 = 64;
+/// The largest reply a slot keeps for retransmissions.
+const MAX_CACHED_REPLY_LENGTH: u32 = 64 * 1024;
+/// How much memory the replies kept in all slots may take. Replies beyond it are not kept, and
+/// their retransmissions fail with `NFS4ERR_RETRY_UNCACHED_REP`.
+const MAX_CACHED_REPLIES_LENGTH: usize = 64 * 1024 * 1024;
+
+/// Client IDs, sessions, locks and delegations shared by every connection to the server.
+#[derive(Default)]
+pub struct State {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    next_client_id: ClientId,
+    next_session_id: u64,
+    clients: HashMap<ClientId, Client>,
+    sessions: HashMap<SessionId, Session>,
+    /// The total length of the replies kept in slots.
+    cached_replies_length: usize,
+    locks: Locks,
+    delegations: Delegations,
+}
+
+struct Client {
+    owner_id: Vec<u8>,
+    verifier: Verifier,
+    sequence_id: SequenceId,
+    confirmed: bool,
+    /// The confirmed record of the same owner from before the client rebooted, which is only
+    /// dropped once this one is confirmed.
+    replaces: Option<ClientId>,
+    reclaim_complete: bool,
+    create_session_reply: Option<CreateSessionResult>,
+    expires: Instant,
+}
+
+struct Session {
+    client_id: ClientId,
+    max_response_size_cached: u32,
+    slots: Vec<Slot>,
+    back_channel: Option<Arc<BackChannel>>,
+}
+
+#[derive(Default)]
+struct Slot {
+    sequence_id: SequenceId,
+    in_progress: bool,
+    cache_this: bool,
+    reply: Option<Vec<u8>>,
+}
+
+pub struct ExchangeId {
+    pub client_id: ClientId,
+    pub sequence_id: SequenceId,
+    pub confirmed: bool,
+}
+
+pub enum Sequence {
+    /// The request is new and its reply should be stored with [`State::complete`].
+    New {
+        client_id: ClientId,
+        result: SequenceResult,
+    },
+    /// The request was already executed and this is the reply that was sent.
+    Replay(Vec<u8>),
+}
+
+/// State dropped by the server that the handler should release too.
+#[derive(Default)]
+pub struct Released {
+    pub sessions: Vec<SessionId>,
+    pub clients: Vec<ClientId>,
+    pub locks: Vec<(FileHandle<'static>, LockOwner<'static>)>,
+}
+
+impl State {
+    /// Returns the record of the client owner, or a new unconfirmed one if the owner is new or
+    /// rebooted (RFC 5661 18.35.5). The state of a rebooted client stays until the new record
+    /// is confirmed by CREATE_SESSION, while unconfirmed records of the owner are replaced.
+    pub fn exchange_id(
+        &self,
+        owner: &ClientOwner<'_>,
+    ) -> (ExchangeId, Released) {
+        let mut inner = self.inner.lock().unwrap();
+        let mut released = Released::default();
+        let records: Vec<_> = inner
+            .clients
+            .iter()
+            .filter(|(_, client)| client.owner_id == *owner.owner_id)
+            .map(|(client_id, client)| (*client_id, client.verifier == owner.verifier))
+            .collect();
+        if let Some((client_id, _)) = records.iter().find(|(_, same_verifier)| *same_verifier) {
+            let client = inner.clients.get_mut(client_id).unwrap();
+            client.expires = Instant::now() + LEASE_TIME;
+            return (
+                ExchangeId {
+                    client_id: *client_id,
+                    sequence_id: client.sequence_id,
+                    confirmed: client.confirmed,
+                },
+                released,
+            );
+        }
+        let mut replaces = None;
+        for (client_id, _) in records {
+            match inner.clients[&client_id].confirmed {
+                true => replaces = Some(client_id),
+                false => inner.remove_client(client_id, &mut released),
+            }
+        }
+        inner.next_client_id += 1;
+        let client_id = inner.next_client_id;
+        inner.clients.insert(
+            client_id,
+            Client {
+                owner_id: owner.owner_id.to_vec(),
+                verifier: owner.verifier,
+                sequence_id: 1,
+                confirmed: false,
+                replaces,
+                reclaim_complete: false,
+                create_session_reply: None,
+                expires: Instant::now() + LEASE_TIME,
+            },
+        );
+        (
+            ExchangeId {
+                client_id,
+                sequence_id: 1,
+                confirmed: false,
+            },
+            released,
+        )
+    }
+
+    /// Creates a session, confirming the client record, which drops the state of the client
+    /// from before it rebooted.
+    pub fn create_session(
+        &self,
+        args: &CreateSessionArgs<'_>,
+    ) -> Result<(CreateSessionResult, Released), Error> {
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+        let mut released = Released::default();
+        let client = inner
+            .clients
+            .get_mut(&args.client_id)
+            .ok_or(Error::STALE_CLIENTID)?;
+        if args.sequence_id.wrapping_add(1) == client.sequence_id {
+            let reply = client.create_session_reply.clone();
+            return reply
+                .map(|reply| (reply, released))
+                .ok_or(Error::SEQ_MISORDERED);
+        }
+        if args.sequence_id != client.sequence_id {
+            return Err(Error::SEQ_MISORDERED);
+        }
+        client.sequence_id = client.sequence_id.wrapping_add(1);
+        client.confirmed = true;
+        client.expires = Instant::now() + LEASE_TIME;
+        if let Some(replaced) = client.replaces.take() {
+            inner.remove_client(replaced, &mut released);
+        }
+
+        let max_record_length = MAX_RECORD_LENGTH as u32;
+        let mut fore_channel_attributes = args.fore_channel_attributes;
+        fore_channel_attributes.max_request_size = fore_channel_attributes
+            .max_request_size
+            .min(max_record_length);
+        fore_channel_attributes.max_response_size = fore_channel_attributes
+            .max_response_size
+            .min(max_record_length);
+        fore_channel_attributes.max_response_size_cached = fore_channel_attributes
+            .max_response_size_cached
+            .min(MAX_CACHED_REPLY_LENGTH);
+        fore_channel_attributes.max_requests =
+            fore_channel_attributes.max_requests.clamp(1, MAX_SLOTS);
+
+        inner.next_session_id += 1;
+        let mut session_id = [0; 16];
+        session_id[..8].copy_from_slice(&args.client_id.to_be_bytes());
+        session_id[8..].copy_from_slice(&inner.next_session_id.to_be_bytes());
+        let result = CreateSessionResult {
+            session_id,
+            sequence_id: args.sequence_id,
+            flags: args.flags & CreateSessionFlags::CONN_BACK_CHAN,
+            fore_channel_attributes,
+            back_channel_attributes: args.back_channel_attributes,
+        };
+        if let Some(client) = inner.clients.get_mut(&args.client_id) {
+            client.create_session_reply = Some(result.clone());
+        }
+        inner.sessions.insert(
+            session_id,
+            Session {
+                client_id: args.client_id,
+                max_response_size_cached: fore_channel_attributes.max_response_size_cached,
+                slots: (0..fore_channel_attributes.max_requests)
+                    .map(|_| Slot::default())
+                    .collect(),
+                back_channel: None,
+            },
+        );
+        Ok((result, released))
+    }
+
+    /// Lets the server call the client of the session on the connection of the back channel.
+    pub fn bind_back_channel(
+        &self,
+        session_id: &SessionId,
+        back_channel: Arc<BackChannel>,
+    ) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(session) = inner.sessions.get_mut(session_id) {
+            session.back_channel = Some(back_channel);
+        }
+    }
+
+    pub fn sequence(
+        &self,
+        args: &SequenceArgs,
+    ) -> Result<Sequence, Error> {
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+        let session = inner
+            .sessions
+            .get_mut(&args.session_id)
+            .ok_or(Error::BADSESSION)?;
+        let highest_slot_id = session.slots.len() as SlotId - 1;
+        let slot = session
+            .slots
+            .get_mut(args.slot_id as usize)
+            .ok_or(Error::BADSLOT)?;
+        if args.sequence_id == slot.sequence_id {
+            if slot.in_progress {
+                return Err(Error::DELAY);
+            }
+            return match slot.reply {
+                Some(ref reply) => Ok(Sequence::Replay(reply.clone())),
+                None => Err(Error::RETRY_UNCACHED_REP),
+            };
+        }
+        if args.sequence_id != slot.sequence_id.wrapping_add(1) {
+            return Err(Error::SEQ_MISORDERED);
+        }
+        slot.sequence_id = args.sequence_id;
+        slot.in_progress = true;
+        slot.cache_this = args.cache_this;
+        if let Some(reply) = slot.reply.take() {
+            inner.cached_replies_length -= reply.len();
+        }
+        if let Some(client) = inner.clients.get_mut(&session.client_id) {
+            client.expires = Instant::now() + LEASE_TIME;
+        }
+        Ok(Sequence::New {
+            client_id: session.client_id,
+            result: SequenceResult {
+                session_id: args.session_id,
+                sequence_id: args.sequence_id,
+                slot_id: args.slot_id,
+                highest_slot_id,
+                target_highest_slot_id: highest_slot_id,
+                status_flags: SequenceStatusFlags::empty(),
+            },
+        })
+    }
+
+    /// Marks the request in the slot as done, keeping its reply if the client asked for it to
+    /// be cached and it fits in the cache.
+    pub fn complete(
+        &self,
+        session_id: &SessionId,
+        slot_id: SlotId,
+        reply: Option<Vec<u8>>,
+    ) {
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+        let Some(session) = inner.sessions.get_mut(session_id) else {
+            return;
+        };
+        let max_response_size_cached = session.max_response_size_cached as usize;
+        let Some(slot) = session.slots.get_mut(slot_id as usize) else {
+            return;
+        };
+        slot.in_progress = false;
+        let Some(reply) = reply.filter(|_| slot.cache_this) else {
+            return;
+        };
+        if reply.len() > max_response_size_cached
+            || inner.cached_replies_length + reply.len() > MAX_CACHED_REPLIES_LENGTH
+        {
+            tracing::debug!("Not caching a reply of {} bytes.", reply.len());
+            return;
+        }
+        inner.cached_replies_length += reply.len();
+        slot.reply = Some(reply);
+    }
+
+    pub fn destroy_session(
+        &self,
+        session_id: &SessionId,
+    ) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .remove_session(session_id)
+            .map(|_| ())
+            .ok_or(Error::BADSESSION)
+    }
+
+    pub fn destroy_client_id(
+        &self,
+        client_id: ClientId,
+    ) -> Result<Released, Error> {
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.clients.contains_key(&client_id) {
+            return Err(Error::STALE_CLIENTID);
+        }
+        if inner
+            .sessions
+            .values()
+            .any(|session| session.client_id == client_id)
+        {
+            return Err(Error::CLIENTID_BUSY);
+        }
+        let mut released = Released::default();
+        inner.remove_client(client_id, &mut released);
+        Ok(released)
+    }
+
+    pub fn reclaim_complete(
+        &self,
+        client_id: ClientId,
+        one_fs: bool,
+    ) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+        let client = inner
+            .clients
+            .get_mut(&client_id)
+            .ok_or(Error::STALE_CLIENTID)?;
+        if one_fs {
+            return Ok(());
+        }
+        if client.reclaim_complete {
+            return Err(Error::COMPLETE_ALREADY);
+        }
+        client.reclaim_complete = true;
+        Ok(())
+    }
+
+    pub fn lock(
+        &self,
+        client_id: ClientId,
+        file_handle: &FileHandle<'_>,
+        args: &LockArgs<'_>,
+    ) -> Result<Locked, LockError<'static>> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.locks.lock(client_id, file_handle, args)
+    }
+
+    pub fn lock_test(
+        &self,
+        client_id: ClientId,
+        file_handle: &FileHandle<'_>,
+        args: &LockTestArgs<'_>,
+    ) -> Result<(), LockError<'static>> {
+        let inner = self.inner.lock().unwrap();
+        inner.locks.lock_test(client_id, file_handle, args)
+    }
+
+    pub fn unlock(
+        &self,
+        client_id: ClientId,
+        file_handle: &FileHandle<'_>,
+        args: &UnlockArgs,
+    ) -> Result<Locked, Error> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.locks.unlock(client_id, file_handle, args)
+    }
+
+    /// Records that the client opened the file, which other clients cannot get delegations
+    /// for anymore.
+    pub fn open(
+        &self,
+        client_id: ClientId,
+        file_handle: &FileHandle<'_>,
+        write: bool,
+    ) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.delegations.open(client_id, file_handle, write);
+    }
+
+    pub fn close(
+        &self,
+        client_id: ClientId,
+        file_handle: &FileHandle<'_>,
+    ) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.delegations.close(client_id, file_handle);
+    }
+
+    /// Hands out a delegation with an open, as long as the client can be called back to
+    /// recall it.
+    pub fn grant_delegation(
+        &self,
+        client_id: ClientId,
+        file_handle: &FileHandle<'_>,
+        share_access: ShareAccessFlags,
+    ) -> Option<Delegated> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.back_channel(client_id)?;
+        inner
+            .delegations
+            .grant(client_id, file_handle, share_access)
+    }
+
+    /// Recalls the delegations of other clients that the open conflicts with, returning
+    /// `None` once there are none left. Delegations whose holder has no back channel are
+    /// revoked on the spot, and the others come with the back channel to recall them on.
+    pub fn recall_delegations(
+        &self,
+        client_id: ClientId,
+        file_handle: &FileHandle<'_>,
+        write: bool,
+    ) -> Option<Vec<(Arc<BackChannel>, Recall)>> {
+        let mut inner = self.inner.lock().unwrap();
+        let mut callbacks = Vec::new();
+        for recall in inner.delegations.recall(client_id, file_handle, write) {
+            match inner.back_channel(recall.client_id) {
+                Some(back_channel) => callbacks.push((back_channel, recall)),
+                None => {
+                    tracing::debug!("Revoked delegation {:?}.", recall.state_id);
+                    inner.delegations.revoke(&recall.state_id);
+                }
+            }
+        }
+        inner
+            .delegations
+            .conflicts(client_id, file_handle, write)
+            .then_some(callbacks)
+    }
+
+    pub fn validate_delegation(
+        &self,
+        client_id: ClientId,
+        file_handle: &FileHandle<'_>,
+        state_id: &StateId,
+    ) -> Result<(), Error> {
+        let inner = self.inner.lock().unwrap();
+        inner.delegations.validate(client_id, file_handle, state_id)
+    }
+
+    pub fn delegation_return(
+        &self,
+        client_id: ClientId,
+        file_handle: &FileHandle<'_>,
+        state_id: &StateId,
+    ) -> Result<(), Error> {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .delegations
+            .delegation_return(client_id, file_handle, state_id)
+    }
+
+    pub fn revoke_delegation(
+        &self,
+        state_id: &StateId,
+    ) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.delegations.revoke(state_id);
+    }
+
+    /// Drops the clients whose lease ran out together with their sessions and locks, and
+    /// revokes the delegations that were not returned in time after a recall.
+    pub fn expire(&self) -> Released {
+        let mut inner = self.inner.lock().unwrap();
+        for state_id in inner.delegations.expire(LEASE_TIME) {
+            tracing::debug!("Revoked delegation {state_id:?}.");
+        }
+        let now = Instant::now();
+        let expired = inner
+            .clients
+            .iter()
+            .filter(|(_, client)| client.expires <= now)
+            .map(|(client_id, _)| *client_id)
+            .collect::<Vec<_>>();
+        let mut released = Released::default();
+        for client_id in expired {
+            tracing::debug!("Lease of client {client_id} expired.");
+            inner.remove_client(client_id, &mut released);
+        }
+        released
+    }
+}
+
+impl Inner {
+    fn back_channel(
+        &self,
+        client_id: ClientId,
+    ) -> Option<Arc<BackChannel>> {
+        self.sessions
+            .values()
+            .filter(|session| session.client_id == client_id)
+            .find_map(|session| session.back_channel.clone())
+    }
+
+    fn remove_session(
+        &mut self,
+        session_id: &SessionId,
+    ) -> Option<Session> {
+        let session = self.sessions.remove(session_id)?;
+        self.cached_replies_length -= session
+            .slots
+            .iter()
+            .filter_map(|slot| slot.reply.as_ref())
+            .map(Vec::len)
+            .sum::<usize>();
+        Some(session)
+    }
+
+    fn remove_client(
+        &mut self,
+        client_id: ClientId,
+        released: &mut Released,
+    ) {
+        let session_ids: Vec<_> = self
+            .sessions
+            .iter()
+            .filter(|(_, session)| session.client_id == client_id)
+            .map(|(session_id, _)| *session_id)
+            .collect();
+        for session_id in session_ids {
+            self.remove_session(&session_id);
+            released.sessions.push(session_id);
+        }
+        self.clients.remove(&client_id);
+        released.clients.push(client_id);
+        released.locks.extend(self.locks.remove_client(client_id));
+        self.delegations.remove_client(client_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn owner(verifier: Verifier) -> ClientOwner<'static> {
+        ClientOwner {
+            verifier,
+            owner_id: b"client".into(),
+        }
+    }
+
+    fn channel_attributes() -> ChannelAttributes {
+        ChannelAttributes {
+            header_pad_size: 0,
+            max_request_size: u32::MAX,
+            max_response_size: u32::MAX,
+            max_response_size_cached: u32::MAX,
+            max_operations: 8,
+            max_requests: 2,
+            rdma_ird: None,
+        }
+    }
+
+    fn create_session(
+        state: &State,
+        client_id: ClientId,
+        sequence_id: SequenceId,
+    ) -> Result<CreateSessionResult, Error> {
+        state
+            .create_session(&CreateSessionArgs {
+                client_id,
+                sequence_id,
+                flags: CreateSessionFlags::empty(),
+                fore_channel_attributes: channel_attributes(),
+                back_channel_attributes: channel_attributes(),
+                cb_program: 0,
+                sec_parms: vec![],
+            })
+            .map(|(result, _)| result)
+    }
+
+    fn sequence(
+        session_id: SessionId,
+        slot_id: SlotId,
+        sequence_id: SequenceId,
+    ) -> SequenceArgs {
+        SequenceArgs {
+            session_id,
+            sequence_id,
+            slot_id,
+            highest_slot_id: slot_id,
+            cache_this: true,
+        }
+    }
+
+    #[test]
+    fn test_create_session() {
+        let state = State::default();
+        let (exchange_id, _) = state.exchange_id(&owner([1; 8]));
+        assert!(!exchange_id.confirmed);
+        let session =
+            create_session(&state, exchange_id.client_id, exchange_id.sequence_id).unwrap();
+        assert_eq!(
+            session.fore_channel_attributes.max_request_size,
+            MAX_RECORD_LENGTH as u32
+        );
+        assert_eq!(
+            create_session(&state, exchange_id.client_id, exchange_id.sequence_id),
+            Ok(session)
+        );
+        assert_eq!(
+            create_session(&state, exchange_id.client_id, exchange_id.sequence_id + 2),
+            Err(Error::SEQ_MISORDERED)
+        );
+        assert_eq!(create_session(&state, 42, 1), Err(Error::STALE_CLIENTID));
+        let (exchange_id, _) = state.exchange_id(&owner([1; 8]));
+        assert!(exchange_id.confirmed);
+    }
+
+    #[test]
+    fn test_slot_replay() {
+        let state = State::default();
+        let (exchange_id, _) = state.exchange_id(&owner([1; 8]));
+        let session = create_session(&state, exchange_id.client_id, 1).unwrap();
+        let session_id = session.session_id;
+        let Ok(Sequence::New { result, .. }) = state.sequence(&sequence(session_id, 0, 1)) else {
+            panic!("expected a new request");
+        };
+        assert_eq!(result.highest_slot_id, 1);
+        assert_eq!(
+            state.sequence(&sequence(session_id, 0, 1)).err(),
+            Some(Error::DELAY)
+        );
+        state.complete(&session_id, 0, Some(vec![1, 2, 3]));
+        let Ok(Sequence::Replay(reply)) = state.sequence(&sequence(session_id, 0, 1)) else {
+            panic!("expected a replay");
+        };
+        assert_eq!(reply, vec![1, 2, 3]);
+        assert_eq!(
+            state.sequence(&sequence(session_id, 0, 3)).err(),
+            Some(Error::SEQ_MISORDERED)
+        );
+        assert_eq!(
+            state.sequence(&sequence(session_id, 2, 1)).err(),
+            Some(Error::BADSLOT)
+        );
+        assert!(matches!(
+            state.sequence(&sequence(session_id, 0, 2)),
+            Ok(Sequence::New { .. })
+        ));
+    }
+
+    #[test]
+    fn test_slot_cache_bound() {
+        let state = State::default();
+        let (exchange_id, _) = state.exchange_id(&owner([1; 8]));
+        let session = create_session(&state, exchange_id.client_id, 1).unwrap();
+        let session_id = session.session_id;
+        assert_eq!(
+            session.fore_channel_attributes.max_response_size_cached,
+            MAX_CACHED_REPLY_LENGTH
+        );
+        state.sequence(&sequence(session_id, 0, 1)).unwrap();
+        state.complete(
+            &session_id,
+            0,
+            Some(vec![0; MAX_CACHED_REPLY_LENGTH as usize + 1]),
+        );
+        assert_eq!(
+            state.sequence(&sequence(session_id, 0, 1)).err(),
+            Some(Error::RETRY_UNCACHED_REP)
+        );
+        state.sequence(&sequence(session_id, 1, 1)).unwrap();
+        state.complete(&session_id, 1, Some(vec![0; 16]));
+        assert_eq!(state.inner.lock().unwrap().cached_replies_length, 16);
+        state.sequence(&sequence(session_id, 1, 2)).unwrap();
+        state.complete(&session_id, 1, Some(vec![0; 8]));
+        assert_eq!(state.inner.lock().unwrap().cached_replies_length, 8);
+        state.destroy_session(&session_id).unwrap();
+        assert_eq!(state.inner.lock().unwrap().cached_replies_length, 0);
+    }
+
+    #[test]
+    fn test_destroy() {
+        let state = State::default();
+        let (exchange_id, _) = state.exchange_id(&owner([1; 8]));
+        let session = create_session(&state, exchange_id.client_id, 1).unwrap();
+        assert_eq!(
+            state.destroy_client_id(exchange_id.client_id).err(),
+            Some(Error::CLIENTID_BUSY)
+        );
+        assert_eq!(state.destroy_session(&session.session_id), Ok(()));
+        assert_eq!(
+            state.sequence(&sequence(session.session_id, 0, 1)).err(),
+            Some(Error::BADSESSION)
+        );
+        let released = state.destroy_client_id(exchange_id.client_id).unwrap();
+        assert_eq!(released.clients, vec![exchange_id.client_id]);
+        assert_eq!(
+            state.destroy_client_id(exchange_id.client_id).err(),
+            Some(Error::STALE_CLIENTID)
+        );
+    }
+
+    #[test]
+    fn test_client_reboot_releases_state() {
+        let state = State::default();
+        let (exchange_id, _) = state.exchange_id(&owner([1; 8]));
+        let session = create_session(&state, exchange_id.client_id, 1).unwrap();
+        let (rebooted, released) = state.exchange_id(&owner([2; 8]));
+        assert_ne!(rebooted.client_id, exchange_id.client_id);
+        assert!(!rebooted.confirmed);
+        // The old state stays until the new record is confirmed.
+        assert!(released.clients.is_empty());
+        assert!(state.sequence(&sequence(session.session_id, 0, 1)).is_ok());
+        // Another unconfirmed record replaces the first.
+        let (retried, released) = state.exchange_id(&owner([3; 8]));
+        assert_eq!(released.clients, vec![rebooted.client_id]);
+        let (_, released) = state
+            .create_session(&CreateSessionArgs {
+                client_id: retried.client_id,
+                sequence_id: retried.sequence_id,
+                flags: CreateSessionFlags::empty(),
+                fore_channel_attributes: channel_attributes(),
+                back_channel_attributes: channel_attributes(),
+                cb_program: 0,
+                sec_parms: vec![],
+            })
+            .unwrap();
+        assert_eq!(released.clients, vec![exchange_id.client_id]);
+        assert_eq!(released.sessions, vec![session.session_id]);
+        assert_eq!(
+            state.sequence(&sequence(session.session_id, 0, 2)).err(),
+            Some(Error::BADSESSION)
+        );
+    }
+
+    #[test]
+    fn test_reclaim_complete() {
+        let state = State::default();
+        let (exchange_id, _) = state.exchange_id(&owner([1; 8]));
+        assert_eq!(state.reclaim_complete(exchange_id.client_id, false), Ok(()));
+        assert_eq!(
+            state.reclaim_complete(exchange_id.client_id, false),
+            Err(Error::COMPLETE_ALREADY)
+        );
+        assert_eq!(state.reclaim_complete(exchange_id.client_id, true), Ok(()));
+    }
+}
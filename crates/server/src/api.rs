@@ -1 +1,60 @@
-impl api::Handler for crate::Server {}
+use std::io;
+
+impl api::Handler for crate::Server {
+    async fn handle_metrics(&self) -> String {
+        self.mqtt
+            .get()
+            .map(|mqtt| mqtt.metrics().prometheus())
+            .unwrap_or_default()
+    }
+
+    async fn handle_list_snapshots(&self) -> api::Result<Vec<api::Snapshot>> {
+        let store = self.store.as_ref().ok_or(api::Error::NotFound)?;
+        Ok(store
+            .snapshots()
+            .into_iter()
+            .map(|name| api::Snapshot { name })
+            .collect())
+    }
+
+    async fn handle_create_snapshot(&self) -> api::Result<api::Snapshot> {
+        let store = self.store.clone().ok_or(api::Error::NotFound)?;
+        match tokio::task::spawn_blocking(move || store.snapshot()).await {
+            Ok(Ok(name)) => {
+                tracing::info!("Took snapshot {name}");
+                Ok(api::Snapshot { name })
+            }
+            // A snapshot was taken within the same second.
+            Ok(Err(error)) if error.kind() == io::ErrorKind::AlreadyExists => {
+                Err(api::Error::Conflict)
+            }
+            Ok(Err(error)) => {
+                tracing::error!("Snapshot failed: {error}");
+                Err(api::Error::Unknown)
+            }
+            Err(error) => {
+                tracing::error!("Snapshot failed: {error}");
+                Err(api::Error::Unknown)
+            }
+        }
+    }
+
+    async fn handle_remove_snapshot(
+        &self,
+        name: String,
+    ) -> api::Result<()> {
+        let store = self.store.clone().ok_or(api::Error::NotFound)?;
+        match tokio::task::spawn_blocking(move || store.remove_snapshot(&name)).await {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(error)) if error.kind() == io::ErrorKind::NotFound => Err(api::Error::NotFound),
+            Ok(Err(error)) => {
+                tracing::error!("Removing snapshot failed: {error}");
+                Err(api::Error::Unknown)
+            }
+            Err(error) => {
+                tracing::error!("Removing snapshot failed: {error}");
+                Err(api::Error::Unknown)
+            }
+        }
+    }
+}
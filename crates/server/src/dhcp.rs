@@ -1 +1,11 @@
-impl dhcp::Handler for crate::Server {}
+use std::net::Ipv4Addr;
+
+impl dhcp::Handler for crate::Server {
+    async fn handle_lease(
+        &self,
+        host_name: &str,
+        address: Ipv4Addr,
+    ) {
+        self.register_node(host_name, address.into()).await;
+    }
+}
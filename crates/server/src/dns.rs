@@ -1 +1,118 @@
-impl dns::Handler for crate::Server {}
+use lararium::prelude::*;
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+/// The names under which the gateway serves, besides those of the nodes.
+const GATEWAY_HOSTS: [&str; 2] = ["gateway", "server"];
+
+impl dns::Handler for crate::Server {
+    async fn handle_question(
+        &self,
+        question: &dns::Question,
+    ) -> Option<dns::Answer> {
+        self.zone.lookup(question)
+    }
+}
+
+impl crate::Server {
+    /// Records the address that a node leased at `nodes/<name>/addresses`, where `name` is the
+    /// first label of its host name, and updates the zone. A node keeps an address of each
+    /// family, so that a dual-stack node does not flap between them.
+    pub(crate) async fn register_node(
+        &self,
+        host_name: &str,
+        address: IpAddr,
+    ) {
+        let name = host_name
+            .split('.')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if name.is_empty()
+            || !name
+                .bytes()
+                .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-')
+        {
+            tracing::debug!("{host_name} does not name a host");
+            return;
+        }
+        if GATEWAY_HOSTS.contains(&name.as_str()) {
+            tracing::warn!("{address} asked for {name}, which is the gateway's");
+            return;
+        }
+        let topic = Topic::from(format!("nodes/{name}/addresses"));
+        let mut entries = self.entries.write().await;
+        let mut addresses = match entries.get(&topic) {
+            Some(Entry::Record { value, .. }) => addresses(value),
+            _ => Vec::new(),
+        };
+        if addresses.contains(&address) {
+            return;
+        }
+        addresses.retain(|current| current.is_ipv4() != address.is_ipv4());
+        addresses.push(address);
+        tracing::info!("{name} is at {address}");
+        let value = addresses
+            .iter()
+            .map(|address| Value::Text(address.to_string()))
+            .collect();
+        entries.insert(
+            topic,
+            Entry::Record {
+                schema: Schema::Any,
+                value: Value::Array(value),
+            },
+        );
+        self.update_zone(&entries);
+    }
+
+    /// Updates the zone with the addresses of the gateway and of the nodes in the registry, which
+    /// are records at `nodes/<name>/addresses`.
+    pub(crate) fn update_zone(
+        &self,
+        entries: &HashMap<Topic, Entry>,
+    ) {
+        let origin = self.zone.origin();
+        let gateway = GATEWAY_HOSTS.iter().flat_map(|host| {
+            self.addresses
+                .iter()
+                .filter_map(move |address| Some((origin.prepend(*host).ok()?, *address)))
+        });
+        let nodes = entries
+            .iter()
+            .filter_map(|(topic, entry)| {
+                let [nodes, name, addresses] = topic.segments.as_slice() else {
+                    return None;
+                };
+                if nodes.as_ref() != "nodes" || addresses.as_ref() != "addresses" {
+                    return None;
+                }
+                let Entry::Record { value, .. } = entry else {
+                    return None;
+                };
+                if GATEWAY_HOSTS.contains(&name.as_ref()) {
+                    return None;
+                }
+                let Ok(host) = origin.prepend(name.as_ref()) else {
+                    tracing::warn!("{topic} does not name a host");
+                    return None;
+                };
+                Some((host, value))
+            })
+            .flat_map(|(host, value)| {
+                addresses(value)
+                    .into_iter()
+                    .map(move |address| (host.clone(), address))
+            });
+        self.zone.set_hosts(gateway.chain(nodes));
+    }
+}
+
+/// The addresses in a record, as text on its own or in an array.
+fn addresses(value: &Value) -> Vec<IpAddr> {
+    match value {
+        Value::Text(text) => text.parse().into_iter().collect(),
+        Value::Array(values) => values.iter().flat_map(addresses).collect(),
+        _ => Vec::new(),
+    }
+}
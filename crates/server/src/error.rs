@@ -7,6 +7,8 @@ pub enum Error {
     Wasm(wasmtime::Error),
     #[from]
     Io(std::io::Error),
+    #[from]
+    Nfs(::nfs::Error),
 }
 
 impl std::error::Error for Error {}
@@ -2,6 +2,7 @@ mod api;
 mod dhcp;
 mod dns;
 mod error;
+mod mqtt;
 mod nfs;
 mod ntp;
 mod prelude;
@@ -17,8 +18,15 @@ use wasmtime::component::{bindgen, Component, Linker, ResourceTable};
 use wasmtime::{Config, Engine, Result, Store};
 use wasmtime_wasi::{WasiCtx, WasiCtxBuilder, WasiView};
 
+use lararium::prelude::*;
+use std::collections::HashMap;
 use std::future::Future;
+use std::net::IpAddr;
+use std::path::Path;
 use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::sync::RwLock;
 
 bindgen!({
     world: "application",
@@ -31,12 +39,38 @@ pub struct Server {
     identity: Identity,
     engine: Engine,
     linker: Linker<State>,
+    entries: Arc<RwLock<HashMap<Topic, Entry>>>,
+    mqtt: Arc<OnceLock<::mqtt::Server<Self>>>,
+    export: Arc<dyn nfs::Drive>,
+    store: Option<Arc<nfs::Store>>,
+    tree: Arc<nfs::EntryTree>,
+    zone: Arc<::dns::Zone>,
+    /// The addresses of the gateway in the zone.
+    addresses: Vec<IpAddr>,
+}
+
+/// How the drive keeps the files that are served over NFS.
+pub enum Storage {
+    /// As they are, in the export directory.
+    Plain,
+    /// Deduplicated by content, in a store in the export directory.
+    Deduplicated {
+        quota: Option<u64>,
+        scrub_period: Duration,
+        /// How often to take a snapshot, if at all, and how many of them to keep.
+        snapshot_period: Option<Duration>,
+        kept_snapshots: usize,
+    },
 }
 
 impl Server {
     pub async fn new(
         ca: Certificate,
         identity: Identity,
+        export_path: impl AsRef<Path>,
+        storage: Storage,
+        zone: ::dns::Zone,
+        addresses: Vec<IpAddr>,
     ) -> Result<Self, Error> {
         let engine = {
             let mut config = Config::new();
@@ -45,12 +79,62 @@ impl Server {
         };
         let mut linker = Linker::new(&engine);
         wasmtime_wasi::add_to_linker_async(&mut linker)?;
-        Ok(Self {
+        let entries = Arc::new(RwLock::new(HashMap::new()));
+        let (export, store): (Arc<dyn nfs::Drive>, _) = match storage {
+            Storage::Plain => (Arc::new(nfs::Export::new(export_path)?), None),
+            Storage::Deduplicated {
+                quota,
+                scrub_period,
+                snapshot_period,
+                kept_snapshots,
+            } => {
+                let store = Arc::new(nfs::Store::open(export_path, quota)?);
+                tokio::spawn(store.clone().scrub_every(scrub_period));
+                if let Some(snapshot_period) = snapshot_period {
+                    tokio::spawn(
+                        store
+                            .clone()
+                            .snapshot_every(snapshot_period, kept_snapshots),
+                    );
+                }
+                (store.clone(), Some(store))
+            }
+        };
+        nfs::create_volumes_directory(&*export)?;
+        let (changes, mut changed) = tokio::sync::mpsc::unbounded_channel();
+        let server = Self {
             ca,
             identity,
             engine,
             linker,
-        })
+            entries: entries.clone(),
+            mqtt: Arc::new(OnceLock::new()),
+            export,
+            store,
+            tree: Arc::new(
+                nfs::EntryTree::new(entries, vec!["drive".into()]).with_changes(changes),
+            ),
+            zone: Arc::new(zone),
+            addresses,
+        };
+        server.update_zone(&HashMap::new());
+        tokio::spawn({
+            let server = server.clone();
+            async move {
+                while let Some(topic) = changed.recv().await {
+                    server.record_changed(&topic).await;
+                }
+            }
+        });
+        Ok(server)
+    }
+
+    /// Attaches the broker so that subscribers receive the current state of matching records.
+    pub fn attach_mqtt(
+        &self,
+        mqtt: ::mqtt::Server<Self>,
+    ) {
+        let _ = self.mqtt.set(mqtt);
     }
 
     pub async fn run(
@@ -1,9 +1,13 @@
 use crypto::{Certificate, PrivateSignatureKey};
-use server::Server;
+use server::{Server, Storage};
 
 use clap::Parser;
-use std::net::{Ipv6Addr, SocketAddr};
+use nix::ifaddrs::getifaddrs;
+use nix::net::if_::InterfaceFlags;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
 use tracing_subscriber::EnvFilter;
 
 #[derive(Parser)]
@@ -11,10 +15,21 @@ use tracing_subscriber::EnvFilter;
 struct Args {
     #[arg(env, long, default_value_t = (Ipv6Addr::UNSPECIFIED, 443).into())]
     api_listen_address: SocketAddr,
+    /// The bearer token that creating and removing snapshots through the API requires.
+    #[arg(env, long)]
+    api_admin_token: Option<String>,
     #[arg(env, long, default_value_t = (Ipv6Addr::UNSPECIFIED, 67).into())]
     dhcp_listen_address: SocketAddr,
     #[arg(env, long, default_value_t = (Ipv6Addr::UNSPECIFIED, 53).into())]
     dns_listen_address: SocketAddr,
+    #[arg(env, long, default_value = "lararium")]
+    dns_zone: String,
+    /// The addresses that `gateway` and `server` in the zone resolve to, by default those of the
+    /// interfaces.
+    #[arg(env, long, value_delimiter = ',')]
+    dns_address: Vec<IpAddr>,
+    #[arg(env, long, default_value_t = (Ipv6Addr::UNSPECIFIED, 1883).into())]
+    mqtt_listen_address: SocketAddr,
     #[arg(env, long, default_value_t = (Ipv6Addr::UNSPECIFIED, 2049).into())]
     nfs_listen_address: SocketAddr,
     #[arg(env, long, default_value_t = (Ipv6Addr::UNSPECIFIED, 123).into())]
@@ -25,6 +40,57 @@ struct Args {
     private_key_path: PathBuf,
     #[arg(env, long)]
     certificate_path: PathBuf,
+    #[arg(env, long, default_value = "/drive")]
+    nfs_export_path: PathBuf,
+    #[arg(env, long)]
+    nfs_deduplicate: bool,
+    #[arg(env, long)]
+    nfs_quota: Option<u64>,
+    #[arg(
+        env,
+        long,
+        default_value_t = 24 * 60 * 60,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    nfs_scrub_period_secs: u64,
+    #[arg(env, long, value_parser = clap::value_parser!(u64).range(1..))]
+    nfs_snapshot_period_secs: Option<u64>,
+    #[arg(env, long, default_value_t = 30)]
+    nfs_kept_snapshots: usize,
+    #[arg(env, long)]
+    nfs_squash_root: bool,
+    #[arg(env, long, value_delimiter = ',')]
+    nfs_uid_map: Vec<IdMapping>,
+    #[arg(env, long, value_delimiter = ',')]
+    nfs_gid_map: Vec<IdMapping>,
+}
+
+/// Maps an id of a station onto an id of the server, written as `station:from:to` where
+/// `station` is the address that the station connects from.
+#[derive(Clone)]
+struct IdMapping {
+    station: IpAddr,
+    from: u32,
+    to: u32,
+}
+
+impl FromStr for IdMapping {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.rsplitn(3, ':');
+        let (Some(to), Some(from), Some(station)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(format!("expected station:from:to, got {s}"));
+        };
+        Ok(Self {
+            station: station
+                .parse()
+                .map_err(|error| format!("{station}: {error}"))?,
+            from: from.parse().map_err(|error| format!("{from}: {error}"))?,
+            to: to.parse().map_err(|error| format!("{to}: {error}"))?,
+        })
+    }
 }
 
 #[tokio::main]
@@ -35,6 +101,7 @@ async fn main() -> color_eyre::Result<()> {
         ("api", "info"),
         ("dhcp", "info"),
         ("dns", "debug"),
+        ("mqtt", "info"),
         ("nfs", "debug"),
         ("ntp", "debug"),
         ("server", "debug"),
@@ -49,16 +116,60 @@ async fn main() -> color_eyre::Result<()> {
     let identity = private_key.clone().into_identity(certificate.clone())?;
     let tls_private_key = PrivateSignatureKey::new()?;
     let tls_csr = tls_private_key.generate_csr()?;
-    let tls_certificate = identity.sign_csr(&tls_csr, "server.lararium")?;
+    let dns_zone = args.dns_zone.trim_end_matches('.');
+    let tls_certificate = identity.sign_csr(&tls_csr, &format!("server.{dns_zone}"))?;
 
-    let api_server =
-        api::Server::bind(args.api_listen_address, tls_private_key, tls_certificate).await?;
+    let api_server = api::Server::bind(args.api_listen_address, tls_private_key, tls_certificate)
+        .await?
+        .with_admin_token(args.api_admin_token.clone());
     let dns_server = dns::Server::bind(args.dns_listen_address).await?;
     let dhcp_server = dhcp::Server::bind(args.dhcp_listen_address).await?;
     let ntp_server = ntp::Server::bind(args.ntp_listen_address).await?;
-    let nfs_server = nfs::Server::bind(args.nfs_listen_address).await?;
+    let mut identity_map = nfs::IdentityMap::default();
+    if args.nfs_squash_root {
+        identity_map = identity_map.squash_root();
+    }
+    for IdMapping { station, from, to } in args.nfs_uid_map {
+        identity_map = identity_map.map_uid(station, from, to);
+    }
+    for IdMapping { station, from, to } in args.nfs_gid_map {
+        identity_map = identity_map.map_gid(station, from, to);
+    }
+    let nfs_server = nfs::Server::bind(args.nfs_listen_address)
+        .await?
+        .map_identities(identity_map);
+    let mqtt_server = mqtt::Server::bind(args.mqtt_listen_address).await?;
 
-    let server = Server::new(ca, identity).await?;
+    let storage = match args.nfs_deduplicate {
+        true => Storage::Deduplicated {
+            quota: args.nfs_quota,
+            scrub_period: Duration::from_secs(args.nfs_scrub_period_secs),
+            snapshot_period: args.nfs_snapshot_period_secs.map(Duration::from_secs),
+            kept_snapshots: args.nfs_kept_snapshots,
+        },
+        false => Storage::Plain,
+    };
+    let origin: dns::Name = dns_zone.parse()?;
+    let interfaces = interface_addresses()?;
+    let mut zone = dns::Zone::new(origin.clone(), origin.prepend("gateway")?);
+    for (address, prefix_length) in interfaces.iter().filter(|(address, _)| is_lan(address)) {
+        zone = zone.with_reverse_network(*address, *prefix_length);
+    }
+    let dns_addresses = match args.dns_address.is_empty() {
+        true => interfaces.iter().map(|(address, _)| *address).collect(),
+        false => args.dns_address,
+    };
+    let server = Server::new(
+        ca,
+        identity,
+        &args.nfs_export_path,
+        storage,
+        zone,
+        dns_addresses,
+    )
+    .await?;
+    server.attach_mqtt(mqtt_server.clone());
+    let api_server = api_server.mount(mqtt_server.router(server.clone()));
 
     let api_server = tokio::spawn({
         let server = server.clone();
@@ -103,6 +214,19 @@ async fn main() -> color_eyre::Result<()> {
         }
     });
 
+    let mqtt_server = tokio::spawn({
+        let server = server.clone();
+        async move {
+            tracing::info!(
+                "📬 Listening for MQTT requests: {}",
+                args.mqtt_listen_address
+            );
+            mqtt_server.listen(server).await?;
+            tracing::info!("🛑 MQTT server stopped");
+            Ok::<(), color_eyre::Report>(())
+        }
+    });
+
     let nfs_server = tokio::spawn({
         async move {
             tracing::info!("💾 Listening for NFS requests: {}", args.nfs_listen_address);
@@ -117,6 +241,7 @@ async fn main() -> color_eyre::Result<()> {
         result = dns_server => result??,
         result = dhcp_server => result??,
         result = ntp_server => result??,
+        result = mqtt_server => result??,
         result = nfs_server => result??,
         _ = tokio::signal::ctrl_c() => (),
     }
@@ -126,6 +251,47 @@ async fn main() -> color_eyre::Result<()> {
     Ok(())
 }
 
+/// The addresses of the interfaces that are up, with the lengths of their network prefixes.
+/// Loopback and link-local addresses are left out, as other hosts cannot reach them by name.
+fn interface_addresses() -> nix::Result<Vec<(IpAddr, u8)>> {
+    let addresses = getifaddrs()?
+        .filter(|interface| {
+            interface.flags.contains(InterfaceFlags::IFF_UP)
+                && !interface.flags.contains(InterfaceFlags::IFF_LOOPBACK)
+        })
+        .filter_map(|interface| {
+            let (address, netmask) = (interface.address?, interface.netmask?);
+            if let (Some(address), Some(netmask)) =
+                (address.as_sockaddr_in(), netmask.as_sockaddr_in())
+            {
+                if address.ip().is_link_local() {
+                    return None;
+                }
+                let prefix_length = u32::from(netmask.ip()).count_ones() as u8;
+                return Some((IpAddr::V4(address.ip()), prefix_length));
+            }
+            let (address, netmask) = (address.as_sockaddr_in6()?, netmask.as_sockaddr_in6()?);
+            // fe80::/10
+            if address.ip().segments()[0] & 0xffc0 == 0xfe80 {
+                return None;
+            }
+            let prefix_length = u128::from(netmask.ip()).count_ones() as u8;
+            Some((IpAddr::V6(address.ip()), prefix_length))
+        })
+        .collect();
+    Ok(addresses)
+}
+
+/// Whether the address is a private one, of a network that the gateway may answer reverse
+/// lookups for, unlike public networks whose reverse zones belong to others.
+fn is_lan(address: &IpAddr) -> bool {
+    match address {
+        IpAddr::V4(address) => address.is_private(),
+        // fc00::/7
+        IpAddr::V6(address) => address.segments()[0] & 0xfe00 == 0xfc00,
+    }
+}
+
 fn init_tracing(filter: &[(&str, &str)]) {
     let filter = filter
         .iter()
@@ -0,0 +1,310 @@
+use lararium::prelude::*;
+use mqtt::server::*;
+use mqtt::{ConnectReasonCode, Payload, PubackReasonCode, SubscribeReasonCode};
+
+impl mqtt::Handler for crate::Server {
+    async fn handle_connect(
+        &self,
+        connect: Connect,
+    ) -> Connack {
+        tracing::info!("{} connected", connect.client_identifier);
+        Connack {
+            reason_code: ConnectReasonCode::Success,
+        }
+    }
+
+    async fn handle_disconnect(
+        &self,
+        disconnect: Disconnect,
+    ) {
+        tracing::debug!(
+            "client {} disconnected: {:?}",
+            disconnect.client_id,
+            disconnect.reason_code
+        );
+    }
+
+    async fn handle_ping(&self) {}
+
+    async fn handle_publish(
+        &self,
+        publish: Publish,
+    ) -> Puback {
+        let accept = Puback {
+            reason_code: PubackReasonCode::Success,
+        };
+        if is_node(&publish.topic) {
+            tracing::warn!("only the gateway registers nodes, at {}", publish.topic);
+            return Puback {
+                reason_code: PubackReasonCode::NotAuthorized,
+            };
+        }
+        let Some(Payload::Value(value)) = publish.payload else {
+            return accept;
+        };
+        let mut entries = self.entries.write().await;
+        // Records are only created inside directories, which only root makes through the NFS tree.
+        if !entries.contains_key(&publish.topic)
+            && !matches!(entries.get(&publish.topic.parent()), Some(Entry::Directory))
+        {
+            tracing::warn!("{} is not in a directory", publish.topic);
+            return Puback {
+                reason_code: PubackReasonCode::NotAuthorized,
+            };
+        }
+        match entries.get_mut(&publish.topic) {
+            Some(Entry::Record {
+                schema,
+                value: current,
+            }) => {
+                if !schema.validate(&value) {
+                    tracing::warn!("{} does not match its schema", publish.topic);
+                    return Puback {
+                        reason_code: PubackReasonCode::PayloadFormatInvalid,
+                    };
+                }
+                *current = value;
+            }
+            Some(Entry::Signal { schema }) => {
+                if !schema.validate(&value) {
+                    tracing::warn!("{} does not match its schema", publish.topic);
+                    return Puback {
+                        reason_code: PubackReasonCode::PayloadFormatInvalid,
+                    };
+                }
+            }
+            Some(Entry::Directory) => {
+                tracing::warn!("{} is a directory", publish.topic);
+                return Puback {
+                    reason_code: PubackReasonCode::TopicNameInvalid,
+                };
+            }
+            None => {
+                entries.insert(
+                    publish.topic.clone(),
+                    Entry::Record {
+                        schema: Schema::Any,
+                        value,
+                    },
+                );
+            }
+        }
+        accept
+    }
+
+    async fn handle_subscribe(
+        &self,
+        subscribe: Subscribe,
+    ) -> Suback {
+        let records: Vec<_> = self
+            .entries
+            .read()
+            .await
+            .iter()
+            .filter_map(|(topic, entry)| match entry {
+                Entry::Record { value, .. } if subscribe.filter.matches(topic) => {
+                    Some((topic.clone(), value.clone()))
+                }
+                _ => None,
+            })
+            .collect();
+        if let Some(mqtt) = self.mqtt.get() {
+            for (topic, value) in records {
+                if let Err(error) = mqtt
+                    .publish(&[subscribe.client_id], &topic, Some(value.into()))
+                    .await
+                {
+                    tracing::warn!("failed to replay {topic}: {error}");
+                }
+            }
+        }
+        Suback {
+            reason_codes: vec![SubscribeReasonCode::GrantedQoS0],
+        }
+    }
+}
+
+impl crate::Server {
+    /// Passes on a record that changed outside the broker, such as through the NFS tree, to the
+    /// zone and to the subscribers of its topic.
+    pub(crate) async fn record_changed(
+        &self,
+        topic: &Topic,
+    ) {
+        let entries = self.entries.read().await;
+        if is_node(topic) {
+            self.update_zone(&entries);
+        }
+        let value = match entries.get(topic) {
+            Some(Entry::Record { value, .. }) => Some(value.clone().into()),
+            _ => None,
+        };
+        drop(entries);
+        if let Some(mqtt) = self.mqtt.get() {
+            if let Err(error) = mqtt.broadcast(topic, value).await {
+                tracing::warn!("failed to pass on {topic}: {error}");
+            }
+        }
+    }
+}
+
+/// Whether the record describes a node, which the zone is built from and which only the gateway
+/// registers, from DHCP leases.
+fn is_node(topic: &Topic) -> bool {
+    topic
+        .segments
+        .first()
+        .is_some_and(|segment| segment.as_ref() == "nodes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Storage;
+    use ::mqtt::async_client::{AsyncClient, Message, Messages, Options};
+    use crypto::Identity;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    /// Starts a gateway whose `kodi/volume` record holds integers, and returns it with its broker
+    /// address and the directory it exports.
+    async fn start_gateway() -> (crate::Server, SocketAddr, TempDir) {
+        let identity = Identity::new("gateway").unwrap();
+        let export_path = TempDir::new().unwrap();
+        let origin: ::dns::Name = "lararium".parse().unwrap();
+        let zone = ::dns::Zone::new(origin.clone(), origin.prepend("gateway").unwrap());
+        let server = crate::Server::new(
+            identity.certificate().clone(),
+            identity,
+            export_path.path(),
+            Storage::Plain,
+            zone,
+            Vec::new(),
+        )
+        .await
+        .unwrap();
+        server.entries.write().await.insert(
+            Topic::from("kodi/volume"),
+            Entry::Record {
+                schema: Schema::Integer,
+                value: Value::Integer(50),
+            },
+        );
+        let mqtt = ::mqtt::Server::bind("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap()
+            .with_sys_interval(None);
+        let address = mqtt.local_address().unwrap();
+        server.attach_mqtt(mqtt.clone());
+        tokio::spawn({
+            let server = server.clone();
+            async move { mqtt.listen(server).await }
+        });
+        (server, address, export_path)
+    }
+
+    async fn next(messages: &mut Messages) -> Option<Message> {
+        tokio::time::timeout(Duration::from_millis(500), messages.recv())
+            .await
+            .ok()
+            .flatten()
+    }
+
+    #[tokio::test]
+    async fn test_kodi_traffic() {
+        let (_, address, _export_path) = start_gateway().await;
+        let options = || Options::new(address.ip().to_string(), address.port());
+        let (subscriber, mut messages) = AsyncClient::connect(options()).await.unwrap();
+        subscriber.subscribe("kodi/#").await.unwrap();
+        // Subscribing replays the current state of the record.
+        let message = next(&mut messages).await.unwrap();
+        assert_eq!(message.payload, Some(Payload::Value(Value::Integer(50))));
+
+        let (publisher, _) = AsyncClient::connect(options()).await.unwrap();
+        publisher
+            .publish("kodi/volume", Value::Text("loud".into()))
+            .await
+            .unwrap();
+        publisher
+            .publish("kodi/volume", Value::Integer(80))
+            .await
+            .unwrap();
+        let message = next(&mut messages).await.unwrap();
+        assert_eq!(message.topic, Topic::from("kodi/volume"));
+        assert_eq!(message.payload, Some(Payload::Value(Value::Integer(80))));
+        assert_eq!(next(&mut messages).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_nodes_reserved() {
+        let (server, _, _export_path) = start_gateway().await;
+        let topic = Topic::from("nodes/gateway/addresses");
+        let publish = Publish {
+            client_id: 1,
+            topic: topic.clone(),
+            payload: Some(Value::Text("192.168.1.66".into()).into()),
+        };
+        let puback = ::mqtt::Handler::handle_publish(&server, publish).await;
+        assert_eq!(puback.reason_code, PubackReasonCode::NotAuthorized);
+        assert!(!server.entries.read().await.contains_key(&topic));
+    }
+
+    #[tokio::test]
+    async fn test_create_in_directory() {
+        let (server, _, _export_path) = start_gateway().await;
+        let topic = Topic::from("kodi/muted");
+        let publish = || Publish {
+            client_id: 1,
+            topic: topic.clone(),
+            payload: Some(Value::Boolean(true).into()),
+        };
+        let puback = ::mqtt::Handler::handle_publish(&server, publish()).await;
+        assert_eq!(puback.reason_code, PubackReasonCode::NotAuthorized);
+        assert!(!server.entries.read().await.contains_key(&topic));
+
+        server
+            .entries
+            .write()
+            .await
+            .insert(Topic::from("kodi"), Entry::Directory);
+        let puback = ::mqtt::Handler::handle_publish(&server, publish()).await;
+        assert_eq!(puback.reason_code, PubackReasonCode::Success);
+        assert!(matches!(
+            server.entries.read().await.get(&topic),
+            Some(Entry::Record {
+                schema: Schema::Any,
+                value: Value::Boolean(true),
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_record_changed() {
+        let (server, address, _export_path) = start_gateway().await;
+        let (subscriber, mut messages) =
+            AsyncClient::connect(Options::new(address.ip().to_string(), address.port()))
+                .await
+                .unwrap();
+        subscriber.subscribe("kodi/#").await.unwrap();
+        next(&mut messages).await.unwrap();
+
+        let topic = Topic::from("kodi/volume");
+        server.entries.write().await.insert(
+            topic.clone(),
+            Entry::Record {
+                schema: Schema::Integer,
+                value: Value::Integer(20),
+            },
+        );
+        server.record_changed(&topic).await;
+        let message = next(&mut messages).await.unwrap();
+        assert_eq!(message.payload, Some(Payload::Value(Value::Integer(20))));
+
+        server.entries.write().await.remove(&topic);
+        server.record_changed(&topic).await;
+        let message = next(&mut messages).await.unwrap();
+        assert_eq!(message.topic, topic);
+        assert_eq!(message.payload, None);
+    }
+}
@@ -0,0 +1,119 @@
+use nfs::*;
+use std::fs::Metadata;
+use std::os::unix::fs::MetadataExt;
+
+/// What access to a file goes by: its mode and its owners.
+#[derive(Clone, Copy)]
+pub struct Ownership {
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub is_directory: bool,
+}
+
+impl From<&Metadata> for Ownership {
+    fn from(metadata: &Metadata) -> Self {
+        Self {
+            mode: metadata.mode(),
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            is_directory: metadata.is_dir(),
+        }
+    }
+}
+
+pub fn check(
+    credentials: &Credentials,
+    file: impl Into<Ownership>,
+    permissions: Permissions,
+) -> Result<(), Error> {
+    let file = file.into();
+    let granted = credentials.permissions(file.mode, file.uid, file.gid, file.is_directory);
+    match granted.contains(permissions) {
+        true => Ok(()),
+        false => Err(Error::Access),
+    }
+}
+
+/// Checks access for READ and WRITE, which owners may always do since the file was opened
+/// before, possibly by the call that created it read-only.
+pub fn check_transfer(
+    credentials: &Credentials,
+    file: impl Into<Ownership>,
+    permissions: Permissions,
+) -> Result<(), Error> {
+    let file = file.into();
+    if credentials.uid == file.uid {
+        return Ok(());
+    }
+    check(credentials, file, permissions)
+}
+
+/// Checks that the caller may set the attributes, where whoever creates a file counts as its
+/// owner.
+pub fn check_attributes(
+    credentials: &Credentials,
+    file: impl Into<Ownership>,
+    attributes: &[AttributeValue<'_>],
+    created: bool,
+) -> Result<(), Error> {
+    let file = file.into();
+    let is_owner = created || credentials.is_root() || credentials.uid == file.uid;
+    for attribute in attributes {
+        match attribute {
+            AttributeValue::Size(_) if !created => check(credentials, file, Permissions::WRITE)?,
+            AttributeValue::Mode(_) | AttributeValue::ModeSetMasked(_) if !is_owner => {
+                return Err(Error::PERM);
+            }
+            AttributeValue::Owner(owner) => {
+                let owner = parse_id(owner)?;
+                if !credentials.is_root() && owner != file.uid {
+                    return Err(Error::PERM);
+                }
+            }
+            AttributeValue::OwnerGroup(group) => {
+                let group = parse_id(group)?;
+                let is_member = credentials.gid == group || credentials.gids.contains(&group);
+                if !(credentials.is_root() || is_owner && is_member) {
+                    return Err(Error::PERM);
+                }
+            }
+            AttributeValue::TimeAccessSet(SetTime::ClientTime(_))
+            | AttributeValue::TimeModifySet(SetTime::ClientTime(_))
+                if !is_owner =>
+            {
+                return Err(Error::PERM);
+            }
+            AttributeValue::TimeAccessSet(SetTime::ServerTime)
+            | AttributeValue::TimeModifySet(SetTime::ServerTime)
+                if !is_owner =>
+            {
+                check(credentials, file, Permissions::WRITE)?
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// In a sticky directory, only the owners of an entry or of the directory may remove it.
+pub fn check_sticky(
+    credentials: &Credentials,
+    directory: impl Into<Ownership>,
+    entry: impl Into<Ownership>,
+) -> Result<(), Error> {
+    let directory = directory.into();
+    if directory.mode & 0o1000 == 0
+        || credentials.is_root()
+        || credentials.uid == directory.uid
+        || credentials.uid == entry.into().uid
+    {
+        return Ok(());
+    }
+    Err(Error::Access)
+}
+
+/// Owners and groups go by their numeric id, as clients do when they authenticate with AUTH_SYS.
+pub fn parse_id(value: &str) -> Result<u32, Error> {
+    value.parse().map_err(|_| Error::BADOWNER)
+}
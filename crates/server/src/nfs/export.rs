@@ -0,0 +1,1531 @@
+use super::access::{check, check_attributes, check_sticky, check_transfer, parse_id};
+use super::{error, Drive, FIRST_COOKIE};
+use nfs::*;
+use nix::dir::Dir;
+use nix::errno::Errno;
+use nix::fcntl::{openat2, readlinkat, renameat, AtFlags, OFlag, OpenHow, ResolveFlag};
+use nix::sys::stat::{fstat, mkdirat, utimensat, Mode, SFlag, UtimensatFlags};
+use nix::sys::statvfs::fstatvfs;
+use nix::sys::time::TimeSpec;
+use nix::unistd::{fchownat, linkat, mkfifoat, symlinkat, unlinkat, Gid, Uid, UnlinkatFlags};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs::{self, File, Metadata};
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd};
+use std::os::unix::fs::{FileExt, FileTypeExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MAX_NAME_LENGTH: usize = 255;
+const MAX_TRANSFER_SIZE: u32 = 1024 * 1024;
+/// How many directories a handle has room to name within the 128 bytes that NFS allows.
+const MAX_HANDLE_LEVELS: usize = (128 - 16) / 2;
+/// How many paths of handles are remembered, beyond which handles are found again.
+const MAX_REMEMBERED_PATHS: usize = 64 * 1024;
+/// How many directories finding a handle again may read.
+const MAX_FIND_READS: usize = 256;
+
+const SUPPORTED_ATTRIBUTES: &[Attribute] = &[
+    Attribute::SupportedAttributes,
+    Attribute::Type,
+    Attribute::FileHandleExpireType,
+    Attribute::Change,
+    Attribute::Size,
+    Attribute::LinkSupport,
+    Attribute::SymlinkSupport,
+    Attribute::NamedAttributes,
+    Attribute::FileSystemId,
+    Attribute::UniqueHandles,
+    Attribute::LeaseTime,
+    Attribute::AclSupport,
+    Attribute::CanSetTime,
+    Attribute::CaseInsensitive,
+    Attribute::CasePreserving,
+    Attribute::ChownRestricted,
+    Attribute::FileHandle,
+    Attribute::FileId,
+    Attribute::FilesAvailable,
+    Attribute::FilesFree,
+    Attribute::FilesTotal,
+    Attribute::Homogeneous,
+    Attribute::MaxFileSize,
+    Attribute::MaxName,
+    Attribute::MaxRead,
+    Attribute::MaxWrite,
+    Attribute::Mode,
+    Attribute::NoTruncate,
+    Attribute::NumberOfLinks,
+    Attribute::Owner,
+    Attribute::OwnerGroup,
+    Attribute::RawDevice,
+    Attribute::SpaceAvailable,
+    Attribute::SpaceFree,
+    Attribute::SpaceTotal,
+    Attribute::SpaceUsed,
+    Attribute::TimeAccess,
+    Attribute::TimeAccessSet,
+    Attribute::TimeDelta,
+    Attribute::TimeMetadata,
+    Attribute::TimeModify,
+    Attribute::TimeModifySet,
+    Attribute::MountedOnFileId,
+    Attribute::ModeSetMasked,
+    Attribute::SupportedAttributesExclusiveCreate,
+];
+
+const SETTABLE_ATTRIBUTES: &[Attribute] = &[
+    Attribute::Size,
+    Attribute::Mode,
+    Attribute::Owner,
+    Attribute::OwnerGroup,
+    Attribute::TimeAccessSet,
+    Attribute::TimeModifySet,
+    Attribute::ModeSetMasked,
+];
+
+/// A directory on the local disk served over NFS.
+///
+/// File handles carry the device and inode number of a file, and a level for every directory on
+/// the way to it, which is the low 16 bits of the directory's inode number. The export remembers
+/// the paths of recent handles, which stay valid across renames through the export, and finds
+/// the others again, e.g. after a restart, by reading only the directories that match the
+/// levels. Handles of files that moved to other directories since, or that lie too deep to name
+/// every directory, are stale then. Symlinks are never followed, not even within the path of a
+/// file, and nothing outside the export's device is served.
+pub struct Export {
+    root: PathBuf,
+    root_fd: OwnedFd,
+    device: u64,
+    root_inode: u64,
+    verifier: Verifier,
+    paths: RwLock<HashMap<u64, PathBuf>>,
+    exclusive_creates: Mutex<HashMap<PathBuf, Verifier>>,
+}
+
+impl Export {
+    pub fn new(root: impl AsRef<Path>) -> io::Result<Self> {
+        let root = fs::canonicalize(root)?;
+        let metadata = fs::metadata(&root)?;
+        if !metadata.is_dir() {
+            return Err(io::ErrorKind::NotADirectory.into());
+        }
+        let boot_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        Ok(Self {
+            root_fd: File::open(&root)?.into(),
+            root,
+            device: metadata.dev(),
+            root_inode: metadata.ino(),
+            verifier: boot_time.to_be_bytes(),
+            paths: RwLock::new(HashMap::new()),
+            exclusive_creates: Mutex::new(HashMap::new()),
+        })
+    }
+}
+
+impl Drive for Export {
+    fn root_file_handle<'a>(&self) -> FileHandle<'a> {
+        let mut bytes = Vec::with_capacity(16);
+        bytes.extend_from_slice(&self.device.to_be_bytes());
+        bytes.extend_from_slice(&self.root_inode.to_be_bytes());
+        FileHandle::from(bytes)
+    }
+
+    fn access(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+        flags: AccessFlags,
+    ) -> Result<AccessResult, Error> {
+        let metadata = self.metadata(file_handle)?;
+        let allowed = credentials.access(
+            metadata.mode(),
+            metadata.uid(),
+            metadata.gid(),
+            metadata.is_dir(),
+        );
+        Ok(AccessResult {
+            supported: flags,
+            access: flags & allowed,
+        })
+    }
+
+    fn lookup<'a>(
+        &self,
+        credentials: &Credentials,
+        directory: &FileHandle<'_>,
+        name: &str,
+    ) -> Result<FileHandle<'a>, Error> {
+        let path = self.child(credentials, directory, name, Permissions::EXECUTE)?;
+        let metadata = self.symlink_metadata(&path)?;
+        if metadata.dev() != self.device {
+            return Err(Error::Access);
+        }
+        let file_handle = self.child_handle(directory, &metadata)?;
+        self.remember(path, &metadata);
+        Ok(file_handle)
+    }
+
+    /// The root of the export has no parent within it, so it is left to whoever mounted the
+    /// export.
+    fn lookup_parent<'a>(
+        &self,
+        credentials: &Credentials,
+        directory: &FileHandle<'_>,
+    ) -> Result<FileHandle<'a>, Error> {
+        let path = self.resolve(directory)?;
+        let metadata = self.symlink_metadata(&path)?;
+        if metadata.is_symlink() {
+            return Err(Error::SYMLINK);
+        }
+        if !metadata.is_dir() {
+            return Err(Error::NOTDIR);
+        }
+        check(credentials, &metadata, Permissions::EXECUTE)?;
+        let parent = match path.parent() {
+            Some(parent) if path != self.root => parent.to_path_buf(),
+            _ => return Err(Error::NOENT),
+        };
+        let metadata = self.symlink_metadata(&parent)?;
+        let (_, _, levels) = parse(directory)?;
+        let file_handle = file_handle(&metadata, &levels[..levels.len().saturating_sub(2)]);
+        self.remember(parent, &metadata);
+        Ok(file_handle)
+    }
+
+    fn get_attributes<'a>(
+        &self,
+        _credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+        mask: AttributeMask<'_>,
+    ) -> Result<Vec<AttributeValue<'a>>, Error> {
+        let metadata = self.metadata(file_handle)?;
+        Ok(self.attributes(&metadata, file_handle, mask))
+    }
+
+    fn read<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+        args: ReadArgs,
+    ) -> Result<ReadResult<'a>, Error> {
+        let file = self.open_file(&self.resolve(file_handle)?, false)?;
+        let metadata = file.metadata().map_err(error)?;
+        if metadata.is_dir() {
+            return Err(Error::ISDIR);
+        }
+        check_transfer(credentials, &metadata, Permissions::READ)?;
+        let mut data = vec![0; args.count.min(MAX_TRANSFER_SIZE) as usize];
+        let mut length = 0;
+        while length < data.len() {
+            match file.read_at(&mut data[length..], args.offset + length as u64) {
+                Ok(0) => break,
+                Ok(count) => length += count,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(error(err)),
+            }
+        }
+        data.truncate(length);
+        Ok(ReadResult {
+            eof: args.offset + length as u64 >= metadata.size(),
+            data: data.into(),
+        })
+    }
+
+    fn read_directory<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+        args: ReadDirectoryArgs<'_>,
+    ) -> Result<ReadDirectoryResult<'a>, Error> {
+        let directory = self.resolve(file_handle)?;
+        let metadata = self.symlink_metadata(&directory)?;
+        if !metadata.is_dir() {
+            return Err(Error::NOTDIR);
+        }
+        check(credentials, &metadata, Permissions::READ)?;
+        let mut names = self.read_dir(&directory)?;
+        names.sort();
+        let mut entries = Vec::new();
+        let mut size = 0;
+        let mut eof = true;
+        for (cookie, (name, _)) in (FIRST_COOKIE..).zip(names) {
+            if cookie <= args.cookie {
+                continue;
+            }
+            let path = directory.join(&name);
+            let Ok(metadata) = self.symlink_metadata(&path) else {
+                continue;
+            };
+            let child = self.child_handle(file_handle, &metadata)?;
+            let attributes = self.attributes(&metadata, &child, args.attributes.clone());
+            size += 32 + name.len() + 16 * attributes.len();
+            if size > args.max_count as usize {
+                eof = false;
+                break;
+            }
+            self.remember(path, &metadata);
+            entries.push(Entry {
+                cookie,
+                name: name.into(),
+                attributes,
+            });
+        }
+        if entries.is_empty() && !eof {
+            return Err(Error::TOOSMALL);
+        }
+        Ok(ReadDirectoryResult {
+            cookie_verf: [0; 8],
+            directory_list: DirectoryList { entries, eof },
+        })
+    }
+
+    fn read_link<'a>(
+        &self,
+        _credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+    ) -> Result<Cow<'a, str>, Error> {
+        let path = self.resolve(file_handle)?;
+        let metadata = self.symlink_metadata(&path)?;
+        if metadata.is_dir() {
+            return Err(Error::ISDIR);
+        }
+        if !metadata.is_symlink() {
+            return Err(Error::INVAL);
+        }
+        let link = self.open_beneath(&path, OFlag::O_PATH)?;
+        let target = readlinkat(Some(link.as_raw_fd()), "")
+            .map_err(io::Error::from)
+            .map_err(error)?;
+        match target.into_string() {
+            Ok(target) => Ok(target.into()),
+            Err(_) => Err(Error::INVAL),
+        }
+    }
+
+    fn open<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+        args: OpenArgs<'_>,
+    ) -> Result<(FileHandle<'a>, OpenResult<'a>), Error> {
+        let (path, change_info, attributes, created) = match args.claim {
+            OpenClaim::Null(name) => {
+                let path = self.child(credentials, file_handle, name, Permissions::EXECUTE)?;
+                let directory = self.resolve(file_handle)?;
+                let directory_metadata = self.symlink_metadata(&directory)?;
+                let before = change(&directory_metadata);
+                let existed = self.symlink_metadata(&path).is_ok();
+                let attributes = match args.how {
+                    OpenFlag::NoCreate => AttributeMask::new(),
+                    OpenFlag::Create(how) => {
+                        if !existed {
+                            check(credentials, &directory_metadata, Permissions::WRITE)?;
+                        }
+                        self.create_file(credentials, &path, how)?
+                    }
+                };
+                let after = change(&self.symlink_metadata(&directory)?);
+                let change_info = ChangeInfo {
+                    atomic: false,
+                    before,
+                    after,
+                };
+                (path, change_info, attributes, !existed)
+            }
+            OpenClaim::FileHandle => {
+                let change_info = ChangeInfo {
+                    atomic: false,
+                    before: 0,
+                    after: 0,
+                };
+                (
+                    self.resolve(file_handle)?,
+                    change_info,
+                    AttributeMask::new(),
+                    false,
+                )
+            }
+            _ => return Err(Error::NOTSUPP),
+        };
+        let metadata = self.symlink_metadata(&path)?;
+        if metadata.is_dir() {
+            return Err(Error::ISDIR);
+        }
+        if metadata.is_symlink() {
+            return Err(Error::SYMLINK);
+        }
+        // Whoever creates a file may open it as asked, whatever mode it was created with.
+        if !created {
+            let mut permissions = Permissions::empty();
+            if args.share_access.contains(ShareAccessFlags::READ) {
+                permissions |= Permissions::READ;
+            }
+            if args.share_access.contains(ShareAccessFlags::WRITE) {
+                permissions |= Permissions::WRITE;
+            }
+            check(credentials, &metadata, permissions)?;
+        }
+        let mut other = [0; 12];
+        other[..8].copy_from_slice(&metadata.ino().to_be_bytes());
+        let file_handle = match args.claim {
+            OpenClaim::FileHandle => file_handle.clone().into_owned(),
+            _ => self.child_handle(file_handle, &metadata)?,
+        };
+        self.remember(path, &metadata);
+        Ok((
+            file_handle,
+            OpenResult {
+                state_id: StateId {
+                    sequence_id: 1,
+                    other,
+                },
+                change_info,
+                flags: OpenResultFlags::empty(),
+                attributes,
+                delegation: OpenDelegation::None,
+            },
+        ))
+    }
+
+    fn create<'a>(
+        &self,
+        credentials: &Credentials,
+        directory: &FileHandle<'_>,
+        args: CreateArgs<'_>,
+    ) -> Result<(FileHandle<'a>, CreateResult<'a>), Error> {
+        let path = self.child(
+            credentials,
+            directory,
+            args.name,
+            Permissions::WRITE | Permissions::EXECUTE,
+        )?;
+        let directory_path = self.resolve(directory)?;
+        let before = change(&self.symlink_metadata(&directory_path)?);
+        let is_symlink = matches!(args.object_type, CreateType::Link(_));
+        let (parent, name) = self.parent(&path)?;
+        let parent = Some(parent.as_raw_fd());
+        match args.object_type {
+            CreateType::Directory => mkdirat(parent, name, Mode::from_bits_truncate(0o777)),
+            CreateType::Link(target) => symlinkat(&*target, parent, name),
+            CreateType::Fifo => mkfifoat(parent, name, Mode::from_bits_truncate(0o644)),
+            _ => return Err(Error::BADTYPE),
+        }
+        .map_err(io::Error::from)
+        .map_err(error)?;
+        give(credentials, &self.open_beneath(&path, OFlag::O_PATH)?)?;
+        // Attributes would be applied to the target of a symlink, so they are ignored.
+        let attributes = match is_symlink {
+            true => AttributeMask::new(),
+            false => {
+                let metadata = self.symlink_metadata(&path)?;
+                check_attributes(credentials, &metadata, &args.attributes, true)?;
+                self.apply_attributes(&path, &args.attributes)?
+            }
+        };
+        let after = change(&self.symlink_metadata(&directory_path)?);
+        let metadata = self.symlink_metadata(&path)?;
+        let file_handle = self.child_handle(directory, &metadata)?;
+        self.remember(path, &metadata);
+        Ok((
+            file_handle,
+            CreateResult {
+                change_info: ChangeInfo {
+                    atomic: false,
+                    before,
+                    after,
+                },
+                attributes,
+            },
+        ))
+    }
+
+    fn link(
+        &self,
+        credentials: &Credentials,
+        source: &FileHandle<'_>,
+        directory: &FileHandle<'_>,
+        name: &str,
+    ) -> Result<ChangeInfo, Error> {
+        let source = self.resolve(source)?;
+        if self.symlink_metadata(&source)?.is_dir() {
+            return Err(Error::ISDIR);
+        }
+        let path = self.child(
+            credentials,
+            directory,
+            name,
+            Permissions::WRITE | Permissions::EXECUTE,
+        )?;
+        let directory = self.resolve(directory)?;
+        let before = change(&self.symlink_metadata(&directory)?);
+        let (source_parent, source_name) = self.parent(&source)?;
+        let (parent, name) = self.parent(&path)?;
+        linkat(
+            Some(source_parent.as_raw_fd()),
+            source_name,
+            Some(parent.as_raw_fd()),
+            name,
+            AtFlags::empty(),
+        )
+        .map_err(io::Error::from)
+        .map_err(error)?;
+        let after = change(&self.symlink_metadata(&directory)?);
+        Ok(ChangeInfo {
+            atomic: false,
+            before,
+            after,
+        })
+    }
+
+    fn remove(
+        &self,
+        credentials: &Credentials,
+        directory: &FileHandle<'_>,
+        name: &str,
+    ) -> Result<ChangeInfo, Error> {
+        let path = self.child(
+            credentials,
+            directory,
+            name,
+            Permissions::WRITE | Permissions::EXECUTE,
+        )?;
+        let directory = self.resolve(directory)?;
+        let metadata = self.symlink_metadata(&path)?;
+        let directory_metadata = self.symlink_metadata(&directory)?;
+        check_sticky(credentials, &directory_metadata, &metadata)?;
+        let before = change(&directory_metadata);
+        let (parent, name) = self.parent(&path)?;
+        let flag = match metadata.is_dir() {
+            true => UnlinkatFlags::RemoveDir,
+            false => UnlinkatFlags::NoRemoveDir,
+        };
+        unlinkat(Some(parent.as_raw_fd()), name, flag)
+            .map_err(io::Error::from)
+            .map_err(error)?;
+        let after = change(&self.symlink_metadata(&directory)?);
+        let mut paths = self.paths.write().unwrap();
+        if paths.get(&metadata.ino()) == Some(&path) {
+            paths.remove(&metadata.ino());
+        }
+        Ok(ChangeInfo {
+            atomic: false,
+            before,
+            after,
+        })
+    }
+
+    fn rename(
+        &self,
+        credentials: &Credentials,
+        source_directory: &FileHandle<'_>,
+        target_directory: &FileHandle<'_>,
+        args: RenameArgs<'_>,
+    ) -> Result<RenameResult, Error> {
+        let permissions = Permissions::WRITE | Permissions::EXECUTE;
+        let source = self.child(credentials, source_directory, args.old_name, permissions)?;
+        let target = self.child(credentials, target_directory, args.new_name, permissions)?;
+        let source_directory = self.resolve(source_directory)?;
+        let target_directory = self.resolve(target_directory)?;
+        let metadata = self.symlink_metadata(&source)?;
+        let source_directory_metadata = self.symlink_metadata(&source_directory)?;
+        check_sticky(credentials, &source_directory_metadata, &metadata)?;
+        let source_before = change(&source_directory_metadata);
+        let target_before = change(&self.symlink_metadata(&target_directory)?);
+        let (source_parent, source_name) = self.parent(&source)?;
+        let (target_parent, target_name) = self.parent(&target)?;
+        renameat(
+            Some(source_parent.as_raw_fd()),
+            source_name,
+            Some(target_parent.as_raw_fd()),
+            target_name,
+        )
+        .map_err(io::Error::from)
+        .map_err(error)?;
+        let source_after = change(&self.symlink_metadata(&source_directory)?);
+        let target_after = change(&self.symlink_metadata(&target_directory)?);
+        // Whatever was known to be below a renamed directory moved along with it.
+        let mut paths = self.paths.write().unwrap();
+        for path in paths.values_mut() {
+            if let Ok(suffix) = path.strip_prefix(&source) {
+                *path = target.join(suffix);
+            }
+        }
+        drop(paths);
+        self.remember(target, &metadata);
+        Ok(RenameResult {
+            source_change_info: ChangeInfo {
+                atomic: false,
+                before: source_before,
+                after: source_after,
+            },
+            target_change_info: ChangeInfo {
+                atomic: false,
+                before: target_before,
+                after: target_after,
+            },
+        })
+    }
+
+    fn set_attributes<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+        args: SetAttributesArgs<'_>,
+    ) -> Result<AttributeMask<'a>, Error> {
+        let path = self.resolve(file_handle)?;
+        let metadata = self.symlink_metadata(&path)?;
+        if metadata.is_symlink() {
+            return Err(Error::SYMLINK);
+        }
+        check_attributes(credentials, &metadata, &args.attributes, false)?;
+        self.apply_attributes(&path, &args.attributes)
+    }
+
+    fn write(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+        args: WriteArgs<'_>,
+    ) -> Result<WriteResult, Error> {
+        let file = self.open_file(&self.resolve(file_handle)?, true)?;
+        check_transfer(
+            credentials,
+            &file.metadata().map_err(error)?,
+            Permissions::WRITE,
+        )?;
+        file.write_all_at(&args.data, args.offset).map_err(error)?;
+        match args.stable {
+            StableHow::Unstable => Ok(()),
+            StableHow::DataSync => file.sync_data(),
+            StableHow::FileSync => file.sync_all(),
+        }
+        .map_err(error)?;
+        Ok(WriteResult {
+            count: args.data.len() as u32,
+            committed: args.stable,
+            verifier: self.verifier,
+        })
+    }
+
+    fn commit(
+        &self,
+        _credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+        _args: CommitArgs,
+    ) -> Result<Verifier, Error> {
+        let file = self.open_file(&self.resolve(file_handle)?, false)?;
+        file.sync_all().map_err(error)?;
+        Ok(self.verifier)
+    }
+}
+
+impl Export {
+    fn metadata(
+        &self,
+        file_handle: &FileHandle<'_>,
+    ) -> Result<Metadata, Error> {
+        self.symlink_metadata(&self.resolve(file_handle)?)
+    }
+
+    fn resolve(
+        &self,
+        file_handle: &FileHandle<'_>,
+    ) -> Result<PathBuf, Error> {
+        let (device, inode, levels) = parse(file_handle)?;
+        if device != self.device {
+            return Err(Error::STALE);
+        }
+        if inode == self.root_inode {
+            return Ok(self.root.clone());
+        }
+        if let Some(path) = self.paths.read().unwrap().get(&inode) {
+            if self
+                .symlink_metadata(path)
+                .is_ok_and(|metadata| metadata.ino() == inode)
+            {
+                return Ok(path.clone());
+            }
+        }
+        // Searching the whole export for the inode would let any client make the server walk
+        // all of it, so only the directories on the way to the file are read.
+        let mut reads = MAX_FIND_READS;
+        let path = self
+            .find(&self.root, levels, inode, &mut reads)
+            .ok_or(Error::STALE)?;
+        let metadata = self.symlink_metadata(&path)?;
+        if metadata.ino() != inode || metadata.dev() != self.device {
+            return Err(Error::STALE);
+        }
+        self.remember(path.clone(), &metadata);
+        Ok(path)
+    }
+
+    /// Looks for the file with the inode below the directory, in the directories whose inode
+    /// matches each level in turn.
+    fn find(
+        &self,
+        directory: &Path,
+        levels: &[u8],
+        inode: u64,
+        reads: &mut usize,
+    ) -> Option<PathBuf> {
+        *reads = reads.checked_sub(1)?;
+        let entries = self.read_dir(directory).ok()?;
+        match levels.split_first_chunk::<2>() {
+            None => entries
+                .into_iter()
+                .find(|(_, entry)| *entry == inode)
+                .map(|(name, _)| directory.join(name)),
+            Some((first, levels)) => entries
+                .into_iter()
+                .filter(|(_, entry)| level(*entry) == *first)
+                .find_map(|(name, _)| self.find(&directory.join(name), levels, inode, reads)),
+        }
+    }
+
+    /// Opens a file of the export without following symlinks anywhere along its path, so that
+    /// a symlink swapped in for one of its directories cannot lead outside of the export.
+    fn open_beneath(
+        &self,
+        path: &Path,
+        flags: OFlag,
+    ) -> Result<OwnedFd, Error> {
+        let path = match path.strip_prefix(&self.root) {
+            Ok(path) if path.as_os_str().is_empty() => Path::new("."),
+            Ok(path) => path,
+            Err(_) => return Err(Error::STALE),
+        };
+        let mut how = OpenHow::new()
+            .flags(flags | OFlag::O_NOFOLLOW | OFlag::O_CLOEXEC)
+            .resolve(ResolveFlag::RESOLVE_BENEATH | ResolveFlag::RESOLVE_NO_SYMLINKS);
+        if flags.contains(OFlag::O_CREAT) {
+            how = how.mode(Mode::from_bits_truncate(0o644));
+        }
+        let fd = openat2(self.root_fd.as_raw_fd(), path, how)
+            .map_err(io::Error::from)
+            .map_err(error)?;
+        // SAFETY: openat2 returned a new descriptor that nothing else owns.
+        Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+
+    fn open_file(
+        &self,
+        path: &Path,
+        write: bool,
+    ) -> Result<File, Error> {
+        let flags = match write {
+            true => OFlag::O_RDWR,
+            false => OFlag::O_RDONLY,
+        };
+        Ok(File::from(self.open_beneath(path, flags)?))
+    }
+
+    /// The metadata of a file, or of a symlink itself.
+    fn symlink_metadata(
+        &self,
+        path: &Path,
+    ) -> Result<Metadata, Error> {
+        File::from(self.open_beneath(path, OFlag::O_PATH)?)
+            .metadata()
+            .map_err(error)
+    }
+
+    /// Opens the directory of a file, for calls that take a directory and a name.
+    fn parent<'p>(
+        &self,
+        path: &'p Path,
+    ) -> Result<(OwnedFd, &'p OsStr), Error> {
+        let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+            return Err(Error::INVAL);
+        };
+        Ok((
+            self.open_beneath(parent, OFlag::O_PATH | OFlag::O_DIRECTORY)?,
+            name,
+        ))
+    }
+
+    /// The names in a directory with their inode numbers.
+    fn read_dir(
+        &self,
+        directory: &Path,
+    ) -> Result<Vec<(String, u64)>, Error> {
+        let fd = self.open_beneath(directory, OFlag::O_RDONLY | OFlag::O_DIRECTORY)?;
+        let mut directory = Dir::from_fd(fd.into_raw_fd())
+            .map_err(io::Error::from)
+            .map_err(error)?;
+        Ok(directory
+            .iter()
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let name = entry.file_name().to_str().ok()?;
+                (name != "." && name != "..").then(|| (name.to_owned(), entry.ino()))
+            })
+            .collect())
+    }
+
+    /// The path of an entry in the directory, once the caller is found to have the permissions
+    /// on the directory.
+    fn child(
+        &self,
+        credentials: &Credentials,
+        directory: &FileHandle<'_>,
+        name: &str,
+        permissions: Permissions,
+    ) -> Result<PathBuf, Error> {
+        if name.is_empty() {
+            return Err(Error::INVAL);
+        }
+        if name.len() > MAX_NAME_LENGTH {
+            return Err(Error::NAMETOOLONG);
+        }
+        if name == "." || name == ".." || name.contains(['/', '\0']) {
+            return Err(Error::BADNAME);
+        }
+        let directory = self.resolve(directory)?;
+        let metadata = self.symlink_metadata(&directory)?;
+        if metadata.is_symlink() {
+            return Err(Error::SYMLINK);
+        }
+        if !metadata.is_dir() {
+            return Err(Error::NOTDIR);
+        }
+        check(credentials, &metadata, permissions)?;
+        Ok(directory.join(name))
+    }
+
+    /// Remembers the path of a file, forgetting another one once there are too many, which
+    /// is then found again when it is asked for.
+    fn remember(
+        &self,
+        path: PathBuf,
+        metadata: &Metadata,
+    ) {
+        if metadata.ino() == self.root_inode {
+            return;
+        }
+        let mut paths = self.paths.write().unwrap();
+        if paths.len() >= MAX_REMEMBERED_PATHS && !paths.contains_key(&metadata.ino()) {
+            if let Some(inode) = paths.keys().next().copied() {
+                paths.remove(&inode);
+            }
+        }
+        paths.insert(metadata.ino(), path);
+    }
+
+    /// The handle of an entry of the directory, with the level of the directory added unless
+    /// it is the root or the handle has no room left.
+    fn child_handle<'a>(
+        &self,
+        directory: &FileHandle<'_>,
+        metadata: &Metadata,
+    ) -> Result<FileHandle<'a>, Error> {
+        let (_, directory_inode, levels) = parse(directory)?;
+        let mut levels = levels.to_vec();
+        if directory_inode != self.root_inode && levels.len() < 2 * MAX_HANDLE_LEVELS {
+            levels.extend_from_slice(&level(directory_inode));
+        }
+        Ok(file_handle(metadata, &levels))
+    }
+
+    fn create_file<'a>(
+        &self,
+        credentials: &Credentials,
+        path: &Path,
+        how: OpenFlagCreate<'_>,
+    ) -> Result<AttributeMask<'a>, Error> {
+        let (attributes, create_new) = match how {
+            OpenFlagCreate::Unchecked { ref attributes } => (attributes.as_slice(), false),
+            OpenFlagCreate::Guarded { ref attributes } => (attributes.as_slice(), true),
+            OpenFlagCreate::Exclusive4_1 {
+                verifier,
+                ref attributes,
+            } => {
+                let mut exclusive_creates = self.exclusive_creates.lock().unwrap();
+                // A retransmitted exclusive create succeeds if it carries the same verifier.
+                if self.symlink_metadata(path).is_ok() {
+                    return match exclusive_creates.get(path) == Some(&verifier) {
+                        true => Ok(AttributeMask::new()),
+                        false => Err(Error::EXIST),
+                    };
+                }
+                exclusive_creates.insert(path.to_owned(), verifier);
+                (attributes.as_slice(), true)
+            }
+        };
+        let mut flags = OFlag::O_WRONLY | OFlag::O_CREAT;
+        if create_new {
+            flags |= OFlag::O_EXCL;
+        }
+        give(credentials, &self.open_beneath(path, flags)?)?;
+        let metadata = self.symlink_metadata(path)?;
+        check_attributes(credentials, &metadata, attributes, true)?;
+        self.apply_attributes(path, attributes)
+    }
+
+    fn apply_attributes<'a>(
+        &self,
+        path: &Path,
+        attributes: &[AttributeValue<'_>],
+    ) -> Result<AttributeMask<'a>, Error> {
+        let mut owner = None;
+        let mut group = None;
+        let mut access_time = TimeSpec::UTIME_OMIT;
+        let mut modify_time = TimeSpec::UTIME_OMIT;
+        for attribute in attributes {
+            match attribute {
+                AttributeValue::Owner(value) => owner = Some(parse_id(value)?),
+                AttributeValue::OwnerGroup(value) => group = Some(parse_id(value)?),
+                AttributeValue::TimeAccessSet(value) => access_time = time_spec(*value),
+                AttributeValue::TimeModifySet(value) => modify_time = time_spec(*value),
+                AttributeValue::Size(_)
+                | AttributeValue::Mode(_)
+                | AttributeValue::ModeSetMasked(_) => {}
+                _ => return Err(Error::ATTRNOTSUPP),
+            }
+        }
+        // Calls that only take paths get the file through its descriptor, which cannot be
+        // swapped for a symlink.
+        let file = self.open_beneath(path, OFlag::O_PATH)?;
+        let file_path = descriptor_path(&file);
+        let stat = fstat(file.as_raw_fd())
+            .map_err(io::Error::from)
+            .map_err(error)?;
+        if SFlag::from_bits_truncate(stat.st_mode) & SFlag::S_IFMT == SFlag::S_IFLNK {
+            return Err(Error::SYMLINK);
+        }
+        let mut applied = Vec::new();
+        for attribute in attributes {
+            match attribute {
+                AttributeValue::Size(size) => {
+                    self.open_file(path, true)?.set_len(*size).map_err(error)?;
+                    applied.push(Attribute::Size);
+                }
+                AttributeValue::Mode(mode) => {
+                    fs::set_permissions(&file_path, fs::Permissions::from_mode(mode & 0o7777))
+                        .map_err(error)?;
+                    applied.push(Attribute::Mode);
+                }
+                AttributeValue::ModeSetMasked(value) => {
+                    let mode = fs::metadata(&file_path).map_err(error)?.mode();
+                    let mode = (mode & !value.mask) | (value.mode & value.mask);
+                    fs::set_permissions(&file_path, fs::Permissions::from_mode(mode & 0o7777))
+                        .map_err(error)?;
+                    applied.push(Attribute::ModeSetMasked);
+                }
+                _ => {}
+            }
+        }
+        if owner.is_some() || group.is_some() {
+            fchownat(
+                Some(file.as_raw_fd()),
+                "",
+                owner.map(Uid::from_raw),
+                group.map(Gid::from_raw),
+                AtFlags::AT_EMPTY_PATH,
+            )
+            .map_err(io::Error::from)
+            .map_err(error)?;
+            applied.extend(owner.map(|_| Attribute::Owner));
+            applied.extend(group.map(|_| Attribute::OwnerGroup));
+        }
+        if access_time != TimeSpec::UTIME_OMIT || modify_time != TimeSpec::UTIME_OMIT {
+            utimensat(
+                None,
+                &file_path,
+                &access_time,
+                &modify_time,
+                UtimensatFlags::FollowSymlink,
+            )
+            .map_err(io::Error::from)
+            .map_err(error)?;
+            if access_time != TimeSpec::UTIME_OMIT {
+                applied.push(Attribute::TimeAccessSet);
+            }
+            if modify_time != TimeSpec::UTIME_OMIT {
+                applied.push(Attribute::TimeModifySet);
+            }
+        }
+        Ok(applied.into_iter().collect())
+    }
+
+    fn attributes<'a>(
+        &self,
+        metadata: &Metadata,
+        file_handle: &FileHandle<'_>,
+        mask: AttributeMask<'_>,
+    ) -> Vec<AttributeValue<'a>> {
+        // The statistics of the file system are only gathered when asked for.
+        let statistics = mask
+            .clone()
+            .into_iter()
+            .any(|attribute| {
+                matches!(
+                    attribute,
+                    Attribute::FilesAvailable
+                        | Attribute::FilesFree
+                        | Attribute::FilesTotal
+                        | Attribute::SpaceAvailable
+                        | Attribute::SpaceFree
+                        | Attribute::SpaceTotal
+                )
+            })
+            .then(|| fstatvfs(&self.root_fd).ok())
+            .flatten();
+        let statistics = statistics.as_ref();
+        mask.into_iter()
+            .filter_map(|attribute| {
+                Some(match attribute {
+                    Attribute::SupportedAttributes => {
+                        AttributeValue::SupportedAttributes(SUPPORTED_ATTRIBUTES.into())
+                    }
+                    Attribute::Type => AttributeValue::Type(file_type(metadata)),
+                    Attribute::FileHandleExpireType => AttributeValue::FileHandleExpireType(0),
+                    Attribute::Change => AttributeValue::Change(change(metadata)),
+                    Attribute::Size => AttributeValue::Size(metadata.size()),
+                    Attribute::LinkSupport => AttributeValue::LinkSupport(true),
+                    Attribute::SymlinkSupport => AttributeValue::SymlinkSupport(true),
+                    Attribute::NamedAttributes => AttributeValue::NamedAttributes(false),
+                    Attribute::FileSystemId => AttributeValue::FileSystemId(FileSystemId {
+                        major: metadata.dev(),
+                        minor: 0,
+                    }),
+                    Attribute::UniqueHandles => AttributeValue::UniqueHandles(false),
+                    Attribute::LeaseTime => AttributeValue::LeaseTime(90),
+                    Attribute::AclSupport => AttributeValue::AclSupport(AclSupportFlags::empty()),
+                    Attribute::CanSetTime => AttributeValue::CanSetTime(true),
+                    Attribute::CaseInsensitive => AttributeValue::CaseInsensitive(false),
+                    Attribute::CasePreserving => AttributeValue::CasePreserving(true),
+                    Attribute::ChownRestricted => AttributeValue::ChownRestricted(true),
+                    Attribute::FileHandle => {
+                        AttributeValue::FileHandle(file_handle.clone().into_owned())
+                    }
+                    Attribute::FileId => AttributeValue::FileId(metadata.ino()),
+                    Attribute::FilesAvailable => {
+                        AttributeValue::FilesAvailable(statistics?.files_available())
+                    }
+                    Attribute::FilesFree => AttributeValue::FilesFree(statistics?.files_free()),
+                    Attribute::FilesTotal => AttributeValue::FilesTotal(statistics?.files()),
+                    Attribute::Homogeneous => AttributeValue::Homogeneous(true),
+                    Attribute::MaxFileSize => AttributeValue::MaxFileSize(i64::MAX as u64),
+                    Attribute::MaxName => AttributeValue::MaxName(MAX_NAME_LENGTH as u32),
+                    Attribute::MaxRead => AttributeValue::MaxRead(MAX_TRANSFER_SIZE as u64),
+                    Attribute::MaxWrite => AttributeValue::MaxWrite(MAX_TRANSFER_SIZE as u64),
+                    Attribute::Mode => AttributeValue::Mode(metadata.mode() & 0o7777),
+                    Attribute::NoTruncate => AttributeValue::NoTruncate(true),
+                    Attribute::NumberOfLinks => {
+                        AttributeValue::NumberOfLinks(metadata.nlink() as u32)
+                    }
+                    Attribute::Owner => AttributeValue::Owner(metadata.uid().to_string().into()),
+                    Attribute::OwnerGroup => {
+                        AttributeValue::OwnerGroup(metadata.gid().to_string().into())
+                    }
+                    Attribute::RawDevice => AttributeValue::RawDevice(SpecData {
+                        major: nix::sys::stat::major(metadata.rdev()) as u32,
+                        minor: nix::sys::stat::minor(metadata.rdev()) as u32,
+                    }),
+                    Attribute::SpaceAvailable => AttributeValue::SpaceAvailable(
+                        statistics?.blocks_available() * statistics?.fragment_size(),
+                    ),
+                    Attribute::SpaceFree => AttributeValue::SpaceFree(
+                        statistics?.blocks_free() * statistics?.fragment_size(),
+                    ),
+                    Attribute::SpaceTotal => AttributeValue::SpaceTotal(
+                        statistics?.blocks() * statistics?.fragment_size(),
+                    ),
+                    // Blocks are counted in units of 512 bytes whatever the block size is.
+                    Attribute::SpaceUsed => AttributeValue::SpaceUsed(metadata.blocks() * 512),
+                    Attribute::TimeAccess => {
+                        AttributeValue::TimeAccess(time(metadata.atime(), metadata.atime_nsec()))
+                    }
+                    Attribute::TimeDelta => AttributeValue::TimeDelta(Time {
+                        seconds: 0,
+                        nanoseconds: 1,
+                    }),
+                    Attribute::TimeMetadata => {
+                        AttributeValue::TimeMetadata(time(metadata.ctime(), metadata.ctime_nsec()))
+                    }
+                    Attribute::TimeModify => {
+                        AttributeValue::TimeModify(time(metadata.mtime(), metadata.mtime_nsec()))
+                    }
+                    Attribute::MountedOnFileId => AttributeValue::MountedOnFileId(metadata.ino()),
+                    Attribute::SupportedAttributesExclusiveCreate => {
+                        AttributeValue::SupportedAttributesExclusiveCreate(
+                            SETTABLE_ATTRIBUTES.into(),
+                        )
+                    }
+                    _ => return None,
+                })
+            })
+            .collect()
+    }
+}
+
+/// The path under `/proc` that resolves to the file of a descriptor.
+fn descriptor_path(fd: &OwnedFd) -> PathBuf {
+    PathBuf::from(format!("/proc/self/fd/{}", fd.as_raw_fd()))
+}
+
+fn file_handle<'a>(
+    metadata: &Metadata,
+    levels: &[u8],
+) -> FileHandle<'a> {
+    let mut bytes = Vec::with_capacity(16 + levels.len());
+    bytes.extend_from_slice(&metadata.dev().to_be_bytes());
+    bytes.extend_from_slice(&metadata.ino().to_be_bytes());
+    bytes.extend_from_slice(levels);
+    FileHandle::from(bytes)
+}
+
+/// The device, the inode and the levels of the directories of a handle.
+fn parse<'h>(file_handle: &'h FileHandle<'_>) -> Result<(u64, u64, &'h [u8]), Error> {
+    let bytes: &[u8] = file_handle;
+    let Some((device, bytes)) = bytes.split_first_chunk::<8>() else {
+        return Err(Error::BADHANDLE);
+    };
+    let Some((inode, levels)) = bytes.split_first_chunk::<8>() else {
+        return Err(Error::BADHANDLE);
+    };
+    if levels.len() % 2 != 0 || levels.len() > 2 * MAX_HANDLE_LEVELS {
+        return Err(Error::BADHANDLE);
+    }
+    Ok((
+        u64::from_be_bytes(*device),
+        u64::from_be_bytes(*inode),
+        levels,
+    ))
+}
+
+/// The level of a directory in the handles of the files below it.
+fn level(inode: u64) -> [u8; 2] {
+    (inode as u16).to_be_bytes()
+}
+
+/// Hands a new file over to the caller, which only works while the server runs as root;
+/// otherwise the file keeps the owner of the server.
+fn give(
+    credentials: &Credentials,
+    file: &OwnedFd,
+) -> Result<(), Error> {
+    match fchownat(
+        Some(file.as_raw_fd()),
+        "",
+        Some(Uid::from_raw(credentials.uid)),
+        Some(Gid::from_raw(credentials.gid)),
+        AtFlags::AT_EMPTY_PATH,
+    ) {
+        Err(Errno::EPERM) => Ok(()),
+        result => result.map_err(io::Error::from).map_err(error),
+    }
+}
+
+fn time(
+    seconds: i64,
+    nanoseconds: i64,
+) -> Time {
+    Time {
+        seconds,
+        nanoseconds: nanoseconds as u32,
+    }
+}
+
+fn time_spec(value: SetTime) -> TimeSpec {
+    match value {
+        SetTime::ServerTime => TimeSpec::UTIME_NOW,
+        SetTime::ClientTime(time) => TimeSpec::new(time.seconds, time.nanoseconds as i64),
+    }
+}
+
+fn change(metadata: &Metadata) -> u64 {
+    metadata.ctime() as u64 * 1_000_000_000 + metadata.ctime_nsec() as u64
+}
+
+fn file_type(metadata: &Metadata) -> FileType {
+    let file_type = metadata.file_type();
+    if file_type.is_dir() {
+        FileType::Directory
+    } else if file_type.is_symlink() {
+        FileType::Symlink
+    } else if file_type.is_block_device() {
+        FileType::BlockDevice
+    } else if file_type.is_char_device() {
+        FileType::CharacterDevice
+    } else if file_type.is_socket() {
+        FileType::Socket
+    } else if file_type.is_fifo() {
+        FileType::Fifo
+    } else {
+        FileType::Regular
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("lararium-{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn superuser() -> Credentials {
+        Credentials {
+            uid: 0,
+            gid: 0,
+            gids: vec![],
+        }
+    }
+
+    fn read_args(count: u32) -> ReadArgs {
+        ReadArgs {
+            state_id: StateId {
+                sequence_id: 0,
+                other: [0; 12],
+            },
+            offset: 0,
+            count,
+        }
+    }
+
+    #[test]
+    fn test_lookup_and_read() {
+        let directory = TempDir::new("nfs-read");
+        fs::write(directory.0.join("hello.txt"), b"hello world").unwrap();
+        let export = Export::new(&directory.0).unwrap();
+        let file_handle = export
+            .lookup(&superuser(), &export.root_file_handle(), "hello.txt")
+            .unwrap();
+        let result = export
+            .read(&superuser(), &file_handle, read_args(1024))
+            .unwrap();
+        assert_eq!(&*result.data, b"hello world");
+        assert!(result.eof);
+        let attributes = export
+            .get_attributes(
+                &superuser(),
+                &file_handle,
+                [Attribute::Type, Attribute::Size].into_iter().collect(),
+            )
+            .unwrap();
+        assert_eq!(
+            attributes,
+            vec![
+                AttributeValue::Type(FileType::Regular),
+                AttributeValue::Size(11)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lookup_parent_and_read_link() {
+        let directory = TempDir::new("nfs-parent");
+        fs::create_dir(directory.0.join("docs")).unwrap();
+        std::os::unix::fs::symlink("../notes.txt", directory.0.join("docs/notes")).unwrap();
+        let export = Export::new(&directory.0).unwrap();
+        let docs = export
+            .lookup(&superuser(), &export.root_file_handle(), "docs")
+            .unwrap();
+        let notes = export.lookup(&superuser(), &docs, "notes").unwrap();
+        assert_eq!(
+            export.read_link(&superuser(), &notes),
+            Ok("../notes.txt".into())
+        );
+        assert_eq!(export.read_link(&superuser(), &docs), Err(Error::ISDIR));
+        assert_eq!(
+            export.lookup_parent(&superuser(), &notes),
+            Err(Error::SYMLINK)
+        );
+        assert_eq!(
+            export.lookup_parent(&superuser(), &docs),
+            Ok(export.root_file_handle())
+        );
+        assert_eq!(
+            export.lookup_parent(&superuser(), &export.root_file_handle()),
+            Err(Error::NOENT)
+        );
+    }
+
+    #[test]
+    fn test_handles_follow_renames() {
+        let directory = TempDir::new("nfs-rename");
+        fs::create_dir(directory.0.join("a")).unwrap();
+        fs::write(directory.0.join("a/file"), b"data").unwrap();
+        let export = Export::new(&directory.0).unwrap();
+        let root = export.root_file_handle();
+        let a = export.lookup(&superuser(), &root, "a").unwrap();
+        let file_handle = export.lookup(&superuser(), &a, "file").unwrap();
+        export
+            .rename(&superuser(), &root, &root, RenameArgs::from(("a", "b")))
+            .unwrap();
+        let result = export
+            .read(&superuser(), &file_handle, read_args(1024))
+            .unwrap();
+        assert_eq!(&*result.data, b"data");
+
+        // A restarted export finds the file again through the directory it is in.
+        let export = Export::new(&directory.0).unwrap();
+        let result = export
+            .read(&superuser(), &file_handle, read_args(1024))
+            .unwrap();
+        assert_eq!(&*result.data, b"data");
+
+        // Unless it moved to another directory before the export saw where.
+        let export = Export::new(&directory.0).unwrap();
+        fs::rename(directory.0.join("b/file"), directory.0.join("file")).unwrap();
+        assert_eq!(
+            export.read(&superuser(), &file_handle, read_args(1024)),
+            Err(Error::STALE)
+        );
+    }
+
+    #[test]
+    fn test_refuses_swapped_symlinks() {
+        let directory = TempDir::new("nfs-swap");
+        fs::create_dir(directory.0.join("export")).unwrap();
+        fs::create_dir(directory.0.join("export/a")).unwrap();
+        fs::write(directory.0.join("export/a/file"), b"data").unwrap();
+        fs::create_dir(directory.0.join("outside")).unwrap();
+        fs::write(directory.0.join("outside/file"), b"secret").unwrap();
+        let export = Export::new(directory.0.join("export")).unwrap();
+        let a = export
+            .lookup(&superuser(), &export.root_file_handle(), "a")
+            .unwrap();
+        // Another client of the directory swaps it for a symlink leading out of the export.
+        fs::rename(directory.0.join("export/a"), directory.0.join("a")).unwrap();
+        std::os::unix::fs::symlink("../outside", directory.0.join("export/a")).unwrap();
+        assert_eq!(export.lookup(&superuser(), &a, "file"), Err(Error::STALE));
+        assert!(export
+            .open_beneath(&directory.0.join("export/a/file"), OFlag::O_RDONLY)
+            .is_err());
+    }
+
+    #[test]
+    fn test_refuses_escapes() {
+        let directory = TempDir::new("nfs-escape");
+        fs::create_dir(directory.0.join("export")).unwrap();
+        fs::write(directory.0.join("secret"), b"secret").unwrap();
+        std::os::unix::fs::symlink("../secret", directory.0.join("export/link")).unwrap();
+        let export = Export::new(directory.0.join("export")).unwrap();
+        assert_eq!(
+            export.lookup(&superuser(), &export.root_file_handle(), ".."),
+            Err(Error::BADNAME)
+        );
+        assert_eq!(
+            export.lookup(&superuser(), &export.root_file_handle(), "../secret"),
+            Err(Error::BADNAME)
+        );
+        let link = export
+            .lookup(&superuser(), &export.root_file_handle(), "link")
+            .unwrap();
+        assert_eq!(
+            export.read(&superuser(), &link, read_args(1024)),
+            Err(Error::SYMLINK)
+        );
+        assert_eq!(
+            export.lookup(&superuser(), &link, "secret"),
+            Err(Error::SYMLINK)
+        );
+        assert_eq!(
+            export.lookup(&superuser(), &FileHandle::from(&[1, 2, 3]), "link"),
+            Err(Error::BADHANDLE)
+        );
+    }
+
+    #[test]
+    fn test_write_and_commit() {
+        let directory = TempDir::new("nfs-write");
+        let export = Export::new(&directory.0).unwrap();
+        let (file_handle, _) = export
+            .create(
+                &superuser(),
+                &export.root_file_handle(),
+                CreateArgs {
+                    object_type: CreateType::Directory,
+                    name: "docs",
+                    attributes: vec![AttributeValue::Mode(0o700)],
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            export
+                .lookup(&superuser(), &export.root_file_handle(), "docs")
+                .unwrap(),
+            file_handle
+        );
+        fs::write(directory.0.join("docs/notes"), b"").unwrap();
+        let notes = export.lookup(&superuser(), &file_handle, "notes").unwrap();
+        let result = export
+            .write(
+                &superuser(),
+                &notes,
+                WriteArgs {
+                    state_id: StateId {
+                        sequence_id: 0,
+                        other: [0; 12],
+                    },
+                    offset: 6,
+                    stable: StableHow::Unstable,
+                    data: b"world".into(),
+                },
+            )
+            .unwrap();
+        assert_eq!(result.count, 5);
+        assert_eq!(
+            export.commit(
+                &superuser(),
+                &notes,
+                CommitArgs {
+                    offset: 0,
+                    count: 0
+                }
+            ),
+            Ok(result.verifier)
+        );
+        assert_eq!(
+            fs::read(directory.0.join("docs/notes")).unwrap(),
+            b"\0\0\0\0\0\0world"
+        );
+        export.remove(&superuser(), &file_handle, "notes").unwrap();
+        assert_eq!(
+            export.lookup(&superuser(), &file_handle, "notes"),
+            Err(Error::NOENT)
+        );
+    }
+
+    #[test]
+    fn test_permission_checks() {
+        let directory = TempDir::new("nfs-permissions");
+        fs::write(directory.0.join("private"), b"private").unwrap();
+        fs::set_permissions(
+            directory.0.join("private"),
+            fs::Permissions::from_mode(0o600),
+        )
+        .unwrap();
+        let export = Export::new(&directory.0).unwrap();
+        let stranger = Credentials {
+            uid: 4242,
+            gid: 4242,
+            gids: vec![],
+        };
+        let file_handle = export
+            .lookup(&stranger, &export.root_file_handle(), "private")
+            .unwrap();
+        assert_eq!(
+            export
+                .access(&stranger, &file_handle, AccessFlags::READ)
+                .map(|result| result.access),
+            Ok(AccessFlags::empty())
+        );
+        assert_eq!(
+            export.read(&stranger, &file_handle, read_args(1024)),
+            Err(Error::Access)
+        );
+        assert_eq!(
+            export.remove(&stranger, &export.root_file_handle(), "private"),
+            Err(Error::Access)
+        );
+        fs::set_permissions(
+            directory.0.join("private"),
+            fs::Permissions::from_mode(0o644),
+        )
+        .unwrap();
+        assert_eq!(
+            &*export
+                .read(&stranger, &file_handle, read_args(1024))
+                .unwrap()
+                .data,
+            b"private"
+        );
+    }
+
+    #[test]
+    fn test_set_times_and_masked_mode() {
+        let directory = TempDir::new("nfs-set-attributes");
+        fs::write(directory.0.join("file"), b"file").unwrap();
+        fs::set_permissions(directory.0.join("file"), fs::Permissions::from_mode(0o644)).unwrap();
+        let export = Export::new(&directory.0).unwrap();
+        let file_handle = export
+            .lookup(&superuser(), &export.root_file_handle(), "file")
+            .unwrap();
+        let modified = Time {
+            seconds: 1736972386,
+            nanoseconds: 339944639,
+        };
+        let args = |attributes| SetAttributesArgs {
+            state_id: StateId {
+                sequence_id: 0,
+                other: [0; 12],
+            },
+            attributes,
+        };
+        let stranger = Credentials {
+            uid: 4242,
+            gid: 4242,
+            gids: vec![],
+        };
+        assert_eq!(
+            export.set_attributes(
+                &stranger,
+                &file_handle,
+                args(vec![AttributeValue::TimeModifySet(SetTime::ClientTime(
+                    modified
+                ))])
+            ),
+            Err(Error::PERM)
+        );
+        assert_eq!(
+            export.set_attributes(
+                &stranger,
+                &file_handle,
+                args(vec![AttributeValue::Owner("nobody".into())])
+            ),
+            Err(Error::BADOWNER)
+        );
+        export
+            .set_attributes(
+                &superuser(),
+                &file_handle,
+                args(vec![
+                    AttributeValue::TimeModifySet(SetTime::ClientTime(modified)),
+                    AttributeValue::ModeSetMasked(ModeMasked {
+                        mode: 0o700,
+                        mask: 0o070,
+                    }),
+                ]),
+            )
+            .unwrap();
+        let attributes = export
+            .get_attributes(
+                &superuser(),
+                &file_handle,
+                [Attribute::Mode, Attribute::TimeModify]
+                    .into_iter()
+                    .collect(),
+            )
+            .unwrap();
+        assert_eq!(
+            attributes,
+            vec![
+                AttributeValue::Mode(0o604),
+                AttributeValue::TimeModify(modified),
+            ]
+        );
+    }
+}
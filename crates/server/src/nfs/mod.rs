@@ -0,0 +1,750 @@
+mod access;
+mod export;
+mod store;
+mod tree;
+
+pub use export::Export;
+pub use store::Store;
+pub use tree::EntryTree;
+
+use nfs::*;
+use nix::errno::Errno;
+use std::borrow::Cow;
+use std::io;
+use std::sync::Arc;
+
+/// The directory of the drive that stations keep their shared volumes in.
+pub const VOLUMES_DIRECTORY: &str = "volumes";
+/// Cookies 0 to 2 are reserved, so directory entries are numbered from 3 in name order.
+const FIRST_COOKIE: u64 = 3;
+
+/// A file system mounted on the entry tree as the drive, whose calls may block.
+pub trait Drive: Send + Sync {
+    /// The handle of the root directory, for mounting the drive below another file system.
+    fn root_file_handle<'a>(&self) -> FileHandle<'a>;
+
+    fn access(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+        flags: AccessFlags,
+    ) -> Result<AccessResult, Error>;
+
+    fn lookup<'a>(
+        &self,
+        credentials: &Credentials,
+        directory: &FileHandle<'_>,
+        name: &str,
+    ) -> Result<FileHandle<'a>, Error>;
+
+    fn lookup_parent<'a>(
+        &self,
+        credentials: &Credentials,
+        directory: &FileHandle<'_>,
+    ) -> Result<FileHandle<'a>, Error>;
+
+    fn get_attributes<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+        mask: AttributeMask<'_>,
+    ) -> Result<Vec<AttributeValue<'a>>, Error>;
+
+    fn read<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+        args: ReadArgs,
+    ) -> Result<ReadResult<'a>, Error>;
+
+    fn read_directory<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+        args: ReadDirectoryArgs<'_>,
+    ) -> Result<ReadDirectoryResult<'a>, Error>;
+
+    fn read_link<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+    ) -> Result<Cow<'a, str>, Error>;
+
+    fn open<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+        args: OpenArgs<'_>,
+    ) -> Result<(FileHandle<'a>, OpenResult<'a>), Error>;
+
+    fn close(
+        &self,
+        _credentials: &Credentials,
+        _file_handle: &FileHandle<'_>,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn create<'a>(
+        &self,
+        credentials: &Credentials,
+        directory: &FileHandle<'_>,
+        args: CreateArgs<'_>,
+    ) -> Result<(FileHandle<'a>, CreateResult<'a>), Error>;
+
+    fn link(
+        &self,
+        credentials: &Credentials,
+        source: &FileHandle<'_>,
+        directory: &FileHandle<'_>,
+        name: &str,
+    ) -> Result<ChangeInfo, Error>;
+
+    fn remove(
+        &self,
+        credentials: &Credentials,
+        directory: &FileHandle<'_>,
+        name: &str,
+    ) -> Result<ChangeInfo, Error>;
+
+    fn rename(
+        &self,
+        credentials: &Credentials,
+        source_directory: &FileHandle<'_>,
+        target_directory: &FileHandle<'_>,
+        args: RenameArgs<'_>,
+    ) -> Result<RenameResult, Error>;
+
+    fn set_attributes<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+        args: SetAttributesArgs<'_>,
+    ) -> Result<AttributeMask<'a>, Error>;
+
+    fn write(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+        args: WriteArgs<'_>,
+    ) -> Result<WriteResult, Error>;
+
+    fn commit(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+        args: CommitArgs,
+    ) -> Result<Verifier, Error>;
+}
+
+/// Creates the directory of the shared volumes unless it exists. Every station may create
+/// volumes in it, but like in `/tmp` only remove its own.
+pub fn create_volumes_directory(drive: &dyn Drive) -> Result<(), Error> {
+    let credentials = Credentials {
+        uid: 0,
+        gid: 0,
+        gids: Vec::new(),
+    };
+    let root = drive.root_file_handle();
+    match drive.lookup(&credentials, &root, VOLUMES_DIRECTORY) {
+        Err(Error::NOENT) => (),
+        result => return result.map(|_| ()),
+    }
+    drive.create(
+        &credentials,
+        &root,
+        CreateArgs {
+            object_type: CreateType::Directory,
+            name: VOLUMES_DIRECTORY,
+            attributes: vec![AttributeValue::Mode(0o1777)],
+        },
+    )?;
+    Ok(())
+}
+
+impl crate::Server {
+    /// Runs a call of the drive on the blocking thread pool, as file system calls may block.
+    async fn blocking<T: Send + 'static>(
+        &self,
+        call: impl FnOnce(&dyn Drive) -> Result<T, Error> + Send + 'static,
+    ) -> Result<T, Error> {
+        let export = Arc::clone(&self.export);
+        tokio::task::spawn_blocking(move || call(&*export))
+            .await
+            .unwrap_or_else(|error| {
+                tracing::error!("Drive call failed: {error}");
+                Err(Error::SERVERFAULT)
+            })
+    }
+}
+
+fn error(error: io::Error) -> Error {
+    if error.raw_os_error() == Some(Errno::ELOOP as i32) {
+        return Error::SYMLINK;
+    }
+    match error.kind() {
+        io::ErrorKind::NotFound => Error::NOENT,
+        io::ErrorKind::PermissionDenied => Error::Access,
+        io::ErrorKind::AlreadyExists => Error::EXIST,
+        io::ErrorKind::NotADirectory => Error::NOTDIR,
+        io::ErrorKind::IsADirectory => Error::ISDIR,
+        io::ErrorKind::DirectoryNotEmpty => Error::NOTEMPTY,
+        io::ErrorKind::ReadOnlyFilesystem => Error::ROFS,
+        io::ErrorKind::StorageFull => Error::NOSPC,
+        io::ErrorKind::FileTooLarge => Error::FBIG,
+        io::ErrorKind::CrossesDevices => Error::XDEV,
+        io::ErrorKind::InvalidFilename => Error::NAMETOOLONG,
+        _ => Error::IO,
+    }
+}
+
+/// Copies what a call borrows from the request, to hand it to the blocking thread pool.
+fn owned(
+    credentials: &Credentials,
+    file_handle: &FileHandle<'_>,
+) -> (Credentials, FileHandle<'static>) {
+    (credentials.clone(), file_handle.clone().into_owned())
+}
+
+fn owned_attributes(attributes: Vec<AttributeValue<'_>>) -> Vec<AttributeValue<'static>> {
+    attributes
+        .into_iter()
+        .map(AttributeValue::into_owned)
+        .collect()
+}
+
+/// The name an open claims, if any.
+fn claimed_name<'a>(claim: &OpenClaim<'a>) -> &'a str {
+    match *claim {
+        OpenClaim::Null(name)
+        | OpenClaim::DelegateCurrent(_, name)
+        | OpenClaim::DelegatePrevious(name) => name,
+        _ => "",
+    }
+}
+
+/// The same claim for another name.
+fn reclaim<'a>(
+    claim: &OpenClaim<'_>,
+    name: &'a str,
+) -> OpenClaim<'a> {
+    match claim {
+        OpenClaim::Null(_) => OpenClaim::Null(name),
+        OpenClaim::Previous(delegation_type) => OpenClaim::Previous(*delegation_type),
+        OpenClaim::DelegateCurrent(state_id, _) => {
+            OpenClaim::DelegateCurrent(state_id.clone(), name)
+        }
+        OpenClaim::DelegatePrevious(_) => OpenClaim::DelegatePrevious(name),
+        OpenClaim::FileHandle => OpenClaim::FileHandle,
+        OpenClaim::DelegateCurrentFileHandle(state_id) => {
+            OpenClaim::DelegateCurrentFileHandle(state_id.clone())
+        }
+        OpenClaim::DelegatePreviousFileHandle => OpenClaim::DelegatePreviousFileHandle,
+    }
+}
+
+impl Handler for crate::Server {
+    async fn access(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+        flags: AccessFlags,
+    ) -> Result<AccessResult, Error> {
+        match self.tree.owns(file_handle) {
+            true => self.tree.access(credentials, file_handle, flags).await,
+            false => {
+                let (credentials, file_handle) = owned(credentials, file_handle);
+                self.blocking(move |export| export.access(&credentials, &file_handle, flags))
+                    .await
+            }
+        }
+    }
+
+    async fn lookup<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'a>,
+        name: &str,
+    ) -> Result<FileHandle<'a>, Error> {
+        if self.tree.is_mount(file_handle, name) {
+            return Ok(self.export.root_file_handle());
+        }
+        match self.tree.owns(file_handle) {
+            true => self.tree.lookup(credentials, file_handle, name).await,
+            false => {
+                let (credentials, file_handle) = owned(credentials, file_handle);
+                let name = name.to_owned();
+                self.blocking(move |export| export.lookup(&credentials, &file_handle, &name))
+                    .await
+            }
+        }
+    }
+
+    async fn lookup_parent<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'a>,
+    ) -> Result<FileHandle<'a>, Error> {
+        if *file_handle == self.export.root_file_handle() {
+            return Ok(self.tree.root_file_handle());
+        }
+        match self.tree.owns(file_handle) {
+            true => self.tree.lookup_parent(credentials, file_handle).await,
+            false => {
+                let (credentials, file_handle) = owned(credentials, file_handle);
+                self.blocking(move |export| export.lookup_parent(&credentials, &file_handle))
+                    .await
+            }
+        }
+    }
+
+    async fn get_attributes<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'a>,
+        mask: AttributeMask<'a>,
+    ) -> Result<Vec<AttributeValue<'a>>, Error> {
+        match self.tree.owns(file_handle) {
+            true => {
+                self.tree
+                    .get_attributes(credentials, file_handle, mask)
+                    .await
+            }
+            false => {
+                let (credentials, file_handle) = owned(credentials, file_handle);
+                let mask = mask.into_owned();
+                self.blocking(move |export| export.get_attributes(&credentials, &file_handle, mask))
+                    .await
+            }
+        }
+    }
+
+    async fn read<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'a>,
+        args: ReadArgs,
+    ) -> Result<ReadResult<'a>, Error> {
+        match self.tree.owns(file_handle) {
+            true => self.tree.read(credentials, file_handle, args).await,
+            false => {
+                let (credentials, file_handle) = owned(credentials, file_handle);
+                self.blocking(move |export| export.read(&credentials, &file_handle, args))
+                    .await
+            }
+        }
+    }
+
+    async fn read_directory<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'a>,
+        args: ReadDirectoryArgs<'a>,
+    ) -> Result<ReadDirectoryResult<'a>, Error> {
+        if !self.tree.owns(file_handle) {
+            let (credentials, file_handle) = owned(credentials, file_handle);
+            let args = ReadDirectoryArgs {
+                attributes: args.attributes.into_owned(),
+                ..args
+            };
+            return self
+                .blocking(move |export| export.read_directory(&credentials, &file_handle, args))
+                .await;
+        }
+        let mask = args.attributes.clone();
+        let mut result = self
+            .tree
+            .read_directory(credentials, file_handle, args)
+            .await?;
+        // Mount points show the root of what is mounted on them.
+        for entry in &mut result.directory_list.entries {
+            if self.tree.is_mount(file_handle, &entry.name) {
+                let credentials = credentials.clone();
+                let mask = mask.clone().into_owned();
+                entry.attributes = self
+                    .blocking(move |export| {
+                        export.get_attributes(&credentials, &export.root_file_handle(), mask)
+                    })
+                    .await?;
+            }
+        }
+        Ok(result)
+    }
+
+    async fn read_link<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'a>,
+    ) -> Result<Cow<'a, str>, Error> {
+        match self.tree.owns(file_handle) {
+            true => self.tree.read_link(credentials, file_handle).await,
+            false => {
+                let (credentials, file_handle) = owned(credentials, file_handle);
+                self.blocking(move |export| export.read_link(&credentials, &file_handle))
+                    .await
+            }
+        }
+    }
+
+    async fn open<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'a>,
+        args: OpenArgs<'a>,
+    ) -> Result<(FileHandle<'a>, OpenResult<'a>), Error> {
+        match self.tree.owns(file_handle) {
+            true => self.tree.open(credentials, file_handle, args).await,
+            false => {
+                let (credentials, file_handle) = owned(credentials, file_handle);
+                let name = claimed_name(&args.claim).to_owned();
+                let claim = reclaim(&args.claim, "");
+                let owner = args.owner.into_owned();
+                let how = args.how.into_owned();
+                self.blocking(move |export| {
+                    let args = OpenArgs {
+                        owner,
+                        how,
+                        claim: reclaim(&claim, &name),
+                        ..args
+                    };
+                    export.open(&credentials, &file_handle, args)
+                })
+                .await
+            }
+        }
+    }
+
+    async fn close<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'a>,
+        _args: CloseArgs,
+    ) -> Result<(), Error> {
+        match self.tree.owns(file_handle) {
+            true => Ok(()),
+            false => {
+                let (credentials, file_handle) = owned(credentials, file_handle);
+                self.blocking(move |export| export.close(&credentials, &file_handle))
+                    .await
+            }
+        }
+    }
+
+    async fn create<'a>(
+        &self,
+        credentials: &Credentials,
+        directory: &FileHandle<'a>,
+        args: CreateArgs<'a>,
+    ) -> Result<(FileHandle<'a>, CreateResult<'a>), Error> {
+        match self.tree.owns(directory) {
+            true => self.tree.create(credentials, directory, args).await,
+            false => {
+                let (credentials, directory) = owned(credentials, directory);
+                let object_type = args.object_type.into_owned();
+                let name = args.name.to_owned();
+                let attributes = owned_attributes(args.attributes);
+                self.blocking(move |export| {
+                    let args = CreateArgs {
+                        object_type,
+                        name: &name,
+                        attributes,
+                    };
+                    export.create(&credentials, &directory, args)
+                })
+                .await
+            }
+        }
+    }
+
+    async fn link<'a>(
+        &self,
+        credentials: &Credentials,
+        source: &FileHandle<'a>,
+        directory: &FileHandle<'a>,
+        name: &str,
+    ) -> Result<ChangeInfo, Error> {
+        match (self.tree.owns(source), self.tree.owns(directory)) {
+            (false, false) => {
+                let (credentials, source) = owned(credentials, source);
+                let directory = directory.clone().into_owned();
+                let name = name.to_owned();
+                self.blocking(move |export| export.link(&credentials, &source, &directory, &name))
+                    .await
+            }
+            (true, true) => Err(Error::NOTSUPP),
+            _ => Err(Error::XDEV),
+        }
+    }
+
+    async fn remove<'a>(
+        &self,
+        credentials: &Credentials,
+        directory: &FileHandle<'a>,
+        name: &str,
+    ) -> Result<ChangeInfo, Error> {
+        match self.tree.owns(directory) {
+            true => self.tree.remove(credentials, directory, name).await,
+            false => {
+                let (credentials, directory) = owned(credentials, directory);
+                let name = name.to_owned();
+                self.blocking(move |export| export.remove(&credentials, &directory, &name))
+                    .await
+            }
+        }
+    }
+
+    async fn rename<'a>(
+        &self,
+        credentials: &Credentials,
+        source_directory: &FileHandle<'a>,
+        target_directory: &FileHandle<'a>,
+        args: RenameArgs<'a>,
+    ) -> Result<RenameResult, Error> {
+        match (
+            self.tree.owns(source_directory),
+            self.tree.owns(target_directory),
+        ) {
+            (false, false) => {
+                let (credentials, source_directory) = owned(credentials, source_directory);
+                let target_directory = target_directory.clone().into_owned();
+                let (old_name, new_name) = (args.old_name.to_owned(), args.new_name.to_owned());
+                self.blocking(move |export| {
+                    let args = RenameArgs {
+                        old_name: &old_name,
+                        new_name: &new_name,
+                    };
+                    export.rename(&credentials, &source_directory, &target_directory, args)
+                })
+                .await
+            }
+            (true, true) => Err(Error::NOTSUPP),
+            _ => Err(Error::XDEV),
+        }
+    }
+
+    async fn set_attributes<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'a>,
+        args: SetAttributesArgs<'a>,
+    ) -> Result<AttributeMask<'a>, Error> {
+        match self.tree.owns(file_handle) {
+            true => {
+                self.tree
+                    .set_attributes(credentials, file_handle, args)
+                    .await
+            }
+            false => {
+                let (credentials, file_handle) = owned(credentials, file_handle);
+                let args = SetAttributesArgs {
+                    state_id: args.state_id,
+                    attributes: owned_attributes(args.attributes),
+                };
+                self.blocking(move |export| export.set_attributes(&credentials, &file_handle, args))
+                    .await
+            }
+        }
+    }
+
+    async fn write<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'a>,
+        args: WriteArgs<'a>,
+    ) -> Result<WriteResult, Error> {
+        match self.tree.owns(file_handle) {
+            true => self.tree.write(credentials, file_handle, args).await,
+            false => {
+                let (credentials, file_handle) = owned(credentials, file_handle);
+                let args = WriteArgs {
+                    data: Cow::Owned(args.data.into_owned()),
+                    ..args
+                };
+                self.blocking(move |export| export.write(&credentials, &file_handle, args))
+                    .await
+            }
+        }
+    }
+
+    async fn commit<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'a>,
+        args: CommitArgs,
+    ) -> Result<Verifier, Error> {
+        match self.tree.owns(file_handle) {
+            true => self.tree.commit(credentials, file_handle, args).await,
+            false => {
+                let (credentials, file_handle) = owned(credentials, file_handle);
+                self.blocking(move |export| export.commit(&credentials, &file_handle, args))
+                    .await
+            }
+        }
+    }
+
+    /// Records change over MQTT and the files of a plain export on disk, neither of which
+    /// the server hears of, so only the files of a store are delegated.
+    fn delegates(
+        &self,
+        file_handle: &FileHandle<'_>,
+    ) -> bool {
+        self.store.is_some() && !self.tree.owns(file_handle)
+    }
+
+    async fn destroy_session(
+        &self,
+        _session_id: SessionId,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn destroy_client_id(
+        &self,
+        _client_id: ClientId,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Storage;
+    use crypto::Identity;
+    use nix::unistd::{getgid, getuid};
+    use std::net::SocketAddr;
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+    use std::path::{Path, PathBuf};
+
+    /// Serves a gateway whose drive is a plain export of a new directory.
+    async fn start_gateway(name: &str) -> (SocketAddr, PathBuf) {
+        let export_path =
+            std::env::temp_dir().join(format!("lararium-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&export_path);
+        std::fs::create_dir_all(&export_path).unwrap();
+        (serve(&export_path).await, export_path)
+    }
+
+    /// Serves a gateway whose drive is a plain export of the directory.
+    async fn serve(export_path: &Path) -> SocketAddr {
+        let identity = Identity::new("gateway").unwrap();
+        let origin: ::dns::Name = "lararium".parse().unwrap();
+        let zone = ::dns::Zone::new(origin.clone(), origin.prepend("gateway").unwrap());
+        let server = crate::Server::new(
+            identity.certificate().clone(),
+            identity,
+            export_path,
+            Storage::Plain,
+            zone,
+            Vec::new(),
+        )
+        .await
+        .unwrap();
+        let nfs = ::nfs::Server::bind("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let address = nfs.local_address().unwrap();
+        tokio::spawn(async move { nfs.listen(server).await });
+        address
+    }
+
+    #[tokio::test]
+    async fn test_shared_volumes() {
+        let (address, export_path) = start_gateway("nfs-volumes").await;
+        let metadata = std::fs::metadata(export_path.join(VOLUMES_DIRECTORY)).unwrap();
+        assert!(metadata.is_dir());
+        assert_eq!(metadata.permissions().mode() & 0o7777, 0o1777);
+        let credentials = AuthSysParms {
+            stamp: 0,
+            machine_name: "station".into(),
+            uid: 1000,
+            gid: 1000,
+            gids: vec![],
+        };
+        let mut client = ::nfs::Client::connect(address, &credentials).await.unwrap();
+        client.create_dir("/drive/volumes/music").await.unwrap();
+        assert!(matches!(
+            client.create_dir("/drive/volumes/music").await,
+            Err(::nfs::client::Error::Nfs(Error::EXIST))
+        ));
+        let metadata = std::fs::metadata(export_path.join("volumes/music")).unwrap();
+        assert_eq!(metadata.uid(), 1000);
+        client.disconnect().await.unwrap();
+        std::fs::remove_dir_all(&export_path).unwrap();
+    }
+
+    /// The drive is served from a single threaded runtime, where blocking in place panics.
+    #[tokio::test]
+    async fn test_client() {
+        let (address, export_path) = start_gateway("nfs-client").await;
+        std::fs::write(export_path.join("hello.txt"), b"hello world").unwrap();
+        let credentials = AuthSysParms {
+            stamp: 0,
+            machine_name: "test".into(),
+            uid: getuid().as_raw(),
+            gid: getgid().as_raw(),
+            gids: vec![],
+        };
+        let mut client = ::nfs::Client::connect(address, &credentials).await.unwrap();
+        assert_eq!(
+            client.read_file("/drive/hello.txt").await.unwrap(),
+            b"hello world"
+        );
+        client
+            .write_file("/drive/notes.txt", b"notes")
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(export_path.join("notes.txt")).unwrap(),
+            b"notes"
+        );
+        let mut names = client.list_dir("/drive").await.unwrap();
+        names.sort();
+        assert_eq!(names, ["hello.txt", "notes.txt", VOLUMES_DIRECTORY]);
+        std::os::unix::fs::symlink("/etc/passwd", export_path.join("passwd")).unwrap();
+        assert!(matches!(
+            client.read_file("/drive/passwd").await,
+            Err(::nfs::client::Error::Nfs(Error::SYMLINK))
+        ));
+        client.disconnect().await.unwrap();
+        std::fs::remove_dir_all(&export_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_handles_survive_restart() {
+        let (address, export_path) = start_gateway("nfs-restart").await;
+        std::fs::create_dir_all(export_path.join("music/albums")).unwrap();
+        std::fs::write(export_path.join("music/albums/track"), b"song").unwrap();
+        let credentials = AuthSysParms {
+            stamp: 0,
+            machine_name: "test".into(),
+            uid: getuid().as_raw(),
+            gid: getgid().as_raw(),
+            gids: vec![],
+        };
+        let mut client = ::nfs::Client::connect(address, &credentials).await.unwrap();
+        let file_handle = client
+            .file_handle("/drive/music/albums/track")
+            .await
+            .unwrap();
+        client.disconnect().await.unwrap();
+
+        let address = serve(&export_path).await;
+        let mut client = ::nfs::Client::connect(address, &credentials).await.unwrap();
+        assert_eq!(
+            client.read_file_handle(&file_handle).await.unwrap(),
+            b"song"
+        );
+        std::fs::rename(
+            export_path.join("music/albums"),
+            export_path.join("music/singles"),
+        )
+        .unwrap();
+        assert_eq!(
+            client.read_file_handle(&file_handle).await.unwrap(),
+            b"song"
+        );
+        client.disconnect().await.unwrap();
+        std::fs::remove_dir_all(&export_path).unwrap();
+    }
+}
@@ -0,0 +1,245 @@
+use super::index::{self, Content, Hash};
+use super::{chunker, error, State, Store};
+use nfs::*;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// A chunk file and the number of references to it.
+pub struct Stored {
+    pub length: u32,
+    pub references: u64,
+}
+
+impl Store {
+    /// Moves the content of a file to the staging directory, where it can be written to.
+    pub(super) fn unseal(
+        &self,
+        state: &mut State,
+        id: u64,
+        truncate: bool,
+    ) -> Result<(), Error> {
+        if state.staged.contains(&id) {
+            return Ok(());
+        }
+        let mut file = File::create(self.staging_path(id)).map_err(error)?;
+        if !truncate {
+            for chunk in state.inode(id)?.chunks() {
+                file.write_all(&self.read_chunk(&chunk.hash)?)
+                    .map_err(error)?;
+            }
+        }
+        file.sync_data().map_err(error)?;
+        state.staged.insert(id);
+        if truncate {
+            if let Content::File { size, .. } = &mut state.inode_mut(id)?.content {
+                *size = 0;
+            }
+        }
+        Ok(())
+    }
+
+    /// Chunks a staged file, storing the chunks that are new.
+    pub(super) fn seal(
+        &self,
+        state: &mut State,
+        id: u64,
+    ) -> Result<(), Error> {
+        if !state.staged.contains(&id) {
+            return Ok(());
+        }
+        let (chunks, length) = self
+            .chunk_staged(id, |hash| state.chunks.contains_key(hash))
+            .map_err(error)?;
+        self.replace_chunks(state, id, chunks, length)
+    }
+
+    /// Chunks a staged file like `seal`, but reads it and writes its chunks without holding the
+    /// lock. Should the file change in between, it is chunked again under the lock.
+    pub(super) fn seal_unlocked(
+        &self,
+        id: u64,
+    ) -> Result<(), Error> {
+        let before = {
+            let state = self.state.read().unwrap();
+            if !state.staged.contains(&id) {
+                return Ok(());
+            }
+            state.inode(id)?.change()
+        };
+        let chunked = self.chunk_staged(id, |hash| self.chunk_path(hash).exists());
+        let mut state = self.state.write().unwrap();
+        let Ok((chunks, length)) = chunked else {
+            return self.seal(&mut state, id);
+        };
+        // A chunk found on disk may have been removed since, as nothing held on to it.
+        let unchanged = state.staged.contains(&id)
+            && state.inode(id).is_ok_and(|inode| inode.change() == before)
+            && chunks.iter().all(|chunk| {
+                state.chunks.contains_key(&chunk.hash) || self.chunk_path(&chunk.hash).exists()
+            });
+        if unchanged {
+            return self.replace_chunks(&mut state, id, chunks, length);
+        }
+        for chunk in &chunks {
+            if !state.chunks.contains_key(&chunk.hash) {
+                let _ = fs::remove_file(self.chunk_path(&chunk.hash));
+            }
+        }
+        self.seal(&mut state, id)
+    }
+
+    /// Splits a staged file into chunks, writing those that `stored` does not know of.
+    fn chunk_staged(
+        &self,
+        id: u64,
+        stored: impl Fn(&Hash) -> bool,
+    ) -> io::Result<(Vec<index::Chunk>, u64)> {
+        let file = File::open(self.staging_path(id))?;
+        let mut chunks = Vec::new();
+        let mut length = 0;
+        chunker::split(&file, |data| {
+            let hash = openssl::sha::sha256(data);
+            if !stored(&hash) {
+                self.write_chunk(&hash, data)?;
+            }
+            chunks.push(index::Chunk {
+                hash,
+                length: data.len() as u32,
+            });
+            length += data.len() as u64;
+            Ok(())
+        })?;
+        Ok((chunks, length))
+    }
+
+    /// Makes the chunks of a staged file its content, which is then no longer staged.
+    fn replace_chunks(
+        &self,
+        state: &mut State,
+        id: u64,
+        chunks: Vec<index::Chunk>,
+        length: u64,
+    ) -> Result<(), Error> {
+        retain(state, &chunks);
+        let inode = state.inode_mut(id)?;
+        let Content::File { size, chunks: old } = &mut inode.content else {
+            return Err(Error::INVAL);
+        };
+        *size = length;
+        let old = std::mem::replace(old, chunks);
+        if let Err(err) = self.records.save(id, inode) {
+            if let Content::File { chunks, .. } = &mut inode.content {
+                let chunks = std::mem::replace(chunks, old);
+                release(state, &chunks, |hash| {
+                    let _ = fs::remove_file(self.chunk_path(hash));
+                });
+            }
+            return Err(error(err));
+        }
+        release(state, &old, |hash| self.remove_chunk(hash));
+        state.staged.remove(&id);
+        fs::remove_file(self.staging_path(id)).map_err(error)?;
+        Ok(())
+    }
+
+    pub(super) fn read_chunk(
+        &self,
+        hash: &Hash,
+    ) -> Result<Vec<u8>, Error> {
+        let data = fs::read(self.chunk_path(hash)).map_err(error)?;
+        if openssl::sha::sha256(&data) != *hash {
+            tracing::error!("Chunk {} is corrupt", hex(hash));
+            return Err(Error::IO);
+        }
+        Ok(data)
+    }
+
+    pub(super) fn write_chunk(
+        &self,
+        hash: &Hash,
+        data: &[u8],
+    ) -> io::Result<()> {
+        let path = self.chunk_path(hash);
+        fs::create_dir_all(path.parent().unwrap())?;
+        let temporary = path.with_extension("tmp");
+        let mut file = File::create(&temporary)?;
+        file.write_all(data)?;
+        file.sync_data()?;
+        fs::rename(&temporary, &path)
+    }
+
+    pub(super) fn remove_chunk(
+        &self,
+        hash: &Hash,
+    ) {
+        if let Err(error) = fs::remove_file(self.chunk_path(hash)) {
+            tracing::error!("Failed to remove chunk {}: {error}", hex(hash));
+        }
+    }
+
+    pub(super) fn chunk_path(
+        &self,
+        hash: &Hash,
+    ) -> PathBuf {
+        let name = hex(hash);
+        self.root.join("chunks").join(&name[..2]).join(name)
+    }
+
+    pub(super) fn staging_path(
+        &self,
+        id: u64,
+    ) -> PathBuf {
+        self.root.join("staging").join(format!("{id:016x}"))
+    }
+}
+
+pub fn retain(
+    state: &mut State,
+    chunks: &[index::Chunk],
+) {
+    for chunk in chunks {
+        let stored = state.chunks.entry(chunk.hash).or_insert(Stored {
+            length: chunk.length,
+            references: 0,
+        });
+        if stored.references == 0 {
+            state.stored += chunk.length as u64;
+        }
+        stored.references += 1;
+    }
+}
+
+/// Drops references to chunks, calling `remove` for chunks that are no longer used.
+pub fn release(
+    state: &mut State,
+    chunks: &[index::Chunk],
+    mut remove: impl FnMut(&Hash),
+) {
+    for chunk in chunks {
+        let Some(stored) = state.chunks.get_mut(&chunk.hash) else {
+            continue;
+        };
+        stored.references -= 1;
+        if stored.references == 0 {
+            state.stored -= stored.length as u64;
+            state.chunks.remove(&chunk.hash);
+            remove(&chunk.hash);
+        }
+    }
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+pub fn unhex(name: &str) -> Option<Hash> {
+    if name.len() != 64 {
+        return None;
+    }
+    let mut hash = [0; 32];
+    for (i, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(name.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(hash)
+}
@@ -0,0 +1,133 @@
+use std::io::{self, Read};
+
+pub const MIN_CHUNK_SIZE: usize = 16 * 1024;
+pub const AVERAGE_CHUNK_SIZE: usize = 64 * 1024;
+pub const MAX_CHUNK_SIZE: usize = 256 * 1024;
+
+/// Cuts below the average size need 18 zero bits and cuts above it 14, which keeps chunk sizes
+/// close to the average. The high bits of the hash depend on the most bytes.
+const MASK_SMALL: u64 = !0 << (64 - 18);
+const MASK_LARGE: u64 = !0 << (64 - 14);
+
+const GEAR: [u64; 256] = gear();
+
+/// Random values for every byte, from SplitMix64 so that chunk boundaries never change.
+const fn gear() -> [u64; 256] {
+    let mut table = [0; 256];
+    let mut state: u64 = 0x6c61_7261_7269_756d;
+    let mut i = 0;
+    while i < table.len() {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut value = state;
+        value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = value ^ (value >> 31);
+        i += 1;
+    }
+    table
+}
+
+/// The length of the chunk at the start of `data`, cut where a rolling hash of its content
+/// (FastCDC's gear hash) hits a pattern. Boundaries follow the content, so inserting bytes into
+/// a file only changes the chunks around the insertion.
+pub fn cut(data: &[u8]) -> usize {
+    if data.len() <= MIN_CHUNK_SIZE {
+        return data.len();
+    }
+    let end = data.len().min(MAX_CHUNK_SIZE);
+    let normal = end.min(AVERAGE_CHUNK_SIZE);
+    let mut hash: u64 = 0;
+    for (i, byte) in data.iter().enumerate().take(end).skip(MIN_CHUNK_SIZE) {
+        hash = (hash << 1).wrapping_add(GEAR[*byte as usize]);
+        let mask = match i < normal {
+            true => MASK_SMALL,
+            false => MASK_LARGE,
+        };
+        if hash & mask == 0 {
+            return i + 1;
+        }
+    }
+    end
+}
+
+/// Reads everything from the reader, handing it over chunk by chunk.
+pub fn split(
+    mut reader: impl Read,
+    mut chunk: impl FnMut(&[u8]) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut buffer = vec![0; MAX_CHUNK_SIZE];
+    let mut length = 0;
+    let mut eof = false;
+    loop {
+        while !eof && length < buffer.len() {
+            match reader.read(&mut buffer[length..]) {
+                Ok(0) => eof = true,
+                Ok(count) => length += count,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            }
+        }
+        if length == 0 {
+            return Ok(());
+        }
+        let size = cut(&buffer[..length]);
+        chunk(&buffer[..size])?;
+        buffer.copy_within(size..length, 0);
+        length -= size;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random(length: usize) -> Vec<u8> {
+        let mut state: u64 = 42;
+        (0..length)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn chunks(data: &[u8]) -> Vec<Vec<u8>> {
+        let mut chunks = Vec::new();
+        split(data, |chunk| {
+            chunks.push(chunk.to_vec());
+            Ok(())
+        })
+        .unwrap();
+        chunks
+    }
+
+    #[test]
+    fn test_chunk_sizes() {
+        let data = random(4 * 1024 * 1024);
+        let chunks = chunks(&data);
+        assert_eq!(chunks.concat(), data);
+        let (last, chunks) = chunks.split_last().unwrap();
+        assert!(last.len() <= MAX_CHUNK_SIZE);
+        assert!(chunks
+            .iter()
+            .all(|chunk| (MIN_CHUNK_SIZE..=MAX_CHUNK_SIZE).contains(&chunk.len())));
+        let average = data.len() / (chunks.len() + 1);
+        assert!((AVERAGE_CHUNK_SIZE / 2..AVERAGE_CHUNK_SIZE * 2).contains(&average));
+    }
+
+    #[test]
+    fn test_insertion_keeps_later_chunks() {
+        let data = random(2 * 1024 * 1024);
+        let mut shifted = b"a few more bytes".to_vec();
+        shifted.extend_from_slice(&data);
+        let original = chunks(&data);
+        let shifted = chunks(&shifted);
+        let shared = shifted
+            .iter()
+            .filter(|chunk| original.contains(chunk))
+            .count();
+        assert!(shared >= original.len() - 2);
+    }
+}
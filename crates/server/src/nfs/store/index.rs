@@ -0,0 +1,224 @@
+use nfs::Time;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const ROOT_ID: u64 = 1;
+
+pub type Hash = [u8; 32];
+
+/// A file, directory or symlink of the store, kept as one record per inode.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Inode {
+    pub content: Content,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub access_time: Timestamp,
+    pub modify_time: Timestamp,
+    pub change_time: Timestamp,
+    /// The directory entries that lead to the inode, counted when the index is loaded.
+    #[serde(skip)]
+    pub links: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Content {
+    Directory {
+        parent: u64,
+        entries: BTreeMap<String, u64>,
+    },
+    /// The content of a file as a list of chunks, which is stale while the file is staged.
+    File {
+        size: u64,
+        chunks: Vec<Chunk>,
+    },
+    Symlink(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Chunk {
+    pub hash: Hash,
+    pub length: u32,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Timestamp {
+    pub seconds: i64,
+    pub nanoseconds: u32,
+}
+
+impl Inode {
+    pub fn new(
+        content: Content,
+        mode: u32,
+        uid: u32,
+        gid: u32,
+    ) -> Self {
+        let now = Timestamp::now();
+        Self {
+            content,
+            mode,
+            uid,
+            gid,
+            access_time: now,
+            modify_time: now,
+            change_time: now,
+            links: 0,
+        }
+    }
+
+    pub fn is_directory(&self) -> bool {
+        matches!(self.content, Content::Directory { .. })
+    }
+
+    pub fn size(&self) -> u64 {
+        match &self.content {
+            Content::Directory { entries, .. } => entries.len() as u64,
+            Content::File { size, .. } => *size,
+            Content::Symlink(target) => target.len() as u64,
+        }
+    }
+
+    pub fn chunks(&self) -> &[Chunk] {
+        match &self.content {
+            Content::File { chunks, .. } => chunks,
+            _ => &[],
+        }
+    }
+
+    /// The change attribute, which moves with the metadata time.
+    pub fn change(&self) -> u64 {
+        self.change_time.seconds as u64 * 1_000_000_000 + self.change_time.nanoseconds as u64
+    }
+
+    pub fn touch(&mut self) {
+        let now = Timestamp::now();
+        // Changes within the resolution of the clock still have to show in the change attribute.
+        self.change_time = match now > self.change_time {
+            true => now,
+            false => self.change_time.next(),
+        };
+    }
+
+    pub fn modify(&mut self) {
+        self.touch();
+        self.modify_time = self.change_time;
+    }
+}
+
+impl Timestamp {
+    pub fn now() -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Self {
+            seconds: now.as_secs() as i64,
+            nanoseconds: now.subsec_nanos(),
+        }
+    }
+
+    fn next(self) -> Self {
+        match self.nanoseconds {
+            999_999_999 => Self {
+                seconds: self.seconds + 1,
+                nanoseconds: 0,
+            },
+            nanoseconds => Self {
+                seconds: self.seconds,
+                nanoseconds: nanoseconds + 1,
+            },
+        }
+    }
+}
+
+impl From<Time> for Timestamp {
+    fn from(time: Time) -> Self {
+        Self {
+            seconds: time.seconds,
+            nanoseconds: time.nanoseconds,
+        }
+    }
+}
+
+impl From<Timestamp> for Time {
+    fn from(timestamp: Timestamp) -> Self {
+        Self {
+            seconds: timestamp.seconds,
+            nanoseconds: timestamp.nanoseconds,
+        }
+    }
+}
+
+/// The records of the inodes, one file each in the `inodes` directory of the store, so that a
+/// change only rewrites the records it touches.
+pub struct Records {
+    directory: PathBuf,
+}
+
+impl Records {
+    pub fn new(root: &Path) -> io::Result<Self> {
+        let directory = root.join("inodes");
+        fs::create_dir_all(&directory)?;
+        Ok(Self { directory })
+    }
+
+    pub fn load(&self) -> io::Result<HashMap<u64, Inode>> {
+        let mut inodes = HashMap::new();
+        for entry in fs::read_dir(&self.directory)? {
+            let path = entry?.path();
+            let Some(id) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| u64::from_str_radix(name, 16).ok())
+            else {
+                // Left over from a write that never finished.
+                fs::remove_file(&path)?;
+                continue;
+            };
+            match ciborium::from_reader(File::open(&path)?) {
+                Ok(inode) => {
+                    inodes.insert(id, inode);
+                }
+                Err(error) => tracing::error!("Skipping inode record {}: {error}", path.display()),
+            }
+        }
+        Ok(inodes)
+    }
+
+    /// Replaces the record of the inode in one step, so that it is either old or new after a
+    /// crash.
+    pub fn save(
+        &self,
+        id: u64,
+        inode: &Inode,
+    ) -> io::Result<()> {
+        let path = self.path(id);
+        let temporary = path.with_extension("tmp");
+        let mut file = File::create(&temporary)?;
+        ciborium::into_writer(inode, &mut file).map_err(io::Error::other)?;
+        file.flush()?;
+        file.sync_data()?;
+        fs::rename(&temporary, &path)
+    }
+
+    pub fn remove(
+        &self,
+        id: u64,
+    ) -> io::Result<()> {
+        match fs::remove_file(self.path(id)) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+            _ => Ok(()),
+        }
+    }
+
+    fn path(
+        &self,
+        id: u64,
+    ) -> PathBuf {
+        self.directory.join(format!("{id:016x}"))
+    }
+}
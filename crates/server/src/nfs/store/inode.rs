@@ -0,0 +1,314 @@
+use super::chunk::release;
+use super::index::{Content, Inode, ROOT_ID};
+use super::{
+    check, error, State, Store, FILE_HANDLE_TAG, FILE_SYSTEM_ID, MAX_NAME_LENGTH,
+    SNAPSHOTS_FILE_ID, SNAPSHOTS_NAME, SNAPSHOTS_TAG, SNAPSHOT_TAG,
+};
+use nfs::*;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::sync::Arc;
+
+/// What a file handle leads to: an inode of the store, the `.snapshots` directory, or an inode
+/// of a snapshot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Node {
+    Live(u64),
+    Snapshots,
+    Snapshot(u64, u64),
+}
+
+impl Store {
+    pub(super) fn node(
+        &self,
+        file_handle: &FileHandle<'_>,
+    ) -> Result<Node, Error> {
+        let bytes: &[u8] = file_handle;
+        let number = |bytes: &[u8]| u64::from_be_bytes(bytes.try_into().unwrap());
+        match bytes {
+            [FILE_HANDLE_TAG, id @ ..] if id.len() == 8 => Ok(Node::Live(number(id))),
+            [SNAPSHOTS_TAG] => Ok(Node::Snapshots),
+            [SNAPSHOT_TAG, rest @ ..] if rest.len() == 16 => {
+                Ok(Node::Snapshot(number(&rest[..8]), number(&rest[8..])))
+            }
+            _ => Err(Error::BADHANDLE),
+        }
+    }
+
+    /// The inode of a file handle that may be changed, which those of snapshots may not.
+    pub(super) fn id(
+        &self,
+        file_handle: &FileHandle<'_>,
+    ) -> Result<u64, Error> {
+        match self.node(file_handle)? {
+            Node::Live(id) => Ok(id),
+            _ => Err(Error::ROFS),
+        }
+    }
+
+    pub(super) fn file_handle<'a>(
+        &self,
+        node: Node,
+    ) -> FileHandle<'a> {
+        let mut bytes = Vec::with_capacity(17);
+        match node {
+            Node::Live(id) => {
+                bytes.push(FILE_HANDLE_TAG);
+                bytes.extend_from_slice(&id.to_be_bytes());
+            }
+            Node::Snapshots => bytes.push(SNAPSHOTS_TAG),
+            Node::Snapshot(taken, id) => {
+                bytes.push(SNAPSHOT_TAG);
+                bytes.extend_from_slice(&taken.to_be_bytes());
+                bytes.extend_from_slice(&id.to_be_bytes());
+            }
+        }
+        FileHandle::from(bytes)
+    }
+
+    /// The inode of an entry in the directory, once the caller is found to have the
+    /// permissions on the directory.
+    pub(super) fn child(
+        &self,
+        state: &State,
+        credentials: &Credentials,
+        directory: u64,
+        name: &str,
+        permissions: Permissions,
+    ) -> Result<Option<u64>, Error> {
+        let entries = entries(credentials, state.inode(directory)?, name, permissions)?;
+        // `.snapshots` takes the place of any entry by that name in the root directory.
+        if directory == ROOT_ID && name == SNAPSHOTS_NAME {
+            return Err(Error::Access);
+        }
+        Ok(entries.get(name).copied())
+    }
+
+    /// Adds a new inode to the directory.
+    pub(super) fn insert(
+        &self,
+        state: &mut State,
+        directory: u64,
+        name: &str,
+        mut inode: Inode,
+    ) -> Result<u64, Error> {
+        let id = state.next_id;
+        state.next_id += 1;
+        inode.links = 1;
+        self.records.save(id, &inode).map_err(error)?;
+        state.inodes.insert(id, Arc::new(inode));
+        self.add_entry(state, directory, name, id)?;
+        Ok(id)
+    }
+
+    pub(super) fn add_entry(
+        &self,
+        state: &mut State,
+        directory: u64,
+        name: &str,
+        id: u64,
+    ) -> Result<Option<u64>, Error> {
+        self.change_entries(state, directory, |entries| {
+            entries.insert(name.to_string(), id)
+        })
+    }
+
+    pub(super) fn remove_entry(
+        &self,
+        state: &mut State,
+        directory: u64,
+        name: &str,
+    ) -> Result<Option<u64>, Error> {
+        self.change_entries(state, directory, |entries| entries.remove(name))
+    }
+
+    pub(super) fn change_entries(
+        &self,
+        state: &mut State,
+        directory: u64,
+        change: impl FnOnce(&mut BTreeMap<String, u64>) -> Option<u64>,
+    ) -> Result<Option<u64>, Error> {
+        let inode = state.inode_mut(directory)?;
+        let Content::Directory { entries, .. } = &mut inode.content else {
+            return Err(Error::NOTDIR);
+        };
+        let previous = change(entries);
+        inode.modify();
+        self.records.save(directory, inode).map_err(error)?;
+        Ok(previous)
+    }
+
+    /// Drops an entry that led to the inode, removing the inode with its last entry.
+    pub(super) fn unlink(
+        &self,
+        state: &mut State,
+        id: u64,
+    ) -> Result<(), Error> {
+        let inode = state.inode_mut(id)?;
+        inode.links = inode.links.saturating_sub(1);
+        if inode.links > 0 && !inode.is_directory() {
+            inode.touch();
+            return self.records.save(id, inode).map_err(error);
+        }
+        self.records.remove(id).map_err(error)?;
+        let inode = state.inodes.remove(&id).unwrap();
+        if state.staged.remove(&id) {
+            fs::remove_file(self.staging_path(id)).map_err(error)?;
+        }
+        release(state, inode.chunks(), |hash| self.remove_chunk(hash));
+        Ok(())
+    }
+}
+
+impl Node {
+    pub fn child(
+        self,
+        id: u64,
+    ) -> Node {
+        match self {
+            Node::Live(_) => Node::Live(id),
+            Node::Snapshots => Node::Snapshot(id, ROOT_ID),
+            Node::Snapshot(taken, _) => Node::Snapshot(taken, id),
+        }
+    }
+
+    /// The directory above, where the root of a snapshot lies in `.snapshots` and that in the
+    /// root directory.
+    pub fn parent(
+        self,
+        parent: u64,
+    ) -> Option<Node> {
+        match self {
+            Node::Live(ROOT_ID) => None,
+            Node::Live(_) => Some(Node::Live(parent)),
+            Node::Snapshots => Some(Node::Live(ROOT_ID)),
+            Node::Snapshot(_, ROOT_ID) => Some(Node::Snapshots),
+            Node::Snapshot(taken, _) => Some(Node::Snapshot(taken, parent)),
+        }
+    }
+
+    pub fn file_id(self) -> u64 {
+        match self {
+            Node::Live(id) | Node::Snapshot(_, id) => id,
+            Node::Snapshots => SNAPSHOTS_FILE_ID,
+        }
+    }
+
+    /// Every snapshot is a file system of its own, since it has the same file ids as the store.
+    pub fn file_system_id(self) -> FileSystemId {
+        match self {
+            Node::Live(_) | Node::Snapshots => FILE_SYSTEM_ID,
+            Node::Snapshot(taken, _) => FileSystemId {
+                major: taken,
+                minor: FILE_SYSTEM_ID.minor + 1,
+            },
+        }
+    }
+
+    /// The inode of the store whose content is in the staging directory.
+    pub fn staged(
+        self,
+        state: &State,
+    ) -> Option<u64> {
+        match self {
+            Node::Live(id) if state.staged.contains(&id) => Some(id),
+            _ => None,
+        }
+    }
+}
+
+impl State {
+    pub fn inode(
+        &self,
+        id: u64,
+    ) -> Result<&Inode, Error> {
+        self.inodes.get(&id).map(Arc::as_ref).ok_or(Error::STALE)
+    }
+
+    pub fn inode_mut(
+        &mut self,
+        id: u64,
+    ) -> Result<&mut Inode, Error> {
+        self.inodes
+            .get_mut(&id)
+            .map(Arc::make_mut)
+            .ok_or(Error::STALE)
+    }
+
+    pub fn resolve(
+        &self,
+        node: Node,
+    ) -> Result<Cow<'_, Inode>, Error> {
+        match node {
+            Node::Live(id) => self.inode(id).map(Cow::Borrowed),
+            Node::Snapshots => Ok(Cow::Owned(self.snapshots_directory())),
+            Node::Snapshot(taken, id) => self
+                .snapshots
+                .get(&taken)
+                .and_then(|snapshot| snapshot.inodes.get(&id))
+                .map(|inode| Cow::Borrowed(inode.as_ref()))
+                .ok_or(Error::STALE),
+        }
+    }
+
+    /// The inodes that the node is one of, which `.snapshots` is not.
+    pub fn tree(
+        &self,
+        node: Node,
+    ) -> Option<&HashMap<u64, Arc<Inode>>> {
+        match node {
+            Node::Live(_) => Some(&self.inodes),
+            Node::Snapshots => None,
+            Node::Snapshot(taken, _) => self.snapshots.get(&taken).map(|snapshot| &snapshot.inodes),
+        }
+    }
+
+    pub fn number_of_links(
+        &self,
+        node: Node,
+        inode: &Inode,
+    ) -> u32 {
+        match &inode.content {
+            Content::Directory { entries, .. } => {
+                let directories = match self.tree(node) {
+                    Some(inodes) => entries
+                        .values()
+                        .filter(|id| inodes.get(id).is_some_and(|inode| inode.is_directory()))
+                        .count(),
+                    // The entries of `.snapshots` are the root directories of the snapshots.
+                    None => entries.len(),
+                };
+                2 + directories as u32
+            }
+            _ => inode.links,
+        }
+    }
+}
+
+/// The entries of a directory, once the name is found to be valid and the caller to have the
+/// permissions on the directory.
+pub fn entries<'a>(
+    credentials: &Credentials,
+    inode: &'a Inode,
+    name: &str,
+    permissions: Permissions,
+) -> Result<&'a BTreeMap<String, u64>, Error> {
+    if name.is_empty() {
+        return Err(Error::INVAL);
+    }
+    if name.len() > MAX_NAME_LENGTH {
+        return Err(Error::NAMETOOLONG);
+    }
+    if name == "." || name == ".." || name.contains(['/', '\0']) {
+        return Err(Error::BADNAME);
+    }
+    let Content::Directory { entries, .. } = &inode.content else {
+        return Err(match inode.content {
+            Content::Symlink(_) => Error::SYMLINK,
+            _ => Error::NOTDIR,
+        });
+    };
+    check(credentials, inode, permissions)?;
+    Ok(entries)
+}
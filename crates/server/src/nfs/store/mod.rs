@@ -0,0 +1,1481 @@
+mod chunk;
+mod chunker;
+mod index;
+mod inode;
+mod quota;
+mod scrub;
+mod snapshot;
+
+use self::chunk::{retain, unhex, Stored};
+use self::index::{Content, Hash, Inode, Records, Timestamp, ROOT_ID};
+
+use self::inode::{entries, Node};
+use self::snapshot::{Manifests, Snapshot};
+use super::access::{check, check_attributes, check_sticky, check_transfer, parse_id, Ownership};
+use super::{Drive, FIRST_COOKIE};
+use nfs::*;
+use std::borrow::Cow;
+use std::collections::hash_map;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock, RwLockWriteGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const FILE_HANDLE_TAG: u8 = 4;
+const SNAPSHOTS_TAG: u8 = 5;
+const SNAPSHOT_TAG: u8 = 6;
+const SNAPSHOTS_NAME: &str = ".snapshots";
+/// The file id of `.snapshots`, which no inode has.
+const SNAPSHOTS_FILE_ID: u64 = 0;
+const MAX_NAME_LENGTH: usize = 255;
+const MAX_TRANSFER_SIZE: u32 = 1024 * 1024;
+const FILE_SYSTEM_ID: FileSystemId = FileSystemId { major: 0, minor: 1 };
+/// How long a file may stay staged without being written to before a scrub chunks it.
+const STAGING_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+const SUPPORTED_ATTRIBUTES: &[Attribute] = &[
+    Attribute::SupportedAttributes,
+    Attribute::Type,
+    Attribute::FileHandleExpireType,
+    Attribute::Change,
+    Attribute::Size,
+    Attribute::LinkSupport,
+    Attribute::SymlinkSupport,
+    Attribute::NamedAttributes,
+    Attribute::FileSystemId,
+    Attribute::UniqueHandles,
+    Attribute::LeaseTime,
+    Attribute::AclSupport,
+    Attribute::CanSetTime,
+    Attribute::CaseInsensitive,
+    Attribute::CasePreserving,
+    Attribute::ChownRestricted,
+    Attribute::FileHandle,
+    Attribute::FileId,
+    Attribute::Homogeneous,
+    Attribute::MaxFileSize,
+    Attribute::MaxName,
+    Attribute::MaxRead,
+    Attribute::MaxWrite,
+    Attribute::Mode,
+    Attribute::NoTruncate,
+    Attribute::NumberOfLinks,
+    Attribute::Owner,
+    Attribute::OwnerGroup,
+    Attribute::SpaceAvailable,
+    Attribute::SpaceFree,
+    Attribute::SpaceTotal,
+    Attribute::SpaceUsed,
+    Attribute::TimeAccess,
+    Attribute::TimeAccessSet,
+    Attribute::TimeDelta,
+    Attribute::TimeMetadata,
+    Attribute::TimeModify,
+    Attribute::TimeModifySet,
+    Attribute::MountedOnFileId,
+    Attribute::ModeSetMasked,
+    Attribute::SupportedAttributesExclusiveCreate,
+];
+
+const SETTABLE_ATTRIBUTES: &[Attribute] = &[
+    Attribute::Size,
+    Attribute::Mode,
+    Attribute::Owner,
+    Attribute::OwnerGroup,
+    Attribute::TimeAccessSet,
+    Attribute::TimeModifySet,
+    Attribute::ModeSetMasked,
+];
+
+/// Files kept by their content, so that files with the same content share their disk space.
+///
+/// Files are split into chunks at boundaries picked by their content, and every chunk is stored
+/// once under its SHA-256 hash in the `chunks` directory. The records in the `inodes` directory
+/// list the chunks of every file. A file that is written to is staged whole in the `staging`
+/// directory until it is closed, when it is chunked again.
+///
+/// Snapshots keep the inodes as they were and hold on to their chunks, so files removed since
+/// can still be read from `.snapshots/<time>/` in the root directory, which lists show no entry
+/// for.
+pub struct Store {
+    root: PathBuf,
+    records: Records,
+    manifests: Manifests,
+    quota: Option<u64>,
+    verifier: Verifier,
+    state: RwLock<State>,
+    exclusive_creates: Mutex<HashMap<(u64, String), Verifier>>,
+    /// Held while a snapshot is taken or removed, which mostly happens without the lock on the
+    /// state.
+    taking_snapshot: Mutex<()>,
+}
+
+#[derive(Default)]
+struct State {
+    /// The inodes, which are copied before they change while a snapshot shares them.
+    inodes: HashMap<u64, Arc<Inode>>,
+    chunks: HashMap<Hash, Stored>,
+    staged: HashSet<u64>,
+    next_id: u64,
+    /// The bytes in chunk files.
+    stored: u64,
+    /// The snapshots by the second they were taken at.
+    snapshots: BTreeMap<u64, Snapshot>,
+    /// When a snapshot was last taken or removed, which is when `.snapshots` changed.
+    snapshots_changed: Timestamp,
+}
+
+impl Store {
+    /// Opens the store in the directory, limiting the bytes it keeps to the quota.
+    ///
+    /// Whatever a crash left behind is tidied up: entries without inodes are dropped, inodes
+    /// that no entry leads to and chunks that no file uses are removed, and staged files are
+    /// chunked.
+    pub fn open(
+        root: impl AsRef<Path>,
+        quota: Option<u64>,
+    ) -> io::Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        fs::create_dir_all(root.join("chunks"))?;
+        fs::create_dir_all(root.join("staging"))?;
+        let records = Records::new(&root)?;
+        let manifests = Manifests::new(&root)?;
+        let mut inodes = records.load()?;
+        if let hash_map::Entry::Vacant(entry) = inodes.entry(ROOT_ID) {
+            let directory = Content::Directory {
+                parent: ROOT_ID,
+                entries: BTreeMap::new(),
+            };
+            let inode = Inode::new(directory, 0o755, 0, 0);
+            records.save(ROOT_ID, &inode)?;
+            entry.insert(inode);
+        }
+        let boot_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        let store = Self {
+            root,
+            records,
+            manifests,
+            quota,
+            verifier: boot_time.to_be_bytes(),
+            state: RwLock::new(State::default()),
+            exclusive_creates: Mutex::new(HashMap::new()),
+            taking_snapshot: Mutex::new(()),
+        };
+        store.recover(inodes)?;
+        Ok(store)
+    }
+
+    fn recover(
+        &self,
+        mut inodes: HashMap<u64, Inode>,
+    ) -> io::Result<()> {
+        // Walk the directories from the root, dropping entries without inodes and counting
+        // links. A crash during a rename may leave a directory in two places, of which the
+        // first one found is kept.
+        let mut reachable = HashSet::from([ROOT_ID]);
+        let mut changed = HashSet::new();
+        let mut directories = VecDeque::from([ROOT_ID]);
+        while let Some(id) = directories.pop_front() {
+            let Content::Directory { entries, .. } = &inodes[&id].content else {
+                continue;
+            };
+            let mut dangling = Vec::new();
+            for (name, child) in entries.clone() {
+                let Some(inode) = inodes.get_mut(&child) else {
+                    dangling.push(name);
+                    continue;
+                };
+                if let Content::Directory { parent, .. } = &mut inode.content {
+                    if !reachable.insert(child) {
+                        dangling.push(name);
+                        continue;
+                    }
+                    if *parent != id {
+                        *parent = id;
+                        changed.insert(child);
+                    }
+                    directories.push_back(child);
+                }
+                reachable.insert(child);
+                inode.links += 1;
+            }
+            if let Content::Directory { entries, .. } = &mut inodes.get_mut(&id).unwrap().content {
+                for name in dangling {
+                    entries.remove(&name);
+                    changed.insert(id);
+                }
+            }
+        }
+        for id in changed {
+            self.records.save(id, &inodes[&id])?;
+        }
+        let unreachable = inodes
+            .keys()
+            .filter(|id| !reachable.contains(id))
+            .copied()
+            .collect::<Vec<_>>();
+        for id in unreachable {
+            self.records.remove(id)?;
+            inodes.remove(&id);
+        }
+
+        let mut state = self.state.write().unwrap();
+        for entry in fs::read_dir(self.root.join("staging"))? {
+            let path = entry?.path();
+            let id = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| u64::from_str_radix(name, 16).ok());
+            match id.and_then(|id| inodes.get_mut(&id).map(|inode| (id, inode))) {
+                Some((id, inode)) if matches!(inode.content, Content::File { .. }) => {
+                    let metadata = fs::metadata(&path)?;
+                    if let Content::File { size, .. } = &mut inode.content {
+                        *size = metadata.len();
+                    }
+                    state.staged.insert(id);
+                }
+                _ => fs::remove_file(&path)?,
+            }
+        }
+
+        // Staged files still hold on to their chunks until they are chunked again.
+        let inodes = inodes
+            .into_iter()
+            .map(|(id, inode)| (id, Arc::new(inode)))
+            .collect::<HashMap<_, _>>();
+        for inode in inodes.values() {
+            retain(&mut state, inode.chunks());
+        }
+        self.load_snapshots(&mut state, &inodes)?;
+        let mut present = HashSet::new();
+        for directory in fs::read_dir(self.root.join("chunks"))? {
+            for entry in fs::read_dir(directory?.path())? {
+                let path = entry?.path();
+                match path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .and_then(unhex)
+                {
+                    Some(hash) if state.chunks.contains_key(&hash) => {
+                        present.insert(hash);
+                    }
+                    _ => fs::remove_file(&path)?,
+                }
+            }
+        }
+        let missing = state
+            .chunks
+            .keys()
+            .filter(|hash| !present.contains(*hash))
+            .count();
+        if missing > 0 {
+            tracing::error!("{missing} chunks of the store are missing");
+        }
+        state.next_id = inodes.keys().max().copied().unwrap_or(ROOT_ID) + 1;
+        state.inodes = inodes;
+        state.snapshots_changed = Timestamp::now();
+        let staged = state.staged.iter().copied().collect::<Vec<_>>();
+        for id in staged {
+            if let Err(error) = self.seal(&mut state, id) {
+                tracing::error!("Failed to chunk staged file {id}: {error:?}");
+            }
+        }
+        Ok(())
+    }
+
+    fn create_file<'a>(
+        &self,
+        state: &mut State,
+        credentials: &Credentials,
+        directory: u64,
+        name: &str,
+        existing: Option<u64>,
+        how: OpenFlagCreate<'_>,
+    ) -> Result<(u64, AttributeMask<'a>), Error> {
+        let (attributes, create_new) = match how {
+            OpenFlagCreate::Unchecked { attributes } => (attributes, false),
+            OpenFlagCreate::Guarded { attributes } => (attributes, true),
+            OpenFlagCreate::Exclusive4_1 {
+                verifier,
+                attributes,
+            } => {
+                let mut exclusive_creates = self.exclusive_creates.lock().unwrap();
+                let key = (directory, name.to_string());
+                // A retransmitted exclusive create succeeds if it carries the same verifier.
+                if let Some(id) = existing {
+                    return match exclusive_creates.get(&key) == Some(&verifier) {
+                        true => Ok((id, AttributeMask::new())),
+                        false => Err(Error::EXIST),
+                    };
+                }
+                exclusive_creates.insert(key, verifier);
+                (attributes, true)
+            }
+        };
+        let id = match existing {
+            Some(_) if create_new => return Err(Error::EXIST),
+            Some(id) => {
+                check_attributes(credentials, state.inode(id)?, &attributes, false)?;
+                id
+            }
+            None => {
+                check(credentials, state.inode(directory)?, Permissions::WRITE)?;
+                let inode = Inode::new(
+                    Content::File {
+                        size: 0,
+                        chunks: vec![],
+                    },
+                    0o644,
+                    credentials.uid,
+                    credentials.gid,
+                );
+                check_attributes(credentials, &inode, &attributes, true)?;
+                self.insert(state, directory, name, inode)?
+            }
+        };
+        Ok((id, self.apply_attributes(state, id, &attributes)?))
+    }
+
+    fn apply_attributes<'a>(
+        &self,
+        state: &mut State,
+        id: u64,
+        attributes: &[AttributeValue<'_>],
+    ) -> Result<AttributeMask<'a>, Error> {
+        let mut owner = None;
+        let mut group = None;
+        for attribute in attributes {
+            match attribute {
+                AttributeValue::Owner(value) => owner = Some(parse_id(value)?),
+                AttributeValue::OwnerGroup(value) => group = Some(parse_id(value)?),
+                AttributeValue::Size(_)
+                | AttributeValue::Mode(_)
+                | AttributeValue::ModeSetMasked(_)
+                | AttributeValue::TimeAccessSet(_)
+                | AttributeValue::TimeModifySet(_) => {}
+                _ => return Err(Error::ATTRNOTSUPP),
+            }
+        }
+        let mut applied = Vec::new();
+        for attribute in attributes {
+            if let AttributeValue::Size(new_size) = attribute {
+                let inode = state.inode(id)?;
+                match inode.content {
+                    Content::File { size, .. } => {
+                        self.check_quota(state, new_size.saturating_sub(size))?;
+                    }
+                    Content::Directory { .. } => return Err(Error::ISDIR),
+                    Content::Symlink(_) => return Err(Error::INVAL),
+                }
+                if *new_size != inode.size() {
+                    self.unseal(state, id, *new_size == 0)?;
+                    OpenOptions::new()
+                        .write(true)
+                        .open(self.staging_path(id))
+                        .and_then(|file| file.set_len(*new_size))
+                        .map_err(error)?;
+                    let inode = state.inode_mut(id)?;
+                    if let Content::File { size, .. } = &mut inode.content {
+                        *size = *new_size;
+                    }
+                    inode.modify();
+                }
+                applied.push(Attribute::Size);
+            }
+        }
+        let inode = state.inode_mut(id)?;
+        for attribute in attributes {
+            match attribute {
+                AttributeValue::Mode(mode) => {
+                    inode.mode = mode & 0o7777;
+                    applied.push(Attribute::Mode);
+                }
+                AttributeValue::ModeSetMasked(value) => {
+                    let mode = (inode.mode & !value.mask) | (value.mode & value.mask);
+                    inode.mode = mode & 0o7777;
+                    applied.push(Attribute::ModeSetMasked);
+                }
+                AttributeValue::TimeAccessSet(value) => {
+                    inode.access_time = timestamp(*value);
+                    applied.push(Attribute::TimeAccessSet);
+                }
+                AttributeValue::TimeModifySet(value) => {
+                    inode.modify_time = timestamp(*value);
+                    applied.push(Attribute::TimeModifySet);
+                }
+                _ => {}
+            }
+        }
+        if let Some(owner) = owner {
+            inode.uid = owner;
+            applied.push(Attribute::Owner);
+        }
+        if let Some(group) = group {
+            inode.gid = group;
+            applied.push(Attribute::OwnerGroup);
+        }
+        if !applied.is_empty() {
+            inode.touch();
+            self.records.save(id, inode).map_err(error)?;
+        }
+        Ok(applied.into_iter().collect())
+    }
+
+    fn attributes<'a>(
+        &self,
+        state: &State,
+        node: Node,
+        mask: AttributeMask<'_>,
+    ) -> Result<Vec<AttributeValue<'a>>, Error> {
+        let inode = state.resolve(node)?;
+        // The space of the file system is only looked up when asked for.
+        let space = mask
+            .clone()
+            .into_iter()
+            .any(|attribute| {
+                matches!(
+                    attribute,
+                    Attribute::SpaceAvailable | Attribute::SpaceFree | Attribute::SpaceTotal
+                )
+            })
+            .then(|| self.space(state))
+            .flatten();
+        Ok(mask
+            .into_iter()
+            .filter_map(|attribute| {
+                Some(match attribute {
+                    Attribute::SupportedAttributes => {
+                        AttributeValue::SupportedAttributes(SUPPORTED_ATTRIBUTES.into())
+                    }
+                    Attribute::Type => AttributeValue::Type(match inode.content {
+                        Content::Directory { .. } => FileType::Directory,
+                        Content::File { .. } => FileType::Regular,
+                        Content::Symlink(_) => FileType::Symlink,
+                    }),
+                    Attribute::FileHandleExpireType => AttributeValue::FileHandleExpireType(0),
+                    Attribute::Change => AttributeValue::Change(inode.change()),
+                    Attribute::Size => AttributeValue::Size(inode.size()),
+                    Attribute::LinkSupport => AttributeValue::LinkSupport(true),
+                    Attribute::SymlinkSupport => AttributeValue::SymlinkSupport(true),
+                    Attribute::NamedAttributes => AttributeValue::NamedAttributes(false),
+                    Attribute::FileSystemId => AttributeValue::FileSystemId(node.file_system_id()),
+                    Attribute::UniqueHandles => AttributeValue::UniqueHandles(true),
+                    Attribute::LeaseTime => AttributeValue::LeaseTime(90),
+                    Attribute::AclSupport => AttributeValue::AclSupport(AclSupportFlags::empty()),
+                    Attribute::CanSetTime => AttributeValue::CanSetTime(true),
+                    Attribute::CaseInsensitive => AttributeValue::CaseInsensitive(false),
+                    Attribute::CasePreserving => AttributeValue::CasePreserving(true),
+                    Attribute::ChownRestricted => AttributeValue::ChownRestricted(true),
+                    Attribute::FileHandle => AttributeValue::FileHandle(self.file_handle(node)),
+                    Attribute::FileId => AttributeValue::FileId(node.file_id()),
+                    Attribute::Homogeneous => AttributeValue::Homogeneous(true),
+                    Attribute::MaxFileSize => AttributeValue::MaxFileSize(i64::MAX as u64),
+                    Attribute::MaxName => AttributeValue::MaxName(MAX_NAME_LENGTH as u32),
+                    Attribute::MaxRead => AttributeValue::MaxRead(MAX_TRANSFER_SIZE as u64),
+                    Attribute::MaxWrite => AttributeValue::MaxWrite(MAX_TRANSFER_SIZE as u64),
+                    Attribute::Mode => AttributeValue::Mode(inode.mode),
+                    Attribute::NoTruncate => AttributeValue::NoTruncate(true),
+                    Attribute::NumberOfLinks => {
+                        AttributeValue::NumberOfLinks(state.number_of_links(node, &inode))
+                    }
+                    Attribute::Owner => AttributeValue::Owner(inode.uid.to_string().into()),
+                    Attribute::OwnerGroup => {
+                        AttributeValue::OwnerGroup(inode.gid.to_string().into())
+                    }
+                    Attribute::SpaceAvailable => AttributeValue::SpaceAvailable(space?.1),
+                    Attribute::SpaceFree => AttributeValue::SpaceFree(space?.1),
+                    Attribute::SpaceTotal => AttributeValue::SpaceTotal(space?.0),
+                    Attribute::SpaceUsed => {
+                        AttributeValue::SpaceUsed(state.space_used(node, &inode))
+                    }
+                    Attribute::TimeAccess => AttributeValue::TimeAccess(inode.access_time.into()),
+                    Attribute::TimeDelta => AttributeValue::TimeDelta(Time {
+                        seconds: 0,
+                        nanoseconds: 1,
+                    }),
+                    Attribute::TimeMetadata => {
+                        AttributeValue::TimeMetadata(inode.change_time.into())
+                    }
+                    Attribute::TimeModify => AttributeValue::TimeModify(inode.modify_time.into()),
+                    Attribute::MountedOnFileId => AttributeValue::MountedOnFileId(node.file_id()),
+                    Attribute::SupportedAttributesExclusiveCreate => {
+                        AttributeValue::SupportedAttributesExclusiveCreate(
+                            SETTABLE_ATTRIBUTES.into(),
+                        )
+                    }
+                    _ => return None,
+                })
+            })
+            .collect())
+    }
+}
+
+impl Drive for Store {
+    fn root_file_handle<'a>(&self) -> FileHandle<'a> {
+        self.file_handle(Node::Live(ROOT_ID))
+    }
+
+    fn access(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+        flags: AccessFlags,
+    ) -> Result<AccessResult, Error> {
+        let node = self.node(file_handle)?;
+        let state = self.state.read().unwrap();
+        let inode = state.resolve(node)?;
+        let mut allowed =
+            credentials.access(inode.mode, inode.uid, inode.gid, inode.is_directory());
+        if !matches!(node, Node::Live(_)) {
+            allowed.remove(AccessFlags::MODIFY | AccessFlags::EXTEND | AccessFlags::DELETE);
+        }
+        Ok(AccessResult {
+            supported: flags,
+            access: flags & allowed,
+        })
+    }
+
+    fn lookup<'a>(
+        &self,
+        credentials: &Credentials,
+        directory: &FileHandle<'_>,
+        name: &str,
+    ) -> Result<FileHandle<'a>, Error> {
+        let directory = self.node(directory)?;
+        let state = self.state.read().unwrap();
+        let inode = state.resolve(directory)?;
+        let entries = entries(credentials, &inode, name, Permissions::EXECUTE)?;
+        if directory == Node::Live(ROOT_ID) && name == SNAPSHOTS_NAME {
+            return Ok(self.file_handle(Node::Snapshots));
+        }
+        let id = entries.get(name).ok_or(Error::NOENT)?;
+        Ok(self.file_handle(directory.child(*id)))
+    }
+
+    fn lookup_parent<'a>(
+        &self,
+        credentials: &Credentials,
+        directory: &FileHandle<'_>,
+    ) -> Result<FileHandle<'a>, Error> {
+        let node = self.node(directory)?;
+        let state = self.state.read().unwrap();
+        let inode = state.resolve(node)?;
+        let Content::Directory { parent, .. } = inode.content else {
+            return Err(match inode.content {
+                Content::Symlink(_) => Error::SYMLINK,
+                _ => Error::NOTDIR,
+            });
+        };
+        check(credentials, &*inode, Permissions::EXECUTE)?;
+        let parent = node.parent(parent).ok_or(Error::NOENT)?;
+        Ok(self.file_handle(parent))
+    }
+
+    fn get_attributes<'a>(
+        &self,
+        _credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+        mask: AttributeMask<'_>,
+    ) -> Result<Vec<AttributeValue<'a>>, Error> {
+        let node = self.node(file_handle)?;
+        let state = self.state.read().unwrap();
+        self.attributes(&state, node, mask)
+    }
+
+    fn read<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+        args: ReadArgs,
+    ) -> Result<ReadResult<'a>, Error> {
+        let node = self.node(file_handle)?;
+        let state = self.state.read().unwrap();
+        let inode = state.resolve(node)?;
+        let Content::File { size, chunks } = &inode.content else {
+            return Err(match inode.content {
+                Content::Directory { .. } => Error::ISDIR,
+                _ => Error::SYMLINK,
+            });
+        };
+        check_transfer(credentials, &*inode, Permissions::READ)?;
+        let start = args.offset.min(*size);
+        let end = args
+            .offset
+            .saturating_add(args.count.min(MAX_TRANSFER_SIZE) as u64)
+            .min(*size);
+        let mut data = vec![0; (end - start) as usize];
+        if let Some(id) = node.staged(&state) {
+            let file = File::open(self.staging_path(id)).map_err(error)?;
+            file.read_exact_at(&mut data, start).map_err(error)?;
+        } else {
+            let mut offset = 0;
+            for chunk in chunks {
+                let chunk_end = offset + chunk.length as u64;
+                if chunk_end > start && offset < end {
+                    let content = self.read_chunk(&chunk.hash)?;
+                    let from = start.max(offset);
+                    let to = end.min(chunk_end);
+                    data[(from - start) as usize..(to - start) as usize].copy_from_slice(
+                        &content[(from - offset) as usize..(to - offset) as usize],
+                    );
+                }
+                offset = chunk_end;
+            }
+        }
+        Ok(ReadResult {
+            eof: end >= *size,
+            data: data.into(),
+        })
+    }
+
+    fn read_directory<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+        args: ReadDirectoryArgs<'_>,
+    ) -> Result<ReadDirectoryResult<'a>, Error> {
+        let node = self.node(file_handle)?;
+        let state = self.state.read().unwrap();
+        let inode = state.resolve(node)?;
+        let Content::Directory { entries, .. } = &inode.content else {
+            return Err(Error::NOTDIR);
+        };
+        check(credentials, &*inode, Permissions::READ)?;
+        let mut list = Vec::new();
+        let mut size = 0;
+        let mut eof = true;
+        for (cookie, (name, child)) in (FIRST_COOKIE..).zip(entries) {
+            if cookie <= args.cookie {
+                continue;
+            }
+            let attributes =
+                self.attributes(&state, node.child(*child), args.attributes.clone())?;
+            size += 32 + name.len() + 16 * attributes.len();
+            if size > args.max_count as usize {
+                eof = false;
+                break;
+            }
+            list.push(Entry {
+                cookie,
+                name: name.clone().into(),
+                attributes,
+            });
+        }
+        if list.is_empty() && !eof {
+            return Err(Error::TOOSMALL);
+        }
+        Ok(ReadDirectoryResult {
+            cookie_verf: [0; 8],
+            directory_list: DirectoryList { entries: list, eof },
+        })
+    }
+
+    fn read_link<'a>(
+        &self,
+        _credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+    ) -> Result<Cow<'a, str>, Error> {
+        let node = self.node(file_handle)?;
+        let state = self.state.read().unwrap();
+        match &state.resolve(node)?.content {
+            Content::Symlink(target) => Ok(target.clone().into()),
+            Content::Directory { .. } => Err(Error::ISDIR),
+            Content::File { .. } => Err(Error::INVAL),
+        }
+    }
+
+    fn open<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+        args: OpenArgs<'_>,
+    ) -> Result<(FileHandle<'a>, OpenResult<'a>), Error> {
+        let directory = match self.node(file_handle)? {
+            Node::Live(id) => id,
+            node => return self.open_snapshot(credentials, node, args),
+        };
+        let mut state = self.state.write().unwrap();
+        let (id, change_info, attributes, created) = match args.claim {
+            OpenClaim::Null(name) => {
+                let existing =
+                    self.child(&state, credentials, directory, name, Permissions::EXECUTE)?;
+                let before = state.inode(directory)?.change();
+                let (id, attributes) = match args.how {
+                    OpenFlag::NoCreate => (existing.ok_or(Error::NOENT)?, AttributeMask::new()),
+                    OpenFlag::Create(how) => {
+                        self.create_file(&mut state, credentials, directory, name, existing, how)?
+                    }
+                };
+                let change_info = ChangeInfo {
+                    atomic: true,
+                    before,
+                    after: state.inode(directory)?.change(),
+                };
+                (id, change_info, attributes, existing.is_none())
+            }
+            OpenClaim::FileHandle => {
+                let change_info = ChangeInfo {
+                    atomic: false,
+                    before: 0,
+                    after: 0,
+                };
+                (directory, change_info, AttributeMask::new(), false)
+            }
+            _ => return Err(Error::NOTSUPP),
+        };
+        let inode = state.inode(id)?;
+        match inode.content {
+            Content::Directory { .. } => return Err(Error::ISDIR),
+            Content::Symlink(_) => return Err(Error::SYMLINK),
+            Content::File { .. } => {}
+        }
+        // Whoever creates a file may open it as asked, whatever mode it was created with.
+        if !created {
+            let mut permissions = Permissions::empty();
+            if args.share_access.contains(ShareAccessFlags::READ) {
+                permissions |= Permissions::READ;
+            }
+            if args.share_access.contains(ShareAccessFlags::WRITE) {
+                permissions |= Permissions::WRITE;
+            }
+            check(credentials, inode, permissions)?;
+        }
+        let mut other = [0; 12];
+        other[..8].copy_from_slice(&id.to_be_bytes());
+        Ok((
+            self.file_handle(Node::Live(id)),
+            OpenResult {
+                state_id: StateId {
+                    sequence_id: 1,
+                    other,
+                },
+                change_info,
+                flags: OpenResultFlags::empty(),
+                attributes,
+                delegation: OpenDelegation::None,
+            },
+        ))
+    }
+
+    /// Chunks what was written to the file, since a client that closes it is done writing.
+    fn close(
+        &self,
+        _credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+    ) -> Result<(), Error> {
+        let Node::Live(id) = self.node(file_handle)? else {
+            return Ok(());
+        };
+        let mut state = self.state.write().unwrap();
+        self.seal(&mut state, id)
+    }
+
+    fn create<'a>(
+        &self,
+        credentials: &Credentials,
+        directory: &FileHandle<'_>,
+        args: CreateArgs<'_>,
+    ) -> Result<(FileHandle<'a>, CreateResult<'a>), Error> {
+        let directory = self.id(directory)?;
+        let mut state = self.state.write().unwrap();
+        let permissions = Permissions::WRITE | Permissions::EXECUTE;
+        if self
+            .child(&state, credentials, directory, args.name, permissions)?
+            .is_some()
+        {
+            return Err(Error::EXIST);
+        }
+        let (content, mode) = match args.object_type {
+            CreateType::Directory => {
+                let content = Content::Directory {
+                    parent: directory,
+                    entries: BTreeMap::new(),
+                };
+                (content, 0o755)
+            }
+            CreateType::Link(ref target) => (Content::Symlink(target.to_string()), 0o777),
+            _ => return Err(Error::BADTYPE),
+        };
+        let inode = Inode::new(content, mode, credentials.uid, credentials.gid);
+        let is_symlink = matches!(inode.content, Content::Symlink(_));
+        if !is_symlink {
+            check_attributes(credentials, &inode, &args.attributes, true)?;
+        }
+        let before = state.inode(directory)?.change();
+        let id = self.insert(&mut state, directory, args.name, inode)?;
+        // Attributes would be applied to the target of a symlink, so they are ignored.
+        let attributes = match is_symlink {
+            true => AttributeMask::new(),
+            false => self.apply_attributes(&mut state, id, &args.attributes)?,
+        };
+        Ok((
+            self.file_handle(Node::Live(id)),
+            CreateResult {
+                change_info: ChangeInfo {
+                    atomic: true,
+                    before,
+                    after: state.inode(directory)?.change(),
+                },
+                attributes,
+            },
+        ))
+    }
+
+    fn link(
+        &self,
+        credentials: &Credentials,
+        source: &FileHandle<'_>,
+        directory: &FileHandle<'_>,
+        name: &str,
+    ) -> Result<ChangeInfo, Error> {
+        let source = self.id(source)?;
+        let directory = self.id(directory)?;
+        let mut state = self.state.write().unwrap();
+        if state.inode(source)?.is_directory() {
+            return Err(Error::ISDIR);
+        }
+        let permissions = Permissions::WRITE | Permissions::EXECUTE;
+        if self
+            .child(&state, credentials, directory, name, permissions)?
+            .is_some()
+        {
+            return Err(Error::EXIST);
+        }
+        let before = state.inode(directory)?.change();
+        self.add_entry(&mut state, directory, name, source)?;
+        let inode = state.inode_mut(source)?;
+        inode.links += 1;
+        inode.touch();
+        self.records.save(source, inode).map_err(error)?;
+        Ok(ChangeInfo {
+            atomic: true,
+            before,
+            after: state.inode(directory)?.change(),
+        })
+    }
+
+    fn remove(
+        &self,
+        credentials: &Credentials,
+        directory: &FileHandle<'_>,
+        name: &str,
+    ) -> Result<ChangeInfo, Error> {
+        let directory = self.id(directory)?;
+        let mut state = self.state.write().unwrap();
+        let permissions = Permissions::WRITE | Permissions::EXECUTE;
+        let id = self
+            .child(&state, credentials, directory, name, permissions)?
+            .ok_or(Error::NOENT)?;
+        let inode = state.inode(id)?;
+        check_sticky(credentials, state.inode(directory)?, inode)?;
+        if matches!(&inode.content, Content::Directory { entries, .. } if !entries.is_empty()) {
+            return Err(Error::NOTEMPTY);
+        }
+        let before = state.inode(directory)?.change();
+        self.remove_entry(&mut state, directory, name)?;
+        self.unlink(&mut state, id)?;
+        Ok(ChangeInfo {
+            atomic: true,
+            before,
+            after: state.inode(directory)?.change(),
+        })
+    }
+
+    fn rename(
+        &self,
+        credentials: &Credentials,
+        source_directory: &FileHandle<'_>,
+        target_directory: &FileHandle<'_>,
+        args: RenameArgs<'_>,
+    ) -> Result<RenameResult, Error> {
+        let source_directory = self.id(source_directory)?;
+        let target_directory = self.id(target_directory)?;
+        let mut state = self.state.write().unwrap();
+        let permissions = Permissions::WRITE | Permissions::EXECUTE;
+        let id = self
+            .child(
+                &state,
+                credentials,
+                source_directory,
+                args.old_name,
+                permissions,
+            )?
+            .ok_or(Error::NOENT)?;
+        let existing = self.child(
+            &state,
+            credentials,
+            target_directory,
+            args.new_name,
+            permissions,
+        )?;
+        let inode = state.inode(id)?;
+        check_sticky(credentials, state.inode(source_directory)?, inode)?;
+        let source_before = state.inode(source_directory)?.change();
+        let target_before = state.inode(target_directory)?.change();
+        let unchanged = |state: &RwLockWriteGuard<State>| {
+            Ok(RenameResult {
+                source_change_info: ChangeInfo {
+                    atomic: true,
+                    before: source_before,
+                    after: state.inode(source_directory)?.change(),
+                },
+                target_change_info: ChangeInfo {
+                    atomic: true,
+                    before: target_before,
+                    after: state.inode(target_directory)?.change(),
+                },
+            })
+        };
+        // Renaming a file onto another link to it does nothing.
+        if existing == Some(id) {
+            return unchanged(&state);
+        }
+        if inode.is_directory() {
+            let mut ancestor = target_directory;
+            while ancestor != ROOT_ID {
+                if ancestor == id {
+                    return Err(Error::INVAL);
+                }
+                ancestor = match state.inode(ancestor)?.content {
+                    Content::Directory { parent, .. } => parent,
+                    _ => return Err(Error::NOTDIR),
+                };
+            }
+        }
+        if let Some(existing) = existing {
+            let target = state.inode(existing)?;
+            match (&inode.content, &target.content) {
+                (Content::Directory { .. }, Content::Directory { entries, .. })
+                    if entries.is_empty() => {}
+                (_, Content::Directory { .. }) => return Err(Error::EXIST),
+                (Content::Directory { .. }, _) => return Err(Error::NOTDIR),
+                _ => {}
+            }
+            check_sticky(credentials, state.inode(target_directory)?, target)?;
+        }
+        // The new entry comes first, so that a crash leaves the file in both directories
+        // rather than in neither.
+        self.add_entry(&mut state, target_directory, args.new_name, id)?;
+        let inode = state.inode_mut(id)?;
+        if let Content::Directory { parent, .. } = &mut inode.content {
+            *parent = target_directory;
+        }
+        inode.touch();
+        self.records.save(id, inode).map_err(error)?;
+        self.remove_entry(&mut state, source_directory, args.old_name)?;
+        if let Some(existing) = existing {
+            self.unlink(&mut state, existing)?;
+        }
+        unchanged(&state)
+    }
+
+    fn set_attributes<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+        args: SetAttributesArgs<'_>,
+    ) -> Result<AttributeMask<'a>, Error> {
+        let id = self.id(file_handle)?;
+        let mut state = self.state.write().unwrap();
+        let inode = state.inode(id)?;
+        if matches!(inode.content, Content::Symlink(_)) {
+            return Err(Error::SYMLINK);
+        }
+        check_attributes(credentials, inode, &args.attributes, false)?;
+        self.apply_attributes(&mut state, id, &args.attributes)
+    }
+
+    fn write(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+        args: WriteArgs<'_>,
+    ) -> Result<WriteResult, Error> {
+        let id = self.id(file_handle)?;
+        let end = args
+            .offset
+            .checked_add(args.data.len() as u64)
+            .filter(|end| *end <= i64::MAX as u64)
+            .ok_or(Error::FBIG)?;
+        let mut state = self.state.write().unwrap();
+        let inode = state.inode(id)?;
+        match inode.content {
+            Content::Directory { .. } => return Err(Error::ISDIR),
+            Content::Symlink(_) => return Err(Error::SYMLINK),
+            Content::File { .. } => {}
+        }
+        check_transfer(credentials, inode, Permissions::WRITE)?;
+        self.check_quota(&state, end.saturating_sub(inode.size()))?;
+        self.unseal(&mut state, id, false)?;
+        let file = OpenOptions::new()
+            .write(true)
+            .open(self.staging_path(id))
+            .map_err(error)?;
+        file.write_all_at(&args.data, args.offset).map_err(error)?;
+        match args.stable {
+            StableHow::Unstable => Ok(()),
+            StableHow::DataSync => file.sync_data(),
+            StableHow::FileSync => file.sync_all(),
+        }
+        .map_err(error)?;
+        let inode = state.inode_mut(id)?;
+        if let Content::File { size, .. } = &mut inode.content {
+            *size = (*size).max(end);
+        }
+        inode.modify();
+        Ok(WriteResult {
+            count: args.data.len() as u32,
+            committed: args.stable,
+            verifier: self.verifier,
+        })
+    }
+
+    /// Staged files are as safe as chunks, so they are only synced here and chunked once the
+    /// file is closed, rather than again after every batch of writes.
+    fn commit(
+        &self,
+        _credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+        _args: CommitArgs,
+    ) -> Result<Verifier, Error> {
+        let node = self.node(file_handle)?;
+        let state = self.state.read().unwrap();
+        state.resolve(node)?;
+        if let Some(id) = node.staged(&state) {
+            File::open(self.staging_path(id))
+                .and_then(|file| file.sync_all())
+                .map_err(error)?;
+        }
+        Ok(self.verifier)
+    }
+}
+
+fn timestamp(value: SetTime) -> Timestamp {
+    match value {
+        SetTime::ServerTime => Timestamp::now(),
+        SetTime::ClientTime(time) => time.into(),
+    }
+}
+
+/// The store's own files are not what the client asked about, so their errors are only logged.
+fn error(error: io::Error) -> Error {
+    tracing::error!("Store I/O failed: {error}");
+    match super::error(error) {
+        Error::NOSPC => Error::NOSPC,
+        _ => Error::IO,
+    }
+}
+
+impl From<&Inode> for Ownership {
+    fn from(inode: &Inode) -> Self {
+        Self {
+            mode: inode.mode,
+            uid: inode.uid,
+            gid: inode.gid,
+            is_directory: inode.is_directory(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("lararium-{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn superuser() -> Credentials {
+        Credentials {
+            uid: 0,
+            gid: 0,
+            gids: vec![],
+        }
+    }
+
+    fn random(
+        seed: u64,
+        length: usize,
+    ) -> Vec<u8> {
+        let mut state = seed;
+        (0..length)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn state_id() -> StateId {
+        StateId {
+            sequence_id: 0,
+            other: [0; 12],
+        }
+    }
+
+    fn create<'a>(
+        store: &Store,
+        directory: &FileHandle<'_>,
+        name: &str,
+    ) -> Result<FileHandle<'a>, Error> {
+        let args = OpenArgs {
+            sequence_id: 0,
+            share_access: ShareAccessFlags::READ | ShareAccessFlags::WRITE,
+            share_deny: ShareDenyFlags::empty(),
+            owner: OpenOwner::from(StateOwner {
+                client_id: 1,
+                owner: (&[1]).into(),
+            }),
+            how: OpenFlag::Create(OpenFlagCreate::Guarded { attributes: vec![] }),
+            claim: OpenClaim::Null(name),
+        };
+        let (file_handle, _) = store.open(&superuser(), directory, args)?;
+        Ok(file_handle)
+    }
+
+    fn write(
+        store: &Store,
+        file_handle: &FileHandle<'_>,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        for (i, data) in data.chunks(MAX_TRANSFER_SIZE as usize).enumerate() {
+            let args = WriteArgs {
+                state_id: state_id(),
+                offset: offset + (i * MAX_TRANSFER_SIZE as usize) as u64,
+                stable: StableHow::Unstable,
+                data: data.into(),
+            };
+            store.write(&superuser(), file_handle, args)?;
+        }
+        Ok(())
+    }
+
+    fn read(
+        store: &Store,
+        file_handle: &FileHandle<'_>,
+    ) -> Result<Vec<u8>, Error> {
+        let mut data = Vec::new();
+        loop {
+            let args = ReadArgs {
+                state_id: state_id(),
+                offset: data.len() as u64,
+                count: MAX_TRANSFER_SIZE,
+            };
+            let result = store.read(&superuser(), file_handle, args)?;
+            data.extend_from_slice(&result.data);
+            if result.eof {
+                return Ok(data);
+            }
+        }
+    }
+
+    fn space_used(
+        store: &Store,
+        file_handle: &FileHandle<'_>,
+    ) -> u64 {
+        let attributes = store
+            .get_attributes(
+                &superuser(),
+                file_handle,
+                [Attribute::SpaceUsed].into_iter().collect(),
+            )
+            .unwrap();
+        match attributes[..] {
+            [AttributeValue::SpaceUsed(space_used)] => space_used,
+            _ => panic!("no space used in {attributes:?}"),
+        }
+    }
+
+    #[test]
+    fn test_identical_files_share_chunks() {
+        let directory = TempDir::new("store-dedup");
+        let store = Store::open(&directory.0, None).unwrap();
+        let root = store.root_file_handle();
+        let photo = random(1, 3 * 1024 * 1024);
+        let mut edited = photo.clone();
+        edited.splice(1000..1000, *b"a caption");
+        let mut file_handles = Vec::new();
+        for (name, data) in [("a.jpg", &photo), ("b.jpg", &photo), ("c.jpg", &edited)] {
+            let file_handle = create(&store, &root, name).unwrap();
+            write(&store, &file_handle, 0, data).unwrap();
+            store.close(&superuser(), &file_handle).unwrap();
+            file_handles.push(file_handle);
+        }
+        assert_eq!(read(&store, &file_handles[1]).unwrap(), photo);
+        assert_eq!(read(&store, &file_handles[2]).unwrap(), edited);
+        let stored = store.state.read().unwrap().stored;
+        assert!(stored < photo.len() as u64 + 512 * 1024);
+        let shares = file_handles
+            .iter()
+            .map(|file_handle| space_used(&store, file_handle))
+            .collect::<Vec<_>>();
+        assert_eq!(shares[0], shares[1]);
+        assert!(shares[0] < photo.len() as u64 / 2);
+        let total = shares.iter().sum::<u64>();
+        assert!((stored..stored + 100).contains(&total));
+
+        store.remove(&superuser(), &root, "a.jpg").unwrap();
+        store.remove(&superuser(), &root, "c.jpg").unwrap();
+        assert_eq!(space_used(&store, &file_handles[1]), photo.len() as u64);
+        store.remove(&superuser(), &root, "b.jpg").unwrap();
+        assert_eq!(store.state.read().unwrap().stored, 0);
+        assert_eq!(
+            fs::read_dir(directory.0.join("chunks"))
+                .unwrap()
+                .map(|directory| fs::read_dir(directory.unwrap().path()).unwrap().count())
+                .sum::<usize>(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_reopen_chunks_staged_files() {
+        let directory = TempDir::new("store-reopen");
+        let data = random(2, 512 * 1024);
+        let file_handle = {
+            let store = Store::open(&directory.0, None).unwrap();
+            let root = store.root_file_handle();
+            let (docs, _) = store
+                .create(
+                    &superuser(),
+                    &root,
+                    CreateArgs {
+                        object_type: CreateType::Directory,
+                        name: "docs",
+                        attributes: vec![AttributeValue::Mode(0o700)],
+                    },
+                )
+                .unwrap();
+            let file_handle = create(&store, &docs, "notes").unwrap();
+            write(&store, &file_handle, 0, &data).unwrap();
+            file_handle
+        };
+        let store = Store::open(&directory.0, None).unwrap();
+        assert_eq!(
+            fs::read_dir(directory.0.join("staging")).unwrap().count(),
+            0
+        );
+        let docs = store
+            .lookup(&superuser(), &store.root_file_handle(), "docs")
+            .unwrap();
+        assert_eq!(
+            store.lookup(&superuser(), &docs, "notes"),
+            Ok(file_handle.clone())
+        );
+        assert_eq!(read(&store, &file_handle).unwrap(), data);
+        assert_eq!(
+            store.lookup_parent(&superuser(), &docs),
+            Ok(store.root_file_handle())
+        );
+    }
+
+    #[test]
+    fn test_quota() {
+        let directory = TempDir::new("store-quota");
+        let store = Store::open(&directory.0, Some(512 * 1024)).unwrap();
+        let root = store.root_file_handle();
+        let file_handle = create(&store, &root, "video").unwrap();
+        write(&store, &file_handle, 0, &random(3, 200 * 1024)).unwrap();
+        assert_eq!(
+            write(&store, &file_handle, 200 * 1024, &random(4, 400 * 1024)),
+            Err(Error::DQUOT)
+        );
+        store.close(&superuser(), &file_handle).unwrap();
+        let copy = create(&store, &root, "copy").unwrap();
+        write(&store, &copy, 0, &read(&store, &file_handle).unwrap()).unwrap();
+        store.close(&superuser(), &copy).unwrap();
+        let attributes = store
+            .get_attributes(
+                &superuser(),
+                &root,
+                [Attribute::SpaceAvailable, Attribute::SpaceTotal]
+                    .into_iter()
+                    .collect(),
+            )
+            .unwrap();
+        assert_eq!(
+            attributes,
+            vec![
+                AttributeValue::SpaceAvailable(312 * 1024),
+                AttributeValue::SpaceTotal(512 * 1024),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_bounds() {
+        let directory = TempDir::new("store-write-bounds");
+        let store = Store::open(&directory.0, None).unwrap();
+        let root = store.root_file_handle();
+        let file_handle = create(&store, &root, "file").unwrap();
+        for offset in [u64::MAX, i64::MAX as u64] {
+            assert_eq!(
+                write(&store, &file_handle, offset, b"data"),
+                Err(Error::FBIG)
+            );
+        }
+        write(&store, &file_handle, 0, b"data").unwrap();
+        assert_eq!(read(&store, &file_handle).unwrap(), b"data");
+    }
+
+    #[test]
+    fn test_scrub_finds_corrupt_chunks() {
+        let directory = TempDir::new("store-scrub");
+        let store = Store::open(&directory.0, None).unwrap();
+        let root = store.root_file_handle();
+        let file_handle = create(&store, &root, "file").unwrap();
+        write(&store, &file_handle, 0, &random(5, 100 * 1024)).unwrap();
+        store.close(&superuser(), &file_handle).unwrap();
+        let report = store.scrub();
+        assert!(report.checked > 0);
+        assert!(report.corrupt.is_empty());
+        let hash = store.state.read().unwrap().inodes[&2].chunks()[0].hash;
+        fs::write(store.chunk_path(&hash), b"rot").unwrap();
+        let report = store.scrub();
+        assert_eq!(report.corrupt, vec![hash]);
+        assert_eq!(report.damaged, vec![2]);
+        assert_eq!(read(&store, &file_handle), Err(Error::IO));
+    }
+
+    #[test]
+    fn test_link_and_rename() {
+        let directory = TempDir::new("store-rename");
+        let store = Store::open(&directory.0, None).unwrap();
+        let root = store.root_file_handle();
+        let (a, _) = store
+            .create(
+                &superuser(),
+                &root,
+                CreateArgs {
+                    object_type: CreateType::Directory,
+                    name: "a",
+                    attributes: vec![],
+                },
+            )
+            .unwrap();
+        let file_handle = create(&store, &a, "file").unwrap();
+        write(&store, &file_handle, 0, b"data").unwrap();
+        store
+            .link(&superuser(), &file_handle, &root, "link")
+            .unwrap();
+        let rename = |source: &FileHandle, target: &FileHandle, old_name, new_name| {
+            store.rename(
+                &superuser(),
+                source,
+                target,
+                RenameArgs { old_name, new_name },
+            )
+        };
+        assert_eq!(rename(&root, &a, "a", "b").map(|_| ()), Err(Error::INVAL));
+        rename(&a, &root, "file", "link").unwrap();
+        assert_eq!(
+            store.lookup(&superuser(), &a, "file"),
+            Ok(file_handle.clone())
+        );
+        rename(&root, &root, "a", "b").unwrap();
+        let b = store.lookup(&superuser(), &root, "b").unwrap();
+        rename(&b, &root, "file", "moved").unwrap();
+        assert_eq!(store.lookup(&superuser(), &b, "file"), Err(Error::NOENT));
+        assert_eq!(
+            store.get_attributes(
+                &superuser(),
+                &file_handle,
+                [Attribute::NumberOfLinks].into_iter().collect()
+            ),
+            Ok(vec![AttributeValue::NumberOfLinks(2)])
+        );
+        store.remove(&superuser(), &root, "link").unwrap();
+        assert_eq!(read(&store, &file_handle).unwrap(), b"data");
+        store.remove(&superuser(), &root, "moved").unwrap();
+        assert_eq!(read(&store, &file_handle), Err(Error::STALE));
+        assert_eq!(store.remove(&superuser(), &root, "b").map(|_| ()), Ok(()));
+    }
+
+    #[test]
+    fn test_snapshots() {
+        let directory = TempDir::new("store-snapshots");
+        let photo = random(6, 300 * 1024);
+        let (name, file_handle) = {
+            let store = Store::open(&directory.0, None).unwrap();
+            let root = store.root_file_handle();
+            let file_handle = create(&store, &root, "photo.jpg").unwrap();
+            write(&store, &file_handle, 0, &photo).unwrap();
+            let name = store.snapshot().unwrap();
+            assert_eq!(store.snapshots(), vec![name.clone()]);
+            store.remove(&superuser(), &root, "photo.jpg").unwrap();
+            let list = |file_handle| {
+                let args = ReadDirectoryArgs {
+                    cookie: 0,
+                    cookie_verifier: [0; 8],
+                    dir_count: 1024,
+                    max_count: 1024,
+                    attributes: AttributeMask::new(),
+                };
+                store
+                    .read_directory(&superuser(), file_handle, args)
+                    .unwrap()
+                    .directory_list
+                    .entries
+                    .into_iter()
+                    .map(|entry| entry.name.to_string())
+                    .collect::<Vec<_>>()
+            };
+            assert!(list(&root).is_empty());
+            let snapshots = store.lookup(&superuser(), &root, ".snapshots").unwrap();
+            assert_eq!(list(&snapshots), vec![name.clone()]);
+            let snapshot = store.lookup(&superuser(), &snapshots, &name).unwrap();
+            assert_eq!(
+                store.lookup_parent(&superuser(), &snapshot),
+                Ok(snapshots.clone())
+            );
+            let file_handle = store.lookup(&superuser(), &snapshot, "photo.jpg").unwrap();
+            assert_eq!(read(&store, &file_handle).unwrap(), photo);
+            assert_eq!(write(&store, &file_handle, 0, b"edit"), Err(Error::ROFS));
+            assert_eq!(
+                store.remove(&superuser(), &snapshot, "photo.jpg"),
+                Err(Error::ROFS)
+            );
+            assert_eq!(
+                create(&store, &root, ".snapshots").map(|_| ()),
+                Err(Error::Access)
+            );
+            (name, file_handle)
+        };
+        let store = Store::open(&directory.0, None).unwrap();
+        assert_eq!(read(&store, &file_handle).unwrap(), photo);
+        store.remove_snapshot(&name).unwrap();
+        assert_eq!(read(&store, &file_handle), Err(Error::STALE));
+        assert_eq!(store.state.read().unwrap().stored, 0);
+        let chunk_files = fs::read_dir(directory.0.join("chunks"))
+            .unwrap()
+            .flat_map(|prefix| fs::read_dir(prefix.unwrap().path()).unwrap())
+            .count();
+        assert_eq!(chunk_files, 0);
+        assert_eq!(
+            store.remove_snapshot(&name).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+}
@@ -0,0 +1,66 @@
+use super::index::Inode;
+use super::inode::Node;
+use super::{State, Store};
+use nfs::*;
+use nix::sys::statvfs::statvfs;
+
+impl Store {
+    /// Fails with DQUOT when growing files by `growth` bytes would exceed the quota.
+    pub(super) fn check_quota(
+        &self,
+        state: &State,
+        growth: u64,
+    ) -> Result<(), Error> {
+        match self.quota {
+            Some(quota) if growth > 0 && state.used() + growth > quota => Err(Error::DQUOT),
+            _ => Ok(()),
+        }
+    }
+
+    /// The total and available bytes, which are bounded by the quota as well as the disk.
+    pub(super) fn space(
+        &self,
+        state: &State,
+    ) -> Option<(u64, u64)> {
+        let statistics = statvfs(&self.root).ok()?;
+        let total = statistics.blocks() * statistics.fragment_size();
+        let available = statistics.blocks_available() * statistics.fragment_size();
+        Some(match self.quota {
+            Some(quota) => (quota, quota.saturating_sub(state.used()).min(available)),
+            None => (total, available),
+        })
+    }
+}
+
+impl State {
+    /// The bytes that count against the quota: the chunks and the files that are staged.
+    pub fn used(&self) -> u64 {
+        let staged = self
+            .staged
+            .iter()
+            .filter_map(|id| self.inodes.get(id))
+            .map(|inode| inode.size())
+            .sum::<u64>();
+        self.stored + staged
+    }
+
+    /// The share of the stored bytes that a file accounts for, where chunks used by several
+    /// files are split between them. Summed over all files, this is what the store takes up.
+    pub fn space_used(
+        &self,
+        node: Node,
+        inode: &Inode,
+    ) -> u64 {
+        if node.staged(self).is_some() {
+            return inode.size();
+        }
+        inode
+            .chunks()
+            .iter()
+            .filter_map(|chunk| {
+                let stored = self.chunks.get(&chunk.hash)?;
+                Some((stored.length as u64).div_ceil(stored.references))
+            })
+            .sum()
+    }
+}
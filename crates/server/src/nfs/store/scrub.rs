@@ -0,0 +1,111 @@
+use super::chunk::hex;
+use super::index::Hash;
+use super::{Store, STAGING_TIMEOUT};
+use std::fs;
+use std::io;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// What a scrub of the store found.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ScrubReport {
+    pub checked: usize,
+    /// Chunks whose content no longer matches their hash.
+    pub corrupt: Vec<Hash>,
+    pub missing: Vec<Hash>,
+    /// The files that have corrupt or missing chunks.
+    pub damaged: Vec<u64>,
+}
+
+impl Store {
+    /// Checks every chunk against its hash, and chunks files that were left staged.
+    pub fn scrub(&self) -> ScrubReport {
+        {
+            let mut state = self.state.write().unwrap();
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            let idle = state
+                .staged
+                .iter()
+                .filter(|id| {
+                    let modified = state.inodes[*id].modify_time.seconds;
+                    now.as_secs() as i64 - modified >= STAGING_TIMEOUT.as_secs() as i64
+                })
+                .copied()
+                .collect::<Vec<_>>();
+            for id in idle {
+                if let Err(error) = self.seal(&mut state, id) {
+                    tracing::error!("Failed to chunk staged file {id}: {error:?}");
+                }
+            }
+        }
+        let hashes = self
+            .state
+            .read()
+            .unwrap()
+            .chunks
+            .keys()
+            .copied()
+            .collect::<Vec<_>>();
+        let mut report = ScrubReport::default();
+        for hash in hashes {
+            match fs::read(self.chunk_path(&hash)) {
+                Ok(data) if openssl::sha::sha256(&data) == hash => {}
+                // Chunks of files removed since the scrub started are gone for good reason.
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                    if !self.state.read().unwrap().chunks.contains_key(&hash) {
+                        continue;
+                    }
+                    report.missing.push(hash);
+                }
+                _ => report.corrupt.push(hash),
+            }
+            report.checked += 1;
+        }
+        let state = self.state.read().unwrap();
+        report.damaged = state
+            .inodes
+            .iter()
+            .filter(|(_, inode)| {
+                inode.chunks().iter().any(|chunk| {
+                    report.corrupt.contains(&chunk.hash) || report.missing.contains(&chunk.hash)
+                })
+            })
+            .map(|(id, _)| *id)
+            .collect();
+        report.damaged.sort();
+        report
+    }
+
+    /// Scrubs the store over and over, logging what is found.
+    pub async fn scrub_every(
+        self: Arc<Self>,
+        period: Duration,
+    ) {
+        let mut interval = tokio::time::interval(period);
+        // The first tick is right away, and the store was tidied up when it was opened.
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let store = self.clone();
+            let report = match tokio::task::spawn_blocking(move || store.scrub()).await {
+                Ok(report) => report,
+                Err(error) => {
+                    tracing::error!("Scrub failed: {error}");
+                    continue;
+                }
+            };
+            tracing::info!("Scrubbed {} chunks", report.checked);
+            for hash in &report.corrupt {
+                tracing::error!("Chunk {} is corrupt", hex(hash));
+            }
+            for hash in &report.missing {
+                tracing::error!("Chunk {} is missing", hex(hash));
+            }
+            for id in &report.damaged {
+                tracing::error!("File {id} is damaged");
+            }
+        }
+    }
+}
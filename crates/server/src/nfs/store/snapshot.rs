@@ -0,0 +1,458 @@
+use super::chunk::{hex, release, retain};
+use super::index::{self, Chunk, Content, Inode, Timestamp, ROOT_ID};
+use super::inode::{entries, Node};
+use super::{check, chunker, State, Store};
+use nfs::*;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The inodes of the store as they were when a snapshot was taken. Inodes that did not change
+/// since are shared with the store and with the other snapshots.
+pub struct Snapshot {
+    pub inodes: HashMap<u64, Arc<Inode>>,
+    /// The chunks that hold the records of the inodes.
+    pub chunks: Vec<Chunk>,
+}
+
+/// The snapshots, one file each in the `snapshots` directory of the store, named by the second
+/// they were taken at. A file lists the chunks that hold the records of the inodes, so that
+/// snapshots share the chunks of the records that did not change in between.
+pub struct Manifests {
+    directory: PathBuf,
+}
+
+impl Manifests {
+    pub fn new(root: &Path) -> io::Result<Self> {
+        let directory = root.join("snapshots");
+        fs::create_dir_all(&directory)?;
+        Ok(Self { directory })
+    }
+
+    pub fn load(&self) -> io::Result<BTreeMap<u64, Vec<Chunk>>> {
+        let mut manifests = BTreeMap::new();
+        for entry in fs::read_dir(&self.directory)? {
+            let path = entry?.path();
+            let Some(snapshot) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| u64::from_str_radix(name, 16).ok())
+            else {
+                // Left over from a snapshot that was never finished.
+                fs::remove_file(&path)?;
+                continue;
+            };
+            match ciborium::from_reader(File::open(&path)?) {
+                Ok(chunks) => {
+                    manifests.insert(snapshot, chunks);
+                }
+                Err(error) => tracing::error!("Skipping snapshot {}: {error}", path.display()),
+            }
+        }
+        Ok(manifests)
+    }
+
+    pub fn save(
+        &self,
+        snapshot: u64,
+        chunks: &[Chunk],
+    ) -> io::Result<()> {
+        let path = self.path(snapshot);
+        let temporary = path.with_extension("tmp");
+        let mut file = File::create(&temporary)?;
+        ciborium::into_writer(chunks, &mut file).map_err(io::Error::other)?;
+        file.flush()?;
+        file.sync_data()?;
+        fs::rename(&temporary, &path)
+    }
+
+    pub fn remove(
+        &self,
+        snapshot: u64,
+    ) -> io::Result<()> {
+        match fs::remove_file(self.path(snapshot)) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+            _ => Ok(()),
+        }
+    }
+
+    fn path(
+        &self,
+        snapshot: u64,
+    ) -> PathBuf {
+        self.directory.join(format!("{snapshot:016x}"))
+    }
+}
+
+/// The records of the inodes in the order of their ids, so that unchanged inodes encode to the
+/// same bytes in every snapshot.
+pub fn encode(inodes: &HashMap<u64, Arc<Inode>>) -> io::Result<Vec<u8>> {
+    let mut records = inodes
+        .iter()
+        .map(|(id, inode)| (*id, inode.as_ref()))
+        .collect::<Vec<_>>();
+    records.sort_by_key(|(id, _)| *id);
+    let mut data = Vec::new();
+    ciborium::into_writer(&records, &mut data).map_err(io::Error::other)?;
+    Ok(data)
+}
+
+/// Reads back the records of the inodes and counts their links.
+pub fn decode(data: &[u8]) -> io::Result<HashMap<u64, Inode>> {
+    let records: Vec<(u64, Inode)> = ciborium::from_reader(data).map_err(io::Error::other)?;
+    let mut inodes = records.into_iter().collect::<HashMap<_, _>>();
+    let children = inodes
+        .values()
+        .filter_map(|inode| match &inode.content {
+            Content::Directory { entries, .. } => Some(entries.values().copied()),
+            _ => None,
+        })
+        .flatten()
+        .collect::<Vec<_>>();
+    for child in children {
+        if let Some(inode) = inodes.get_mut(&child) {
+            inode.links += 1;
+        }
+    }
+    Ok(inodes)
+}
+
+/// The name of the snapshot in `.snapshots`, which is the UTC time it was taken at.
+pub fn name(snapshot: u64) -> String {
+    let days = (snapshot / 86400) as i64;
+    let seconds = snapshot % 86400;
+    // Days since the epoch to a civil date, after Howard Hinnant's algorithm.
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = match month_index < 10 {
+        true => month_index + 3,
+        false => month_index - 9,
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+impl Store {
+    /// The names of the snapshots, from the oldest to the latest.
+    pub fn snapshots(&self) -> Vec<String> {
+        let state = self.state.read().unwrap();
+        state.snapshots.keys().copied().map(name).collect()
+    }
+
+    /// Takes a snapshot of the files as they are, returning its name. Files that are being
+    /// written to are chunked first.
+    ///
+    /// The chunks of a snapshot count against the quota until it is removed, like those of the
+    /// files.
+    ///
+    /// Files are chunked and the records of the inodes written without holding the lock, which
+    /// is only held to see which inodes the snapshot holds and to add it once its manifest is
+    /// saved.
+    pub fn snapshot(&self) -> io::Result<String> {
+        let _taking = self.taking_snapshot.lock().unwrap();
+        let taken = Timestamp::now().seconds as u64;
+        if self.state.read().unwrap().snapshots.contains_key(&taken) {
+            return Err(io::ErrorKind::AlreadyExists.into());
+        }
+        let staged = self.state.read().unwrap().staged.clone();
+        for id in staged {
+            self.seal_unlocked(id)
+                .map_err(|error| io::Error::other(format!("{error:?}")))?;
+        }
+        let inodes = {
+            let mut state = self.state.write().unwrap();
+            // Files written to in the meantime.
+            let staged = state.staged.iter().copied().collect::<Vec<_>>();
+            for id in staged {
+                self.seal(&mut state, id)
+                    .map_err(|error| io::Error::other(format!("{error:?}")))?;
+            }
+            let inodes = state.inodes.clone();
+            for inode in inodes.values() {
+                retain(&mut state, inode.chunks());
+            }
+            inodes
+        };
+        let chunks = match self.save_manifest(taken, &inodes) {
+            Ok(chunks) => chunks,
+            Err(error) => {
+                let mut state = self.state.write().unwrap();
+                for inode in inodes.values() {
+                    release(&mut state, inode.chunks(), |hash| self.remove_chunk(hash));
+                }
+                return Err(error);
+            }
+        };
+        let mut state = self.state.write().unwrap();
+        state.snapshots.insert(taken, Snapshot { inodes, chunks });
+        state.snapshots_changed = Timestamp::now();
+        Ok(name(taken))
+    }
+
+    /// Writes the records of the inodes as chunks and the manifest that lists them, returning
+    /// the chunks, which are retained.
+    fn save_manifest(
+        &self,
+        taken: u64,
+        inodes: &HashMap<u64, Arc<Inode>>,
+    ) -> io::Result<Vec<Chunk>> {
+        let data = encode(inodes)?;
+        let mut chunks = Vec::new();
+        chunker::split(data.as_slice(), |data| {
+            let hash = openssl::sha::sha256(data);
+            if !self.chunk_path(&hash).exists() {
+                self.write_chunk(&hash, data)?;
+            }
+            chunks.push(index::Chunk {
+                hash,
+                length: data.len() as u32,
+            });
+            Ok(())
+        })?;
+        {
+            let mut state = self.state.write().unwrap();
+            // A chunk found on disk may have been removed since, as nothing held on to it.
+            let mut offset = 0;
+            for chunk in &chunks {
+                let end = offset + chunk.length as usize;
+                if !state.chunks.contains_key(&chunk.hash) && !self.chunk_path(&chunk.hash).exists()
+                {
+                    self.write_chunk(&chunk.hash, &data[offset..end])?;
+                }
+                offset = end;
+            }
+            retain(&mut state, &chunks);
+        }
+        if let Err(error) = self.manifests.save(taken, &chunks) {
+            let mut state = self.state.write().unwrap();
+            release(&mut state, &chunks, |hash| self.remove_chunk(hash));
+            return Err(error);
+        }
+        Ok(chunks)
+    }
+
+    /// Removes a snapshot, along with the chunks that only it used.
+    ///
+    /// The manifest and the chunks are removed without holding the write lock. Chunks are only
+    /// removed under the read lock, which keeps them from being used again in the meantime.
+    pub fn remove_snapshot(
+        &self,
+        name: &str,
+    ) -> io::Result<()> {
+        let _taking = self.taking_snapshot.lock().unwrap();
+        let taken = self
+            .state
+            .read()
+            .unwrap()
+            .snapshots
+            .keys()
+            .copied()
+            .find(|taken| self::name(*taken) == name)
+            .ok_or(io::ErrorKind::NotFound)?;
+        self.manifests.remove(taken)?;
+        let mut unused = Vec::new();
+        {
+            let mut state = self.state.write().unwrap();
+            let snapshot = state.snapshots.remove(&taken).unwrap();
+            release(&mut state, &snapshot.chunks, |hash| unused.push(*hash));
+            for inode in snapshot.inodes.values() {
+                release(&mut state, inode.chunks(), |hash| unused.push(*hash));
+            }
+            state.snapshots_changed = Timestamp::now();
+        }
+        let state = self.state.read().unwrap();
+        for hash in unused {
+            if !state.chunks.contains_key(&hash) {
+                self.remove_chunk(&hash);
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes a snapshot over and over, keeping the latest `keep` of them.
+    pub async fn snapshot_every(
+        self: Arc<Self>,
+        period: Duration,
+        keep: usize,
+    ) {
+        let mut interval = tokio::time::interval(period);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let store = self.clone();
+            let result = tokio::task::spawn_blocking(move || {
+                let name = store.snapshot()?;
+                let names = store.snapshots();
+                for name in &names[..names.len().saturating_sub(keep)] {
+                    store.remove_snapshot(name)?;
+                }
+                Ok::<_, io::Error>(name)
+            })
+            .await;
+            match result {
+                Ok(Ok(name)) => tracing::info!("Took snapshot {name}"),
+                Ok(Err(error)) => tracing::error!("Snapshot failed: {error}"),
+                Err(error) => tracing::error!("Snapshot failed: {error}"),
+            }
+        }
+    }
+
+    /// Reads back the snapshots, sharing the inodes that are the same as in the store or in
+    /// the snapshot before.
+    pub(super) fn load_snapshots(
+        &self,
+        state: &mut State,
+        live: &HashMap<u64, Arc<Inode>>,
+    ) -> io::Result<()> {
+        for (taken, chunks) in self.manifests.load()? {
+            let decoded = match self.read_snapshot(&chunks) {
+                Ok(decoded) => decoded,
+                Err(error) => {
+                    tracing::error!("Skipping snapshot {}: {error}", name(taken));
+                    continue;
+                }
+            };
+            let previous = state.snapshots.values().next_back();
+            let inodes = decoded
+                .into_iter()
+                .map(|(id, inode)| {
+                    let shared = [
+                        previous.and_then(|snapshot| snapshot.inodes.get(&id)),
+                        live.get(&id),
+                    ]
+                    .into_iter()
+                    .flatten()
+                    .find(|shared| ***shared == inode);
+                    let inode = match shared {
+                        Some(shared) => shared.clone(),
+                        None => Arc::new(inode),
+                    };
+                    (id, inode)
+                })
+                .collect::<HashMap<_, _>>();
+            retain(state, &chunks);
+            for inode in inodes.values() {
+                retain(state, inode.chunks());
+            }
+            state.snapshots.insert(taken, Snapshot { inodes, chunks });
+        }
+        Ok(())
+    }
+
+    pub(super) fn read_snapshot(
+        &self,
+        chunks: &[index::Chunk],
+    ) -> io::Result<HashMap<u64, Inode>> {
+        let mut data = Vec::new();
+        for chunk in chunks {
+            let content = fs::read(self.chunk_path(&chunk.hash))?;
+            if openssl::sha::sha256(&content) != chunk.hash {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("chunk {} is corrupt", hex(&chunk.hash)),
+                ));
+            }
+            data.extend_from_slice(&content);
+        }
+        decode(&data)
+    }
+
+    /// Opens a file of a snapshot, which may only be read.
+    pub(super) fn open_snapshot<'a>(
+        &self,
+        credentials: &Credentials,
+        node: Node,
+        args: OpenArgs<'_>,
+    ) -> Result<(FileHandle<'a>, OpenResult<'a>), Error> {
+        if matches!(args.how, OpenFlag::Create(_))
+            || args.share_access.contains(ShareAccessFlags::WRITE)
+        {
+            return Err(Error::ROFS);
+        }
+        let state = self.state.read().unwrap();
+        let node = match args.claim {
+            OpenClaim::Null(name) => {
+                let inode = state.resolve(node)?;
+                let entries = entries(credentials, &inode, name, Permissions::EXECUTE)?;
+                node.child(*entries.get(name).ok_or(Error::NOENT)?)
+            }
+            OpenClaim::FileHandle => node,
+            _ => return Err(Error::NOTSUPP),
+        };
+        let inode = state.resolve(node)?;
+        match inode.content {
+            Content::Directory { .. } => return Err(Error::ISDIR),
+            Content::Symlink(_) => return Err(Error::SYMLINK),
+            Content::File { .. } => {}
+        }
+        check(credentials, &*inode, Permissions::READ)?;
+        let mut other = [0; 12];
+        other[..8].copy_from_slice(&node.file_id().to_be_bytes());
+        Ok((
+            self.file_handle(node),
+            OpenResult {
+                state_id: StateId {
+                    sequence_id: 1,
+                    other,
+                },
+                change_info: ChangeInfo {
+                    atomic: false,
+                    before: 0,
+                    after: 0,
+                },
+                flags: OpenResultFlags::empty(),
+                attributes: AttributeMask::new(),
+                delegation: OpenDelegation::None,
+            },
+        ))
+    }
+}
+
+impl State {
+    pub fn snapshots_directory(&self) -> Inode {
+        let entries = self
+            .snapshots
+            .keys()
+            .map(|taken| (name(*taken), *taken))
+            .collect();
+        Inode {
+            content: Content::Directory {
+                parent: ROOT_ID,
+                entries,
+            },
+            mode: 0o555,
+            uid: 0,
+            gid: 0,
+            access_time: self.snapshots_changed,
+            modify_time: self.snapshots_changed,
+            change_time: self.snapshots_changed,
+            links: 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_name() {
+        assert_eq!(name(0), "1970-01-01T00:00:00Z");
+        assert_eq!(name(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(name(1_792_326_896), "2026-10-18T12:34:56Z");
+    }
+}
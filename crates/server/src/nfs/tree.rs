@@ -0,0 +1,1187 @@
+use super::access::{check, Ownership};
+use super::FIRST_COOKIE;
+use lararium::{Schema, Topic, Value};
+use nfs::*;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::{mpsc, RwLock};
+
+const ROOT_FILE_HANDLE: &[u8] = &[0];
+const MAX_NAME_LENGTH: usize = 255;
+const MAX_FILE_SIZE: u32 = 1024 * 1024;
+const FILE_SYSTEM_ID: FileSystemId = FileSystemId { major: 0, minor: 0 };
+
+const SUPPORTED_ATTRIBUTES: &[Attribute] = &[
+    Attribute::SupportedAttributes,
+    Attribute::Type,
+    Attribute::FileHandleExpireType,
+    Attribute::Change,
+    Attribute::Size,
+    Attribute::LinkSupport,
+    Attribute::SymlinkSupport,
+    Attribute::NamedAttributes,
+    Attribute::FileSystemId,
+    Attribute::UniqueHandles,
+    Attribute::LeaseTime,
+    Attribute::AclSupport,
+    Attribute::CaseInsensitive,
+    Attribute::CasePreserving,
+    Attribute::FileHandle,
+    Attribute::FileId,
+    Attribute::Homogeneous,
+    Attribute::MaxFileSize,
+    Attribute::MaxName,
+    Attribute::MaxRead,
+    Attribute::MaxWrite,
+    Attribute::Mode,
+    Attribute::NoTruncate,
+    Attribute::NumberOfLinks,
+    Attribute::Owner,
+    Attribute::OwnerGroup,
+    Attribute::RawDevice,
+    Attribute::SpaceUsed,
+    Attribute::MountedOnFileId,
+    Attribute::SupportedAttributesExclusiveCreate,
+];
+
+const SETTABLE_ATTRIBUTES: &[Attribute] = &[Attribute::Size];
+
+type Entries = HashMap<Topic, lararium::Entry>;
+
+/// The entry tree of the gateway served over NFS.
+///
+/// Directory entries and the prefixes of all topics are directories, and every record is a file
+/// twice over, as `name.json` and `name.cbor`. Whatever is written to a record must decode to a
+/// value that matches its schema. Everything belongs to root, so only root may change it.
+pub struct EntryTree {
+    entries: Arc<RwLock<Entries>>,
+    mounts: Vec<String>,
+    verifier: Verifier,
+    nodes: Mutex<HashMap<u64, Node>>,
+    pending: Mutex<HashMap<u64, Vec<u8>>>,
+    changes: Option<mpsc::UnboundedSender<Topic>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Json,
+    Cbor,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Directory(Topic),
+    Record(Topic, Format),
+}
+
+impl EntryTree {
+    /// Serves the entries, with the `mounts` listed in the root directory for other file systems
+    /// to be mounted on.
+    pub fn new(
+        entries: Arc<RwLock<Entries>>,
+        mounts: Vec<String>,
+    ) -> Self {
+        let boot_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        Self {
+            entries,
+            mounts,
+            verifier: boot_time.to_be_bytes(),
+            nodes: Mutex::new(HashMap::new()),
+            pending: Mutex::new(HashMap::new()),
+            changes: None,
+        }
+    }
+
+    /// Sends the topics of the records that are written to or removed, for the gateway to pass
+    /// the changes on as it does for publishes.
+    pub fn with_changes(
+        mut self,
+        changes: mpsc::UnboundedSender<Topic>,
+    ) -> Self {
+        self.changes = Some(changes);
+        self
+    }
+
+    /// Whether the handle belongs to the tree rather than to a file system mounted on it.
+    pub fn root_file_handle<'a>(&self) -> FileHandle<'a> {
+        FileHandle::from(ROOT_FILE_HANDLE)
+    }
+
+    pub fn owns(
+        &self,
+        file_handle: &FileHandle<'_>,
+    ) -> bool {
+        let bytes: &[u8] = file_handle;
+        bytes == ROOT_FILE_HANDLE || matches!(bytes, [1..=3, id @ ..] if id.len() == 8)
+    }
+
+    /// Whether another file system is mounted on the name in the directory.
+    pub fn is_mount(
+        &self,
+        directory: &FileHandle<'_>,
+        name: &str,
+    ) -> bool {
+        let bytes: &[u8] = directory;
+        bytes == ROOT_FILE_HANDLE && self.mounts.iter().any(|mount| mount == name)
+    }
+
+    pub async fn access(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+        flags: AccessFlags,
+    ) -> Result<AccessResult, Error> {
+        let entries = self.entries.read().await;
+        let node = self.resolve(&entries, file_handle)?;
+        let allowed = credentials.access(node.mode(), 0, 0, node.is_directory());
+        Ok(AccessResult {
+            supported: flags,
+            access: flags & allowed,
+        })
+    }
+
+    pub async fn lookup<'a>(
+        &self,
+        credentials: &Credentials,
+        directory: &FileHandle<'_>,
+        name: &str,
+    ) -> Result<FileHandle<'a>, Error> {
+        let entries = self.entries.read().await;
+        let directory = self.resolve(&entries, directory)?;
+        let node = self
+            .child(
+                credentials,
+                &entries,
+                &directory,
+                name,
+                Permissions::EXECUTE,
+            )?
+            .ok_or(Error::NOENT)?;
+        Ok(self.remember(node))
+    }
+
+    pub async fn lookup_parent<'a>(
+        &self,
+        credentials: &Credentials,
+        directory: &FileHandle<'_>,
+    ) -> Result<FileHandle<'a>, Error> {
+        let entries = self.entries.read().await;
+        let directory = self.resolve(&entries, directory)?;
+        let Node::Directory(ref topic) = directory else {
+            return Err(Error::NOTDIR);
+        };
+        if topic.segments.is_empty() {
+            return Err(Error::NOENT);
+        }
+        check(credentials, &directory, Permissions::EXECUTE)?;
+        Ok(self.remember(Node::Directory(topic.parent())))
+    }
+
+    pub async fn get_attributes<'a>(
+        &self,
+        _credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+        mask: AttributeMask<'_>,
+    ) -> Result<Vec<AttributeValue<'a>>, Error> {
+        let entries = self.entries.read().await;
+        let node = self.resolve(&entries, file_handle)?;
+        Ok(self.attributes(&entries, &node, mask))
+    }
+
+    pub async fn read<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+        args: ReadArgs,
+    ) -> Result<ReadResult<'a>, Error> {
+        let entries = self.entries.read().await;
+        let node = self.resolve(&entries, file_handle)?;
+        if node.is_directory() {
+            return Err(Error::ISDIR);
+        }
+        check(credentials, &node, Permissions::READ)?;
+        let content = self.content(&entries, &node);
+        let start = content.len().min(args.offset as usize);
+        let end = content.len().min(start + args.count as usize);
+        Ok(ReadResult {
+            eof: end == content.len(),
+            data: content[start..end].to_vec().into(),
+        })
+    }
+
+    pub async fn read_directory<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+        args: ReadDirectoryArgs<'_>,
+    ) -> Result<ReadDirectoryResult<'a>, Error> {
+        let entries = self.entries.read().await;
+        let directory = self.resolve(&entries, file_handle)?;
+        let Node::Directory(ref topic) = directory else {
+            return Err(Error::NOTDIR);
+        };
+        check(credentials, &directory, Permissions::READ)?;
+        let mut children = children(&entries, topic);
+        if topic.segments.is_empty() {
+            for mount in &self.mounts {
+                children.insert(mount.clone(), Node::Directory(Topic::from(mount.as_str())));
+            }
+        }
+        let mut directory_entries = Vec::new();
+        let mut size = 0;
+        let mut eof = true;
+        for (cookie, (name, node)) in (FIRST_COOKIE..).zip(children) {
+            if cookie <= args.cookie {
+                continue;
+            }
+            let attributes = self.attributes(&entries, &node, args.attributes.clone());
+            size += 32 + name.len() + 16 * attributes.len();
+            if size > args.max_count as usize {
+                eof = false;
+                break;
+            }
+            self.remember(node);
+            directory_entries.push(Entry {
+                cookie,
+                name: name.into(),
+                attributes,
+            });
+        }
+        if directory_entries.is_empty() && !eof {
+            return Err(Error::TOOSMALL);
+        }
+        Ok(ReadDirectoryResult {
+            cookie_verf: [0; 8],
+            directory_list: DirectoryList {
+                entries: directory_entries,
+                eof,
+            },
+        })
+    }
+
+    /// There are no symbolic links among the entries.
+    pub async fn read_link<'a>(
+        &self,
+        _credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+    ) -> Result<Cow<'a, str>, Error> {
+        let entries = self.entries.read().await;
+        match self.resolve(&entries, file_handle)? {
+            Node::Directory(_) => Err(Error::ISDIR),
+            Node::Record(..) => Err(Error::INVAL),
+        }
+    }
+
+    pub async fn open<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+        args: OpenArgs<'_>,
+    ) -> Result<(FileHandle<'a>, OpenResult<'a>), Error> {
+        let mut entries = self.entries.write().await;
+        let (node, change_info, attributes, created) = match args.claim {
+            OpenClaim::Null(name) => {
+                let directory = self.resolve(&entries, file_handle)?;
+                let before = change(&entries, &directory);
+                let child = self.child(
+                    credentials,
+                    &entries,
+                    &directory,
+                    name,
+                    Permissions::EXECUTE,
+                )?;
+                let (node, attributes, created) = match (child, args.how) {
+                    (Some(node), OpenFlag::NoCreate) => (node, AttributeMask::new(), false),
+                    (Some(node), OpenFlag::Create(OpenFlagCreate::Unchecked { attributes })) => {
+                        let attributes = self.apply_attributes(&entries, &node, &attributes)?;
+                        (node, attributes, false)
+                    }
+                    (Some(_), OpenFlag::Create(_)) => return Err(Error::EXIST),
+                    (None, OpenFlag::NoCreate) => return Err(Error::NOENT),
+                    (None, OpenFlag::Create(how)) => {
+                        check(credentials, &directory, Permissions::WRITE)?;
+                        let node = record(&directory, name)?;
+                        entries.insert(
+                            node.topic().clone(),
+                            lararium::Entry::Record {
+                                schema: Schema::Any,
+                                value: Value::Null,
+                            },
+                        );
+                        let attributes = match how {
+                            OpenFlagCreate::Unchecked { attributes }
+                            | OpenFlagCreate::Guarded { attributes }
+                            | OpenFlagCreate::Exclusive4_1 { attributes, .. } => attributes,
+                        };
+                        let attributes = self.apply_attributes(&entries, &node, &attributes)?;
+                        (node, attributes, true)
+                    }
+                };
+                let change_info = ChangeInfo {
+                    atomic: true,
+                    before,
+                    after: change(&entries, &directory),
+                };
+                (node, change_info, attributes, created)
+            }
+            OpenClaim::FileHandle => {
+                let change_info = ChangeInfo {
+                    atomic: false,
+                    before: 0,
+                    after: 0,
+                };
+                (
+                    self.resolve(&entries, file_handle)?,
+                    change_info,
+                    AttributeMask::new(),
+                    false,
+                )
+            }
+            _ => return Err(Error::NOTSUPP),
+        };
+        if node.is_directory() {
+            return Err(Error::ISDIR);
+        }
+        if !created {
+            let mut permissions = Permissions::empty();
+            if args.share_access.contains(ShareAccessFlags::READ) {
+                permissions |= Permissions::READ;
+            }
+            if args.share_access.contains(ShareAccessFlags::WRITE) {
+                permissions |= Permissions::WRITE;
+            }
+            check(credentials, &node, permissions)?;
+        }
+        let mut other = [0; 12];
+        other[..8].copy_from_slice(&node.id().to_be_bytes());
+        Ok((
+            self.remember(node),
+            OpenResult {
+                state_id: StateId {
+                    sequence_id: 1,
+                    other,
+                },
+                change_info,
+                flags: OpenResultFlags::empty(),
+                attributes,
+                delegation: OpenDelegation::None,
+            },
+        ))
+    }
+
+    pub async fn create<'a>(
+        &self,
+        credentials: &Credentials,
+        directory: &FileHandle<'_>,
+        args: CreateArgs<'_>,
+    ) -> Result<(FileHandle<'a>, CreateResult<'a>), Error> {
+        let mut entries = self.entries.write().await;
+        let directory = self.resolve(&entries, directory)?;
+        let permissions = Permissions::WRITE | Permissions::EXECUTE;
+        if self
+            .child(credentials, &entries, &directory, args.name, permissions)?
+            .is_some()
+        {
+            return Err(Error::EXIST);
+        }
+        let CreateType::Directory = args.object_type else {
+            return Err(Error::BADTYPE);
+        };
+        let before = change(&entries, &directory);
+        let topic = directory.topic().child(args.name.into());
+        entries.insert(topic.clone(), lararium::Entry::Directory);
+        let after = change(&entries, &directory);
+        Ok((
+            self.remember(Node::Directory(topic)),
+            CreateResult {
+                change_info: ChangeInfo {
+                    atomic: true,
+                    before,
+                    after,
+                },
+                attributes: AttributeMask::new(),
+            },
+        ))
+    }
+
+    pub async fn remove(
+        &self,
+        credentials: &Credentials,
+        directory: &FileHandle<'_>,
+        name: &str,
+    ) -> Result<ChangeInfo, Error> {
+        let mut entries = self.entries.write().await;
+        let directory = self.resolve(&entries, directory)?;
+        let permissions = Permissions::WRITE | Permissions::EXECUTE;
+        let node = self
+            .child(credentials, &entries, &directory, name, permissions)?
+            .ok_or(Error::NOENT)?;
+        let before = change(&entries, &directory);
+        if let Node::Directory(ref topic) = node {
+            if !children(&entries, topic).is_empty() {
+                return Err(Error::NOTEMPTY);
+            }
+        }
+        entries.remove(node.topic());
+        let mut pending = self.pending.lock().unwrap();
+        for format in [Format::Json, Format::Cbor] {
+            pending.remove(&Node::Record(node.topic().clone(), format).id());
+        }
+        self.changed(node.topic());
+        Ok(ChangeInfo {
+            atomic: true,
+            before,
+            after: change(&entries, &directory),
+        })
+    }
+
+    pub async fn set_attributes<'a>(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+        args: SetAttributesArgs<'_>,
+    ) -> Result<AttributeMask<'a>, Error> {
+        let entries = self.entries.read().await;
+        let node = self.resolve(&entries, file_handle)?;
+        for attribute in &args.attributes {
+            match attribute {
+                AttributeValue::Size(_) => check(credentials, &node, Permissions::WRITE)?,
+                AttributeValue::Mode(_) => return Err(Error::PERM),
+                _ => {}
+            }
+        }
+        self.apply_attributes(&entries, &node, &args.attributes)
+    }
+
+    pub async fn write(
+        &self,
+        credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+        args: WriteArgs<'_>,
+    ) -> Result<WriteResult, Error> {
+        let mut entries = self.entries.write().await;
+        let node = self.resolve(&entries, file_handle)?;
+        if node.is_directory() {
+            return Err(Error::ISDIR);
+        }
+        check(credentials, &node, Permissions::WRITE)?;
+        let end = args
+            .offset
+            .checked_add(args.data.len() as u64)
+            .filter(|end| *end <= MAX_FILE_SIZE as u64)
+            .ok_or(Error::FBIG)? as usize;
+        let mut content = self.content(&entries, &node);
+        if content.len() < end {
+            content.resize(end, 0);
+        }
+        content[args.offset as usize..end].copy_from_slice(&args.data);
+        // Values may take several writes, so whatever does not decode yet is kept until it does.
+        let committed = match self.store(&mut entries, &node, &content)? {
+            true => StableHow::FileSync,
+            false => {
+                self.pending.lock().unwrap().insert(node.id(), content);
+                StableHow::Unstable
+            }
+        };
+        Ok(WriteResult {
+            count: args.data.len() as u32,
+            committed,
+            verifier: self.verifier,
+        })
+    }
+
+    pub async fn commit(
+        &self,
+        _credentials: &Credentials,
+        file_handle: &FileHandle<'_>,
+        _args: CommitArgs,
+    ) -> Result<Verifier, Error> {
+        let mut entries = self.entries.write().await;
+        let node = self.resolve(&entries, file_handle)?;
+        let Some(content) = self.pending.lock().unwrap().remove(&node.id()) else {
+            return Ok(self.verifier);
+        };
+        match self.store(&mut entries, &node, &content) {
+            Ok(true) => Ok(self.verifier),
+            _ => {
+                tracing::warn!("discarding undecodable write to {}", node.topic());
+                Err(Error::IO)
+            }
+        }
+    }
+
+    fn resolve(
+        &self,
+        entries: &Entries,
+        file_handle: &FileHandle<'_>,
+    ) -> Result<Node, Error> {
+        let bytes: &[u8] = file_handle;
+        if bytes == ROOT_FILE_HANDLE {
+            return Ok(Node::Directory(Topic { segments: vec![] }));
+        }
+        let id = match bytes {
+            [1..=3, id @ ..] if id.len() == 8 => u64::from_be_bytes(id.try_into().unwrap()),
+            _ => return Err(Error::BADHANDLE),
+        };
+        if let Some(node) = self.nodes.lock().unwrap().get(&id) {
+            if exists(entries, node) {
+                return Ok(node.clone());
+            }
+        }
+        // The handle predates a restart, so look for it among every node of every topic.
+        let node = entries
+            .iter()
+            .flat_map(|(topic, entry)| nodes(topic, entry))
+            .find(|node| node.id() == id)
+            .ok_or(Error::STALE)?;
+        self.nodes.lock().unwrap().insert(id, node.clone());
+        Ok(node)
+    }
+
+    fn child(
+        &self,
+        credentials: &Credentials,
+        entries: &Entries,
+        directory: &Node,
+        name: &str,
+        permissions: Permissions,
+    ) -> Result<Option<Node>, Error> {
+        if name.is_empty() {
+            return Err(Error::INVAL);
+        }
+        if name.len() > MAX_NAME_LENGTH {
+            return Err(Error::NAMETOOLONG);
+        }
+        if name == "." || name == ".." || name.contains(['/', '\0']) {
+            return Err(Error::BADNAME);
+        }
+        let Node::Directory(topic) = directory else {
+            return Err(Error::NOTDIR);
+        };
+        check(credentials, directory, permissions)?;
+        Ok(children(entries, topic).remove(name))
+    }
+
+    fn remember<'a>(
+        &self,
+        node: Node,
+    ) -> FileHandle<'a> {
+        let file_handle = node.file_handle();
+        if !node.topic().segments.is_empty() {
+            self.nodes.lock().unwrap().insert(node.id(), node);
+        }
+        file_handle
+    }
+
+    /// The bytes of a record as last written, or as its value encodes.
+    fn content(
+        &self,
+        entries: &Entries,
+        node: &Node,
+    ) -> Vec<u8> {
+        let Node::Record(topic, format) = node else {
+            return vec![];
+        };
+        if let Some(content) = self.pending.lock().unwrap().get(&node.id()) {
+            return content.clone();
+        }
+        match entries.get(topic) {
+            Some(lararium::Entry::Record { value, .. }) => format.encode(value),
+            _ => vec![],
+        }
+    }
+
+    /// Stores the content as the value of the record once it decodes, which it must do to a value
+    /// that matches the schema.
+    fn store(
+        &self,
+        entries: &mut Entries,
+        node: &Node,
+        content: &[u8],
+    ) -> Result<bool, Error> {
+        let Node::Record(topic, format) = node else {
+            return Err(Error::ISDIR);
+        };
+        let Some(new_value) = format.decode(content) else {
+            return Ok(false);
+        };
+        let Some(lararium::Entry::Record { schema, value }) = entries.get_mut(topic) else {
+            return Err(Error::STALE);
+        };
+        if !schema.validate(&new_value) {
+            tracing::warn!("{topic} does not match its schema");
+            return Err(Error::INVAL);
+        }
+        *value = new_value;
+        self.pending.lock().unwrap().remove(&node.id());
+        self.changed(topic);
+        Ok(true)
+    }
+
+    fn changed(
+        &self,
+        topic: &Topic,
+    ) {
+        if let Some(changes) = &self.changes {
+            let _ = changes.send(topic.clone());
+        }
+    }
+
+    /// Applies the size, which only truncates or extends what is pending for the record. Modes
+    /// are accepted when creating files but not applied.
+    fn apply_attributes<'a>(
+        &self,
+        entries: &Entries,
+        node: &Node,
+        attributes: &[AttributeValue<'_>],
+    ) -> Result<AttributeMask<'a>, Error> {
+        let mut applied = Vec::new();
+        for attribute in attributes {
+            match attribute {
+                AttributeValue::Size(_) | AttributeValue::Mode(_) => {}
+                _ => return Err(Error::ATTRNOTSUPP),
+            }
+        }
+        for attribute in attributes {
+            if let AttributeValue::Size(size) = attribute {
+                if node.is_directory() {
+                    return Err(Error::ISDIR);
+                }
+                if *size > MAX_FILE_SIZE as u64 {
+                    return Err(Error::FBIG);
+                }
+                let mut content = self.content(entries, node);
+                content.resize(*size as usize, 0);
+                self.pending.lock().unwrap().insert(node.id(), content);
+                applied.push(Attribute::Size);
+            }
+        }
+        Ok(applied.into_iter().collect())
+    }
+
+    fn attributes<'a>(
+        &self,
+        entries: &Entries,
+        node: &Node,
+        mask: AttributeMask<'_>,
+    ) -> Vec<AttributeValue<'a>> {
+        let (size, links) = match node {
+            Node::Directory(topic) => {
+                let children = children(entries, topic);
+                let mut directories = children
+                    .values()
+                    .filter(|child| child.is_directory())
+                    .count();
+                if topic.segments.is_empty() {
+                    directories += self.mounts.len();
+                }
+                (children.len() as u64, 2 + directories as u32)
+            }
+            Node::Record(..) => (self.content(entries, node).len() as u64, 1),
+        };
+        mask.into_iter()
+            .filter_map(|attribute| {
+                Some(match attribute {
+                    Attribute::SupportedAttributes => {
+                        AttributeValue::SupportedAttributes(SUPPORTED_ATTRIBUTES.into())
+                    }
+                    Attribute::Type => AttributeValue::Type(match node {
+                        Node::Directory(_) => FileType::Directory,
+                        Node::Record(..) => FileType::Regular,
+                    }),
+                    Attribute::FileHandleExpireType => AttributeValue::FileHandleExpireType(0),
+                    Attribute::Change => AttributeValue::Change(match node {
+                        Node::Directory(_) => change(entries, node),
+                        Node::Record(..) => hash(&self.content(entries, node)),
+                    }),
+                    Attribute::Size => AttributeValue::Size(size),
+                    Attribute::LinkSupport => AttributeValue::LinkSupport(false),
+                    Attribute::SymlinkSupport => AttributeValue::SymlinkSupport(false),
+                    Attribute::NamedAttributes => AttributeValue::NamedAttributes(false),
+                    Attribute::FileSystemId => AttributeValue::FileSystemId(FILE_SYSTEM_ID),
+                    Attribute::UniqueHandles => AttributeValue::UniqueHandles(true),
+                    Attribute::LeaseTime => AttributeValue::LeaseTime(90),
+                    Attribute::AclSupport => AttributeValue::AclSupport(AclSupportFlags::empty()),
+                    Attribute::CaseInsensitive => AttributeValue::CaseInsensitive(false),
+                    Attribute::CasePreserving => AttributeValue::CasePreserving(true),
+                    Attribute::FileHandle => AttributeValue::FileHandle(node.file_handle()),
+                    Attribute::FileId => AttributeValue::FileId(node.id()),
+                    Attribute::Homogeneous => AttributeValue::Homogeneous(true),
+                    Attribute::MaxFileSize => AttributeValue::MaxFileSize(MAX_FILE_SIZE as u64),
+                    Attribute::MaxName => AttributeValue::MaxName(MAX_NAME_LENGTH as u32),
+                    Attribute::MaxRead => AttributeValue::MaxRead(MAX_FILE_SIZE as u64),
+                    Attribute::MaxWrite => AttributeValue::MaxWrite(MAX_FILE_SIZE as u64),
+                    Attribute::Mode => AttributeValue::Mode(node.mode()),
+                    Attribute::NoTruncate => AttributeValue::NoTruncate(true),
+                    Attribute::NumberOfLinks => AttributeValue::NumberOfLinks(links),
+                    Attribute::Owner => AttributeValue::Owner("0".into()),
+                    Attribute::OwnerGroup => AttributeValue::OwnerGroup("0".into()),
+                    Attribute::RawDevice => {
+                        AttributeValue::RawDevice(SpecData { major: 0, minor: 0 })
+                    }
+                    Attribute::SpaceUsed => AttributeValue::SpaceUsed(size),
+                    Attribute::MountedOnFileId => AttributeValue::MountedOnFileId(node.id()),
+                    Attribute::SupportedAttributesExclusiveCreate => {
+                        AttributeValue::SupportedAttributesExclusiveCreate(
+                            SETTABLE_ATTRIBUTES.into(),
+                        )
+                    }
+                    _ => return None,
+                })
+            })
+            .collect()
+    }
+}
+
+impl Format {
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Cbor => "cbor",
+        }
+    }
+
+    fn encode(
+        self,
+        value: &Value,
+    ) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let result = match self {
+            Format::Json => serde_json::to_writer_pretty(&mut bytes, value)
+                .map(|()| bytes.push(b'\n'))
+                .map_err(|error| error.to_string()),
+            Format::Cbor => {
+                ciborium::into_writer(value, &mut bytes).map_err(|error| error.to_string())
+            }
+        };
+        if let Err(error) = result {
+            tracing::warn!("failed to encode value as {}: {error}", self.extension());
+        }
+        bytes
+    }
+
+    fn decode(
+        self,
+        bytes: &[u8],
+    ) -> Option<Value> {
+        match self {
+            Format::Json => serde_json::from_slice(bytes).ok(),
+            Format::Cbor => ciborium::from_reader(bytes).ok(),
+        }
+    }
+}
+
+impl Node {
+    fn topic(&self) -> &Topic {
+        match self {
+            Node::Directory(topic) | Node::Record(topic, _) => topic,
+        }
+    }
+
+    fn is_directory(&self) -> bool {
+        matches!(self, Node::Directory(_))
+    }
+
+    fn kind(&self) -> u8 {
+        match self {
+            Node::Directory(_) => 1,
+            Node::Record(_, Format::Json) => 2,
+            Node::Record(_, Format::Cbor) => 3,
+        }
+    }
+
+    fn mode(&self) -> u32 {
+        match self {
+            Node::Directory(_) => 0o755,
+            Node::Record(..) => 0o644,
+        }
+    }
+
+    /// A hash of the kind and topic, so that handles stay valid across restarts.
+    fn id(&self) -> u64 {
+        let mut bytes = vec![self.kind()];
+        bytes.extend_from_slice(self.topic().to_string().as_bytes());
+        hash(&bytes)
+    }
+
+    fn file_handle<'a>(&self) -> FileHandle<'a> {
+        if self.topic().segments.is_empty() {
+            return FileHandle::from(ROOT_FILE_HANDLE);
+        }
+        let mut bytes = Vec::with_capacity(9);
+        bytes.push(self.kind());
+        bytes.extend_from_slice(&self.id().to_be_bytes());
+        FileHandle::from(bytes)
+    }
+}
+
+/// Everything belongs to root.
+impl From<&Node> for Ownership {
+    fn from(node: &Node) -> Self {
+        Self {
+            mode: node.mode(),
+            uid: 0,
+            gid: 0,
+            is_directory: node.is_directory(),
+        }
+    }
+}
+
+/// The record that a new file in the directory stands for, going by its extension.
+fn record(
+    directory: &Node,
+    name: &str,
+) -> Result<Node, Error> {
+    let (name, format) = match name.rsplit_once('.') {
+        Some((name, "json")) if !name.is_empty() => (name, Format::Json),
+        Some((name, "cbor")) if !name.is_empty() => (name, Format::Cbor),
+        _ => return Err(Error::INVAL),
+    };
+    Ok(Node::Record(directory.topic().child(name.into()), format))
+}
+
+fn is_within(
+    topic: &Topic,
+    directory: &Topic,
+) -> bool {
+    topic.segments.len() > directory.segments.len()
+        && topic.segments.starts_with(&directory.segments)
+}
+
+fn children(
+    entries: &Entries,
+    directory: &Topic,
+) -> BTreeMap<String, Node> {
+    let depth = directory.segments.len();
+    let mut children = BTreeMap::new();
+    for (topic, entry) in entries {
+        if !is_within(topic, directory) {
+            continue;
+        }
+        let segment = &topic.segments[depth];
+        let child = directory.child(segment.clone());
+        match entry {
+            _ if topic.segments.len() > depth + 1 => {
+                children.insert(segment.to_string(), Node::Directory(child));
+            }
+            lararium::Entry::Directory => {
+                children.insert(segment.to_string(), Node::Directory(child));
+            }
+            lararium::Entry::Record { .. } => {
+                for format in [Format::Json, Format::Cbor] {
+                    let name = format!("{segment}.{}", format.extension());
+                    children.insert(name, Node::Record(child.clone(), format));
+                }
+            }
+            lararium::Entry::Signal { .. } => {}
+        }
+    }
+    children
+}
+
+/// Every node that the entry at the topic makes up, including its parent directories.
+fn nodes(
+    topic: &Topic,
+    entry: &lararium::Entry,
+) -> Vec<Node> {
+    let mut nodes: Vec<_> = (1..topic.segments.len())
+        .map(|length| {
+            Node::Directory(Topic {
+                segments: topic.segments[..length].to_vec(),
+            })
+        })
+        .collect();
+    match entry {
+        lararium::Entry::Directory => nodes.push(Node::Directory(topic.clone())),
+        lararium::Entry::Record { .. } => {
+            nodes.push(Node::Record(topic.clone(), Format::Json));
+            nodes.push(Node::Record(topic.clone(), Format::Cbor));
+        }
+        lararium::Entry::Signal { .. } => {}
+    }
+    nodes
+}
+
+fn exists(
+    entries: &Entries,
+    node: &Node,
+) -> bool {
+    match node {
+        Node::Directory(topic) => {
+            topic.segments.is_empty()
+                || matches!(entries.get(topic), Some(lararium::Entry::Directory))
+                || entries.keys().any(|other| is_within(other, topic))
+        }
+        Node::Record(topic, _) => {
+            matches!(entries.get(topic), Some(lararium::Entry::Record { .. }))
+        }
+    }
+}
+
+/// Changes with the names in a directory.
+fn change(
+    entries: &Entries,
+    directory: &Node,
+) -> u64 {
+    let names = children(entries, directory.topic())
+        .into_keys()
+        .collect::<Vec<_>>()
+        .join("/");
+    hash(names.as_bytes())
+}
+
+/// The 64-bit FNV-1a hash, which unlike the hasher of the standard library is stable.
+fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn superuser() -> Credentials {
+        Credentials {
+            uid: 0,
+            gid: 0,
+            gids: vec![],
+        }
+    }
+
+    fn root() -> FileHandle<'static> {
+        FileHandle::from(ROOT_FILE_HANDLE)
+    }
+
+    fn read_args() -> ReadArgs {
+        ReadArgs {
+            state_id: StateId {
+                sequence_id: 0,
+                other: [0; 12],
+            },
+            offset: 0,
+            count: 1024,
+        }
+    }
+
+    fn write_args(data: &[u8]) -> WriteArgs<'_> {
+        WriteArgs {
+            state_id: StateId {
+                sequence_id: 0,
+                other: [0; 12],
+            },
+            offset: 0,
+            stable: StableHow::Unstable,
+            data: data.into(),
+        }
+    }
+
+    fn tree() -> EntryTree {
+        let entries = HashMap::from([
+            (
+                Topic::from("system/dns/ttl"),
+                lararium::Entry::Record {
+                    schema: Schema::Integer,
+                    value: Value::Integer(300),
+                },
+            ),
+            (Topic::from("applications"), lararium::Entry::Directory),
+        ]);
+        EntryTree::new(Arc::new(RwLock::new(entries)), vec!["drive".into()])
+    }
+
+    #[tokio::test]
+    async fn test_browse_entries() {
+        let tree = tree();
+        let listing = tree
+            .read_directory(
+                &superuser(),
+                &root(),
+                ReadDirectoryArgs {
+                    cookie: 0,
+                    cookie_verifier: [0; 8],
+                    dir_count: 1024,
+                    max_count: 1024,
+                    attributes: AttributeMask::new(),
+                },
+            )
+            .await
+            .unwrap();
+        let names: Vec<_> = listing
+            .directory_list
+            .entries
+            .iter()
+            .map(|entry| entry.name.to_string())
+            .collect();
+        assert_eq!(names, ["applications", "drive", "system"]);
+        assert!(tree.is_mount(&root(), "drive"));
+        let system = tree.lookup(&superuser(), &root(), "system").await.unwrap();
+        let dns = tree.lookup(&superuser(), &system, "dns").await.unwrap();
+        let ttl = tree.lookup(&superuser(), &dns, "ttl.json").await.unwrap();
+        let result = tree.read(&superuser(), &ttl, read_args()).await.unwrap();
+        assert_eq!(&*result.data, b"300\n");
+        assert!(result.eof);
+        assert_eq!(
+            tree.lookup(&superuser(), &dns, "ttl").await,
+            Err(Error::NOENT)
+        );
+        assert_eq!(tree.lookup_parent(&superuser(), &dns).await, Ok(system));
+        assert_eq!(
+            tree.lookup_parent(&superuser(), &root()).await,
+            Err(Error::NOENT)
+        );
+        assert_eq!(tree.read_link(&superuser(), &ttl).await, Err(Error::INVAL));
+        // Handles stay valid for a tree that has never seen them.
+        let tree = EntryTree::new(tree.entries.clone(), vec![]);
+        let result = tree.read(&superuser(), &ttl, read_args()).await.unwrap();
+        assert_eq!(&*result.data, b"300\n");
+    }
+
+    #[tokio::test]
+    async fn test_write_validates_schema() {
+        let tree = tree();
+        let system = tree.lookup(&superuser(), &root(), "system").await.unwrap();
+        let dns = tree.lookup(&superuser(), &system, "dns").await.unwrap();
+        let ttl = tree.lookup(&superuser(), &dns, "ttl.json").await.unwrap();
+        let stranger = Credentials {
+            uid: 1000,
+            gid: 1000,
+            gids: vec![],
+        };
+        assert_eq!(
+            tree.write(&stranger, &ttl, write_args(b"60\n")).await,
+            Err(Error::Access)
+        );
+        assert_eq!(
+            tree.write(&superuser(), &ttl, write_args(b"\"sixty\"\n"))
+                .await,
+            Err(Error::INVAL)
+        );
+        let result = tree
+            .write(&superuser(), &ttl, write_args(b"60\n"))
+            .await
+            .unwrap();
+        assert_eq!(result.committed, StableHow::FileSync);
+        assert_eq!(
+            tree.entries
+                .read()
+                .await
+                .get(&Topic::from("system/dns/ttl")),
+            Some(&lararium::Entry::Record {
+                schema: Schema::Integer,
+                value: Value::Integer(60),
+            })
+        );
+        let ttl = tree.lookup(&superuser(), &dns, "ttl.cbor").await.unwrap();
+        let result = tree
+            .write(&superuser(), &ttl, write_args(&[0x19]))
+            .await
+            .unwrap();
+        assert_eq!(result.committed, StableHow::Unstable);
+        let commit = CommitArgs {
+            offset: 0,
+            count: 0,
+        };
+        assert_eq!(
+            tree.commit(&superuser(), &ttl, commit).await,
+            Err(Error::IO)
+        );
+        let result = tree.read(&superuser(), &ttl, read_args()).await.unwrap();
+        assert_eq!(&*result.data, &[0x18, 60]);
+    }
+
+    #[tokio::test]
+    async fn test_write_bounds() {
+        let tree = tree();
+        let system = tree.lookup(&superuser(), &root(), "system").await.unwrap();
+        let dns = tree.lookup(&superuser(), &system, "dns").await.unwrap();
+        let ttl = tree.lookup(&superuser(), &dns, "ttl.json").await.unwrap();
+        for offset in [u64::MAX, u64::MAX - 1, MAX_FILE_SIZE as u64] {
+            let args = WriteArgs {
+                offset,
+                ..write_args(b"60")
+            };
+            assert_eq!(tree.write(&superuser(), &ttl, args).await, Err(Error::FBIG));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_changes() {
+        let (changes, mut changed) = mpsc::unbounded_channel();
+        let tree = tree().with_changes(changes);
+        let system = tree.lookup(&superuser(), &root(), "system").await.unwrap();
+        let dns = tree.lookup(&superuser(), &system, "dns").await.unwrap();
+        let ttl = tree.lookup(&superuser(), &dns, "ttl.json").await.unwrap();
+        tree.write(&superuser(), &ttl, write_args(b"60\n"))
+            .await
+            .unwrap();
+        assert_eq!(changed.try_recv(), Ok(Topic::from("system/dns/ttl")));
+        tree.remove(&superuser(), &dns, "ttl.json").await.unwrap();
+        assert_eq!(changed.try_recv(), Ok(Topic::from("system/dns/ttl")));
+        assert!(changed.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_create_and_remove() {
+        let tree = tree();
+        let applications = tree
+            .lookup(&superuser(), &root(), "applications")
+            .await
+            .unwrap();
+        let (kodi, _) = tree
+            .create(
+                &superuser(),
+                &applications,
+                CreateArgs {
+                    object_type: CreateType::Directory,
+                    name: "kodi",
+                    attributes: vec![],
+                },
+            )
+            .await
+            .unwrap();
+        let (volume, _) = tree
+            .open(
+                &superuser(),
+                &kodi,
+                OpenArgs {
+                    sequence_id: 0,
+                    share_access: ShareAccessFlags::READ | ShareAccessFlags::WRITE,
+                    share_deny: ShareDenyFlags::empty(),
+                    owner: StateOwner {
+                        client_id: 0,
+                        owner: (&[]).into(),
+                    }
+                    .into(),
+                    how: OpenFlag::Create(OpenFlagCreate::Guarded {
+                        attributes: vec![AttributeValue::Mode(0o644)],
+                    }),
+                    claim: OpenClaim::Null("volume.json"),
+                },
+            )
+            .await
+            .unwrap();
+        tree.write(&superuser(), &volume, write_args(b"0.5"))
+            .await
+            .unwrap();
+        assert_eq!(
+            tree.remove(&superuser(), &applications, "kodi").await,
+            Err(Error::NOTEMPTY)
+        );
+        tree.remove(&superuser(), &kodi, "volume.cbor")
+            .await
+            .unwrap();
+        tree.remove(&superuser(), &applications, "kodi")
+            .await
+            .unwrap();
+        assert_eq!(
+            tree.lookup(&superuser(), &applications, "kodi").await,
+            Err(Error::NOENT)
+        );
+    }
+}
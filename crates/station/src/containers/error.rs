@@ -1,7 +1,13 @@
 use derive_more::From;
 
 #[derive(Debug, From)]
-pub enum Error {}
+pub enum Error {
+    /// A volume could not be mounted at its path in the container.
+    Mount {
+        path: std::path::PathBuf,
+        error: nix::Error,
+    },
+}
 
 impl std::error::Error for Error {}
 
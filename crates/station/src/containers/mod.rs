@@ -14,12 +14,16 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::ffi::CString;
 use std::fs;
+use std::net::{IpAddr, SocketAddr};
 use std::os::fd::IntoRawFd;
 use std::os::unix::io::AsRawFd;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::sync::{oneshot, RwLock};
 
+/// The user and group ID that containers run as.
+pub const CONTAINER_ID: u32 = 1000;
+
 #[derive(Clone)]
 pub struct ContainerRuntime {
     blueprints: HashMap<String, ContainerBlueprint>,
@@ -34,6 +38,32 @@ pub struct ContainerBlueprint {
     pub args: Vec<String>,
     pub env: Vec<(String, String)>,
     pub wayland: bool,
+    pub volumes: Vec<Volume>,
+}
+
+/// A volume mounted at a path of the container, relative to its root directory.
+#[derive(Clone, Deserialize, Serialize)]
+pub enum Volume {
+    /// A directory of the node, kept across runs.
+    Local { path: PathBuf, source: PathBuf },
+    /// A directory exported by the NFS server of the gateway.
+    Shared {
+        path: PathBuf,
+        address: SocketAddr,
+        export_path: String,
+    },
+    /// A tmpfs, whose content is gone when the container exits.
+    Temporary { path: PathBuf },
+}
+
+/// What mounting a volume comes down to, as the arguments of mount(2).
+#[derive(Debug, PartialEq, Eq)]
+struct MountPlan {
+    source: PathBuf,
+    target: PathBuf,
+    fstype: Option<&'static str>,
+    flags: MsFlags,
+    data: Option<String>,
 }
 
 pub struct ContainerHandle {
@@ -51,6 +81,7 @@ struct ContainerInstance {
     gid: Gid,
     uid: Uid,
     wayland: bool,
+    volumes: Vec<Volume>,
     signal_rx: oneshot::Receiver<Signal>,
 }
 
@@ -104,13 +135,19 @@ impl ContainerBlueprint {
             command: self.command.clone(),
             args: self.args.clone(),
             env: self.env.clone(),
-            gid: Gid::from_raw(1000),
-            uid: Uid::from_raw(1000),
+            gid: Gid::from_raw(CONTAINER_ID),
+            uid: Uid::from_raw(CONTAINER_ID),
             wayland: self.wayland,
+            volumes: self.volumes.clone(),
             signal_rx,
         };
 
-        tokio::task::spawn_blocking(move || container.run().unwrap());
+        tokio::task::spawn_blocking(move || {
+            let hostname = container.hostname.clone();
+            if let Err(error) = container.run() {
+                error!("Container {hostname} failed: {error}");
+            }
+        });
 
         Ok(ContainerHandle {
             signal_tx: Some(signal_tx),
@@ -118,6 +155,100 @@ impl ContainerBlueprint {
     }
 }
 
+impl Volume {
+    fn path(&self) -> &Path {
+        match self {
+            Volume::Local { path, .. } => path,
+            Volume::Shared { path, .. } => path,
+            Volume::Temporary { path } => path,
+        }
+    }
+
+    fn plan(
+        &self,
+        root_dir: &Path,
+    ) -> MountPlan {
+        let target = root_dir.join(self.path());
+        match self {
+            Volume::Local { source, .. } => MountPlan {
+                source: source.clone(),
+                target,
+                fstype: None,
+                flags: MsFlags::MS_BIND,
+                data: None,
+            },
+            Volume::Shared {
+                address,
+                export_path,
+                ..
+            } => {
+                let source = match address.ip() {
+                    IpAddr::V4(ip) => format!("{ip}:{export_path}"),
+                    IpAddr::V6(ip) => format!("[{ip}]:{export_path}"),
+                };
+                // Without mount.nfs to resolve the server, the kernel is given its address.
+                let options = format!("vers=4.1,addr={},port={}", address.ip(), address.port());
+                MountPlan {
+                    source: source.into(),
+                    target,
+                    fstype: Some("nfs4"),
+                    flags: MsFlags::MS_NOSUID | MsFlags::MS_NODEV,
+                    data: Some(options),
+                }
+            }
+            Volume::Temporary { .. } => MountPlan {
+                source: "tmpfs".into(),
+                target,
+                fstype: Some("tmpfs"),
+                flags: MsFlags::MS_NOSUID | MsFlags::MS_NODEV,
+                data: None,
+            },
+        }
+    }
+
+    fn mount(
+        &self,
+        root_dir: &Path,
+    ) -> Result<(), Error> {
+        let plan = self.plan(root_dir);
+        mount(
+            Some(&plan.source),
+            &plan.target,
+            plan.fstype,
+            plan.flags,
+            plan.data.as_deref(),
+        )
+        .map_err(|error| Error::Mount {
+            path: self.path().into(),
+            error,
+        })
+    }
+}
+
+/// Turns the absolute path of a volume in a container into one relative to its root directory.
+pub fn volume_path(path: &str) -> Result<PathBuf, String> {
+    let path = Path::new(path)
+        .strip_prefix("/")
+        .map_err(|_| String::from("path must be absolute"))?;
+    if path.as_os_str().is_empty() {
+        return Err(String::from("path must not be the root of the container"));
+    }
+    if path
+        .components()
+        .any(|component| !matches!(component, std::path::Component::Normal(_)))
+    {
+        return Err(String::from("path must not leave the container"));
+    }
+    Ok(path.into())
+}
+
+pub fn validate_volume_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+        return Err(String::from("invalid volume name"));
+    }
+    Ok(())
+}
+
 impl Drop for ContainerHandle {
     fn drop(&mut self) {
         if let Some(signal_tx) = self.signal_tx.take() {
@@ -157,6 +288,12 @@ impl ContainerInstance {
         fs::File::create(self.root_dir.join("dev/null")).unwrap();
         fs::File::create(self.root_dir.join("dev/random")).unwrap();
         fs::File::create(self.root_dir.join("dev/urandom")).unwrap();
+        for volume in &self.volumes {
+            fs::create_dir_all(self.root_dir.join(volume.path())).unwrap();
+            if let Volume::Local { source, .. } = volume {
+                fs::create_dir_all(source).unwrap();
+            }
+        }
 
         let run_user_dir = self.root_dir.join("run/user").join(self.uid.to_string());
         fs::create_dir_all(&run_user_dir).unwrap();
@@ -209,6 +346,18 @@ impl ContainerInstance {
         )
         .unwrap();
 
+        // Volumes go into the mount namespace before forking, so that a failure is returned
+        // instead of taking down the child.
+        for (mounted, volume) in self.volumes.iter().enumerate() {
+            if let Err(error) = volume.mount(&self.root_dir) {
+                for volume in self.volumes[..mounted].iter().rev() {
+                    let _ = umount(&self.root_dir.join(volume.path()));
+                }
+                let _ = fs::remove_dir(&cgroup_path);
+                return Err(error);
+            }
+        }
+
         match unsafe { unistd::fork() } {
             Ok(ForkResult::Parent { child }) => {
                 drop(stdout_write);
@@ -301,6 +450,12 @@ impl ContainerInstance {
                         error!("Failed to unmount wayland socket: {error}");
                     }
                 }
+                for volume in self.volumes.iter().rev() {
+                    let path = volume.path();
+                    if let Err(error) = umount(&self.root_dir.join(path)) {
+                        error!("Failed to unmount volume /{}: {error}", path.display());
+                    }
+                }
                 Ok(())
             }
             Ok(ForkResult::Child) => {
@@ -444,3 +599,88 @@ impl ContainerInstance {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mount_plan() {
+        let root_dir = Path::new("/tmp/rootfs");
+        let local = Volume::Local {
+            path: "var/lib/kodi".into(),
+            source: "/var/lib/lararium/volumes/kodi".into(),
+        };
+        assert_eq!(
+            local.plan(root_dir),
+            MountPlan {
+                source: "/var/lib/lararium/volumes/kodi".into(),
+                target: "/tmp/rootfs/var/lib/kodi".into(),
+                fstype: None,
+                flags: MsFlags::MS_BIND,
+                data: None,
+            }
+        );
+        let shared = Volume::Shared {
+            path: "media".into(),
+            address: "10.0.0.1:2049".parse().unwrap(),
+            export_path: "/drive/volumes/media".into(),
+        };
+        assert_eq!(
+            shared.plan(root_dir),
+            MountPlan {
+                source: "10.0.0.1:/drive/volumes/media".into(),
+                target: "/tmp/rootfs/media".into(),
+                fstype: Some("nfs4"),
+                flags: MsFlags::MS_NOSUID | MsFlags::MS_NODEV,
+                data: Some("vers=4.1,addr=10.0.0.1,port=2049".into()),
+            }
+        );
+        let shared = Volume::Shared {
+            path: "media".into(),
+            address: "[fd00::1]:2050".parse().unwrap(),
+            export_path: "/drive/volumes/media".into(),
+        };
+        let plan = shared.plan(root_dir);
+        assert_eq!(plan.source, Path::new("[fd00::1]:/drive/volumes/media"));
+        assert_eq!(
+            plan.data.as_deref(),
+            Some("vers=4.1,addr=fd00::1,port=2050")
+        );
+        let temporary = Volume::Temporary {
+            path: "cache".into(),
+        };
+        assert_eq!(
+            temporary.plan(root_dir),
+            MountPlan {
+                source: "tmpfs".into(),
+                target: "/tmp/rootfs/cache".into(),
+                fstype: Some("tmpfs"),
+                flags: MsFlags::MS_NOSUID | MsFlags::MS_NODEV,
+                data: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_volume_path() {
+        assert_eq!(volume_path("/media"), Ok(PathBuf::from("media")));
+        assert_eq!(
+            volume_path("/var/lib/kodi"),
+            Ok(PathBuf::from("var/lib/kodi"))
+        );
+        assert!(volume_path("media").is_err());
+        assert!(volume_path("/").is_err());
+        assert!(volume_path("/media/../etc").is_err());
+        assert!(volume_path("/media/./kodi").is_ok());
+    }
+
+    #[test]
+    fn test_validate_volume_name() {
+        assert_eq!(validate_volume_name("media"), Ok(()));
+        assert!(validate_volume_name("").is_err());
+        assert!(validate_volume_name(".").is_err());
+        assert!(validate_volume_name("..").is_err());
+        assert!(validate_volume_name("media/kodi").is_err());
+    }
+}
@@ -5,11 +5,14 @@ mod prelude;
 mod stderr;
 mod stdout;
 
-use crate::containers::ContainerRuntime;
+use crate::containers::{
+    validate_volume_name, volume_path, ContainerRuntime, Volume, CONTAINER_ID,
+};
 use crate::error::Error;
 
-use std::fs;
+use nfs::AuthSysParms;
 use std::future::Future;
+use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::sync::Arc;
@@ -20,6 +23,9 @@ use wasmtime::component::{bindgen, Component, Linker, ResourceTable};
 use wasmtime::{Config, Engine, Result, Store};
 use wasmtime_wasi::{async_trait, DirPerms, FilePerms, WasiCtx, WasiCtxBuilder, WasiView};
 
+/// The directory of the gateway's NFS export that holds the shared volumes.
+const SHARED_VOLUMES_PATH: &str = "/drive/volumes";
+
 bindgen!({
     world: "extension",
     async: true,
@@ -39,6 +45,9 @@ pub struct RunArgs<'a> {
     pub node_name: &'a str,
     pub gateway: &'a str,
     pub mqtt_port: u16,
+    pub nfs_port: u16,
+    /// The directory of the node that holds the local volumes.
+    pub volumes_dir: PathBuf,
 }
 
 struct State {
@@ -46,6 +55,13 @@ struct State {
     table: ResourceTable,
     container_runtime: Arc<Mutex<ContainerRuntime>>,
     root_dir: PathBuf,
+    gateway: String,
+    nfs_port: u16,
+    /// The host name of the node, which the station goes by on the gateway.
+    node_name: String,
+    volumes_dir: PathBuf,
+    /// The volumes mounted into the next container that gets created.
+    volumes: Vec<Volume>,
 }
 
 impl Station {
@@ -71,12 +87,13 @@ impl Station {
     ) -> Result<(), Error> {
         let component = Component::new(&self.engine, args.wasm)?;
         let uname = nix::sys::utsname::uname()?;
+        let node_name = uname.nodename().to_string_lossy().into_owned();
         std::fs::create_dir_all(&args.root_dir)?;
         let ctx = WasiCtxBuilder::new()
             .stdout(StdOut::new())
             .stderr(StdErr::new())
             .env("NAME", args.name)
-            .env("NODE_NAME", uname.nodename().to_string_lossy())
+            .env("NODE_NAME", &node_name)
             .env("GATEWAY", args.gateway)
             .env("MQTT_PORT", args.mqtt_port.to_string())
             .env("KERNEL", uname.release().to_string_lossy())
@@ -96,7 +113,12 @@ impl Station {
                 ctx,
                 table: ResourceTable::new(),
                 container_runtime: self.container_runtime.clone(),
-                root_dir: args.root_dir.into(),
+                root_dir: args.root_dir,
+                gateway: args.gateway.into(),
+                nfs_port: args.nfs_port,
+                node_name,
+                volumes_dir: args.volumes_dir,
+                volumes: Vec::new(),
             },
         );
         let bindings = Extension::instantiate_async(&mut store, &component, &self.linker).await?;
@@ -144,6 +166,7 @@ impl ExtensionImports for State {
                 args: args.args,
                 env: args.env,
                 wayland: args.wayland,
+                volumes: std::mem::take(&mut self.volumes),
             },
         );
         Ok(())
@@ -170,6 +193,12 @@ impl ExtensionImports for State {
         path: String,
         name: String,
     ) -> Result<(), String> {
+        let path = volume_path(&path)?;
+        validate_volume_name(&name)?;
+        self.volumes.push(Volume::Local {
+            path,
+            source: self.volumes_dir.join(name),
+        });
         Ok(())
     }
 
@@ -178,12 +207,22 @@ impl ExtensionImports for State {
         path: String,
         name: String,
     ) -> Result<(), String> {
-        let path = PathBuf::from(path);
-        let path = path
-            .strip_prefix("/")
-            .map_err(|_| String::from("path must be absolute"))?;
-        let path = self.root_dir.join(path);
-        fs::create_dir_all(path).map_err(|_| String::from("failed to create directory"))?;
+        let path = volume_path(&path)?;
+        validate_volume_name(&name)?;
+        let address = tokio::net::lookup_host((self.gateway.as_str(), self.nfs_port))
+            .await
+            .ok()
+            .and_then(|mut addresses| addresses.next())
+            .ok_or_else(|| String::from("failed to resolve gateway"))?;
+        let export_path = format!("{SHARED_VOLUMES_PATH}/{name}");
+        create_shared_volume(address, &self.node_name, &export_path)
+            .await
+            .map_err(|error| format!("failed to create shared volume: {error}"))?;
+        self.volumes.push(Volume::Shared {
+            path,
+            address,
+            export_path,
+        });
         Ok(())
     }
 
@@ -191,10 +230,35 @@ impl ExtensionImports for State {
         &mut self,
         path: String,
     ) -> Result<(), String> {
+        let path = volume_path(&path)?;
+        self.volumes.push(Volume::Temporary { path });
         Ok(())
     }
 }
 
+/// Creates the directory of a shared volume on the gateway unless it exists, owned by the user
+/// that containers run as.
+async fn create_shared_volume(
+    address: SocketAddr,
+    node_name: &str,
+    export_path: &str,
+) -> Result<(), nfs::client::Error> {
+    let credentials = AuthSysParms {
+        stamp: 0,
+        machine_name: node_name.into(),
+        uid: CONTAINER_ID,
+        gid: CONTAINER_ID,
+        gids: vec![],
+    };
+    let mut client = nfs::Client::connect(address, &credentials).await?;
+    let result = match client.create_dir(export_path).await {
+        Err(nfs::client::Error::Nfs(nfs::Error::EXIST)) => Ok(()),
+        result => result,
+    };
+    client.disconnect().await?;
+    result
+}
+
 impl WasiView for State {
     fn ctx(&mut self) -> &mut WasiCtx {
         &mut self.ctx
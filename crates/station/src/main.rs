@@ -17,6 +17,10 @@ struct Args {
     gateway_api_port: u16,
     #[arg(env, long, default_value_t = 1883)]
     gateway_mqtt_port: u16,
+    #[arg(env, long, default_value_t = 2049)]
+    gateway_nfs_port: u16,
+    #[arg(env, long, default_value = "/var/lib/lararium/volumes")]
+    volumes_dir: PathBuf,
 }
 
 #[tokio::main]
@@ -40,6 +44,8 @@ async fn main() -> color_eyre::Result<()> {
                     node_name: "rpi5",
                     gateway: &args.gateway_host,
                     mqtt_port: args.gateway_mqtt_port,
+                    nfs_port: args.gateway_nfs_port,
+                    volumes_dir: args.volumes_dir,
                 })
                 .await?;
             Ok::<(), color_eyre::Report>(())